@@ -0,0 +1,202 @@
+//! Benchmarks for the paths that run on every key press or frame: editing large files,
+//! highlighting lines, composing the frame grid and decoding semantic tokens.
+
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::sync::mpsc::channel;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use crossterm::style::Color;
+
+use oxidy::buffer::Buffer;
+use oxidy::editor::Editor;
+use oxidy::highlighter::Highlighter;
+use oxidy::lsp::LspResponse::SemanticTokensLegend;
+use oxidy::plugins::config::Config;
+use oxidy::renderer::Layer;
+use oxidy::renderer::crossterm::{Composite, CrossTermRenderer, GutterLayer, TextLayer, GUTTER_WIDTH};
+use oxidy::services::lsp_service;
+use oxidy::types::{EditorAction, Grid, Rect, RenderCell, Size, Token};
+use oxidy::ui::ui_manager::UiManager;
+
+const LARGE_FILE_LINES: usize = 100_000;
+const SCREEN: Size = Size { cols: 200, rows: 60 };
+
+const SOURCE_LINES: [&str; 4] = [
+    "    pub fn handle_action(&mut self, action: &EditorAction) -> Option<Vec<Token>> {",
+    "        let view = self.views.get(&self.active_view).unwrap(); // \"quoted\" 123",
+    "            tokens.push(Token { row: index, text: cap.as_str().to_string(), offset: 0 });",
+    "/// Adds the tokens in `data`, encoded relative to each other as the server sends them.",
+];
+
+fn source(lines: usize) -> String {
+    (0..lines).map(|i| SOURCE_LINES[i % SOURCE_LINES.len()]).collect::<Vec<_>>().join("\n")
+}
+
+fn editor_with(lines: usize) -> Editor {
+    let mut editor = Editor::new(channel().0);
+    editor.open_buffer("bench.rs".into(), source(lines), SCREEN);
+    editor
+}
+
+fn buffer_edits(c: &mut Criterion) {
+    let mut group = c.benchmark_group("edit");
+
+    group.bench_function("insert char, 100k lines", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut editor = editor_with(LARGE_FILE_LINES);
+                editor.set_cursor(LARGE_FILE_LINES / 2, 10);
+                editor
+            },
+            |editor| editor.handle_action(black_box(&EditorAction::InsertChar('x'))),
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("split and join line, 100k lines", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut editor = editor_with(LARGE_FILE_LINES);
+                editor.set_cursor(LARGE_FILE_LINES / 2, 10);
+                editor
+            },
+            |editor| {
+                editor.handle_action(&EditorAction::InsertNewline);
+                editor.handle_action(&EditorAction::DeleteChar);
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn highlight(c: &mut Criterion) {
+    let mut group = c.benchmark_group("highlight");
+    let highlighter = Highlighter::new(HashMap::new());
+
+    group.bench_function("source line, uncached", |b| {
+        b.iter(|| {
+            highlighter.clear_cache();
+            for (row, line) in SOURCE_LINES.iter().enumerate() {
+                black_box(highlighter.highlight(black_box(line), row));
+            }
+        })
+    });
+
+    group.bench_function("source line, cached", |b| {
+        b.iter(|| {
+            for (row, line) in SOURCE_LINES.iter().enumerate() {
+                black_box(highlighter.highlight(black_box(line), row));
+            }
+        })
+    });
+
+    group.finish();
+}
+
+fn grid(c: &mut Criterion) {
+    let mut group = c.benchmark_group("grid");
+
+    let editor = editor_with(1000);
+    let view = editor.active_view().unwrap().clone();
+    let ui = UiManager::new();
+    let config = Config::default();
+
+    let gutter_rect = Rect { x: 0, y: 0, cols: GUTTER_WIDTH, rows: SCREEN.rows };
+    let text_rect = Rect { x: 0, y: 0, cols: SCREEN.cols - GUTTER_WIDTH, rows: SCREEN.rows };
+
+    group.bench_function("render view layers", |b| {
+        b.iter(|| {
+            let gutter = GutterLayer::render(&editor, &view, &ui, &config, gutter_rect.clone());
+            let text = TextLayer::render(&editor, &view, &ui, &config, text_rect.clone());
+            black_box(Composite::merge(&gutter, &text))
+        })
+    });
+
+    let base = Grid::new(SCREEN.rows as usize, SCREEN.cols as usize, RenderCell::space(&config));
+    let mut overlay = Grid::new(SCREEN.rows as usize, SCREEN.cols as usize, RenderCell::blank());
+    for row in overlay.cells.iter_mut().take(12) {
+        row.fill(RenderCell::space(&config));
+    }
+
+    group.bench_function("overlay ui", |b| {
+        b.iter(|| black_box(Composite::overlay(&base, &overlay)))
+    });
+
+    // What the renderer writes between two frames, for the changes frames usually have
+    let frame = Composite::merge(
+        &GutterLayer::render(&editor, &view, &ui, &config, gutter_rect.clone()),
+        &TextLayer::render(&editor, &view, &ui, &config, text_rect.clone()),
+    );
+
+    let mut typed = frame.clone();
+    let row = &mut typed.cells[SCREEN.rows as usize / 2];
+    row[GUTTER_WIDTH as usize + 20..].rotate_right(1);
+    row[GUTTER_WIDTH as usize + 20].ch = 'x';
+
+    let mut scrolled_view = view.clone();
+    scrolled_view.scroll.vertical += 1;
+    let scrolled = Composite::merge(
+        &GutterLayer::render(&editor, &scrolled_view, &ui, &config, gutter_rect.clone()),
+        &TextLayer::render(&editor, &scrolled_view, &ui, &config, text_rect.clone()),
+    );
+
+    // Rows of wide characters where one changes, the columns have to be counted up to it
+    let wide_line = |ch: char| -> Vec<RenderCell> {
+        "日本語のテキスト ".repeat(11).chars().take(SCREEN.cols as usize / 2)
+            .chain(std::iter::once(ch))
+            .map(|ch| RenderCell { ch, ..RenderCell::space(&config) })
+            .collect()
+    };
+    let wide = Grid { cells: vec![wide_line('a'); SCREEN.rows as usize] };
+    let wide_changed = Grid { cells: vec![wide_line('b'); SCREEN.rows as usize] };
+
+    let spans = |old: &Grid<RenderCell>, new: &Grid<RenderCell>| -> usize {
+        old.cells.iter().zip(&new.cells)
+            .map(|(old, new)| CrossTermRenderer::dirty_spans(old, new).map_or(0, |spans| spans.len()))
+            .sum()
+    };
+
+    for (name, old, new) in [
+        ("dirty spans, typed char", &frame, &typed),
+        ("dirty spans, scrolled", &frame, &scrolled),
+        ("dirty spans, wide chars", &wide, &wide_changed),
+    ] {
+        group.bench_function(name, |b| b.iter(|| black_box(spans(black_box(old), black_box(new)))));
+    }
+
+    group.finish();
+}
+
+fn token_decoding(c: &mut Criterion) {
+    let legend = SemanticTokensLegend {
+        tokenTypes: ["namespace", "type", "function", "variable", "keyword", "string"].map(String::from).to_vec(),
+        tokenModifiers: ["declaration", "readonly"].map(String::from).to_vec(),
+    };
+    let colors: HashMap<String, Color> = legend.tokenTypes.iter()
+        .map(|token_type| (token_type.clone(), Color::Blue))
+        .collect();
+
+    let buffer = Buffer::from_content(&source(LARGE_FILE_LINES), "bench.rs".into());
+
+    // Eight tokens a line, each relative to the one before it like servers send them
+    let data: Vec<i32> = (0..LARGE_FILE_LINES as i32)
+        .flat_map(|line| (0..8).flat_map(move |i| match i {
+            0 => [if line == 0 { 0 } else { 1 }, 4, 4, 0, 0],
+            _ => [0, 6, 4, i % 6, i % 4],
+        }))
+        .collect();
+
+    c.bench_function("decode semantic tokens, 100k lines", |b| {
+        b.iter(|| {
+            let mut tokens: Vec<Vec<Token>> = vec![Vec::new(); buffer.lines.len()];
+            lsp_service::decode_tokens(&legend, black_box(&data), &buffer, &colors, &mut tokens);
+            black_box(tokens)
+        })
+    });
+}
+
+criterion_group!(benches, buffer_edits, highlight, grid, token_decoding);
+criterion_main!(benches);
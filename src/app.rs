@@ -1,40 +1,207 @@
 use std::sync::mpsc::{Receiver, channel};
 use std::fs::File;
+use std::path::Path;
 use std::io::{self, Read};
 use std::sync::Arc;
 use std::collections::HashMap;
 
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::types::{EditorAction, EditorEvent, EditorMode, Size, Direction};
+use crate::types::{BufferId, CallDirection, Cursor, Key, LocationKind, EditorAction, EditorEvent, EditorMode, LineScope, MarkCommand, Modifiers, ReplScope, Size, Direction};
 use crate::editor::Editor;
 use crate::command::{self, CommandManager};
 use crate::highlighter::Highlighter;
 use crate::plugins::plugin_manager::PluginManager;
-use crate::services::lsp_service::{LspService, LspServiceEvent, LspState};
+use crate::services::lsp_service::{self, LspService, LspServiceEvent, LspState, Signature};
+use crate::services::lsp_manager::{LspManager, ServerKey};
 use crate::ui::ui_manager::UiManager;
 use crate::ui::status_bar::StatusBar;
 use crate::ui::command::Command;
+use crate::ui::card::{Card, CardType};
+use crate::ui::diagnostics_panel::DiagnosticsPanel;
+use crate::ui::transform_preview::TransformPreview;
+use crate::ui::hover::{self, HOVER_POPUP};
+use crate::ui::signature_help::SignatureHelp;
+use crate::ui::repl_panel::ReplPanel;
+use crate::ui::undo_tree::UndoTreePanel;
+use crate::ui::palette::CommandPalette;
+use crate::ui::scripted::ScriptedUiElement;
+use crate::ui::process_panel::ProcessPanel;
+use crate::ui::call_hierarchy::CallHierarchyPanel;
+use crate::ui::show_cmd::ShowCmd;
+use crate::ui::popup::Popups;
+use crate::ui::definition_preview::{DefinitionPreview, PreviewTarget};
+use crate::transform::{self, TransformJob};
+use crate::shell::{ShellJob, ShellOutput, ShellTarget};
+use crate::text_edit::{self, FileEdit, TextEdit};
+use crate::clipboard::{self, ClipboardKind};
+use crate::tmux;
+use crate::repl::{self, Repl};
+use crate::processes::{ProcessOwner, ProcessRegistry};
+use crate::signals;
+use crate::recovery;
+use crate::undo::{HistoryStep, UndoTree};
+use crate::diagnostics::{self, Diagnostic, Severity};
 use crate::renderer::Renderer;
-use crate::input::{InputHandler, InputEvent};
+use crate::input::{InputHandler, InputEvent, MouseButton, MouseType, SessionInput};
+use crate::renderer::crossterm::GUTTER_WIDTH;
+use crate::renderer::session::SessionRenderer;
+use crate::links;
+use crate::cmdline::{self, CommandInvocation, LineRange};
+use crate::plugins::keymap::KeymapConfig;
+use crate::plugins::widgets::WidgetKind;
+use crate::plugins::script_api::{event_details, EditorApi, ScriptState};
+use crate::export::{self, ExportFormat};
+use crate::crypto::{self, Encryption};
+use crate::collab;
+use crate::services::collab_service::{self, CollabService, CollabServiceEvent};
+use crate::services::remote_service::RemoteService;
+use crate::services::git_service::{GitService, GitUpdate};
+use crate::git::BlameLine;
+use crate::marks;
+use crate::history::CommandHistory;
+use crate::services::session_service::{SessionEvent, SessionService};
+use crate::buffer::{Buffer, LineEnding};
+use crate::encoding::{self, Encoding};
 use crate::plugins::config::Config;
-use crate::keymap::Keymap;
+use crate::plugins::options::Options;
+use crate::plugins::filetype::FiletypeTheme;
+use crate::plugins::lsp::LspConfig;
+use crate::keymap::{KeyCombo, Keymap};
 use crate::log;
 use crate::KeyRepeatState;
 
+const DEFAULT_COLLAB_ADDRESS: &str = "127.0.0.1:7878";
+
+/// How long a save waits for the language server to format before writing the file as is.
+const FORMAT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Edits closer together than this share one request for semantic tokens.
+const TOKENS_DELAY: Duration = Duration::from_millis(50);
+
+/// How often the `:ps` panel reads CPU and memory use while it is open.
+const PROCESS_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often unsaved buffers are written to recovery files while detached.
+const RECOVERY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often the git gutter signs look at the index again, for `git add` from a shell.
+const GIT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A formatting request that hasn't been answered yet.
+pub struct PendingFormat {
+    pub buffer: BufferId,
+    pub save: bool,
+    pub started: Instant,
+}
+
+/// Render timings, to check that input stays responsive on slow terminals.
+#[derive(Debug, Default)]
+pub struct FrameStats {
+    pub frames: u64,
+    pub overruns: u64,
+    pub worst: Duration,
+    pub total: Duration,
+}
+
+impl FrameStats {
+    pub fn record(&mut self, duration: Duration, budget: Duration) {
+        self.frames += 1;
+        self.total += duration;
+        self.worst = self.worst.max(duration);
+
+        if duration > budget {
+            self.overruns += 1;
+            log!("Frame took {:?}, budget is {:?}", duration, budget);
+        }
+    }
+
+    pub fn average(&self) -> Duration {
+        if self.frames == 0 { return Duration::ZERO }
+        self.total / self.frames as u32
+    }
+}
+
 pub struct App {
     pub size: Size,
     pub editor: Editor,
     pub commands: CommandManager,
     pub keymap: Keymap,
     pub plugins: PluginManager,
-    pub lsp: Option<LspService>,
+    pub lsp: LspManager,
+    pub collab: Option<CollabService>,
+    // The buffer shared through `collab`
+    pub collab_buffer: Option<BufferId>,
+    // The shared buffer as the others know it and the version it had then
+    collab_synced: (u32, Vec<String>),
+    // External command whose output is waiting to be accepted
+    pub transform: Option<TransformJob>,
+    // `:!` commands that haven't exited yet
+    pub shell_jobs: Vec<ShellJob>,
+    // What the status bar snippets last saw, they are evaluated again when it changes
+    pub status_inputs: String,
+    // The theme the highlighting rules were last colored with, a file type may set its own
+    pub shown_theme: Option<String>,
+    // The theme before the `:theme` list started previewing, Esc goes back to it
+    pub previewing_theme: Option<String>,
+    // Set while config hooks run, what they trigger doesn't run hooks again
+    pub in_hook: bool,
+    pub command_history: CommandHistory,
+    pub repl: Option<Repl>,
     pub ui: UiManager,
     pub renderer: Box<dyn Renderer>,
     pub input: Box<dyn InputHandler>,
     pub config: Config,
     pub key_repeat: KeyRepeatState,
+    pub last_edit: Option<Instant>,
+    pub startup_pending: bool,
+    // Ex commands from `+cmd` and `-c cmd`, run once the config is loaded
+    pub startup_commands: Vec<String>,
+    pub exit_code: i32,
+    pub frame_stats: FrameStats,
+    // Encrypted file waiting for its passphrase to be typed in
+    pub pending_passphrase: Option<(String, Encryption)>,
+    // Whether the user was already told that the caches went over opt.cache_budget
+    pub cache_warned: bool,
+    // The location being looked up is shown in the preview window instead of jumped to
+    pub preview_location: bool,
+    pub processes: ProcessRegistry,
+    // When the :ps panel last read CPU and memory use
+    pub processes_sampled: Option<Instant>,
+    // The server the call hierarchy panel came from, its calls are asked for there
+    pub calls_server: Option<ServerKey>,
+    // Set once `shutdown` ran, the window may be closed after :q already shut everything down
+    pub shut_down: bool,
+    // The terminal went away with `hangup: "detach"`, nothing is drawn or read anymore
+    pub detached: bool,
+    // When unsaved buffers were last written to recovery files while detached
+    pub recovery_written: Option<Instant>,
+    // Listens for `oxidy --attach` once detached
+    pub session: Option<SessionService>,
+    // The attached frontend, the input and frames of the app go through it
+    pub session_client: Option<u64>,
+    pub pending_format: Option<PendingFormat>,
+    pub remote: Option<RemoteService>,
+    pub git: GitService,
+    // When the staged versions of the open files were last read
+    pub git_checked: Option<Instant>,
+    // The commit of each line by buffer, until the file is saved
+    pub blame: HashMap<BufferId, Vec<BlameLine>>,
+    // Where the next save of a buffer writes to, from `:w <path>`
+    pub save_paths: HashMap<BufferId, String>,
+    // Buffer whose blame is shown once git answered
+    pub pending_blame: Option<BufferId>,
+    // Buffer, version and first visible line the current inlay hints were asked for
+    pub inlay_hints_requested: Option<(BufferId, u32, usize)>,
+    // Buffer and version the folding ranges were last asked for
+    pub folding_requested: Option<(BufferId, u32)>,
+    // Same for the last semantic tokens asked for just the visible rows
+    pub range_tokens_requested: Option<(BufferId, u32, usize)>,
+    // Buffers edited since their last didChange, sent together once the edits in a step are done
+    pub unsynced: Vec<BufferId>,
+    // When the semantic tokens for the last edits are asked for, every edit pushes it back
+    pub tokens_due: Option<Instant>,
 
     pub event_receiver: Receiver<EditorEvent>,
 }
@@ -43,13 +210,100 @@ impl App {
     pub fn new(size: Size, renderer: Box<dyn Renderer>, input: Box<dyn InputHandler>) -> Self {
         let commands = CommandManager::new();
         let mut plugins = PluginManager::new();
-        let lsp = None; //LspService::new();
+        let lsp = LspManager::new();
         let mut ui = UiManager::new();
         let status_bar = StatusBar::new();
         ui.add(status_bar);
         let command = Command::new();
         ui.add(command);
+        let card = Card::new("".into());
+        ui.add(card);
+        ui.add(DiagnosticsPanel::new());
+        ui.add(TransformPreview::new());
+        ui.add(DefinitionPreview::new());
+        ui.add(SignatureHelp::new());
+        ui.add(ReplPanel::new());
+        ui.add(UndoTreePanel::new());
+        ui.add(ProcessPanel::new());
+        ui.add(CallHierarchyPanel::new());
+        ui.add(ScriptedUiElement::new());
+        ui.add(CommandPalette::new());
+        ui.add(ShowCmd::new());
+        ui.add(Popups::new());
+
+        let keymap = Self::default_keymap();
+
+        let config = Config::default();
+
+        let key_repeat = KeyRepeatState {
+            last_movement: None
+        };
+
+        let (event_sender, event_receiver) = channel();
+
+        let editor = Editor::new(event_sender);
+
+        let mut app = Self {
+            size,
+            editor,
+            commands,
+            keymap,
+            plugins,
+            lsp,
+            collab: None,
+            collab_buffer: None,
+            collab_synced: (0, vec![]),
+            transform: None,
+            shell_jobs: vec![],
+            status_inputs: String::new(),
+            shown_theme: None,
+            previewing_theme: None,
+            in_hook: false,
+            command_history: CommandHistory::new(),
+            repl: None,
+            ui,
+            renderer,
+            input,
+            config,
+            key_repeat,
+            last_edit: None,
+            startup_pending: true,
+            startup_commands: vec![],
+            exit_code: 0,
+            frame_stats: FrameStats::default(),
+            pending_passphrase: None,
+            cache_warned: false,
+            preview_location: false,
+            processes: ProcessRegistry::new(),
+            processes_sampled: None,
+            calls_server: None,
+            shut_down: false,
+            detached: false,
+            recovery_written: None,
+            session: None,
+            session_client: None,
+            pending_format: None,
+            remote: None,
+            git: GitService::new(),
+            git_checked: None,
+            blame: HashMap::new(),
+            save_paths: HashMap::new(),
+            pending_blame: None,
+            inlay_hints_requested: None,
+            folding_requested: None,
+            range_tokens_requested: None,
+            unsynced: vec![],
+            tokens_due: None,
 
+            event_receiver
+        };
+
+        app.register_commands();
+        app
+    }
+
+    /// The built-in bindings, rebuilt whenever the config is reloaded.
+    fn default_keymap() -> Keymap {
         let mut keymap = Keymap::new();
 
         keymap
@@ -60,6 +314,43 @@ impl App {
                 .map("<Down>", EditorAction::MoveCursor(Direction::Down))
                 .map("<Left>", EditorAction::MoveCursor(Direction::Left))
                 .map("<Right>", EditorAction::MoveCursor(Direction::Right))
+                .map("gg", EditorAction::GotoFirstLine)
+                .map("G", EditorAction::GotoLastLine)
+                .map("<C-p>", EditorAction::CommandPalette)
+                .map("<C-o>", EditorAction::JumpBack)
+                .map("<C-i>", EditorAction::JumpForward)
+                .map("<Tab>", EditorAction::JumpForward)
+                .map("m", EditorAction::Mark(MarkCommand::Set))
+                .map("'", EditorAction::Mark(MarkCommand::Line))
+                .map("`", EditorAction::Mark(MarkCommand::Exact))
+                .map("gx", EditorAction::OpenLink)
+                .map("gf", EditorAction::GotoFile)
+                .map("<C-^>", EditorAction::AlternateBuffer)
+                .map("<C-6>", EditorAction::AlternateBuffer)
+                .map("gd", EditorAction::GotoLocation(LocationKind::Definition))
+                .map("gD", EditorAction::GotoLocation(LocationKind::Declaration))
+                .map("gy", EditorAction::GotoLocation(LocationKind::TypeDefinition))
+                .map("gp", EditorAction::PreviewLocation(LocationKind::Definition))
+                .map("za", EditorAction::ToggleFold)
+                .map("zo", EditorAction::OpenFold)
+                .map("zc", EditorAction::CloseFold)
+                .map("zf", EditorAction::CreateFold)
+                .map("]d", EditorAction::NextDiagnostic)
+                .map("[d", EditorAction::PrevDiagnostic)
+                .map("K", EditorAction::Hover)
+                .map("u", EditorAction::Undo)
+                .map("<C-r>", EditorAction::Redo)
+                .map("g-", EditorAction::Earlier)
+                .map("g+", EditorAction::Later)
+                .map("==", EditorAction::Reindent(LineScope::Line))
+                .map("=j", EditorAction::Reindent(LineScope::Down))
+                .map("=k", EditorAction::Reindent(LineScope::Up))
+                .map("=ip", EditorAction::Reindent(LineScope::Paragraph))
+                .map("=G", EditorAction::Reindent(LineScope::ToEnd))
+                .map("=gg", EditorAction::Reindent(LineScope::ToStart))
+                .map("!", EditorAction::FilterLines)
+                .map("<C-c><C-c>", EditorAction::SendToRepl(ReplScope::Paragraph))
+                .map("<C-c>l", EditorAction::SendToRepl(ReplScope::Line))
                 .map("w", EditorAction::SaveCurrentBuffer)
                 .map("q", EditorAction::QuitRequested);
         keymap.insert()
@@ -70,69 +361,337 @@ impl App {
                 .map("<Left>", EditorAction::MoveCursor(Direction::Left))
                 .map("<Right>", EditorAction::MoveCursor(Direction::Right))
                 .map("<Esc>", EditorAction::ChangeMode(EditorMode::Normal));
+        keymap.filetype_normal("md")
+                .map("<Space>x", EditorAction::ToggleCheckbox);
+        keymap.filetype_insert("md")
+                .map("<Enter>", EditorAction::ContinueList);
         keymap.command()
                 .map("<Left>", EditorAction::MoveCursor(Direction::Left))
                 .map("<Right>", EditorAction::MoveCursor(Direction::Right))
+                .map("<Up>", EditorAction::MoveCursor(Direction::Up))
+                .map("<Down>", EditorAction::MoveCursor(Direction::Down))
                 .map("<Backspace>", EditorAction::DeleteCommandChar)
                 .map("<Enter>", EditorAction::ExecuteCommand)
+                .map("<Tab>", EditorAction::CompleteCommand)
                 .map("<Esc>", EditorAction::ChangeMode(EditorMode::Normal));
 
-
-        let config = Config::default();
-
-        let key_repeat = KeyRepeatState {
-            last_movement: None
-        };
-
-        let (event_sender, event_receiver) = channel();
-
-        let editor = Editor::new(event_sender);
-
-        plugins.load_config();
-        plugins.start_watcher().unwrap();
-
-        Self {
-            size,
-            editor,
-            commands,
-            keymap,
-            plugins,
-            lsp,
-            ui,
-            renderer,
-            input,
-            config,
-            key_repeat,
-
-            event_receiver
-        }
+        keymap
     }
 
     pub fn run(&mut self) {
-        self.register_commands();
         loop {
             if !self.step() { break }
         }
     }
 
     pub fn step(&mut self) -> bool {
+        if signals::terminate_requested() {
+            log!("Terminated, shutting down");
+            self.save_on_exit();
+            self.shutdown();
+            return false;
+        }
+
+        if signals::take_hangup() {
+            if self.config.opt.hangup.as_deref() == Some("detach") {
+                log!("Terminal closed, detaching");
+                self.detached = true;
+
+                if self.session.is_none() {
+                    match SessionService::start() {
+                        Ok(session) => self.session = Some(session),
+                        Err(error) => log!("Could not listen for session clients: {}", error),
+                    }
+                }
+            } else {
+                log!("Terminal closed, shutting down");
+                self.save_on_exit();
+                self.shutdown();
+                return false;
+            }
+        }
+
+        self.poll_session();
+
+        if self.detached {
+            self.step_detached();
+            return true;
+        }
+
         self.handle_input_event();
         
         
         self.poll_plugin_events();
+        self.poll_widgets();
+        self.poll_intervals();
+        self.poll_tasks();
         self.poll_lsp_events();
+        self.poll_collab_events();
+        self.poll_transform();
+        self.poll_shell();
+        self.poll_repl();
+        self.poll_auto_save();
+        self.poll_pending_format();
+        self.poll_remote();
+        self.poll_git();
+        self.poll_semantic_tokens();
+        self.enforce_cache_budget();
 
         while let Ok(event) = self.event_receiver.try_recv() {
+            // Anything that may talk to the server has to come after the edits before it
+            if event != EditorEvent::RequestDeltaSemantics {
+                self.sync_lsp();
+            }
+
             match event {
                 EditorEvent::QuitRequested => { 
+                    self.shutdown();
                     return false;
                 }
-                EditorEvent::SaveRequested(_) => {
-                    if let Some(lsp) = self.lsp.as_mut() {
-                        let buffer = self.editor.active_buffer().unwrap();
-                        // lsp.did_change(&buffer.path, buffer.version, &buffer.text());
+                EditorEvent::QuitWithCode(code) => {
+                    self.exit_code = code;
+                    self.shutdown();
+                    return false;
+                }
+                EditorEvent::SaveRequested { id, path } => {
+                    if let Some(path) = path {
+                        self.save_paths.insert(id, path);
+                    }
+                    self.dispatch_hook("buffer_saving", id, event_details(&[]));
+                    let opt = self.buffer_options(id);
+
+                    if opt.strip_whitespace.unwrap_or(false) {
+                        self.editor.strip_trailing_whitespace(id);
+                    }
+
+                    let format = opt.format_on_save.unwrap_or(false);
+
+                    // All of them save once the formatter or the server answered
+                    let waiting = match self.formatter(id).filter(|_| format) {
+                        Some(formatter) => self.run_formatter(id, &formatter),
+                        None => (format && self.request_formatting(id, true)) || self.request_will_save(id),
+                    };
+                    if !waiting {
+                        self.save_buffer(id);
+                    }
+                }
+                EditorEvent::BufferClosed { id, path } => {
+                    if let Some(lsp) = self.lsp.for_buffer(id) {
+                        lsp.did_close(&path);
+                    }
+                    self.lsp.forget(id);
+                    self.dispatch_hook("buffer_closed", id, event_details(&[("path", path.as_str())]));
+
+                    if self.pending_format.as_ref().is_some_and(|pending| pending.buffer == id) {
+                        self.pending_format = None;
+                    }
+                    self.blame.remove(&id);
+                    if self.collab_buffer == Some(id) {
+                        self.stop_collab();
+                    }
+
+                    if let Some(status) = self.ui.get_mut::<StatusBar>() {
+                        status.file = self.editor.active_buffer().map(|b| b.name().to_string()).unwrap_or_default();
+                    }
+                }
+                EditorEvent::BufferFocused(_) => {
+                    if let Some(status) = self.ui.get_mut::<StatusBar>() {
+                        status.file = self.editor.active_buffer().map(|b| b.name().to_string()).unwrap_or_default();
+                    }
+                }
+                EditorEvent::Format => {
+                    match self.editor.active_view().map(|view| view.buffer) {
+                        Some(id) if self.request_formatting(id, false) => {}
+                        _ => crate::notify!(self.editor, Duration::from_secs(2), "No language server to format with"),
+                    }
+                }
+                EditorEvent::HostCollab { address, token } => {
+                    self.host_collab(&address, token);
+                }
+                EditorEvent::JoinCollab { address, token } => {
+                    self.join_collab(&address, token);
+                }
+                EditorEvent::StopCollab => {
+                    self.stop_collab();
+                }
+                EditorEvent::StartTransform { command, range } => {
+                    self.start_transform(&command, range);
+                }
+                EditorEvent::AcceptTransform => {
+                    self.accept_transform();
+                }
+                EditorEvent::RejectTransform => {
+                    self.reject_transform();
+                }
+                EditorEvent::RequestLocation(kind) => {
+                    self.preview_location = false;
+                    self.request_location(kind);
+                }
+                EditorEvent::PreviewLocation(kind) => {
+                    self.preview_location = true;
+                    self.request_location(kind);
+                }
+                EditorEvent::ShowCalls(direction) => {
+                    self.request_call_hierarchy(direction);
+                }
+                EditorEvent::StartRepl(command) => {
+                    self.start_repl(command);
+                }
+                EditorEvent::StopRepl => {
+                    if let Some(mut repl) = self.repl.take() {
+                        repl.stop();
+                    }
+                    if let Some(panel) = self.ui.get_mut::<ReplPanel>() {
+                        panel.shown = false;
+                    }
+                }
+                EditorEvent::SendToRepl(text) => {
+                    self.send_to_repl(&text);
+                }
+                EditorEvent::ToggleRepl => {
+                    if let Some(panel) = self.ui.get_mut::<ReplPanel>() {
+                        panel.shown = !panel.shown;
+                    }
+                }
+                EditorEvent::RequestHover => {
+                    self.request_hover();
+                }
+                EditorEvent::RequestSignatureHelp => {
+                    self.request_signature_help();
+                }
+                EditorEvent::HideSignatureHelp => {
+                    if let Some(popup) = self.ui.get_mut::<SignatureHelp>() {
+                        popup.hide();
+                    }
+                }
+                EditorEvent::Rename(new_name) => {
+                    self.request_rename(&new_name);
+                }
+                EditorEvent::ShowFrameStats => {
+                    let stats = &self.frame_stats;
+                    crate::notify!(
+                        self.editor,
+                        Duration::from_secs(6),
+                        "{} frames, {} over budget, avg {:.1?}, worst {:.1?}",
+                        stats.frames, stats.overruns, stats.average(), stats.worst
+                    );
+                }
+                EditorEvent::ShowStats => {
+                    let text = self.memory_stats();
+                    self.open_scratch(text);
+                    self.editor.set_filetype("md".into());
+                }
+                EditorEvent::ShowLspLog => {
+                    let text = self.lsp_log();
+                    self.open_scratch(text);
+                    self.editor.set_filetype("md".into());
+                }
+                EditorEvent::ShowMessages => {
+                    let text = self.messages();
+                    let last = text.lines().count().saturating_sub(1);
+                    self.open_scratch(text);
+                    self.editor.set_filetype("md".into());
+                    self.editor.set_readonly(true);
+                    self.editor.set_cursor(last, 0);
+                }
+                EditorEvent::CheckHealth => {
+                    let text = self.memory_stats() + &self.contrast_report() + &self.config_report() + &self.plugin_report();
+                    self.open_scratch(text);
+                    self.editor.set_filetype("md".into());
+                }
+                EditorEvent::ShowKeys(path) => {
+                    self.show_keys(path);
+                }
+                EditorEvent::ToggleUndoTree => {
+                    if let Some(panel) = self.ui.get_mut::<UndoTreePanel>() {
+                        panel.shown = !panel.shown;
+                    }
+                }
+                EditorEvent::ToggleProcesses => {
+                    if let Some(panel) = self.ui.get_mut::<ProcessPanel>() {
+                        panel.shown = !panel.shown;
+                    }
+                    self.processes_sampled = None;
+                }
+                EditorEvent::Reindent(start, end) => {
+                    self.reindent(start, end);
+                }
+                EditorEvent::RestartLsp => {
+                    self.restart_lsp();
+                }
+                EditorEvent::AttachLsp => {
+                    self.attach_lsp();
+                }
+                EditorEvent::DetachLsp => {
+                    self.detach_lsp();
+                }
+                EditorEvent::Export(format, path) => {
+                    self.export(format, path);
+                }
+                EditorEvent::Screenshot(path) => {
+                    let path = path.unwrap_or_else(|| format!("oxidy-{}.png", Self::timestamp()));
+                    match self.renderer.screenshot(&self.editor, &self.ui, &self.config, Path::new(&path)) {
+                        Ok(()) => crate::notify!(self.editor, Duration::from_secs(3), "Saved {}", path),
+                        Err(error) => crate::notify!(self.editor, Duration::from_secs(3), "Could not take a screenshot: {}", error),
+                    }
+                }
+                EditorEvent::StartRecording(dir) => {
+                    let dir = dir.unwrap_or_else(|| format!("oxidy-recording-{}", Self::timestamp()));
+                    match self.renderer.start_recording(dir.clone().into()) {
+                        Ok(()) => crate::notify!(self.editor, Duration::from_secs(3), "Recording frames to {}, :record stop ends it", dir),
+                        Err(error) => crate::notify!(self.editor, Duration::from_secs(3), "Could not start recording: {}", error),
+                    }
+                }
+                EditorEvent::StopRecording => {
+                    match self.renderer.stop_recording() {
+                        Some((dir, frames)) => crate::notify!(
+                            self.editor,
+                            Duration::from_secs(6),
+                            "Wrote {} frames to {}, e.g. ffmpeg -framerate 30 -i {}/%05d.png demo.gif",
+                            frames, dir.display(), dir.display()
+                        ),
+                        None => crate::notify!(self.editor, Duration::from_secs(2), "Not recording"),
+                    }
+                }
+                EditorEvent::SetList(list) => {
+                    let shown = self.config.opt.list.unwrap_or(false);
+                    self.config.opt.list = Some(list.unwrap_or(!shown));
+                }
+                EditorEvent::ShowBlame => {
+                    self.show_blame();
+                }
+                EditorEvent::RunUserCommand(invocation) => {
+                    self.run_user_command(invocation);
+                }
+                EditorEvent::ShowPalette => {
+                    let commands = self.commands.list().into_iter()
+                        .map(|(name, description)| (name.to_string(), description.to_string()))
+                        .collect();
 
-                        self.plugins.save_buffer(&buffer);
+                    if let Some(palette) = self.ui.get_mut::<CommandPalette>() {
+                        palette.open(commands);
+                    }
+                }
+                EditorEvent::PromptCommand(text) => {
+                    if let Some(command) = self.ui.get_mut::<Command>() {
+                        command.cursor = text.len();
+                        command.command = text;
+                        command.shown = true;
+                    }
+                }
+                EditorEvent::RunShell(command, target) => {
+                    self.run_shell(&command, target);
+                }
+                EditorEvent::ScaffoldConfig => {
+                    match self.plugins.scaffold_config() {
+                        Ok(path) => crate::notify!(self.editor, Duration::from_secs(4), "Created {}", path.display()),
+                        Err(error) => crate::notify!(self.editor, Duration::from_secs(4), "Could not create the config: {}", error),
+                    }
+                    self.config = self.plugins.config.clone();
+                }
+                EditorEvent::ToggleDiagnostics => {
+                    if let Some(panel) = self.ui.get_mut::<DiagnosticsPanel>() {
+                        panel.shown = !panel.shown;
                     }
                 }
                 EditorEvent::ShowCommand => {
@@ -142,7 +701,34 @@ impl App {
                         command.shown = true;
                     }
                 }
+                EditorEvent::RunCommandLine(line) => {
+                    self.execute_command_line(&line);
+                }
+                EditorEvent::CompleteCommand => {
+                    let line = self.ui.get_mut::<Command>().map(|command| command.command.clone()).unwrap_or_default();
+
+                    if self.complete_command(&line) {
+                        if let Some(command) = self.ui.get_mut::<Command>() {
+                            command.command.clear();
+                            command.cursor = 0;
+                        }
+                        self.editor.handle_action(&EditorAction::ChangeMode(EditorMode::Normal));
+                    }
+                }
+                EditorEvent::SetTheme(name) => {
+                    match name {
+                        Some(name) => self.set_theme(&name),
+                        None => { self.complete_command("theme"); }
+                    }
+                }
+                EditorEvent::ModeChanged(mode) => {
+                    let mode = format!("{:?}", mode).to_lowercase();
+                    if let Some(id) = self.editor.active_view().map(|view| view.buffer) {
+                        self.dispatch_hook("mode_changed", id, event_details(&[("mode", mode.as_str())]));
+                    }
+                }
                 EditorEvent::HideCommand => {
+                    self.command_history.reset();
                     let command = self.ui.get_mut::<Command>();
 
                     if let Some(command) = command {
@@ -158,6 +744,19 @@ impl App {
                     }
 
                 }
+                EditorEvent::CommandHistory(step) => {
+                    if let Some(command) = self.ui.get_mut::<Command>() {
+                        let recalled = match step < 0 {
+                            true => self.command_history.prev(&command.command),
+                            false => self.command_history.next(),
+                        };
+
+                        if let Some(line) = recalled {
+                            command.cursor = line.len();
+                            command.command = line;
+                        }
+                    }
+                }
                 EditorEvent::CommandCharInserted(ch) => {
                     let command = self.ui.get_mut::<Command>();
 
@@ -189,27 +788,45 @@ impl App {
                     */
                 }
                 EditorEvent::RequestDeltaSemantics => {
-                    if let Some(lsp) = self.lsp.as_mut() {
-                        let buffer = self.editor.active_buffer().unwrap();
-                        lsp.did_change(&buffer.path, buffer.version, &buffer.text());
-                        std::thread::sleep(std::time::Duration::from_millis(10));
-                        lsp.request_semantic_tokens(&buffer);
+                    self.last_edit = Some(Instant::now());
+
+                    let id = self.editor.active_view().unwrap().buffer;
+                    if !self.unsynced.contains(&id) {
+                        self.unsynced.push(id);
+                    }
+                    self.tokens_due = Some(Instant::now() + TOKENS_DELAY);
+                }
+                EditorEvent::OpenUrl(url) => {
+                    if let Err(error) = links::open_url(&url) {
+                        crate::notify!(self.editor, Duration::from_secs(4), "Could not open {}: {}", url, error);
                     }
                 }
+                EditorEvent::OpenFile { path, row, col } => {
+                    self.goto_file(path, row, col);
+                }
+                EditorEvent::ViewFile(path) => {
+                    self.goto_file(path, None, None);
+                    self.editor.set_readonly(true);
+                }
                 EditorEvent::ExecuteCommand => {
                     let command = self.ui.get_mut::<Command>();
 
-                    if let Some(command) = command {
-                        let mut cmd: Vec<String> = command.command.clone()
-                            .split(" ")
-                            .map(|s| s.to_string())
-                            .collect();
-                        
-                        let name = cmd.remove(0);
-                        self.commands.execute(&name, cmd, &mut self.editor);
+                    let line = command.map(|command| {
+                        let line = command.command.clone();
                         command.command = "".into();
                         command.cursor = 0;
                         command.shown = false;
+                        line
+                    });
+
+                    if let Some(line) = line {
+                        if let Err(error) = self.command_history.push(&line) {
+                            log!("Could not write the command history: {}", error);
+                        }
+                        if let Some(show_cmd) = self.ui.get_mut::<ShowCmd>() {
+                            show_cmd.flash(format!(":{}", line));
+                        }
+                        self.execute_command_line(&line);
                     }
                     self.editor.handle_action(&EditorAction::ChangeMode(EditorMode::Normal));
                 }
@@ -217,115 +834,3339 @@ impl App {
             }
         }
 
-        self.renderer.begin_frame();
-        self.renderer.draw_buffer(&self.editor, &self.ui, &self.config);
-        self.renderer.end_frame();
+        self.sync_lsp();
+        self.editor.record_history();
 
-        true
-    }
+        // After the events so the server has seen the latest didChange
+        self.request_inlay_hints();
+        self.request_folding_ranges();
+        self.request_visible_tokens(true);
+        self.update_diagnostics_ui();
+        self.update_progress_ui();
+        self.update_status_ui();
+        self.update_show_cmd_ui();
+        self.update_undo_tree_ui();
+        self.update_process_ui();
+        self.request_calls();
 
-    pub fn handle_input(&mut self, input: InputEvent) {
-        let mode = match self.editor.active_view() {
-            Some(view) => &view.mode,
-            None => &EditorMode::Normal
-        };
-        
-        let action = match self.keymap.resolve(input, mode) {
-            Some(a) => a,
-            None => return,
-        };
-        self.editor.handle_action(&action);
-    }
+        let notifications = self.editor.logs.drain_notifications();
+        if let Some(card) = self.ui.get_mut::<Card>() {
+            let (description, card_type) = notifications.last().cloned().unwrap_or((String::new(), CardType::INFO));
+            card.update(description, card_type);
+        }
 
-    fn handle_input_event(&mut self) {
-        let input = match self.input.poll() {
-            Ok(Some(ev)) => ev,
-            _ => return,
-        };
-        
-        self.handle_input(input);
-    }
+        self.render_frame();
 
-    fn poll_plugin_events(&mut self) {
-        self.plugins.poll_reload();
-        self.config = self.plugins.config.clone();
+        if self.startup_pending {
+            self.finish_startup();
+        }
+
+        true
     }
 
-    fn poll_lsp_events(&mut self) {
-        if let Some(lsp) = self.lsp.as_mut() {
-            match lsp.poll() {
-                LspServiceEvent::Initialized => {
-                    let buffer = self.editor.active_buffer();
-                    if let Some(buffer) = buffer {
-                        lsp.open_file(&buffer.path, &buffer.text());
-                    }
-                }
-                LspServiceEvent::OpenedFile | LspServiceEvent::ReceivedDelta => {
-                    let buffer = self.editor.active_buffer();
-                    if let Some(buffer) = buffer {
-                        lsp.request_semantic_tokens(&buffer);
-                    }
-                }
-                LspServiceEvent::ReceivedSemantics { semantics: _ } => {
-                    let theme = self.config.current_theme();
-                    let buffer = self.editor.active_buffer();
-                    if let Some(buffer) = buffer {
-                        let tokens = lsp.set_tokens(&buffer, theme);
-                        self.editor.update_tokens(tokens);
-                    }
-                }
-                _ => {}
-            }
+    fn render_frame(&mut self) {
+        let start = Instant::now();
+
+        self.apply_filetype_theme();
+        self.renderer.begin_frame();
+        self.renderer.draw_buffer(&self.editor, &self.ui, &self.config);
+
+        let latency = self.config.opt.render_latency.unwrap_or(0);
+        if latency > 0 {
+            thread::sleep(Duration::from_millis(latency));
         }
+
+        self.renderer.end_frame();
+
+        let budget = Duration::from_millis(self.config.opt.frame_budget.unwrap_or(16));
+        self.frame_stats.record(start.elapsed(), budget);
     }
 
-    pub fn open_file(&mut self, path: String) {
+    /// Loads the user config, starts the watcher and spawns the LSP.
+    /// Runs after the first frame so the editor shows up immediately with the defaults.
+    fn finish_startup(&mut self) {
+        self.startup_pending = false;
+
+        self.plugins.reload_config();
+        if let Err(error) = self.plugins.start_watcher() {
+            log!("Could not watch config: {}", error);
+        }
         self.config = self.plugins.config.clone();
-        let content = std::fs::read_to_string(&path)
-            .expect("Failed to open file");
+        self.editor.set_syntax_rules(self.plugins.syntax_rules());
+        self.editor.set_filetypes(self.plugins.filetype_configs());
+        self.editor.set_theme_colors(self.config.current_theme().to_map());
+        self.shown_theme = None;
+        self.commands.set_user_commands(self.plugins.user_commands());
+        self.report_plugin_errors();
+        self.editor.global_marks = marks::load();
+        self.command_history = CommandHistory::load();
+        self.update_status_symbols();
+        self.apply_keymap_options();
 
-        // TODO: Calculate size based on opened buffers
-        let buffer_size = Size {
-            cols: self.size.cols.clone(),
-            rows: self.size.rows.clone() - self.ui.top_offset() as u16
-        };
+        if !self.plugins.has_config_dir() {
+            crate::notify!(self.editor, Duration::from_secs(8), "No config found, :config init creates {}", self.plugins.config_dir().display());
+        }
 
-        self.editor.open_buffer(path.clone(), content, buffer_size);
+        // Before anything sees the text, files from the command line may turn out encrypted
+        self.detect_encrypted_buffers();
 
-        let status = self.ui.get_mut::<StatusBar>();
+        let path = self.editor.active_buffer().map(|buffer| buffer.path.clone());
+        if let Some(path) = path {
+            self.start_lsp(&path);
+        }
 
-        if let Some(status) = status {
-            status.file = path.to_string().clone();
+        // Files from the command line were opened before the config was loaded
+        for id in self.editor.buffer_ids() {
+            let path = self.editor.buffer(&id).map(|buffer| buffer.path.clone()).unwrap_or_default();
+            if !path.is_empty() {
+                self.dispatch_hook("file_opened", id, event_details(&[("path", path.as_str())]));
+            }
         }
 
-        // autostart lsp if configured
-        let file_type_index = path.to_string().rfind(".");
-        if let Some(file_type_index) = file_type_index {
-            let file_type = &path[file_type_index + 1..];
-            log!("File type: {}", file_type);
+        // Last so they can use what the config defines
+        for command in std::mem::take(&mut self.startup_commands) {
+            self.execute_command_line(&command);
+        }
+    }
 
-            // log!("{:?}", self.config.lsps);
-            if let Some(lsp_config) = self.config.lsps.get(file_type) {
-                log!("Starting lsp.");
-                eprintln!("STARTING.");
-                self.lsp = LspService::new(lsp_config.command.clone(), lsp_config.args.clone());
+    /// Runs an ex command line like "w foo.rs" or "set ft=rust".
+    pub fn execute_command_line(&mut self, line: &str) {
+        let invocations = match cmdline::parse(line.trim(), &self.editor.addresses(), &self.editor.expansions()) {
+            Ok(invocations) => invocations,
+            Err(error) => {
+                crate::notify!(self.editor, Duration::from_secs(2), "{}", error);
+                return;
             }
+        };
 
-            if let Some(lsp) = self.lsp.as_mut() {
-                let root_index = path.rfind("/").unwrap();
-                let root_uri = &path[0..root_index];
-                lsp.initialize(&root_uri);
+        for invocation in invocations {
+            match (invocation.name.is_empty(), invocation.bang, invocation.range) {
+                // `:!cmd` shows the output, with a range the lines are filtered through it
+                (true, true, Some(range)) => self.filter_lines(&invocation.raw, range),
+                (true, true, None) => self.run_shell(&invocation.raw, ShellTarget::Show),
+                // A range alone goes to its last line
+                (true, false, Some(range)) => {
+                    self.editor.push_jump();
+                    self.editor.set_cursor(range.end, 0);
+                }
+                (true, false, None) => {}
+                _ => {
+                    if let Err(error) = self.commands.execute(invocation, &mut self.editor) {
+                        crate::notify!(self.editor, Duration::from_secs(2), "{}", error);
+                    }
+                }
             }
         }
     }
 
+    pub fn handle_input(&mut self, input: InputEvent) {
+        match input {
+            InputEvent::FocusGained => {
+                self.handle_focus_gained();
+                return;
+            }
+            InputEvent::FocusLost => {
+                self.handle_focus_lost();
+                return;
+            }
+            InputEvent::Paste(text) => {
+                // Never let a paste end up in the buffer while a passphrase is typed
+                if self.pending_passphrase.is_none() {
+                    self.paste(&text);
+                }
+                return;
+            }
+            InputEvent::Mouse(kind, modifiers) => {
+                self.handle_mouse(kind, modifiers);
+                return;
+            }
+            InputEvent::Resize(cols, rows) => {
+                self.resize(Size { cols, rows });
+                return;
+            }
+            _ => {}
+        }
+
+        if self.pending_passphrase.is_some() {
+            self.handle_passphrase_input(input);
+            return;
+        }
+
+        if self.handle_palette_input(&input) {
+            return;
+        }
+
+        if self.handle_hover_input(&input) {
+            return;
+        }
+
+        if self.handle_preview_input(&input) {
+            return;
+        }
+
+        if self.handle_undo_tree_input(&input) {
+            return;
+        }
+
+        if self.handle_process_input(&input) {
+            return;
+        }
+
+        if self.handle_call_hierarchy_input(&input) {
+            return;
+        }
+
+        // The key after `m`, `'` or a backtick names the mark
+        if let InputEvent::Key { key, modifiers } = &input {
+            if let Some(command) = self.editor.pending_mark.take() {
+                match key {
+                    Key::Char(name) if !modifiers.ctrl && !modifiers.alt => self.editor.use_mark(command, *name),
+                    _ => {}
+                }
+                return;
+            }
+        }
+
+        // `=` re-indents the mouse selection right away, any other key clears it
+        if let InputEvent::Key { key: Key::Char('='), modifiers } = &input {
+            let normal = self.editor.active_view().is_some_and(|view| view.mode == EditorMode::Normal);
+
+            if normal && !modifiers.ctrl && !modifiers.alt && self.editor.selected_text().is_some() {
+                if let Some((start, end)) = self.editor.operator_rows(LineScope::Line) {
+                    self.reindent(start, end);
+                }
+                self.editor.clear_selection();
+                return;
+            }
+        }
+
+        if let InputEvent::Key { key: Key::Esc, .. } = input {
+            if let Some(popup) = self.ui.get_mut::<SignatureHelp>() {
+                popup.hide();
+            }
+        }
+
+        let mode = match self.editor.active_view() {
+            Some(view) => &view.mode,
+            None => &EditorMode::Normal
+        };
+        let filetype = self.editor.active_buffer().map(|buffer| buffer.filetype.as_str()).unwrap_or("");
+        
+        let is_key = matches!(input, InputEvent::Key { .. });
+        let action = match self.keymap.resolve(input, mode, filetype) {
+            Some(a) => a,
+            None => {
+                // `zf` folds the selection, it is kept while a mapping is being typed
+                if is_key && self.keymap.pending_keys().is_empty() {
+                    self.editor.clear_selection();
+                }
+                return;
+            }
+        };
+
+        if is_key && action != EditorAction::CreateFold && action != EditorAction::FilterLines {
+            self.editor.clear_selection();
+        }
+
+        // The start of a typed sequence like "jk" went into the buffer already
+        for _ in 0..self.keymap.take_provisional() {
+            self.editor.handle_action(&EditorAction::DeleteChar);
+        }
+        self.editor.handle_action(&action);
+    }
+
+    fn handle_passphrase_input(&mut self, input: InputEvent) {
+        let key = match input {
+            InputEvent::Key { key, .. } => key,
+            _ => return,
+        };
+
+        let command = match self.ui.get_mut::<Command>() {
+            Some(command) => command,
+            None => return,
+        };
+
+        match key {
+            Key::Char(ch) => {
+                command.command.push(ch);
+                command.cursor += 1;
+            }
+            Key::Backspace => {
+                if command.command.pop().is_some() {
+                    command.cursor -= 1;
+                }
+            }
+            Key::Esc => {
+                command.take_prompt();
+                self.pending_passphrase = None;
+            }
+            Key::Enter => {
+                let passphrase = command.take_prompt();
+
+                if let Some((path, mut encryption)) = self.pending_passphrase.take() {
+                    encryption.passphrase = Some(passphrase);
+                    self.open_encrypted(path, encryption);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Types into the command palette and runs the picked command on Enter. Tab puts it on
+    /// the command line instead, to add arguments. Returns true if the input was used up by it.
+    fn handle_palette_input(&mut self, input: &InputEvent) -> bool {
+        let palette = match self.ui.get_mut::<CommandPalette>() {
+            Some(palette) if palette.shown => palette,
+            _ => return false,
+        };
+
+        match input {
+            InputEvent::Key { key: Key::Down, .. } | InputEvent::Key { key: Key::Char('n'), modifiers: Modifiers { ctrl: true, .. } } => palette.move_selection(1),
+            InputEvent::Key { key: Key::Up, .. } | InputEvent::Key { key: Key::Char('p'), modifiers: Modifiers { ctrl: true, .. } } => palette.move_selection(-1),
+            InputEvent::Scroll(Direction::Down) => palette.move_selection(1),
+            InputEvent::Scroll(Direction::Up) => palette.move_selection(-1),
+            InputEvent::Key { key: Key::Backspace, .. } => palette.pop(),
+            InputEvent::Key { key: Key::Char(ch), modifiers } if !modifiers.ctrl && !modifiers.alt => palette.push(*ch),
+            InputEvent::Key { key: Key::Enter, .. } | InputEvent::Key { key: Key::Tab, .. } if palette.completing.is_some() => {
+                let item = palette.selected_command().map(String::from);
+                let command = palette.completing.take().unwrap_or_default();
+                palette.shown = false;
+
+                match item {
+                    Some(item) => self.execute_command_line(&format!("{} {}", command, item)),
+                    None => self.end_theme_preview(),
+                }
+            }
+            InputEvent::Key { key: Key::Enter, .. } | InputEvent::Key { key: Key::Tab, .. } if palette.picker.is_some() => {
+                let item = palette.selected_command().map(String::from);
+                let picker = palette.picker.take().unwrap_or_default();
+                palette.shown = false;
+
+                match item {
+                    Some(item) => self.run_pick(&picker, item),
+                    None => { self.plugins.widgets.close(&picker); }
+                }
+            }
+            InputEvent::Key { key: Key::Enter, .. } | InputEvent::Key { key: Key::Tab, .. } => {
+                let name = palette.selected_command().map(String::from);
+                palette.shown = false;
+
+                match (name, input) {
+                    (Some(name), InputEvent::Key { key: Key::Tab, .. }) => {
+                        self.editor.handle_action(&EditorAction::ChangeMode(EditorMode::Command));
+                        self.editor.event_sender.send(EditorEvent::PromptCommand(format!("{} ", name)));
+                    }
+                    (Some(name), _) => self.execute_command_line(&name),
+                    (None, _) => {}
+                }
+            }
+            InputEvent::Key { key: Key::Esc, .. } => {
+                palette.shown = false;
+                palette.completing = None;
+                if let Some(picker) = palette.picker.take() {
+                    self.plugins.widgets.close(&picker);
+                }
+                self.end_theme_preview();
+            }
+            _ => {}
+        }
+
+        self.preview_theme();
+        true
+    }
+
+    /// Lists what can go after the command of `line` in the palette, the command line runs
+    /// with what is picked. Returns false if the command takes nothing that can be listed.
+    fn complete_command(&mut self, line: &str) -> bool {
+        let (name, query) = line.trim_start().split_once(' ').unwrap_or((line.trim(), ""));
+
+        let (items, current) = match name {
+            "theme" => (self.config.theme_names(), self.plugins.config.theme.clone()),
+            _ => return false,
+        };
+
+        if let Some(palette) = self.ui.get_mut::<CommandPalette>() {
+            palette.open_completion(name, items, query.trim());
+            if let Some(current) = current {
+                palette.select(&current);
+            }
+        }
+
+        true
+    }
+
+    /// Shows the theme selected in the `:theme` list right away.
+    fn preview_theme(&mut self) {
+        let selected = match self.ui.get_mut::<CommandPalette>() {
+            Some(palette) if palette.shown && palette.completing.as_deref() == Some("theme") => palette.selected_command().map(String::from),
+            _ => return,
+        };
+
+        if let Some(name) = selected {
+            if self.previewing_theme.is_none() {
+                self.previewing_theme = self.plugins.config.theme.clone();
+            }
+            self.switch_theme(&name);
+        }
+    }
+
+    /// Goes back to the theme from before the preview, when the list was closed without a pick.
+    fn end_theme_preview(&mut self) {
+        if let Some(name) = self.previewing_theme.take() {
+            self.switch_theme(&name);
+        }
+    }
+
+    /// `:theme <name>`, the theme stays until the config is changed.
+    fn set_theme(&mut self, name: &str) {
+        self.previewing_theme = None;
+
+        if !self.config.theme_names().iter().any(|theme| theme == name) {
+            crate::notify!(self.editor, Duration::from_secs(3), "No theme {}, :theme lists them", name);
+            return;
+        }
+
+        self.switch_theme(name);
+    }
+
+    /// Draws everything in theme `name` from the next frame on.
+    fn switch_theme(&mut self, name: &str) {
+        if self.plugins.config.theme.as_deref() == Some(name) { return }
+
+        self.plugins.config.theme = Some(name.to_string());
+        self.config.theme = Some(name.to_string());
+        self.editor.set_theme_colors(self.config.current_theme().to_map());
+        self.shown_theme = None;
+        self.update_status_symbols();
+        self.renderer.invalidate();
+    }
+
+    /// Scrolls or closes an open hover popup. Returns true if the input was used up by it.
+    /// Other keys close the popup and are handled as usual.
+    fn handle_hover_input(&mut self, input: &InputEvent) -> bool {
+        let popups = match self.ui.get_mut::<Popups>() {
+            Some(popups) if popups.is_shown(HOVER_POPUP) => popups,
+            _ => return false,
+        };
+
+        let amount = match input {
+            InputEvent::Key { key: Key::Down, .. } | InputEvent::Key { key: Key::Char('j'), .. } => 1,
+            InputEvent::Key { key: Key::Up, .. } | InputEvent::Key { key: Key::Char('k'), .. } => -1,
+            InputEvent::Key { key: Key::PageDown, .. } => 8,
+            InputEvent::Key { key: Key::PageUp, .. } => -8,
+            InputEvent::Scroll(Direction::Down) => 1,
+            InputEvent::Scroll(Direction::Up) => -1,
+            InputEvent::Key { key: Key::Esc, .. } | InputEvent::Key { key: Key::Char('q'), .. } => {
+                popups.hide(HOVER_POPUP);
+                return true;
+            }
+            _ => {
+                popups.hide(HOVER_POPUP);
+                return false;
+            }
+        };
+
+        if let Some(popup) = popups.get_mut(HOVER_POPUP) {
+            popup.scroll_by(amount);
+        }
+        true
+    }
+
+    /// Scrolls the definition preview, Enter jumps to the definition. Returns true if the
+    /// input was used up by it, other keys close the preview and are handled as usual.
+    fn handle_preview_input(&mut self, input: &InputEvent) -> bool {
+        let preview = match self.ui.get_mut::<DefinitionPreview>() {
+            Some(preview) if preview.shown => preview,
+            _ => return false,
+        };
+
+        match input {
+            InputEvent::Key { key: Key::Down, .. } | InputEvent::Key { key: Key::Char('j'), .. } => preview.scroll_by(1),
+            InputEvent::Key { key: Key::Up, .. } | InputEvent::Key { key: Key::Char('k'), .. } => preview.scroll_by(-1),
+            InputEvent::Key { key: Key::PageDown, .. } => preview.scroll_by(8),
+            InputEvent::Key { key: Key::PageUp, .. } => preview.scroll_by(-8),
+            InputEvent::Scroll(Direction::Down) => preview.scroll_by(1),
+            InputEvent::Scroll(Direction::Up) => preview.scroll_by(-1),
+            InputEvent::Key { key: Key::Esc, .. } | InputEvent::Key { key: Key::Char('q'), .. } => preview.hide(),
+            InputEvent::Key { key: Key::Enter, .. } => {
+                let target = preview.target.take();
+                preview.hide();
+
+                if let Some(target) = target {
+                    self.jump_to_target(target);
+                }
+            }
+            _ => {
+                preview.hide();
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Moves through the undo tree panel and jumps to the selected state.
+    /// Other keys close the panel and are handled as usual.
+    fn handle_undo_tree_input(&mut self, input: &InputEvent) -> bool {
+        let panel = match self.ui.get_mut::<UndoTreePanel>() {
+            Some(panel) if panel.shown => panel,
+            _ => return false,
+        };
+
+        match input {
+            InputEvent::Key { key: Key::Down, .. } | InputEvent::Key { key: Key::Char('j'), .. } => panel.move_selection(1),
+            InputEvent::Key { key: Key::Up, .. } | InputEvent::Key { key: Key::Char('k'), .. } => panel.move_selection(-1),
+            InputEvent::Scroll(Direction::Down) => panel.move_selection(1),
+            InputEvent::Scroll(Direction::Up) => panel.move_selection(-1),
+            InputEvent::Key { key: Key::Enter, .. } => {
+                if let Some(node) = panel.selected_node() {
+                    self.editor.jump_history(node);
+                }
+            }
+            InputEvent::Key { key: Key::Esc, .. } | InputEvent::Key { key: Key::Char('q'), .. } => panel.shown = false,
+            _ => {
+                panel.shown = false;
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Moves through the `:ps` panel, restarts or kills the selected process.
+    /// Other keys close the panel and are handled as usual.
+    fn handle_process_input(&mut self, input: &InputEvent) -> bool {
+        let panel = match self.ui.get_mut::<ProcessPanel>() {
+            Some(panel) if panel.shown => panel,
+            _ => return false,
+        };
+
+        match input {
+            InputEvent::Key { key: Key::Down, .. } | InputEvent::Key { key: Key::Char('j'), .. } => panel.move_selection(1),
+            InputEvent::Key { key: Key::Up, .. } | InputEvent::Key { key: Key::Char('k'), .. } => panel.move_selection(-1),
+            InputEvent::Scroll(Direction::Down) => panel.move_selection(1),
+            InputEvent::Scroll(Direction::Up) => panel.move_selection(-1),
+            InputEvent::Key { key: Key::Char('r'), .. } => {
+                if let Some(owner) = panel.selected_process().map(|process| process.owner.clone()) {
+                    self.restart_process(owner);
+                }
+            }
+            InputEvent::Key { key: Key::Char('x'), .. } => {
+                if let Some(owner) = panel.selected_process().map(|process| process.owner.clone()) {
+                    self.kill_process(owner);
+                }
+            }
+            InputEvent::Key { key: Key::Esc, .. } | InputEvent::Key { key: Key::Char('q'), .. } => panel.shown = false,
+            _ => {
+                panel.shown = false;
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Moves through the call hierarchy, expands it and jumps to the selected call.
+    /// Other keys close the panel and are handled as usual.
+    fn handle_call_hierarchy_input(&mut self, input: &InputEvent) -> bool {
+        let panel = match self.ui.get_mut::<CallHierarchyPanel>() {
+            Some(panel) if panel.shown => panel,
+            _ => return false,
+        };
+
+        match input {
+            InputEvent::Key { key: Key::Down, .. } | InputEvent::Key { key: Key::Char('j'), .. } => panel.move_selection(1),
+            InputEvent::Key { key: Key::Up, .. } | InputEvent::Key { key: Key::Char('k'), .. } => panel.move_selection(-1),
+            InputEvent::Scroll(Direction::Down) => panel.move_selection(1),
+            InputEvent::Scroll(Direction::Up) => panel.move_selection(-1),
+            InputEvent::Key { key: Key::Right, .. } | InputEvent::Key { key: Key::Char('l'), .. } | InputEvent::Key { key: Key::Tab, .. } => panel.set_expanded(true),
+            InputEvent::Key { key: Key::Left, .. } | InputEvent::Key { key: Key::Char('h'), .. } => panel.set_expanded(false),
+            InputEvent::Key { key: Key::Enter, .. } => {
+                if let Some(location) = panel.selected_call().map(|call| call.location.clone()) {
+                    self.load_background(&location.path);
+                    self.jump_to_target(PreviewTarget { path: location.path, row: location.row, col: location.col });
+                }
+            }
+            InputEvent::Key { key: Key::Esc, .. } | InputEvent::Key { key: Key::Char('q'), .. } => panel.shown = false,
+            _ => {
+                panel.shown = false;
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn handle_mouse(&mut self, kind: MouseType, modifiers: Modifiers) {
+        match kind {
+            MouseType::Move(col, row) => {
+                self.editor.hover = self.screen_to_buffer(col, row);
+            }
+            MouseType::Down(MouseButton::Left, col, row) => {
+                // The REPL output is drawn over the buffer
+                let panel_link = self.ui.get::<ReplPanel>()
+                    .filter(|_| modifiers.ctrl)
+                    .and_then(|panel| panel.link_at(self.size.rows as usize, row as usize, col as usize));
+                if let Some(link) = panel_link {
+                    self.editor.open_link(link.target);
+                    return;
+                }
+
+                let pos = match self.screen_to_buffer(col, row) {
+                    Some(pos) => pos,
+                    None => return
+                };
+
+                if modifiers.ctrl {
+                    self.editor.follow_link_at(pos.row, pos.col, false);
+                } else {
+                    self.editor.start_selection(pos.row, pos.col);
+                }
+            }
+            MouseType::Drag(MouseButton::Left, col, row) => {
+                if let Some(pos) = self.screen_to_buffer(col, row) {
+                    self.editor.extend_selection(pos.row, pos.col);
+                }
+            }
+            MouseType::Up(MouseButton::Left, _, _) => {
+                match self.editor.selected_text() {
+                    Some(text) if self.primary_selection_enabled() => {
+                        if let Err(error) = clipboard::copy(ClipboardKind::Primary, &text) {
+                            log!("Could not set the primary selection: {}", error);
+                        }
+                    }
+                    Some(_) => {}
+                    None => self.editor.clear_selection(),
+                }
+            }
+            MouseType::Down(MouseButton::Middle, col, row) => {
+                if !self.primary_selection_enabled() { return }
+
+                let pos = match self.screen_to_buffer(col, row) {
+                    Some(pos) => pos,
+                    None => return
+                };
+
+                match clipboard::paste(ClipboardKind::Primary) {
+                    Ok(text) => {
+                        self.editor.clear_selection();
+                        self.editor.set_cursor(pos.row, pos.col);
+                        self.paste(&text);
+                    }
+                    Err(error) => crate::notify!(self.editor, Duration::from_secs(2), "Could not paste: {}", error),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Converts a terminal cell position into a position inside the active buffer.
+    fn screen_to_buffer(&self, col: u16, row: u16) -> Option<Cursor> {
+        let view = self.editor.active_view()?;
+        let buffer = self.editor.active_buffer()?;
+        let row = (row as usize).checked_sub(self.ui.top_offset())?;
+        let col = (col as usize).checked_sub(GUTTER_WIDTH as usize)?;
+
+        // Past the end of the buffer clicks land on the last line
+        let rows = view.screen_rows(buffer.lines.len());
+        let row = rows.get(row).or(rows.last()).copied()?;
+
+        Some(Cursor {
+            row,
+            col: col + view.scroll.horizontal
+        })
+    }
+
+    fn handle_input_event(&mut self) {
+        let input = match self.input.poll() {
+            Ok(Some(ev)) => ev,
+            _ => return,
+        };
+        
+        self.handle_input(input);
+    }
+
+    fn poll_plugin_events(&mut self) {
+        // `finish_startup` loads the config the first time
+        if self.startup_pending { return }
+
+        let reloaded = self.plugins.poll_reload();
+        let previous = std::mem::replace(&mut self.config, self.plugins.config.clone());
+
+        if reloaded {
+            self.config_reloaded(&previous);
+        }
+    }
+
+    /// Adds the bindings that come from options to the keymap.
+    fn apply_keymap_options(&mut self) {
+        let timeout = self.config.opt.sequence_timeout.unwrap_or(300);
+        self.keymap.set_typed_timeout(Duration::from_millis(timeout));
+
+        if let Some(keys) = self.config.opt.escape_sequence.clone() {
+            self.keymap.map_typed(&keys, EditorAction::ChangeMode(EditorMode::Normal));
+        }
+
+        // Over the defaults, a mapping that can't be parsed would otherwise just never fire
+        let mut errors: Vec<String> = vec![];
+        for (mode, bindings) in self.config.keymap.modes() {
+            for (keys, value) in bindings {
+                match KeyCombo::parse_sequence_strict(keys) {
+                    Ok(_) => { self.keymap.mode(&mode).map(keys, KeymapConfig::action(value)); }
+                    Err(error) => errors.push(error),
+                }
+            }
+        }
+
+        for error in &errors {
+            log!("Invalid keymap: {}", error);
+        }
+        if let Some(error) = errors.first() {
+            crate::notify!(self.editor, Duration::from_secs(5), "Invalid keymap: {}", error);
+        }
+    }
+
+    /// Shows what went wrong loading config.rhai and the plugins, the rest of them still work.
+    fn report_plugin_errors(&mut self) {
+        let errors = self.plugins.take_errors();
+
+        let message = match errors.as_slice() {
+            [] => return,
+            [error] => error.clone(),
+            [error, rest @ ..] => format!("{} (and {} more, see :checkhealth)", error, rest.len()),
+        };
+        self.editor.logs.push_notification_as(message, Duration::from_secs(8), CardType::ERROR);
+    }
+
+    /// Uses the configured status bar components and the caps the terminal can draw,
+    /// or the configured fallbacks.
+    fn update_status_symbols(&mut self) {
+        let statusbar = self.config.statusbar.clone().unwrap_or_default();
+        let (left, right) = statusbar.symbols();
+        let colors = self.config.current_theme().to_map();
+
+        if let Some(status) = self.ui.get_mut::<StatusBar>() {
+            status.left_symbol = left;
+            status.right_symbol = right;
+            status.components = statusbar.components;
+            status.colors = colors;
+        }
+        // The snippets may have changed too
+        self.status_inputs.clear();
+    }
+
+    /// Brings what was built from the config up to date after it changed on disk.
+    fn config_reloaded(&mut self, previous: &Config) {
+        self.editor.set_syntax_rules(self.plugins.syntax_rules());
+        self.editor.set_filetypes(self.plugins.filetype_configs());
+        self.editor.set_theme_colors(self.config.current_theme().to_map());
+        self.shown_theme = None;
+        self.commands.set_user_commands(self.plugins.user_commands());
+        self.report_plugin_errors();
+        self.keymap = Self::default_keymap();
+        self.apply_keymap_options();
+        self.update_status_symbols();
+
+        // Running servers keep their old command until they are restarted
+        let command = |config: &Config, name: &str| config.lsps.get(name).map(|lsp| (lsp.command.clone(), lsp.args.clone()));
+        let mut changed: Vec<String> = self.lsp.servers()
+            .map(|(key, _)| key.name.clone())
+            .filter(|name| command(previous, name) != command(&self.config, name))
+            .collect();
+        changed.sort();
+        changed.dedup();
+
+        if !changed.is_empty() {
+            crate::notify!(self.editor, Duration::from_secs(6), "Language server config changed for {}, :lsp restart to apply", changed.join(", "));
+        }
+
+        // Buffers without a server may have gotten one configured
+        let paths: Vec<String> = self.editor.buffer_ids().into_iter()
+            .filter(|id| self.lsp.key_for(*id).is_none())
+            .filter_map(|id| self.editor.buffer(&id).map(|buffer| buffer.path.clone()))
+            .filter(|path| !path.is_empty())
+            .collect();
+
+        for path in paths {
+            self.start_lsp(&path);
+        }
+    }
+
+    /// Lets `oxidy --remote` invocations open their files here.
+    pub fn start_remote(&mut self) {
+        match RemoteService::start() {
+            Ok(remote) => self.remote = Some(remote),
+            Err(error) => log!("Could not listen for remote files: {}", error),
+        }
+    }
+
+    fn poll_remote(&mut self) {
+        let requests = match &self.remote {
+            Some(remote) => remote.poll(),
+            None => return
+        };
+
+        for request in requests {
+            self.goto_file(request.path, request.line.map(|line| line.saturating_sub(1)), request.col.map(|col| col.saturating_sub(1)));
+            self.editor.center_cursor();
+        }
+    }
+
+    /// Hands what git answered with to the buffers and the status bar and asks again now and
+    /// then. The signs of the active buffer follow its edits.
+    fn poll_git(&mut self) {
+        for update in self.git.poll() {
+            match update {
+                GitUpdate::Staged(id, base) => match self.editor.buffer_mut(&id) {
+                    // The index has the encrypted file
+                    Some(buffer) if buffer.encryption.is_none() => buffer.set_git_base(base),
+                    _ => {}
+                },
+                GitUpdate::Blame(id, lines) => {
+                    let shown = self.pending_blame == Some(id);
+                    if shown {
+                        self.pending_blame = None;
+                    }
+
+                    match lines {
+                        Some(lines) => {
+                            self.blame.insert(id, lines);
+                            if shown {
+                                self.show_blame();
+                            }
+                        }
+                        None if shown => crate::notify!(self.editor, Duration::from_secs(2), "The file is not tracked by git"),
+                        None => {}
+                    }
+                }
+                GitUpdate::Status(status) => {
+                    if let Some(bar) = self.ui.get_mut::<StatusBar>() {
+                        bar.git = status;
+                    }
+                }
+            }
+        }
+
+        let due = self.git_checked.is_none_or(|checked| checked.elapsed() >= GIT_INTERVAL);
+        if due {
+            self.git_checked = Some(Instant::now());
+            self.request_git_status();
+
+            for id in self.editor.buffer_ids() {
+                self.request_git_base(id);
+            }
+        }
+
+        let active = self.editor.active_view().map(|view| view.buffer);
+        if let Some(buffer) = active.and_then(|id| self.editor.buffer_mut(&id)) {
+            buffer.update_git_signs();
+        }
+    }
+
+    /// Shows the commit that last changed the cursor line, blaming the file first if it
+    /// wasn't since it was opened or saved.
+    fn show_blame(&mut self) {
+        let (id, row) = match self.editor.active_view() {
+            Some(view) => (view.buffer, view.cursor.row),
+            None => return
+        };
+
+        let line = match self.blame.get(&id) {
+            Some(lines) => lines.get(row).map(|line| line.describe()).unwrap_or_else(|| "Not committed yet".to_string()),
+            None => {
+                match self.editor.buffer(&id) {
+                    Some(buffer) if !buffer.is_scratch() && buffer.encryption.is_none() => {
+                        self.git.request_blame(id, &buffer.path);
+                        self.pending_blame = Some(id);
+                    }
+                    _ => crate::notify!(self.editor, Duration::from_secs(2), "Only files can be blamed"),
+                }
+                return;
+            }
+        };
+
+        self.show_hover(line);
+    }
+
+    /// Runs a command from config.rhai on a copy of the active buffer and applies what the
+    /// script changed once it returned. Nothing is applied when it fails.
+    fn run_user_command(&mut self, invocation: CommandInvocation) {
+        let range = invocation.range.map(|range| (range.start, range.end));
+        self.run_script(&invocation.name, range, |plugins, api| plugins.call_command(&invocation.name, api, invocation.args.clone()));
+    }
+
+    /// Calls the callback of the script picker `name` with the line that was picked.
+    fn run_pick(&mut self, name: &str, item: String) {
+        let Some(picker) = self.plugins.widgets.close(name) else { return };
+        self.run_script(&picker.title, None, |plugins, api| plugins.call_pick(&picker, api, item));
+    }
+
+    /// Runs `call` with a copy of the active buffer for `editor`, errors are shown under `label`.
+    fn run_script(&mut self, label: &str, range: Option<(usize, usize)>, call: impl FnOnce(&PluginManager, EditorApi) -> Result<(), String>) {
+        let Some(id) = self.editor.active_view().map(|view| view.buffer) else { return };
+        let Some(mut state) = self.script_state(id) else { return };
+        state.range = range;
+
+        let api = EditorApi::new(state);
+
+        match call(&self.plugins, api.clone()) {
+            Ok(()) => self.apply_script_state(id, api.take()),
+            Err(error) => {
+                self.notify_script_messages(&api.take());
+                crate::notify!(self.editor, Duration::from_secs(6), "{}: {}", label, error);
+            }
+        }
+    }
+
+    /// Renders the widgets of scripts that are due and shows what changed.
+    fn poll_widgets(&mut self) {
+        if self.startup_pending { return }
+
+        self.plugins.refresh_widgets();
+        if !self.plugins.widgets.take_changed() { return }
+
+        let widgets = self.plugins.widgets.all();
+
+        if let Some(scripted) = self.ui.get_mut::<ScriptedUiElement>() {
+            scripted.set_widgets(&widgets);
+        }
+
+        if let Some(status) = self.ui.get_mut::<StatusBar>() {
+            status.segments = widgets.iter()
+                .filter(|widget| widget.kind == WidgetKind::Status)
+                .map(|widget| (widget.name.clone(), widget.lines.join(" ")))
+                .collect();
+        }
+
+        let picker = widgets.iter().find(|widget| widget.kind == WidgetKind::Picker);
+        if let Some(palette) = self.ui.get_mut::<CommandPalette>() {
+            match picker {
+                Some(picker) if !palette.shown || palette.picker.as_deref() != Some(picker.name.as_str()) => {
+                    palette.open_picker(&picker.name, &picker.title, picker.lines.clone());
+                }
+                Some(_) => {}
+                None if palette.picker.is_some() => {
+                    palette.picker = None;
+                    palette.shown = false;
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Calls the callbacks of `set_interval` that are due.
+    fn poll_intervals(&mut self) {
+        if self.startup_pending { return }
+
+        for interval in self.plugins.tasks.due() {
+            self.run_script("set_interval", None, |plugins, api| plugins.call_interval(&interval, api));
+        }
+    }
+
+    /// Hands the output of `spawn_task` commands that exited to their callbacks.
+    fn poll_tasks(&mut self) {
+        for (task, output) in self.plugins.tasks.poll() {
+            self.run_script("spawn_task", None, |plugins, api| plugins.finish_task(task, output, api));
+        }
+    }
+
+    /// Runs the config's callbacks for `event` on buffer `id`. A closed buffer only has its path.
+    fn dispatch_hook(&mut self, event: &str, id: BufferId, details: rhai::Map) {
+        if self.in_hook || !self.plugins.has_hooks(event) { return }
+
+        let state = self.script_state(id).unwrap_or_default();
+        let api = EditorApi::new(state);
+
+        self.in_hook = true;
+        match self.plugins.call_hooks(event, api.clone(), details) {
+            Ok(()) if self.editor.buffer(&id).is_some() => self.apply_script_state(id, api.take()),
+            Ok(()) => self.notify_script_messages(&api.take()),
+            Err(error) => {
+                self.notify_script_messages(&api.take());
+                crate::notify!(self.editor, Duration::from_secs(6), "{} hook: {}", event, error);
+            }
+        }
+        self.in_hook = false;
+    }
+
+    /// What a script sees of buffer `id`, the cursor is the active view's if it shows it.
+    fn script_state(&self, id: BufferId) -> Option<ScriptState> {
+        let buffer = self.editor.buffer(&id)?;
+        let cursor = self.editor.active_view()
+            .filter(|view| view.buffer == id)
+            .map(|view| (view.cursor.row, view.cursor.col))
+            .unwrap_or((0, 0));
+
+        Some(ScriptState {
+            lines: buffer.lines.clone(),
+            row: cursor.0,
+            col: cursor.1,
+            path: buffer.path.clone(),
+            filetype: buffer.filetype.clone(),
+            readonly: buffer.readonly,
+            ..ScriptState::default()
+        })
+    }
+
+    fn notify_script_messages(&mut self, state: &ScriptState) {
+        for message in &state.messages {
+            crate::notify!(self.editor, Duration::from_secs(4), "{}", message);
+        }
+    }
+
+    /// Applies what a script that returned without error changed in buffer `id`.
+    fn apply_script_state(&mut self, id: BufferId, state: ScriptState) {
+        self.notify_script_messages(&state);
+
+        if state.lines_changed {
+            match state.readonly {
+                true => crate::notify!(self.editor, Duration::from_secs(2), "Buffer is read-only"),
+                false => self.editor.replace_lines(id, state.lines),
+            }
+        }
+        let active = self.editor.active_view().is_some_and(|view| view.buffer == id);
+        if state.cursor_changed && active {
+            self.editor.set_cursor(state.row, state.col);
+        }
+
+        for line in state.commands {
+            self.execute_command_line(&line);
+        }
+    }
+
+    /// Asks for the branch of the active file's repository, or the working directory's.
+    fn request_git_status(&self) {
+        let dir = self.editor.active_buffer()
+            .filter(|buffer| !buffer.is_scratch())
+            .and_then(|buffer| std::path::absolute(&buffer.path).ok())
+            .and_then(|path| path.parent().map(|dir| dir.to_path_buf()))
+            .unwrap_or_else(|| ".".into());
+
+        self.git.request_status(dir);
+    }
+
+    /// The options for buffer `id`, what its file type sets over the config.
+    fn buffer_options(&self, id: BufferId) -> Options {
+        self.editor.views().values()
+            .find(|view| view.buffer == id)
+            .map(|view| view.options(&self.config.opt))
+            .unwrap_or_else(|| self.config.opt.clone())
+    }
+
+    fn active_options(&self) -> Options {
+        match self.editor.active_view() {
+            Some(view) => view.options(&self.config.opt),
+            None => self.config.opt.clone(),
+        }
+    }
+
+    /// The shell command the file type of buffer `id` is formatted with.
+    fn formatter(&self, id: BufferId) -> Option<String> {
+        self.editor.views().values()
+            .find(|view| view.buffer == id)
+            .and_then(|view| view.filetype.as_ref()?.formatter.clone())
+    }
+
+    /// Pipes the buffer through `formatter`, it is saved once that exited.
+    fn run_formatter(&mut self, id: BufferId, formatter: &str) -> bool {
+        let version = match self.editor.buffer(&id) {
+            Some(buffer) => buffer.version,
+            None => return false
+        };
+
+        self.run_shell(formatter, ShellTarget::Format { buffer: id, version });
+        true
+    }
+
+    /// Puts in what the formatter made of the buffer and saves it, as it was if that failed.
+    fn finish_format(&mut self, command: &str, id: BufferId, version: u32, output: ShellOutput) {
+        if !output.success {
+            let error = output.stderr.lines().next().unwrap_or("exited with an error").to_string();
+            crate::notify!(self.editor, Duration::from_secs(4), "{}: {}", command, error);
+        } else if self.editor.buffer(&id).is_some_and(|buffer| buffer.version != version) {
+            crate::notify!(self.editor, Duration::from_secs(4), "The buffer changed while {} ran", command);
+        } else {
+            let lines: Vec<String> = output.stdout.lines().map(String::from).collect();
+            if self.editor.buffer(&id).is_some_and(|buffer| buffer.lines != lines) {
+                self.editor.replace_lines(id, lines);
+            }
+        }
+
+        if self.editor.buffer(&id).is_some() && !self.request_will_save(id) {
+            self.save_buffer(id);
+        }
+    }
+
+    /// Shows the theme the file type of the active view sets, the config's one otherwise.
+    /// `config` comes fresh from the plugins every step, so this runs every step too.
+    fn apply_filetype_theme(&mut self) {
+        let filetype = self.editor.active_view().and_then(|view| Some((view.buffer, view.filetype.as_ref()?.theme.clone()?)));
+
+        if let Some((id, theme)) = filetype {
+            let name = match theme {
+                FiletypeTheme::Named(name) => name,
+                FiletypeTheme::Colors(colors) => {
+                    let extension = self.editor.buffer(&id).map(|buffer| buffer.filetype.clone()).unwrap_or_default();
+                    let name = format!("filetype:{}", extension);
+                    let merged = colors.merge(&self.config.current_theme());
+                    self.config.themes.insert(name.clone(), merged);
+                    name
+                }
+            };
+            self.config.theme = Some(name);
+        }
+
+        // The highlighting rules are only colored again when the theme changes
+        if self.shown_theme != self.config.theme {
+            self.shown_theme = self.config.theme.clone();
+            self.editor.set_theme_colors(self.config.current_theme().to_map());
+        }
+    }
+
+    fn request_git_base(&self, id: BufferId) {
+        if !self.buffer_options(id).git_signs.unwrap_or(true) { return }
+
+        match self.editor.buffer(&id) {
+            Some(buffer) if !buffer.is_scratch() && buffer.encryption.is_none() => self.git.request(id, &buffer.path),
+            _ => {}
+        }
+    }
+
+    fn paste(&mut self, text: &str) {
+        let opt = self.active_options();
+        let reindent = opt.paste_indent.unwrap_or(true);
+        let tab_size = opt.tab_size.unwrap_or(2).max(1);
+
+        let start = self.editor.active_view().map(|view| view.cursor.row);
+        self.editor.paste(text, reindent, tab_size);
+        let end = self.editor.active_view().map(|view| view.cursor.row);
+
+        if opt.paste_format.unwrap_or(false) {
+            if let (Some(start), Some(end)) = (start, end) {
+                if end > start {
+                    self.reindent(start, end);
+                }
+            }
+        }
+    }
+
+    /// Re-indents rows of the active buffer, with the language server when it can
+    /// format ranges and with the indent engine otherwise.
+    fn reindent(&mut self, start: usize, end: usize) {
+        let id = match self.editor.active_view() {
+            Some(view) => view.buffer,
+            None => return
+        };
+
+        if self.editor.buffer(&id).is_some_and(|buffer| buffer.readonly) {
+            crate::notify!(self.editor, Duration::from_secs(2), "Buffer is read-only");
+            return;
+        }
+
+        if self.pending_format.is_some() || !self.request_range_formatting(id, start, end) {
+            let tab_size = self.buffer_options(id).tab_size.unwrap_or(2).max(1);
+            self.editor.reindent_rows(id, start, end, tab_size);
+        }
+    }
+
+    /// Writes the selection, or the whole active buffer, highlighted with the current theme.
+    /// Without a path it goes next to the file.
+    fn export(&mut self, format: ExportFormat, path: Option<String>) {
+        let (view, buffer) = match (self.editor.active_view(), self.editor.active_buffer()) {
+            (Some(view), Some(buffer)) => (view, buffer),
+            _ => return
+        };
+
+        let (start, end) = view.selection.as_ref()
+            .filter(|selection| !selection.is_empty())
+            .map(|selection| {
+                let (start, end) = selection.range();
+                ((start.row, start.col), (end.row, end.col))
+            })
+            .unwrap_or(((0, 0), (buffer.lines.len().saturating_sub(1), usize::MAX)));
+
+        let theme = self.config.current_theme();
+        let runs = export::runs(&buffer.lines, start, end, &view.highlighter, theme.foreground());
+        let text = match format {
+            ExportFormat::Html => export::to_html(&runs, buffer.name(), theme.background(), theme.foreground()),
+            ExportFormat::Ansi => export::to_ansi(&runs),
+        };
+
+        let path = path.unwrap_or_else(|| match buffer.is_scratch() {
+            true => format!("oxidy-export.{}", format.extension()),
+            false => format!("{}.{}", buffer.path, format.extension()),
+        });
+
+        match std::fs::write(&path, text) {
+            Ok(()) => crate::notify!(self.editor, Duration::from_secs(3), "Exported to {}", path),
+            Err(error) => crate::notify!(self.editor, Duration::from_secs(3), "Could not write {}: {}", path, error),
+        }
+    }
+
+    fn primary_selection_enabled(&self) -> bool {
+        cfg!(target_os = "linux") && self.config.opt.primary_selection.unwrap_or(true)
+    }
+
+    fn auto_save_enabled(&self) -> bool {
+        self.config.opt.auto_save.unwrap_or(false)
+    }
+
+    /// Returns the point in time at which pending edits will be auto-saved.
+    pub fn auto_save_deadline(&self) -> Option<Instant> {
+        if !self.auto_save_enabled() { return None }
+
+        let delay = Duration::from_millis(self.config.opt.auto_save_delay.unwrap_or(1000));
+        self.last_edit.map(|last_edit| last_edit + delay)
+    }
+
+    fn poll_auto_save(&mut self) {
+        if let Some(deadline) = self.auto_save_deadline() {
+            if Instant::now() >= deadline {
+                self.last_edit = None;
+                self.save_dirty_buffers();
+            }
+        }
+    }
+
+    fn enforce_cache_budget(&mut self) {
+        let budget = self.config.opt.cache_budget.unwrap_or(8192) * 1024;
+        let evicted = self.editor.enforce_cache_budget(budget);
+
+        if evicted > 0 {
+            log!("Evicted {} highlight cache entries to stay under {} KB", evicted, budget / 1024);
+
+            if !self.cache_warned {
+                self.cache_warned = true;
+                crate::notify!(self.editor, Duration::from_secs(4), "Highlight caches went over opt.cache_budget, see :stats");
+            }
+        }
+    }
+
+    fn memory_stats(&self) -> String {
+        let kb = |bytes: usize| format!("{:.1} KB", bytes as f64 / 1024.0);
+        let usage = self.editor.memory_usage();
+
+        let mut text = String::from("# Oxidy memory usage\n\n");
+        text.push_str("| Buffer | Text | Highlight cache | Cached lines | LSP tokens |\n| --- | --- | --- | --- | --- |\n");
+
+        for view in &usage {
+            let name = if view.path.is_empty() { "[scratch]" } else { view.path.as_str() };
+            text.push_str(&format!(
+                "| {} | {} | {} | {} | {} |\n",
+                name, kb(view.text), kb(view.cache), view.cache_entries, kb(view.tokens)
+            ));
+        }
+
+        let cache: usize = usage.iter().map(|view| view.cache).sum();
+        let budget = self.config.opt.cache_budget.unwrap_or(8192) * 1024;
+
+        text.push_str(&format!("\nHighlight caches: {} of {} (opt.cache_budget)\n", kb(cache), kb(budget)));
+
+        if self.lsp.is_empty() {
+            text.push_str("LSP: not running\n");
+        }
+        for (key, lsp) in self.lsp.servers() {
+            text.push_str(&format!("LSP {} ({}) semantic token data: {}\n", key.name, key.root, kb(lsp.memory_usage())));
+        }
+
+        text.push_str(&format!("Frames: {} rendered, {} over opt.frame_budget\n", self.frame_stats.frames, self.frame_stats.overruns));
+
+        text
+    }
+
+    /// Everything the running servers logged, one section per server.
+    fn lsp_log(&self) -> String {
+        let mut text = String::from("# Language server log\n");
+
+        if self.lsp.is_empty() {
+            text.push_str("\nNo language server is running\n");
+        }
+
+        for (key, lsp) in self.lsp.servers() {
+            text.push_str(&format!("\n## {} ({})\n\n", key.name, key.root));
+            for line in lsp.log() {
+                text.push_str(line);
+                text.push('\n');
+            }
+        }
+
+        text
+    }
+
+    /// The notifications shown so far and what was logged, newest last.
+    fn messages(&self) -> String {
+        let mut text = String::from("# Messages\n\n");
+
+        if self.editor.logs.history.is_empty() {
+            text.push_str("No messages yet\n");
+        }
+        for (card_type, message) in &self.editor.logs.history {
+            let level = match card_type {
+                CardType::INFO => "info",
+                CardType::WARNING => "warning",
+                CardType::ERROR => "error",
+            };
+            text.push_str(&format!("[{}] {}\n", level, message.replace('\n', " ")));
+        }
+
+        // Written to /tmp/oxidy.log too, where older lines are
+        text.push_str("\n# Log\n\n");
+        let recent = crate::logger::LOGGER.get().map(|logger| logger.recent()).unwrap_or_default();
+        if recent.is_empty() {
+            text.push_str("Nothing logged yet\n");
+        }
+        for line in recent {
+            text.push_str(&line);
+            text.push('\n');
+        }
+
+        text
+    }
+
+    /// Theme colors that are hard to read on the background.
+    fn contrast_report(&self) -> String {
+        let min = self.config.opt.min_contrast.unwrap_or(4.5);
+        let name = self.config.theme.clone().unwrap_or_default();
+        let low = self.config.current_theme().low_contrast(min);
+
+        let mut text = String::from("\n# Theme contrast\n\n");
+
+        if low.is_empty() {
+            text.push_str(&format!("Every color of {} has at least {:.1}:1 contrast with the background (opt.min_contrast)\n", name, min));
+            return text;
+        }
+
+        text.push_str(&format!("These colors of {} have less than {:.1}:1 contrast with the background (opt.min_contrast):\n\n", name, min));
+        text.push_str("| Color | Contrast |\n| --- | --- |\n");
+        for (key, ratio) in low {
+            text.push_str(&format!("| {} | {:.2}:1 |\n", key, ratio));
+        }
+        text.push_str("\nSet opt.enforce_contrast to raise them, or try the oldworld-deuteranopia and oldworld-tritanopia themes.\n");
+
+        text
+    }
+
+    fn config_report(&self) -> String {
+        let mut text = String::from("\n# Config\n\n");
+
+        if self.plugins.config_issues.is_empty() {
+            text.push_str(&format!("{} has no unknown keys, wrong types or bad colors\n", self.plugins.config_path.display()));
+            return text;
+        }
+
+        text.push_str("These were left out, the defaults are used for them:\n\n");
+        for issue in &self.plugins.config_issues {
+            text.push_str(&format!("- {}\n", issue));
+        }
+
+        text
+    }
+
+    fn plugin_report(&self) -> String {
+        let mut text = String::from("\n# Plugins\n\n");
+
+        if self.plugins.plugins.is_empty() {
+            text.push_str(&format!("No scripts in {} or oxidy.imports\n", self.plugins.config_dir().join("plugins").display()));
+            return text;
+        }
+
+        text.push_str("| Plugin | Path | Status |\n| --- | --- | --- |\n");
+        for plugin in &self.plugins.plugins {
+            let status = plugin.error.as_deref().unwrap_or("loaded").replace('\n', " ");
+            text.push_str(&format!("| {} | {} | {} |\n", plugin.name, plugin.path.display(), status));
+        }
+
+        text
+    }
+
+    pub fn handle_focus_lost(&mut self) {
+        if self.auto_save_enabled() {
+            self.last_edit = None;
+            self.save_dirty_buffers();
+        }
+    }
+
+    /// Coming back from another pane or window, pick up files changed there, e.g. by a `git checkout`.
+    pub fn handle_focus_gained(&mut self) {
+        self.reload_changed_buffers();
+    }
+
+    /// Reloads buffers without unsaved edits whose file changed on disk.
+    fn reload_changed_buffers(&mut self) {
+        let mut reloaded = 0;
+
+        for id in self.editor.buffer_ids() {
+            let (path, current) = match self.editor.buffer(&id) {
+                Some(buffer) if !buffer.is_dirty() && !buffer.is_scratch() && buffer.encryption.is_none() => {
+                    match buffer.file_bytes() {
+                        Ok(bytes) => (buffer.path.clone(), bytes),
+                        Err(_) => continue,
+                    }
+                }
+                _ => continue
+            };
+
+            let bytes = match std::fs::read(&path) {
+                Ok(bytes) if bytes != current => bytes,
+                _ => continue
+            };
+
+            let (content, encoding) = encoding::decode(&bytes);
+            let lines = Buffer::from_content(&content, path).lines;
+
+            self.editor.replace_lines(id, lines);
+            if let Some(buffer) = self.editor.buffer_mut(&id) {
+                buffer.encoding = encoding;
+                buffer.mark_saved();
+            }
+            reloaded += 1;
+        }
+
+        if reloaded > 0 {
+            crate::notify!(self.editor, Duration::from_secs(2), "Reloaded {} file(s) changed on disk", reloaded);
+        }
+    }
+
+    pub fn save_dirty_buffers(&mut self) {
+        for id in self.editor.dirty_buffers() {
+            // Scratch buffers need an explicit `:w <path>`
+            if self.editor.buffer(&id).map(|b| b.is_scratch()).unwrap_or(true) { continue }
+
+            self.save_buffer(id);
+        }
+    }
+
+    /// Writes the buffer to its path, or to the one `:w <path>` gave, which it only takes
+    /// over once the write worked.
+    pub fn save_buffer(&mut self, id: BufferId) {
+        let save_path = self.save_paths.remove(&id);
+
+        let result = match self.editor.buffer(&id) {
+            Some(buffer) if buffer.is_scratch() && save_path.is_none() => {
+                crate::notify!(self.editor, Duration::from_secs(2), "No file name (use :w <path>)");
+                return;
+            }
+            Some(buffer) => {
+                let path = save_path.clone().unwrap_or_else(|| buffer.path.clone());
+                match buffer.encryption.as_ref() {
+                    Some(encryption) => buffer.file_bytes().and_then(|bytes| crypto::encrypt(&path, &bytes, encryption)),
+                    None => self.plugins.save_buffer(buffer, &path),
+                }.map(|_| path)
+            }
+            None => return,
+        };
+
+        match result {
+            Ok(path) => {
+                if let Some(buffer) = self.editor.buffer_mut(&id) {
+                    if save_path.is_some() {
+                        buffer.path = path.clone();
+                    }
+                    buffer.mark_saved();
+                }
+                self.request_git_base(id);
+                self.request_git_status();
+                self.blame.remove(&id);
+                self.dispatch_hook("buffer_saved", id, event_details(&[("path", path.as_str())]));
+                if let (Some(lsp), Some(buffer)) = (self.lsp.for_buffer(id), self.editor.buffer(&id)) {
+                    lsp.did_save(buffer);
+                }
+                crate::notify!(self.editor, Duration::from_secs(2), "Saved {}", path);
+            }
+            Err(error) => {
+                crate::notify!(self.editor, Duration::from_secs(4), "Could not save: {}", error);
+            }
+        }
+    }
+
+    /// Markdown cheatsheet of every binding per mode, grouped by category, plus the ex commands.
+    fn keys_cheatsheet(&self) -> String {
+        let mut text = String::from("# Oxidy key bindings\n");
+
+        for (title, mode) in [("Normal", EditorMode::Normal), ("Insert", EditorMode::Insert), ("Command", EditorMode::Command)] {
+            text.push_str(&format!("\n## {} mode\n", title));
+
+            let mut bindings = self.keymap.bindings(&mode);
+            bindings.sort_by_key(|(_, action)| action.category());
+
+            let mut category = "";
+            for (keys, action) in bindings {
+                if action.category() != category {
+                    category = action.category();
+                    text.push_str(&format!("\n### {}\n\n| Keys | Action |\n| --- | --- |\n", category));
+                }
+                text.push_str(&format!("| `{}` | {} |\n", keys.replace('|', "\\|"), action.description()));
+            }
+        }
+
+        for filetype in self.keymap.filetypes() {
+            text.push_str(&format!("\n## .{} files\n\n| Mode | Keys | Action |\n| --- | --- | --- |\n", filetype));
+
+            for (title, mode) in [("Normal", EditorMode::Normal), ("Insert", EditorMode::Insert)] {
+                for (keys, action) in self.keymap.filetype_bindings(&filetype, &mode) {
+                    text.push_str(&format!("| {} | `{}` | {} |\n", title, keys.replace('|', "\\|"), action.description()));
+                }
+            }
+        }
+
+        text.push_str("\n## Commands\n\n| Command | Description |\n| --- | --- |\n");
+        for (name, description) in self.commands.list() {
+            text.push_str(&format!("| `:{}` | {} |\n", name, description.replace('|', "\\|")));
+        }
+
+        text
+    }
+
+    fn show_keys(&mut self, export: Option<String>) {
+        let text = self.keys_cheatsheet();
+
+        if let Some(path) = export {
+            match std::fs::write(&path, &text) {
+                Ok(_) => crate::notify!(self.editor, Duration::from_secs(2), "Wrote {}", path),
+                Err(error) => crate::notify!(self.editor, Duration::from_secs(4), "Could not write {}: {}", path, error),
+            }
+        }
+
+        self.open_scratch(text);
+        self.editor.set_filetype("md".into());
+    }
+
+    /// Pipes the rows of `range`, or the whole active buffer, through `command` and previews the result.
+    fn start_transform(&mut self, command: &str, range: Option<(usize, usize)>) {
+        self.reject_transform();
+
+        let Some(id) = self.editor.active_view().map(|view| view.buffer) else { return };
+        let (start, end, version, text) = match self.editor.buffer(&id) {
+            Some(buffer) if buffer.readonly => {
+                crate::notify!(self.editor, Duration::from_secs(2), "Buffer is read-only");
+                return;
+            }
+            Some(buffer) => {
+                let last = buffer.lines.len() - 1;
+                let (start, end) = range.map(|(start, end)| (start.min(last), end.min(last))).unwrap_or((0, last));
+                (start, end, buffer.version, buffer.lines[start..=end].join("\n") + "\n")
+            }
+            None => return
+        };
+
+        self.editor.clear_selection();
+
+        match TransformJob::spawn(command, id, (start, end), version, text) {
+            Ok(job) => {
+                self.transform = Some(job);
+                if let Some(preview) = self.ui.get_mut::<TransformPreview>() {
+                    preview.shown = true;
+                }
+                self.update_transform_preview();
+            }
+            Err(error) => {
+                crate::notify!(self.editor, Duration::from_secs(4), "Could not run {}: {}", command, error);
+            }
+        }
+    }
+
+    /// Streams new output of the running transform into the preview.
+    fn poll_transform(&mut self) {
+        let changed = self.transform.as_mut().map(|job| job.poll()).unwrap_or(false);
+
+        if changed {
+            self.update_transform_preview();
+        }
+    }
+
+    /// Pipes the lines of `range` through `command`.
+    fn filter_lines(&mut self, command: &str, range: LineRange) {
+        let (id, start, end) = match self.editor.active_view() {
+            Some(view) => (view.buffer, range.start, range.end),
+            None => return
+        };
+        let (version, readonly) = match self.editor.buffer(&id) {
+            Some(buffer) => (buffer.version, buffer.readonly),
+            None => return
+        };
+        if readonly {
+            crate::notify!(self.editor, Duration::from_secs(2), "Buffer is read-only");
+            return;
+        }
+
+        self.editor.clear_selection();
+        self.run_shell(command, ShellTarget::Filter { buffer: id, start, end, version });
+    }
+
+    fn run_shell(&mut self, command: &str, target: ShellTarget) {
+        let command = command.trim();
+        if command.is_empty() {
+            crate::notify!(self.editor, Duration::from_secs(2), "Usage: !<cmd>");
+            return;
+        }
+
+        let input = match &target {
+            ShellTarget::Filter { buffer, start, end, .. } => self.editor.buffer(buffer)
+                .map(|buffer| buffer.lines[*start..=*end].join("\n") + "\n"),
+            ShellTarget::Format { buffer, .. } => self.editor.buffer(buffer)
+                .map(|buffer| buffer.lines.join("\n") + "\n"),
+            _ => None,
+        };
+
+        match ShellJob::spawn(command, input, target.clone()) {
+            Ok(job) => self.shell_jobs.push(job),
+            Err(error) => self.shell_failed(command, target, error),
+        }
+    }
+
+    /// Puts the output of the shell commands that exited where they were meant to go.
+    fn poll_shell(&mut self) {
+        let mut finished = vec![];
+        self.shell_jobs.retain_mut(|job| match job.poll() {
+            Some(result) => {
+                finished.push((job.command.clone(), job.target.clone(), result));
+                false
+            }
+            None => true,
+        });
+
+        for (command, target, result) in finished {
+            match result {
+                Ok(output) => self.finish_shell(&command, target, output),
+                Err(error) => self.shell_failed(&command, target, error),
+            }
+        }
+    }
+
+    fn shell_failed(&mut self, command: &str, target: ShellTarget, error: io::Error) {
+        crate::notify!(self.editor, Duration::from_secs(4), "Could not run {}: {}", command, error);
+        // The save waited for the formatter
+        if let ShellTarget::Format { buffer, .. } = target {
+            if !self.request_will_save(buffer) {
+                self.save_buffer(buffer);
+            }
+        }
+    }
+
+    fn finish_shell(&mut self, command: &str, target: ShellTarget, output: ShellOutput) {
+        let lines: Vec<String> = output.stdout.lines().map(String::from).collect();
+
+        let (id, start, end) = match target {
+            ShellTarget::Show => {
+                let text = output.stdout + &output.stderr;
+
+                match text.trim_end().lines().count() {
+                    0 if output.success => crate::notify!(self.editor, Duration::from_secs(2), "{} finished", command),
+                    0 => crate::notify!(self.editor, Duration::from_secs(4), "{} failed", command),
+                    1 => crate::notify!(self.editor, Duration::from_secs(6), "{}", text.trim_end()),
+                    _ => self.open_scratch(text),
+                }
+                return;
+            }
+            ShellTarget::Format { buffer, version } => {
+                self.finish_format(command, buffer, version, output);
+                return;
+            }
+            _ if !output.success => {
+                let error = output.stderr.lines().next().unwrap_or("exited with an error").to_string();
+                crate::notify!(self.editor, Duration::from_secs(4), "{}: {}", command, error);
+                return;
+            }
+            ShellTarget::Insert { buffer, row } => (buffer, row + 1, row),
+            ShellTarget::Filter { buffer, start, end, version } => {
+                if self.editor.buffer(&buffer).is_some_and(|buffer| buffer.version != version) {
+                    crate::notify!(self.editor, Duration::from_secs(4), "The buffer changed while {} ran", command);
+                    return;
+                }
+                (buffer, start, end)
+            }
+        };
+
+        // Rows `start..=end` are replaced, none when inserting
+        let mut text = match self.editor.buffer(&id) {
+            Some(buffer) if !buffer.readonly => buffer.lines.clone(),
+            _ => return
+        };
+        let start = start.min(text.len());
+        let end = (end + 1).clamp(start, text.len());
+        text.splice(start..end, lines);
+
+        self.editor.replace_lines(id, text);
+    }
+
+    /// Starts `command`, or the interpreter for the active buffer's filetype.
+    fn start_repl(&mut self, command: Option<String>) {
+        let filetype = self.editor.active_buffer().map(|buffer| buffer.filetype.clone()).unwrap_or_default();
+
+        let command = match command.or_else(|| repl::default_command(&filetype).map(String::from)) {
+            Some(command) => command,
+            None => {
+                crate::notify!(self.editor, Duration::from_secs(2), "No REPL known for .{} files, use :repl start <cmd>", filetype);
+                return;
+            }
+        };
+
+        match Repl::spawn(&command) {
+            Ok(repl) => {
+                if let Some(panel) = self.ui.get_mut::<ReplPanel>() {
+                    panel.update(&repl.command, &repl.output);
+                    panel.shown = true;
+                }
+                self.repl = Some(repl);
+            }
+            Err(error) => crate::notify!(self.editor, Duration::from_secs(4), "Could not start {}: {}", command, error),
+        }
+    }
+
+    fn send_to_repl(&mut self, text: &str) {
+        let repl = match self.repl.as_mut() {
+            Some(repl) => repl,
+            None => {
+                crate::notify!(self.editor, Duration::from_secs(2), "No REPL running, use :repl start [cmd]");
+                return;
+            }
+        };
+
+        if let Err(error) = repl.send(text) {
+            crate::notify!(self.editor, Duration::from_secs(2), "Could not send to the REPL: {}", error);
+            return;
+        }
+
+        if let Some(panel) = self.ui.get_mut::<ReplPanel>() {
+            panel.shown = true;
+        }
+    }
+
+    fn poll_repl(&mut self) {
+        let repl = match self.repl.as_mut() {
+            Some(repl) => repl,
+            None => return
+        };
+
+        if !repl.poll() { return }
+
+        if let Some(panel) = self.ui.get_mut::<ReplPanel>() {
+            panel.update(&repl.command, &repl.output);
+        }
+
+        if repl.exited {
+            crate::notify!(self.editor, Duration::from_secs(2), "{} exited", repl.command);
+            self.repl = None;
+        }
+    }
+
+    fn update_transform_preview(&mut self) {
+        let job = match self.transform.as_ref() {
+            Some(job) => job,
+            None => return
+        };
+
+        let old = self.editor.buffer(&job.buffer)
+            .and_then(|buffer| buffer.lines.get(job.start..=job.end).map(<[String]>::to_vec))
+            .unwrap_or_default();
+        let status = match (&job.error, job.finished) {
+            (Some(error), _) => format!("failed: {}", error),
+            (None, true) => ":transform accept | :transform reject".to_string(),
+            (None, false) => "running…".to_string(),
+        };
+
+        if let Some(preview) = self.ui.get_mut::<TransformPreview>() {
+            preview.title = format!("{} ({})", job.command, status);
+
+            // Until the command is done only compare as many lines as it has produced
+            let old = if job.finished { &old[..] } else { &old[..old.len().min(job.output.len())] };
+            preview.update(&transform::diff_lines(old, &job.output));
+        }
+    }
+
+    fn accept_transform(&mut self) {
+        let ready = self.transform.as_ref().map(|job| job.finished && job.error.is_none());
+
+        match ready {
+            Some(true) => {}
+            Some(false) => {
+                crate::notify!(self.editor, Duration::from_secs(2), "Transform has not finished successfully");
+                return;
+            }
+            None => return
+        }
+
+        if let Some(preview) = self.ui.get_mut::<TransformPreview>() {
+            preview.shown = false;
+        }
+
+        let Some(job) = self.transform.take() else { return };
+        let Some(buffer) = self.editor.buffer(&job.buffer) else { return };
+
+        if buffer.readonly {
+            crate::notify!(self.editor, Duration::from_secs(2), "Buffer is read-only");
+            return;
+        }
+        if buffer.version != job.version {
+            crate::notify!(self.editor, Duration::from_secs(4), "The buffer changed while {} ran, run it again", job.command);
+            return;
+        }
+
+        let mut lines = buffer.lines.clone();
+        lines.splice(job.start..=job.end, job.output);
+        self.editor.replace_lines(job.buffer, lines);
+    }
+
+    fn reject_transform(&mut self) {
+        if let Some(mut job) = self.transform.take() {
+            job.cancel();
+        }
+
+        if let Some(preview) = self.ui.get_mut::<TransformPreview>() {
+            preview.shown = false;
+        }
+    }
+
+    /// Every process the editor spawned and still owns.
+    fn running_processes(&self) -> Vec<(ProcessOwner, String, u32)> {
+        let mut running: Vec<(ProcessOwner, String, u32)> = self.lsp.servers()
+            .map(|(key, lsp)| (ProcessOwner::Lsp(key.clone()), format!("{} ({})", key.name, key.root), lsp.pid()))
+            .collect();
+
+        if let Some(repl) = self.repl.as_ref().filter(|repl| !repl.exited) {
+            running.push((ProcessOwner::Repl, repl.command.clone(), repl.pid()));
+        }
+        if let Some(job) = self.transform.as_ref().filter(|job| !job.finished) {
+            running.push((ProcessOwner::Transform, job.command.clone(), job.pid()));
+        }
+        for job in &self.shell_jobs {
+            running.push((ProcessOwner::Shell(job.pid()), job.command.clone(), job.pid()));
+        }
+        for (id, command, pid) in self.plugins.tasks.running() {
+            running.push((ProcessOwner::Task(id), command, pid));
+        }
+
+        running
+    }
+
+    /// Refreshes the `:ps` panel about once a second while it is shown.
+    fn update_process_ui(&mut self) {
+        if !self.ui.get::<ProcessPanel>().is_some_and(|panel| panel.shown) { return }
+        if self.processes_sampled.is_some_and(|sampled| sampled.elapsed() < PROCESS_SAMPLE_INTERVAL) { return }
+
+        self.processes.update(self.running_processes());
+        self.processes.sample();
+        self.processes_sampled = Some(Instant::now());
+
+        if let Some(panel) = self.ui.get_mut::<ProcessPanel>() {
+            panel.update(self.processes.processes());
+        }
+    }
+
+    fn restart_process(&mut self, owner: ProcessOwner) {
+        match owner {
+            ProcessOwner::Lsp(key) => {
+                let lsp_config = match self.config.lsps.get(&key.name) {
+                    Some(lsp_config) => lsp_config.clone(),
+                    None => return
+                };
+
+                self.lsp.stop(&key);
+                log!("Restarting {} in {}", key.name, key.root);
+                self.spawn_lsp(key, lsp_config);
+            }
+            ProcessOwner::Repl => {
+                let command = self.repl.take().map(|repl| repl.command.clone());
+                self.start_repl(command);
+            }
+            ProcessOwner::Transform => {
+                crate::notify!(self.editor, Duration::from_secs(2), "Jobs can't be restarted, run :transform again");
+            }
+            ProcessOwner::Shell(_) | ProcessOwner::Task(_) => {
+                crate::notify!(self.editor, Duration::from_secs(2), "Shell commands can't be restarted, run them again");
+            }
+        }
+
+        self.processes_sampled = None;
+    }
+
+    fn kill_process(&mut self, owner: ProcessOwner) {
+        match owner {
+            ProcessOwner::Lsp(key) => self.lsp.stop(&key),
+            ProcessOwner::Repl => {
+                if let Some(repl) = self.repl.as_mut() {
+                    repl.stop();
+                }
+            }
+            ProcessOwner::Transform => self.reject_transform(),
+            ProcessOwner::Shell(pid) => {
+                if let Some(job) = self.shell_jobs.iter_mut().find(|job| job.pid() == pid) {
+                    job.kill();
+                }
+            }
+            ProcessOwner::Task(id) => self.plugins.tasks.kill(id),
+        }
+
+        self.processes_sampled = None;
+    }
+
+    /// A frontend that attaches takes over drawing and input, from the dead terminal or from
+    /// the frontend before it. When it goes away the app is detached again.
+    fn poll_session(&mut self) {
+        let events = match &self.session {
+            Some(session) => session.poll(),
+            None => return
+        };
+
+        for event in events {
+            match event {
+                SessionEvent::Attached { id, stream, size, input } => {
+                    log!("Session client {} attached", id);
+                    self.renderer = Box::new(SessionRenderer::new(stream, size.clone()));
+                    self.input = Box::new(SessionInput::new(input));
+                    self.session_client = Some(id);
+                    self.detached = false;
+                    self.resize(size);
+                }
+                // Frontends that were replaced leave too, only the current one detaches
+                SessionEvent::Detached(id) if self.session_client == Some(id) => {
+                    log!("Session client {} detached", id);
+                    self.session_client = None;
+                    self.detached = true;
+                }
+                SessionEvent::Detached(_) => {}
+            }
+        }
+    }
+
+    /// The terminal is gone, so there is no input and nothing to draw to. Servers and jobs
+    /// keep running and unsaved buffers are written to recovery files now and then.
+    fn step_detached(&mut self) {
+        self.poll_lsp_events();
+        self.poll_collab_events();
+        self.poll_transform();
+        self.poll_repl();
+
+        let due = self.recovery_written.is_none_or(|written| written.elapsed() >= RECOVERY_INTERVAL);
+        if due {
+            self.recovery_written = Some(Instant::now());
+            self.write_recovery_files();
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    /// Writes every unsaved buffer to the recovery directory. Encrypted buffers are left out,
+    /// their plain text never goes to disk.
+    fn write_recovery_files(&self) {
+        for id in self.editor.dirty_buffers() {
+            let buffer = match self.editor.buffer(&id) {
+                Some(buffer) if buffer.encryption.is_none() => buffer,
+                _ => continue,
+            };
+
+            match recovery::write(id, buffer) {
+                Ok(path) => log!("Wrote {} to {}", buffer.name(), path.display()),
+                Err(error) => log!("Could not write recovery file for {}: {}", buffer.name(), error),
+            }
+        }
+    }
+
+    /// Saves what can be saved before being terminated, anything that can't, like scratch
+    /// buffers or files that fail to write, goes to recovery files.
+    fn save_on_exit(&mut self) {
+        self.save_dirty_buffers();
+        self.write_recovery_files();
+    }
+
+    /// Adapts the renderer and every view to a new terminal size.
+    pub fn resize(&mut self, size: Size) {
+        self.renderer.resize(size.clone());
+
+        let view_size = Size {
+            cols: size.cols,
+            rows: size.rows.saturating_sub(self.ui.top_offset() as u16)
+        };
+        self.editor.resize_views(view_size);
+        self.size = size;
+    }
+
+    /// The orderly way out: the config's `on_exit` hook runs while everything is still up,
+    /// then servers get a chance to shut down cleanly and jobs are stopped. The terminal is
+    /// restored when the renderer is dropped after this. Does nothing the second time.
+    pub fn shutdown(&mut self) {
+        if self.shut_down { return }
+        self.shut_down = true;
+
+        if let Err(error) = self.plugins.call_hook("on_exit") {
+            log!("on_exit failed: {}", error);
+        }
+
+        self.lsp.shutdown();
+
+        if let Some(mut repl) = self.repl.take() {
+            repl.stop();
+        }
+        if let Some(mut job) = self.transform.take() {
+            job.cancel();
+        }
+        self.shell_jobs.clear();
+        self.plugins.tasks.clear();
+        self.stop_collab();
+        self.remote = None;
+        self.session = None;
+    }
+
+    fn update_diagnostics_ui(&mut self) {
+        let diagnostics = self.editor.active_buffer()
+            .map(|buffer| buffer.diagnostics.as_slice())
+            .unwrap_or(&[]);
+
+        if let Some(status) = self.ui.get_mut::<StatusBar>() {
+            status.errors = diagnostics::count(diagnostics, Severity::Error);
+            status.warnings = diagnostics::count(diagnostics, Severity::Warning);
+        }
+
+        if let Some(panel) = self.ui.get_mut::<DiagnosticsPanel>() {
+            if panel.shown {
+                panel.update(diagnostics);
+            }
+        }
+    }
+
+    /// Shows what the server of the active buffer is busy with, like indexing.
+    fn update_progress_ui(&mut self) {
+        let progress = self.editor.active_view()
+            .map(|view| view.buffer)
+            .and_then(|id| self.lsp.for_buffer(id))
+            .and_then(|lsp| {
+                let progress = lsp.progress()?;
+                let percentage = progress.percentage.map(|percentage| format!("{}% ", percentage)).unwrap_or_default();
+                Some(format!("{}: {}{}", lsp.name, percentage, progress.title))
+            });
+
+        if let Some(status) = self.ui.get_mut::<StatusBar>() {
+            status.progress = progress;
+        }
+    }
+
+    /// Brings the cursor and mode in the status bar up to date and evaluates its `Eval`
+    /// snippets again when what they can see changed.
+    fn update_status_ui(&mut self) {
+        let (pos, mode) = match self.editor.active_view() {
+            Some(view) => (view.cursor.clone(), view.mode.clone()),
+            None => (Cursor { row: 0, col: 0 }, EditorMode::Normal),
+        };
+        let total_lines = self.editor.active_buffer().map(|buffer| buffer.lines.len()).unwrap_or(1);
+
+        let Some(status) = self.ui.get_mut::<StatusBar>() else { return };
+        status.pos = pos;
+        status.mode = mode;
+        status.total_lines = total_lines;
+
+        let snippets = status.snippets();
+        if snippets.is_empty() { return }
+
+        let mut scope = status.scope();
+        let inputs = format!("{:?}", scope);
+        if inputs == self.status_inputs { return }
+
+        let evaluated = snippets.into_iter()
+            .map(|source| {
+                let value = self.plugins.eval_snippet(&source, &mut scope).unwrap_or_else(|error| {
+                    log!("Status bar snippet {:?} failed: {}", source, error);
+                    "?".to_string()
+                });
+                (source, value)
+            })
+            .collect();
+
+        if let Some(status) = self.ui.get_mut::<StatusBar>() {
+            status.evaluated = evaluated;
+        }
+        self.status_inputs = inputs;
+    }
+
+    fn update_show_cmd_ui(&mut self) {
+        let pending = self.keymap.pending_keys();
+
+        if let Some(show_cmd) = self.ui.get_mut::<ShowCmd>() {
+            show_cmd.pending = pending;
+        }
+    }
+
+    fn update_undo_tree_ui(&mut self) {
+        let panel = match self.ui.get_mut::<UndoTreePanel>() {
+            Some(panel) if panel.shown => panel,
+            _ => return
+        };
+
+        if let (Some(view), Some(buffer)) = (self.editor.active_view(), self.editor.active_buffer()) {
+            panel.update(view.buffer, &buffer.history);
+        }
+    }
+
+    fn request_location(&mut self, kind: LocationKind) {
+        let (id, path, cursor) = match (self.editor.active_buffer(), self.editor.active_view()) {
+            (Some(buffer), Some(view)) => (view.buffer, buffer.path.clone(), view.cursor.clone()),
+            _ => return
+        };
+
+        match self.lsp.for_buffer(id) {
+            Some(lsp) => lsp.request_location(kind, &path, cursor.row, cursor.col),
+            None => crate::notify!(self.editor, Duration::from_secs(2), "No language server for {} lookup", kind.name()),
+        }
+    }
+
+    /// Starts `:calls` from the function under the cursor.
+    fn request_call_hierarchy(&mut self, direction: CallDirection) {
+        let (id, path, cursor) = match (self.editor.active_buffer(), self.editor.active_view()) {
+            (Some(buffer), Some(view)) => (view.buffer, buffer.path.clone(), view.cursor.clone()),
+            _ => return
+        };
+
+        match self.lsp.for_buffer(id) {
+            Some(lsp) if lsp.supports_call_hierarchy() => {
+                lsp.request_call_hierarchy(direction, &path, cursor.row, cursor.col);
+                self.calls_server = self.lsp.key_for(id).cloned();
+            }
+            Some(_) => crate::notify!(self.editor, Duration::from_secs(2), "The language server has no call hierarchy"),
+            None => crate::notify!(self.editor, Duration::from_secs(2), "No language server for {}", direction.name()),
+        }
+    }
+
+    /// Asks for the calls of the functions expanded in the call hierarchy.
+    fn request_calls(&mut self) {
+        let (direction, items) = match self.ui.get_mut::<CallHierarchyPanel>() {
+            Some(panel) if panel.shown => (panel.direction, panel.take_unloaded()),
+            _ => return
+        };
+        if items.is_empty() { return }
+
+        let lsp = match self.calls_server.as_ref().and_then(|key| self.lsp.get_mut(key)) {
+            Some(lsp) => lsp,
+            None => return
+        };
+
+        for item in items {
+            lsp.request_calls(direction, &item);
+        }
+    }
+
+    fn request_hover(&mut self) {
+        let (id, path, cursor) = match (self.editor.active_buffer(), self.editor.active_view()) {
+            (Some(buffer), Some(view)) => (view.buffer, buffer.path.clone(), view.cursor.clone()),
+            _ => return
+        };
+
+        match self.lsp.for_buffer(id) {
+            Some(lsp) => lsp.request_hover(&path, cursor.row, cursor.col),
+            None => crate::notify!(self.editor, Duration::from_secs(2), "No language server for hover"),
+        }
+    }
+
+    /// Asks the language server to format a buffer, only the selection if there is one.
+    /// Returns false if there is no server that has the buffer open.
+    fn request_formatting(&mut self, id: BufferId, save: bool) -> bool {
+        let view = match self.editor.active_view() {
+            Some(view) if view.buffer == id => view,
+            _ => return false
+        };
+        let path = match self.editor.buffer(&id) {
+            Some(buffer) => buffer.path.clone(),
+            None => return false
+        };
+        let tab_size = view.options(&self.config.opt).tab_size.unwrap_or(2);
+        let lsp = match self.lsp.for_buffer(id) {
+            Some(lsp) => lsp,
+            None => return false
+        };
+
+        let range = view.selection.as_ref()
+            .filter(|selection| !selection.is_empty())
+            .map(|selection| {
+                let (start, end) = selection.range();
+                ((start.row, start.col), (end.row, end.col))
+            });
+
+        lsp.request_formatting(&path, tab_size, range);
+        self.pending_format = Some(PendingFormat { buffer: id, save, started: Instant::now() });
+
+        true
+    }
+
+    /// Formats whole rows with the language server. Returns false if it can't format ranges
+    /// of this buffer.
+    fn request_range_formatting(&mut self, id: BufferId, start: usize, end: usize) -> bool {
+        let tab_size = self.buffer_options(id).tab_size.unwrap_or(2);
+        let buffer = match self.editor.buffer(&id) {
+            Some(buffer) => buffer,
+            None => return false
+        };
+        let lsp = match self.lsp.for_buffer(id) {
+            Some(lsp) if lsp.supports_range_formatting() => lsp,
+            _ => return false
+        };
+
+        let end_col = buffer.lines.get(end).map(|line| line.encode_utf16().count()).unwrap_or(0);
+        lsp.request_formatting(&buffer.path, tab_size, Some(((start, 0), (end, end_col))));
+        self.pending_format = Some(PendingFormat { buffer: id, save: false, started: Instant::now() });
+
+        true
+    }
+
+    /// Gives the server a chance to edit the buffer before it is saved, when it asks for that.
+    /// Returns false if the buffer can be saved right away.
+    fn request_will_save(&mut self, id: BufferId) -> bool {
+        let path = match self.editor.buffer(&id) {
+            Some(buffer) => buffer.path.clone(),
+            None => return false
+        };
+
+        match self.lsp.for_buffer(id) {
+            Some(lsp) if lsp.supports_will_save_wait_until() => lsp.request_will_save(&path),
+            _ => return false
+        }
+
+        self.pending_format = Some(PendingFormat { buffer: id, save: true, started: Instant::now() });
+        true
+    }
+
+    fn apply_formatting(&mut self, result: Result<Vec<TextEdit>, String>) {
+        let pending = match self.pending_format.take() {
+            Some(pending) => pending,
+            None => return
+        };
+
+        let result = result.and_then(|edits| self.editor.apply_text_edits(pending.buffer, &edits));
+        if let Err(error) = result {
+            crate::notify!(self.editor, Duration::from_secs(4), "Could not format: {}", error);
+        }
+
+        if pending.save {
+            self.save_buffer(pending.buffer);
+        }
+    }
+
+    /// Saves anyway when the server takes too long to format.
+    fn poll_pending_format(&mut self) {
+        let timed_out = self.pending_format.as_ref()
+            .is_some_and(|pending| pending.started.elapsed() > FORMAT_TIMEOUT);
+
+        if timed_out {
+            self.apply_formatting(Err("the language server did not answer in time".into()));
+        }
+    }
+
+    fn request_rename(&mut self, new_name: &str) {
+        let (id, path, cursor) = match (self.editor.active_buffer(), self.editor.active_view()) {
+            (Some(buffer), Some(view)) => (view.buffer, buffer.path.clone(), view.cursor.clone()),
+            _ => return
+        };
+
+        match self.lsp.for_buffer(id) {
+            Some(lsp) => lsp.request_rename(&path, cursor.row, cursor.col, new_name),
+            None => crate::notify!(self.editor, Duration::from_secs(2), "No language server to rename with"),
+        }
+    }
+
+    /// Applies a WorkspaceEdit. Every file is edited in memory first so a bad edit leaves all of them untouched.
+    /// Open buffers get their new content as one change, other files are rewritten on disk.
+    fn apply_workspace_edit(&mut self, files: Vec<FileEdit>) {
+        let mut buffers = vec![];
+        let mut unopened = vec![];
+
+        for file in &files {
+            let result = match self.editor.find_buffer(&file.path) {
+                Some(id) => {
+                    let lines = self.editor.buffer(&id).map(|b| b.lines.clone()).unwrap_or_default();
+                    text_edit::apply(&lines, &file.edits).map(|lines| buffers.push((id, lines)))
+                }
+                None => text_edit::apply_to_file(&file.path, &file.edits)
+                    .map(|bytes| unopened.push((file.path.clone(), bytes)))
+                    .map_err(|error| error.to_string()),
+            };
+
+            if let Err(error) = result {
+                crate::notify!(self.editor, Duration::from_secs(4), "Rename aborted, {}: {}", file.path, error);
+                return;
+            }
+        }
+
+        if buffers.is_empty() && unopened.is_empty() {
+            crate::notify!(self.editor, Duration::from_secs(2), "Nothing to rename");
+            return;
+        }
+
+        let count = buffers.len() + unopened.len();
+
+        for (id, lines) in buffers {
+            self.editor.replace_lines(id, lines);
+        }
+
+        for (path, bytes) in unopened {
+            if let Err(error) = std::fs::write(&path, bytes) {
+                crate::notify!(self.editor, Duration::from_secs(4), "Could not write {}: {}", path, error);
+                return;
+            }
+        }
+
+        crate::notify!(self.editor, Duration::from_secs(2), "Renamed in {} file{}", count, if count == 1 { "" } else { "s" });
+    }
+
+    /// Screen cell of the cursor, where popups attach to.
+    fn cursor_screen_position(&self) -> Option<(usize, usize)> {
+        let view = self.editor.active_view()?;
+
+        Some((
+            view.screen_row(view.cursor.row) + self.ui.top_offset(),
+            view.cursor.col.saturating_sub(view.scroll.horizontal) + GUTTER_WIDTH as usize
+        ))
+    }
+
+    fn show_definition_preview(&mut self, target: PreviewTarget) {
+        let anchor = match self.cursor_screen_position() {
+            Some(anchor) => anchor,
+            None => return
+        };
+
+        let buffer = match self.editor.find_buffer(&target.path).and_then(|id| self.editor.buffer(&id)) {
+            Some(buffer) => buffer,
+            None => {
+                crate::notify!(self.editor, Duration::from_secs(2), "Can't preview {}", target.path);
+                return;
+            }
+        };
+
+        let title = buffer.name().to_string();
+        let lines = buffer.lines.clone();
+
+        if let Some(preview) = self.ui.get_mut::<DefinitionPreview>() {
+            preview.show(title, &lines, target, anchor);
+        }
+    }
+
+    /// Goes to a location found by the language server, remembering where the jump came from.
+    fn jump_to_target(&mut self, target: PreviewTarget) {
+        self.editor.push_jump();
+        self.goto_file(target.path, Some(target.row), Some(target.col));
+        self.editor.center_cursor();
+    }
+
+    fn show_hover(&mut self, markdown: String) {
+        let anchor = match self.cursor_screen_position() {
+            Some(anchor) => anchor,
+            None => return
+        };
+
+        let popup = hover::hover_popup(&markdown, anchor, self.size.cols as usize);
+        if popup.lines.is_empty() { return }
+
+        if let Some(popups) = self.ui.get_mut::<Popups>() {
+            popups.show(popup);
+        }
+    }
+
+    /// Asks for the hints of the visible lines whenever the buffer changes or scrolls.
+    fn request_inlay_hints(&mut self) {
+        if !self.active_options().inlay_hints.unwrap_or(true) {
+            if self.inlay_hints_requested.take().is_some() {
+                self.editor.clear_inlay_hints();
+            }
+            return;
+        }
+
+        let (id, path, version, top, rows) = match (self.editor.active_buffer(), self.editor.active_view()) {
+            (Some(buffer), Some(view)) => (view.buffer, buffer.path.clone(), buffer.version, view.scroll.vertical, view.size.rows as usize),
+            _ => return
+        };
+
+        if self.inlay_hints_requested == Some((id, version, top)) { return }
+
+        if let Some(lsp) = self.lsp.for_buffer(id) {
+            if lsp.request_inlay_hints(&path, top, top + rows) {
+                self.inlay_hints_requested = Some((id, version, top));
+            }
+        }
+    }
+
+    /// Asks what can be folded whenever the active buffer changes.
+    fn request_folding_ranges(&mut self) {
+        let (id, path, version) = match (self.editor.active_buffer(), self.editor.active_view()) {
+            (Some(buffer), Some(view)) => (view.buffer, buffer.path.clone(), buffer.version),
+            _ => return
+        };
+
+        if self.folding_requested == Some((id, version)) { return }
+
+        if let Some(lsp) = self.lsp.for_buffer(id) {
+            if lsp.request_folding_ranges(&path) {
+                self.folding_requested = Some((id, version));
+            }
+        }
+    }
+
+    /// Asks for the semantic tokens of the visible rows of a large buffer. After scrolling
+    /// that is only worth it while the tokens for the whole file are still on their way.
+    fn request_visible_tokens(&mut self, scrolled: bool) {
+        let (id, version, top, rows) = match (self.editor.active_buffer(), self.editor.active_view()) {
+            (Some(buffer), Some(view)) => (view.buffer, buffer.version, view.scroll.vertical, view.size.rows as usize),
+            _ => return
+        };
+
+        if self.range_tokens_requested == Some((id, version, top)) { return }
+
+        if let (Some(lsp), Some(buffer)) = (self.lsp.for_buffer(id), self.editor.active_buffer()) {
+            if scrolled && !lsp.tokens_pending() { return }
+
+            lsp.request_range_tokens(buffer, top, top + rows);
+            self.range_tokens_requested = Some((id, version, top));
+        }
+    }
+
+    /// Sends the edits made since the last call to the servers, one didChange per buffer.
+    fn sync_lsp(&mut self) {
+        for id in std::mem::take(&mut self.unsynced) {
+            if let Some(lsp) = self.lsp.for_buffer(id) {
+                let changes = self.editor.take_changes(id);
+                if let Some(buffer) = self.editor.buffer(&id) {
+                    lsp.did_change(buffer, changes);
+                }
+            }
+        }
+    }
+
+    /// Asks for new semantic tokens once the edits have paused for `TOKENS_DELAY`.
+    fn poll_semantic_tokens(&mut self) {
+        if !self.tokens_due.is_some_and(|due| Instant::now() >= due) { return }
+        self.tokens_due = None;
+
+        let id = match self.editor.active_view() {
+            Some(view) => view.buffer,
+            None => return
+        };
+
+        // The visible rows first, they are answered long before the whole file
+        self.request_visible_tokens(false);
+        if let (Some(lsp), Some(buffer)) = (self.lsp.for_buffer(id), self.editor.active_buffer()) {
+            lsp.request_semantic_tokens(buffer);
+        }
+    }
+
+    /// The next time `step` has something to do without any input: auto-saving,
+    /// asking for semantic tokens or restarting a crashed server.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        // The :ps panel is kept up to date while it is open
+        let processes_due = self.processes_sampled
+            .filter(|_| self.ui.get::<ProcessPanel>().is_some_and(|panel| panel.shown))
+            .map(|sampled| sampled + PROCESS_SAMPLE_INTERVAL);
+
+        [self.auto_save_deadline(), self.tokens_due, self.lsp.next_restart(), processes_due].into_iter().flatten().min()
+    }
+
+    fn request_signature_help(&mut self) {
+        let (id, path, cursor) = match (self.editor.active_buffer(), self.editor.active_view()) {
+            (Some(buffer), Some(view)) => (view.buffer, buffer.path.clone(), view.cursor.clone()),
+            _ => return
+        };
+
+        if let Some(lsp) = self.lsp.for_buffer(id) {
+            lsp.request_signature_help(&path, cursor.row, cursor.col);
+        }
+    }
+
+    fn show_signature_help(&mut self, signature: Option<Signature>) {
+        let anchor = self.cursor_screen_position();
+        let insert_mode = self.editor.active_view().is_some_and(|view| view.mode == EditorMode::Insert);
+
+        if let Some(popup) = self.ui.get_mut::<SignatureHelp>() {
+            match (signature, anchor) {
+                // The answer may come in after insert mode was left
+                (Some(signature), Some(anchor)) if insert_mode => popup.show(signature, anchor),
+                _ => popup.hide(),
+            }
+        }
+    }
+
+    fn poll_lsp_events(&mut self) {
+        self.poll_lsp_crashes();
+
+        let mut events = vec![];
+
+        for (key, lsp) in self.lsp.servers_mut() {
+            for params in lsp.poll_diagnostics() {
+                let path = lsp_service::path_from_uri(&params.uri);
+                let diagnostics = params.diagnostics.iter().map(Diagnostic::from_lsp).collect();
+                self.editor.set_diagnostics(&path, diagnostics);
+            }
+
+            events.push((key.clone(), lsp.poll()));
+        }
+
+        // Tokens are only meant for the active buffer if it is opened on the server they came from
+        let active = self.editor.active_view().map(|view| view.buffer);
+
+        for (key, event) in events {
+            let from_active = active.is_some_and(|id| self.lsp.key_for(id) == Some(&key));
+
+            match event {
+                LspServiceEvent::Initialized => {
+                    self.open_lsp_documents();
+                }
+                LspServiceEvent::Locations(locations) => {
+                    match locations.into_iter().next() {
+                        Some(location) => {
+                            // Files that aren't open yet are loaded without being shown
+                            self.load_background(&location.path);
+
+                            let target = PreviewTarget { path: location.path, row: location.row, col: location.col };
+                            match std::mem::take(&mut self.preview_location) {
+                                true => self.show_definition_preview(target),
+                                false => self.jump_to_target(target),
+                            }
+                        }
+                        None => crate::notify!(self.editor, Duration::from_secs(2), "Nothing found"),
+                    }
+                }
+                LspServiceEvent::CallHierarchy { direction, items } => {
+                    if items.is_empty() {
+                        crate::notify!(self.editor, Duration::from_secs(2), "No function under the cursor");
+                    } else if let Some(panel) = self.ui.get_mut::<CallHierarchyPanel>() {
+                        panel.show(direction, items);
+                    }
+                }
+                LspServiceEvent::Calls { direction, parent, items } => {
+                    if let Some(panel) = self.ui.get_mut::<CallHierarchyPanel>() {
+                        panel.add_calls(direction, &parent, items);
+                    }
+                }
+                LspServiceEvent::Hover(Some(markdown)) => {
+                    self.show_hover(markdown);
+                }
+                LspServiceEvent::Hover(None) => {
+                    crate::notify!(self.editor, Duration::from_secs(2), "No documentation found");
+                }
+                LspServiceEvent::FoldingRanges { path, ranges } => {
+                    self.editor.set_fold_ranges(&path, ranges);
+                }
+                LspServiceEvent::InlayHints { path, hints } => {
+                    let opt = self.editor.find_buffer(&path).map(|id| self.buffer_options(id));
+                    if opt.unwrap_or_else(|| self.config.opt.clone()).inlay_hints.unwrap_or(true) {
+                        self.editor.set_inlay_hints(&path, hints);
+                    }
+                }
+                LspServiceEvent::ShowMessage { level, message } => {
+                    let card_type = match level {
+                        lsp_service::MESSAGE_ERROR => CardType::ERROR,
+                        lsp_service::MESSAGE_WARNING => CardType::WARNING,
+                        _ => CardType::INFO,
+                    };
+                    self.editor.logs.push_notification_as(format!("{}: {}", key.name, message), Duration::from_secs(5), card_type);
+                }
+                LspServiceEvent::SignatureHelp(signature) => {
+                    self.show_signature_help(signature);
+                }
+                LspServiceEvent::Formatting(result) => {
+                    self.apply_formatting(result);
+                }
+                LspServiceEvent::WorkspaceEdit(Ok(files)) => {
+                    self.apply_workspace_edit(files);
+                }
+                LspServiceEvent::WorkspaceEdit(Err(message)) => {
+                    crate::notify!(self.editor, Duration::from_secs(4), "Rename failed: {}", message);
+                }
+                LspServiceEvent::OpenedFile if from_active => {
+                    self.request_visible_tokens(false);
+                    if let (Some(lsp), Some(buffer)) = (self.lsp.get_mut(&key), self.editor.active_buffer()) {
+                        lsp.request_semantic_tokens(&buffer);
+                    }
+                }
+                LspServiceEvent::ReceivedSemantics { semantics: _ } if from_active => {
+                    let theme = self.config.current_theme();
+                    if let (Some(lsp), Some(buffer)) = (self.lsp.get_mut(&key), self.editor.active_buffer()) {
+                        let tokens = lsp.set_tokens(&buffer, theme);
+                        self.editor.update_tokens(tokens);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Seconds since the epoch, to name screenshots and recordings.
+    fn timestamp() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default()
+    }
+
+    fn collab_name() -> String {
+        std::env::var("USER").unwrap_or_else(|_| "anonymous".to_string())
+    }
+
+    /// Shares the active buffer on `address` with those who know `token`, a new one if `None`.
+    fn host_collab(&mut self, address: &str, token: Option<String>) {
+        let id = match self.editor.active_view() {
+            Some(view) => view.buffer,
+            None => {
+                crate::notify!(self.editor, Duration::from_secs(2), "Nothing to share");
+                return;
+            }
+        };
+
+        // The plain text would go over the network unencrypted
+        if self.editor.buffer(&id).is_some_and(|buffer| buffer.encryption.is_some()) {
+            crate::notify!(self.editor, Duration::from_secs(4), "Encrypted buffers can't be shared");
+            return;
+        }
+
+        let token = token.unwrap_or_else(collab_service::new_token);
+
+        match CollabService::host(address, Self::collab_name(), token.clone()) {
+            Ok(collab) => {
+                self.collab = Some(collab);
+                self.collab_buffer = Some(id);
+                self.mark_collab_synced();
+                crate::notify!(self.editor, Duration::from_secs(10), "Sharing on {}, join with: collab join {} {}", address, address, token);
+            }
+            Err(error) => {
+                crate::notify!(self.editor, Duration::from_secs(4), "Could not host on {}: {}", address, error);
+            }
+        }
+    }
+
+    fn join_collab(&mut self, address: &str, token: String) {
+        match CollabService::join(address, Self::collab_name(), token) {
+            Ok(collab) => {
+                self.collab = Some(collab);
+                // Set once the host sent the buffer
+                self.collab_buffer = None;
+            }
+            Err(error) => {
+                crate::notify!(self.editor, Duration::from_secs(4), "Could not join {}: {}", address, error);
+            }
+        }
+    }
+
+    fn stop_collab(&mut self) {
+        if self.collab.take().is_some() {
+            crate::notify!(self.editor, Duration::from_secs(2), "Left the session");
+        }
+        self.collab_buffer = None;
+        self.editor.remote_cursors.clear();
+    }
+
+    /// Takes the shared buffer as it is now as what the others have.
+    fn mark_collab_synced(&mut self) {
+        self.collab_synced = self.collab_buffer
+            .and_then(|id| self.editor.buffer(&id))
+            .map(|buffer| (buffer.version, buffer.lines.clone()))
+            .unwrap_or_default();
+    }
+
+    /// Sends whatever changed in the shared buffer since the last call, be it typing, undo,
+    /// a paste or a plugin, as the edits that lead there.
+    fn send_collab_changes(&mut self) {
+        let (Some(collab), Some(buffer)) = (self.collab.as_mut(), self.collab_buffer.and_then(|id| self.editor.buffer(&id))) else { return };
+        if buffer.version == self.collab_synced.0 { return }
+
+        for edit in collab::edits_between(&self.collab_synced.1, &buffer.lines) {
+            collab.local_edit(edit);
+        }
+        self.collab_synced = (buffer.version, buffer.lines.clone());
+    }
+
+    fn poll_collab_events(&mut self) {
+        // Local changes first, the remote edits polled below are transformed against them
+        self.send_collab_changes();
+
+        let collab = match self.collab.as_mut() {
+            Some(collab) => collab,
+            None => return
+        };
+
+        let shared = self.collab_buffer.and_then(|id| self.editor.buffer(&id));
+        let events = collab.poll(shared);
+
+        for event in events {
+            match event {
+                CollabServiceEvent::Joined { path, text } => {
+                    // Opened as scratch so a save never clobbers a local file of the same name
+                    self.open_scratch(text);
+                    self.collab_buffer = self.editor.active_view().map(|view| view.buffer);
+                    self.mark_collab_synced();
+                    crate::notify!(self.editor, Duration::from_secs(4), "Joined session for {}", path);
+                }
+                CollabServiceEvent::RemoteEdit(edit) => {
+                    if let Some(id) = self.collab_buffer {
+                        self.editor.apply_edit(id, &edit);
+                        collab::apply(&mut self.collab_synced.1, &edit);
+                        self.collab_synced.0 = self.editor.buffer(&id).map(|buffer| buffer.version).unwrap_or_default();
+                    }
+                }
+                CollabServiceEvent::PeerJoined(name) => {
+                    crate::notify!(self.editor, Duration::from_secs(4), "{} joined", name);
+                }
+                CollabServiceEvent::PeerLeft(name) => {
+                    crate::notify!(self.editor, Duration::from_secs(4), "{} left", name);
+                }
+                CollabServiceEvent::PeerRefused => {
+                    crate::notify!(self.editor, Duration::from_secs(4), "Refused a connection with a wrong token");
+                }
+                CollabServiceEvent::Refused(reason) => {
+                    self.collab = None;
+                    self.collab_buffer = None;
+                    self.editor.remote_cursors.clear();
+                    crate::notify!(self.editor, Duration::from_secs(4), "The host refused to let you in: {}", reason);
+                    return;
+                }
+                CollabServiceEvent::Disconnected => {
+                    self.collab = None;
+                    self.collab_buffer = None;
+                    self.editor.remote_cursors.clear();
+                    crate::notify!(self.editor, Duration::from_secs(4), "Disconnected from the session");
+                    return;
+                }
+            }
+        }
+
+        let collab = match self.collab.as_mut() {
+            Some(collab) => collab,
+            None => return
+        };
+
+        let view = self.editor.active_view().filter(|view| Some(view.buffer) == self.collab_buffer);
+
+        match view {
+            Some(view) => {
+                collab.update_cursor(view.cursor.row, view.cursor.col);
+                self.editor.remote_cursors = collab.cursors().to_vec();
+            }
+            None => self.editor.remote_cursors.clear(),
+        }
+    }
+
+    pub fn open_file(&mut self, path: String) {
+        if let Some(encryption) = crypto::detect(&path, self.config.encryption.as_ref()) {
+            if encryption.needs_passphrase() {
+                if let Some(command) = self.ui.get_mut::<Command>() {
+                    command.show_prompt(&format!("Passphrase for {}: ", path));
+                }
+                self.pending_passphrase = Some((path, encryption));
+            } else {
+                self.open_encrypted(path, encryption);
+            }
+            return;
+        }
+
+        let (content, encoding) = match std::fs::read(&path) {
+            Ok(bytes) => encoding::decode(&bytes),
+            // A new file, it is created on the first save
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => (String::new(), Encoding::Utf8),
+            Err(error) => {
+                crate::notify!(self.editor, Duration::from_secs(4), "Could not open {}: {}", path, error);
+                return;
+            }
+        };
+
+        self.open_content(path, content);
+
+        if let Some(id) = self.editor.active_view().map(|view| view.buffer) {
+            if let Some(buffer) = self.editor.buffer_mut(&id) {
+                buffer.encoding = encoding;
+            }
+        }
+    }
+
+    /// Opens again the buffers the config's encryption patterns match, they were read as
+    /// they are on disk when the files came from the command line before it was loaded.
+    fn detect_encrypted_buffers(&mut self) {
+        for id in self.editor.buffer_ids() {
+            let path = match self.editor.buffer(&id) {
+                Some(buffer) if !buffer.is_scratch() && buffer.encryption.is_none() && !buffer.is_dirty() => buffer.path.clone(),
+                _ => continue
+            };
+
+            if crypto::detect(&path, self.config.encryption.as_ref()).is_some() {
+                self.open_file(path);
+            }
+        }
+    }
+
+    /// Decrypts `path` into memory. The plaintext is never written to disk. A buffer already
+    /// open for `path` gets the plaintext in place of what it has.
+    fn open_encrypted(&mut self, path: String, encryption: Encryption) {
+        let (content, encoding) = match crypto::decrypt(&path, &encryption) {
+            Ok(bytes) => encoding::decode(&bytes),
+            Err(error) => {
+                crate::notify!(self.editor, Duration::from_secs(4), "Could not decrypt {}: {}", path, error);
+                return;
+            }
+        };
+
+        if let Some(id) = self.editor.find_buffer(&path) {
+            let lines = Buffer::from_content(&content, path).lines;
+            self.editor.replace_lines(id, lines.clone());
+
+            if let Some(buffer) = self.editor.buffer_mut(&id) {
+                // Undo shouldn't go back to the encrypted bytes
+                buffer.history = UndoTree::new(lines, buffer.version);
+                buffer.encryption = Some(encryption);
+                buffer.encoding = encoding;
+                buffer.mark_saved();
+            }
+            return;
+        }
+
+        self.open_content(path, content);
+
+        if let Some(id) = self.editor.active_view().map(|view| view.buffer) {
+            if let Some(buffer) = self.editor.buffer_mut(&id) {
+                buffer.encryption = Some(encryption);
+                buffer.encoding = encoding;
+            }
+        }
+    }
+
+    /// Loads `path` into a buffer that isn't shown yet, unless it is open already.
+    /// Encrypted files are left alone, they need a passphrase first.
+    fn load_background(&mut self, path: &str) -> Option<BufferId> {
+        if let Some(id) = self.editor.find_buffer(path) {
+            return Some(id);
+        }
+
+        if crypto::detect(path, self.config.encryption.as_ref()).is_some() {
+            return None;
+        }
+
+        let bytes = std::fs::read(path).ok()?;
+        let (content, encoding) = encoding::decode(&bytes);
+
+        let id = self.editor.load_buffer(path.to_string(), content);
+        if let Some(buffer) = self.editor.buffer_mut(&id) {
+            buffer.encoding = encoding;
+        }
+
+        if !self.startup_pending {
+            self.start_lsp(path);
+        }
+
+        Some(id)
+    }
+
+    /// Opens `content` in an unnamed scratch buffer.
+    pub fn open_scratch(&mut self, content: String) {
+        self.open_content(String::new(), content);
+    }
+
+    fn open_content(&mut self, path: String, content: String) {
+        self.config = self.plugins.config.clone();
+
+        // TODO: Calculate size based on opened buffers
+        let buffer_size = Size {
+            cols: self.size.cols.clone(),
+            rows: self.size.rows.clone() - self.ui.top_offset() as u16
+        };
+
+        self.editor.open_buffer(path.clone(), content, buffer_size);
+
+        let status = self.ui.get_mut::<StatusBar>();
+
+        if let Some(status) = status {
+            status.file = self.editor.active_buffer().map(|b| b.name().to_string()).unwrap_or_default();
+        }
+
+        if !self.startup_pending && !path.is_empty() {
+            self.start_lsp(&path);
+        }
+
+        if let Some(id) = self.editor.active_view().map(|view| view.buffer) {
+            self.request_git_base(id);
+
+            if !self.startup_pending && !path.is_empty() {
+                self.dispatch_hook("file_opened", id, event_details(&[("path", path.as_str())]));
+            }
+        }
+    }
+
+    /// Starts the LSP configured for the file type of `path`, or hands the file to it if it already runs.
+    fn start_lsp(&mut self, path: &str) {
+        // Detached with :lsp detach, no server is started for it either
+        if self.editor.find_buffer(path).is_some_and(|id| self.lsp.is_disabled(id)) {
+            return;
+        }
+
+        let extension = Path::new(path).extension()
+            .map(|ext| ext.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let (name, lsp_config) = match self.config.lsp_for(&extension) {
+            Some((name, lsp_config)) => (name.clone(), lsp_config.clone()),
+            None => return
+        };
+
+        let key = ServerKey::new(&name, path, lsp_config.single_file);
+        if self.lsp.contains(&key) {
+            self.open_lsp_documents();
+            return;
+        }
+
+        log!("Starting {} in {} for {}", name, key.root, path);
+        self.spawn_lsp(key, lsp_config);
+    }
+
+    fn spawn_lsp(&mut self, key: ServerKey, lsp_config: LspConfig) {
+        match LspService::new(&key.name, lsp_config.command, lsp_config.args) {
+            Some(mut lsp) => {
+                lsp.initialize(key.workspace());
+                self.lsp.insert(key, lsp);
+            }
+            None => crate::notify!(self.editor, Duration::from_secs(4), "Could not start {}", key.name),
+        }
+    }
+
+    /// Tells about servers that died and starts them again once their backoff is over.
+    fn poll_lsp_crashes(&mut self) {
+        for crash in self.lsp.reap_crashed() {
+            let status = crash.status.map(|status| format!(" ({})", status)).unwrap_or_default();
+            log!("{} in {} exited{}", crash.key.name, crash.key.root, status);
+
+            match crash.retry_in {
+                Some(delay) => crate::notify!(self.editor, Duration::from_secs(4), "{} exited{}, restarting in {}s", crash.key.name, status, delay.as_secs()),
+                None => crate::notify!(self.editor, Duration::from_secs(6), "{} keeps crashing, :lsp restart starts it again", crash.key.name),
+            }
+        }
+
+        for key in self.lsp.due_restarts() {
+            // Gone from the config since it crashed
+            let lsp_config = match self.config.lsps.get(&key.name) {
+                Some(lsp_config) => lsp_config.clone(),
+                None => continue
+            };
+
+            log!("Restarting {} in {}", key.name, key.root);
+            self.spawn_lsp(key, lsp_config);
+        }
+    }
+
+    /// Restarts the server of the active buffer, e.g. to pick up a changed command.
+    fn restart_lsp(&mut self) {
+        let (id, path, filetype) = match (self.editor.active_view(), self.editor.active_buffer()) {
+            (Some(view), Some(buffer)) => (view.buffer, buffer.path.clone(), buffer.filetype.clone()),
+            _ => return
+        };
+
+        let (name, single_file) = match self.config.lsp_for(&filetype) {
+            Some((name, lsp_config)) => (name.clone(), lsp_config.single_file),
+            None => {
+                crate::notify!(self.editor, Duration::from_secs(2), "No language server configured for this file");
+                return;
+            }
+        };
+
+        // Also stops waiting for a crashed server and resets its backoff
+        let key = self.lsp.key_for(id).cloned().unwrap_or_else(|| ServerKey::new(&name, &path, single_file));
+        self.lsp.stop(&key);
+        self.lsp.enable(id);
+        self.start_lsp(&path);
+    }
+
+    /// Opens the active buffer on its server again after `:lsp detach`, starting the server if needed.
+    fn attach_lsp(&mut self) {
+        let (id, path) = match (self.editor.active_view(), self.editor.active_buffer()) {
+            (Some(view), Some(buffer)) if !buffer.path.is_empty() => (view.buffer, buffer.path.clone()),
+            _ => return
+        };
+
+        if self.lsp.for_buffer(id).is_some() {
+            crate::notify!(self.editor, Duration::from_secs(2), "Already attached");
+            return;
+        }
+
+        self.lsp.enable(id);
+        self.start_lsp(&path);
+    }
+
+    /// Closes the active buffer on its server and keeps it closed, its diagnostics and
+    /// semantic highlighting go away with it. The server keeps running for other buffers.
+    fn detach_lsp(&mut self) {
+        let (id, path) = match (self.editor.active_view(), self.editor.active_buffer()) {
+            (Some(view), Some(buffer)) => (view.buffer, buffer.path.clone()),
+            _ => return
+        };
+
+        if let Some(lsp) = self.lsp.for_buffer(id) {
+            lsp.did_close(&path);
+        }
+        self.lsp.disable(id);
+        self.unsynced.retain(|unsynced| *unsynced != id);
+
+        self.editor.set_diagnostics(&path, vec![]);
+        self.editor.update_tokens(vec![]);
+    }
+
+    /// Sends every buffer with didOpen to the server for its language and workspace, the active one first.
+    fn open_lsp_documents(&mut self) {
+        let active = self.editor.active_view().map(|view| view.buffer);
+        let mut ids = self.editor.buffer_ids();
+        ids.sort_by_key(|id| Some(*id) != active);
+
+        for id in ids {
+            let buffer = match self.editor.buffer(&id) {
+                Some(buffer) if !buffer.path.is_empty() && !self.lsp.is_disabled(id) => buffer,
+                _ => continue
+            };
+
+            let (key, language_id) = match self.config.lsp_for(&buffer.filetype) {
+                Some((name, lsp_config)) => (ServerKey::new(name, &buffer.path, lsp_config.single_file), lsp_config.language_id(&buffer.filetype)),
+                None => continue
+            };
+
+            let lsp = match self.lsp.get_mut(&key) {
+                Some(lsp) => lsp,
+                None => continue
+            };
+
+            lsp.open_file(buffer, &language_id);
+            self.lsp.attach(id, key);
+
+            // Edits made before are already part of the opened text
+            self.editor.take_changes(id);
+        }
+    }
+
+    /// Opens `path` (relative to the active buffer if needed) and moves the cursor to `row`/`col`.
+    pub fn goto_file(&mut self, path: String, row: Option<usize>, col: Option<usize>) {
+        let path = match self.resolve_path(&path) {
+            Some(path) => path,
+            None => {
+                crate::notify!(self.editor, Duration::from_secs(2), "No such file: {}", path);
+                return;
+            }
+        };
+
+        // Where `#` and the jump list lead back to
+        self.editor.push_jump();
+
+        match self.editor.find_buffer(&path) {
+            Some(id) => {
+                self.editor.focus_buffer(id);
+
+                if let Some(status) = self.ui.get_mut::<StatusBar>() {
+                    status.file = path.clone();
+                }
+            }
+            None => self.open_file(path),
+        }
+
+        if let Some(row) = row {
+            self.editor.set_cursor(row, col.unwrap_or(0));
+        }
+    }
+
+    fn resolve_path(&self, path: &str) -> Option<String> {
+        if Path::new(path).is_file() {
+            return Some(path.to_string());
+        }
+
+        let buffer = self.editor.active_buffer()?;
+        let candidate = Path::new(&buffer.path).parent()?.join(path);
+
+        if candidate.is_file() {
+            return Some(candidate.to_string_lossy().to_string());
+        }
+
+        None
+    }
+
     pub fn register_commands(&mut self) {
         self.commands.register(
             command::Command {
-                name: "q".into(),
-                description: "Quit Oxidy.".into(),
+                name: "q".into(),
+                description: "Quit Oxidy.".into(),
+                execute: (|editor, args| {
+                    editor.event_sender.send(EditorEvent::QuitRequested);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "cq".into(),
+                description: "Quit Oxidy with a non-zero exit code.".into(),
+                execute: (|editor, args| {
+                    let code = args.first().and_then(|c| c.parse::<i32>().ok()).unwrap_or(1);
+                    editor.event_sender.send(EditorEvent::QuitWithCode(code));
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "set".into(),
+                description: "Set an option, e.g. set ft=gitcommit, set ff=dos, set fenc=latin1 or set list".into(),
+                execute: (|editor, args| {
+                    for arg in args.iter().filter(|arg| !arg.is_empty()) {
+                        match arg.split_once('=') {
+                            Some(("ft", value)) | Some(("filetype", value)) => {
+                                editor.set_filetype(value.to_string());
+                            }
+                            Some(("fenc", value)) | Some(("fileencoding", value)) => {
+                                match Encoding::parse(value) {
+                                    Some(encoding) => editor.set_encoding(encoding),
+                                    None => {
+                                        crate::notify!(editor, Duration::from_secs(2), "Unknown encoding: {}", value);
+                                    }
+                                }
+                            }
+                            Some(("ff", value)) | Some(("fileformat", value)) => {
+                                match LineEnding::parse(value) {
+                                    Some(line_ending) => editor.set_line_ending(line_ending),
+                                    None => {
+                                        crate::notify!(editor, Duration::from_secs(2), "Unknown file format: {}", value);
+                                    }
+                                }
+                            }
+                            None if matches!(arg.as_str(), "list" | "nolist" | "list!") => {
+                                let list = match arg.as_str() {
+                                    "list" => Some(true),
+                                    "nolist" => Some(false),
+                                    _ => None,
+                                };
+                                editor.event_sender.send(EditorEvent::SetList(list));
+                            }
+                            _ => {
+                                crate::notify!(editor, Duration::from_secs(2), "Unknown option: {}", arg);
+                            }
+                        }
+                    }
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "w".into(),
+                description: "Save the current buffer, optionally under a new path".into(),
+                execute: (|editor, args| {
+                    editor.request_save(false, args.first().filter(|path| !path.is_empty()).cloned());
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "w!".into(),
+                description: "Save the current buffer, even if it is read-only, optionally under a new path".into(),
+                execute: (|editor, args| {
+                    editor.request_save(true, args.first().filter(|path| !path.is_empty()).cloned());
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "bd".into(),
+                description: "Close the current buffer".into(),
+                execute: (|editor, _| {
+                    editor.close_active_buffer(false);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "bd!".into(),
+                description: "Close the current buffer, discarding unsaved edits".into(),
+                execute: (|editor, _| {
+                    editor.close_active_buffer(true);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "e".into(),
+                description: "Open a file, % and # stand for the current and alternate file as in e %:h/other.rs".into(),
+                execute: (|editor, args| {
+                    match args.first().filter(|path| !path.is_empty()) {
+                        Some(path) => { editor.event_sender.send(EditorEvent::OpenFile { path: path.clone(), row: None, col: None }); }
+                        None => crate::notify!(editor, Duration::from_secs(2), "Usage: e <path>"),
+                    }
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "view".into(),
+                description: "Open a file read-only, or make the current buffer read-only".into(),
+                execute: (|editor, args| {
+                    match args.first().filter(|path| !path.is_empty()) {
+                        Some(path) => { editor.event_sender.send(EditorEvent::ViewFile(path.clone())); }
+                        None => editor.set_readonly(true),
+                    }
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "lsp".into(),
+                description: "Interface the LSP: lsp restart starts the server of this buffer again, also after it crashed, lsp detach|attach closes or reopens this buffer on it".into(),
+                execute: (|editor, args| {
+                    if let Some(subcommand) = args.first() {
+                        match subcommand.as_str() {
+                            "start" => {
+                                /*
+                                let lsp_name = args[1].clone();
+                                let lsp_args = args[2..].iter().cloned();
+                                
+                                self.lsp = LspService::new(lsp_name, lsp_args);
+                                if let Some(lsp) = self.lsp.as_mut() {
+                                    let path = self.editor.active_buffer().unwrap().path.clone();
+
+                                    let root_index = path.rfind("/").unwrap();
+                                    let root_uri = &path[0..root_index];
+                                    lsp.initialize(&root_uri);
+                                }
+
+                                // editor.event_sender.send(EditorEvent::StartLsp(lsp_name));
+                                */
+                            }
+                            "restart" => { editor.event_sender.send(EditorEvent::RestartLsp); }
+                            "attach" => { editor.event_sender.send(EditorEvent::AttachLsp); }
+                            "detach" => { editor.event_sender.send(EditorEvent::DetachLsp); }
+                            "end" => {}
+                            _ => {}
+                        }
+                    }
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "lsplog".into(),
+                description: "Show what the language servers logged and told the user".into(),
+                execute: (|editor, _| {
+                    editor.event_sender.send(EditorEvent::ShowLspLog);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "messages".into(),
+                description: "Show the notifications that were shown and the recent log".into(),
+                execute: (|editor, _| {
+                    editor.event_sender.send(EditorEvent::ShowMessages);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "framestats".into(),
+                description: "Show render timings and how many frames went over opt.frame_budget".into(),
+                execute: (|editor, _| {
+                    editor.event_sender.send(EditorEvent::ShowFrameStats);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "stats".into(),
+                description: "Show how much memory the buffers, highlight caches and LSP data use".into(),
+                execute: (|editor, _| {
+                    editor.event_sender.send(EditorEvent::ShowStats);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "config".into(),
+                description: "config init: create the config directory with an example config".into(),
+                execute: (|editor, args| {
+                    match args.first().map(|s| s.as_str()) {
+                        Some("init") => { editor.event_sender.send(EditorEvent::ScaffoldConfig); }
+                        _ => crate::notify!(editor, Duration::from_secs(2), "Usage: config init"),
+                    }
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "screenshot".into(),
+                description: "Save the window as a PNG, GUI only: screenshot [path]".into(),
+                execute: (|editor, args| {
+                    let path = args.first().filter(|path| !path.is_empty()).cloned();
+                    editor.event_sender.send(EditorEvent::Screenshot(path));
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "record".into(),
+                description: "Save every frame as a PNG for making GIFs, GUI only: record start [dir] | record stop".into(),
+                execute: (|editor, args| {
+                    match args.first().map(|s| s.as_str()) {
+                        Some("start") => { editor.event_sender.send(EditorEvent::StartRecording(args.get(1).cloned())); }
+                        Some("stop") => { editor.event_sender.send(EditorEvent::StopRecording); }
+                        _ => crate::notify!(editor, Duration::from_secs(2), "Usage: record start [dir] | record stop"),
+                    }
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "checkhealth".into(),
+                description: "Show the stats and theme colors that are hard to read".into(),
+                execute: (|editor, _| {
+                    editor.event_sender.send(EditorEvent::CheckHealth);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "theme".into(),
+                description: "Switch the theme, without a name pick one with a preview: theme [name]".into(),
                 execute: (|editor, args| {
-                    editor.event_sender.send(EditorEvent::QuitRequested);
+                    let name = args.first().filter(|name| !name.is_empty()).cloned();
+                    editor.event_sender.send(EditorEvent::SetTheme(name));
 
                     Ok(())
                 })
@@ -334,10 +4175,10 @@ impl App {
 
         self.commands.register(
             command::Command {
-                name: "w".into(),
-                description: "Save the current buffer".into(),
+                name: "format".into(),
+                description: "Format the buffer, or the selection, with the language server".into(),
                 execute: (|editor, _| {
-                    editor.event_sender.send(EditorEvent::SaveRequested(editor.active_view().unwrap().buffer));
+                    editor.event_sender.send(EditorEvent::Format);
 
                     Ok(())
                 })
@@ -346,31 +4187,408 @@ impl App {
 
         self.commands.register(
             command::Command {
-                name: "lsp".into(),
-                description: "Interface the LSP.".into(),
+                name: "preview".into(),
+                description: "Toggle the rendered preview of a Markdown buffer".into(),
+                execute: (|editor, _| {
+                    if editor.toggle_markdown_preview().is_none() {
+                        crate::notify!(editor, Duration::from_secs(2), "Only Markdown buffers have a preview");
+                    }
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "trim".into(),
+                description: "Remove trailing whitespace from every line".into(),
+                execute: (|editor, _| {
+                    let id = match editor.active_view() {
+                        Some(view) => view.buffer,
+                        None => return Ok(())
+                    };
+
+                    if editor.buffer(&id).is_some_and(|buffer| buffer.readonly) {
+                        crate::notify!(editor, Duration::from_secs(2), "Buffer is read-only");
+                        return Ok(());
+                    }
+
+                    let stripped = editor.strip_trailing_whitespace(id);
+                    crate::notify!(editor, Duration::from_secs(2), "Trimmed {} lines", stripped);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "export-html".into(),
+                description: "Write the buffer, or the selection, with its colors to an HTML page: export-html [path]".into(),
                 execute: (|editor, args| {
-                    if let Some(subcommand) = args.first() {
-                        match subcommand.as_str() {
-                            "start" => {
-                                /*
-                                let lsp_name = args[1].clone();
-                                let lsp_args = args[2..].iter().cloned();
-                                
-                                self.lsp = LspService::new(lsp_name, lsp_args);
-                                if let Some(lsp) = self.lsp.as_mut() {
-                                    let path = self.editor.active_buffer().unwrap().path.clone();
+                    let path = args.first().filter(|path| !path.is_empty()).cloned();
+                    editor.event_sender.send(EditorEvent::Export(ExportFormat::Html, path));
 
-                                    let root_index = path.rfind("/").unwrap();
-                                    let root_uri = &path[0..root_index];
-                                    lsp.initialize(&root_uri);
-                                }
+                    Ok(())
+                })
+            }
+        );
 
-                                // editor.event_sender.send(EditorEvent::StartLsp(lsp_name));
-                                */
-                            }
-                            "end" => {}
-                            _ => {}
+        self.commands.register(
+            command::Command {
+                name: "export-ansi".into(),
+                description: "Write the buffer, or the selection, with terminal color codes: export-ansi [path]".into(),
+                execute: (|editor, args| {
+                    let path = args.first().filter(|path| !path.is_empty()).cloned();
+                    editor.event_sender.send(EditorEvent::Export(ExportFormat::Ansi, path));
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "rename".into(),
+                description: "Rename the symbol under the cursor everywhere using the language server: rename <newname>".into(),
+                execute: (|editor, args| {
+                    match args.first().filter(|name| !name.is_empty()) {
+                        Some(name) => {
+                            editor.event_sender.send(EditorEvent::Rename(name.clone()));
+                        }
+                        None => crate::notify!(editor, Duration::from_secs(2), "Usage: rename <newname>"),
+                    }
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "keys".into(),
+                description: "List all key bindings in a new buffer, optionally exporting them as markdown: keys [path]".into(),
+                execute: (|editor, args| {
+                    let path = args.first().filter(|path| !path.is_empty()).cloned();
+                    editor.event_sender.send(EditorEvent::ShowKeys(path));
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "transform".into(),
+                description: "Pipe the buffer or a range through a command and preview the result: transform <cmd>, transform accept, transform reject".into(),
+                execute: (|editor, args| {
+                    let event = match args.first().map(|s| s.as_str()) {
+                        Some("accept") if args.len() == 1 => EditorEvent::AcceptTransform,
+                        Some("reject") if args.len() == 1 => EditorEvent::RejectTransform,
+                        Some(command) if !command.is_empty() => EditorEvent::StartTransform {
+                            command: args.raw.clone(),
+                            range: args.range.map(|range| (range.start, range.end)),
+                        },
+                        _ => {
+                            crate::notify!(editor, Duration::from_secs(2), "Usage: transform <cmd> | accept | reject");
+                            return Ok(());
+                        }
+                    };
+                    editor.event_sender.send(event);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "diagnostics".into(),
+                description: "Toggle the list of diagnostics for the current buffer".into(),
+                execute: (|editor, _| {
+                    editor.event_sender.send(EditorEvent::ToggleDiagnostics);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "dnext".into(),
+                description: "Go to the next diagnostic, like ]d".into(),
+                execute: (|editor, _| {
+                    editor.jump_to_diagnostic(true);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "dprev".into(),
+                description: "Go to the previous diagnostic, like [d".into(),
+                execute: (|editor, _| {
+                    editor.jump_to_diagnostic(false);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "fold".into(),
+                description: "Fold the lines from start to end, like zf on a selection: [range]fold or fold <start> <end>".into(),
+                execute: (|editor, args| {
+                    let rows: Vec<usize> = args.iter().filter_map(|arg| arg.parse().ok()).collect();
+
+                    match (args.range, rows.as_slice()) {
+                        (Some(range), _) => editor.create_fold(range.start, range.end),
+                        (None, [start, end]) if *start > 0 => editor.create_fold(start - 1, end.saturating_sub(1)),
+                        _ => crate::notify!(editor, Duration::from_secs(2), "Usage: [range]fold or fold <start> <end>"),
+                    }
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "calls".into(),
+                description: "Browse the callers (in) or callees (out) of the function under the cursor".into(),
+                execute: (|editor, args| {
+                    let direction = match args.first().map(|arg| arg.as_str()) {
+                        Some("in") => CallDirection::Incoming,
+                        Some("out") => CallDirection::Outgoing,
+                        _ => {
+                            crate::notify!(editor, Duration::from_secs(2), "Usage: calls in|out");
+                            return Ok(());
+                        }
+                    };
+                    editor.event_sender.send(EditorEvent::ShowCalls(direction));
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "ps".into(),
+                description: "Show the processes the editor spawned with their CPU and memory use, r restarts and x kills one".into(),
+                execute: (|editor, _| {
+                    editor.event_sender.send(EditorEvent::ToggleProcesses);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "r".into(),
+                description: "Insert the output of a shell command below the cursor: r !<cmd>".into(),
+                execute: (|editor, args| {
+                    let command = match args.raw.strip_prefix('!') {
+                        Some(command) if !command.trim().is_empty() => command.to_string(),
+                        _ => {
+                            crate::notify!(editor, Duration::from_secs(2), "Usage: r !<cmd>");
+                            return Ok(());
+                        }
+                    };
+
+                    let target = match editor.active_view() {
+                        Some(view) => ShellTarget::Insert { buffer: view.buffer, row: view.cursor.row },
+                        None => return Ok(())
+                    };
+                    if editor.active_buffer().is_some_and(|buffer| buffer.readonly) {
+                        crate::notify!(editor, Duration::from_secs(2), "Buffer is read-only");
+                        return Ok(());
+                    }
+                    editor.event_sender.send(EditorEvent::RunShell(command, target));
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "d".into(),
+                description: "Delete the lines of a range, or the cursor line: [range]d".into(),
+                execute: (|editor, args| {
+                    let (id, row) = match editor.active_view() {
+                        Some(view) => (view.buffer, view.cursor.row),
+                        None => return Ok(())
+                    };
+                    let range = args.range.unwrap_or(LineRange { start: row, end: row });
+
+                    let (readonly, mut lines) = match editor.buffer(&id) {
+                        Some(buffer) => (buffer.readonly, buffer.lines.clone()),
+                        None => return Ok(())
+                    };
+                    if readonly {
+                        crate::notify!(editor, Duration::from_secs(2), "Buffer is read-only");
+                        return Ok(());
+                    }
+
+                    let end = (range.end + 1).min(lines.len());
+                    lines.drain(range.start.min(end)..end);
+                    editor.replace_lines(id, lines);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "palette".into(),
+                description: "Pick a command from the list of all of them".into(),
+                execute: (|editor, _| {
+                    editor.event_sender.send(EditorEvent::ShowPalette);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "blame".into(),
+                description: "Show the commit that last changed the cursor line".into(),
+                execute: (|editor, _| {
+                    editor.event_sender.send(EditorEvent::ShowBlame);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "undotree".into(),
+                description: "Toggle the undo tree of the current buffer".into(),
+                execute: (|editor, _| {
+                    editor.event_sender.send(EditorEvent::ToggleUndoTree);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "earlier".into(),
+                description: "Go back in the undo history by changes or time, like 3, 30s, 2m or 1h".into(),
+                execute: (|editor, args| {
+                    match HistoryStep::parse(args.first().map(|arg| arg.as_str()).unwrap_or("1")) {
+                        Some(step) => editor.earlier(step),
+                        None => crate::notify!(editor, Duration::from_secs(2), "Usage: earlier [count|Ns|Nm|Nh]"),
+                    }
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "later".into(),
+                description: "Go forward in the undo history by changes or time, like 3, 30s, 2m or 1h".into(),
+                execute: (|editor, args| {
+                    match HistoryStep::parse(args.first().map(|arg| arg.as_str()).unwrap_or("1")) {
+                        Some(step) => editor.later(step),
+                        None => crate::notify!(editor, Duration::from_secs(2), "Usage: later [count|Ns|Nm|Nh]"),
+                    }
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "repl".into(),
+                description: "Run an interpreter to send code to: repl start [cmd], repl send, repl toggle, repl stop".into(),
+                execute: (|editor, args| {
+                    let event = match args.first().map(|s| s.as_str()) {
+                        Some("start") => {
+                            let command = args.raw.strip_prefix("start").unwrap_or("").trim().to_string();
+                            EditorEvent::StartRepl(if command.is_empty() { None } else { Some(command) })
+                        }
+                        Some("send") => match editor.repl_text(ReplScope::Line) {
+                            Some(text) => EditorEvent::SendToRepl(text),
+                            None => return Ok(()),
+                        },
+                        Some("toggle") => EditorEvent::ToggleRepl,
+                        Some("stop") => EditorEvent::StopRepl,
+                        _ => {
+                            crate::notify!(editor, Duration::from_secs(2), "Usage: repl start [cmd] | send | toggle | stop");
+                            return Ok(());
+                        }
+                    };
+                    editor.event_sender.send(event);
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "tmux".into(),
+                description: "Send the selection or current line to another tmux pane, the last active one by default: tmux send [target]".into(),
+                execute: (|editor, args| {
+                    if args.first().map(|s| s.as_str()) != Some("send") {
+                        crate::notify!(editor, Duration::from_secs(2), "Usage: tmux send [target]");
+                        return Ok(());
+                    }
+
+                    let target = args.get(1).filter(|target| !target.is_empty())
+                        .map(|target| target.as_str())
+                        .unwrap_or(tmux::DEFAULT_TARGET);
+
+                    let text = match editor.selected_text() {
+                        Some(text) => text,
+                        None => match (editor.active_buffer(), editor.active_view()) {
+                            (Some(buffer), Some(view)) => buffer.lines.get(view.cursor.row).cloned().unwrap_or_default(),
+                            _ => return Ok(())
+                        }
+                    };
+
+                    if let Err(error) = tmux::send(target, &text) {
+                        crate::notify!(editor, Duration::from_secs(2), "Could not send to tmux: {}", error);
+                    }
+
+                    Ok(())
+                })
+            }
+        );
+
+        self.commands.register(
+            command::Command {
+                name: "collab".into(),
+                description: "Share the current buffer: collab host [address] [token], collab join <address> <token>, collab stop".into(),
+                execute: (|editor, args| {
+                    let address = args.get(1).filter(|address| !address.is_empty()).cloned();
+                    let token = args.get(2).filter(|token| !token.is_empty()).cloned();
+
+                    match args.first().map(|s| s.as_str()) {
+                        Some("host") => {
+                            let address = address.unwrap_or_else(|| DEFAULT_COLLAB_ADDRESS.to_string());
+                            editor.event_sender.send(EditorEvent::HostCollab { address, token });
+                        }
+                        Some("join") => match (address, token) {
+                            (Some(address), Some(token)) => { editor.event_sender.send(EditorEvent::JoinCollab { address, token }); }
+                            _ => crate::notify!(editor, Duration::from_secs(2), "Usage: collab join <address> <token>"),
                         }
+                        Some("stop") => { editor.event_sender.send(EditorEvent::StopCollab); }
+                        _ => crate::notify!(editor, Duration::from_secs(2), "Usage: collab host|join|stop"),
                     }
 
                     Ok(())
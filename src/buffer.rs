@@ -1,11 +1,71 @@
 use std::collections::HashMap;
+use std::cell::RefCell;
+use std::io;
 
 use crate::types::{Size, EditorMode, BufferId, Cursor, ScrollOffset, ViewId};
 use crate::highlighter::Highlighter;
+use crate::renderer::crossterm::GUTTER_WIDTH;
+use crate::crypto::Encryption;
+use crate::encoding::Encoding;
+use crate::diagnostics::Diagnostic;
+use crate::inlay_hints::InlayHint;
+use crate::folding::{self, Fold};
+use crate::undo::UndoTree;
+use crate::text_edit::TextEdit;
+use crate::git::{self, GitSign};
+use crate::plugins::filetype::FiletypeConfig;
+use crate::plugins::options::Options;
 
 
-#[derive(Debug, Clone)]
-pub struct Selection {} // TODO: Support selections
+/// Text between where a selection started and where it currently ends, either may come first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    pub anchor: Cursor,
+    pub head: Cursor,
+}
+
+impl Selection {
+    /// Start and end of the selection in document order, the end is exclusive.
+    pub fn range(&self) -> (Cursor, Cursor) {
+        if (self.anchor.row, self.anchor.col) <= (self.head.row, self.head.col) {
+            (self.anchor.clone(), self.head.clone())
+        } else {
+            (self.head.clone(), self.anchor.clone())
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    /// The columns of `row` that are selected, `None` if the selection doesn't touch the row.
+    pub fn cols_on(&self, row: usize, line_len: usize) -> Option<(usize, usize)> {
+        let (start, end) = self.range();
+        if self.is_empty() || row < start.row || row > end.row { return None }
+
+        let from = if row == start.row { start.col } else { 0 };
+        // Include the line break when the selection goes on to the next line
+        let to = if row == end.row { end.col } else { line_len + 1 };
+
+        Some((from, to))
+    }
+
+    pub fn text(&self, buffer: &Buffer) -> String {
+        let (start, end) = self.range();
+        let mut text = String::new();
+
+        for row in start.row..=end.row.min(buffer.lines.len().saturating_sub(1)) {
+            let line = &buffer.lines[row];
+            let from = if row == start.row { start.col } else { 0 };
+            let to = if row == end.row { end.col } else { usize::MAX };
+
+            text.extend(line.chars().skip(from).take(to.saturating_sub(from)));
+            if row != end.row { text.push('\n'); }
+        }
+
+        text
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct BufferView {
@@ -16,7 +76,63 @@ pub struct BufferView {
     pub selection: Option<Selection>,
     pub size: Size,
     pub mode: EditorMode,
-    pub highlighter: Highlighter
+    pub highlighter: Highlighter,
+    /// Closed folds, their lines are skipped when drawing and moving.
+    pub folds: Vec<Fold>,
+    /// Folds made by hand with `zf` or `:fold`, open or closed.
+    pub manual_folds: Vec<Fold>,
+    /// The buffer shown before this one, where Ctrl-^ and `#` lead back to.
+    pub alternate: Option<BufferId>,
+    /// Markdown is drawn rendered, the cursor's line keeps its markup for editing.
+    pub markdown_preview: bool,
+    /// What a `filetype(...)` block in the config sets for the buffer's file type.
+    pub filetype: Option<FiletypeConfig>,
+    line_info: RefCell<Option<LineInfo>>
+}
+
+/// Cached facts about a single line so cursor movement doesn't rescan it on every keystroke.
+#[derive(Debug, Clone, Copy)]
+pub struct LineInfo {
+    pub row: usize,
+    pub version: u32,
+    pub chars: usize,
+    pub ascii: bool,
+}
+
+impl LineInfo {
+    pub fn byte_index(&self, line: &str, col: usize) -> usize {
+        if self.ascii {
+            return col.min(line.len());
+        }
+
+        line.char_indices()
+            .nth(col)
+            .map(|(i, _)| i)
+            .unwrap_or_else(|| line.len())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Unix,
+    Dos,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Unix => "\n",
+            LineEnding::Dos => "\r\n",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(LineEnding::Unix),
+            "dos" => Some(LineEnding::Dos),
+            _ => None,
+        }
+    }
 }
 
 pub enum BufferLocation {
@@ -33,21 +149,139 @@ pub struct Buffer {
     pub lines: Vec<String>,
     pub path: String,
     pub version: u32,
+    pub saved_version: u32,
+    pub readonly: bool,
+    pub filetype: String,
+    /// Set when the file on disk is encrypted, saves go back through the same tool.
+    pub encryption: Option<Encryption>,
+    pub line_ending: LineEnding,
+    /// Whether the file ended with a line ending when it was read.
+    pub final_newline: bool,
+    pub encoding: Encoding,
+    /// Latest diagnostics published by the LSP.
+    pub diagnostics: Vec<Diagnostic>,
+    /// Hints for the lines around the last visible range, shown as virtual text.
+    pub inlay_hints: Vec<InlayHint>,
+    /// What the language server says can be folded, `za` and `zc` close these.
+    pub fold_ranges: Vec<Fold>,
+    pub history: UndoTree,
+    /// Edits the language server hasn't been sent yet, in order.
+    /// `None` once so many piled up that sending the whole text is cheaper.
+    pub unsynced: Option<Vec<TextEdit>>,
+    /// The file as staged in git, `None` if it isn't tracked.
+    pub git_base: Option<Vec<String>>,
+    /// Gutter signs by row against `git_base`, and the version they were made for.
+    pub git_signs: Vec<Option<GitSign>>,
+    git_signs_version: u32,
+    /// Positions set with `m` and a lowercase letter.
+    pub marks: HashMap<char, Cursor>,
 }
 
 impl Buffer {
     pub fn new(lines: Vec<String>, path: String) -> Self {
+        let filetype = std::path::Path::new(&path)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let history = UndoTree::new(lines.clone(), 1);
+
         Self {
             lines,
             path,
-            version: 1
+            filetype,
+            version: 1,
+            saved_version: 1,
+            readonly: false,
+            encryption: None,
+            line_ending: LineEnding::Unix,
+            final_newline: false,
+            encoding: Encoding::Utf8,
+            diagnostics: Vec::new(),
+            inlay_hints: Vec::new(),
+            fold_ranges: Vec::new(),
+            history,
+            unsynced: Some(vec![]),
+            git_base: None,
+            git_signs: Vec::new(),
+            git_signs_version: 0,
+            marks: HashMap::new()
+        }
+    }
+
+    pub fn set_git_base(&mut self, base: Option<Vec<String>>) {
+        if base == self.git_base { return }
+
+        self.git_base = base;
+        self.git_signs_version = 0;
+        self.update_git_signs();
+    }
+
+    /// Compares the lines to `git_base` again if they changed since the last time.
+    pub fn update_git_signs(&mut self) {
+        if self.git_signs_version == self.version { return }
+        self.git_signs_version = self.version;
+
+        self.git_signs = match &self.git_base {
+            Some(base) => git::signs(base, &self.lines),
+            None => Vec::new(),
+        };
+    }
+
+    /// Display name of the buffer. Scratch buffers have no path.
+    pub fn name(&self) -> &str {
+        if self.path.is_empty() { "[No Name]" } else { &self.path }
+    }
+
+    pub fn is_scratch(&self) -> bool {
+        self.path.is_empty()
+    }
+
+    /// Splits `content` into lines, remembering its line ending and trailing newline.
+    pub fn from_content(content: &str, path: String) -> Self {
+        let line_ending = if content.contains("\r\n") { LineEnding::Dos } else { LineEnding::Unix };
+        let final_newline = content.ends_with('\n') || content.ends_with('\r');
+
+        let mut lines: Vec<String> = content
+            .replace("\r\n", "\n")
+            .replace("\r", "\n")
+            .split("\n")
+            .map(|s| s.to_string())
+            .collect();
+
+        if final_newline {
+            lines.pop();
         }
+
+        let mut buffer = Self::new(lines, path);
+        buffer.line_ending = line_ending;
+        buffer.final_newline = final_newline;
+        buffer
     }
 
     pub fn text(&self) -> String {
         self.lines.join("\n")
     }
 
+    /// The buffer as it should be written to disk, with the original line endings.
+    pub fn file_contents(&self) -> String {
+        let eol = self.line_ending.as_str();
+        let mut content = self.lines.join(eol);
+
+        if self.final_newline {
+            content.push_str(eol);
+        }
+
+        content
+    }
+
+    /// `file_contents` encoded the way the file was read. Fails if the encoding can't
+    /// represent some character.
+    pub fn file_bytes(&self) -> io::Result<Vec<u8>> {
+        self.encoding.encode(&self.file_contents())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))
+    }
+
     pub fn line_at_scroll(&self, scroll: &ScrollOffset, row: usize) -> Option<&str> {
         let absolute = row + scroll.vertical;
         self.lines.get(absolute).map(|s| s.as_str())
@@ -56,6 +290,32 @@ impl Buffer {
     pub fn set(&mut self, lines: Vec<String>, path: String) {
         self.lines = lines;
         self.path = path;
+        self.unsynced = None;
+    }
+
+    pub fn record_change(&mut self, edit: TextEdit) {
+        const MAX_UNSYNCED: usize = 512;
+
+        if let Some(changes) = self.unsynced.as_mut() {
+            if changes.len() < MAX_UNSYNCED {
+                changes.push(edit);
+            } else {
+                self.unsynced = None;
+            }
+        }
+    }
+
+    /// The edits since the last call, `None` if the whole text has to be sent.
+    pub fn take_changes(&mut self) -> Option<Vec<TextEdit>> {
+        self.unsynced.replace(vec![])
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.version != self.saved_version
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_version = self.version;
     }
 
     pub fn len(&self) -> usize {
@@ -80,7 +340,21 @@ impl BufferView {
             scroll: ScrollOffset { horizontal: 0, vertical: 0 },
             selection: None,
             mode: EditorMode::Normal,
-            highlighter
+            highlighter,
+            folds: Vec::new(),
+            manual_folds: Vec::new(),
+            alternate: None,
+            markdown_preview: false,
+            filetype: None,
+            line_info: RefCell::new(None)
+        }
+    }
+
+    /// The options of the view, what its file type sets over `global`.
+    pub fn options(&self, global: &Options) -> Options {
+        match &self.filetype {
+            Some(filetype) => filetype.opt.merge(global),
+            None => global.clone(),
         }
     }
 
@@ -91,4 +365,84 @@ impl BufferView {
     pub fn visible_bottom(&self) -> usize {
         self.scroll.vertical + (self.size.rows as usize).saturating_sub(1)
     }
+
+    /// The buffer rows on screen from top to bottom, with folded lines left out.
+    pub fn screen_rows(&self, total_lines: usize) -> Vec<usize> {
+        folding::visible_rows(&self.folds, self.scroll.vertical, self.size.rows as usize, total_lines)
+    }
+
+    /// How far below the top of the view buffer row `row` is drawn.
+    pub fn screen_row(&self, row: usize) -> usize {
+        folding::screen_row(&self.folds, self.scroll.vertical, row)
+    }
+
+    pub fn line_info(&self, buffer: &Buffer, row: usize) -> LineInfo {
+        if let Some(info) = *self.line_info.borrow() {
+            if info.row == row && info.version == buffer.version {
+                return info;
+            }
+        }
+
+        let line = buffer.line(row).unwrap_or("");
+        let info = LineInfo {
+            row,
+            version: buffer.version,
+            chars: line.chars().count(),
+            ascii: line.is_ascii(),
+        };
+
+        *self.line_info.borrow_mut() = Some(info);
+        info
+    }
+
+    /// Keeps the cached info of `row` after `ch` was typed into it, the buffer is at `version` now.
+    pub fn char_inserted(&self, row: usize, ch: char, version: u32) {
+        let mut cached = self.line_info.borrow_mut();
+
+        if let Some(info) = cached.as_mut().filter(|info| info.row == row && info.version + 1 == version) {
+            info.version = version;
+            info.chars += 1;
+            info.ascii &= ch.is_ascii();
+        }
+    }
+
+    /// Keeps the cached info of `row` after `ch` was deleted from it. Whether the line is
+    /// still all ASCII isn't known without looking at it, so that drops the cache.
+    pub fn char_removed(&self, row: usize, ch: char, version: u32) {
+        let mut cached = self.line_info.borrow_mut();
+
+        match cached.as_mut().filter(|info| info.row == row && info.version + 1 == version) {
+            Some(info) if info.ascii || ch.is_ascii() => {
+                info.version = version;
+                info.chars = info.chars.saturating_sub(1);
+            }
+            _ => *cached = None,
+        }
+    }
+
+    /// Adjusts the scroll offsets so the cursor stays visible.
+    /// `line_chars` is the length of the cursor line, the cursor is drawn clamped to it.
+    pub fn scroll_to_cursor(&mut self, line_chars: usize) {
+        let rows = (self.size.rows as usize).max(1);
+        let cols = (self.size.cols.saturating_sub(GUTTER_WIDTH) as usize).max(1);
+        let col = self.cursor.col.min(line_chars);
+
+        if self.cursor.row < self.scroll.vertical {
+            self.scroll.vertical = self.cursor.row;
+        } else if self.screen_row(self.cursor.row) >= rows {
+            // Walk up from the cursor so folded lines don't count against the height
+            let mut top = self.cursor.row;
+            for _ in 1..rows {
+                if top == 0 { break }
+                top = folding::previous_visible(&self.folds, top - 1);
+            }
+            self.scroll.vertical = top;
+        }
+
+        if col < self.scroll.horizontal {
+            self.scroll.horizontal = col;
+        } else if col >= self.scroll.horizontal + cols {
+            self.scroll.horizontal = col + 1 - cols;
+        }
+    }
 }
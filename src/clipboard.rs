@@ -0,0 +1,89 @@
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+
+use crate::tmux;
+
+/// The X11/Wayland selections. `Primary` holds whatever was selected last and is pasted with a middle click.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClipboardKind {
+    Primary,
+    System,
+}
+
+/// The command line tool that talks to the display server, picked by the session type.
+/// Without a display, e.g. over ssh, tmux buffers stand in for both selections.
+fn copy_command(kind: ClipboardKind) -> Option<(&'static str, Vec<&'static str>)> {
+    let primary = kind == ClipboardKind::Primary;
+
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        return Some(("wl-copy", if primary { vec!["--primary"] } else { vec![] }));
+    }
+
+    if std::env::var_os("DISPLAY").is_some() {
+        return Some(("xclip", vec!["-selection", if primary { "primary" } else { "clipboard" }]));
+    }
+
+    if tmux::is_inside() {
+        return Some(("tmux", vec!["load-buffer", "-"]));
+    }
+
+    None
+}
+
+fn paste_command(kind: ClipboardKind) -> Option<(&'static str, Vec<&'static str>)> {
+    let primary = kind == ClipboardKind::Primary;
+
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        return Some(("wl-paste", if primary { vec!["--primary", "--no-newline"] } else { vec!["--no-newline"] }));
+    }
+
+    if std::env::var_os("DISPLAY").is_some() {
+        return Some(("xclip", vec!["-o", "-selection", if primary { "primary" } else { "clipboard" }]));
+    }
+
+    if tmux::is_inside() {
+        return Some(("tmux", vec!["save-buffer", "-"]));
+    }
+
+    None
+}
+
+pub fn copy(kind: ClipboardKind, text: &str) -> io::Result<()> {
+    let (program, args) = copy_command(kind)
+        .ok_or_else(|| io::Error::other("no X11 or Wayland display and not inside tmux"))?;
+
+    // wl-copy and xclip keep running to serve the selection, so don't wait for them
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+
+    std::thread::spawn(move || child.wait());
+
+    Ok(())
+}
+
+pub fn paste(kind: ClipboardKind) -> io::Result<String> {
+    let (program, args) = paste_command(kind)
+        .ok_or_else(|| io::Error::other("no X11 or Wayland display and not inside tmux"))?;
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let mut text = String::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        stdout.read_to_string(&mut text)?;
+    }
+    child.wait()?;
+
+    Ok(text)
+}
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::path::Path;
+
+/// What the special tokens of a command line stand for.
+pub struct Expansions {
+    // Path of the current buffer, empty for a scratch buffer
+    pub current: String,
+    // Path of the alternate buffer, if there is one
+    pub alternate: Option<String>,
+    // Keyword and whitespace-delimited word under the cursor
+    pub cword: Option<String>,
+    pub cbig_word: Option<String>,
+}
+
+/// Replaces `%` and `#` with the current and alternate file and `<cword>`/`<cWORD>` with the
+/// word under the cursor. File names take modifiers like Vim's: `%:p` absolute, `%:h` directory,
+/// `%:t` name, `%:r` without extension, `%:e` extension, chained as in `%:t:r`.
+/// A backslash keeps the next character as it is, `\|` is a bar that doesn't chain commands.
+pub fn expand(line: &str, expansions: &Expansions) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = line;
+
+    while let Some(ch) = rest.chars().next() {
+        rest = &rest[ch.len_utf8()..];
+
+        match ch {
+            '\\' if rest.starts_with(['%', '#', '<', '\\', '|']) => {
+                let escaped = rest.chars().next().unwrap();
+                out.push(escaped);
+                rest = &rest[escaped.len_utf8()..];
+            }
+            '%' | '#' => {
+                let path = match ch {
+                    '%' if !expansions.current.is_empty() => expansions.current.as_str(),
+                    '%' => return Err("No file name to substitute for %".to_string()),
+                    _ => expansions.alternate.as_deref()
+                        .ok_or_else(|| "No alternate file name to substitute for #".to_string())?,
+                };
+
+                let (path, after) = apply_modifiers(path, rest);
+                out.push_str(&path);
+                rest = after;
+            }
+            '<' if rest.starts_with("cword>") || rest.starts_with("cWORD>") => {
+                let big = rest.starts_with("cWORD>");
+                let word = match big {
+                    true => expansions.cbig_word.as_deref(),
+                    false => expansions.cword.as_deref(),
+                };
+
+                match word {
+                    Some(word) => out.push_str(word),
+                    None => return Err("No word under the cursor".to_string()),
+                }
+                rest = &rest["cword>".len()..];
+            }
+            _ => out.push(ch),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Applies the `:x` modifiers at the start of `rest` to `path`, returns the result and what
+/// follows the modifiers.
+fn apply_modifiers<'a>(path: &str, mut rest: &'a str) -> (String, &'a str) {
+    let mut path = path.to_string();
+
+    loop {
+        let modifier = match rest.strip_prefix(':').and_then(|after| after.chars().next()) {
+            Some(modifier @ ('p' | 'h' | 't' | 'r' | 'e')) => modifier,
+            _ => break
+        };
+        rest = &rest[2..];
+
+        path = match modifier {
+            'p' => std::path::absolute(&path)
+                .map(|absolute| absolute.to_string_lossy().into_owned())
+                .unwrap_or(path),
+            'h' => match Path::new(&path).parent().map(|parent| parent.to_string_lossy().into_owned()) {
+                Some(parent) if !parent.is_empty() => parent,
+                // A name without a directory is in the current one
+                _ => ".".to_string(),
+            },
+            't' => Path::new(&path).file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or(path),
+            'r' => match Path::new(&path).extension().map(|extension| path.len() - extension.len() - 1) {
+                Some(end) => path[..end].to_string(),
+                None => path,
+            },
+            _ => Path::new(&path).extension()
+                .map(|extension| extension.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+    }
+
+    (path, rest)
+}
+
+/// The word around character `col` of `line`: letters, digits and `_` for a keyword, anything but
+/// whitespace for a big word. Like Vim, the next word is used when the cursor is before one.
+pub fn word_at(line: &str, col: usize, big: bool) -> Option<String> {
+    let is_word = |ch: char| match big {
+        true => !ch.is_whitespace(),
+        false => ch.is_alphanumeric() || ch == '_',
+    };
+
+    let chars: Vec<char> = line.chars().collect();
+    let mut start = (col.min(chars.len())..chars.len()).find(|&i| is_word(chars[i]))?;
+    while start > 0 && is_word(chars[start - 1]) { start -= 1 }
+
+    let end = (start..chars.len()).find(|&i| !is_word(chars[i])).unwrap_or(chars.len());
+
+    Some(chars[start..end].iter().collect())
+}
+
+/// Where the addresses of a range lead to, as rows.
+pub struct Addresses {
+    pub cursor: usize,
+    pub last: usize,
+    // The last selection of the buffer, `'<` and `'>`
+    pub selection: Option<(usize, usize)>,
+    // Rows of the buffer's marks
+    pub marks: HashMap<char, usize>,
+}
+
+/// Rows a command works on, inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// One command of a command line like `:10,20d`, `:w! "my file.txt"` or `:'<,'>!sort`.
+/// It derefs to its arguments.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CommandInvocation {
+    pub range: Option<LineRange>,
+    // Empty for `:!cmd` and for a range alone
+    pub name: String,
+    // Written with a `!` right after the name, as in `:w!`
+    pub bang: bool,
+    // Split on whitespace, quotes group words
+    pub args: Vec<String>,
+    // The arguments as typed after expanding, for commands that take a shell command
+    pub raw: String,
+}
+
+impl Deref for CommandInvocation {
+    type Target = [String];
+
+    fn deref(&self) -> &[String] {
+        &self.args
+    }
+}
+
+/// Parses a command line into its commands, chained with `|`. Each one is an optional range,
+/// a name, an optional `!` and the arguments, which are expanded as in `expand`.
+/// A shell command, `:!cmd` or `:r !cmd`, takes the rest of the line, bars included.
+pub fn parse(line: &str, addresses: &Addresses, expansions: &Expansions) -> Result<Vec<CommandInvocation>, String> {
+    let mut invocations = vec![];
+    let mut rest = line;
+
+    loop {
+        let segment = rest.trim_start().trim_start_matches(':');
+        let (range, after) = parse_range(segment, addresses)?;
+
+        let after = after.trim_start();
+        let name_len = after.find(|ch: char| !(ch.is_alphanumeric() || ch == '-' || ch == '_')).unwrap_or(after.len());
+        let name = after[..name_len].to_string();
+
+        let after = &after[name_len..];
+        let bang = after.starts_with('!');
+        let after = if bang { &after[1..] } else { after };
+
+        let shell = (name.is_empty() && bang) || after.trim_start().starts_with('!');
+        let (args, next) = match shell {
+            true => (after, None),
+            false => split_bar(after),
+        };
+
+        let raw = expand(args.trim(), expansions)?;
+        let args = split_args(&raw)?;
+        invocations.push(CommandInvocation { range, name, bang, args, raw });
+
+        match next {
+            Some(next) => rest = next,
+            None => break,
+        }
+    }
+
+    Ok(invocations)
+}
+
+/// Reads a range like `10,20`, `%`, `.,$`, `'<,'>`, `'a,+2` from the start of `text`.
+/// Returns it with what follows, `None` if there is none.
+fn parse_range<'a>(text: &'a str, addresses: &Addresses) -> Result<(Option<LineRange>, &'a str), String> {
+    if let Some(after) = text.strip_prefix('%') {
+        return Ok((Some(LineRange { start: 0, end: addresses.last }), after));
+    }
+
+    let (start, after) = match parse_address(text, addresses)? {
+        (Some(start), after) => (start, after),
+        (None, _) => return Ok((None, text)),
+    };
+
+    let (end, after) = match after.strip_prefix([',', ';']) {
+        Some(after) => match parse_address(after, addresses)? {
+            (Some(end), after) => (end, after),
+            // "5," goes to the cursor line
+            (None, after) => (addresses.cursor, after),
+        },
+        None => (start, after),
+    };
+
+    Ok((Some(LineRange { start: start.min(end), end: start.max(end) }), after))
+}
+
+/// A line number, `.`, `$` or `'x` mark, followed by any number of `+n` and `-n` offsets.
+/// Offsets alone count from the cursor.
+fn parse_address<'a>(text: &'a str, addresses: &Addresses) -> Result<(Option<usize>, &'a str), String> {
+    let mut rest = text;
+
+    let mut row = if let Some(after) = rest.strip_prefix('.') {
+        rest = after;
+        Some(addresses.cursor)
+    } else if let Some(after) = rest.strip_prefix('$') {
+        rest = after;
+        Some(addresses.last)
+    } else if let Some(after) = rest.strip_prefix('\'') {
+        let name = after.chars().next().ok_or_else(|| "Missing the name of the mark".to_string())?;
+        rest = &after[name.len_utf8()..];
+
+        let row = match name {
+            '<' => addresses.selection.map(|(start, _)| start),
+            '>' => addresses.selection.map(|(_, end)| end),
+            _ => addresses.marks.get(&name).copied(),
+        };
+        Some(row.ok_or_else(|| format!("Mark {} is not set", name))?)
+    } else {
+        let digits = rest.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(rest.len());
+        let number = rest[..digits].parse::<usize>().ok();
+        rest = &rest[digits..];
+        number.map(|number| number.saturating_sub(1))
+    };
+
+    while let Some(sign) = rest.chars().next().filter(|ch| matches!(ch, '+' | '-')) {
+        rest = &rest[1..];
+
+        let digits = rest.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(rest.len());
+        let amount = rest[..digits].parse::<usize>().unwrap_or(1);
+        rest = &rest[digits..];
+
+        let base = row.unwrap_or(addresses.cursor);
+        row = Some(match sign {
+            '+' => base.saturating_add(amount),
+            _ => base.saturating_sub(amount),
+        });
+    }
+
+    Ok((row.map(|row| row.min(addresses.last)), rest))
+}
+
+/// Splits at the first `|` outside of quotes that isn't escaped.
+fn split_bar(text: &str) -> (&str, Option<&str>) {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (i, ch) in text.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' | '\'' if quote.is_none() => quote = Some(ch),
+            _ if quote == Some(ch) => quote = None,
+            '|' if quote.is_none() => return (&text[..i], Some(&text[i + 1..])),
+            _ => {}
+        }
+    }
+
+    (text, None)
+}
+
+/// Splits arguments on whitespace. Quotes keep words together, a backslash in double quotes
+/// keeps the next character as it is and single quotes are taken literally.
+pub fn split_args(text: &str) -> Result<Vec<String>, String> {
+    let mut args = vec![];
+    let mut current: Option<String> = None;
+    let mut chars = text.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' | '\'' => {
+                let arg = current.get_or_insert_with(String::new);
+
+                loop {
+                    match chars.next() {
+                        Some(end) if end == ch => break,
+                        Some('\\') if ch == '"' => arg.extend(chars.next()),
+                        Some(inner) => arg.push(inner),
+                        None => return Err(format!("Unclosed {} in the arguments", ch)),
+                    }
+                }
+            }
+            ch if ch.is_whitespace() => args.extend(current.take()),
+            ch => current.get_or_insert_with(String::new).push(ch),
+        }
+    }
+    args.extend(current);
+
+    Ok(args)
+}
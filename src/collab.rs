@@ -0,0 +1,267 @@
+use serde::{Deserialize, Serialize};
+
+use crate::text_edit::{self, TextEdit};
+
+/// A single edit as produced by the editor. Positions are char columns.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind")]
+pub enum Edit {
+    Insert { row: usize, col: usize, ch: char },
+    Newline { row: usize, col: usize },
+    // Backspace at row/col. At col 0 the line is joined onto the previous one, which was `join_col` chars long.
+    Delete { row: usize, col: usize, join_col: usize },
+}
+
+/// Messages exchanged between the host and its clients, one JSON object per line.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum CollabMessage {
+    // The token has to match the host's, or the host answers `Refused` and hangs up
+    Hello { name: String, token: String },
+    Refused { reason: String },
+    Welcome { site: u64, revision: u64, path: String, text: String },
+    // Sent by a client, based on the host revision it has seen so far
+    Edit { revision: u64, edit: Edit },
+    // Broadcast by the host once an edit got its place in the history. `None` if it was cancelled out.
+    Applied { site: u64, edit: Option<Edit> },
+    Cursor { site: u64, name: String, row: usize, col: usize },
+    Leave { site: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteCursor {
+    pub site: u64,
+    pub name: String,
+    pub row: usize,
+    pub col: usize,
+}
+
+/// Where the position `(row, col)` ends up after `edit` was applied.
+/// `after` decides whether a position exactly at an insertion point moves along with it.
+pub fn map_position(row: usize, col: usize, edit: &Edit, after: bool) -> (usize, usize) {
+    match *edit {
+        Edit::Insert { row: r, col: c, .. } => {
+            if row == r && (col > c || (col == c && after)) {
+                (row, col + 1)
+            } else {
+                (row, col)
+            }
+        }
+        Edit::Newline { row: r, col: c } => {
+            if row == r && (col > c || (col == c && after)) {
+                (row + 1, col - c)
+            } else if row > r {
+                (row + 1, col)
+            } else {
+                (row, col)
+            }
+        }
+        Edit::Delete { row: r, col: c, .. } if c > 0 => {
+            if row == r && col >= c {
+                (row, col - 1)
+            } else {
+                (row, col)
+            }
+        }
+        Edit::Delete { row: r, join_col, .. } => {
+            if r == 0 {
+                (row, col)
+            } else if row == r {
+                (row - 1, join_col + col)
+            } else if row > r {
+                (row - 1, col)
+            } else {
+                (row, col)
+            }
+        }
+    }
+}
+
+/// Position of the character (or line break) removed by a delete.
+fn deleted_position(edit: &Edit) -> Option<(usize, usize)> {
+    match *edit {
+        Edit::Delete { row, col, .. } if col > 0 => Some((row, col - 1)),
+        Edit::Delete { row, join_col, .. } if row > 0 => Some((row - 1, join_col)),
+        _ => None,
+    }
+}
+
+/// Rewrites `edit` so it can be applied after `other`, which happened concurrently.
+/// `first` breaks ties between two edits at the same spot. Returns `None` if `other` already did the same.
+pub fn transform(edit: &Edit, other: &Edit, first: bool) -> Option<Edit> {
+    match *edit {
+        Edit::Insert { row, col, ch } => {
+            let (row, col) = map_position(row, col, other, !first);
+            Some(Edit::Insert { row, col, ch })
+        }
+        Edit::Newline { row, col } => {
+            let (row, col) = map_position(row, col, other, !first);
+            Some(Edit::Newline { row, col })
+        }
+        Edit::Delete { .. } => {
+            let deleted = deleted_position(edit)?;
+
+            if deleted_position(other) == Some(deleted) {
+                return None;
+            }
+
+            let is_join = matches!(edit, Edit::Delete { col: 0, .. });
+            let (row, col) = map_position(deleted.0, deleted.1, other, true);
+
+            if is_join {
+                Some(Edit::Delete { row: row + 1, col: 0, join_col: col })
+            } else {
+                Some(Edit::Delete { row, col: col + 1, join_col: 0 })
+            }
+        }
+    }
+}
+
+fn byte_index(line: &str, col: usize) -> usize {
+    line.char_indices().nth(col).map(|(i, _)| i).unwrap_or(line.len())
+}
+
+/// Applies `edit` to `lines`. Out of range positions are clamped rather than rejected.
+pub fn apply(lines: &mut Vec<String>, edit: &Edit) {
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+
+    match *edit {
+        Edit::Insert { row, col, ch } => {
+            let row = row.min(lines.len() - 1);
+            let idx = byte_index(&lines[row], col);
+            lines[row].insert(idx, ch);
+        }
+        Edit::Newline { row, col } => {
+            let row = row.min(lines.len() - 1);
+            let idx = byte_index(&lines[row], col);
+            let rest = lines[row].split_off(idx);
+            lines.insert(row + 1, rest);
+        }
+        Edit::Delete { row, col, .. } if col > 0 => {
+            if let Some(line) = lines.get_mut(row) {
+                let idx = byte_index(line, col - 1);
+                if idx < line.len() {
+                    line.remove(idx);
+                }
+            }
+        }
+        Edit::Delete { row, .. } => {
+            if row > 0 && row < lines.len() {
+                let line = lines.remove(row);
+                lines[row - 1].push_str(&line);
+            }
+        }
+    }
+}
+
+/// The edits that turn `old` into `new`, for changes that didn't come from typing like undo,
+/// paste or a plugin rewriting the buffer. The text between the common start and end is
+/// deleted from its end and the new text typed in its place.
+pub fn edits_between(old: &[String], new: &[String]) -> Vec<Edit> {
+    let old_chars: Vec<char> = old.join("\n").chars().collect();
+    let new_chars: Vec<char> = new.join("\n").chars().collect();
+
+    let prefix = old_chars.iter().zip(&new_chars).take_while(|(a, b)| a == b).count();
+    let suffix = old_chars[prefix..].iter().rev()
+        .zip(new_chars[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    // Row and column of every char of `old`, and of its end
+    let mut positions = Vec::with_capacity(old_chars.len() + 1);
+    let (mut row, mut col) = (0, 0);
+    for ch in &old_chars {
+        positions.push((row, col));
+        if *ch == '\n' { (row, col) = (row + 1, 0) } else { col += 1 }
+    }
+    positions.push((row, col));
+
+    let mut edits = vec![];
+
+    // Backwards so everything before the removed char is still where it was in `old`
+    for index in (prefix..old_chars.len() - suffix).rev() {
+        let (row, col) = positions[index + 1];
+        let join_col = if old_chars[index] == '\n' { positions[index].1 } else { 0 };
+        edits.push(Edit::Delete { row, col, join_col });
+    }
+
+    let (mut row, mut col) = positions[prefix];
+    for &ch in &new_chars[prefix..new_chars.len() - suffix] {
+        if ch == '\n' {
+            edits.push(Edit::Newline { row, col });
+            (row, col) = (row + 1, 0);
+        } else {
+            edits.push(Edit::Insert { row, col, ch });
+            col += 1;
+        }
+    }
+
+    edits
+}
+
+/// The same change as a ranged edit with UTF-16 columns, for the language server.
+/// Has to be called before the edit is applied to `lines`.
+pub fn to_text_edit(lines: &[String], edit: &Edit) -> Option<TextEdit> {
+    let last = lines.len().checked_sub(1)?;
+
+    match *edit {
+        Edit::Insert { row, col, ch } => {
+            let row = row.min(last);
+            Some(TextEdit::insert(row, text_edit::utf16_col(&lines[row], col), &ch.to_string()))
+        }
+        Edit::Newline { row, col } => {
+            let row = row.min(last);
+            Some(TextEdit::insert(row, text_edit::utf16_col(&lines[row], col), "\n"))
+        }
+        Edit::Delete { row, col, .. } if col > 0 => {
+            let line = lines.get(row)?;
+            let ch = line.chars().nth(col - 1)?;
+            let start = text_edit::utf16_col(line, col - 1);
+            Some(TextEdit::delete(row, start, row, start + ch.len_utf16()))
+        }
+        Edit::Delete { row, .. } => {
+            if row == 0 || row > last { return None }
+            Some(TextEdit::delete(row - 1, lines[row - 1].encode_utf16().count(), row, 0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(String::from).collect()
+    }
+
+    fn assert_turns_into(old: &str, new: &str) {
+        let (old, new) = (lines(old), lines(new));
+        let mut applied = old.clone();
+
+        for edit in edits_between(&old, &new) {
+            apply(&mut applied, &edit);
+        }
+        assert_eq!(applied, new);
+    }
+
+    #[test]
+    fn edits_between_lines() {
+        assert_turns_into("abc", "abc");
+        assert_turns_into("abc", "abXc");
+        assert_turns_into("abc", "ac");
+        assert_turns_into("one\ntwo\nthree", "one\nthree");
+        assert_turns_into("one\ntwo", "one\n2\n3\ntwo");
+        assert_turns_into("one\ntwo", "onetwo");
+        assert_turns_into("héllo wörld", "hello\nwörld!");
+        assert_turns_into("", "pasted\ntext\n");
+        assert_turns_into("everything\ngoes", "");
+    }
+
+    #[test]
+    fn typing_is_a_single_edit() {
+        assert_eq!(edits_between(&lines("ab"), &lines("axb")), [Edit::Insert { row: 0, col: 1, ch: 'x' }]);
+        assert_eq!(edits_between(&lines("a\nb"), &lines("ab")), [Edit::Delete { row: 1, col: 0, join_col: 1 }]);
+    }
+}
@@ -1,9 +1,11 @@
-use std::io::Result;
+use std::io::{Error, ErrorKind, Result};
 use std::collections::HashMap;
 
+use crate::cmdline::CommandInvocation;
 use crate::editor::Editor;
+use crate::types::EditorEvent;
 
-pub type CommandFn = fn(&mut Editor, Vec<String>) -> Result<()>;
+pub type CommandFn = fn(&mut Editor, CommandInvocation) -> Result<()>;
 
 pub struct Command {
     pub name: String,
@@ -13,22 +15,57 @@ pub struct Command {
 
 pub struct CommandManager {
     commands: HashMap<String, Command>,
+    /// Descriptions of the commands registered in config.rhai by name, the app runs them.
+    user: HashMap<String, String>,
 }
 
 impl CommandManager {
     pub fn new() -> Self {
-        Self { commands: HashMap::new() }
+        Self { commands: HashMap::new(), user: HashMap::new() }
     }
 
     pub fn register(&mut self, cmd: Command) {
         self.commands.insert(cmd.name.clone(), cmd);
     }
 
-    pub fn execute(&mut self, name: &str, args: Vec<String>, editor: &mut Editor) -> Result<()> {
-        if let Some(cmd) = self.commands.get(name) {
-            let _ = (cmd.execute)(editor, args);
-        }
+    /// Replaces the commands from the config. One named like a built-in is left out.
+    pub fn set_user_commands(&mut self, commands: Vec<(String, String)>) {
+        let user = commands.into_iter()
+            .filter(|(name, _)| !self.commands.contains_key(name))
+            .collect();
+        self.user = user;
+    }
 
-        Ok(())
+    /// Names and descriptions of all registered commands, sorted by name.
+    pub fn list(&self) -> Vec<(&str, &str)> {
+        let mut commands: Vec<(&str, &str)> = self.commands.values()
+            .map(|cmd| (cmd.name.as_str(), cmd.description.as_str()))
+            .chain(self.user.iter().map(|(name, description)| (name.as_str(), description.as_str())))
+            .collect();
+
+        commands.sort();
+        commands
+    }
+
+    /// Runs the command the invocation names. `:w!` runs a command registered as "w!" if
+    /// there is one, otherwise "w" with `bang` set.
+    pub fn execute(&mut self, invocation: CommandInvocation, editor: &mut Editor) -> Result<()> {
+        let banged = format!("{}!", invocation.name);
+        let cmd = match invocation.bang {
+            true => self.commands.get(&banged).or_else(|| self.commands.get(&invocation.name)),
+            false => self.commands.get(&invocation.name),
+        };
+
+        match cmd {
+            Some(cmd) => {
+                let _ = (cmd.execute)(editor, invocation);
+                Ok(())
+            }
+            None if self.user.contains_key(&invocation.name) => {
+                editor.event_sender.send(EditorEvent::RunUserCommand(invocation));
+                Ok(())
+            }
+            None => Err(Error::new(ErrorKind::NotFound, format!("Not an editor command: {}", invocation.name))),
+        }
     }
 }
@@ -0,0 +1,195 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::plugins::encryption::EncryptionConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CryptoTool {
+    Gpg,
+    Age,
+}
+
+/// How an encrypted buffer is read and written back.
+/// Decrypted text only ever lives in memory, the tools write ciphertext straight to disk.
+#[derive(Clone, PartialEq)]
+pub struct Encryption {
+    pub tool: CryptoTool,
+    pub passphrase: Option<String>,
+    pub identity: Option<String>,
+    pub recipients: Vec<String>,
+}
+
+// Keep the passphrase out of logs
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Encryption")
+            .field("tool", &self.tool)
+            .field("passphrase", &self.passphrase.as_ref().map(|_| "***"))
+            .field("identity", &self.identity)
+            .field("recipients", &self.recipients)
+            .finish()
+    }
+}
+
+impl Encryption {
+    pub fn needs_passphrase(&self) -> bool {
+        self.tool == CryptoTool::Gpg && self.passphrase.is_none()
+    }
+}
+
+/// Matches `text` against a glob pattern where `*` matches any run of characters.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+
+    let mut rest = text;
+
+    for (i, part) in parts.iter().enumerate() {
+        if i == 0 {
+            match rest.strip_prefix(part) {
+                Some(r) => rest = r,
+                None => return false,
+            }
+        } else if i == parts.len() - 1 {
+            return rest.ends_with(part);
+        } else {
+            match rest.find(part) {
+                Some(idx) => rest = &rest[idx + part.len()..],
+                None => return false,
+            }
+        }
+    }
+
+    true
+}
+
+/// Figures out whether `path` should be handled encrypted.
+/// `.gpg`/`.asc` and `.age` files are always recognised, other files by the configured patterns.
+pub fn detect(path: &str, config: Option<&EncryptionConfig>) -> Option<Encryption> {
+    let extension = Path::new(path).extension().map(|ext| ext.to_string_lossy().to_string());
+
+    let tool = match extension.as_deref() {
+        Some("gpg") | Some("asc") => Some(CryptoTool::Gpg),
+        Some("age") => Some(CryptoTool::Age),
+        _ => None,
+    };
+
+    let configured = config.filter(|config| {
+        config.patterns.iter().any(|pattern| glob_match(pattern, path))
+    });
+
+    let tool = match (tool, configured) {
+        (Some(tool), _) => tool,
+        (None, Some(config)) if config.tool == "age" => CryptoTool::Age,
+        (None, Some(_)) => CryptoTool::Gpg,
+        (None, None) => return None,
+    };
+
+    Some(Encryption {
+        tool,
+        passphrase: None,
+        identity: config.and_then(|c| c.identity.clone()),
+        recipients: config.and_then(|c| c.recipients.clone()).unwrap_or_default(),
+    })
+}
+
+fn gpg() -> Command {
+    let mut command = Command::new("gpg");
+    command.args(["--batch", "--quiet", "--yes", "--pinentry-mode", "loopback", "--passphrase-fd", "0"]);
+    command
+}
+
+fn run(mut command: Command, input: &[u8]) -> io::Result<Vec<u8>> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input)?;
+    }
+
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(message));
+    }
+
+    Ok(output.stdout)
+}
+
+/// Passphrase (if any) followed by the payload, as gpg reads both from stdin.
+fn input(encryption: &Encryption, payload: &[u8]) -> Vec<u8> {
+    let mut input = Vec::new();
+
+    if encryption.tool == CryptoTool::Gpg {
+        input.extend_from_slice(encryption.passphrase.as_deref().unwrap_or("").as_bytes());
+        input.push(b'\n');
+    }
+
+    input.extend_from_slice(payload);
+    input
+}
+
+pub fn decrypt(path: &str, encryption: &Encryption) -> io::Result<Vec<u8>> {
+    let command = match encryption.tool {
+        CryptoTool::Gpg => {
+            let mut command = gpg();
+            command.args(["--decrypt", path]);
+            command
+        }
+        CryptoTool::Age => {
+            let identity = encryption.identity.as_ref().ok_or_else(|| {
+                io::Error::other("no age identity configured")
+            })?;
+
+            let mut command = Command::new("age");
+            command.args(["--decrypt", "-i", identity, path]);
+            command
+        }
+    };
+
+    run(command, &input(encryption, &[]))
+}
+
+pub fn encrypt(path: &str, data: &[u8], encryption: &Encryption) -> io::Result<()> {
+    let command = match encryption.tool {
+        // Encrypted to public keys, those need no passphrase on stdin
+        CryptoTool::Gpg if !encryption.recipients.is_empty() => {
+            let mut command = Command::new("gpg");
+            command.args(["--batch", "--quiet", "--yes", "--encrypt"]);
+            for recipient in &encryption.recipients {
+                command.args(["-r", recipient]);
+            }
+            command.args(["--output", path]);
+            return run(command, data).map(|_| ());
+        }
+        CryptoTool::Gpg => {
+            let mut command = gpg();
+            command.args(["--symmetric", "--output", path]);
+            command
+        }
+        CryptoTool::Age => {
+            if encryption.recipients.is_empty() {
+                return Err(io::Error::other("no age recipients configured"));
+            }
+
+            let mut command = Command::new("age");
+            command.arg("--encrypt");
+            for recipient in &encryption.recipients {
+                command.args(["-r", recipient]);
+            }
+            command.args(["-o", path]);
+            command
+        }
+    };
+
+    run(command, &input(encryption, data)).map(|_| ())
+}
@@ -0,0 +1,122 @@
+use crossterm::style::Color;
+
+use crate::lsp::LspResponse::LspDiagnostic;
+use crate::services::lsp_service::utf16_to_byte_index;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+    Hint,
+}
+
+impl Severity {
+    pub fn from_lsp(severity: Option<u8>) -> Self {
+        match severity {
+            Some(1) => Severity::Error,
+            Some(2) => Severity::Warning,
+            Some(3) => Severity::Info,
+            Some(4) => Severity::Hint,
+            // The spec leaves a missing severity up to the client
+            _ => Severity::Error,
+        }
+    }
+
+    pub fn sign(&self) -> char {
+        match self {
+            Severity::Error => 'E',
+            Severity::Warning => 'W',
+            Severity::Info => 'I',
+            Severity::Hint => 'H',
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Severity::Error => Color::Rgb { r: 234, g: 131, b: 165 },
+            Severity::Warning => Color::Rgb { r: 230, g: 185, b: 157 },
+            Severity::Info => Color::Rgb { r: 146, g: 162, b: 213 },
+            Severity::Hint => Color::Rgb { r: 139, g: 134, b: 147 },
+        }
+    }
+}
+
+/// A diagnostic attached to a buffer. Rows and columns are zero based.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub row: usize,
+    pub col: usize,
+    pub end_row: usize,
+    pub end_col: usize,
+    pub severity: Severity,
+    pub message: String,
+    pub source: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn from_lsp(diagnostic: &LspDiagnostic) -> Self {
+        Self {
+            row: diagnostic.range.start.line as usize,
+            col: diagnostic.range.start.character as usize,
+            end_row: diagnostic.range.end.line as usize,
+            end_col: diagnostic.range.end.character as usize,
+            severity: Severity::from_lsp(diagnostic.severity),
+            message: diagnostic.message.clone(),
+            source: diagnostic.source.clone(),
+        }
+    }
+
+    /// The server sends UTF-16 offsets, the renderers work in chars.
+    pub fn to_char_cols(mut self, lines: &[String]) -> Self {
+        let char_col = |row: usize, col: usize| match lines.get(row) {
+            Some(line) => line[..utf16_to_byte_index(line, col)].chars().count(),
+            None => col,
+        };
+
+        self.col = char_col(self.row, self.col);
+        self.end_col = char_col(self.end_row, self.end_col);
+        self
+    }
+
+    /// The columns of `row` covered by this diagnostic, `None` if it doesn't touch the row.
+    /// Empty ranges still mark a single column so they stay visible.
+    pub fn cols_on(&self, row: usize, line_len: usize) -> Option<(usize, usize)> {
+        if row < self.row || row > self.end_row { return None }
+
+        let start = if row == self.row { self.col } else { 0 };
+        let end = if row == self.end_row { self.end_col } else { line_len };
+
+        Some((start, end.max(start + 1)))
+    }
+}
+
+/// Most severe diagnostic on `row`, used for the gutter sign.
+pub fn worst_on(diagnostics: &[Diagnostic], row: usize) -> Option<Severity> {
+    diagnostics.iter()
+        .filter(|d| row >= d.row && row <= d.end_row)
+        .map(|d| d.severity)
+        .min()
+}
+
+/// Most severe diagnostic starting on `row`, its message is shown after the end of the line.
+pub fn shown_on(diagnostics: &[Diagnostic], row: usize) -> Option<&Diagnostic> {
+    diagnostics.iter()
+        .filter(|d| d.row == row)
+        .min_by_key(|d| d.severity)
+}
+
+/// The first diagnostic after `(row, col)`, or before it when going back. Wraps around the buffer.
+pub fn next_from(diagnostics: &[Diagnostic], row: usize, col: usize, forward: bool) -> Option<&Diagnostic> {
+    let mut sorted: Vec<&Diagnostic> = diagnostics.iter().collect();
+    sorted.sort_by_key(|d| (d.row, d.col));
+
+    match forward {
+        true => sorted.iter().find(|d| (d.row, d.col) > (row, col)).or(sorted.first()).copied(),
+        false => sorted.iter().rev().find(|d| (d.row, d.col) < (row, col)).or(sorted.last()).copied(),
+    }
+}
+
+pub fn count(diagnostics: &[Diagnostic], severity: Severity) -> usize {
+    diagnostics.iter().filter(|d| d.severity == severity).count()
+}
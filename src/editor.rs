@@ -7,10 +7,25 @@ use std::time::Duration;
 use std::collections::HashMap;
 
 use unicode_segmentation::UnicodeSegmentation;
+use crossterm::style::Color;
 
-use crate::buffer::{Buffer, BufferView};
+use crate::buffer::{Buffer, BufferView, LineEnding, Selection};
+use crate::encoding::Encoding;
+use crate::diagnostics::{self, Diagnostic};
+use crate::inlay_hints::InlayHint;
+use crate::folding::{self, Fold};
+use crate::markdown::{self, Marker};
+use crate::indent;
+use crate::undo::{self, HistoryStep};
 use crate::input::InputHandler;
-use crate::types::{BufferId, ViewId, EditorAction, Direction};
+use crate::types::{BufferId, ViewId, EditorAction, Direction, Cursor, ReplScope, LineScope, MarkCommand};
+use crate::marks::{self, GlobalMark};
+use crate::links::{self, Link, LinkTarget};
+use crate::collab::{self, Edit, RemoteCursor};
+use crate::text_edit::{self, TextEdit};
+use crate::cmdline;
+use crate::whitespace;
+use crate::plugins::filetype::{self, FiletypeConfig};
 
 use crate::plugins::plugin_manager::PluginManager;
 use crate::renderer::Renderer;
@@ -38,11 +53,54 @@ macro_rules! notify {
     }};
 }
 
+/// Approximate memory held by a view, in bytes.
+#[derive(Debug, Clone)]
+pub struct MemoryUsage {
+    pub path: String,
+    pub text: usize,
+    pub cache_entries: usize,
+    pub cache: usize,
+    pub tokens: usize,
+}
+
+/// How many positions the jump list keeps.
+const JUMP_LIMIT: usize = 100;
+
+/// A position jumped away from, e.g. by go to definition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Jump {
+    pub buffer: BufferId,
+    pub cursor: Cursor,
+}
+
 pub struct Editor {
     buffers: HashMap<BufferId, Buffer>,
     views: HashMap<ViewId, BufferView>,
     active_view: ViewId,
+    // Ids aren't reused after a buffer is closed
+    next_buffer: u64,
+    next_view: u64,
 
+    pub hover: Option<Cursor>,
+    pub remote_cursors: Vec<RemoteCursor>,
+    pub jumps: Vec<Jump>,
+    // Where Ctrl-O and Ctrl-I are in `jumps`, its length while not walking it
+    jump_index: usize,
+    // Where the last jump came from, the `'` mark
+    last_jump: Option<Jump>,
+    // Uppercase marks, loaded from the last session
+    pub global_marks: HashMap<char, GlobalMark>,
+    // Set by `m`, `'` or a backtick until the next key names the mark
+    pub pending_mark: Option<MarkCommand>,
+    // Rows of the last selection that was cleared, `'<` and `'>` in a range
+    last_selection: Option<(BufferId, usize, usize)>,
+    pub logs: LogManager,
+    // Regex highlighting rules by file type, every view's highlighter gets them
+    syntax_rules: HashMap<String, HashMap<String, String>>,
+    // The theme's colors by key, for the rules
+    theme_colors: HashMap<String, Color>,
+    // What `filetype(...)` blocks set, resolved for a view when it is created
+    filetypes: HashMap<String, FiletypeConfig>,
     pub event_sender: Sender<EditorEvent>
 }
 
@@ -52,6 +110,20 @@ impl Editor {
             buffers: HashMap::new(),
             views: HashMap::new(),
             active_view: ViewId(0),
+            next_buffer: 0,
+            next_view: 0,
+            hover: None,
+            remote_cursors: Vec::new(),
+            jumps: Vec::new(),
+            jump_index: 0,
+            last_jump: None,
+            global_marks: HashMap::new(),
+            pending_mark: None,
+            last_selection: None,
+            logs: LogManager::new(),
+            syntax_rules: HashMap::new(),
+            theme_colors: HashMap::new(),
+            filetypes: HashMap::new(),
             event_sender
         }
     }
@@ -68,7 +140,12 @@ impl Editor {
                             Direction::Right => {
                                 self.event_sender.send(EditorEvent::CommandCursorMoved(1));
                             }
-                            _ => {}
+                            Direction::Up => {
+                                self.event_sender.send(EditorEvent::CommandHistory(-1));
+                            }
+                            Direction::Down => {
+                                self.event_sender.send(EditorEvent::CommandHistory(1));
+                            }
                         }
                         return
                     }
@@ -84,17 +161,26 @@ impl Editor {
             EditorAction::InsertCommandChar(ch) => {
                 self.event_sender.send(EditorEvent::CommandCharInserted(*ch));
             }
+            EditorAction::InsertChar(_) | EditorAction::DeleteChar | EditorAction::InsertNewline
+                | EditorAction::ContinueList | EditorAction::ToggleCheckbox | EditorAction::Undo | EditorAction::Redo
+                | EditorAction::Earlier | EditorAction::Later | EditorAction::Reindent(_) | EditorAction::FilterLines
+                if self.active_buffer().map(|b| b.readonly).unwrap_or(false) => {
+                notify!(self, Duration::from_secs(2), "Buffer is read-only");
+            }
             EditorAction::InsertChar(ch) => {
                 let view = self.views.get(&self.active_view).unwrap();
                 if let Some(buffer) = self.buffers.get_mut(&view.buffer) {
+                    let info = view.line_info(buffer, view.cursor.row);
+
                     if let Some(line) = buffer.lines.get_mut(view.cursor.row) {
                         // check if cursor is inside char (unicode)
-                        let byte_idx = line.char_indices()
-                            .nth(view.cursor.col)
-                            .map(|(i, _)| i)
-                            .unwrap_or_else(|| line.len());
+                        let byte_idx = info.byte_index(line, view.cursor.col);
                         line.insert(byte_idx, *ch);
+                        let start = text_edit::utf16_col(line, view.cursor.col.min(info.chars));
                         buffer.version += 1;
+                        buffer.record_change(TextEdit::insert(view.cursor.row, start, &ch.to_string()));
+                        view.char_inserted(view.cursor.row, *ch, buffer.version);
+
                         view.highlighter.apply_edit(
                             view.cursor.row,
                             view.cursor.col,
@@ -106,6 +192,13 @@ impl Editor {
                         self.move_cursor_right();
 
                         self.event_sender.send(EditorEvent::RequestDeltaSemantics);
+
+                        // After the change is sent so the server sees the new text
+                        match ch {
+                            '(' | ',' => { self.event_sender.send(EditorEvent::RequestSignatureHelp); }
+                            ')' => { self.event_sender.send(EditorEvent::HideSignatureHelp); }
+                            _ => {}
+                        }
                     }
                 }
             }
@@ -116,18 +209,22 @@ impl Editor {
                 let view = self.views.get_mut(&self.active_view).unwrap();
                 if let Some(buffer) = self.buffers.get_mut(&view.buffer) {
                     let line_index = view.cursor.row;
-                    let mut new_col = view.cursor.col;
+                    let info = view.line_info(buffer, line_index);
+                    let mut new_col = view.cursor.col.min(info.chars);
                     let mut move_up = false;
+                    let mut removed_char = None;
 
-                    if view.cursor.col == 0 {
+                    if new_col == 0 {
                         if line_index > 0 {
                             // split the slice to borrow both lines safely
                             let (before, after) = buffer.lines.split_at_mut(line_index);
                             let prev = &mut before[line_index - 1];
                             let curr = &mut after[0];
-                            new_col = prev.clone().len();
+                            new_col = prev.chars().count();
+                            let prev_len = prev.encode_utf16().count();
                             prev.push_str(curr);
                             buffer.lines.remove(line_index);
+                            buffer.record_change(TextEdit::delete(line_index - 1, prev_len, line_index, 0));
                             move_up = true;
 
                             view.highlighter.apply_edit(
@@ -140,12 +237,12 @@ impl Editor {
                             );
                         }
                     } else if let Some(line) = buffer.lines.get_mut(line_index) {
-                        if view.cursor.col <= line.len() {
-                            let byte_idx = line.char_indices()
-                                .nth(view.cursor.col - 1)
-                                .map(|(i, _)| i)
-                                .unwrap_or_else(|| line.len());
-                            line.remove(byte_idx);
+                        if new_col > 0 {
+                            let byte_idx = info.byte_index(line, new_col - 1);
+                            let removed = line.remove(byte_idx);
+                            let start = text_edit::utf16_col(line, new_col - 1);
+                            buffer.record_change(TextEdit::delete(line_index, start, line_index, start + removed.len_utf16()));
+                            removed_char = Some(removed);
                             new_col -= 1;
 
                             view.highlighter.apply_edit(
@@ -158,12 +255,18 @@ impl Editor {
                             );
                         }
                     }
-                    buffer.version += 1; 
-                    
+                    buffer.version += 1;
+                    if let Some(ch) = removed_char {
+                        view.char_removed(line_index, ch, buffer.version);
+                    }
+
                     view.cursor.col = new_col;
+                    view.scroll_to_cursor(new_col);
+                    let id = view.buffer;
                     if move_up { self.move_cursor_up(); }
 
                     self.event_sender.send(EditorEvent::RequestDeltaSemantics);
+                    if move_up { self.shift_folds(id, line_index, -1); }
                 }
             }
             EditorAction::InsertNewline => {
@@ -173,11 +276,14 @@ impl Editor {
                         return;
                     }
 
+                    let info = view.line_info(buffer, view.cursor.row);
+
                     // Take ownership of the current line (no borrow remains)
                     let line = buffer.lines.remove(view.cursor.row);
+                    let byte_idx = info.byte_index(&line, view.cursor.col);
 
-                    if view.cursor.col < line.len() {
-                        let (first, second) = line.split_at(view.cursor.col);
+                    if byte_idx < line.len() {
+                        let (first, second) = line.split_at(byte_idx);
 
                         buffer.lines.insert(view.cursor.row, first.to_string());
                         buffer.lines.insert(view.cursor.row + 1, second.to_string());
@@ -188,6 +294,9 @@ impl Editor {
                     }
                     buffer.version += 1;
 
+                    let start = buffer.lines[view.cursor.row].encode_utf16().count();
+                    buffer.record_change(TextEdit::insert(view.cursor.row, start, "\n"));
+
                     view.highlighter.apply_edit(
                         view.cursor.row,
                         view.cursor.col,
@@ -197,14 +306,21 @@ impl Editor {
                         0
                     );
 
+                    let (id, row) = (view.buffer, view.cursor.row);
+
                     view.cursor.row += 1;
                     view.cursor.col = 0;
+                    view.scroll_to_cursor(0);
 
                     self.event_sender.send(EditorEvent::RequestDeltaSemantics);
+                    self.shift_folds(id, row, 1);
                 }
             }
             EditorAction::ChangeMode(mode) => {
                 if let Some(view) = self.views.get_mut(&self.active_view) {
+                    if view.mode != *mode {
+                        self.event_sender.send(EditorEvent::ModeChanged(mode.clone()));
+                    }
                     view.mode = mode.clone();
                 }
 
@@ -217,33 +333,115 @@ impl Editor {
             EditorAction::ExecuteCommand => {
                 self.event_sender.send(EditorEvent::ExecuteCommand);
             }
+            EditorAction::CompleteCommand => {
+                self.event_sender.send(EditorEvent::CompleteCommand);
+            }
             EditorAction::SaveCurrentBuffer => {
+                self.request_save(false, None);
+            }
+            EditorAction::QuitRequested => {self.event_sender.send(EditorEvent::QuitRequested);},
+            EditorAction::OpenLink | EditorAction::GotoFile => {
+                let cursor = match self.active_view() {
+                    Some(view) => view.cursor.clone(),
+                    None => return
+                };
+                let bare_paths = *action == EditorAction::GotoFile;
+
+                if !self.follow_link_at(cursor.row, cursor.col, bare_paths) {
+                    notify!(self, Duration::from_secs(2), "No link under cursor");
+                }
+            }
+            EditorAction::GotoLocation(kind) => {
+                self.event_sender.send(EditorEvent::RequestLocation(*kind));
+            }
+            EditorAction::PreviewLocation(kind) => {
+                self.event_sender.send(EditorEvent::PreviewLocation(*kind));
+            }
+            EditorAction::Hover => {
+                self.event_sender.send(EditorEvent::RequestHover);
+            }
+            EditorAction::ContinueList => {
+                self.continue_list();
+            }
+            EditorAction::ToggleCheckbox => {
+                let (id, row) = match self.active_view() {
+                    Some(view) => (view.buffer, view.cursor.row),
+                    None => return
+                };
+                let line = match self.buffers.get(&id).and_then(|buffer| buffer.lines.get(row)) {
+                    Some(line) => line.clone(),
+                    None => return
+                };
+
+                match markdown::toggle_checkbox(&line) {
+                    Some(text) => self.replace_line(id, row, text),
+                    None => notify!(self, Duration::from_secs(2), "Not a list item"),
+                }
+            }
+            EditorAction::ToggleFold => self.toggle_fold(),
+            EditorAction::OpenFold => self.open_fold(),
+            EditorAction::CloseFold => self.close_fold(),
+            EditorAction::CreateFold => {
+                match self.operator_rows(LineScope::Line).filter(|_| self.selected_text().is_some()) {
+                    Some((start, end)) => self.create_fold(start, end),
+                    None => notify!(self, Duration::from_secs(2), "Select the lines to fold"),
+                }
+                self.clear_selection();
+            }
+            EditorAction::GotoFirstLine => {
+                self.push_jump();
+                self.set_cursor(0, 0);
+            }
+            EditorAction::GotoLastLine => {
+                let last = self.active_buffer().map(|buffer| buffer.lines.len().saturating_sub(1)).unwrap_or(0);
+                self.push_jump();
+                self.set_cursor(last, 0);
+            }
+            EditorAction::Mark(command) => self.pending_mark = Some(*command),
+            EditorAction::CommandPalette => { self.event_sender.send(EditorEvent::ShowPalette); }
+            EditorAction::RunCommand(line) => { self.event_sender.send(EditorEvent::RunCommandLine(line.clone())); }
+            EditorAction::JumpBack => self.walk_jumps(true),
+            EditorAction::JumpForward => self.walk_jumps(false),
+            EditorAction::SwitchBuffer(id) => self.switch_buffer(*id),
+            EditorAction::AlternateBuffer => self.switch_to_alternate(),
+            EditorAction::NextDiagnostic => self.jump_to_diagnostic(true),
+            EditorAction::PrevDiagnostic => self.jump_to_diagnostic(false),
+            EditorAction::Undo => self.undo(),
+            EditorAction::Redo => self.redo(),
+            EditorAction::Earlier => self.earlier(HistoryStep::Changes(1)),
+            EditorAction::Later => self.later(HistoryStep::Changes(1)),
+            EditorAction::Reindent(scope) => {
+                // The app decides between the language server and the indent engine
+                if let Some((start, end)) = self.operator_rows(*scope) {
+                    self.event_sender.send(EditorEvent::Reindent(start, end));
+                }
+            }
+            EditorAction::FilterLines => {
+                let range = if self.selected_text().is_some() { "'<,'>" } else { "." };
+
                 if let Some(view) = self.views.get_mut(&self.active_view) {
-                    self.event_sender.send(EditorEvent::SaveRequested(view.buffer));
+                    view.mode = EditorMode::Command;
+                }
+                self.event_sender.send(EditorEvent::PromptCommand(format!("{}!", range)));
+            }
+            EditorAction::SendToRepl(scope) => {
+                if let Some(text) = self.repl_text(*scope) {
+                    self.event_sender.send(EditorEvent::SendToRepl(text));
                 }
             }
-            EditorAction::QuitRequested => {self.event_sender.send(EditorEvent::QuitRequested);},
             _ => {}
         }
     }
 
     pub fn open_buffer(&mut self, path: String, content: String, size: Size) {
-        let lines: Vec<String> = content
-            .replace("\r\n", "\n")
-            .replace("\r", "\n")
-            .split("\n")
-            .map(|s| s.to_string())
-            .collect();
-
-        let buffer_id = self.buffers.len();
-        let buffer = Buffer::new(lines, path);
+        let buffer_id = self.next_buffer;
+        self.next_buffer += 1;
+        let buffer = Buffer::from_content(&content, path);
         
-        self.buffers.insert(BufferId(buffer_id as u64), buffer);
+        self.buffers.insert(BufferId(buffer_id), buffer);
 
-        let view_id = ViewId(self.views.len() as u64);
-        let view = BufferView::new(view_id.clone(), BufferId(buffer_id as u64), size.clone());
-        
-        self.views.insert(view_id.clone(), view.clone());
+        let view_id = self.new_view(BufferId(buffer_id), size.clone());
+        self.activate(view_id);
 
         /*
         let file_type_index = path.to_string().rfind(".");
@@ -255,12 +453,1068 @@ impl Editor {
         */
     }
 
+    /// Loads a file into a buffer without showing it, e.g. to preview a definition in it.
+    /// It gets a view once it is focused.
+    pub fn load_buffer(&mut self, path: String, content: String) -> BufferId {
+        let buffer_id = BufferId(self.next_buffer);
+        self.next_buffer += 1;
+
+        self.buffers.insert(buffer_id, Buffer::from_content(&content, path));
+        buffer_id
+    }
+
+    /// Closes the active buffer and shows another one. Buffers with unsaved edits are only closed when forced.
+    pub fn close_active_buffer(&mut self, force: bool) {
+        let id = match self.active_view() {
+            Some(view) => view.buffer,
+            None => return
+        };
+
+        if self.buffers.len() < 2 {
+            notify!(self, Duration::from_secs(2), "Can't close the last buffer (use :q)");
+            return;
+        }
+
+        if !force && self.buffer(&id).is_some_and(|buffer| buffer.is_dirty()) {
+            notify!(self, Duration::from_secs(2), "No write since last change (add ! to override)");
+            return;
+        }
+
+        let alternate = self.active_view().and_then(|view| view.alternate);
+
+        let path = match self.buffers.remove(&id) {
+            Some(buffer) => buffer.path,
+            None => return
+        };
+        self.views.retain(|_, view| view.buffer != id);
+        for view in self.views.values_mut() {
+            view.alternate = view.alternate.filter(|alternate| *alternate != id);
+        }
+
+        // Back to where the closed buffer was opened from, like Vim
+        let next = alternate
+            .and_then(|alternate| self.views.values().find(|view| view.buffer == alternate))
+            .or_else(|| self.views.values().min_by_key(|view| view.id.0))
+            .map(|view| view.id);
+
+        if let Some(view) = next {
+            self.active_view = view;
+        }
+
+        self.event_sender.send(EditorEvent::BufferClosed { id, path });
+    }
+
+    /// Asks the app to save the active buffer, under `path` instead of its own if given. Read-only buffers are only saved when forced.
+    pub fn request_save(&mut self, force: bool, path: Option<String>) {
+        let view = match self.active_view() {
+            Some(view) => view,
+            None => return
+        };
+        let id = view.buffer;
+
+        if !force && self.buffer(&id).map(|b| b.readonly).unwrap_or(false) {
+            notify!(self, Duration::from_secs(2), "Buffer is read-only (add ! to override)");
+            return;
+        }
+
+        self.event_sender.send(EditorEvent::SaveRequested { id, path });
+    }
+
+    pub fn set_filetype(&mut self, filetype: String) {
+        let view = match self.views.get_mut(&self.active_view) {
+            Some(view) => view,
+            None => return
+        };
+
+        if let Some(buffer) = self.buffers.get_mut(&view.buffer) {
+            buffer.filetype = filetype.clone();
+            view.filetype = filetype::for_extension(&self.filetypes, &filetype).cloned();
+            view.highlighter.init(filetype);
+        }
+    }
+
+    pub fn set_readonly(&mut self, readonly: bool) {
+        let id = match self.active_view() {
+            Some(view) => view.buffer,
+            None => return
+        };
+
+        if let Some(buffer) = self.buffers.get_mut(&id) {
+            buffer.readonly = readonly;
+        }
+    }
+
+    /// Applies an edit that came from somewhere else than the keyboard, keeping every cursor in place.
+    pub fn apply_edit(&mut self, id: BufferId, edit: &Edit) {
+        let buffer = match self.buffers.get_mut(&id) {
+            Some(buffer) => buffer,
+            None => return
+        };
+
+        if let Some(change) = collab::to_text_edit(&buffer.lines, edit) {
+            buffer.record_change(change);
+        }
+        collab::apply(&mut buffer.lines, edit);
+        buffer.version += 1;
+
+        for view in self.views.values_mut().filter(|view| view.buffer == id) {
+            (view.cursor.row, view.cursor.col) = collab::map_position(view.cursor.row, view.cursor.col, edit, false);
+            view.cursor.row = view.cursor.row.min(buffer.lines.len().saturating_sub(1));
+
+            match *edit {
+                Edit::Insert { row, col, .. } => view.highlighter.apply_edit(row, col, 0, 0, 0, 1),
+                Edit::Newline { row, col } => view.highlighter.apply_edit(row, col, 0, 0, 1, 0),
+                Edit::Delete { row, col, .. } if col > 0 => view.highlighter.apply_edit(row, col, 0, 1, 0, 0),
+                Edit::Delete { row, col, .. } => view.highlighter.apply_edit(row, col, 1, 0, 0, 0),
+            }
+        }
+
+        self.event_sender.send(EditorEvent::RequestDeltaSemantics);
+    }
+
+    /// Replaces the diagnostics of the buffer showing `path`.
+    pub fn set_diagnostics(&mut self, path: &str, diagnostics: Vec<Diagnostic>) {
+        let target = std::fs::canonicalize(path).ok();
+
+        let buffer = self.buffers.values_mut().find(|buffer| {
+            buffer.path == path || (target.is_some() && std::fs::canonicalize(&buffer.path).ok() == target)
+        });
+
+        if let Some(buffer) = buffer {
+            buffer.diagnostics = diagnostics.into_iter().map(|diagnostic| diagnostic.to_char_cols(&buffer.lines)).collect();
+        }
+    }
+
+    /// Replaces the inlay hints of the buffer showing `path`.
+    pub fn set_inlay_hints(&mut self, path: &str, hints: Vec<InlayHint>) {
+        let buffer = match self.find_buffer(path).and_then(|id| self.buffers.get_mut(&id)) {
+            Some(buffer) => buffer,
+            None => return
+        };
+
+        buffer.inlay_hints = hints.into_iter()
+            .filter_map(|hint| {
+                let line = buffer.lines.get(hint.row)?;
+                Some(hint.to_char_col(line))
+            })
+            .collect();
+    }
+
+    pub fn clear_inlay_hints(&mut self) {
+        for buffer in self.buffers.values_mut() {
+            buffer.inlay_hints.clear();
+        }
+    }
+
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        let id = match self.active_view() {
+            Some(view) => view.buffer,
+            None => return
+        };
+
+        if let Some(buffer) = self.buffers.get_mut(&id) {
+            if buffer.encoding != encoding {
+                buffer.encoding = encoding;
+                buffer.version += 1;
+            }
+        }
+    }
+
+    /// Replaces the whole content of a buffer, keeping cursors inside it.
+    pub fn replace_lines(&mut self, id: BufferId, lines: Vec<String>) {
+        let buffer = match self.buffers.get_mut(&id) {
+            Some(buffer) => buffer,
+            None => return
+        };
+
+        let lines = if lines.is_empty() { vec![String::new()] } else { lines };
+        buffer.record_change(text_edit::diff(&buffer.lines, &lines));
+        buffer.lines = lines;
+        buffer.version += 1;
+        buffer.fold_ranges.retain(|fold| fold.end < buffer.lines.len());
+
+        for view in self.views.values_mut().filter(|view| view.buffer == id) {
+            view.folds.retain(|fold| fold.end < buffer.lines.len());
+            view.manual_folds.retain(|fold| fold.end < buffer.lines.len());
+            view.cursor.row = view.cursor.row.min(buffer.lines.len() - 1);
+            view.cursor.col = view.cursor.col.min(buffer.lines[view.cursor.row].chars().count());
+        }
+
+        self.event_sender.send(EditorEvent::RequestDeltaSemantics);
+    }
+
+    /// Replaces a single line of a buffer.
+    pub fn replace_line(&mut self, id: BufferId, row: usize, text: String) {
+        let buffer = match self.buffers.get_mut(&id) {
+            Some(buffer) => buffer,
+            None => return
+        };
+
+        let end = match buffer.lines.get(row) {
+            Some(line) => line.encode_utf16().count(),
+            None => return
+        };
+        buffer.record_change(TextEdit { start_row: row, start_col: 0, end_row: row, end_col: end, new_text: text.clone() });
+        buffer.lines[row] = text;
+        buffer.version += 1;
+
+        for view in self.views.values_mut().filter(|view| view.buffer == id && view.cursor.row == row) {
+            view.cursor.col = view.cursor.col.min(buffer.lines[row].chars().count());
+        }
+
+        self.event_sender.send(EditorEvent::RequestDeltaSemantics);
+    }
+
+    /// The edits to a buffer the language server hasn't seen, see `Buffer::take_changes`.
+    pub fn take_changes(&mut self, id: BufferId) -> Option<Vec<TextEdit>> {
+        self.buffers.get_mut(&id).and_then(|buffer| buffer.take_changes())
+    }
+
+    /// Adds an undo step for every buffer that changed since its last one.
+    /// Everything typed in one go in insert mode becomes a single step once the mode is left.
+    pub fn record_history(&mut self) {
+        for (id, buffer) in self.buffers.iter_mut() {
+            if buffer.history.is_recorded(buffer.version) { continue }
+
+            let inserting = self.views.values().any(|view| view.buffer == *id && view.mode == EditorMode::Insert);
+            if inserting { continue }
+
+            buffer.history.record(&buffer.lines, buffer.version);
+        }
+    }
+
+    pub fn undo(&mut self) {
+        self.record_history();
+
+        let id = match self.active_view() {
+            Some(view) => view.buffer,
+            None => return
+        };
+
+        match self.buffers.get_mut(&id).and_then(|buffer| buffer.history.undo()).map(|lines| lines.to_vec()) {
+            Some(lines) => self.restore_history(id, lines),
+            None => notify!(self, Duration::from_secs(2), "Already at the oldest change"),
+        }
+    }
+
+    pub fn redo(&mut self) {
+        self.record_history();
+
+        let id = match self.active_view() {
+            Some(view) => view.buffer,
+            None => return
+        };
+
+        match self.buffers.get_mut(&id).and_then(|buffer| buffer.history.redo()).map(|lines| lines.to_vec()) {
+            Some(lines) => self.restore_history(id, lines),
+            None => notify!(self, Duration::from_secs(2), "Already at the newest change"),
+        }
+    }
+
+    /// Goes back through the states of the active buffer in the order they were made.
+    pub fn earlier(&mut self, step: HistoryStep) {
+        self.record_history();
+
+        let history = match self.active_buffer() {
+            Some(buffer) => &buffer.history,
+            None => return
+        };
+
+        let target = history.earlier(step);
+        if target == history.current() {
+            notify!(self, Duration::from_secs(2), "Already at the oldest change");
+            return;
+        }
+        self.jump_history(target);
+    }
+
+    pub fn later(&mut self, step: HistoryStep) {
+        self.record_history();
+
+        let history = match self.active_buffer() {
+            Some(buffer) => &buffer.history,
+            None => return
+        };
+
+        let target = history.later(step);
+        if target == history.current() {
+            notify!(self, Duration::from_secs(2), "Already at the newest change");
+            return;
+        }
+        self.jump_history(target);
+    }
+
+    /// Puts the active buffer into the state of any node of its undo tree.
+    pub fn jump_history(&mut self, node: usize) {
+        self.record_history();
+
+        let id = match self.active_view() {
+            Some(view) => view.buffer,
+            None => return
+        };
+
+        if let Some(lines) = self.buffers.get_mut(&id).and_then(|buffer| buffer.history.jump(node)).map(|lines| lines.to_vec()) {
+            self.restore_history(id, lines);
+        }
+    }
+
+    fn restore_history(&mut self, id: BufferId, lines: Vec<String>) {
+        let row = match self.buffers.get(&id) {
+            Some(buffer) => undo::first_difference(&buffer.lines, &lines),
+            None => return
+        };
+
+        self.replace_lines(id, lines);
+
+        if let Some(buffer) = self.buffers.get_mut(&id) {
+            buffer.history.mark_recorded(buffer.version);
+        }
+
+        // Show where the change was
+        let col = self.active_view().map(|view| view.cursor.col).unwrap_or(0);
+        self.set_cursor(row, col);
+    }
+
+    /// Enter inside a markdown list item starts the next item, on an empty item it ends the list.
+    fn continue_list(&mut self) {
+        let (id, row, col) = match self.active_view() {
+            Some(view) => (view.buffer, view.cursor.row, view.cursor.col),
+            None => return
+        };
+        let line = match self.buffers.get(&id).and_then(|buffer| buffer.lines.get(row)) {
+            Some(line) => line.clone(),
+            None => return
+        };
+        let byte_col = line.char_indices().nth(col).map(|(i, _)| i).unwrap_or(line.len());
+
+        let item = match markdown::list_item(&line) {
+            Some(item) if byte_col >= item.content => item,
+            _ => {
+                self.handle_action(&EditorAction::InsertNewline);
+                return;
+            }
+        };
+
+        if line[item.content..].trim().is_empty() {
+            self.replace_line(id, row, String::new());
+            self.set_cursor(row, 0);
+            return;
+        }
+
+        self.handle_action(&EditorAction::InsertNewline);
+        self.insert_text(&item.continuation());
+
+        if let Marker::Ordered(..) = item.marker {
+            self.renumber_list(id, row + 1);
+        }
+    }
+
+    /// Makes the ordered list around `row` count up again after an item was added.
+    pub fn renumber_list(&mut self, id: BufferId, row: usize) {
+        let mut lines = match self.buffers.get(&id) {
+            Some(buffer) => buffer.lines.clone(),
+            None => return
+        };
+
+        let changed = markdown::renumber(&lines, row);
+        if changed.is_empty() { return }
+
+        for (row, text) in changed {
+            lines[row] = text;
+        }
+        self.replace_lines(id, lines);
+    }
+
+    /// Opens the fold the cursor is on, or closes the innermost section around it.
+    fn toggle_fold(&mut self) {
+        let on_fold = self.active_view().is_some_and(|view| view.folds.iter().any(|fold| fold.start == view.cursor.row));
+
+        match on_fold {
+            true => self.open_fold(),
+            false => self.close_fold(),
+        }
+    }
+
+    fn open_fold(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.active_view) {
+            let row = view.cursor.row;
+            view.folds.retain(|fold| fold.start != row);
+        }
+    }
+
+    /// Closes the smallest foldable section around the cursor that isn't closed yet, so
+    /// repeating it folds ever larger sections. Sections are manual folds, ranges from the
+    /// language server and markdown headings.
+    fn close_fold(&mut self) {
+        let view = match self.views.get_mut(&self.active_view) {
+            Some(view) => view,
+            None => return
+        };
+        let buffer = match self.buffers.get(&view.buffer) {
+            Some(buffer) => buffer,
+            None => return
+        };
+        let row = view.cursor.row;
+
+        let mut candidates: Vec<Fold> = view.manual_folds.iter().chain(buffer.fold_ranges.iter()).copied().collect();
+        if buffer.filetype == "md" {
+            if let Some((start, end)) = markdown::heading_section(&buffer.lines, row) {
+                candidates.push(Fold { start, end });
+            }
+        }
+
+        let fold = candidates.into_iter()
+            .filter(|fold| fold.contains(row) && fold.end > fold.start && !view.folds.contains(fold))
+            .min_by_key(|fold| fold.len());
+
+        match fold {
+            Some(fold) => {
+                view.folds.push(fold);
+                view.cursor.row = fold.start;
+
+                let line_chars = view.line_info(buffer, fold.start).chars;
+                view.scroll_to_cursor(line_chars);
+            }
+            None => notify!(self, Duration::from_secs(2), "Nothing to fold"),
+        }
+    }
+
+    /// Adds a manual fold over `start..=end` and closes it.
+    pub fn create_fold(&mut self, start: usize, end: usize) {
+        let last = self.active_buffer().map(|buffer| buffer.lines.len().saturating_sub(1)).unwrap_or(0);
+        let end = end.min(last);
+        if end <= start {
+            notify!(self, Duration::from_secs(2), "A fold needs at least two lines");
+            return;
+        }
+
+        let view = match self.views.get_mut(&self.active_view) {
+            Some(view) => view,
+            None => return
+        };
+        let buffer = match self.buffers.get(&view.buffer) {
+            Some(buffer) => buffer,
+            None => return
+        };
+
+        let fold = Fold { start, end };
+        if !view.manual_folds.contains(&fold) {
+            view.manual_folds.push(fold);
+        }
+        if !view.folds.contains(&fold) {
+            view.folds.push(fold);
+        }
+        view.cursor.row = start;
+
+        let line_chars = view.line_info(buffer, start).chars;
+        view.scroll_to_cursor(line_chars);
+    }
+
+    /// Replaces what the language server says can be folded in the buffer at `path`.
+    pub fn set_fold_ranges(&mut self, path: &str, ranges: Vec<Fold>) {
+        if let Some(buffer) = self.find_buffer(path).and_then(|id| self.buffers.get_mut(&id)) {
+            buffer.fold_ranges = ranges;
+        }
+    }
+
+    /// Moves the folds of a buffer and its views after `delta` lines were added or removed below `row`.
+    fn shift_folds(&mut self, id: BufferId, row: usize, delta: isize) {
+        if let Some(buffer) = self.buffers.get_mut(&id) {
+            folding::shift(&mut buffer.fold_ranges, row, delta);
+        }
+
+        for view in self.views.values_mut().filter(|view| view.buffer == id) {
+            folding::shift(&mut view.folds, row, delta);
+            folding::shift(&mut view.manual_folds, row, delta);
+        }
+    }
+
+    /// Applies LSP text edits to a buffer, keeping every cursor on the text it was on.
+    pub fn apply_text_edits(&mut self, id: BufferId, edits: &[TextEdit]) -> Result<(), String> {
+        let lines = match self.buffers.get(&id) {
+            Some(buffer) => text_edit::apply(&buffer.lines, edits)?,
+            None => return Ok(())
+        };
+
+        let cursors: Vec<(ViewId, (usize, usize))> = self.views.values()
+            .filter(|view| view.buffer == id)
+            .map(|view| (view.id, text_edit::map_position(edits, view.cursor.row, view.cursor.col)))
+            .collect();
+
+        self.replace_lines(id, lines);
+
+        for (view_id, (row, col)) in cursors {
+            let line_len = match self.buffers.get(&id) {
+                Some(buffer) => buffer.lines.get(row).map(|line| line.chars().count()),
+                None => None
+            };
+
+            if let (Some(view), Some(line_len)) = (self.views.get_mut(&view_id), line_len) {
+                view.cursor = Cursor { row, col: col.min(line_len) };
+                view.scroll_to_cursor(line_len);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        let id = match self.active_view() {
+            Some(view) => view.buffer,
+            None => return
+        };
+
+        if let Some(buffer) = self.buffers.get_mut(&id) {
+            if buffer.line_ending != line_ending {
+                buffer.line_ending = line_ending;
+                // Only the file on disk changes, but it still needs saving
+                buffer.version += 1;
+            }
+        }
+    }
+
+    pub fn find_buffer(&self, path: &str) -> Option<BufferId> {
+        // The LSP reports absolute paths while buffers keep whatever was typed
+        let canonical = std::fs::canonicalize(path).ok();
+
+        self.buffers.iter()
+            .find(|(_, buffer)| {
+                buffer.path == path
+                    || (canonical.is_some() && !buffer.path.is_empty() && std::fs::canonicalize(&buffer.path).ok() == canonical)
+            })
+            .map(|(id, _)| *id)
+    }
+
+    /// Remembers the current position so it can be returned to after a jump.
+    /// Jumping somewhere new drops the positions Ctrl-O walked back over.
+    pub fn push_jump(&mut self) {
+        let jump = match self.active_view() {
+            Some(view) => Jump { buffer: view.buffer, cursor: view.cursor.clone() },
+            None => return
+        };
+
+        self.last_jump = Some(jump.clone());
+        self.jumps.truncate(self.jump_index);
+        if self.jumps.last() != Some(&jump) {
+            self.jumps.push(jump);
+        }
+        if self.jumps.len() > JUMP_LIMIT {
+            self.jumps.remove(0);
+        }
+        self.jump_index = self.jumps.len();
+    }
+
+    /// Sets the mark `name` at the cursor, or goes to it, depending on the key before it.
+    pub fn use_mark(&mut self, command: MarkCommand, name: char) {
+        match command {
+            MarkCommand::Set => self.set_mark(name),
+            MarkCommand::Line => self.goto_mark(name, false),
+            MarkCommand::Exact => self.goto_mark(name, true),
+        }
+    }
+
+    fn set_mark(&mut self, name: char) {
+        let (id, cursor) = match self.active_view() {
+            Some(view) => (view.buffer, view.cursor.clone()),
+            None => return
+        };
+
+        match name {
+            'a'..='z' => {
+                if let Some(buffer) = self.buffers.get_mut(&id) {
+                    buffer.marks.insert(name, cursor);
+                }
+            }
+            'A'..='Z' => {
+                let path = match self.buffers.get(&id) {
+                    Some(buffer) if !buffer.is_scratch() => std::path::absolute(&buffer.path)
+                        .map(|path| path.to_string_lossy().to_string())
+                        .unwrap_or_else(|_| buffer.path.clone()),
+                    _ => {
+                        notify!(self, Duration::from_secs(2), "Only files can have uppercase marks");
+                        return;
+                    }
+                };
+
+                self.global_marks.insert(name, GlobalMark { path, row: cursor.row, col: cursor.col });
+                if let Err(error) = marks::save(&self.global_marks) {
+                    notify!(self, Duration::from_secs(4), "Could not save the marks: {}", error);
+                }
+            }
+            _ => notify!(self, Duration::from_secs(2), "Marks are named a-z or A-Z"),
+        }
+    }
+
+    /// Goes to a mark, to its line's first non-blank unless `exact`. An uppercase mark in a
+    /// file that isn't open has the app open it.
+    fn goto_mark(&mut self, name: char, exact: bool) {
+        let current = self.active_view().map(|view| view.buffer);
+
+        let target = match name {
+            '\'' | '`' => self.last_jump.clone().filter(|jump| self.buffers.contains_key(&jump.buffer)),
+            'a'..='z' => current
+                .and_then(|id| Some(Jump { buffer: id, cursor: self.buffers.get(&id)?.marks.get(&name)?.clone() })),
+            'A'..='Z' => {
+                let mark = match self.global_marks.get(&name) {
+                    Some(mark) => mark.clone(),
+                    None => {
+                        notify!(self, Duration::from_secs(2), "Mark {} is not set", name);
+                        return;
+                    }
+                };
+
+                match self.find_buffer(&mark.path) {
+                    Some(id) => Some(Jump { buffer: id, cursor: Cursor { row: mark.row, col: mark.col } }),
+                    None => {
+                        let col = if exact { mark.col } else { 0 };
+                        self.event_sender.send(EditorEvent::OpenFile { path: mark.path, row: Some(mark.row), col: Some(col) });
+                        return;
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        let jump = match target {
+            Some(jump) => jump,
+            None => {
+                notify!(self, Duration::from_secs(2), "Mark {} is not set", name);
+                return;
+            }
+        };
+
+        self.push_jump();
+        if current != Some(jump.buffer) && self.focus_buffer(jump.buffer) {
+            self.event_sender.send(EditorEvent::BufferFocused(jump.buffer));
+        }
+
+        let col = match exact {
+            true => jump.cursor.col,
+            false => self.buffers.get(&jump.buffer)
+                .and_then(|buffer| buffer.lines.get(jump.cursor.row))
+                .map(|line| line.chars().take_while(|ch| ch.is_whitespace()).count())
+                .unwrap_or(0),
+        };
+        self.set_cursor(jump.cursor.row, col);
+    }
+
+    /// Goes to the previous or next position in the jump list, Ctrl-O and Ctrl-I in Vim.
+    /// Positions in closed buffers are skipped.
+    pub fn walk_jumps(&mut self, back: bool) {
+        let current = match self.active_view() {
+            Some(view) => Jump { buffer: view.buffer, cursor: view.cursor.clone() },
+            None => return
+        };
+
+        // Walking back from the end, Ctrl-I returns to where it started
+        if back && self.jump_index >= self.jumps.len() {
+            self.push_jump();
+            self.jump_index = self.jumps.len().saturating_sub(1);
+        }
+
+        let mut index = self.jump_index;
+        loop {
+            index = match back {
+                true if index > 0 => index - 1,
+                false if index + 1 < self.jumps.len() => index + 1,
+                _ => {
+                    notify!(self, Duration::from_secs(2), "{} of the jump list", if back { "Start" } else { "End" });
+                    return;
+                }
+            };
+
+            let jump = &self.jumps[index];
+            if self.buffers.contains_key(&jump.buffer) && *jump != current { break }
+        }
+
+        self.jump_index = index;
+        let jump = self.jumps[index].clone();
+
+        if current.buffer != jump.buffer && self.focus_buffer(jump.buffer) {
+            self.event_sender.send(EditorEvent::BufferFocused(jump.buffer));
+        }
+        self.set_cursor(jump.cursor.row, jump.cursor.col);
+    }
+
+    /// Makes the view showing `buffer` the active one.
+    pub fn focus_buffer(&mut self, buffer: BufferId) -> bool {
+        let view = self.views.values().find(|view| view.buffer == buffer).map(|view| view.id);
+
+        if let Some(view) = view {
+            self.activate(view);
+            return true;
+        }
+
+        // Loaded in the background, it is shown in a view the size of the current one
+        let size = match (self.buffers.contains_key(&buffer), self.active_view()) {
+            (true, Some(view)) => view.size.clone(),
+            _ => return false
+        };
+
+        let view_id = self.new_view(buffer, size);
+        self.activate(view_id);
+
+        true
+    }
+
+    /// Adds a view of `buffer`, highlighted with the rules of its file type.
+    fn new_view(&mut self, buffer: BufferId, size: Size) -> ViewId {
+        let view_id = ViewId(self.next_view);
+        self.next_view += 1;
+
+        let mut view = BufferView::new(view_id, buffer, size);
+        view.highlighter.rules = self.syntax_rules.clone();
+        view.highlighter.set_theme(&self.theme_colors);
+        if let Some(buffer) = self.buffers.get(&buffer) {
+            view.highlighter.init(buffer.filetype.clone());
+            view.filetype = filetype::for_extension(&self.filetypes, &buffer.filetype).cloned();
+        }
+
+        self.views.insert(view_id, view);
+        view_id
+    }
+
+    /// Switches the active view between the text and the rendered preview of a Markdown
+    /// buffer. Returns whether the preview is shown, `None` for other buffers.
+    pub fn toggle_markdown_preview(&mut self) -> Option<bool> {
+        if self.active_buffer()?.filetype != "md" { return None }
+
+        let view = self.views.get_mut(&self.active_view)?;
+        view.markdown_preview = !view.markdown_preview;
+        Some(view.markdown_preview)
+    }
+
+    /// Replaces the regex highlighting rules, from the config and syntax files.
+    pub fn set_syntax_rules(&mut self, rules: HashMap<String, HashMap<String, String>>) {
+        if rules == self.syntax_rules { return }
+
+        for view in self.views.values_mut() {
+            view.highlighter.rules = rules.clone();
+            view.highlighter.clear_cache();
+        }
+        self.syntax_rules = rules;
+    }
+
+    /// Replaces what `filetype(...)` blocks set and resolves it again for every view.
+    pub fn set_filetypes(&mut self, filetypes: HashMap<String, FiletypeConfig>) {
+        for view in self.views.values_mut() {
+            let extension = self.buffers.get(&view.buffer).map(|buffer| buffer.filetype.as_str()).unwrap_or_default();
+            view.filetype = filetype::for_extension(&filetypes, extension).cloned();
+        }
+        self.filetypes = filetypes;
+    }
+
+    /// Colors the regex highlighting of every view with the theme, e.g. after it changed.
+    pub fn set_theme_colors(&mut self, colors: HashMap<String, Color>) {
+        if colors == self.theme_colors { return }
+
+        for view in self.views.values_mut() {
+            view.highlighter.set_theme(&colors);
+        }
+        self.theme_colors = colors;
+    }
+
+    /// Makes `view` the active one, the buffer shown until now becomes its alternate.
+    fn activate(&mut self, view: ViewId) {
+        let previous = self.active_view().map(|active| active.buffer);
+
+        if let Some(view) = self.views.get_mut(&view) {
+            if previous.is_some_and(|previous| previous != view.buffer) {
+                view.alternate = previous;
+            }
+        }
+
+        self.active_view = view;
+    }
+
+    /// Shows `buffer` and tells the app, which updates the status bar.
+    pub fn switch_buffer(&mut self, buffer: BufferId) {
+        if self.active_view().is_some_and(|view| view.buffer == buffer) { return }
+
+        self.push_jump();
+        if self.focus_buffer(buffer) {
+            self.event_sender.send(EditorEvent::BufferFocused(buffer));
+        }
+    }
+
+    /// Switches to the buffer shown before the current one, Ctrl-^ in Vim.
+    pub fn switch_to_alternate(&mut self) {
+        match self.active_view().and_then(|view| view.alternate) {
+            Some(alternate) => self.switch_buffer(alternate),
+            None => notify!(self, Duration::from_secs(2), "No alternate file"),
+        }
+    }
+
+    pub fn set_cursor(&mut self, row: usize, col: usize) {
+        if let Some(view) = self.views.get_mut(&self.active_view) {
+            if let Some(buffer) = self.buffers.get(&view.buffer) {
+                let row = row.min(buffer.lines.len().saturating_sub(1));
+                let line_len = buffer.line(row).map(|l| l.chars().count()).unwrap_or(0);
+
+                // Jumping into a fold opens it
+                view.folds.retain(|fold| !fold.hides(row));
+                view.cursor = Cursor { row, col: col.min(line_len) };
+                view.scroll_to_cursor(line_len);
+            }
+        }
+    }
+
+    /// Moves the cursor to the next or previous diagnostic of the buffer, wrapping around.
+    pub fn jump_to_diagnostic(&mut self, forward: bool) {
+        let target = match (self.active_buffer(), self.active_view()) {
+            (Some(buffer), Some(view)) => diagnostics::next_from(&buffer.diagnostics, view.cursor.row, view.cursor.col, forward)
+                .map(|diagnostic| (diagnostic.row, diagnostic.col)),
+            _ => return
+        };
+
+        match target {
+            Some((row, col)) => self.set_cursor(row, col),
+            None => notify!(self, Duration::from_secs(2), "No diagnostics"),
+        }
+    }
+
+    pub fn start_selection(&mut self, row: usize, col: usize) {
+        self.set_cursor(row, col);
+
+        if let Some(view) = self.views.get_mut(&self.active_view) {
+            view.selection = Some(Selection { anchor: view.cursor.clone(), head: view.cursor.clone() });
+        }
+    }
+
+    pub fn extend_selection(&mut self, row: usize, col: usize) {
+        self.set_cursor(row, col);
+
+        if let Some(view) = self.views.get_mut(&self.active_view) {
+            let cursor = view.cursor.clone();
+            match view.selection.as_mut() {
+                Some(selection) => selection.head = cursor,
+                None => view.selection = Some(Selection { anchor: cursor.clone(), head: cursor }),
+            }
+        }
+    }
+
+    pub fn clear_selection(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.active_view) {
+            if let Some(selection) = view.selection.take().filter(|selection| !selection.is_empty()) {
+                let (start, end) = selection.range();
+                self.last_selection = Some((view.buffer, start.row, end.row));
+            }
+        }
+    }
+
+    pub fn selected_text(&self) -> Option<String> {
+        let view = self.active_view()?;
+        let selection = view.selection.as_ref().filter(|selection| !selection.is_empty())?;
+
+        Some(selection.text(self.buffers.get(&view.buffer)?))
+    }
+
+    /// The selection, or the line or paragraph around the cursor when nothing is selected.
+    pub fn repl_text(&self, scope: ReplScope) -> Option<String> {
+        if let Some(text) = self.selected_text() {
+            return Some(text);
+        }
+
+        let buffer = self.active_buffer()?;
+        let row = self.active_view()?.cursor.row;
+        let blank = |row: usize| buffer.lines[row].trim().is_empty();
+
+        match scope {
+            ReplScope::Line => buffer.lines.get(row).cloned(),
+            ReplScope::Paragraph => {
+                if row >= buffer.lines.len() || blank(row) { return None }
+
+                let mut start = row;
+                while start > 0 && !blank(start - 1) { start -= 1 }
+
+                let mut end = row;
+                while end + 1 < buffer.lines.len() && !blank(end + 1) { end += 1 }
+
+                Some(buffer.lines[start..=end].join("\n"))
+            }
+        }
+    }
+
+    /// Rows an operator like `=` works on: the selected ones, or `scope` around the cursor.
+    pub fn operator_rows(&self, scope: LineScope) -> Option<(usize, usize)> {
+        let view = self.active_view()?;
+        let buffer = self.buffers.get(&view.buffer)?;
+        let last = buffer.lines.len().saturating_sub(1);
+        let row = view.cursor.row.min(last);
+        let blank = |row: usize| buffer.lines[row].trim().is_empty();
+
+        if let Some(selection) = view.selection.as_ref().filter(|selection| !selection.is_empty()) {
+            let (start, end) = selection.range();
+            return Some((start.row.min(last), end.row.min(last)));
+        }
+
+        Some(match scope {
+            LineScope::Line => (row, row),
+            LineScope::Down => (row, (row + 1).min(last)),
+            LineScope::Up => (row.saturating_sub(1), row),
+            LineScope::Paragraph => {
+                if blank(row) { return None }
+
+                let mut start = row;
+                while start > 0 && !blank(start - 1) { start -= 1 }
+
+                let mut end = row;
+                while end < last && !blank(end + 1) { end += 1 }
+
+                (start, end)
+            }
+            LineScope::ToEnd => (row, last),
+            LineScope::ToStart => (0, row),
+        })
+    }
+
+    /// Re-indents rows of a buffer with the bracket based indent engine,
+    /// in the indent style the buffer already uses.
+    pub fn reindent_rows(&mut self, id: BufferId, start: usize, end: usize, tab_size: usize) {
+        let buffer = match self.buffers.get(&id) {
+            Some(buffer) => buffer,
+            None => return
+        };
+
+        let style = indent::detect(&buffer.lines, tab_size);
+        let lines = indent::reindent_rows(&buffer.lines, start, end, style, tab_size);
+
+        if lines != buffer.lines {
+            self.replace_lines(id, lines);
+        }
+    }
+
+    /// Removes whitespace at the end of every line of a buffer, returns how many lines had some.
+    pub fn strip_trailing_whitespace(&mut self, id: BufferId) -> usize {
+        let buffer = match self.buffers.get(&id) {
+            Some(buffer) => buffer,
+            None => return 0
+        };
+
+        let lines = whitespace::strip_trailing(&buffer.lines);
+        let stripped = lines.iter().zip(&buffer.lines).filter(|(new, old)| new.len() != old.len()).count();
+
+        if stripped > 0 {
+            self.replace_lines(id, lines);
+        }
+        stripped
+    }
+
+    /// Types `text` at the cursor, as if it was entered in insert mode.
+    pub fn insert_text(&mut self, text: &str) {
+        for ch in text.chars() {
+            match ch {
+                '\n' => self.handle_action(&EditorAction::InsertNewline),
+                '\r' => {}
+                ch => self.handle_action(&EditorAction::InsertChar(ch)),
+            }
+        }
+    }
+
+    /// Inserts pasted text at the cursor. With `reindent` multi-line text is shifted
+    /// to the indentation of the cursor line, in the style the buffer already uses.
+    pub fn paste(&mut self, text: &str, reindent: bool, tab_size: usize) {
+        let text = text.replace("\r\n", "\n");
+
+        let reindented = match (self.active_buffer(), self.active_view()) {
+            (Some(buffer), Some(view)) if reindent => {
+                let base = buffer.lines.get(view.cursor.row).map(|line| indent::leading(line)).unwrap_or("");
+                let style = indent::detect(&buffer.lines, tab_size);
+                Some(indent::reindent(&text, base, style, tab_size))
+            }
+            _ => None,
+        };
+
+        self.insert_text(reindented.as_deref().unwrap_or(&text));
+    }
+
+    /// Scrolls the active view so the cursor line sits in the middle.
+    pub fn center_cursor(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.active_view) {
+            view.scroll.vertical = view.cursor.row.saturating_sub(view.size.rows as usize / 2);
+        }
+    }
+
+    pub fn link_at(&self, row: usize, col: usize, bare_paths: bool) -> Option<Link> {
+        let line = self.active_buffer()?.line(row)?;
+
+        if bare_paths {
+            links::path_at(line, col)
+        } else {
+            links::link_at(line, col)
+        }
+    }
+
+    /// Opens the link at the given position. Returns false if there is none.
+    pub fn follow_link_at(&mut self, row: usize, col: usize, bare_paths: bool) -> bool {
+        let link = match self.link_at(row, col, bare_paths) {
+            Some(link) => link,
+            None => return false
+        };
+
+        self.open_link(link.target);
+        true
+    }
+
+    /// Opens a URL in the browser or jumps to a file.
+    pub fn open_link(&mut self, target: LinkTarget) {
+        match target {
+            LinkTarget::Url(url) => {
+                self.event_sender.send(EditorEvent::OpenUrl(url));
+            }
+            LinkTarget::File { path, row, col } => {
+                self.event_sender.send(EditorEvent::OpenFile { path, row, col });
+            }
+        }
+    }
+
     pub fn update_tokens(&mut self, tokens: Vec<Vec<Token>>) {
         if let Some(view) = self.views.get(&self.active_view) {
             view.highlighter.update_tokens(tokens);
         }
     }
 
+    pub fn memory_usage(&self) -> Vec<MemoryUsage> {
+        let mut usage: Vec<MemoryUsage> = self.views.values()
+            .map(|view| {
+                let buffer = self.buffers.get(&view.buffer);
+
+                MemoryUsage {
+                    path: buffer.map(|b| b.path.clone()).unwrap_or_default(),
+                    text: buffer.map(|b| b.lines.iter().map(|line| line.capacity()).sum()).unwrap_or(0),
+                    cache_entries: view.highlighter.cache_entries(),
+                    cache: view.highlighter.cache_size(),
+                    tokens: view.highlighter.tokens_size(),
+                }
+            })
+            .collect();
+
+        usage.sort_by(|a, b| a.path.cmp(&b.path));
+        usage
+    }
+
+    /// Gives every view the new size, keeping the cursor on screen.
+    pub fn resize_views(&mut self, size: Size) {
+        for view in self.views.values_mut() {
+            view.size = size.clone();
+
+            let rows = (size.rows as usize).max(1);
+            if view.cursor.row >= view.scroll.vertical + rows {
+                view.scroll.vertical = view.cursor.row + 1 - rows;
+            }
+        }
+    }
+
+    /// Evicts the least recently used highlight cache entries once all views together use more than `budget` bytes.
+    /// The budget is shared evenly between the views. Returns how many entries were evicted.
+    pub fn enforce_cache_budget(&self, budget: usize) -> usize {
+        let total: usize = self.views.values().map(|view| view.highlighter.cache_size()).sum();
+        if total <= budget || self.views.is_empty() { return 0 }
+
+        let share = budget / self.views.len();
+        self.views.values().map(|view| view.highlighter.evict_to(share)).sum()
+    }
+
     pub fn active_view(&self) -> Option<&BufferView> {
         return self.views.get(&self.active_view)
     }
@@ -274,60 +1528,132 @@ impl Editor {
         None
     }
 
-    pub fn views(&self) -> HashMap<ViewId, BufferView> {
-        return self.views.clone()
+    /// Path of the active view's alternate buffer, `None` for a scratch buffer.
+    pub fn alternate_path(&self) -> Option<String> {
+        let alternate = self.active_view()?.alternate?;
+
+        self.buffer(&alternate)
+            .map(|buffer| buffer.path.clone())
+            .filter(|path| !path.is_empty())
+    }
+
+    /// What `%`, `#` and `<cword>` stand for on the command line right now.
+    pub fn expansions(&self) -> cmdline::Expansions {
+        let line_and_col = match (self.active_buffer(), self.active_view()) {
+            (Some(buffer), Some(view)) => buffer.lines.get(view.cursor.row).map(|line| (line.as_str(), view.cursor.col)),
+            _ => None
+        };
+
+        cmdline::Expansions {
+            current: self.active_buffer().map(|buffer| buffer.path.clone()).unwrap_or_default(),
+            alternate: self.alternate_path(),
+            cword: line_and_col.and_then(|(line, col)| cmdline::word_at(line, col, false)),
+            cbig_word: line_and_col.and_then(|(line, col)| cmdline::word_at(line, col, true)),
+        }
+    }
+
+    /// What the addresses of a range like `'<,'>` or `.,+3` lead to in the active buffer.
+    pub fn addresses(&self) -> cmdline::Addresses {
+        let (view, buffer) = match (self.active_view(), self.active_buffer()) {
+            (Some(view), Some(buffer)) => (view, buffer),
+            _ => return cmdline::Addresses { cursor: 0, last: 0, selection: None, marks: HashMap::new() }
+        };
+
+        let selection = match view.selection.as_ref().filter(|selection| !selection.is_empty()) {
+            Some(selection) => {
+                let (start, end) = selection.range();
+                Some((start.row, end.row))
+            }
+            None => self.last_selection
+                .filter(|(id, _, _)| *id == view.buffer)
+                .map(|(_, start, end)| (start, end)),
+        };
+
+        cmdline::Addresses {
+            cursor: view.cursor.row,
+            last: buffer.lines.len().saturating_sub(1),
+            selection,
+            marks: buffer.marks.iter().map(|(name, cursor)| (*name, cursor.row)).collect(),
+        }
+    }
+
+    pub fn views(&self) -> &HashMap<ViewId, BufferView> {
+        &self.views
     }
 
     pub fn buffer(&self, id: &BufferId) -> Option<&Buffer> {
         return self.buffers.get(id);
     }
 
+    pub fn buffer_mut(&mut self, id: &BufferId) -> Option<&mut Buffer> {
+        return self.buffers.get_mut(id);
+    }
+
+    pub fn buffer_ids(&self) -> Vec<BufferId> {
+        self.buffers.keys().copied().collect()
+    }
+
+    pub fn dirty_buffers(&self) -> Vec<BufferId> {
+        self.buffers.iter()
+            .filter(|(_, buffer)| buffer.is_dirty())
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
     fn move_cursor_up(&mut self) {
         if let Some(view) = self.views.get_mut(&self.active_view) {
             if view.cursor.row > 0 {
-                view.cursor.row -= 1;
+                view.cursor.row = folding::previous_visible(&view.folds, view.cursor.row - 1);
             }
 
-            if view.scroll.vertical == 0 { return }
-
-            if view.cursor.row < view.scroll.vertical {
-                view.scroll.vertical -= 1
-            }
+            let buffer = self.buffers.get(&view.buffer).unwrap();
+            let line_chars = view.line_info(buffer, view.cursor.row).chars;
+            view.scroll_to_cursor(line_chars);
         }
     }
 
     fn move_cursor_down(&mut self) {
         if let Some(view) = self.views.get_mut(&self.active_view) {
-            if view.cursor.row < self.buffers.get(&view.buffer).unwrap().lines.len() - 1 {
-                view.cursor.row += 1;
-            }
+            let buffer = self.buffers.get(&view.buffer).unwrap();
 
-            if view.cursor.row >= view.size.rows as usize + view.scroll.vertical {
-                view.scroll.vertical += 1;
+            // Folded lines are stepped over
+            let next = folding::next_visible(&view.folds, view.cursor.row + 1);
+            if next < buffer.lines.len() {
+                view.cursor.row = next;
             }
+
+            let line_chars = view.line_info(buffer, view.cursor.row).chars;
+            view.scroll_to_cursor(line_chars);
         }
     }
 
     fn move_cursor_left(&mut self) {
         if let Some(view) = self.views.get_mut(&self.active_view) {
-            let line = self.buffers.get(&view.buffer).unwrap().line(view.cursor.row).unwrap();
-            if view.cursor.col >= line.len() {
-                view.cursor.col = line.len();
+            let buffer = self.buffers.get(&view.buffer).unwrap();
+            let line_chars = view.line_info(buffer, view.cursor.row).chars;
+
+            if view.cursor.col >= line_chars {
+                view.cursor.col = line_chars;
             }
 
             if view.cursor.col > 0 {
                 view.cursor.col -= 1;
             }
+
+            view.scroll_to_cursor(line_chars);
         }
     }
 
     fn move_cursor_right(&mut self) {
         if let Some(view) = self.views.get_mut(&self.active_view) {
-            if let Some(line) = self.buffers.get(&view.buffer).unwrap().line(view.cursor.row) {
-                if view.cursor.col < line.graphemes(true).count() {
-                    view.cursor.col += 1;
-                }
+            let buffer = self.buffers.get(&view.buffer).unwrap();
+            let line_chars = view.line_info(buffer, view.cursor.row).chars;
+
+            if view.cursor.col < line_chars {
+                view.cursor.col += 1;
             }
+
+            view.scroll_to_cursor(line_chars);
         }
     }
 
@@ -0,0 +1,150 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-8-bom" | "utf8-bom" => Some(Encoding::Utf8Bom),
+            "utf-16le" | "utf16le" | "utf-16" | "utf16" => Some(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    /// Guesses the encoding from a BOM, falling back to Latin-1 for anything that isn't valid UTF-8.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+            Encoding::Utf8Bom
+        } else if bytes.starts_with(&[0xFF, 0xFE]) {
+            Encoding::Utf16Le
+        } else if bytes.starts_with(&[0xFE, 0xFF]) {
+            Encoding::Utf16Be
+        } else if std::str::from_utf8(bytes).is_ok() {
+            Encoding::Utf8
+        } else {
+            Encoding::Latin1
+        }
+    }
+
+    /// Decodes `bytes`, dropping the BOM if there is one.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes).to_string(),
+            Encoding::Utf8Bom => String::from_utf8_lossy(bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes)).to_string(),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let body = bytes.strip_prefix(&self.bom()[..]).unwrap_or(bytes);
+
+                let units = body.chunks_exact(2).map(|pair| match self {
+                    Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                });
+
+                char::decode_utf16(units)
+                    .map(|ch| ch.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            }
+            // Every byte maps straight to the code point of the same value
+            Encoding::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+        }
+    }
+
+    /// Encodes `text` for writing to disk. Fails at the first character Latin-1 can't
+    /// represent rather than writing something else in its place.
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, Unencodable> {
+        let mut bytes = self.bom();
+
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => bytes.extend_from_slice(text.as_bytes()),
+            Encoding::Utf16Le => bytes.extend(text.encode_utf16().flat_map(|unit| unit.to_le_bytes())),
+            Encoding::Utf16Be => bytes.extend(text.encode_utf16().flat_map(|unit| unit.to_be_bytes())),
+            Encoding::Latin1 => {
+                if let Some(index) = text.find(|ch: char| ch as u32 >= 0x100) {
+                    let before = &text[..index];
+                    let line_start = before.rfind('\n').map(|newline| newline + 1).unwrap_or(0);
+
+                    return Err(Unencodable {
+                        encoding: *self,
+                        ch: text[index..].chars().next().unwrap(),
+                        line: before.matches('\n').count() + 1,
+                        column: before[line_start..].chars().count() + 1,
+                    });
+                }
+                bytes.extend(text.chars().map(|ch| ch as u8));
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    fn bom(&self) -> Vec<u8> {
+        match self {
+            Encoding::Utf8Bom => vec![0xEF, 0xBB, 0xBF],
+            Encoding::Utf16Le => vec![0xFF, 0xFE],
+            Encoding::Utf16Be => vec![0xFE, 0xFF],
+            Encoding::Utf8 | Encoding::Latin1 => vec![],
+        }
+    }
+}
+
+/// A character the encoding has no bytes for, at a 1-based line and column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unencodable {
+    pub encoding: Encoding,
+    pub ch: char,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Unencodable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} can't encode {:?} at line {}, column {}", self.encoding.name(), self.ch, self.line, self.column)
+    }
+}
+
+/// Detects the encoding of `bytes` and decodes them.
+pub fn decode(bytes: &[u8]) -> (String, Encoding) {
+    let encoding = Encoding::detect(bytes);
+    (encoding.decode(bytes), encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latin1_round_trips() {
+        let text = "caf\u{e9} \u{ff}\r\n";
+        let bytes = Encoding::Latin1.encode(text).unwrap();
+
+        assert_eq!(bytes, b"caf\xe9 \xff\r\n");
+        assert_eq!(Encoding::Latin1.decode(&bytes), text);
+    }
+
+    #[test]
+    fn latin1_refuses_what_it_cant_encode() {
+        let error = Encoding::Latin1.encode("first\r\nsecond \u{e9}\u{20ac}\n\u{2603}").unwrap_err();
+
+        assert_eq!(error, Unencodable { encoding: Encoding::Latin1, ch: '\u{20ac}', line: 2, column: 9 });
+        assert_eq!(error.to_string(), "latin1 can't encode '\u{20ac}' at line 2, column 9");
+    }
+}
@@ -0,0 +1,121 @@
+use crossterm::style::{Color, Stylize};
+
+use crate::highlighter::Highlighter;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Html,
+    Ansi,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Html => "html",
+            ExportFormat::Ansi => "ansi",
+        }
+    }
+}
+
+/// Text of one line split where its color changes.
+pub type Runs = Vec<(String, Color)>;
+
+/// Colors the lines between `start` and `end` (row, column in characters, end exclusive)
+/// with the same tokens the editor draws. Text outside tokens gets `foreground`.
+pub fn runs(lines: &[String], start: (usize, usize), end: (usize, usize), highlighter: &Highlighter, foreground: Color) -> Vec<Runs> {
+    let last = end.0.min(lines.len().saturating_sub(1));
+
+    (start.0..=last)
+        .filter_map(|row| lines.get(row).map(|line| (row, line)))
+        .map(|(row, line)| {
+            let chars: Vec<char> = line.chars().collect();
+            let mut colors = vec![foreground; chars.len()];
+
+            for token in highlighter.highlight(line, row) {
+                // Offsets are in bytes, cells in characters
+                let first = match line.get(..token.offset) {
+                    Some(before) => before.chars().count(),
+                    None => continue
+                };
+
+                for color in colors.iter_mut().skip(first).take(token.text.chars().count()) {
+                    *color = token.style.unwrap_or(foreground);
+                }
+            }
+
+            let from = if row == start.0 { start.1.min(chars.len()) } else { 0 };
+            let to = if row == end.0 { end.1.min(chars.len()) } else { chars.len() };
+
+            let mut runs: Runs = vec![];
+            for (ch, color) in chars.iter().zip(&colors).take(to).skip(from) {
+                match runs.last_mut() {
+                    Some((text, last)) if last == color => text.push(*ch),
+                    _ => runs.push((ch.to_string(), *color)),
+                }
+            }
+
+            runs
+        })
+        .collect()
+}
+
+/// A standalone HTML page with the text in a `<pre>` on the theme background.
+pub fn to_html(lines: &[Runs], title: &str, background: Color, foreground: Color) -> String {
+    let mut body = String::new();
+
+    for (i, runs) in lines.iter().enumerate() {
+        if i > 0 { body.push('\n') }
+
+        for (text, color) in runs {
+            body.push_str(&format!("<span style=\"color:{}\">{}</span>", css_color(*color), escape(text)));
+        }
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body style=\"margin:0\">\n<pre style=\"background:{};color:{};padding:1em;margin:0;font-family:monospace\">{}</pre>\n</body>\n</html>\n",
+        escape(title), css_color(background), css_color(foreground), body
+    )
+}
+
+/// The text with 24-bit color escapes, for `cat` or pasting into a terminal.
+pub fn to_ansi(lines: &[Runs]) -> String {
+    let mut text = String::new();
+
+    for runs in lines {
+        for (run, color) in runs {
+            text.push_str(&run.as_str().with(*color).to_string());
+        }
+        text.push('\n');
+    }
+
+    text
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// CSS for a terminal color, named ones get the usual xterm values.
+fn css_color(color: Color) -> String {
+    let (r, g, b) = match color {
+        Color::Rgb { r, g, b } => (r, g, b),
+        Color::Black => (0, 0, 0),
+        Color::DarkGrey => (127, 127, 127),
+        Color::Red => (255, 0, 0),
+        Color::DarkRed => (205, 0, 0),
+        Color::Green => (0, 255, 0),
+        Color::DarkGreen => (0, 205, 0),
+        Color::Yellow => (255, 255, 0),
+        Color::DarkYellow => (205, 205, 0),
+        Color::Blue => (92, 92, 255),
+        Color::DarkBlue => (0, 0, 238),
+        Color::Magenta => (255, 0, 255),
+        Color::DarkMagenta => (205, 0, 205),
+        Color::Cyan => (0, 255, 255),
+        Color::DarkCyan => (0, 205, 205),
+        Color::Grey => (229, 229, 229),
+        _ => (255, 255, 255),
+    };
+
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
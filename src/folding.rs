@@ -0,0 +1,81 @@
+/// A closed fold. `start` stays on screen as a summary, the lines after it up to `end` are hidden.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fold {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Fold {
+    pub fn hides(&self, row: usize) -> bool {
+        row > self.start && row <= self.end
+    }
+
+    /// Number of hidden lines.
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+impl Fold {
+    pub fn contains(&self, row: usize) -> bool {
+        row >= self.start && row <= self.end
+    }
+}
+
+pub fn is_hidden(folds: &[Fold], row: usize) -> bool {
+    folds.iter().any(|fold| fold.hides(row))
+}
+
+/// The outermost closed fold whose summary is drawn on `row`.
+pub fn closed_at(folds: &[Fold], row: usize) -> Option<&Fold> {
+    folds.iter()
+        .filter(|fold| fold.start == row)
+        .max_by_key(|fold| fold.end)
+}
+
+/// The first row at or after `row` that isn't hidden.
+pub fn next_visible(folds: &[Fold], mut row: usize) -> usize {
+    while let Some(fold) = folds.iter().filter(|fold| fold.hides(row)).max_by_key(|fold| fold.end) {
+        row = fold.end + 1;
+    }
+    row
+}
+
+/// The first row at or before `row` that isn't hidden.
+pub fn previous_visible(folds: &[Fold], mut row: usize) -> usize {
+    while let Some(fold) = folds.iter().filter(|fold| fold.hides(row)).min_by_key(|fold| fold.start) {
+        row = fold.start;
+    }
+    row
+}
+
+/// The buffer rows drawn on `count` screen rows starting at `top`, stopping at the end of the buffer.
+pub fn visible_rows(folds: &[Fold], top: usize, count: usize, total: usize) -> Vec<usize> {
+    let mut rows = Vec::with_capacity(count);
+    let mut row = next_visible(folds, top);
+
+    while rows.len() < count && row < total {
+        rows.push(row);
+        row = next_visible(folds, row + 1);
+    }
+
+    rows
+}
+
+/// Moves folds after `delta` lines were added or removed below `row`, dropping the ones that became empty.
+pub fn shift(folds: &mut Vec<Fold>, row: usize, delta: isize) {
+    for fold in folds.iter_mut() {
+        if fold.start > row {
+            fold.start = fold.start.saturating_add_signed(delta);
+        }
+        if fold.end >= row {
+            fold.end = fold.end.saturating_add_signed(delta);
+        }
+    }
+    folds.retain(|fold| fold.end > fold.start);
+}
+
+/// How many screen rows below `top` buffer row `row` is drawn.
+pub fn screen_row(folds: &[Fold], top: usize, row: usize) -> usize {
+    (top..row).filter(|row| !is_hidden(folds, *row)).count()
+}
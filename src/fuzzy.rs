@@ -0,0 +1,32 @@
+/// How well `pattern` matches `text` when its characters appear in order, ignoring case.
+/// Runs of consecutive characters and matches at the start of words score higher, `None`
+/// if it doesn't match at all.
+pub fn score(pattern: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.chars().collect();
+    let mut score = 0;
+    let mut from = 0;
+    let mut previous: Option<usize> = None;
+
+    for ch in pattern.chars().filter(|ch| !ch.is_whitespace()) {
+        let offset = text[from..].iter().position(|candidate| candidate.to_lowercase().eq(ch.to_lowercase()))?;
+        let index = from + offset;
+
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == index) {
+            score += 4;
+        }
+        if index == 0 || !text[index - 1].is_alphanumeric() {
+            score += 3;
+        }
+        // Characters skipped before the first match cost less than gaps inside it
+        score -= match previous {
+            Some(_) => offset as i64,
+            None => offset as i64 / 4,
+        };
+
+        previous = Some(index);
+        from = index + 1;
+    }
+
+    Some(score)
+}
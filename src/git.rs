@@ -0,0 +1,251 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crossterm::style::Color;
+
+/// Above this many cells the middle of a diff isn't matched line by line, it is all modified.
+const MAX_DIFF_CELLS: usize = 1_000_000;
+
+/// How a line differs from the version staged in git.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitSign {
+    Added,
+    Modified,
+    /// Lines were deleted after this one, or before the first line for row 0.
+    Removed,
+}
+
+impl GitSign {
+    pub fn sign(&self) -> char {
+        match self {
+            GitSign::Added => '+',
+            GitSign::Modified => '~',
+            GitSign::Removed => '-',
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            GitSign::Added => Color::Rgb { r: 144, g: 185, b: 159 },
+            GitSign::Modified => Color::Rgb { r: 230, g: 185, b: 157 },
+            GitSign::Removed => Color::Rgb { r: 234, g: 131, b: 165 },
+        }
+    }
+}
+
+/// The lines of `path` as staged in git's index, `None` if it isn't tracked or not in a repository.
+pub fn staged(path: &str) -> Option<Vec<String>> {
+    let path = Path::new(path);
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = path.file_name()?.to_string_lossy();
+
+    let output = Command::new("git")
+        .arg("-C").arg(dir)
+        .arg("show").arg(format!(":./{}", name))
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() { return None }
+
+    Some(String::from_utf8_lossy(&output.stdout).lines().map(String::from).collect())
+}
+
+/// The checked out branch of a repository and whether tracked files have changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitStatus {
+    // "HEAD" when detached
+    pub branch: String,
+    pub dirty: bool,
+}
+
+/// The status of the repository `dir` is in, `None` outside of one.
+pub fn status(dir: &Path) -> Option<GitStatus> {
+    let output = Command::new("git")
+        .arg("-C").arg(dir)
+        .args(["status", "--porcelain=v1", "--branch", "--untracked-files=no"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() { return None }
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut lines = text.lines();
+
+    // "## main...origin/main [ahead 1]", "## No commits yet on main" or "## HEAD (no branch)"
+    let header = lines.next()?.strip_prefix("## ")?;
+    let header = header.strip_prefix("No commits yet on ").unwrap_or(header);
+    let branch = match header.split("...").next().unwrap_or(header).split(' ').next() {
+        Some(branch) if !branch.is_empty() => branch.to_string(),
+        _ => "HEAD".to_string(),
+    };
+
+    Some(GitStatus { branch, dirty: lines.next().is_some() })
+}
+
+/// The commit that last changed a line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameLine {
+    pub commit: String,
+    pub author: String,
+    // Seconds since the epoch
+    pub time: i64,
+    pub summary: String,
+}
+
+impl BlameLine {
+    /// Lines that were changed but not committed blame the all zero commit.
+    pub fn is_committed(&self) -> bool {
+        self.commit.chars().any(|c| c != '0')
+    }
+
+    /// "1a2b3c4d Jane Doe, 2025-03-14: Fix the parser"
+    pub fn describe(&self) -> String {
+        if !self.is_committed() { return "Not committed yet".to_string() }
+
+        let short: String = self.commit.chars().take(8).collect();
+        format!("{} {}, {}: {}", short, self.author, date(self.time), self.summary)
+    }
+}
+
+/// The commit of each line of the committed or staged `path`, `None` if it isn't tracked.
+pub fn blame(path: &str) -> Option<Vec<BlameLine>> {
+    let path = Path::new(path);
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = path.file_name()?;
+
+    let output = Command::new("git")
+        .arg("-C").arg(dir)
+        .args(["blame", "--line-porcelain", "--"]).arg(name)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() { return None }
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut lines = vec![];
+    let mut current: Option<BlameLine> = None;
+
+    for line in text.lines() {
+        // The content of the line ends its block
+        if line.starts_with('\t') {
+            lines.extend(current.take());
+            continue;
+        }
+
+        let Some(blame) = current.as_mut() else {
+            let commit = line.split(' ').next().unwrap_or_default().to_string();
+            current = Some(BlameLine { commit, author: String::new(), time: 0, summary: String::new() });
+            continue;
+        };
+
+        if let Some(author) = line.strip_prefix("author ") {
+            blame.author = author.to_string();
+        } else if let Some(time) = line.strip_prefix("author-time ") {
+            blame.time = time.parse().unwrap_or(0);
+        } else if let Some(summary) = line.strip_prefix("summary ") {
+            blame.summary = summary.to_string();
+        }
+    }
+
+    Some(lines)
+}
+
+/// The UTC day of `time` as "2025-03-14".
+fn date(time: i64) -> String {
+    // Days to civil date, from Howard Hinnant's date algorithms
+    let days = time.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// The sign of each row of `lines` compared to `base`.
+pub fn signs(base: &[String], lines: &[String]) -> Vec<Option<GitSign>> {
+    let mut signs = vec![None; lines.len()];
+
+    let prefix = base.iter().zip(lines).take_while(|(a, b)| a == b).count();
+    let suffix = base[prefix..].iter().rev()
+        .zip(lines[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old = &base[prefix..base.len() - suffix];
+    let new = &lines[prefix..lines.len() - suffix];
+
+    for (_, old_len, new_start, new_len) in hunks(old, new) {
+        let row = prefix + new_start;
+
+        for i in 0..new_len {
+            signs[row + i] = Some(if i < old_len { GitSign::Modified } else { GitSign::Added });
+        }
+
+        if new_len == 0 && old_len > 0 && !lines.is_empty() {
+            signs[row.saturating_sub(1).min(lines.len() - 1)] = Some(GitSign::Removed);
+        }
+    }
+
+    signs
+}
+
+/// The runs of lines that differ between `old` and `new`, as start and length in each,
+/// from their longest common subsequence.
+fn hunks(old: &[String], new: &[String]) -> Vec<(usize, usize, usize, usize)> {
+    if old.is_empty() && new.is_empty() { return vec![] }
+
+    if old.len().saturating_mul(new.len()) > MAX_DIFF_CELLS || old.is_empty() || new.is_empty() {
+        return vec![(0, old.len(), 0, new.len())];
+    }
+
+    // lengths[i][j]: length of the common subsequence of old[i..] and new[j..]
+    let mut lengths = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = match old[i] == new[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    let mut hunks = vec![];
+    let (mut i, mut j) = (0, 0);
+    let mut start: Option<(usize, usize)> = None;
+
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            if let Some((old_start, new_start)) = start.take() {
+                hunks.push((old_start, i - old_start, new_start, j - new_start));
+            }
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        start.get_or_insert((i, j));
+
+        if j < new.len() && (i == old.len() || lengths[i][j + 1] >= lengths[i + 1][j]) {
+            j += 1;
+        } else {
+            i += 1;
+        }
+    }
+
+    if let Some((old_start, new_start)) = start {
+        hunks.push((old_start, old.len() - old_start, new_start, new.len() - new_start));
+    }
+
+    hunks
+}
@@ -1,68 +1,80 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
+use crate::buffer::Buffer;
 use crate::types::Token;
+#[cfg(feature = "tree-sitter")]
+use crate::syntax::{self, SyntaxTree};
 use crossterm::style::Color;
 use regex::Regex;
 
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 
+/// Lines longer than this (in bytes) are drawn without highlighting.
+pub const MAX_HIGHLIGHT_LENGTH: usize = 3000;
+
 #[derive(Debug, Clone)]
 pub struct Highlighter {
     pub current_filetype: String,
     pub rules: HashMap<String, HashMap<String, String>>,
     pub colors: HashMap<String, Color>,
     pub tokens: RefCell<Vec<Vec<Token>>>,
-    pub cache: RefCell<HashMap<u64, Vec<Token>>>
+    pub cache: RefCell<HashMap<u64, Vec<Token>>>,
+    // Last time each cache entry was used, for evicting the oldest ones first
+    cache_ticks: RefCell<HashMap<u64, u64>>,
+    clock: Cell<u64>,
+    cache_bytes: Cell<usize>,
+    // Tokens of the rows drawn so far with the hash of the line they were made for
+    rows: RefCell<HashMap<usize, (u64, Vec<Token>)>>,
+    rows_bytes: Cell<usize>,
+    // Shared with the clones the renderer draws from, so a parse isn't thrown away with them
+    #[cfg(feature = "tree-sitter")]
+    syntax: Arc<Mutex<Option<SyntaxTree>>>,
 }
 
 impl Highlighter {
     pub fn new(rules: HashMap<String, HashMap<String, String>>) -> Self {
-        let mut colors: HashMap<String, Color> = HashMap::new();
-
-        colors.insert("bg".into(), Color::Reset);
-        colors.insert("fg".into(), Color::White);
-
-        colors.insert("namespace".into(), Color::Blue);
-        colors.insert("type".into(), Color::Magenta);
-        colors.insert("class".into(), Color::Magenta);
-        colors.insert("enum".into(), Color::Magenta);
-        colors.insert("interface".into(), Color::Magenta);
-        colors.insert("struct".into(), Color::Magenta);
-        colors.insert("typeParameter".into(), Color::Cyan);
-
-        colors.insert("parameter".into(), Color::White);
-        colors.insert("variable".into(), Color::White);
-        colors.insert("property".into(), Color::Yellow);
-        colors.insert("enumMember".into(), Color::Yellow);
-
-        colors.insert("event".into(), Color::Green);
-        colors.insert("function".into(), Color::Green);
-        colors.insert("method".into(), Color::Green);
-        colors.insert("macro".into(), Color::Cyan);
-
-        colors.insert("keyword".into(), Color::Blue);
-        colors.insert("modifier".into(), Color::Blue);
-        colors.insert("operator".into(), Color::White);
-
-        colors.insert("comment".into(), Color::DarkGrey);
-        colors.insert("string".into(), Color::Red);
-        colors.insert("number".into(), Color::Cyan);
-        colors.insert("regexp".into(), Color::Cyan);
+        let colors = default_colors();
 
         Self {
             current_filetype: "".to_string(),
             rules,
             colors,
             cache: RefCell::new(HashMap::new()),
+            cache_ticks: RefCell::new(HashMap::new()),
+            clock: Cell::new(0),
+            cache_bytes: Cell::new(0),
+            rows: RefCell::new(HashMap::new()),
+            rows_bytes: Cell::new(0),
             tokens: RefCell::new(Vec::new()),
+            #[cfg(feature = "tree-sitter")]
+            syntax: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Colors the rules with a theme's colors, as `Theme::to_map` names them. Tokens made
+    /// with the colors before are dropped.
+    pub fn set_theme(&mut self, theme: &HashMap<String, Color>) {
+        let mut colors = default_colors();
+        colors.extend(theme.iter().map(|(key, color)| (key.clone(), *color)));
+
+        for (short, long) in [("fg", "foreground"), ("bg", "background")] {
+            if let Some(color) = theme.get(long) {
+                colors.insert(short.into(), *color);
+            }
+        }
+
+        if colors != self.colors {
+            self.colors = colors;
+            self.clear_cache();
         }
     }
 
     pub fn init(&mut self, current_filetype: String) {
         self.current_filetype = current_filetype;
+        self.clear_cache();
     }
 
     pub fn hash_bytes_default_hasher(&self, data: &[u8]) -> u64 {
@@ -72,97 +84,240 @@ impl Highlighter {
     }
 
     pub fn highlight(&self, line: &str, index: usize) -> Vec<Token> {
-        let mut tokens: Vec<Token> = Vec::new();
+        if line.len() > MAX_HIGHLIGHT_LENGTH {
+            return Vec::new();
+        }
 
-        if let Some(val) = self.tokens.borrow().get(index) {
-            tokens.extend(val.clone());
+        #[cfg(feature = "tree-sitter")]
+        if let Some(syntax_tokens) = self.syntax_tokens(line, index) {
+            return self.with_semantic_tokens(line, index, syntax_tokens);
         }
 
         let checksum = self.hash_bytes_default_hasher(line.as_bytes());
 
-        if let Some(cached) = self.cache.borrow().get(&checksum) && cached.len() > 0 {
-            tokens.extend(cached.clone());
-            return tokens;
+        // A row keeps its tokens until its text or the semantic tokens on it change
+        if let Some((row_checksum, tokens)) = self.rows.borrow().get(&index) {
+            if *row_checksum == checksum {
+                return tokens.clone();
+            }
+        }
+
+        let semantic = self.tokens.borrow().get(index).cloned().unwrap_or_default();
+        let tokens = match semantic.is_empty() {
+            true => self.rule_tokens(line, index, checksum),
+            false => fill_gaps(line, index, semantic),
+        };
+
+        self.rows_bytes.set(self.rows_bytes.get() + tokens_size(&tokens));
+        if let Some((_, old)) = self.rows.borrow_mut().insert(index, (checksum, tokens.clone())) {
+            self.rows_bytes.set(self.rows_bytes.get() - tokens_size(&old));
+        }
+
+        tokens
+    }
+
+    /// Tokens from the rules of the file type. They only depend on the text of the line,
+    /// so they are cached by its hash and survive edits to other lines.
+    fn rule_tokens(&self, line: &str, index: usize, checksum: u64) -> Vec<Token> {
+        if let Some(cached) = self.cache.borrow().get(&checksum) {
+            self.touch(checksum);
+            return cached.iter().map(|token| Token { row: index, ..token.clone() }).collect();
         }
 
+        let mut tokens: Vec<Token> = Vec::new();
+
         if line.is_empty() {
             return tokens;
         }
 
-        if tokens.is_empty() {
-            if let Some(rules) = self.rules.get(&self.current_filetype) {
-                for (key, regex_source) in rules {
-                    let re = Regex::new(regex_source).unwrap();
-
-                    for cap in re.captures_iter(line) {
-                        if let Some(cap) = cap.get(1) {
-                            tokens.push(Token {
-                                row: index,
-                                text: cap.as_str().to_string(),
-                                offset: cap.start(),
-                                style: Some(self.colors[key].clone()),
-                            });
-                        }
+        if let Some(rules) = self.rules.get(&self.current_filetype) {
+            for (key, regex_source) in rules {
+                let re = Regex::new(regex_source).unwrap();
+
+                for cap in re.captures_iter(line) {
+                    if let Some(cap) = cap.get(1) {
+                        tokens.push(Token {
+                            row: index,
+                            text: cap.as_str().to_string(),
+                            offset: cap.start(),
+                            style: Some(self.rule_color(key)),
+                        });
                     }
                 }
-            } else {
-                tokens.push(Token {
-                    row: index,
-                    text: line.to_string(),
-                    offset: 0,
-                    style: Some(self.colors["fg"].clone()),
-                });
             }
+        } else {
+            tokens.push(Token {
+                row: index,
+                text: line.to_string(),
+                offset: 0,
+                style: Some(self.colors["fg"].clone()),
+            });
         }
 
-        let mut found_tokens = Vec::new();
-        let mut buffer = String::new();
+        let tokens = fill_gaps(line, index, tokens);
+        self.cache_insert(checksum, tokens.clone());
+
+        tokens
+    }
 
-        let mut i = 0;
-        while i < line.len() {
-            let is_token_start = tokens.iter().any(|t| t.offset == i);
+    /// The color of a rule, by its key or the first part of a key like "string.quoted".
+    fn rule_color(&self, key: &str) -> Color {
+        self.colors.get(key)
+            .or_else(|| key.split('.').next().and_then(|color| self.colors.get(color)))
+            .or_else(|| self.colors.get("fg"))
+            .copied()
+            .unwrap_or(Color::Reset)
+    }
 
-            if is_token_start {
-                if !buffer.is_empty() {
-                    let start = i - buffer.len();
-                    found_tokens.push(Token {
-                        row: index,
-                        text: buffer.clone(),
-                        offset: start,
-                        style: Some(Color::White),
-                    });
-                    buffer.clear();
-                }
+    /// Drops the tokens kept for rows from `row` on, the next frame highlights them again.
+    fn forget_row(&self, row: usize) {
+        if let Some((_, old)) = self.rows.borrow_mut().remove(&row) {
+            self.rows_bytes.set(self.rows_bytes.get() - tokens_size(&old));
+        }
+    }
 
-                if let Some(existing) = tokens.iter().find(|t| t.offset == i) {
-                    i += existing.text.len();
-                    continue;
-                }
+    /// Moves the tokens kept for the rows after `row` by `delta` rows, for lines inserted or
+    /// deleted there. `row` itself changed and is dropped.
+    fn shift_rows(&self, row: usize, delta: isize) {
+        self.forget_row(row);
+        if delta == 0 { return }
+
+        let mut rows = self.rows.borrow_mut();
+        let old = std::mem::take(&mut *rows);
+        let mut bytes = self.rows_bytes.get();
+
+        for (index, entry) in old {
+            match index.checked_add_signed(delta) {
+                Some(moved) if index > row && moved > row => { rows.insert(moved, entry); }
+                Some(_) if index < row => { rows.insert(index, entry); }
+                _ => bytes -= tokens_size(&entry.1),
             }
+        }
 
-            if let Some(ch) = line.chars().nth(i) {
-                buffer.push(ch);
+        self.rows_bytes.set(bytes);
+    }
+
+    /// Brings the syntax tree up to date with `buffer`. Without the `tree-sitter` feature, or for
+    /// file types without a grammar, lines keep going through the rules and semantic tokens.
+    pub fn update_syntax(&self, buffer: &Buffer, colors: &HashMap<String, Color>) {
+        #[cfg(feature = "tree-sitter")]
+        {
+            let mut syntax = match self.syntax.lock() {
+                Ok(syntax) => syntax,
+                Err(_) => return
+            };
+
+            if syntax.as_ref().is_none_or(|syntax| syntax.filetype != buffer.filetype) {
+                *syntax = SyntaxTree::for_filetype(&buffer.filetype);
+            }
+            if let Some(syntax) = syntax.as_mut() {
+                syntax.update(&buffer.lines, buffer.version, colors);
             }
+        }
+    }
 
-            if i == line.len() - 1 && !buffer.is_empty() {
-                let start = i + 1 - buffer.len();
-                found_tokens.push(Token {
-                    row: index,
-                    text: buffer.clone(),
-                    offset: start,
-                    style: Some(Color::White),
-                });
+    #[cfg(feature = "tree-sitter")]
+    fn syntax_tokens(&self, line: &str, index: usize) -> Option<Vec<Token>> {
+        self.syntax.lock().ok()?.as_mut()?.tokens(line, index)
+    }
+
+    /// Draws the semantic tokens of the language server over the syntax tokens, which
+    /// still color everything the server leaves out.
+    #[cfg(feature = "tree-sitter")]
+    fn with_semantic_tokens(&self, line: &str, index: usize, syntax_tokens: Vec<Token>) -> Vec<Token> {
+        let semantic = self.tokens.borrow();
+        let semantic = match semantic.get(index) {
+            Some(tokens) if !tokens.is_empty() => tokens,
+            _ => return syntax_tokens
+        };
+
+        let mut styles: Vec<Option<Color>> = vec![None; line.len()];
+
+        // Semantic tokens without a theme color don't hide the syntax color under them
+        let semantic = semantic.iter().filter(|token| token.style.is_some());
+
+        for token in syntax_tokens.iter().chain(semantic) {
+            let end = (token.offset + token.text.len()).min(line.len());
+            for style in styles.iter_mut().take(end).skip(token.offset) {
+                *style = token.style;
             }
+        }
+
+        syntax::runs(line, index, &styles)
+    }
+
+    fn touch(&self, checksum: u64) {
+        let tick = self.clock.get() + 1;
+        self.clock.set(tick);
+        self.cache_ticks.borrow_mut().insert(checksum, tick);
+    }
+
+    fn cache_insert(&self, checksum: u64, tokens: Vec<Token>) {
+        let added = tokens_size(&tokens);
 
-            i += 1;
+        if let Some(old) = self.cache.borrow_mut().insert(checksum, tokens) {
+            self.cache_bytes.set(self.cache_bytes.get() - tokens_size(&old));
         }
 
-        tokens.extend(found_tokens);
-        tokens.sort_by_key(|t| t.offset);
+        self.cache_bytes.set(self.cache_bytes.get() + added);
+        self.touch(checksum);
+    }
 
-        self.cache.borrow_mut().insert(checksum, tokens.clone());
+    pub fn clear_cache(&self) {
+        self.cache.borrow_mut().clear();
+        self.cache_ticks.borrow_mut().clear();
+        self.cache_bytes.set(0);
+        self.clear_rows();
+    }
 
-        tokens
+    fn clear_rows(&self) {
+        self.rows.borrow_mut().clear();
+        self.rows_bytes.set(0);
+    }
+
+    /// Approximate heap size of the line cache and the tokens kept for drawn rows.
+    pub fn cache_size(&self) -> usize {
+        self.cache_bytes.get() + self.rows_bytes.get()
+    }
+
+    pub fn cache_entries(&self) -> usize {
+        self.cache.borrow().len() + self.rows.borrow().len()
+    }
+
+    /// Approximate heap size of the LSP tokens.
+    pub fn tokens_size(&self) -> usize {
+        self.tokens.borrow().iter().map(|line| tokens_size(line)).sum()
+    }
+
+    /// Drops the least recently used cache entries until the cache fits in `budget` bytes.
+    /// Returns how many entries were evicted.
+    pub fn evict_to(&self, budget: usize) -> usize {
+        if self.cache_size() <= budget { return 0 }
+
+        // Rows are rebuilt from the line cache cheaply, they go first
+        let mut evicted = self.rows.borrow().len();
+        self.clear_rows();
+
+        let mut by_age: Vec<(u64, u64)> = self.cache_ticks.borrow()
+            .iter()
+            .map(|(checksum, tick)| (*tick, *checksum))
+            .collect();
+        by_age.sort_unstable();
+
+        let mut cache = self.cache.borrow_mut();
+        let mut ticks = self.cache_ticks.borrow_mut();
+
+        for (_, checksum) in by_age {
+            if self.cache_bytes.get() <= budget { break }
+
+            if let Some(tokens) = cache.remove(&checksum) {
+                self.cache_bytes.set(self.cache_bytes.get() - tokens_size(&tokens));
+                evicted += 1;
+            }
+            ticks.remove(&checksum);
+        }
+
+        evicted
     }
 
     pub fn shift_line_tokens(&self, row: usize, col: usize, width: isize) {
@@ -174,13 +329,19 @@ impl Highlighter {
                 }
             }
         }
-        self.cache.borrow_mut().clear();
+        self.forget_row(row);
     }
 
     pub fn apply_edit(&self, start_row: usize, start_col: usize, 
                   deleted_lines: usize, deleted_cols: usize,
                   inserted_lines: usize, inserted_cols: usize) 
     {
+        // Deleting a line joins it into the one above
+        match deleted_lines {
+            0 => self.shift_rows(start_row, inserted_lines as isize),
+            _ => self.shift_rows(start_row.saturating_sub(1), inserted_lines as isize - deleted_lines as isize),
+        }
+
         let mut tokens = self.tokens.borrow_mut();
 
         if tokens.is_empty() { return }
@@ -228,8 +389,6 @@ impl Highlighter {
             }
             
         }
-
-        self.cache.borrow_mut().clear();
     }
 
     pub fn get_tokens(&self, row: usize) -> Option<Vec<Token>> {
@@ -240,6 +399,100 @@ impl Highlighter {
     
     pub fn update_tokens(&self, tokens: Vec<Vec<Token>>) {
         *self.tokens.borrow_mut() = tokens;
-        self.cache.borrow_mut().clear();
+        // The line cache only holds tokens from the rules, it stays valid
+        self.clear_rows();
     }
 }
+
+/// Adds foreground tokens for the text between `tokens`, so every part of the line is drawn.
+fn fill_gaps(line: &str, index: usize, mut tokens: Vec<Token>) -> Vec<Token> {
+    let mut found_tokens = Vec::new();
+    let mut buffer = String::new();
+
+    let mut i = 0;
+    while i < line.len() {
+        let is_token_start = tokens.iter().any(|t| t.offset == i);
+
+        if is_token_start {
+            if !buffer.is_empty() {
+                let start = i - buffer.len();
+                found_tokens.push(Token {
+                    row: index,
+                    text: buffer.clone(),
+                    offset: start,
+                    style: Some(Color::White),
+                });
+                buffer.clear();
+            }
+
+            if let Some(existing) = tokens.iter().find(|t| t.offset == i) {
+                i += existing.text.len();
+                continue;
+            }
+        }
+
+        if let Some(ch) = line.chars().nth(i) {
+            buffer.push(ch);
+        }
+
+        if i == line.len() - 1 && !buffer.is_empty() {
+            let start = i + 1 - buffer.len();
+            found_tokens.push(Token {
+                row: index,
+                text: buffer.clone(),
+                offset: start,
+                style: Some(Color::White),
+            });
+        }
+
+        i += 1;
+    }
+
+    tokens.extend(found_tokens);
+    tokens.sort_by_key(|t| t.offset);
+    tokens
+}
+
+fn tokens_size(tokens: &[Token]) -> usize {
+    tokens.iter()
+        .map(|token| std::mem::size_of::<Token>() + token.text.capacity())
+        .sum::<usize>()
+        + std::mem::size_of::<Vec<Token>>()
+}
+
+/// Colors of the rules until a theme is set, and for keys the theme leaves out.
+fn default_colors() -> HashMap<String, Color> {
+    let mut colors: HashMap<String, Color> = HashMap::new();
+
+    colors.insert("bg".into(), Color::Reset);
+    colors.insert("fg".into(), Color::White);
+
+    colors.insert("namespace".into(), Color::Blue);
+    colors.insert("type".into(), Color::Magenta);
+    colors.insert("class".into(), Color::Magenta);
+    colors.insert("enum".into(), Color::Magenta);
+    colors.insert("interface".into(), Color::Magenta);
+    colors.insert("struct".into(), Color::Magenta);
+    colors.insert("typeParameter".into(), Color::Cyan);
+
+    colors.insert("parameter".into(), Color::White);
+    colors.insert("variable".into(), Color::White);
+    colors.insert("property".into(), Color::Yellow);
+    colors.insert("enumMember".into(), Color::Yellow);
+
+    colors.insert("event".into(), Color::Green);
+    colors.insert("function".into(), Color::Green);
+    colors.insert("method".into(), Color::Green);
+    colors.insert("macro".into(), Color::Cyan);
+
+    colors.insert("keyword".into(), Color::Blue);
+    colors.insert("modifier".into(), Color::Blue);
+    colors.insert("operator".into(), Color::White);
+
+    colors.insert("comment".into(), Color::DarkGrey);
+    colors.insert("string".into(), Color::Red);
+    colors.insert("number".into(), Color::Cyan);
+    colors.insert("regexp".into(), Color::Cyan);
+
+    colors
+}
@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+
+/// How many command lines are kept.
+const HISTORY_LIMIT: usize = 500;
+
+/// Command lines run before, oldest first. Up and Down walk the ones starting with what
+/// was typed when the walk began.
+pub struct CommandHistory {
+    entries: Vec<String>,
+    // The entry shown while walking, `None` when back at the typed line
+    position: Option<usize>,
+    prefix: String,
+}
+
+/// Where the history is kept between sessions, ~/.local/share/oxidy/history on Linux.
+pub fn path() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("oxidy/history")
+}
+
+impl CommandHistory {
+    pub fn new() -> Self {
+        Self { entries: vec![], position: None, prefix: String::new() }
+    }
+
+    /// The history of the last session, empty if it can't be read.
+    pub fn load() -> Self {
+        let entries = std::fs::read_to_string(path())
+            .map(|text| text.lines().filter(|line| !line.is_empty()).map(String::from).collect())
+            .unwrap_or_default();
+
+        Self { entries, ..Self::new() }
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        let path = path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        std::fs::write(path, self.entries.join("\n") + "\n")
+    }
+
+    /// Adds a line that was run, moving it to the end if it was run before.
+    pub fn push(&mut self, line: &str) -> std::io::Result<()> {
+        self.reset();
+
+        let line = line.trim();
+        if line.is_empty() { return Ok(()) }
+
+        self.entries.retain(|entry| entry != line);
+        self.entries.push(line.to_string());
+        if self.entries.len() > HISTORY_LIMIT {
+            self.entries.remove(0);
+        }
+
+        self.save()
+    }
+
+    /// The next older line starting with the typed one, `typed` is only read on the first step.
+    pub fn prev(&mut self, typed: &str) -> Option<String> {
+        if self.position.is_none() {
+            self.prefix = typed.to_string();
+        }
+
+        let end = self.position.unwrap_or(self.entries.len());
+        let index = self.entries[..end].iter().rposition(|entry| entry.starts_with(&self.prefix))?;
+
+        self.position = Some(index);
+        Some(self.entries[index].clone())
+    }
+
+    /// The next newer line starting with the typed one, or the typed line past the newest.
+    pub fn next(&mut self) -> Option<String> {
+        let start = self.position? + 1;
+
+        match self.entries[start..].iter().position(|entry| entry.starts_with(&self.prefix)) {
+            Some(offset) => {
+                self.position = Some(start + offset);
+                Some(self.entries[start + offset].clone())
+            }
+            None => {
+                self.position = None;
+                Some(self.prefix.clone())
+            }
+        }
+    }
+
+    /// Forgets the walk, the next Up starts from the newest line again.
+    pub fn reset(&mut self) {
+        self.position = None;
+    }
+}
@@ -0,0 +1,157 @@
+/// How a buffer indents its lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndentStyle {
+    Tabs,
+    Spaces(usize),
+}
+
+impl IndentStyle {
+    /// The indentation for `width` columns.
+    pub fn render(&self, width: usize, tab_size: usize) -> String {
+        match self {
+            IndentStyle::Tabs => format!("{}{}", "\t".repeat(width / tab_size), " ".repeat(width % tab_size)),
+            IndentStyle::Spaces(_) => " ".repeat(width),
+        }
+    }
+}
+
+/// Leading whitespace of a line.
+pub fn leading(line: &str) -> &str {
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// Columns the leading whitespace of a line takes up.
+pub fn width(line: &str, tab_size: usize) -> usize {
+    leading(line).chars().fold(0, |col, ch| match ch {
+        '\t' => (col / tab_size + 1) * tab_size,
+        _ => col + 1,
+    })
+}
+
+/// Guesses the indent style from the lines, falling back to `tab_size` spaces
+/// when nothing is indented.
+pub fn detect(lines: &[String], tab_size: usize) -> IndentStyle {
+    let mut tabs = 0;
+    let mut spaces = 0;
+    let mut step = 0;
+    let mut previous = 0;
+
+    for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+        let indent = leading(line);
+
+        if indent.starts_with('\t') {
+            tabs += 1;
+        } else if !indent.is_empty() {
+            spaces += 1;
+
+            // The smallest change between lines is the indent unit,
+            // absolute widths would mistake 8 spaces for one level
+            let diff = indent.len().abs_diff(previous);
+            if diff > 0 && (step == 0 || diff < step) {
+                step = diff;
+            }
+        }
+
+        previous = if indent.starts_with('\t') { 0 } else { indent.len() };
+    }
+
+    if tabs > spaces {
+        IndentStyle::Tabs
+    } else if spaces > 0 && step > 0 {
+        IndentStyle::Spaces(step)
+    } else {
+        IndentStyle::Spaces(tab_size)
+    }
+}
+
+/// Re-indents pasted text so its least indented line lines up with `base`, the
+/// indentation of the line it is pasted into. The first line continues at the
+/// cursor and keeps only its indentation relative to the rest.
+pub fn reindent(text: &str, base: &str, style: IndentStyle, tab_size: usize) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    if lines.len() < 2 { return text.to_string() }
+
+    let common = lines.iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| width(line, tab_size))
+        .min()
+        .unwrap_or(0);
+    let base_width = width(base, tab_size);
+
+    lines.iter().enumerate()
+        .map(|(i, line)| {
+            let content = line.trim_start_matches([' ', '\t']);
+            if content.trim().is_empty() { return content.to_string() }
+
+            let extra = width(line, tab_size) - common;
+            let width = if i == 0 { extra } else { base_width + extra };
+            format!("{}{}", style.render(width, tab_size), content)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Brackets a line opens minus the ones it closes, and how many closing brackets
+/// it starts with. Brackets in strings and `//` comments don't count.
+fn brackets(line: &str) -> (isize, isize) {
+    let content = line.trim_start();
+    let leading = content.chars().take_while(|ch| matches!(ch, '}' | ')' | ']')).count() as isize;
+
+    let mut net = 0;
+    let mut quote = None;
+    let mut chars = content.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (Some(_), '\\') => { chars.next(); }
+            (Some(q), ch) if ch == q => quote = None,
+            (Some(_), _) => {}
+            // Single quotes are left out, they are lifetimes in Rust
+            (None, '"' | '`') => quote = Some(ch),
+            (None, '/') if chars.peek() == Some(&'/') => break,
+            (None, '{' | '(' | '[') => net += 1,
+            (None, '}' | ')' | ']') => net -= 1,
+            _ => {}
+        }
+    }
+
+    (net, leading)
+}
+
+/// Re-indents rows `start..=end` from the brackets of the lines above them, for
+/// when no language server can format the range. Returns the new lines.
+pub fn reindent_rows(lines: &[String], start: usize, end: usize, style: IndentStyle, tab_size: usize) -> Vec<String> {
+    let mut result = lines.to_vec();
+    if start >= lines.len() { return result }
+
+    let unit = match style {
+        IndentStyle::Tabs => tab_size,
+        IndentStyle::Spaces(step) => step,
+    } as isize;
+
+    // The last non-blank line above the range decides where it starts
+    let mut next = lines[..start].iter()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (net, leading) = brackets(line);
+            width(line, tab_size) as isize + unit * (net + leading)
+        })
+        .unwrap_or(0);
+
+    for row in start..=end.min(lines.len() - 1) {
+        let content = lines[row].trim_start_matches([' ', '\t']);
+        if content.trim().is_empty() {
+            result[row] = String::new();
+            continue;
+        }
+
+        let (net, leading) = brackets(content);
+        let width = (next - unit * leading).max(0);
+
+        result[row] = format!("{}{}", style.render(width as usize, tab_size), content);
+        next = width + unit * (net + leading);
+    }
+
+    result
+}
@@ -0,0 +1,71 @@
+use crate::services::lsp_service::utf16_to_byte_index;
+
+/// Virtual text the LSP wants shown before `col`, like a type after a binding or a parameter name.
+/// Rows are zero based, `col` counts chars. `label` already has the padding the server asked for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InlayHint {
+    pub row: usize,
+    pub col: usize,
+    pub label: String,
+}
+
+impl InlayHint {
+    /// The server sends UTF-16 offsets, the renderers work in chars.
+    pub fn to_char_col(mut self, line: &str) -> Self {
+        let byte = utf16_to_byte_index(line, self.col);
+        self.col = line[..byte].chars().count();
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.label.chars().count()
+    }
+}
+
+/// Hints on `row` in the order they appear.
+pub fn on_row(hints: &[InlayHint], row: usize) -> Vec<&InlayHint> {
+    let mut hints: Vec<&InlayHint> = hints.iter().filter(|hint| hint.row == row).collect();
+    hints.sort_by_key(|hint| hint.col);
+    hints
+}
+
+/// Where char `col` of `row` is drawn once the hints before it push the text to the right.
+/// Hints scrolled out on the left are not drawn, so they don't count.
+pub fn display_col(hints: &[InlayHint], row: usize, col: usize, horiz_scroll: usize) -> usize {
+    col + hints.iter()
+        .filter(|hint| hint.row == row && hint.col >= horiz_scroll && hint.col < col)
+        .map(|hint| hint.width())
+        .sum::<usize>()
+}
+
+/// The hints of `row` with columns counted from `horiz_scroll`, for drawing the part of the
+/// line on screen. Hints scrolled out on the left are left out.
+pub fn scrolled(hints: &[InlayHint], row: usize, horiz_scroll: usize) -> Vec<InlayHint> {
+    on_row(hints, row).into_iter()
+        .filter(|hint| hint.col >= horiz_scroll)
+        .map(|hint| InlayHint { col: hint.col - horiz_scroll, ..hint.clone() })
+        .collect()
+}
+
+/// Splits `line` into pieces of text and hints for renderers that draw a line in one go.
+/// Hint pieces are flagged with true.
+pub fn segments<'a>(line: &'a str, hints: &'a [InlayHint], row: usize) -> Vec<(&'a str, bool)> {
+    let mut segments = vec![];
+    let mut start = 0;
+
+    for hint in on_row(hints, row) {
+        let byte = line.char_indices().nth(hint.col).map(|(i, _)| i).unwrap_or(line.len());
+
+        if byte > start {
+            segments.push((&line[start..byte], false));
+        }
+        segments.push((hint.label.as_str(), true));
+        start = byte;
+    }
+
+    if start < line.len() {
+        segments.push((&line[start..], false));
+    }
+
+    segments
+}
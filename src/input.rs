@@ -1,19 +1,23 @@
 use std::{io, time::Duration};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
 
-use crossterm::event::{poll, read, Event, KeyCode, KeyEvent, KeyModifiers, MouseEventKind};
+use crossterm::event::{poll, read, Event, KeyCode, KeyEvent, KeyModifiers, MouseEventKind, MouseButton as CrosstermMouseButton};
+use crossterm::terminal;
+use serde::{Deserialize, Serialize};
 
 use crate::{buffer::BufferLocation, types::EditorMode};
+use crate::signals;
 
 use crate::types::{Key, Modifiers, Direction};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum MouseButton {
     Left,
     Right,
     Middle,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum MouseType {
     Down(MouseButton, u16, u16),
     Up(MouseButton, u16, u16),
@@ -21,11 +25,15 @@ pub enum MouseType {
     Move(u16, u16),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum InputEvent {
     Key { key: Key, modifiers: Modifiers },
-    Mouse(MouseType),
+    Mouse(MouseType, Modifiers),
     Scroll(Direction),
+    FocusGained,
+    FocusLost,
+    Paste(String),
+    Resize(u16, u16),
 }
 
 pub trait InputHandler {
@@ -36,10 +44,18 @@ pub struct CrosstermInput;
 
 impl InputHandler for CrosstermInput {
     fn poll(&mut self) -> io::Result<Option<InputEvent>> {
+        // For terminals that change size without crossterm noticing
+        if signals::take_resize() {
+            let (cols, rows) = terminal::size()?;
+            return Ok(Some(InputEvent::Resize(cols, rows)));
+        }
+
         if poll(Duration::from_millis(16))? {
             match read()? {
                 Event::Key(e) => Ok(Some(self.translate_key_event(e))),
                 Event::Mouse(e) => {
+                    let modifiers = Self::translate_modifiers(e.modifiers);
+
                     match e.kind {
                         MouseEventKind::ScrollDown => {
                             Ok(Some(InputEvent::Scroll(Direction::Down)))
@@ -47,9 +63,26 @@ impl InputHandler for CrosstermInput {
                         MouseEventKind::ScrollUp => {
                             Ok(Some(InputEvent::Scroll(Direction::Up)))
                         }
+                        MouseEventKind::Down(button) => {
+                            Ok(Some(InputEvent::Mouse(MouseType::Down(Self::translate_button(button), e.column, e.row), modifiers)))
+                        }
+                        MouseEventKind::Up(button) => {
+                            Ok(Some(InputEvent::Mouse(MouseType::Up(Self::translate_button(button), e.column, e.row), modifiers)))
+                        }
+                        MouseEventKind::Drag(button) => {
+                            Ok(Some(InputEvent::Mouse(MouseType::Drag(Self::translate_button(button), e.column, e.row), modifiers)))
+                        }
+                        MouseEventKind::Moved => {
+                            Ok(Some(InputEvent::Mouse(MouseType::Move(e.column, e.row), modifiers)))
+                        }
                         _ => { Ok(None) }
                     }
                 }
+                // Inside tmux these only arrive with `set -g focus-events on`
+                Event::FocusGained => Ok(Some(InputEvent::FocusGained)),
+                Event::FocusLost => Ok(Some(InputEvent::FocusLost)),
+                Event::Resize(cols, rows) => Ok(Some(InputEvent::Resize(cols, rows))),
+                Event::Paste(text) => Ok(Some(InputEvent::Paste(text))),
                 _ => Ok(None),
             }
         } else {
@@ -78,13 +111,29 @@ impl CrosstermInput {
                 _ => Key::Unknown,
             },
             modifiers: Modifiers {
-                ctrl: event.modifiers.contains(KeyModifiers::CONTROL),
-                alt: event.modifiers.contains(KeyModifiers::ALT),
-                shift: event.modifiers.contains(KeyModifiers::SHIFT),
-                super_key: false,
+                // Shift is already part of the character, so "D" matches a mapping for "D"
+                shift: event.modifiers.contains(KeyModifiers::SHIFT) && !matches!(event.code, KeyCode::Char(_)),
+                ..Self::translate_modifiers(event.modifiers)
             },
         }
     }
+
+    fn translate_modifiers(modifiers: KeyModifiers) -> Modifiers {
+        Modifiers {
+            ctrl: modifiers.contains(KeyModifiers::CONTROL),
+            alt: modifiers.contains(KeyModifiers::ALT),
+            shift: modifiers.contains(KeyModifiers::SHIFT),
+            super_key: false,
+        }
+    }
+
+    fn translate_button(button: CrosstermMouseButton) -> MouseButton {
+        match button {
+            CrosstermMouseButton::Left => MouseButton::Left,
+            CrosstermMouseButton::Right => MouseButton::Right,
+            CrosstermMouseButton::Middle => MouseButton::Middle,
+        }
+    }
 }
 
 pub struct WgpuInput;
@@ -100,3 +149,28 @@ impl WgpuInput {
         Self
     }
 }
+
+/// Input of a frontend attached to the session, read off the socket by the session service.
+pub struct SessionInput {
+    receiver: Receiver<InputEvent>,
+}
+
+impl SessionInput {
+    pub fn new(receiver: Receiver<InputEvent>) -> Self {
+        Self { receiver }
+    }
+}
+
+impl InputHandler for SessionInput {
+    fn poll(&mut self) -> io::Result<Option<InputEvent>> {
+        match self.receiver.recv_timeout(Duration::from_millis(16)) {
+            Ok(event) => Ok(Some(event)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            // The frontend went away, the session service reports it and the app detaches
+            Err(RecvTimeoutError::Disconnected) => {
+                std::thread::sleep(Duration::from_millis(16));
+                Ok(None)
+            }
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 use crate::types::{Key, Modifiers, EditorAction, EditorMode};
 use crate::input::InputEvent;
@@ -10,79 +11,211 @@ pub struct KeyCombo {
 }
 
 impl KeyCombo {
+    /// Lenient version of `parse` for the built-in bindings, bad input becomes `Key::Unknown`.
     pub fn from_str(s: &str) -> Self {
-        // Case 1: Single character without brackets
-        if !s.starts_with('<') && s.len() == 1 {
-            return KeyCombo {
-                key: Key::Char(s.chars().next().unwrap()),
-                mods: Modifiers::default(),
+        Self::parse(s).unwrap_or(KeyCombo {
+            key: Key::Unknown,
+            mods: Modifiers::default(),
+        })
+    }
+
+    /// Parses a single key, either one character or a bracketed combo like "<C-s>".
+    /// Names and modifiers are case insensitive, anything not understood is an error.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut chars = s.chars();
+        if let (Some(ch), None) = (chars.next(), chars.next()) {
+            return Ok(KeyCombo { key: Key::Char(ch), mods: Modifiers::default() });
+        }
+
+        let inner = match s.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+            Some(inner) if !inner.is_empty() => inner,
+            _ => return Err(format!("'{}' is not a key, use one character or <...>", s)),
+        };
+
+        // The key itself may be '-', as in "<C-->"
+        let (modifiers, name) = match inner.strip_suffix("--") {
+            Some(modifiers) => (modifiers, "-"),
+            None => match inner.rsplit_once('-') {
+                Some((modifiers, name)) => (modifiers, name),
+                None => ("", inner),
+            },
+        };
+
+        let mut mods = Modifiers::default();
+
+        for modifier in modifiers.split('-').filter(|_| !modifiers.is_empty()) {
+            let flag = match modifier.to_lowercase().as_str() {
+                "c" | "ctrl" => &mut mods.ctrl,
+                "a" | "alt" | "m" => &mut mods.alt,
+                "s" | "shift" => &mut mods.shift,
+                "d" | "super" | "cmd" | "meta" => &mut mods.super_key,
+                _ => return Err(format!("Unknown modifier '{}' in {}", modifier, s)),
             };
+
+            if *flag {
+                return Err(format!("Modifier '{}' given twice in {}", modifier, s));
+            }
+            *flag = true;
         }
 
-        // Case 2: Bracketed key combo like "<C-s>"
-        if s.starts_with('<') && s.ends_with('>') {
-            let inner = &s[1..s.len()-1]; // remove < >
+        let key = match Self::named_key(name) {
+            Some(key) => key,
+            None => return Err(format!("Unknown key '{}' in {}", name, s)),
+        };
 
-            // Split by '-' to get tokens
-            let parts: Vec<&str> = inner.split('-').collect();
+        Ok(KeyCombo { key, mods }.normalized())
+    }
 
-            let mut mods = Modifiers::default();
-            let mut key = Key::Unknown;
+    fn named_key(name: &str) -> Option<Key> {
+        let mut chars = name.chars();
+        if let (Some(ch), None) = (chars.next(), chars.next()) {
+            return Some(Key::Char(ch));
+        }
 
-            for (i, part) in parts.iter().enumerate() {
-                let p = part.to_lowercase();
+        let name = name.to_lowercase();
 
-                // If this is NOT the last token, it's modifier
-                let is_last = i == parts.len() - 1;
+        if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+            return (1..=12).contains(&n).then_some(Key::F(n));
+        }
 
-                if !is_last {
-                    match p.as_str() {
-                        "c" | "ctrl" => mods.ctrl = true,
-                        "a" | "alt"  => mods.alt = true,
-                        "s" | "shift" => mods.shift = true,
-                        "super" | "cmd" | "meta" => mods.super_key = true,
-                        _ => {}
-                    }
+        let key = match name.as_str() {
+            "esc" => Key::Esc,
+            "space" => Key::Char(' '),
+            "lt" => Key::Char('<'),
+            "bslash" => Key::Char('\\'),
+            "bar" => Key::Char('|'),
+            "enter" | "cr" | "ret" | "return" => Key::Enter,
+            "tab" => Key::Tab,
+            "backspace" | "bs" => Key::Backspace,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "home" => Key::Home,
+            "end" => Key::End,
+            "pageup" => Key::PageUp,
+            "pagedown" => Key::PageDown,
+            "delete" | "del" => Key::Delete,
+            "insert" | "ins" => Key::Insert,
+            _ => return None,
+        };
+
+        Some(key)
+    }
+
+    /// Brings the combo into the form input events arrive in: shift is part of the character,
+    /// so "<S-a>" is "A", and control combos use the lower case letter.
+    pub fn normalized(mut self) -> Self {
+        if let Key::Char(ch) = self.key {
+            if self.mods.shift {
+                self.key = Key::Char(ch.to_uppercase().next().unwrap_or(ch));
+                self.mods.shift = false;
+            } else if self.mods.ctrl && ch.is_ascii_uppercase() {
+                self.key = Key::Char(ch.to_ascii_lowercase());
+            }
+        }
+
+        self
+    }
+
+    /// Writes the combo back in the notation `from_str` accepts, e.g. "<C-s>".
+    pub fn to_notation(&self) -> String {
+        let name = match self.key {
+            Key::Char(' ') => "Space".to_string(),
+            Key::Char('<') => "lt".to_string(),
+            Key::Char(ch) => ch.to_string(),
+            Key::Enter => "Enter".into(),
+            Key::Backspace => "Backspace".into(),
+            Key::Tab => "Tab".into(),
+            Key::Esc => "Esc".into(),
+            Key::Left => "Left".into(),
+            Key::Right => "Right".into(),
+            Key::Up => "Up".into(),
+            Key::Down => "Down".into(),
+            Key::Home => "Home".into(),
+            Key::End => "End".into(),
+            Key::PageUp => "PageUp".into(),
+            Key::PageDown => "PageDown".into(),
+            Key::Delete => "Del".into(),
+            Key::Insert => "Ins".into(),
+            Key::F(n) => format!("F{}", n),
+            Key::Unknown => "?".into(),
+        };
+
+        // Control combos are read with the lower case letter, an upper case one needs the shift
+        let (name, shift) = match self.key {
+            Key::Char(ch) if self.mods.ctrl && ch.is_ascii_uppercase() => (ch.to_ascii_lowercase().to_string(), true),
+            _ => (name, self.mods.shift),
+        };
+
+        let mut prefix = String::new();
+        if self.mods.ctrl { prefix.push_str("C-") }
+        if self.mods.alt { prefix.push_str("A-") }
+        if shift { prefix.push_str("S-") }
+        if self.mods.super_key { prefix.push_str("Super-") }
+
+        if prefix.is_empty() && matches!(self.key, Key::Char(ch) if ch != ' ' && ch != '<') {
+            name
+        } else {
+            format!("<{}{}>", prefix, name)
+        }
+    }
+
+    /// Parses a sequence of keys like "gx" or "<C-w>h" into its combos.
+    pub fn parse_sequence(s: &str) -> Vec<KeyCombo> {
+        let mut combos = Vec::new();
+        let mut rest = s;
+
+        while let Some(ch) = rest.chars().next() {
+            if ch == '<' {
+                if let Some(end) = rest.find('>') {
+                    combos.push(KeyCombo::from_str(&rest[..=end]));
+                    rest = &rest[end + 1..];
                     continue;
                 }
+            }
 
-                // Last token = key
-                key = match p.as_str() {
-                    "esc" => Key::Esc,
-                    "enter" => Key::Enter,
-                    "ret" | "return" => Key::Enter,
-                    "tab" => Key::Tab,
-                    "backspace" => Key::Backspace,
-                    "bs" => Key::Backspace,
-                    "left" => Key::Left,
-                    "right" => Key::Right,
-                    "up" => Key::Up,
-                    "down" => Key::Down,
-                    "home" => Key::Home,
-                    "end" => Key::End,
-                    "pageup" => Key::PageUp,
-                    "pagedown" => Key::PageDown,
-                    "delete" | "del" => Key::Delete,
-                    "insert" | "ins" => Key::Insert,
-
-                    // Single-character key: <C-x>
-                    c if c.len() == 1 => {
-                        let ch = c.chars().next().unwrap();
-                        Key::Char(ch)
-                    }
+            combos.push(KeyCombo {
+                key: Key::Char(ch),
+                mods: Modifiers::default(),
+            });
+            rest = &rest[ch.len_utf8()..];
+        }
 
-                    _ => Key::Unknown
-                };
-            }
+        combos
+    }
 
-            return KeyCombo { key, mods };
+    /// Strict version of `parse_sequence` for mappings from the config. A '<' has to start
+    /// a combo, a literal one is written "<lt>".
+    pub fn parse_sequence_strict(s: &str) -> Result<Vec<KeyCombo>, String> {
+        if s.is_empty() {
+            return Err("Empty key sequence".into());
         }
 
-        // Fallback: unknown
-        KeyCombo {
-            key: Key::Unknown,
-            mods: Modifiers::default(),
+        let mut combos = Vec::new();
+        let mut rest = s;
+
+        while let Some(ch) = rest.chars().next() {
+            let len = match ch {
+                '<' => match rest.find('>') {
+                    // "<C->>" ends with two of them, but "<C-->>" is "<C-->" and then ">"
+                    Some(end) if rest[end + 1..].starts_with('>') && rest[..end].ends_with('-') && KeyCombo::parse(&rest[..=end]).is_err() => end + 2,
+                    Some(end) => end + 1,
+                    None => return Err(format!("Unclosed '<' in {}, write <lt> for the key itself", s)),
+                },
+                ch => ch.len_utf8(),
+            };
+
+            combos.push(KeyCombo::parse(&rest[..len])?);
+            rest = &rest[len..];
         }
+
+        Ok(combos)
+    }
+
+    /// Writes a sequence in the notation `parse_sequence_strict` reads back.
+    pub fn sequence_notation(combos: &[KeyCombo]) -> String {
+        combos.iter().map(|combo| combo.to_notation()).collect()
     }
 
     pub fn from_input_event(event: &InputEvent) -> Option<Self> {
@@ -99,9 +232,26 @@ impl KeyCombo {
 }
 
 pub struct Keymap {
-    normal: HashMap<KeyCombo, EditorAction>,
-    insert: HashMap<KeyCombo, EditorAction>,
-    command: HashMap<KeyCombo, EditorAction>,
+    normal: HashMap<Vec<KeyCombo>, EditorAction>,
+    insert: HashMap<Vec<KeyCombo>, EditorAction>,
+    command: HashMap<Vec<KeyCombo>, EditorAction>,
+    /// Bindings that only apply in buffers of one filetype, they win over the global ones.
+    filetypes: HashMap<String, FiletypeKeymap>,
+    pending: Vec<KeyCombo>,
+    /// Insert mode sequences of typed characters like "jk". All but the last character
+    /// are inserted as usual and taken back out once the sequence completes.
+    typed: HashMap<Vec<char>, EditorAction>,
+    typed_pending: Vec<char>,
+    typed_at: Option<Instant>,
+    typed_timeout: Duration,
+    /// Characters the last resolved action asks to delete first, see `take_provisional`.
+    provisional: usize,
+}
+
+#[derive(Default)]
+struct FiletypeKeymap {
+    normal: HashMap<Vec<KeyCombo>, EditorAction>,
+    insert: HashMap<Vec<KeyCombo>, EditorAction>,
 }
 
 impl Keymap {
@@ -110,27 +260,73 @@ impl Keymap {
             normal: HashMap::new(),
             insert: HashMap::new(),
             command: HashMap::new(),
+            filetypes: HashMap::new(),
+            pending: Vec::new(),
+            typed: HashMap::new(),
+            typed_pending: Vec::new(),
+            typed_at: None,
+            typed_timeout: Duration::from_millis(300),
+            provisional: 0,
         }
     }
 
-    pub fn resolve(&self, input: InputEvent, mode: &EditorMode) -> Option<EditorAction> {
+    pub fn resolve(&mut self, input: InputEvent, mode: &EditorMode, filetype: &str) -> Option<EditorAction> {
         let combo = KeyCombo::from_input_event(&input);
 
+        // Any other key breaks a typed sequence
+        let typed = *mode == EditorMode::Insert
+            && matches!(&input, InputEvent::Key { key: Key::Char(_), modifiers } if !modifiers.ctrl && !modifiers.alt);
+        if !typed {
+            self.typed_pending.clear();
+        }
+
         let table = match mode {
             EditorMode::Normal => &self.normal,
             EditorMode::Insert => &self.insert,
             EditorMode::Command => &self.command,
         };
 
+        let local = self.filetypes.get(filetype).and_then(|maps| match mode {
+            EditorMode::Normal => Some(&maps.normal),
+            EditorMode::Insert => Some(&maps.insert),
+            EditorMode::Command => None,
+        });
+
+        let lookup = |sequence: &[KeyCombo]| local
+            .and_then(|local| local.get(sequence))
+            .or_else(|| table.get(sequence))
+            .cloned();
+
         if let Some(ref c) = combo {
-            if let Some(action) = table.get(c) {
-                return Some(action.clone());
+            self.pending.push(c.clone());
+
+            if let Some(action) = lookup(&self.pending) {
+                self.pending.clear();
+                return Some(action);
+            }
+
+            // Wait for the next key if this could still become a mapped sequence
+            let mut sequences = table.keys().chain(local.into_iter().flat_map(|local| local.keys()));
+            if sequences.any(|seq| seq.starts_with(&self.pending)) {
+                return None;
+            }
+
+            let was_sequence = self.pending.len() > 1;
+            self.pending.clear();
+
+            if was_sequence {
+                if let Some(action) = lookup(std::slice::from_ref(c)) {
+                    return Some(action);
+                }
             }
         }
 
         if let EditorMode::Insert = mode {
             if let InputEvent::Key { key: Key::Char(ch), modifiers } = input {
                 if !modifiers.ctrl && !modifiers.alt {
+                    if let Some(action) = self.resolve_typed(ch) {
+                        return Some(action);
+                    }
                     return Some(EditorAction::InsertChar(ch));
                 }
             }
@@ -147,6 +343,98 @@ impl Keymap {
         None
     }
 
+    /// The keys typed so far of a mapping that isn't complete yet, in key notation.
+    pub fn pending_keys(&self) -> String {
+        KeyCombo::sequence_notation(&self.pending)
+    }
+
+    /// Follows `ch` through the typed sequences. Returns the action once one completes in time,
+    /// after noting how many of its characters were inserted already.
+    fn resolve_typed(&mut self, ch: char) -> Option<EditorAction> {
+        if self.typed.is_empty() { return None }
+
+        if self.typed_at.is_some_and(|at| at.elapsed() > self.typed_timeout) {
+            self.typed_pending.clear();
+        }
+        self.typed_pending.push(ch);
+        self.typed_at = Some(Instant::now());
+
+        // "jjk" still ends in "jk"
+        while !self.typed_pending.is_empty() && !self.typed.keys().any(|seq| seq.starts_with(&self.typed_pending)) {
+            self.typed_pending.remove(0);
+        }
+
+        let action = self.typed.get(&self.typed_pending).cloned()?;
+        self.provisional = self.typed_pending.len() - 1;
+        self.typed_pending.clear();
+
+        Some(action)
+    }
+
+    /// How many characters before the cursor belong to the typed sequence that was just
+    /// resolved and have to be deleted before running its action.
+    pub fn take_provisional(&mut self) -> usize {
+        std::mem::take(&mut self.provisional)
+    }
+
+    /// Maps a sequence of characters typed in insert mode, like "jk" for leaving it.
+    pub fn map_typed(&mut self, keys: &str, action: EditorAction) {
+        if keys.is_empty() { return }
+        self.typed.insert(keys.chars().collect(), action);
+    }
+
+    /// How long typing may pause within a typed sequence.
+    pub fn set_typed_timeout(&mut self, timeout: Duration) {
+        self.typed_timeout = timeout;
+    }
+
+    /// All bindings of `mode` in key notation, sorted by their keys.
+    pub fn bindings(&self, mode: &EditorMode) -> Vec<(String, EditorAction)> {
+        let table = match mode {
+            EditorMode::Normal => &self.normal,
+            EditorMode::Insert => &self.insert,
+            EditorMode::Command => &self.command,
+        };
+
+        let mut bindings = Self::notation(table);
+        if *mode == EditorMode::Insert {
+            bindings.extend(self.typed.iter().map(|(keys, action)| (keys.iter().collect(), action.clone())));
+            bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+
+        bindings
+    }
+
+    /// The filetypes that have bindings of their own, sorted.
+    pub fn filetypes(&self) -> Vec<String> {
+        let mut filetypes: Vec<String> = self.filetypes.keys().cloned().collect();
+        filetypes.sort();
+        filetypes
+    }
+
+    /// Bindings of `mode` that only apply to `filetype`, in key notation.
+    pub fn filetype_bindings(&self, filetype: &str, mode: &EditorMode) -> Vec<(String, EditorAction)> {
+        let table = self.filetypes.get(filetype).and_then(|maps| match mode {
+            EditorMode::Normal => Some(&maps.normal),
+            EditorMode::Insert => Some(&maps.insert),
+            EditorMode::Command => None,
+        });
+
+        table.map(Self::notation).unwrap_or_default()
+    }
+
+    fn notation(table: &HashMap<Vec<KeyCombo>, EditorAction>) -> Vec<(String, EditorAction)> {
+        let mut bindings: Vec<(String, EditorAction)> = table.iter()
+            .map(|(sequence, action)| {
+                let keys = KeyCombo::sequence_notation(sequence);
+                (keys, action.clone())
+            })
+            .collect();
+
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+
     pub fn normal(&mut self) -> KeymapBuilder {
         KeymapBuilder { map: &mut self.normal }
     }
@@ -158,16 +446,152 @@ impl Keymap {
     pub fn command(&mut self) -> KeymapBuilder {
         KeymapBuilder { map: &mut self.command }
     }
+
+    pub fn mode(&mut self, mode: &EditorMode) -> KeymapBuilder {
+        match mode {
+            EditorMode::Normal => self.normal(),
+            EditorMode::Insert => self.insert(),
+            EditorMode::Command => self.command(),
+        }
+    }
+
+    pub fn filetype_normal(&mut self, filetype: &str) -> KeymapBuilder {
+        KeymapBuilder { map: &mut self.filetypes.entry(filetype.to_string()).or_default().normal }
+    }
+
+    pub fn filetype_insert(&mut self, filetype: &str) -> KeymapBuilder {
+        KeymapBuilder { map: &mut self.filetypes.entry(filetype.to_string()).or_default().insert }
+    }
 }
 
 pub struct KeymapBuilder<'a> {
-    map: &'a mut HashMap<KeyCombo, EditorAction>,
+    map: &'a mut HashMap<Vec<KeyCombo>, EditorAction>,
 }
 
 impl<'a> KeymapBuilder<'a> {
-    pub fn map(mut self, key: &str, action: EditorAction) -> Self {
-        let combo = KeyCombo::from_str(key);
-        self.map.insert(combo, action);
+    pub fn map(mut self, keys: &str, action: EditorAction) -> Self {
+        let sequence = KeyCombo::parse_sequence(keys);
+        self.map.insert(sequence, action);
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small xorshift generator, the same combos on every run.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+            items[self.below(items.len())]
+        }
+    }
+
+    const CHARS: &[char] = &['a', 'z', 'A', 'Z', 's', 'f', 'C', 'S', '0', '9', ' ', '<', '>', '-', '\\', '|', '/', ':', '[', 'é', 'É', 'ß', '日', '🦀'];
+    const NAMED: &[Key] = &[
+        Key::Enter, Key::Backspace, Key::Tab, Key::Esc, Key::Left, Key::Right, Key::Up, Key::Down,
+        Key::Home, Key::End, Key::PageUp, Key::PageDown, Key::Delete, Key::Insert,
+    ];
+
+    fn combo(rng: &mut Rng) -> KeyCombo {
+        let key = match rng.below(4) {
+            0 => rng.pick(NAMED),
+            1 => Key::F(1 + rng.below(12) as u8),
+            _ => Key::Char(rng.pick(CHARS)),
+        };
+        let mods = Modifiers {
+            ctrl: rng.below(3) == 0,
+            alt: rng.below(3) == 0,
+            shift: rng.below(3) == 0,
+            super_key: rng.below(5) == 0,
+        };
+
+        // Only the form parsing produces can come back the same
+        KeyCombo { key, mods }.normalized()
+    }
+
+    #[test]
+    fn sequences_round_trip() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+
+        for _ in 0..5000 {
+            let sequence: Vec<KeyCombo> = (0..1 + rng.below(4)).map(|_| combo(&mut rng)).collect();
+            let notation = KeyCombo::sequence_notation(&sequence);
+
+            assert_eq!(KeyCombo::parse_sequence_strict(&notation).as_ref(), Ok(&sequence), "{}", notation);
+        }
+    }
+
+    #[test]
+    fn single_combos_round_trip() {
+        let mut rng = Rng(42);
+
+        for _ in 0..2000 {
+            let combo = combo(&mut rng);
+            assert_eq!(KeyCombo::parse(&combo.to_notation()).as_ref(), Ok(&combo), "{}", combo.to_notation());
+        }
+    }
+
+    #[test]
+    fn tricky_notations() {
+        let char = |ch| KeyCombo { key: Key::Char(ch), mods: Modifiers::default() };
+        let ctrl = |key| KeyCombo { key, mods: Modifiers { ctrl: true, ..Modifiers::default() } };
+
+        assert_eq!(KeyCombo::parse_sequence_strict("<lt>"), Ok(vec![char('<')]));
+        assert_eq!(KeyCombo::parse_sequence_strict("<C-lt>"), Ok(vec![ctrl(Key::Char('<'))]));
+        assert_eq!(KeyCombo::parse_sequence_strict("<C->>"), Ok(vec![ctrl(Key::Char('>'))]));
+        assert_eq!(KeyCombo::parse_sequence_strict("<C-->>"), Ok(vec![ctrl(Key::Char('-')), char('>')]));
+        assert_eq!(KeyCombo::parse_sequence_strict("<C-S-a>"), Ok(vec![ctrl(Key::Char('A'))]));
+        assert_eq!(KeyCombo::parse_sequence_strict("<c-W>h"), Ok(vec![ctrl(Key::Char('w')), char('h')]));
+        assert_eq!(KeyCombo::parse_sequence_strict("<S-Tab>").map(|combos| combos[0].mods.shift), Ok(true));
+        assert_eq!(KeyCombo::sequence_notation(&[char('<'), char(' '), ctrl(Key::Char('A'))]), "<lt><Space><C-S-a>");
+    }
+
+    #[test]
+    fn bad_input_is_an_error() {
+        for input in ["", "<", "<C-a", "a<b", "<>", "<C->", "<X-a>", "<C-C-a>", "<F13>", "<F0>", "<nope>", "<C-日本>"] {
+            assert!(KeyCombo::parse_sequence_strict(input).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn arbitrary_input_does_not_panic() {
+        const PIECES: &[&str] = &["<", ">", "-", "C", "S-", "A-", "lt", "F1", "f99", "Space", "日", "🦀", "é", "\\", " ", "\0", "<<", ">>", "--", "x"];
+        let mut rng = Rng(7);
+
+        for _ in 0..20_000 {
+            let input: String = (0..rng.below(12)).map(|_| rng.pick(PIECES)).collect();
+
+            let _ = KeyCombo::parse(&input);
+            let _ = KeyCombo::parse_sequence(&input);
+            if let Ok(sequence) = KeyCombo::parse_sequence_strict(&input) {
+                // Whatever parses has a notation that parses the same
+                let notation = KeyCombo::sequence_notation(&sequence);
+                assert_eq!(KeyCombo::parse_sequence_strict(&notation).as_ref(), Ok(&sequence), "{:?} -> {}", input, notation);
+            }
+        }
+
+        // Random bytes, most of them not valid keys
+        for _ in 0..5000 {
+            let bytes: Vec<u8> = (0..rng.below(16)).map(|_| rng.next() as u8).collect();
+            let input = String::from_utf8_lossy(&bytes);
+
+            let _ = KeyCombo::parse(&input);
+            let _ = KeyCombo::parse_sequence(&input);
+            let _ = KeyCombo::parse_sequence_strict(&input);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+#![allow(warnings)]
+
+pub mod app;
+pub mod types;
+pub mod highlighter;
+pub mod editor;
+pub mod plugins;
+pub mod lsp;
+pub mod buffer;
+pub mod renderer;
+pub mod input;
+pub mod services;
+pub mod ui;
+pub mod log_manager;
+pub mod command;
+pub mod keymap;
+pub mod logger;
+pub mod links;
+pub mod crypto;
+pub mod collab;
+pub mod encoding;
+pub mod diagnostics;
+pub mod transform;
+pub mod shell;
+pub mod text_edit;
+pub mod inlay_hints;
+pub mod clipboard;
+pub mod tmux;
+pub mod repl;
+pub mod folding;
+pub mod markdown;
+pub mod indent;
+pub mod undo;
+pub mod export;
+pub mod processes;
+pub mod signals;
+pub mod recovery;
+pub mod cmdline;
+pub mod history;
+pub mod fuzzy;
+pub mod whitespace;
+pub mod git;
+pub mod marks;
+pub mod session;
+#[cfg(feature = "tree-sitter")]
+pub mod syntax;
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+#[macro_export]
+macro_rules! log {
+    ($($arg:tt)*) => {{
+        $crate::logger::LOGGER
+            .get_or_init(|| $crate::logger::Logger::new())
+            .log(format!($($arg)*));
+    }};
+}
+
+pub struct KeyRepeatState {
+    pub last_movement: Option<HashMap<crate::types::Key, Instant>>,
+}
@@ -0,0 +1,144 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkTarget {
+    Url(String),
+    File { path: String, row: Option<usize>, col: Option<usize> },
+}
+
+/// A link found inside a line. `start` and `end` are char columns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub start: usize,
+    pub end: usize,
+    pub target: LinkTarget,
+}
+
+impl Link {
+    pub fn contains(&self, col: usize) -> bool {
+        col >= self.start && col < self.end
+    }
+}
+
+fn url_regex() -> &'static Regex {
+    static URL: OnceLock<Regex> = OnceLock::new();
+    URL.get_or_init(|| Regex::new(r#"\b(?:https?|ftp)://[^\s<>"'`)\]}]+"#).unwrap())
+}
+
+fn path_regex() -> &'static Regex {
+    static PATH: OnceLock<Regex> = OnceLock::new();
+    PATH.get_or_init(|| Regex::new(r"[A-Za-z0-9_.~/-]*[A-Za-z0-9_~-](?::(\d+))?(?::(\d+))?").unwrap())
+}
+
+fn char_col(line: &str, byte_idx: usize) -> usize {
+    line[..byte_idx].chars().count()
+}
+
+fn file_link(line: &str, cap: regex::Captures) -> Option<Link> {
+    let whole = cap.get(0)?;
+    let mut path = whole.as_str();
+
+    let row = cap.get(1).and_then(|m| m.as_str().parse::<usize>().ok());
+    let col = cap.get(2).and_then(|m| m.as_str().parse::<usize>().ok());
+
+    if let Some(idx) = path.find(':') {
+        path = &path[..idx];
+    }
+
+    Some(Link {
+        start: char_col(line, whole.start()),
+        end: char_col(line, whole.end()),
+        target: LinkTarget::File {
+            path: path.to_string(),
+            row: row.map(|r| r.saturating_sub(1)),
+            col: col.map(|c| c.saturating_sub(1)),
+        },
+    })
+}
+
+/// Finds all URLs and `path:line:col` references inside a line.
+/// Bare words are only treated as paths if they contain a `/` or look like `file.ext:line`.
+pub fn find_links(line: &str) -> Vec<Link> {
+    let mut links: Vec<Link> = url_regex()
+        .find_iter(line)
+        .map(|m| {
+            let url = m.as_str().trim_end_matches(['.', ',', ';', ':']);
+            let start = char_col(line, m.start());
+
+            Link {
+                start,
+                end: start + url.chars().count(),
+                target: LinkTarget::Url(url.to_string()),
+            }
+        })
+        .collect();
+
+    for cap in path_regex().captures_iter(line) {
+        let whole = cap.get(0).unwrap().as_str();
+        let is_path = whole.contains('/') || (cap.get(1).is_some() && whole.contains('.'));
+
+        if !is_path { continue }
+
+        if let Some(link) = file_link(line, cap) {
+            if links.iter().any(|l| l.start < link.end && link.start < l.end) { continue }
+            links.push(link);
+        }
+    }
+
+    links.sort_by_key(|l| l.start);
+    links
+}
+
+pub fn link_at(line: &str, col: usize) -> Option<Link> {
+    find_links(line).into_iter().find(|l| l.contains(col))
+}
+
+/// Like `link_at`, but also accepts a bare file name under the cursor.
+pub fn path_at(line: &str, col: usize) -> Option<Link> {
+    if let Some(link) = link_at(line, col) {
+        return Some(link);
+    }
+
+    path_regex()
+        .captures_iter(line)
+        .filter_map(|cap| file_link(line, cap))
+        .find(|l| l.contains(col))
+}
+
+pub fn open_url(url: &str) -> std::io::Result<()> {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(target_os = "windows") {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+
+    let mut child = std::process::Command::new(opener)
+        .arg(url)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+
+    // The opener hands the URL off and exits, waited for so it doesn't stay around as a zombie
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_ends_before_trailing_punctuation() {
+        let line = "see https://example.com/a. then";
+        let link = link_at(line, 10).unwrap();
+
+        assert_eq!(link.target, LinkTarget::Url("https://example.com/a".to_string()));
+        assert_eq!(link.end, 25);
+        assert!(link_at(line, 25).is_none());
+    }
+}
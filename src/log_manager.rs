@@ -1,5 +1,10 @@
 use std::time::{Instant, Duration};
 
+use crate::ui::card::CardType;
+
+/// Messages `:messages` can show, the oldest are dropped past this.
+const MAX_HISTORY: usize = 500;
+
 pub enum LogKind {
     Notification,
     Persistent,
@@ -9,40 +14,59 @@ pub struct TimedLog {
     pub time_created: Instant,
     pub duration: Duration,
     pub message: String,
+    pub card_type: CardType,
 }
 
 pub struct LogManager {
     pub notifications: Vec<TimedLog>,
     pub persistent: Vec<String>,
+    /// Every notification and persistent message, oldest first, kept after they are gone.
+    pub history: Vec<(CardType, String)>,
 }
 
 impl LogManager {
     pub fn new() -> Self {
         Self {
             notifications: Vec::new(),
-            persistent: Vec::new()
+            persistent: Vec::new(),
+            history: Vec::new(),
         }
     }
 
     pub fn push_notification(&mut self, msg: String, dur: Duration) {
+        self.push_notification_as(msg, dur, CardType::INFO);
+    }
+
+    /// A notification drawn as a warning or error card.
+    pub fn push_notification_as(&mut self, msg: String, dur: Duration, card_type: CardType) {
+        self.remember(card_type.clone(), msg.clone());
         self.notifications.push(TimedLog {
             message: msg,
             duration: dur,
             time_created: Instant::now(),
+            card_type,
         });
     }
 
     pub fn push_persistent(&mut self, msg: String) {
+        self.remember(CardType::ERROR, msg.clone());
         self.persistent.push(msg);
     }
 
-    pub fn drain_notifications(&mut self) -> Vec<String> {
+    fn remember(&mut self, card_type: CardType, msg: String) {
+        self.history.push((card_type, msg));
+        if self.history.len() > MAX_HISTORY {
+            self.history.drain(..self.history.len() - MAX_HISTORY);
+        }
+    }
+
+    pub fn drain_notifications(&mut self) -> Vec<(String, CardType)> {
         let now = Instant::now();
         
         let mut new = Vec::new();
         self.notifications.retain(|log| {
             if now.duration_since(log.time_created) < log.duration {
-                new.push(log.message.clone());
+                new.push((log.message.clone(), log.card_type.clone()));
                 true
             } else {
                 false 
@@ -3,8 +3,12 @@ use std::sync::Mutex;
 use std::io::Write;
 use std::sync::OnceLock;
 
+/// Lines `recent` keeps for `:messages`, the log file has all of them.
+const MAX_RECENT: usize = 500;
+
 pub struct Logger {
-    file: Mutex<File>
+    file: Mutex<File>,
+    recent: Mutex<Vec<String>>,
 }
 
 impl Logger {
@@ -16,13 +20,26 @@ impl Logger {
             .unwrap();
 
         Self {
-            file: Mutex::new(w)
+            file: Mutex::new(w),
+            recent: Mutex::new(Vec::new()),
         }
     }
 
     pub fn log(&self, message: String) {
         let mut f = self.file.lock().unwrap();
         writeln!(f, "{}", message).unwrap();
+
+        let mut recent = self.recent.lock().unwrap();
+        recent.push(message);
+        if recent.len() > MAX_RECENT {
+            let excess = recent.len() - MAX_RECENT;
+            recent.drain(..excess);
+        }
+    }
+
+    /// What was logged since the editor started, oldest first.
+    pub fn recent(&self) -> Vec<String> {
+        self.recent.lock().unwrap().clone()
     }
 }
 
@@ -13,7 +13,8 @@ pub struct LspMessage<T> {
 pub struct InitializeParams {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub capabilities: Option<InitializeClientCapabilities>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    // Null means there is no workspace, servers then only look at the opened files
+    #[serde(rename = "rootUri")]
     pub root_uri: Option<String>,
 }
 
@@ -21,12 +22,36 @@ pub struct InitializeParams {
 pub struct InitializeClientCapabilities {
     #[serde(rename = "textDocument")]
     pub text_document: Option<TextDocumentClientCapabilities>,
+    pub window: Option<WindowClientCapabilities>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WindowClientCapabilities {
+    #[serde(rename = "workDoneProgress")]
+    pub work_done_progress: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TextDocumentClientCapabilities {
     #[serde(rename = "synchronization")]
     pub synchronization: Option<TextDocumentSyncClientCapabilities>,
+    #[serde(rename = "inlayHint")]
+    pub inlay_hint: Option<InlayHintClientCapabilities>,
+    #[serde(rename = "foldingRange")]
+    pub folding_range: Option<FoldingRangeClientCapabilities>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FoldingRangeClientCapabilities {
+    // Folds are whole lines, columns would be ignored anyway
+    #[serde(rename = "lineFoldingOnly")]
+    pub line_folding_only: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InlayHintClientCapabilities {
+    #[serde(rename = "dynamicRegistration")]
+    pub dynamic_registration: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -37,6 +62,10 @@ pub struct TextDocumentSyncClientCapabilities {
     pub did_change: bool,
     #[serde(rename = "didClose")]
     pub did_close: bool,
+    #[serde(rename = "didSave")]
+    pub did_save: bool,
+    #[serde(rename = "willSaveWaitUntil")]
+    pub will_save_wait_until: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
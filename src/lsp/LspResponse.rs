@@ -1,13 +1,37 @@
 #![allow(non_snake_case)]
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Reads a capability that servers spell in many ways as `None` when it doesn't fit the
+/// expected shape, instead of failing the whole initialize response.
+fn lenient<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: for<'a> Deserialize<'a>,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value).ok())
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LspResponse<T> {
     pub jsonrpc: String,
     pub method: Option<String>,
     pub id: Option<i32>,
-    pub result: T
+    // Missing on errors and on notifications
+    #[serde(default)]
+    pub result: T,
+    // Only on notifications and requests from the server
+    #[serde(default)]
+    pub params: serde_json::Value,
+    pub error: Option<LspResponseError>
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct LspResponseError {
+    pub code: i32,
+    pub message: String
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,8 +42,36 @@ pub struct LspDiagnostics {
 }
 
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct LspDiagnosticParams {}
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct LspDiagnosticParams {
+    pub uri: String,
+    pub diagnostics: Vec<LspDiagnostic>,
+    pub version: Option<i32>
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct LspDiagnostic {
+    pub range: LspRange,
+    pub severity: Option<u8>,
+    pub message: String,
+    pub source: Option<String>
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct LspRange {
+    pub start: LspPosition,
+    pub end: LspPosition
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct LspPosition {
+    pub line: u32,
+    pub character: u32
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(untagged)]
@@ -30,6 +82,7 @@ pub enum LspSemanticResponseResult {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SemanticTokensFullData {
+    // Only servers that support deltas send one
     pub resultId: Option<String>,
     pub data: Vec<i32>,
 }
@@ -40,7 +93,8 @@ pub struct SemanticTokensDeltaData {
     pub edits: Vec<SemanticTokensDeltaEdit>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
 pub struct SemanticTokensDeltaEdit {
     pub start: u32,
     pub deleteCount: u32,
@@ -50,16 +104,20 @@ pub struct SemanticTokensDeltaEdit {
     pub data: Vec<i32>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct LspDidOpenResponseResult {}
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct LspResponseResult {
     pub capabilities: LspResponseCapabilities,
-    pub serverInfo: LspResponseServerInfo
+    #[serde(deserialize_with = "lenient")]
+    pub serverInfo: Option<LspResponseServerInfo>
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct LspResponseCapabilities {
     /*
     pub positionEncoding: String,
@@ -78,7 +136,6 @@ pub struct LspResponseCapabilities {
     pub codeActionProvider: bool,
     pub codeLensProvider: CodeLensProvider,
     pub documentFormattingProvider: bool,
-    pub documentRangeFormattingProvider: bool,
     pub documentOnTypeFormattingProvider: DocumentOnTypeFormattingProvider,
     pub renameProvider: RenameProvider,
     pub foldingRangeProvider: bool,
@@ -86,15 +143,27 @@ pub struct LspResponseCapabilities {
     pub workspace: LspWorkspace,
     pub callHierarchyProvider: bool,
     */
-    pub semanticTokensProvider: SemanticTokensProvider,
+    // Every server sends a different subset, anything missing or malformed is None
+    #[serde(deserialize_with = "lenient")]
+    pub textDocumentSync: Option<TextDocumentSyncProvider>,
+    #[serde(deserialize_with = "lenient")]
+    pub semanticTokensProvider: Option<SemanticTokensProvider>,
+    #[serde(deserialize_with = "lenient")]
+    pub inlayHintProvider: Option<InlayHintProvider>,
+    /// `true` or the formatting options.
+    pub documentRangeFormattingProvider: Option<serde_json::Value>,
+    /// `true` or the call hierarchy options.
+    pub callHierarchyProvider: Option<serde_json::Value>,
+    /// `true` or the folding range options.
+    pub foldingRangeProvider: Option<serde_json::Value>,
     /*
-    pub inlayHintProvider: InlayHintProvider,
     pub diagnosticProvider: DiagnosticProvider,
     pub experimental: LspExperimental
     */
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct LspExperimental {
     pub externalDocs: bool,
     pub hoverRange: bool,
@@ -110,12 +179,14 @@ pub struct LspExperimental {
     pub workspaceSymbolScopeKindFiltering: bool
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct LspExperimentalRunnables {
     pub kinds: Vec<String>
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct DiagnosticProvider {
     pub identifier: String,
     pub interFileDependencies: bool,
@@ -123,23 +194,56 @@ pub struct DiagnosticProvider {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct InlayHintProvider {
-    pub resolveProvider: bool,
+#[serde(untagged)]
+pub enum InlayHintProvider {
+    Boolean(bool),
+    Options { resolveProvider: Option<bool> },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct SemanticTokensProvider {
     pub legend: SemanticTokensLegend,
-    pub range: bool,
+    // `true` or an empty object
+    pub range: Option<serde_json::Value>,
+    #[serde(deserialize_with = "lenient")]
     pub full: Option<SemanticTokensFull>
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl SemanticTokensProvider {
+    /// `full: true` and `full: { ... }` both mean the whole file can be requested.
+    pub fn supports_full(&self) -> bool {
+        match &self.full {
+            Some(SemanticTokensFull::Boolean(enabled)) => *enabled,
+            Some(SemanticTokensFull::Options { .. }) => true,
+            None => false,
+        }
+    }
+
+    /// Only `full: { delta: true }` allows delta requests.
+    pub fn supports_delta(&self) -> bool {
+        matches!(self.full, Some(SemanticTokensFull::Options { delta: Some(true) }))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct SemanticTokensLegend {
+    #[serde(deserialize_with = "lenient_vec")]
     pub tokenTypes: Vec<String>,
+    #[serde(deserialize_with = "lenient_vec")]
     pub tokenModifiers: Vec<String>
 }
 
+/// Like `lenient` for lists, empty when malformed or `null`.
+fn lenient_vec<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: for<'a> Deserialize<'a>,
+{
+    Ok(lenient(deserializer)?.unwrap_or_default())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum SemanticTokensFull {
@@ -147,84 +251,105 @@ pub enum SemanticTokensFull {
     Options { delta: Option<bool> },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct LspWorkspace {
     pub workspaceFolders: LspWorkspaceFolders,
     pub fileOperations: LspWorkspaceFileOperations
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct LspWorkspaceFileOperations {
     pub willRename: LspWorkspaceWillRename
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct LspWorkspaceWillRename {
     pub filters: Vec<LspWorkspaceRenameFilter>
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct LspWorkspaceRenameFilter {
     pub scheme: String,
     pub pattern: LspWorkspaceRenamePattern 
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct LspWorkspaceRenamePattern {
     pub glob: String,
     pub matches: String
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct LspWorkspaceFolders {
     pub supported: bool,
     pub changeNotifications: bool
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct RenameProvider {
     pub prepareProvider: bool
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct DocumentOnTypeFormattingProvider {
     pub firstTriggerCharacter: String,
     pub moreTriggerCharacter: Vec<String>
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct CodeLensProvider {
     pub resolveProvider: bool
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct SignatureHelpProvider {
     pub triggerCharacters: Vec<String>
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct TextDocumentSync {
     pub openClose: bool,
     pub change: i32,
-    pub save: TextDocumentSyncSave
+    // `true` or `{ includeText }`
+    pub save: Option<serde_json::Value>,
+    pub willSaveWaitUntil: bool
 }
 
+/// Servers send either just the change kind or the full options.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct TextDocumentSyncSave {}
+#[serde(untagged)]
+pub enum TextDocumentSyncProvider {
+    Kind(i32),
+    Options(TextDocumentSync),
+}
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct CompletionProvider {
     pub resolveProvider: bool,
     pub triggerCharacters: Vec<String>,
     pub completionItem: CompletionItem
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct CompletionItem {
     pub labelDetailsSupport: bool
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct LspResponseServerInfo {
     pub name: String,
     pub version: String
@@ -2,31 +2,17 @@
 
 use std::io;
 use std::env;
-use std::io::Write;
+use std::io::{Read, Write};
 use std::panic;
 use std::sync::Arc;
-
-pub mod app;
-pub mod types;
-pub mod highlighter;
-pub mod editor;
-pub mod plugins;
-pub mod lsp;
-pub mod buffer;
-pub mod renderer;
-pub mod input;
-pub mod services;
-pub mod ui;
-pub mod log_manager;
-pub mod command;
-pub mod keymap;
-pub mod logger;
+use std::rc::Rc;
+use std::cell::Cell;
 
 use crossterm::cursor;
 use crossterm::terminal;
 use crossterm::terminal::EndSynchronizedUpdate;
 use crossterm::ExecutableCommand;
-use app::App;
+use oxidy::app::App;
 
 use wgpu::CompositeAlphaMode;
 use wgpu_glyph::{GlyphBrushBuilder, Section, Text, ab_glyph};
@@ -35,33 +21,22 @@ use winit::event::ElementState;
 use winit::event::Ime;
 use winit::keyboard::Key::Character;
 
-use crate::input::{InputHandler, CrosstermInput, WgpuInput};
-use crate::renderer::Renderer;
-use crate::renderer::wgpu::renderer::WgpuRenderer;
-use crate::renderer::crossterm::CrossTermRenderer;
-use crate::types::{Size, EditorAction, Direction, Key};
-
-use crate::editor::Editor;
-use crate::plugins::config::Config;
-use crate::ui::ui_manager::UiManager;
-
-#[macro_export]
-macro_rules! log {
-    ($($arg:tt)*) => {{
-        $crate::logger::LOGGER
-            .get_or_init(|| $crate::logger::Logger::new())
-            .log(format!($($arg)*));
-    }};
-}
+use oxidy::services::remote_service::{self, RemoteRequest};
+use oxidy::{encoding, session, signals};
+use oxidy::input::{InputHandler, InputEvent, MouseType, CrosstermInput, WgpuInput};
+use oxidy::renderer::Renderer;
+use oxidy::renderer::wgpu::renderer::WgpuRenderer;
+use oxidy::renderer::crossterm::CrossTermRenderer;
+use oxidy::types::{Size, EditorAction, Direction, Key};
+
+use oxidy::editor::Editor;
+use oxidy::plugins::config::Config;
+use oxidy::ui::ui_manager::UiManager;
 
 use std::time::{Instant, Duration};
 use std::collections::HashMap;
 
-struct KeyRepeatState {
-    last_movement: Option<HashMap<crate::types::Key, Instant>>,
-}
-
-fn gui_main(file_paths: Vec<String>) -> io::Result<()> {
+fn gui_main(cli: CliArgs) -> io::Result<i32> {
     env_logger::init();
 
     let event_loop = winit::event_loop::EventLoop::new().unwrap();
@@ -87,9 +62,22 @@ fn gui_main(file_paths: Vec<String>) -> io::Result<()> {
     
     let mut app = App::new(size, Box::new(wgpu_renderer), input);
 
-    if let Some(input_file) = file_paths.first() {
-        app.open_file(input_file.clone());
-    }
+    open_initial_file(&mut app, &cli);
+
+    let exit_code = Rc::new(Cell::new(0));
+    let loop_exit_code = exit_code.clone();
+
+    // The loop sleeps until the next event, wake it up when SIGTERM arrives
+    let proxy = event_loop.create_proxy();
+    std::thread::spawn(move || {
+        while !signals::terminate_requested() {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        let _ = proxy.send_event(());
+    });
+
+    let mut mouse_position = (0.0, 0.0);
+    let mut mouse_button: Option<oxidy::input::MouseButton> = None;
 
     event_loop
         .run(move |event, elwt| {
@@ -97,7 +85,14 @@ fn gui_main(file_paths: Vec<String>) -> io::Result<()> {
                 winit::event::Event::WindowEvent {
                     event: winit::event::WindowEvent::CloseRequested,
                     ..
-                } => elwt.exit(),
+                } => {
+                    app.shutdown();
+                    loop_exit_code.set(app.exit_code);
+                    elwt.exit();
+                }
+                winit::event::Event::UserEvent(()) => {
+                    window.request_redraw();
+                }
                 winit::event::Event::WindowEvent {
                     event: winit::event::WindowEvent::Resized(new_size),
                     ..
@@ -108,29 +103,91 @@ fn gui_main(file_paths: Vec<String>) -> io::Result<()> {
                             rows: new_size.height as u16
                         }
                     );
-
-                    if let Some(wgpu_renderer) = app.renderer.as_any_mut().downcast_mut::<WgpuRenderer>() {
-                        wgpu_renderer.surface.configure(
-                            &wgpu_renderer.device,
-                            &wgpu::SurfaceConfiguration {
-                                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                                format: wgpu_renderer.render_format,
-                                width: wgpu_renderer.size.width,
-                                height: wgpu_renderer.size.height,
-                                present_mode: wgpu::PresentMode::AutoVsync,
-                                alpha_mode: CompositeAlphaMode::Auto,
-                                view_formats: vec![wgpu_renderer.render_format],
-                                desired_maximum_frame_latency: 2,
-                            },
-                        );
+                }
+                winit::event::Event::WindowEvent {
+                    event: winit::event::WindowEvent::Focused(false),
+                    ..
+                } => {
+                    app.handle_focus_lost();
+                    window.request_redraw();
+                }
+                winit::event::Event::WindowEvent {
+                    event: winit::event::WindowEvent::Focused(true),
+                    ..
+                } => {
+                    app.handle_focus_gained();
+                    window.request_redraw();
+                }
+                winit::event::Event::AboutToWait => {
+                    // Wake up again once pending edits are due to be auto-saved or sent to the server
+                    match app.next_deadline() {
+                        Some(deadline) if Instant::now() >= deadline => window.request_redraw(),
+                        Some(deadline) => elwt.set_control_flow(winit::event_loop::ControlFlow::WaitUntil(deadline)),
+                        // Check for files sent with `oxidy --remote` every now and then
+                        None if app.remote.is_some() => elwt.set_control_flow(
+                            winit::event_loop::ControlFlow::WaitUntil(Instant::now() + Duration::from_millis(250))
+                        ),
+                        None => elwt.set_control_flow(winit::event_loop::ControlFlow::Wait),
+                    }
+                }
+                winit::event::Event::NewEvents(winit::event::StartCause::ResumeTimeReached { .. }) => {
+                    if app.remote.is_some() {
+                        window.request_redraw();
                     }
-                    
                 }
                 winit::event::Event::WindowEvent {
                     event: winit::event::WindowEvent::RedrawRequested,
                     ..
                 } => {
-                    app.step();
+                    let startup_pending = app.startup_pending;
+                    if !app.step() {
+                        loop_exit_code.set(app.exit_code);
+                        elwt.exit();
+                        return;
+                    }
+
+                    // Show the loaded config right away
+                    if startup_pending {
+                        window.request_redraw();
+                    }
+                }
+                winit::event::Event::WindowEvent {
+                    event: winit::event::WindowEvent::CursorMoved { position, .. },
+                    ..
+                } => {
+                    mouse_position = (position.x, position.y);
+
+                    if let Some(button) = mouse_button {
+                        let (col, row) = gui_cell_at(&mut app, mouse_position);
+                        app.handle_input(InputEvent::Mouse(MouseType::Drag(button, col, row), oxidy::types::Modifiers::default()));
+                        window.request_redraw();
+                    }
+                }
+                winit::event::Event::WindowEvent {
+                    event: winit::event::WindowEvent::MouseInput { state, button, .. },
+                    ..
+                } => {
+                    let button = match button {
+                        winit::event::MouseButton::Left => oxidy::input::MouseButton::Left,
+                        winit::event::MouseButton::Right => oxidy::input::MouseButton::Right,
+                        winit::event::MouseButton::Middle => oxidy::input::MouseButton::Middle,
+                        _ => return,
+                    };
+
+                    let (col, row) = gui_cell_at(&mut app, mouse_position);
+                    let kind = match state {
+                        ElementState::Pressed => {
+                            mouse_button = Some(button);
+                            MouseType::Down(button, col, row)
+                        }
+                        ElementState::Released => {
+                            mouse_button = None;
+                            MouseType::Up(button, col, row)
+                        }
+                    };
+
+                    app.handle_input(InputEvent::Mouse(kind, oxidy::types::Modifiers::default()));
+                    window.request_redraw();
                 }
                 winit::event::Event::WindowEvent {
                     event: winit::event::WindowEvent::KeyboardInput { event: input_data, .. },
@@ -158,14 +215,14 @@ fn gui_main(file_paths: Vec<String>) -> io::Result<()> {
 
                     match input_data.state {
                         ElementState::Pressed => {
-                            let modifiers = crate::types::Modifiers {
+                            let modifiers = oxidy::types::Modifiers {
                                 shift: false,
                                 ctrl: false,
                                 alt: false,
                                 super_key: false,
                             };
 
-                            let input = crate::input::InputEvent::Key {
+                            let input = oxidy::input::InputEvent::Key {
                                 key,
                                 modifiers
                             };
@@ -190,7 +247,16 @@ fn gui_main(file_paths: Vec<String>) -> io::Result<()> {
         })
         .unwrap();
 
-    Ok(())
+    Ok(exit_code.get())
+}
+
+fn gui_cell_at(app: &mut App, position: (f64, f64)) -> (u16, u16) {
+    let editor = &app.editor;
+
+    match app.renderer.as_any_mut().downcast_mut::<WgpuRenderer>() {
+        Some(renderer) => renderer.cell_at(editor, position.0, position.1),
+        None => (0, 0),
+    }
 }
 
 fn map_winit_key(key: &winit::keyboard::Key) -> Option<Key> {
@@ -222,46 +288,160 @@ fn map_winit_key(key: &winit::keyboard::Key) -> Option<Key> {
 }
 
 
-fn tui_main(file_paths: Vec<String>) -> io::Result<()> {
+fn tui_main(cli: CliArgs) -> io::Result<i32> {
     let term_size = terminal::size().expect("Size could not be determined.");
     let size = Size { cols: term_size.0, rows: term_size.1 };
         
     let input = Box::new(CrosstermInput::new());
 
-    let renderer = Box::new(CrossTermRenderer::new(size.clone()));
+    let renderer = Box::new(CrossTermRenderer::new(size.clone())?);
 
     let mut app = App::new(size, renderer, input);
 
-    if let Some(input_file) = file_paths.first() {
+    open_initial_file(&mut app, &cli);
+    app.run();
+
+    Ok(app.exit_code)
+}
+
+/// Passes the files to a running instance. Returns false if there is none to take them.
+fn send_to_remote(cli: &CliArgs) -> bool {
+    if cli.files.is_empty() { return false }
+
+    let requests: Vec<RemoteRequest> = cli.files.iter()
+        .enumerate()
+        .map(|(i, path)| RemoteRequest {
+            path: path.clone(),
+            line: if i == 0 { cli.line } else { None },
+            col: if i == 0 { cli.col } else { None },
+        })
+        .collect();
+
+    remote_service::send(&requests).is_ok()
+}
+
+fn open_initial_file(app: &mut App, cli: &CliArgs) {
+    if cli.remote {
+        app.start_remote();
+    }
+
+    if let Some(content) = &cli.stdin {
+        app.open_scratch(content.clone());
+    } else if let Some(input_file) = cli.files.first() {
         app.open_file(input_file.clone());
+        app.editor.set_readonly(cli.readonly);
+
+        if let Some(line) = cli.line {
+            let col = cli.col.unwrap_or(1);
+            app.editor.set_cursor(line.saturating_sub(1), col.saturating_sub(1));
+            app.editor.center_cursor();
+        }
     }
-    app.run();
 
-    Ok(())
+    app.startup_commands = cli.commands.clone();
 }
 
 struct CliArgs {
     gui: bool,
+    readonly: bool,
+    // Open the files in an already running instance, or become the one others open files in
+    remote: bool,
+    // Attach to a detached session, the newest or the one of the given pid
+    attach: Option<Option<u32>>,
+    // Piped input when started as `oxidy -`
+    stdin: Option<String>,
+    // Ex commands from `+cmd` and `-c cmd`, run after the file is opened
+    commands: Vec<String>,
     files: Vec<String>,
+    // 1-based position to open the first file at
+    line: Option<usize>,
+    col: Option<usize>,
 }
 
 fn parse_args() -> CliArgs {
     let mut gui = false;
+    let mut readonly = false;
+    let mut remote = false;
+    let mut attach = None;
+    let mut stdin = None;
+    let mut commands = Vec::new();
     let mut files = Vec::new();
+    let mut line = None;
+    let mut col = None;
 
-    let mut args = std::env::args().skip(1); // skip program name
+    let mut args = std::env::args().skip(1).peekable(); // skip program name
 
     while let Some(arg) = args.next() {
         match arg.as_str() {
             "-g" | "--gui" => gui = true,
+            "-R" | "--readonly" => readonly = true,
+            "--remote" => remote = true,
+            "--attach" => attach = Some(args.next_if(|next| next.parse::<u32>().is_ok()).and_then(|pid| pid.parse().ok())),
+            "-" => {
+                // Read everything before the terminal is put into raw mode.
+                // Crossterm falls back to /dev/tty for input once stdin is not a terminal.
+                let mut bytes = Vec::new();
+                if let Err(error) = io::stdin().read_to_end(&mut bytes) {
+                    eprintln!("Could not read stdin: {}", error);
+                }
+                stdin = Some(encoding::decode(&bytes).0);
+            }
+            // A lone "+" jumps to the last line
+            "+" => line = Some(usize::MAX),
+            _ if arg.starts_with('+') => {
+                match arg[1..].parse::<usize>() {
+                    Ok(n) => line = Some(n),
+                    Err(_) => commands.push(arg[1..].to_string()),
+                }
+            }
+            "-c" => {
+                match args.next() {
+                    Some(command) => commands.push(command),
+                    None => eprintln!("Missing command after -c"),
+                }
+            }
             _ if arg.starts_with('-') => {
                 eprintln!("Unknown option: {}", arg);
             }
-            _ => files.push(arg),
+            _ => {
+                let (path, file_line, file_col) = split_file_position(&arg);
+                if files.is_empty() && file_line.is_some() {
+                    line = file_line;
+                    col = file_col;
+                }
+                files.push(path);
+            }
         }
     }
 
-    CliArgs { gui, files }
+    CliArgs { gui, readonly, remote, attach, stdin, commands, files, line, col }
+}
+
+/// Splits "path:line:col" (as printed by compilers) into its parts.
+/// Arguments naming an existing file are never split.
+fn split_file_position(arg: &str) -> (String, Option<usize>, Option<usize>) {
+    if std::path::Path::new(arg).exists() {
+        return (arg.to_string(), None, None);
+    }
+
+    let mut path = arg.trim_end_matches(':');
+    let mut numbers = Vec::new();
+
+    while numbers.len() < 2 {
+        match path.rsplit_once(':') {
+            Some((rest, n)) if !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()) => {
+                numbers.insert(0, n.parse::<usize>().ok());
+                path = rest;
+            }
+            _ => break,
+        }
+    }
+
+    if numbers.is_empty() {
+        return (arg.to_string(), None, None);
+    }
+
+    (path.to_string(), numbers[0], numbers.get(1).copied().flatten())
 }
 
 // Oxidy comment
@@ -298,8 +478,27 @@ fn main() -> io::Result<()> {
         }
     }));
 
-    if cli.gui { gui_main(cli.files)?; }
-    else { tui_main(cli.files)?; }
+    if cli.remote && send_to_remote(&cli) {
+        return Ok(());
+    }
+
+    signals::install();
+
+    if let Some(pid) = cli.attach {
+        if let Err(error) = session::attach(pid) {
+            eprintln!("Could not attach: {}", error);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let exit_code = if cli.gui { gui_main(cli)? }
+    else { tui_main(cli)? };
+
+    // Everything has been dropped by now, so the terminal and LSP are already cleaned up
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
 
     Ok(())
 }
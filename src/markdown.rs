@@ -0,0 +1,299 @@
+/// The bullet or number a list item starts with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Marker {
+    Bullet(char),
+    /// The number and the character after it, '.' or ')'.
+    Ordered(usize, char),
+}
+
+/// The prefix of a list item like "  - [ ] " or "3. ".
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListItem {
+    pub indent: String,
+    pub marker: Marker,
+    pub checkbox: Option<bool>,
+    /// Byte offset of the text after the marker and checkbox.
+    pub content: usize,
+}
+
+impl ListItem {
+    /// What the next item of the same list starts with. New checkboxes start unchecked.
+    pub fn continuation(&self) -> String {
+        let marker = match self.marker {
+            Marker::Bullet(bullet) => bullet.to_string(),
+            Marker::Ordered(number, delimiter) => format!("{}{}", number + 1, delimiter),
+        };
+
+        match self.checkbox {
+            Some(_) => format!("{}{} [ ] ", self.indent, marker),
+            None => format!("{}{} ", self.indent, marker),
+        }
+    }
+}
+
+pub fn list_item(line: &str) -> Option<ListItem> {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+
+    let (marker, rest) = match trimmed.chars().next()? {
+        bullet @ ('-' | '*' | '+') => (Marker::Bullet(bullet), &trimmed[1..]),
+        _ => {
+            let digits = trimmed.chars().take_while(|ch| ch.is_ascii_digit()).count();
+            if digits == 0 || digits > 9 { return None }
+
+            let delimiter = trimmed[digits..].chars().next().filter(|ch| *ch == '.' || *ch == ')')?;
+            (Marker::Ordered(trimmed[..digits].parse().ok()?, delimiter), &trimmed[digits + 1..])
+        }
+    };
+
+    // "---" is a rule and "*word*" emphasis, a marker is followed by a space
+    if !rest.is_empty() && !rest.starts_with(' ') { return None }
+    let rest = rest.strip_prefix(' ').unwrap_or(rest);
+
+    let (checkbox, rest) = match rest.get(..3) {
+        Some("[ ]") | Some("[x]") | Some("[X]") if rest.len() == 3 || rest[3..].starts_with(' ') => {
+            let after = &rest[3..];
+            (Some(&rest[1..2] != " "), after.strip_prefix(' ').unwrap_or(after))
+        }
+        _ => (None, rest),
+    };
+
+    Some(ListItem {
+        indent: indent.to_string(),
+        marker,
+        checkbox,
+        content: line.len() - rest.len(),
+    })
+}
+
+/// Checks or unchecks the item's checkbox, adding one to items without.
+/// Returns None if the line isn't a list item.
+pub fn toggle_checkbox(line: &str) -> Option<String> {
+    let item = list_item(line)?;
+
+    let (before, after) = line.split_at(item.content);
+
+    Some(match item.checkbox {
+        Some(checked) => {
+            // The box is the "[ ]" right before the content, possibly followed by a space
+            let start = before.trim_end_matches(' ').len() - 3;
+            let mark = if checked { ' ' } else { 'x' };
+            format!("{}[{}]{}{}", &line[..start], mark, &before[start + 3..], after)
+        }
+        None => format!("{}[ ] {}", before, after),
+    })
+}
+
+fn ordered_number(line: &str, indent: &str) -> Option<usize> {
+    match list_item(line) {
+        Some(ListItem { indent: item_indent, marker: Marker::Ordered(number, _), .. }) if item_indent == indent => Some(number),
+        _ => None,
+    }
+}
+
+/// Renumbers the ordered list around `row` so it counts up from its first item.
+/// Lines indented deeper than the items, like nested lists, belong to the list.
+/// Returns the lines that changed with their new text.
+pub fn renumber(lines: &[String], row: usize) -> Vec<(usize, String)> {
+    let indent = match lines.get(row).and_then(|line| list_item(line)) {
+        Some(ListItem { indent, marker: Marker::Ordered(..), .. }) => indent,
+        _ => return vec![],
+    };
+
+    let belongs = |line: &str| {
+        ordered_number(line, &indent).is_some()
+            || (!line.trim().is_empty() && line.len() - line.trim_start().len() > indent.len())
+    };
+
+    let mut start = row;
+    while start > 0 && belongs(&lines[start - 1]) { start -= 1 }
+
+    let mut end = row;
+    while end + 1 < lines.len() && belongs(&lines[end + 1]) { end += 1 }
+
+    let mut next = None;
+    let mut changed = vec![];
+
+    for (i, line) in lines.iter().enumerate().take(end + 1).skip(start) {
+        let number = match ordered_number(line, &indent) {
+            Some(number) => number,
+            None => continue
+        };
+
+        let expected = *next.get_or_insert(number);
+        next = Some(expected + 1);
+
+        if number != expected {
+            let digits = &line[indent.len()..];
+            let digits_len = digits.chars().take_while(|ch| ch.is_ascii_digit()).count();
+            changed.push((i, format!("{}{}{}", indent, expected, &digits[digits_len..])));
+        }
+    }
+
+    changed
+}
+
+/// Level of a "# Heading" line, 1 to 6.
+pub fn heading_level(line: &str) -> Option<usize> {
+    let level = line.chars().take_while(|ch| *ch == '#').count();
+    let rest = &line[level..];
+
+    if (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' ')) {
+        Some(level)
+    } else {
+        None
+    }
+}
+
+/// All headings with their level, skipping "#" lines inside code fences.
+pub fn headings(lines: &[String]) -> Vec<(usize, usize)> {
+    let mut headings = vec![];
+    let mut in_fence = false;
+
+    for (row, line) in lines.iter().enumerate() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+
+        if in_fence { continue }
+
+        if let Some(level) = heading_level(line) {
+            headings.push((row, level));
+        }
+    }
+
+    headings
+}
+
+/// The section `row` is in: its heading up to the next heading of the same or a higher level.
+/// Blank lines at the end stay visible when it is folded.
+pub fn heading_section(lines: &[String], row: usize) -> Option<(usize, usize)> {
+    let headings = headings(lines);
+
+    let index = headings.iter().rposition(|(heading_row, _)| *heading_row <= row)?;
+    let (start, level) = headings[index];
+
+    let mut end = headings[index + 1..].iter()
+        .find(|(_, other)| *other <= level)
+        .map(|(next, _)| next - 1)
+        .unwrap_or(lines.len().saturating_sub(1));
+
+    while end > start && lines[end].trim().is_empty() { end -= 1 }
+
+    if end > start { Some((start, end)) } else { None }
+}
+
+/// How a character is drawn in the preview.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewStyle {
+    Plain,
+    Heading(usize),
+    Strong,
+    Emphasis,
+    /// Inline code and code blocks.
+    Code,
+    /// List bullets and numbers, and the bar of a quote.
+    Marker,
+    Quote,
+}
+
+/// Whether each row is a code fence or inside one.
+pub fn code_rows(lines: &[String]) -> Vec<bool> {
+    let mut in_fence = false;
+
+    lines.iter()
+        .map(|line| {
+            let fence = line.trim_start().starts_with("```");
+            if fence { in_fence = !in_fence }
+            fence || in_fence
+        })
+        .collect()
+}
+
+/// What `line` shows as in the preview: the text without its markup, each character with
+/// its style. `code` is true for rows of a code block, they are shown as they are.
+pub fn preview_line(line: &str, code: bool) -> Vec<(char, PreviewStyle)> {
+    if code {
+        return line.chars().map(|ch| (ch, PreviewStyle::Code)).collect();
+    }
+
+    if let Some(level) = heading_level(line) {
+        let text = line[level..].trim_start();
+        return text.chars().map(|ch| (ch, PreviewStyle::Heading(level))).collect();
+    }
+
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+
+    if let Some(quoted) = trimmed.strip_prefix('>') {
+        let mut out: Vec<(char, PreviewStyle)> = line[..indent].chars().map(|ch| (ch, PreviewStyle::Plain)).collect();
+        out.push(('│', PreviewStyle::Marker));
+        out.extend(inline(quoted, PreviewStyle::Quote));
+        return out;
+    }
+
+    if let Some(item) = list_item(line) {
+        let mut out: Vec<(char, PreviewStyle)> = item.indent.chars().map(|ch| (ch, PreviewStyle::Plain)).collect();
+        let marker = match item.marker {
+            Marker::Bullet(_) => "•".to_string(),
+            Marker::Ordered(number, delimiter) => format!("{}{}", number, delimiter),
+        };
+        let checkbox = match item.checkbox {
+            Some(true) => " ☑",
+            Some(false) => " ☐",
+            None => "",
+        };
+
+        out.extend(format!("{}{} ", marker, checkbox).chars().map(|ch| (ch, PreviewStyle::Marker)));
+        out.extend(inline(&line[item.content..], PreviewStyle::Plain));
+        return out;
+    }
+
+    inline(line, PreviewStyle::Plain)
+}
+
+/// Styles `**strong**`, `*emphasis*` (or with `_`) and `` `code` `` spans of `text` and drops
+/// their delimiters. Delimiters without a closing one, and `_` inside words, are kept as text.
+fn inline(text: &str, base: PreviewStyle) -> Vec<(char, PreviewStyle)> {
+    let mut out: Vec<(char, PreviewStyle)> = vec![];
+    let mut rest = text;
+
+    while let Some(ch) = rest.chars().next() {
+        let in_word = out.last().is_some_and(|(previous, _)| previous.is_alphanumeric());
+
+        let delimiter = match ch {
+            '`' => "`",
+            '*' if rest.starts_with("**") => "**",
+            '_' if in_word => "",
+            '_' if rest.starts_with("__") => "__",
+            '*' | '_' => &rest[..1],
+            _ => "",
+        };
+
+        let closed = match delimiter {
+            "" => None,
+            _ => rest[delimiter.len()..].find(delimiter).filter(|end| *end > 0),
+        };
+
+        match closed {
+            Some(end) => {
+                let style = match delimiter {
+                    "`" => PreviewStyle::Code,
+                    "**" | "__" => PreviewStyle::Strong,
+                    _ => PreviewStyle::Emphasis,
+                };
+                let span = &rest[delimiter.len()..delimiter.len() + end];
+
+                out.extend(span.chars().map(|ch| (ch, style)));
+                rest = &rest[2 * delimiter.len() + end..];
+            }
+            None => {
+                out.push((ch, base));
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+    }
+
+    out
+}
@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// An uppercase mark, it leads to its file from any buffer and outlives the session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GlobalMark {
+    pub path: String,
+    pub row: usize,
+    pub col: usize,
+}
+
+/// Where the global marks are kept between sessions.
+pub fn path() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("oxidy/marks.json")
+}
+
+/// The global marks of the last session, none if they can't be read.
+pub fn load() -> HashMap<char, GlobalMark> {
+    std::fs::read_to_string(path())
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(marks: &HashMap<char, GlobalMark>) -> std::io::Result<()> {
+    let path = path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    std::fs::write(path, serde_json::to_string_pretty(marks)?)
+}
@@ -5,6 +5,8 @@ use crate::plugins::options::Options;
 use crate::plugins::statusbar::StatusBarConfig;
 use crate::plugins::theme::Theme;
 use crate::plugins::lsp::LspConfig;
+use crate::plugins::encryption::EncryptionConfig;
+use crate::plugins::keymap::KeymapConfig;
 
 use crate::log;
 
@@ -14,8 +16,11 @@ pub struct Config {
     pub theme: Option<String>,
     pub themes: HashMap<String, Theme>,
     pub lsps: HashMap<String, LspConfig>,
-    pub keymap: HashMap<String, String>,
+    pub keymap: KeymapConfig,
     pub statusbar: Option<StatusBarConfig>,
+    pub encryption: Option<EncryptionConfig>,
+    // Scripts to load like the ones in plugins/, relative to the config directory
+    pub imports: Vec<String>,
     // pub syntax: HashMap<String, SyntaxConfig>,
 }
 
@@ -29,13 +34,42 @@ impl Config {
             themes: self.themes.clone(),
             lsps: self.lsps.clone(),
             keymap: self.keymap.clone(),
-            statusbar: self.statusbar.clone()
+            statusbar: self.statusbar.clone(),
+            encryption: self.encryption.clone().or(base.encryption.clone()),
+            imports: self.imports.clone(),
         }
     }
 
+    /// The language server for files with `extension` and the name it is configured under.
+    pub fn lsp_for(&self, extension: &str) -> Option<(&String, &LspConfig)> {
+        if extension.is_empty() { return None }
+
+        self.lsps.iter()
+            .find(|(_, lsp)| lsp.extensions.iter().any(|ext| ext == extension))
+            // Older configs only named the entry after the extension
+            .or_else(|| self.lsps.get_key_value(extension))
+    }
+
+    /// The themes of the config and the built-in ones, sorted.
+    pub fn theme_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.themes.keys().cloned()
+            .chain(Theme::BUILTIN.iter().map(|name| name.to_string()))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
     pub fn current_theme(&self) -> Theme {
-        let current = self.themes.get(self.theme.as_ref().unwrap());
-        return current.cloned().unwrap_or_default();
+        let name = self.theme.as_deref().unwrap_or_default();
+        let current = self.themes.get(name).cloned()
+            .or_else(|| Theme::builtin(name))
+            .unwrap_or_default();
+
+        match self.opt.enforce_contrast.unwrap_or(false) {
+            true => current.with_min_contrast(self.opt.min_contrast.unwrap_or(4.5)),
+            false => current,
+        }
     }
 }
 
@@ -45,13 +79,40 @@ impl Default for Config {
             opt: Options {
                 relative_numbers: Some(false),
                 natural_scroll: Some(false),
-                tab_size: Some(2)
+                tab_size: Some(2),
+                auto_save: Some(false),
+                auto_save_delay: Some(1000),
+                render_latency: Some(0),
+                frame_budget: Some(16),
+                cache_budget: Some(8192),
+                primary_selection: Some(true),
+                format_on_save: Some(false),
+                inlay_hints: Some(true),
+                diagnostic_text: Some(true),
+                paste_indent: Some(true),
+                paste_format: Some(false),
+                min_contrast: Some(4.5),
+                enforce_contrast: Some(false),
+                escape_sequence: Some(String::new()),
+                sequence_timeout: Some(300),
+                hangup: Some("exit".to_string()),
+                cursor_color: Some(true),
+                cursor_blink: Some(true),
+                cell_cursor: Some("auto".to_string()),
+                cursorline: Some(false),
+                list: Some(false),
+                strip_whitespace: Some(false),
+                git_signs: Some(true),
+                // Detected by the terminal frontend
+                true_color: None
             },
             theme: Some("".to_string()),
             themes: HashMap::new(),
             lsps: HashMap::new(),
-            keymap: HashMap::new(),
-            statusbar: Some(StatusBarConfig::default())
+            keymap: KeymapConfig::default(),
+            statusbar: Some(StatusBarConfig::default()),
+            encryption: None,
+            imports: vec![],
         }
     }
 }
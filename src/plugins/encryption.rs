@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct EncryptionConfig {
+    // Glob patterns like "*.secret" or "*/secrets/*"
+    pub patterns: Vec<String>,
+    // "gpg" or "age"
+    pub tool: String,
+    pub identity: Option<String>,
+    // Keys files are encrypted to, without them gpg encrypts with the passphrase
+    pub recipients: Option<Vec<String>>
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use rhai::{serde::{from_dynamic, to_dynamic}, Dynamic, EvalAltResult, ImmutableString, Map};
+
+use crate::plugins::lsp::language_id;
+use crate::plugins::options::Options;
+use crate::plugins::theme::Theme;
+
+/// The theme of a file type, another theme by name or colors over the current one.
+#[derive(Clone, Debug)]
+pub enum FiletypeTheme {
+    Named(String),
+    Colors(Theme),
+}
+
+/// What `filetype("rust", |ft| { ... })` sets for buffers of that file type, over the config.
+#[derive(Clone, Debug, Default)]
+pub struct FiletypeConfig {
+    pub opt: Options,
+    pub theme: Option<FiletypeTheme>,
+    /// A shell command the buffer is piped through on save instead of formatting with the
+    /// language server, like "rustfmt --edition 2024".
+    pub formatter: Option<String>,
+}
+
+impl FiletypeConfig {
+    /// Reads what the callback set, `theme` and `formatter` are taken out, the rest are options.
+    pub fn from_map(mut map: Map) -> Result<Self, Box<EvalAltResult>> {
+        let theme = match map.remove("theme") {
+            None => None,
+            Some(theme) if theme.is_string() => Some(FiletypeTheme::Named(theme.to_string())),
+            Some(theme) if theme.is_map() => Some(FiletypeTheme::Colors(from_dynamic::<Theme>(&theme)?)),
+            Some(theme) => return Err(format!("theme: expected a theme name or a map of colors, found {}", theme.type_name()).into()),
+        };
+
+        let formatter = match map.remove("formatter") {
+            None => None,
+            Some(formatter) => Some(formatter.into_string().map_err(|found| format!("formatter: expected a shell command, found {}", found))?),
+        };
+
+        let known = to_dynamic(Options::default())?.cast::<Map>();
+        if let Some(key) = map.keys().find(|key| !known.contains_key(key.as_str())) {
+            return Err(format!("Unknown option {} in filetype(...)", key).into());
+        }
+        let opt = from_dynamic::<Options>(&Dynamic::from_map(map))?;
+
+        Ok(Self { opt, theme, formatter })
+    }
+
+    /// A second block for the same file type adds to the first one.
+    pub fn merge(self, base: &FiletypeConfig) -> Self {
+        Self {
+            opt: self.opt.merge(&base.opt),
+            theme: self.theme.or(base.theme.clone()),
+            formatter: self.formatter.or(base.formatter.clone()),
+        }
+    }
+}
+
+/// The config for files with `extension`, named after the extension or its language like
+/// `filetype("rs", ...)` or `filetype("rust", ...)`. The extension wins when both are set.
+pub fn for_extension<'a>(filetypes: &'a HashMap<String, FiletypeConfig>, extension: &str) -> Option<&'a FiletypeConfig> {
+    if extension.is_empty() { return None }
+
+    filetypes.get(extension).or_else(|| filetypes.get(language_id(extension)))
+}
+
+/// What the callback of `filetype(...)` gets, `ft.tab_size = 4` sets a key of the map.
+/// Shared so the assignments outlive the call, arguments are passed by value.
+#[derive(Clone, Default)]
+pub struct FiletypeSettings(Arc<Mutex<Map>>);
+
+impl FiletypeSettings {
+    pub fn get(&mut self, key: ImmutableString) -> Dynamic {
+        self.0.lock().unwrap().get(key.as_str()).cloned().unwrap_or(Dynamic::UNIT)
+    }
+
+    pub fn set(&mut self, key: ImmutableString, value: Dynamic) {
+        self.0.lock().unwrap().insert(key.as_str().into(), value);
+    }
+
+    pub fn take(&self) -> Map {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
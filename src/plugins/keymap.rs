@@ -0,0 +1,33 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::{EditorAction, EditorMode};
+
+/// Bindings from `oxidy.keymap` per mode, keys in key notation like "<C-s>".
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct KeymapConfig {
+    pub normal: HashMap<String, String>,
+    pub insert: HashMap<String, String>,
+    pub command: HashMap<String, String>,
+}
+
+impl KeymapConfig {
+    pub fn modes(&self) -> [(EditorMode, &HashMap<String, String>); 3] {
+        [
+            (EditorMode::Normal, &self.normal),
+            (EditorMode::Insert, &self.insert),
+            (EditorMode::Command, &self.command),
+        ]
+    }
+
+    /// What a binding runs: the built-in action it names, like "jump_back", or else the
+    /// command line. A leading ":" always means a command line.
+    pub fn action(value: &str) -> EditorAction {
+        match value.strip_prefix(':') {
+            Some(line) => EditorAction::RunCommand(line.to_string()),
+            None => EditorAction::from_name(value).unwrap_or_else(|| EditorAction::RunCommand(value.to_string())),
+        }
+    }
+}
@@ -4,5 +4,42 @@ use serde::{Deserialize, Serialize};
 pub struct LspConfig {
     pub extensions: Vec<String>,
     pub command: String,
-    pub args: Vec<String>
+    pub args: Vec<String>,
+    // Sent with didOpen, guessed from the extension when missing
+    #[serde(default)]
+    pub language_id: Option<String>,
+    // Start a server for every file on its own, without a workspace to index
+    #[serde(default)]
+    pub single_file: bool
+}
+
+impl LspConfig {
+    pub fn language_id(&self, extension: &str) -> String {
+        self.language_id.clone().unwrap_or_else(|| language_id(extension).to_string())
+    }
+}
+
+/// The LSP language identifier for files with `extension`.
+pub fn language_id(extension: &str) -> &str {
+    match extension {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "javascriptreact",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "typescriptreact",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" => "cpp",
+        "cs" => "csharp",
+        "hs" => "haskell",
+        "rb" => "ruby",
+        "sh" | "bash" => "shellscript",
+        "md" => "markdown",
+        "yml" => "yaml",
+        "kt" | "kts" => "kotlin",
+        "ex" | "exs" => "elixir",
+        "tex" => "latex",
+        // go, lua, zig, java, json, toml, html, css, ... are their own extension
+        other => other,
+    }
 }
@@ -4,3 +4,13 @@ pub mod theme;
 pub mod plugin_manager;
 pub mod statusbar;
 pub mod lsp;
+pub mod encryption;
+pub mod syntax_file;
+pub mod script_api;
+pub mod keymap;
+pub mod widgets;
+pub mod validate;
+pub mod filetype;
+pub mod tasks;
+#[cfg(feature = "wasm")]
+pub mod wasm;
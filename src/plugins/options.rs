@@ -1,10 +1,60 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
 pub struct Options {
     pub relative_numbers: Option<bool>,
     pub natural_scroll: Option<bool>,
-    pub tab_size: Option<usize>
+    pub tab_size: Option<usize>,
+    pub auto_save: Option<bool>,
+    pub auto_save_delay: Option<u64>,
+    // Debug: extra milliseconds added to every frame to mimic a slow terminal
+    pub render_latency: Option<u64>,
+    // Milliseconds a frame may take before it is recorded as an overrun
+    pub frame_budget: Option<u64>,
+    // Kilobytes the highlight caches may use before old entries are evicted
+    pub cache_budget: Option<usize>,
+    // Copy mouse selections to the X11/Wayland primary selection and paste it on middle click
+    pub primary_selection: Option<bool>,
+    // Format with the language server before `w` saves
+    pub format_on_save: Option<bool>,
+    // Show types and parameter names from the language server as virtual text
+    pub inlay_hints: Option<bool>,
+    // Show the message of a diagnostic after the end of its line
+    pub diagnostic_text: Option<bool>,
+    // Re-indent pasted lines to the indentation of the line they are pasted into
+    pub paste_indent: Option<bool>,
+    // Re-indent pasted lines like `=` does, with the language server when it can
+    pub paste_format: Option<bool>,
+    // WCAG contrast ratio theme colors should have with the background, checked by :checkhealth
+    pub min_contrast: Option<f64>,
+    // Brighten or darken theme colors that fall below min_contrast
+    pub enforce_contrast: Option<bool>,
+    // Characters typed quickly in insert mode that leave it, like "jk", empty for none
+    pub escape_sequence: Option<String>,
+    // How long typing may pause within escape_sequence, in milliseconds
+    pub sequence_timeout: Option<u64>,
+    // What happens when the terminal goes away: "exit" saves and quits, "detach" keeps
+    // running, writes unsaved buffers to recovery files and waits for `oxidy --attach` from
+    // another terminal. Terminal frontends only, the GUI has no terminal to lose
+    pub hangup: Option<String>,
+    // Set the terminal cursor to the theme's Cursor color, the original comes back on exit
+    pub cursor_color: Option<bool>,
+    // Blinking cursor shapes in the terminal
+    pub cursor_blink: Option<bool>,
+    // Draw the Normal mode cursor as an inverted cell: "auto" for terminals that can't change
+    // the cursor shape, "always" or "never"
+    pub cell_cursor: Option<String>,
+    // Draw the line with the cursor on a different background
+    pub cursorline: Option<bool>,
+    // Show tabs, non-breaking spaces and trailing spaces as dim markers
+    pub list: Option<bool>,
+    // Remove whitespace at the end of lines when saving
+    pub strip_whitespace: Option<bool>,
+    // Mark lines that differ from the version staged in git in the gutter
+    pub git_signs: Option<bool>,
+    // Send 24-bit colors to the terminal, or the closest of 256 when off. Found out from
+    // COLORTERM when left out
+    pub true_color: Option<bool>
 }
 
 impl Options {
@@ -13,6 +63,30 @@ impl Options {
             relative_numbers: self.relative_numbers.or(base.relative_numbers),
             natural_scroll: self.natural_scroll.or(base.natural_scroll),
             tab_size: self.tab_size.or(base.tab_size),
+            auto_save: self.auto_save.or(base.auto_save),
+            auto_save_delay: self.auto_save_delay.or(base.auto_save_delay),
+            render_latency: self.render_latency.or(base.render_latency),
+            frame_budget: self.frame_budget.or(base.frame_budget),
+            cache_budget: self.cache_budget.or(base.cache_budget),
+            primary_selection: self.primary_selection.or(base.primary_selection),
+            format_on_save: self.format_on_save.or(base.format_on_save),
+            inlay_hints: self.inlay_hints.or(base.inlay_hints),
+            diagnostic_text: self.diagnostic_text.or(base.diagnostic_text),
+            paste_indent: self.paste_indent.or(base.paste_indent),
+            paste_format: self.paste_format.or(base.paste_format),
+            min_contrast: self.min_contrast.or(base.min_contrast),
+            enforce_contrast: self.enforce_contrast.or(base.enforce_contrast),
+            escape_sequence: self.escape_sequence.clone().or(base.escape_sequence.clone()),
+            sequence_timeout: self.sequence_timeout.or(base.sequence_timeout),
+            hangup: self.hangup.clone().or(base.hangup.clone()),
+            cursor_color: self.cursor_color.or(base.cursor_color),
+            cursor_blink: self.cursor_blink.or(base.cursor_blink),
+            cell_cursor: self.cell_cursor.clone().or(base.cell_cursor.clone()),
+            cursorline: self.cursorline.or(base.cursorline),
+            list: self.list.or(base.list),
+            strip_whitespace: self.strip_whitespace.or(base.strip_whitespace),
+            git_signs: self.git_signs.or(base.git_signs),
+            true_color: self.true_color.or(base.true_color),
         }
     }
 }
@@ -1,16 +1,50 @@
 use std::{
-    fs::{write, File}, io::{self, Read, Result}, path::PathBuf, sync::mpsc::{self, Receiver}, thread
+    fs::{self, write, File}, io::{self, Read, Result}, path::PathBuf, sync::mpsc::{self, Receiver}, thread
 };
 use std::sync::{Arc, Mutex};
 use crossterm::style::Color;
 use notify::{Event, EventKind, RecursiveMode, Watcher};
-use rhai::{module_resolvers::FileModuleResolver, serde::{from_dynamic, to_dynamic}, Dynamic, Engine, FnPtr, NativeCallContext, Scope};
+use rhai::{module_resolvers::FileModuleResolver, serde::{from_dynamic, to_dynamic}, CallFnOptions, Dynamic, Engine, EvalAltResult, FnPtr, Map, NativeCallContext, Scope};
 
 use std::collections::HashMap;
 
 use crate::buffer::Buffer;
 use crate::plugins::config::Config;
 use crate::plugins::theme::Theme;
+use crate::plugins::syntax_file::SyntaxFile;
+use crate::plugins::script_api::{self, EditorApi};
+use crate::plugins::widgets::{self, Widget, WidgetKind, WidgetRegistry};
+use crate::plugins::validate::{self, ConfigIssue};
+use crate::plugins::filetype::{FiletypeConfig, FiletypeSettings};
+use crate::plugins::tasks::{Interval, Task, TaskRegistry};
+use crate::shell::ShellOutput;
+#[cfg(feature = "wasm")]
+use crate::plugins::wasm::WasmHost;
+
+/// A command the config registered with `command(name, description, callback)`.
+#[derive(Clone)]
+pub struct UserCommand {
+    pub name: String,
+    pub description: String,
+    pub callback: FnPtr,
+    // The plugin that registered it, `None` for config.rhai
+    pub script: Option<usize>,
+}
+
+/// A script from `plugins/` or the config's `imports`, or a WebAssembly module from `plugins/`.
+/// Each one is compiled and evaluated on its own, one that fails doesn't take the others down.
+pub struct Plugin {
+    pub name: String,
+    pub path: PathBuf,
+    pub ast: Option<rhai::AST>,
+    pub error: Option<String>,
+}
+
+/// The events `on(event, callback)` can subscribe to.
+pub const HOOK_EVENTS: &[&str] = &["file_opened", "buffer_saving", "buffer_saved", "buffer_closed", "mode_changed"];
+
+/// Written to `config.rhai` by `:config init`.
+const DEFAULT_CONFIG: &str = include_str!("default_config.rhai");
 
 pub struct PluginManager {
     pub engine: Engine,
@@ -19,6 +53,26 @@ pub struct PluginManager {
     pub ast: rhai::AST,
     pub syntax: Arc<Mutex<HashMap<String, HashMap<String, String>>>>,
     pub current_lang: Arc<Mutex<Option<String>>>,
+    pub user_commands: Arc<Mutex<Vec<UserCommand>>>,
+    /// Callbacks by event and the plugin that subscribed them, in order.
+    pub hooks: Arc<Mutex<HashMap<String, Vec<(Option<usize>, FnPtr)>>>>,
+    pub plugins: Vec<Plugin>,
+    pub widgets: WidgetRegistry,
+    /// What `filetype(...)` blocks set, by file type.
+    pub filetypes: Arc<Mutex<HashMap<String, FiletypeConfig>>>,
+    /// What `set_interval(...)` and `spawn_task(...)` scheduled.
+    pub tasks: TaskRegistry,
+    /// What the last load found wrong in `oxidy`, for :checkhealth.
+    pub config_issues: Vec<ConfigIssue>,
+    /// The `.wasm` plugins, with what they registered. None if the engine could not be set up.
+    #[cfg(feature = "wasm")]
+    pub wasm: Option<WasmHost>,
+    // The text of config.rhai, to find the lines of keys in
+    config_source: String,
+    // The script being evaluated, what it registers belongs to it
+    current_script: Arc<Mutex<Option<usize>>>,
+    // Load errors since the last `take_errors`
+    errors: Vec<String>,
 
     pub rx: Option<Receiver<Event>>,
     // pub themes: Arc<Mutex<HashMap<String, HashMap<String, Color>>>>,
@@ -32,54 +86,73 @@ impl PluginManager {
         let mut config_path = dirs::home_dir().expect("Could not find home directory.");
         config_path.push(".config/oxidy/config.rhai");
 
-        let config_file = File::open(&config_path);
         let mut engine = Engine::new();
 
-        
         let mut resolver = FileModuleResolver::new();
         let mut base = dirs::home_dir().unwrap();
         base.push(".config/oxidy/");
         resolver.set_base_path(base); // or your ~/.config/oxidy
         engine.set_module_resolver(resolver);
         // engine.enable_imports(true);
-        
-        
-        let ret: Self;
+        script_api::register(&mut engine);
+
+        // The config file itself is compiled lazily by `reload_config`
+        let ast = engine.compile("").unwrap();
         let current_lang: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
-                
-        if let Ok(mut config_file) = config_file {
-            let mut config_string = String::new();
-            config_file.read_to_string(&mut config_string).unwrap();
- 
-            let ast = engine.compile(&config_string).expect("AST creation failed.");
-
-            ret = Self {
-                engine,
-                ast,
-                config,
-                config_path,
-                syntax: Arc::new(Mutex::new(HashMap::new())),
-                current_lang,
-                rx: None,
-                // themes,
-                // current_theme
-            }
-        } else {
-            let ast = engine.compile("").unwrap();
-            ret = Self {
-                engine,
-                ast,
-                config,
-                config_path,
-                syntax: Arc::new(Mutex::new(HashMap::new())),
-                current_lang,
-                rx: None,
-                // themes,
-                // current_theme
-            }
+
+        Self {
+            engine,
+            ast,
+            config,
+            config_path,
+            syntax: Arc::new(Mutex::new(HashMap::new())),
+            current_lang,
+            user_commands: Arc::new(Mutex::new(vec![])),
+            hooks: Arc::new(Mutex::new(HashMap::new())),
+            plugins: vec![],
+            widgets: WidgetRegistry::default(),
+            filetypes: Arc::new(Mutex::new(HashMap::new())),
+            tasks: TaskRegistry::default(),
+            config_issues: vec![],
+            #[cfg(feature = "wasm")]
+            wasm: WasmHost::new()
+                .inspect_err(|error| crate::log!("{}", error))
+                .ok(),
+            config_source: String::new(),
+            current_script: Arc::new(Mutex::new(None)),
+            errors: vec![],
+            rx: None,
+            // themes,
+            // current_theme
         }
+    }
+
+    /// The directory `config.rhai` and the scripts it imports live in.
+    pub fn config_dir(&self) -> PathBuf {
+        let mut config_dir = self.config_path.clone();
+        config_dir.pop();
+        config_dir
+    }
 
-        ret
+    pub fn has_config_dir(&self) -> bool {
+        self.config_dir().try_exists().unwrap_or(false)
+    }
+
+    /// Creates the config directory with a commented example config.
+    /// An existing `config.rhai` is never overwritten.
+    pub fn scaffold_config(&mut self) -> Result<PathBuf> {
+        fs::create_dir_all(self.config_dir())?;
+
+        if !self.config_path.try_exists().unwrap_or(false) {
+            write(&self.config_path, DEFAULT_CONFIG)?;
+        }
+
+        if self.rx.is_none() {
+            self.start_watcher()?;
+        }
+        self.reload_config();
+
+        Ok(self.config_path.clone())
     }
 
     /// Spawns a background thread that watches the config file
@@ -109,8 +182,9 @@ impl PluginManager {
             })
             .expect("Failed to create watcher");
 
+            // Recursive for the files in syntax/
             watcher
-                .watch(&config_path, RecursiveMode::NonRecursive)
+                .watch(&config_path, RecursiveMode::Recursive)
                 .expect("Failed to watch config file.");
  
             loop {
@@ -122,25 +196,35 @@ impl PluginManager {
         Ok(())
     }
 
-    /// Checks if a reload event occurred (non-blocking)
-    pub fn poll_reload(&mut self) {
-        let mut config_path = self.config_path.clone();
-        
-        config_path.pop();
+    /// Checks if a reload event occurred (non-blocking). Returns true if the config was reloaded.
+    pub fn poll_reload(&mut self) -> bool {
+        if !self.has_config_dir() {
+            return false
+        }
 
-        if !config_path.try_exists().unwrap_or(false) {
-            return 
+        // The directory was created after startup, e.g. from a shell
+        if self.rx.is_none() {
+            if let Err(error) = self.start_watcher() {
+                crate::log!("Could not watch config: {}", error);
+            }
+            self.reload_config();
+            return true;
         }
 
         if let Some(rx) = &self.rx {
             if let Ok(event) = rx.try_recv() {
                 // println!("Config file changed: {:?}", event);
                 match event.kind {
-                    EventKind::Modify(_) => self.reload_config(),
+                    EventKind::Create(_) | EventKind::Modify(_) => {
+                        self.reload_config();
+                        return true;
+                    }
                     _ => {}
                 }
             }
         }
+
+        false
     }
 
     /// Re-loads and re-evaluates the Rhai config
@@ -155,15 +239,18 @@ impl PluginManager {
                 match self.engine.compile(&config_string) {
                     Ok(ast) => {
                         self.ast = ast;
+                        self.config_source = config_string;
                         self.load_config();
                         // eprintln!("Config reloaded successfully!");
                     }
                     Err(err) => {
-                        println!("Error reloading config: {:?}", err);
+                        crate::log!("Error reloading config: {:?}", err);
+                        self.errors.push(format!("config.rhai: {}", err));
                     }
                 }
             }
-            Err(_error) => {}
+            // Plugins load without a config.rhai too
+            Err(_error) => self.load_config(),
         }
     }
 
@@ -173,19 +260,197 @@ impl PluginManager {
         scope.set_value("oxidy", oxidy_config_struct);
         
         self.syntax();
+        self.commands();
+        self.hooks();
+        self.register_widgets();
+        self.register_filetypes();
+        self.register_tasks();
+        self.user_commands.lock().unwrap().clear();
+        self.tasks.clear();
+        self.filetypes.lock().unwrap().clear();
+        self.hooks.lock().unwrap().clear();
+        self.widgets.retain(|_| false);
+        // Files first, a `syntax(...)` block in the config overrides their rules
+        self.syntax.lock().unwrap().clear();
+        self.load_syntax_files();
         
-        let _ = self.engine.eval_ast_with_scope::<()>(&mut scope, &self.ast);
-
-        match self.engine.eval_with_scope(&mut scope, "oxidy") {
-            Ok(script_result) => {
-                let conf: Config = from_dynamic(&script_result).unwrap();
+        *self.current_script.lock().unwrap() = None;
+        if let Err(error) = self.engine.eval_ast_with_scope::<()>(&mut scope, &self.ast) {
+            crate::log!("Rhai error: {:?}", error);
+            self.errors.push(format!("config.rhai: {}", error));
+        }
 
-                crate::log!("{:?}", conf);
-                self.config = conf.merge(&self.config);
+        match self.engine.eval_with_scope::<Dynamic>(&mut scope, "oxidy") {
+            Ok(mut script_result) => {
+                self.config_issues = validate::validate(&mut script_result, &self.config_source);
+                for issue in &self.config_issues {
+                    crate::log!("{}", issue);
+                    self.errors.push(issue.to_string());
+                }
 
+                // Validation takes out what it knows is wrong, the previous config stays otherwise
+                match from_dynamic::<Config>(&script_result) {
+                    Ok(conf) => {
+                        crate::log!("{:?}", conf);
+                        self.config = conf.merge(&self.config);
+                    }
+                    Err(error) => {
+                        crate::log!("Config not applied: {}", error);
+                        self.errors.push(format!("config.rhai: {}, the config was not applied", error));
+                    }
+                }
             }
             Err(error) => crate::log!("Rhai error: {:?}", error)
         }
+
+        self.load_plugins();
+    }
+
+    /// Scripts and WebAssembly modules in `plugins/` by name, then the config's `imports` in their order.
+    fn plugin_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(self.config_dir().join("plugins"))
+            .map(|entries| entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect())
+            .unwrap_or_default();
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "rhai" || ext == "wasm"));
+        paths.sort();
+
+        for import in &self.config.imports {
+            let path = match import.strip_prefix("~/") {
+                Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+                None => self.config_dir().join(import),
+            };
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+
+        paths
+    }
+
+    /// Compiles and runs every plugin in a scope of its own. Plugins register commands,
+    /// hooks and syntax rules like the config does but don't see or change `oxidy`.
+    fn load_plugins(&mut self) {
+        self.plugins.clear();
+        #[cfg(feature = "wasm")]
+        if let Some(wasm) = self.wasm.as_mut() {
+            wasm.clear();
+        }
+
+        for path in self.plugin_paths() {
+            let name = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+            let index = self.plugins.len();
+
+            if path.extension().is_some_and(|ext| ext == "wasm") {
+                let plugin = self.load_wasm(name, path);
+                self.plugins.push(plugin);
+                continue;
+            }
+
+            let result = fs::read_to_string(&path)
+                .map_err(|error| error.to_string())
+                .and_then(|source| self.engine.compile(&source).map_err(|error| error.to_string()))
+                .and_then(|ast| {
+                    *self.current_script.lock().unwrap() = Some(index);
+                    let result = self.engine.run_ast_with_scope(&mut Scope::new(), &ast);
+                    *self.current_script.lock().unwrap() = None;
+
+                    result.map(|_| ast).map_err(|error| error.to_string())
+                });
+
+            let plugin = match result {
+                Ok(ast) => Plugin { name, path, ast: Some(ast), error: None },
+                Err(error) => {
+                    crate::log!("Plugin {} failed to load: {}", path.display(), error);
+                    self.errors.push(format!("Plugin {}: {}", name, error));
+                    self.forget_script(index);
+                    Plugin { name, path, ast: None, error: Some(error) }
+                }
+            };
+            self.plugins.push(plugin);
+        }
+    }
+
+    #[cfg(feature = "wasm")]
+    fn load_wasm(&mut self, name: String, path: PathBuf) -> Plugin {
+        let result = match self.wasm.as_mut() {
+            Some(wasm) => wasm.load(&path),
+            None => Err(String::from("The WebAssembly engine could not be set up, see the log")),
+        };
+
+        match result {
+            Ok(()) => Plugin { name, path, ast: None, error: None },
+            Err(error) => {
+                crate::log!("Plugin {} failed to load: {}", path.display(), error);
+                self.errors.push(format!("Plugin {}: {}", name, error));
+                Plugin { name, path, ast: None, error: Some(error) }
+            }
+        }
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    fn load_wasm(&mut self, name: String, path: PathBuf) -> Plugin {
+        let error = String::from("Oxidy was built without the wasm feature");
+        self.errors.push(format!("Plugin {}: {}", name, error));
+        Plugin { name, path, ast: None, error: Some(error) }
+    }
+
+    /// Drops what a plugin registered before it failed.
+    fn forget_script(&mut self, index: usize) {
+        self.user_commands.lock().unwrap().retain(|command| command.script != Some(index));
+        for callbacks in self.hooks.lock().unwrap().values_mut() {
+            callbacks.retain(|(script, _)| *script != Some(index));
+        }
+        self.widgets.retain(|widget| widget.script != Some(index));
+        self.tasks.forget_script(index);
+    }
+
+    /// Runs `f` as `script`, so what it registers or shows belongs to that script.
+    fn as_script<T>(&self, script: Option<usize>, f: impl FnOnce() -> T) -> T {
+        let previous = std::mem::replace(&mut *self.current_script.lock().unwrap(), script);
+        let result = f();
+        *self.current_script.lock().unwrap() = previous;
+        result
+    }
+
+    /// The AST callbacks registered by `script` have to be called with.
+    fn script_ast(&self, script: Option<usize>) -> &rhai::AST {
+        script.and_then(|index| self.plugins.get(index)?.ast.as_ref()).unwrap_or(&self.ast)
+    }
+
+    /// Errors from loading the config and plugins since the last call.
+    pub fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Reads the definitions in `syntax/` of the config directory into the syntax rules.
+    /// Files that don't parse are logged and skipped.
+    fn load_syntax_files(&mut self) {
+        let dir = self.config_dir().join("syntax");
+        let mut paths: Vec<PathBuf> = match fs::read_dir(&dir) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect(),
+            Err(_) => return
+        };
+        paths.sort();
+
+        let mut all = self.syntax.lock().unwrap();
+
+        for path in paths {
+            let rules = SyntaxFile::load(&path).and_then(|file| Ok((file.rules()?, file.filetypes)));
+
+            match rules {
+                Ok((rules, filetypes)) => {
+                    for filetype in filetypes {
+                        all.entry(filetype).or_default().extend(rules.clone());
+                    }
+                }
+                Err(error) => crate::log!("Skipping syntax file {}: {}", path.display(), error),
+            }
+        }
+    }
+
+    /// Regex rules by file type, from syntax files and `syntax(...)` blocks.
+    pub fn syntax_rules(&self) -> HashMap<String, HashMap<String, String>> {
+        self.syntax.lock().map(|syntax| syntax.clone()).unwrap_or_default()
     }
 
     pub fn get_current_theme_colors(&self) -> Option<HashMap<String, Color>> {
@@ -232,9 +497,279 @@ impl PluginManager {
         }
     }
 
-    pub fn save_buffer(&self, buffer: &Buffer) -> io::Result<()> {
-        let content = buffer.lines.join("\n");
-        write(buffer.path.clone(), content)
+    fn register_filetypes(&mut self) {
+        self.engine.register_type_with_name::<FiletypeSettings>("FiletypeSettings")
+            .register_indexer_get(FiletypeSettings::get)
+            .register_indexer_set(FiletypeSettings::set);
+
+        let filetypes = self.filetypes.clone();
+        self.engine.register_fn("filetype", move |ctx: NativeCallContext, name: &str, callback: FnPtr| -> std::result::Result<(), Box<EvalAltResult>> {
+            let settings = FiletypeSettings::default();
+            callback.call_within_context::<Dynamic>(&ctx, (settings.clone(),))?;
+            let config = FiletypeConfig::from_map(settings.take())
+                .map_err(|error| format!("filetype(\"{}\"): {}", name, error))?;
+
+            let mut filetypes = filetypes.lock().unwrap();
+            let config = match filetypes.get(name) {
+                Some(base) => config.merge(base),
+                None => config,
+            };
+            filetypes.insert(name.to_string(), config);
+            Ok(())
+        });
+    }
+
+    /// What `filetype(...)` blocks set, by the name they were given.
+    pub fn filetype_configs(&self) -> HashMap<String, FiletypeConfig> {
+        self.filetypes.lock().map(|filetypes| filetypes.clone()).unwrap_or_default()
+    }
+
+    fn commands(&mut self) {
+        let commands = self.user_commands.clone();
+        let current_script = self.current_script.clone();
+        let register = move |name: &str, description: &str, callback: FnPtr| {
+            let script = *current_script.lock().unwrap();
+            let mut commands = commands.lock().unwrap();
+            commands.retain(|command| command.name != name);
+            commands.push(UserCommand { name: name.to_string(), description: description.to_string(), callback, script });
+        };
+
+        let with_description = register.clone();
+        self.engine.register_fn("command", move |name: &str, description: &str, callback: FnPtr| {
+            with_description(name, description, callback)
+        });
+        self.engine.register_fn("command", move |name: &str, callback: FnPtr| {
+            register(name, "Defined in config.rhai", callback)
+        });
+    }
+
+    /// Names and descriptions of the commands the config registered.
+    pub fn user_commands(&self) -> Vec<(String, String)> {
+        #[allow(unused_mut)]
+        let mut commands: Vec<(String, String)> = self.user_commands.lock()
+            .map(|commands| commands.iter().map(|command| (command.name.clone(), command.description.clone())).collect())
+            .unwrap_or_default();
+
+        // A command the config or a script registered wins over one from a module
+        #[cfg(feature = "wasm")]
+        for (name, description) in self.wasm.iter().flat_map(WasmHost::commands) {
+            if !commands.iter().any(|(known, _)| *known == name) {
+                commands.push((name, description));
+            }
+        }
+
+        commands
+    }
+
+    /// Runs a command the config registered, its callback gets `editor` and the arguments.
+    pub fn call_command(&self, name: &str, editor: EditorApi, args: Vec<String>) -> std::result::Result<(), String> {
+        let callback = self.user_commands.lock().unwrap().iter()
+            .find(|command| command.name == name)
+            .map(|command| (command.callback.clone(), command.script));
+
+        #[cfg(feature = "wasm")]
+        if let Some(wasm) = self.wasm.as_ref().filter(|wasm| callback.is_none() && wasm.has_command(name)) {
+            return wasm.call_command(name, &editor, args);
+        }
+
+        let (callback, script) = callback.ok_or_else(|| format!("No command {} in the config", name))?;
+
+        let args: rhai::Array = args.into_iter().map(Dynamic::from).collect();
+        self.as_script(script, || callback.call::<Dynamic>(&self.engine, self.script_ast(script), (editor, args)))
+            .map(|_| ())
+            .map_err(|error| error.to_string())
+    }
+
+    fn hooks(&mut self) {
+        let hooks = self.hooks.clone();
+        let current_script = self.current_script.clone();
+        self.engine.register_fn("on", move |event: &str, callback: FnPtr| -> std::result::Result<(), Box<EvalAltResult>> {
+            if !HOOK_EVENTS.contains(&event) {
+                return Err(format!("Unknown event {}, expected one of {}", event, HOOK_EVENTS.join(", ")).into());
+            }
+
+            let script = *current_script.lock().unwrap();
+            hooks.lock().unwrap().entry(event.to_string()).or_default().push((script, callback));
+            Ok(())
+        });
+    }
+
+    pub fn has_hooks(&self, event: &str) -> bool {
+        #[cfg(feature = "wasm")]
+        if self.wasm.as_ref().is_some_and(|wasm| wasm.has_hooks(event)) {
+            return true;
+        }
+
+        self.hooks.lock().is_ok_and(|hooks| hooks.get(event).is_some_and(|callbacks| !callbacks.is_empty()))
+    }
+
+    /// Calls the callbacks subscribed to `event` with `editor` and the details of the event.
+    /// Stops at the first one that fails.
+    pub fn call_hooks(&self, event: &str, editor: EditorApi, details: Map) -> std::result::Result<(), String> {
+        let callbacks = self.hooks.lock().unwrap().get(event).cloned().unwrap_or_default();
+
+        for (script, callback) in callbacks {
+            self.as_script(script, || callback.call::<Dynamic>(&self.engine, self.script_ast(script), (editor.clone(), details.clone())))
+                .map_err(|error| error.to_string())?;
+        }
+
+        #[cfg(feature = "wasm")]
+        if let Some(wasm) = self.wasm.as_ref() {
+            wasm.call_hooks(event, &editor, &details)?;
+        }
+
+        Ok(())
+    }
+
+    fn register_widgets(&mut self) {
+        {
+            let registry = self.widgets.clone();
+            let current_script = self.current_script.clone();
+            self.engine.register_fn("text_box", move |name: &str, options: Map| -> std::result::Result<(), Box<EvalAltResult>> {
+                let widget = Widget::text_box(name, options, *current_script.lock().unwrap())?;
+                registry.show(widget);
+                Ok(())
+            });
+        }
+
+        {
+            let registry = self.widgets.clone();
+            let current_script = self.current_script.clone();
+            let segment = move |name: &str, lines: Vec<String>, render: Option<FnPtr>, every: Option<rhai::INT>| {
+                let mut widget = Widget::new(name, WidgetKind::Status, *current_script.lock().unwrap());
+                widget.lines = lines;
+                widget.render = render;
+                if let Some(every) = every {
+                    widget.every = std::time::Duration::from_millis(every.max(100) as u64);
+                }
+                registry.show(widget);
+            };
+
+            let text = segment.clone();
+            self.engine.register_fn("status_segment", move |name: &str, value: &str| text(name, vec![value.to_string()], None, None));
+            let rendered = segment.clone();
+            self.engine.register_fn("status_segment", move |name: &str, render: FnPtr| rendered(name, vec![], Some(render), None));
+            self.engine.register_fn("status_segment", move |name: &str, render: FnPtr, every: rhai::INT| segment(name, vec![], Some(render), Some(every)));
+        }
+
+        {
+            let registry = self.widgets.clone();
+            let current_script = self.current_script.clone();
+            self.engine.register_fn("pick", move |title: &str, items: rhai::Array, callback: FnPtr| {
+                let mut widget = Widget::new("picker", WidgetKind::Picker, *current_script.lock().unwrap());
+                widget.title = title.to_string();
+                widget.lines = items.into_iter().map(|item| item.to_string()).collect();
+                widget.on_pick = Some(callback);
+                registry.show(widget);
+            });
+        }
+
+        {
+            let registry = self.widgets.clone();
+            self.engine.register_fn("close_widget", move |name: &str| {
+                registry.close(name);
+            });
+        }
+    }
+
+    fn register_tasks(&mut self) {
+        {
+            let tasks = self.tasks.clone();
+            let current_script = self.current_script.clone();
+            self.engine.register_fn("set_interval", move |every: rhai::INT, callback: FnPtr| -> rhai::INT {
+                let every = std::time::Duration::from_millis(every.max(100) as u64);
+                tasks.set_interval(every, callback, *current_script.lock().unwrap())
+            });
+        }
+
+        {
+            let tasks = self.tasks.clone();
+            self.engine.register_fn("clear_interval", move |id: rhai::INT| {
+                tasks.clear_interval(id);
+            });
+        }
+
+        {
+            let tasks = self.tasks.clone();
+            let current_script = self.current_script.clone();
+            self.engine.register_fn("spawn_task", move |command: &str, callback: FnPtr| -> std::result::Result<rhai::INT, Box<EvalAltResult>> {
+                Ok(tasks.spawn(command, callback, *current_script.lock().unwrap())?)
+            });
+        }
+    }
+
+    /// Calls the callback of an interval with `editor`, one that fails isn't called again.
+    pub fn call_interval(&self, interval: &Interval, editor: EditorApi) -> std::result::Result<(), String> {
+        self.as_script(interval.script, || interval.callback.call::<Dynamic>(&self.engine, self.script_ast(interval.script), (editor,)))
+            .map(|_| ())
+            .map_err(|error| {
+                self.tasks.clear_interval(interval.id);
+                format!("{}, the interval was stopped", error)
+            })
+    }
+
+    /// Calls the callback of task `id` with `editor` and a map of `success`, `stdout` and
+    /// `stderr`. A command that couldn't be started has its error in `stderr`.
+    pub fn finish_task(&self, task: Task, output: std::result::Result<ShellOutput, String>, editor: EditorApi) -> std::result::Result<(), String> {
+        let output = output.unwrap_or_else(|error| ShellOutput { stdout: String::new(), stderr: error, success: false });
+        let mut result = Map::new();
+        result.insert("success".into(), output.success.into());
+        result.insert("stdout".into(), output.stdout.into());
+        result.insert("stderr".into(), output.stderr.into());
+
+        self.as_script(task.script, || task.callback.call::<Dynamic>(&self.engine, self.script_ast(task.script), (editor, result)))
+            .map(|_| ())
+            .map_err(|error| format!("{}: {}", task.command, error))
+    }
+
+    /// Calls the `render` of the widgets that are due for fresh lines.
+    pub fn refresh_widgets(&self) {
+        for widget in self.widgets.due() {
+            let Some(render) = &widget.render else { continue };
+
+            let result = self.as_script(widget.script, || render.call::<Dynamic>(&self.engine, self.script_ast(widget.script), ()))
+                .map(widgets::lines)
+                .map_err(|error| {
+                    crate::log!("Widget {} failed to render: {}", widget.name, error);
+                    format!("{}: {}", widget.name, error)
+                });
+
+            self.widgets.rendered(&widget.name, result);
+        }
+    }
+
+    /// Calls the callback of a picker with `editor` and the line that was picked.
+    pub fn call_pick(&self, picker: &Widget, editor: EditorApi, item: String) -> std::result::Result<(), String> {
+        let Some(callback) = &picker.on_pick else { return Ok(()) };
+
+        self.as_script(picker.script, || callback.call::<Dynamic>(&self.engine, self.script_ast(picker.script), (editor, item)))
+            .map(|_| ())
+            .map_err(|error| error.to_string())
+    }
+
+    /// Evaluates a status bar `Eval` snippet with the fields in `scope`.
+    pub fn eval_snippet(&self, source: &str, scope: &mut Scope) -> std::result::Result<String, String> {
+        self.engine.eval_with_scope::<Dynamic>(scope, source)
+            .map(|value| value.to_string())
+            .map_err(|error| error.to_string())
+    }
+
+    /// Calls a function the config defines without arguments, like `fn on_exit()`.
+    /// Returns false if there is no such function.
+    pub fn call_hook(&self, name: &str) -> std::result::Result<bool, String> {
+        if !self.ast.iter_functions().any(|function| function.name == name && function.params.is_empty()) {
+            return Ok(false);
+        }
+
+        // Without running the config again, it expects `oxidy` in scope
+        let options = CallFnOptions::new().eval_ast(false);
+        self.engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &self.ast, name, ())
+            .map(|_| true)
+            .map_err(|error| error.to_string())
+    }
+
+    pub fn save_buffer(&self, buffer: &Buffer, path: &str) -> io::Result<()> {
+        write(path, buffer.file_bytes()?)
     }
 }
 
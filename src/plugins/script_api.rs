@@ -0,0 +1,162 @@
+use std::sync::{Arc, Mutex};
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, INT};
+
+/// What a script sees of the active buffer, and what it changed. The editor itself never
+/// reaches the script, the changes are applied once it returned.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptState {
+    pub lines: Vec<String>,
+    pub row: usize,
+    pub col: usize,
+    pub path: String,
+    pub filetype: String,
+    pub readonly: bool,
+    // Rows the command was given, as in `:'<,'>fmt`
+    pub range: Option<(usize, usize)>,
+    pub lines_changed: bool,
+    pub cursor_changed: bool,
+    pub messages: Vec<String>,
+    // Command lines to run after the script
+    pub commands: Vec<String>,
+}
+
+/// The `editor` a user command gets.
+#[derive(Clone)]
+pub struct EditorApi(Arc<Mutex<ScriptState>>);
+
+impl EditorApi {
+    pub fn new(state: ScriptState) -> Self {
+        Self(Arc::new(Mutex::new(state)))
+    }
+
+    pub fn take(&self) -> ScriptState {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+
+    pub fn with<T>(&self, f: impl FnOnce(&mut ScriptState) -> T) -> T {
+        f(&mut self.0.lock().unwrap())
+    }
+}
+
+/// The map a hook gets next to `editor`, like `#{ path: "src/main.rs" }`.
+pub fn event_details(pairs: &[(&str, &str)]) -> Map {
+    pairs.iter().map(|(key, value)| ((*key).into(), Dynamic::from(value.to_string()))).collect()
+}
+
+fn row(state: &ScriptState, row: INT) -> Result<usize, Box<EvalAltResult>> {
+    match usize::try_from(row) {
+        Ok(row) if row < state.lines.len() => Ok(row),
+        _ => Err(format!("Line {} is out of range, the buffer has {}", row, state.lines.len()).into()),
+    }
+}
+
+/// Registers the `Editor` type and its methods:
+///
+/// ```rhai
+/// editor.lines()              // all lines as an array
+/// editor.line(0)              // one line, 0-based
+/// editor.set_line(0, "text")
+/// editor.set_lines(["a", "b"])
+/// editor.insert_line(1, "text")
+/// editor.remove_line(1)
+/// editor.line_count()
+/// editor.cursor()             // #{ row: 0, col: 0 }
+/// editor.set_cursor(row, col)
+/// editor.range()              // #{ start, end } of `:10,20cmd`, () without one
+/// editor.path()
+/// editor.filetype()
+/// editor.notify("message")
+/// editor.command("w")         // runs a command line once the script is done
+/// ```
+pub fn register(engine: &mut Engine) {
+    engine.register_type_with_name::<EditorApi>("Editor");
+
+    engine.register_fn("lines", |api: &mut EditorApi| -> Array {
+        api.with(|state| state.lines.iter().cloned().map(Dynamic::from).collect())
+    });
+    engine.register_fn("line", |api: &mut EditorApi, index: INT| -> Result<String, Box<EvalAltResult>> {
+        api.with(|state| Ok(state.lines[row(state, index)?].clone()))
+    });
+    engine.register_fn("line_count", |api: &mut EditorApi| -> INT {
+        api.with(|state| state.lines.len() as INT)
+    });
+    engine.register_fn("set_line", |api: &mut EditorApi, index: INT, text: &str| -> Result<(), Box<EvalAltResult>> {
+        api.with(|state| {
+            let index = row(state, index)?;
+            state.lines[index] = text.to_string();
+            state.lines_changed = true;
+            Ok(())
+        })
+    });
+    engine.register_fn("set_lines", |api: &mut EditorApi, lines: Array| {
+        api.with(|state| {
+            state.lines = lines.into_iter().map(|line| line.to_string()).collect();
+            if state.lines.is_empty() {
+                state.lines.push(String::new());
+            }
+            state.lines_changed = true;
+        })
+    });
+    engine.register_fn("insert_line", |api: &mut EditorApi, index: INT, text: &str| -> Result<(), Box<EvalAltResult>> {
+        api.with(|state| {
+            // One past the last line appends
+            let index = match usize::try_from(index) {
+                Ok(index) if index <= state.lines.len() => index,
+                _ => return Err(format!("Line {} is out of range, the buffer has {}", index, state.lines.len()).into()),
+            };
+            state.lines.insert(index, text.to_string());
+            state.lines_changed = true;
+            Ok(())
+        })
+    });
+    engine.register_fn("remove_line", |api: &mut EditorApi, index: INT| -> Result<String, Box<EvalAltResult>> {
+        api.with(|state| {
+            let index = row(state, index)?;
+            let line = state.lines.remove(index);
+            if state.lines.is_empty() {
+                state.lines.push(String::new());
+            }
+            state.lines_changed = true;
+            Ok(line)
+        })
+    });
+    engine.register_fn("cursor", |api: &mut EditorApi| -> Map {
+        api.with(|state| {
+            let mut cursor = Map::new();
+            cursor.insert("row".into(), (state.row as INT).into());
+            cursor.insert("col".into(), (state.col as INT).into());
+            cursor
+        })
+    });
+    engine.register_fn("set_cursor", |api: &mut EditorApi, row: INT, col: INT| {
+        api.with(|state| {
+            state.row = row.max(0) as usize;
+            state.col = col.max(0) as usize;
+            state.cursor_changed = true;
+        })
+    });
+    engine.register_fn("range", |api: &mut EditorApi| -> Dynamic {
+        api.with(|state| match state.range {
+            Some((start, end)) => {
+                let mut range = Map::new();
+                range.insert("start".into(), (start as INT).into());
+                range.insert("end".into(), (end as INT).into());
+                range.into()
+            }
+            None => Dynamic::UNIT,
+        })
+    });
+    engine.register_fn("path", |api: &mut EditorApi| -> String {
+        api.with(|state| state.path.clone())
+    });
+    engine.register_fn("filetype", |api: &mut EditorApi| -> String {
+        api.with(|state| state.filetype.clone())
+    });
+    engine.register_fn("notify", |api: &mut EditorApi, message: Dynamic| {
+        api.with(|state| state.messages.push(message.to_string()))
+    });
+    engine.register_fn("command", |api: &mut EditorApi, line: &str| {
+        api.with(|state| state.commands.push(line.to_string()))
+    });
+}
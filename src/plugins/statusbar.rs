@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum StatusComponent {
     Text(String),           // static text
-    Field(String),          // dynamic field: "filename", "mode", "git_branch"
+    Field(String),          // dynamic field: "filename", "mode", "cursor", "diagnostics", "git", ... or a script's status segment
     Eval(String),           // Rhai expression -> string
     Spacer,
     Group(Vec<StatusComponent>),
@@ -17,6 +17,42 @@ pub enum StatusComponent {
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct StatusBarConfig {
     pub components: Vec<StatusComponent>,
+    // "auto", "powerline" or "ascii". Auto only uses Powerline caps in terminals known to draw them
+    #[serde(default)]
+    pub symbols: Option<String>,
+    // Caps around every item when Powerline glyphs can't be used
+    #[serde(default)]
+    pub ascii_left: Option<String>,
+    #[serde(default)]
+    pub ascii_right: Option<String>,
+}
+
+impl StatusBarConfig {
+    /// The caps drawn left and right of every item.
+    pub fn symbols(&self) -> (String, String) {
+        let powerline = match self.symbols.as_deref() {
+            Some("powerline") => true,
+            Some("ascii") => false,
+            _ => terminal_draws_powerline(),
+        };
+
+        if powerline {
+            ("\u{e0b6}".to_string(), "\u{e0b4}".to_string())
+        } else {
+            (self.ascii_left.clone().unwrap_or_default(), self.ascii_right.clone().unwrap_or_default())
+        }
+    }
+}
+
+/// Whether the terminal draws Powerline glyphs without a patched font. Kitty, WezTerm and
+/// Ghostty draw them on their own or bundle a symbols font, elsewhere it depends on the
+/// font, which can't be queried, so `symbols: "powerline"` has to opt in.
+pub fn terminal_draws_powerline() -> bool {
+    let var = |name: &str| std::env::var(name).unwrap_or_default().to_lowercase();
+    let term = var("TERM");
+    let program = var("TERM_PROGRAM");
+
+    ["kitty", "wezterm", "ghostty"].iter().any(|name| term.contains(name) || program.contains(name))
 }
 
 impl Default for StatusBarConfig {
@@ -29,11 +65,23 @@ impl Default for StatusBarConfig {
                 StatusComponent::Group(vec![
                     StatusComponent::Field("filename".into())
                 ]),
+                StatusComponent::Group(vec![
+                    StatusComponent::Field("git".into())
+                ]),
                 StatusComponent::Spacer,
                 StatusComponent::Group(vec![
-                    StatusComponent::Eval("format('{}:{} {}', line, total_lines, mode)".into())
+                    StatusComponent::Field("diagnostics".into())
+                ]),
+                StatusComponent::Group(vec![
+                    StatusComponent::Field("cursor".into())
+                ]),
+                StatusComponent::Group(vec![
+                    StatusComponent::Field("mode_flag".into())
                 ])
-            ]
+            ],
+            symbols: None,
+            ascii_left: None,
+            ascii_right: None,
         }
     }
 }
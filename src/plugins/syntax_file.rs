@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use regex::Regex;
+use serde::Deserialize;
+
+/// A declarative syntax definition from `syntax/*.toml` or `syntax/*.json` in the config
+/// directory, the file equivalent of a `syntax(...)` block:
+///
+/// ```toml
+/// filetypes = ["toml"]
+///
+/// [patterns]
+/// comment = '(#.*)$'
+/// "string.quoted" = '("[^"]*")'
+///
+/// [keywords]
+/// "constant.builtin" = ["true", "false"]
+///
+/// [scopes]
+/// "constant.builtin" = "number"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SyntaxFile {
+    // File extensions the definition is for
+    pub filetypes: Vec<String>,
+    // Regexes by scope, the first capture group is colored
+    pub patterns: HashMap<String, String>,
+    // Words by scope, matched as whole words
+    pub keywords: HashMap<String, Vec<String>>,
+    // The theme color of a scope, e.g. "constant.builtin" = "number". Scopes that aren't
+    // listed take the color named by their first part, "string.quoted" the String color
+    pub scopes: HashMap<String, String>,
+}
+
+impl SyntaxFile {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => toml::from_str(&text).map_err(|error| error.to_string()),
+            Some("json") => serde_json::from_str(&text).map_err(|error| error.to_string()),
+            _ => Err("not a .toml or .json file".to_string()),
+        }
+    }
+
+    /// The highlighter rules of the definition. Rules are keyed by the theme color and the
+    /// scope, like "number.constant.builtin", the highlighter colors them by the first part.
+    pub fn rules(&self) -> Result<HashMap<String, String>, String> {
+        let mut rules = HashMap::new();
+
+        for (scope, pattern) in &self.patterns {
+            Regex::new(pattern).map_err(|error| format!("pattern {}: {}", scope, error))?;
+            rules.insert(format!("{}.{}", self.color(scope), scope), pattern.clone());
+        }
+
+        for (scope, words) in &self.keywords {
+            if words.is_empty() { continue }
+
+            let words: Vec<String> = words.iter().map(|word| regex::escape(word)).collect();
+            rules.insert(format!("{}.{}.words", self.color(scope), scope), format!(r"\b({})\b", words.join("|")));
+        }
+
+        Ok(rules)
+    }
+
+    fn color<'a>(&'a self, scope: &'a str) -> &'a str {
+        match self.scopes.get(scope) {
+            Some(color) => color.as_str(),
+            None => scope.split('.').next().unwrap_or(scope),
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rhai::{FnPtr, INT};
+
+use crate::shell::{ShellOutput, ShellProcess};
+
+/// A callback `set_interval(ms, callback)` runs on the main thread every `every`.
+#[derive(Clone)]
+pub struct Interval {
+    pub id: INT,
+    pub every: Duration,
+    pub callback: FnPtr,
+    // The plugin that set it, `None` for config.rhai
+    pub script: Option<usize>,
+    ran: Instant,
+}
+
+/// A shell command `spawn_task(command, callback)` runs in the background, the callback
+/// gets its output once it exited.
+pub struct Task {
+    pub id: INT,
+    pub command: String,
+    pub callback: FnPtr,
+    pub script: Option<usize>,
+    process: ShellProcess,
+}
+
+#[derive(Default)]
+struct Scheduled {
+    next_id: INT,
+    intervals: Vec<Interval>,
+    tasks: Vec<Task>,
+}
+
+/// What scripts scheduled, shared with the functions registered for them.
+#[derive(Clone, Default)]
+pub struct TaskRegistry(Arc<Mutex<Scheduled>>);
+
+impl TaskRegistry {
+    pub fn set_interval(&self, every: Duration, callback: FnPtr, script: Option<usize>) -> INT {
+        let mut scheduled = self.0.lock().unwrap();
+        scheduled.next_id += 1;
+
+        let id = scheduled.next_id;
+        scheduled.intervals.push(Interval { id, every, callback, script, ran: Instant::now() });
+        id
+    }
+
+    pub fn clear_interval(&self, id: INT) {
+        self.0.lock().unwrap().intervals.retain(|interval| interval.id != id);
+    }
+
+    /// The intervals whose time has come, they count as run from now.
+    pub fn due(&self) -> Vec<Interval> {
+        let mut scheduled = self.0.lock().unwrap();
+        let now = Instant::now();
+
+        scheduled.intervals.iter_mut()
+            .filter(|interval| now.duration_since(interval.ran) >= interval.every)
+            .map(|interval| {
+                interval.ran = now;
+                interval.clone()
+            })
+            .collect()
+    }
+
+    /// Starts `command`, its task is handed out by `poll` once it exited.
+    pub fn spawn(&self, command: &str, callback: FnPtr, script: Option<usize>) -> Result<INT, String> {
+        let process = ShellProcess::start(command, None).map_err(|error| format!("Could not run {}: {}", command, error))?;
+
+        let mut scheduled = self.0.lock().unwrap();
+        scheduled.next_id += 1;
+
+        let id = scheduled.next_id;
+        scheduled.tasks.push(Task { id, command: command.to_string(), callback, script, process });
+        Ok(id)
+    }
+
+    /// The tasks whose command exited, with its output.
+    pub fn poll(&self) -> Vec<(Task, Result<ShellOutput, String>)> {
+        let mut scheduled = self.0.lock().unwrap();
+        let mut finished = vec![];
+
+        for mut task in std::mem::take(&mut scheduled.tasks) {
+            match task.process.poll() {
+                Some(output) => finished.push((task, output.map_err(|error| error.to_string()))),
+                None => scheduled.tasks.push(task),
+            }
+        }
+
+        finished
+    }
+
+    /// Id, command and pid of the tasks still running.
+    pub fn running(&self) -> Vec<(INT, String, u32)> {
+        self.0.lock().unwrap().tasks.iter()
+            .map(|task| (task.id, task.command.clone(), task.process.pid()))
+            .collect()
+    }
+
+    /// Stops the command of task `id`, its callback still gets what it wrote.
+    pub fn kill(&self, id: INT) {
+        if let Some(task) = self.0.lock().unwrap().tasks.iter_mut().find(|task| task.id == id) {
+            task.process.kill();
+        }
+    }
+
+    /// Drops everything scheduled, before the config is loaded again or on exit. Commands
+    /// that are still running are killed.
+    pub fn clear(&self) {
+        let mut scheduled = self.0.lock().unwrap();
+        scheduled.intervals.clear();
+        scheduled.tasks.clear();
+    }
+
+    /// Drops what plugin `script` scheduled before it failed.
+    pub fn forget_script(&self, script: usize) {
+        let mut scheduled = self.0.lock().unwrap();
+        scheduled.intervals.retain(|interval| interval.script != Some(script));
+        scheduled.tasks.retain(|task| task.script != Some(script));
+    }
+}
@@ -4,6 +4,31 @@ use serde::{Deserialize, Serialize};
 
 use crossterm::style::Color;
 
+/// Where token types without a color of their own borrow one from, e.g. Python's "decorator" or Go's "label".
+/// Themes can extend or override this with `TokenFallbacks`.
+pub const TOKEN_FALLBACKS: &[(&str, &str)] = &[
+    ("decorator", "macro"),
+    ("attribute", "macro"),
+    ("builtinAttribute", "macro"),
+    ("deriveHelper", "macro"),
+    ("label", "keyword"),
+    ("selfKeyword", "keyword"),
+    ("selfTypeKeyword", "keyword"),
+    ("builtinType", "type"),
+    ("typeAlias", "type"),
+    ("lifetime", "typeParameter"),
+    ("constParameter", "typeParameter"),
+    ("module", "namespace"),
+    ("toolModule", "namespace"),
+    ("boolean", "number"),
+    ("character", "string"),
+    ("escapeSequence", "string"),
+    ("formatSpecifier", "operator"),
+    ("punctuation", "operator"),
+    ("function.builtin", "function"),
+    ("unresolvedReference", "variable"),
+];
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Theme {
     pub Background: Option<String>,
@@ -35,7 +60,15 @@ pub struct Theme {
 
     pub String: Option<String>,
     pub Number: Option<String>,
-    pub Regexp: Option<String>
+    pub Regexp: Option<String>,
+
+    // Color of the terminal cursor, the foreground when left out
+    pub Cursor: Option<String>,
+    // Background of the cursor's line with `cursorline`, a slightly lighter background when left out
+    pub CursorLine: Option<String>,
+
+    // Token type -> theme key to use when the token type has no color, on top of TOKEN_FALLBACKS
+    pub TokenFallbacks: Option<HashMap<String, String>>
 }
 
 impl Default for Theme {
@@ -69,7 +102,12 @@ impl Default for Theme {
 
             String:          Some("#90b99f".to_string()),
             Number:          Some("#e29eca".to_string()),
-            Regexp:          Some("#e29eca".to_string())
+            Regexp:          Some("#e29eca".to_string()),
+
+            Cursor:          None,
+            CursorLine:      None,
+
+            TokenFallbacks:  None
         }
     }
 }
@@ -132,9 +170,43 @@ impl Theme {
         add!(Number);
         add!(Regexp);
 
+        add!(Cursor);
+        add!(CursorLine);
+
+        let fallbacks = self.token_fallbacks();
+        for (token, target) in &fallbacks {
+            if map.contains_key(token) { continue }
+
+            // Follow chains like decorator -> attribute -> macro
+            let mut target = target;
+            for _ in 0..4 {
+                if let Some(color) = map.get(target) {
+                    map.insert(token.clone(), *color);
+                    break;
+                }
+                match fallbacks.get(target) {
+                    Some(next) => target = next,
+                    None => break,
+                }
+            }
+        }
+
         map
     }
 
+    /// The built-in fallback table with the theme's own entries applied over it.
+    pub fn token_fallbacks(&self) -> HashMap<String, String> {
+        let mut fallbacks: HashMap<String, String> = TOKEN_FALLBACKS.iter()
+            .map(|(token, target)| (token.to_string(), target.to_string()))
+            .collect();
+
+        if let Some(own) = &self.TokenFallbacks {
+            fallbacks.extend(own.iter().map(|(token, target)| (token.clone(), lower_first(target))));
+        }
+
+        fallbacks
+    }
+
     pub fn merge(&self, base: &Theme) -> Theme {
         Theme {
             Background: self.Background.clone().or(base.Background.clone()),
@@ -166,7 +238,190 @@ impl Theme {
             String: self.String.clone().or(base.String.clone()),
             Number: self.Number.clone().or(base.Number.clone()),
             Regexp: self.Regexp.clone().or(base.Regexp.clone()),
+
+            Cursor: self.Cursor.clone().or(base.Cursor.clone()),
+            CursorLine: self.CursorLine.clone().or(base.CursorLine.clone()),
+
+            TokenFallbacks: self.TokenFallbacks.clone().or(base.TokenFallbacks.clone()),
+        }
+    }
+
+    /// Names of the themes `builtin` knows.
+    pub const BUILTIN: &'static [&'static str] = &[
+        "oldworld", "oldworld-deuteranopia", "oldworld-tritanopia",
+        "gruvbox", "nord", "tokyonight", "catppuccin-mocha", "solarized-light", "github-light",
+    ];
+
+    /// The themes that ship with the editor, usable by name without defining them in the config.
+    /// The oldworld variants keep the default's look but tell token types apart for color-blind
+    /// eyes. Keys left out of a theme keep the default's color.
+    pub fn builtin(name: &str) -> Option<Theme> {
+        let colors: &[(&str, &str)] = match name {
+            "oldworld" => &[],
+            // Red-green blindness, also fine for protanopia: blue, orange and yellow do the work
+            "oldworld-deuteranopia" => &[
+                ("Namespace", "#cc79a7"), ("Macro", "#cc79a7"),
+                ("Type", "#e69f00"), ("Class", "#e69f00"), ("Struct", "#e69f00"), ("Enum", "#e69f00"), ("Interface", "#e69f00"),
+                ("TypeParameter", "#c9a86a"),
+                ("Property", "#f0e442"), ("EnumMember", "#f0e442"),
+                ("Function", "#56b4e9"), ("Method", "#56b4e9"), ("Event", "#88ccee"),
+                ("Keyword", "#a89fe0"), ("Modifier", "#a89fe0"),
+                ("String", "#35b58f"),
+                ("Number", "#e8743b"), ("Regexp", "#e8743b"),
+            ],
+            // Blue-yellow blindness: reds and teals instead
+            "oldworld-tritanopia" => &[
+                ("Namespace", "#e07ab0"), ("Macro", "#e07ab0"),
+                ("Type", "#ef6f6c"), ("Class", "#ef6f6c"), ("Struct", "#ef6f6c"), ("Enum", "#ef6f6c"), ("Interface", "#ef6f6c"),
+                ("TypeParameter", "#c99a9a"),
+                ("Property", "#f4a7a3"), ("EnumMember", "#f4a7a3"),
+                ("Function", "#5fd0d0"), ("Method", "#5fd0d0"), ("Event", "#9adede"),
+                ("Keyword", "#d98cd9"), ("Modifier", "#d98cd9"),
+                ("String", "#7fc6a4"),
+                ("Number", "#ff9f80"), ("Regexp", "#ff9f80"),
+            ],
+            "gruvbox" => &[
+                ("Background", "#282828"), ("Foreground", "#ebdbb2"), ("CursorLine", "#3c3836"),
+                ("Comment", "#928374"),
+                ("Namespace", "#83a598"), ("Macro", "#8ec07c"),
+                ("Type", "#fabd2f"), ("Class", "#fabd2f"), ("Struct", "#fabd2f"), ("Enum", "#fabd2f"), ("Interface", "#fabd2f"),
+                ("TypeParameter", "#d3869b"),
+                ("Variable", "#ebdbb2"), ("Parameter", "#d5c4a1"),
+                ("Property", "#83a598"), ("EnumMember", "#d3869b"),
+                ("Function", "#8ec07c"), ("Method", "#8ec07c"), ("Event", "#fe8019"),
+                ("Keyword", "#fb4934"), ("Modifier", "#fb4934"), ("Operator", "#fe8019"),
+                ("String", "#b8bb26"),
+                ("Number", "#d3869b"), ("Regexp", "#fe8019"),
+            ],
+            "nord" => &[
+                ("Background", "#2e3440"), ("Foreground", "#d8dee9"), ("CursorLine", "#3b4252"),
+                ("Comment", "#7b88a1"),
+                ("Namespace", "#8fbcbb"), ("Macro", "#5e81ac"),
+                ("Type", "#8fbcbb"), ("Class", "#8fbcbb"), ("Struct", "#8fbcbb"), ("Enum", "#8fbcbb"), ("Interface", "#8fbcbb"),
+                ("TypeParameter", "#8fbcbb"),
+                ("Variable", "#d8dee9"), ("Parameter", "#e5e9f0"),
+                ("Property", "#e5e9f0"), ("EnumMember", "#b48ead"),
+                ("Function", "#88c0d0"), ("Method", "#88c0d0"), ("Event", "#ebcb8b"),
+                ("Keyword", "#81a1c1"), ("Modifier", "#81a1c1"), ("Operator", "#81a1c1"),
+                ("String", "#a3be8c"),
+                ("Number", "#b48ead"), ("Regexp", "#ebcb8b"),
+            ],
+            "tokyonight" => &[
+                ("Background", "#1a1b26"), ("Foreground", "#c0caf5"), ("CursorLine", "#292e42"),
+                ("Comment", "#737aa2"),
+                ("Namespace", "#7dcfff"), ("Macro", "#7dcfff"),
+                ("Type", "#2ac3de"), ("Class", "#2ac3de"), ("Struct", "#2ac3de"), ("Enum", "#2ac3de"), ("Interface", "#2ac3de"),
+                ("TypeParameter", "#2ac3de"),
+                ("Variable", "#c0caf5"), ("Parameter", "#e0af68"),
+                ("Property", "#73daca"), ("EnumMember", "#ff9e64"),
+                ("Function", "#7aa2f7"), ("Method", "#7aa2f7"), ("Event", "#e0af68"),
+                ("Keyword", "#bb9af7"), ("Modifier", "#bb9af7"), ("Operator", "#89ddff"),
+                ("String", "#9ece6a"),
+                ("Number", "#ff9e64"), ("Regexp", "#b4f9f8"),
+            ],
+            "catppuccin-mocha" => &[
+                ("Background", "#1e1e2e"), ("Foreground", "#cdd6f4"), ("CursorLine", "#313244"), ("Cursor", "#f5e0dc"),
+                ("Comment", "#7f849c"),
+                ("Namespace", "#b4befe"), ("Macro", "#f5c2e7"),
+                ("Type", "#f9e2af"), ("Class", "#f9e2af"), ("Struct", "#f9e2af"), ("Enum", "#f9e2af"), ("Interface", "#f9e2af"),
+                ("TypeParameter", "#f2cdcd"),
+                ("Variable", "#cdd6f4"), ("Parameter", "#eba0ac"),
+                ("Property", "#b4befe"), ("EnumMember", "#94e2d5"),
+                ("Function", "#89b4fa"), ("Method", "#89b4fa"), ("Event", "#f5e0dc"),
+                ("Keyword", "#cba6f7"), ("Modifier", "#cba6f7"), ("Operator", "#89dceb"),
+                ("String", "#a6e3a1"),
+                ("Number", "#fab387"), ("Regexp", "#f5c2e7"),
+            ],
+            "solarized-light" => &[
+                ("Background", "#fdf6e3"), ("Foreground", "#586e75"), ("CursorLine", "#eee8d5"),
+                ("Comment", "#839496"),
+                ("Namespace", "#cb4b16"), ("Macro", "#cb4b16"),
+                ("Type", "#b58900"), ("Class", "#b58900"), ("Struct", "#b58900"), ("Enum", "#b58900"), ("Interface", "#b58900"),
+                ("TypeParameter", "#b58900"),
+                ("Variable", "#586e75"), ("Parameter", "#657b83"),
+                ("Property", "#268bd2"), ("EnumMember", "#6c71c4"),
+                ("Function", "#268bd2"), ("Method", "#268bd2"), ("Event", "#cb4b16"),
+                ("Keyword", "#859900"), ("Modifier", "#859900"), ("Operator", "#586e75"),
+                ("String", "#2aa198"),
+                ("Number", "#d33682"), ("Regexp", "#dc322f"),
+            ],
+            "github-light" => &[
+                ("Background", "#ffffff"), ("Foreground", "#24292f"), ("CursorLine", "#f6f8fa"),
+                ("Comment", "#6e7781"),
+                ("Namespace", "#953800"), ("Macro", "#8250df"),
+                ("Type", "#953800"), ("Class", "#953800"), ("Struct", "#953800"), ("Enum", "#953800"), ("Interface", "#953800"),
+                ("TypeParameter", "#953800"),
+                ("Variable", "#24292f"), ("Parameter", "#24292f"),
+                ("Property", "#0550ae"), ("EnumMember", "#0550ae"),
+                ("Function", "#8250df"), ("Method", "#8250df"), ("Event", "#953800"),
+                ("Keyword", "#cf222e"), ("Modifier", "#cf222e"), ("Operator", "#cf222e"),
+                ("String", "#0a3069"),
+                ("Number", "#0550ae"), ("Regexp", "#116329"),
+            ],
+            _ => return None
+        };
+
+        let color = |key: &str| colors.iter().find(|(name, _)| *name == key).map(|(_, color)| color.to_string());
+
+        let mut theme = Theme::default();
+        for (key, value) in theme.foregrounds_mut() {
+            if let Some(color) = color(key) {
+                *value = Some(color);
+            }
+        }
+        theme.Background = color("Background").or(theme.Background);
+        theme.CursorLine = color("CursorLine");
+        theme.Cursor = color("Cursor");
+
+        Some(theme)
+    }
+
+    /// The colors drawn on the background, with their theme key.
+    fn foregrounds_mut(&mut self) -> [(&'static str, &mut Option<String>); 23] {
+        [
+            ("Foreground", &mut self.Foreground), ("Comment", &mut self.Comment),
+            ("Namespace", &mut self.Namespace), ("Type", &mut self.Type), ("Class", &mut self.Class),
+            ("Struct", &mut self.Struct), ("Enum", &mut self.Enum), ("Interface", &mut self.Interface),
+            ("TypeParameter", &mut self.TypeParameter),
+            ("Variable", &mut self.Variable), ("Parameter", &mut self.Parameter),
+            ("Property", &mut self.Property), ("EnumMember", &mut self.EnumMember),
+            ("Function", &mut self.Function), ("Method", &mut self.Method), ("Macro", &mut self.Macro), ("Event", &mut self.Event),
+            ("Keyword", &mut self.Keyword), ("Modifier", &mut self.Modifier), ("Operator", &mut self.Operator),
+            ("String", &mut self.String), ("Number", &mut self.Number), ("Regexp", &mut self.Regexp),
+        ]
+    }
+
+    /// Theme keys whose color has less than `min` contrast with the background, with their contrast.
+    pub fn low_contrast(&self, min: f64) -> Vec<(&'static str, f64)> {
+        let mut theme = self.clone();
+        let background = match self.Background.as_deref().and_then(parse_hex) {
+            Some(background) => background,
+            None => return vec![]
+        };
+
+        theme.foregrounds_mut().into_iter()
+            .filter_map(|(key, value)| Some((key, contrast_ratio(parse_hex(value.as_deref()?)?, background))))
+            .filter(|(_, ratio)| *ratio < min)
+            .collect()
+    }
+
+    /// The theme with every color that has less than `min` contrast with the background
+    /// brightened or darkened just enough to reach it.
+    pub fn with_min_contrast(&self, min: f64) -> Theme {
+        let mut theme = self.clone();
+        let background = match self.Background.as_deref().and_then(parse_hex) {
+            Some(background) => background,
+            None => return theme
+        };
+
+        for (_, value) in theme.foregrounds_mut() {
+            if let Some(color) = value.as_deref().and_then(parse_hex) {
+                let (r, g, b) = raise_contrast(color, background, min);
+                *value = Some(format!("#{:02x}{:02x}{:02x}", r, g, b));
+            }
         }
+
+        theme
     }
 
     pub fn background(&self) -> Color {
@@ -186,4 +441,114 @@ impl Theme {
 
         Color::Rgb { r, g, b }
     }
+
+    /// Background of the cursor's line, the background moved a little toward the foreground
+    /// unless the theme has its own.
+    pub fn cursor_line(&self) -> Color {
+        if let Some((r, g, b)) = self.CursorLine.as_deref().and_then(parse_hex) {
+            return Color::Rgb { r, g, b };
+        }
+
+        let background = self.Background.as_deref().and_then(parse_hex).unwrap_or((0, 0, 0));
+        let foreground = self.Foreground.as_deref().and_then(parse_hex).unwrap_or((255, 255, 255));
+        let mix = |bg: u8, fg: u8| (bg as f64 + (fg as f64 - bg as f64) * 0.08).round() as u8;
+
+        Color::Rgb {
+            r: mix(background.0, foreground.0),
+            g: mix(background.1, foreground.1),
+            b: mix(background.2, foreground.2),
+        }
+    }
+
+    /// The color the terminal cursor is set to.
+    pub fn cursor(&self) -> Color {
+        match self.Cursor.as_deref().and_then(parse_hex) {
+            Some((r, g, b)) => Color::Rgb { r, g, b },
+            None => self.foreground()
+        }
+    }
+}
+
+// Theme keys are written like the fields ("EnumMember") but colors are looked up by token type ("enumMember")
+fn lower_first(key: &str) -> String {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+pub fn parse_hex(hex: &str) -> Option<(u8, u8, u8)> {
+    let hex = hex.trim_start_matches('#');
+    Some((
+        u8::from_str_radix(hex.get(0..2)?, 16).ok()?,
+        u8::from_str_radix(hex.get(2..4)?, 16).ok()?,
+        u8::from_str_radix(hex.get(4..6)?, 16).ok()?,
+    ))
+}
+
+/// The xterm 256-color index closest to `color`, from the 6x6x6 cube or the gray ramp.
+/// The 16 base colors are left out, terminals set those to colors of their own.
+pub fn nearest_ansi((r, g, b): (u8, u8, u8)) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+    let distance = |(r2, g2, b2): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, r2) + d(g, g2) + d(b, b2)
+    };
+    let level = |c: u8| (0..LEVELS.len()).min_by_key(|i| (LEVELS[*i] as i32 - c as i32).abs()).unwrap_or(0);
+
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = 16 + 36 * ri + 6 * gi + bi;
+    let cube_color = (LEVELS[ri], LEVELS[gi], LEVELS[bi]);
+
+    // Grays run from 8 to 238 in steps of 10
+    let average = (r as usize + g as usize + b as usize) / 3;
+    let step = (average.saturating_sub(3) / 10).min(23);
+    let gray = 8 + step as u8 * 10;
+
+    match distance((gray, gray, gray)) < distance(cube_color) {
+        true => 232 + step as u8,
+        false => cube as u8,
+    }
+}
+
+/// Relative luminance as defined by WCAG 2.
+fn luminance((r, g, b): (u8, u8, u8)) -> f64 {
+    let channel = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.03928 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    };
+
+    0.2126 * channel(r) + 0.7152 * channel(g) + 0.0722 * channel(b)
+}
+
+/// WCAG contrast ratio, from 1 for the same color up to 21 for black on white.
+/// Body text should have at least 4.5.
+pub fn contrast_ratio(a: (u8, u8, u8), b: (u8, u8, u8)) -> f64 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Mixes `color` toward white or black, whichever stands out more on `background`, until it
+/// reaches `min` contrast. Keeps as much of the hue as it can.
+fn raise_contrast(color: (u8, u8, u8), background: (u8, u8, u8), min: f64) -> (u8, u8, u8) {
+    if contrast_ratio(color, background) >= min { return color }
+
+    let target = match contrast_ratio((255, 255, 255), background) >= contrast_ratio((0, 0, 0), background) {
+        true => (255, 255, 255),
+        false => (0, 0, 0),
+    };
+    let mix = |t: f64| {
+        let channel = |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * t).round() as u8;
+        (channel(color.0, target.0), channel(color.1, target.1), channel(color.2, target.2))
+    };
+
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..16 {
+        let middle = (low + high) / 2.0;
+        if contrast_ratio(mix(middle), background) >= min { high = middle } else { low = middle }
+    }
+
+    mix(high)
 }
@@ -0,0 +1,181 @@
+use std::fmt;
+
+use rhai::{serde::to_dynamic, Dynamic, Map};
+
+use crate::plugins::config::Config;
+use crate::plugins::theme::{self, Theme};
+
+/// Keys of an `lsps` entry, `LspConfig` has no default to read them from.
+const LSP_KEYS: &[&str] = &["extensions", "command", "args", "language_id", "single_file"];
+
+/// Something wrong in the `oxidy` map the config built. The key is left out or reset to
+/// its default so the rest of the config still applies.
+#[derive(Debug, Clone)]
+pub struct ConfigIssue {
+    /// Like "opt.tab_size" or "themes.dark.Keyword".
+    pub path: String,
+    pub message: String,
+    /// Where the key shows up first in config.rhai, 1-based.
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "config.rhai:{}: {}: {}", line, self.path, self.message),
+            None => write!(f, "config.rhai: {}: {}", self.path, self.message),
+        }
+    }
+}
+
+/// Checks `oxidy` against the keys and types of the default config and the theme colors
+/// for valid hex. What is wrong gets taken out of `oxidy`, `source` is only used to find
+/// the line of a key.
+pub fn validate(oxidy: &mut Dynamic, source: &str) -> Vec<ConfigIssue> {
+    let mut issues = vec![];
+    let schema = to_dynamic(Config::default()).unwrap_or(Dynamic::UNIT);
+    check(oxidy, &schema, "", source, &mut issues);
+    issues
+}
+
+fn check(value: &mut Dynamic, schema: &Dynamic, path: &str, source: &str, issues: &mut Vec<ConfigIssue>) {
+    // Without a default there is nothing to compare with, deserializing will tell
+    if schema.is_unit() || value.is_unit() { return }
+
+    if !same_type(value, schema) {
+        issues.push(issue(path, format!("expected {}, found {}", type_name(schema), type_name(value)), source));
+        *value = schema.clone();
+        return;
+    }
+
+    let Some(schema) = schema.read_lock::<Map>() else { return };
+    let Some(mut map) = value.write_lock::<Map>() else { return };
+
+    let keys: Vec<String> = map.keys().map(|key| key.to_string()).collect();
+    for key in keys {
+        let child_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+        let child = map.get_mut(key.as_str()).unwrap();
+
+        match path {
+            "themes" => check_theme(child, &child_path, source, issues),
+            "lsps" => check_keys(child, LSP_KEYS, &child_path, source, issues),
+            "keymap" if schema.contains_key(key.as_str()) => check_bindings(child, &child_path, source, issues),
+            _ => match schema.get(key.as_str()) {
+                Some(schema) => check(child, schema, &child_path, source, issues),
+                None => {
+                    let known: Vec<&str> = schema.keys().map(|key| key.as_str()).collect();
+                    let message = match closest(&key, &known) {
+                        Some(known) => format!("unknown key, did you mean {}?", known),
+                        None => "unknown key".to_string(),
+                    };
+                    issues.push(issue(&child_path, message, source));
+                    map.remove(key.as_str());
+                }
+            },
+        }
+    }
+}
+
+fn check_theme(theme: &mut Dynamic, path: &str, source: &str, issues: &mut Vec<ConfigIssue>) {
+    let schema = to_dynamic(Theme::default()).unwrap_or(Dynamic::UNIT);
+    check(theme, &schema, path, source, issues);
+
+    let Some(mut map) = theme.write_lock::<Map>() else { return };
+    for (key, color) in map.iter_mut() {
+        let Ok(hex) = color.clone().into_string() else { continue };
+
+        if theme::parse_hex(&hex).is_none() || !hex.starts_with('#') {
+            let message = format!("{:?} is not a color, expected \"#rrggbb\"", hex);
+            issues.push(ConfigIssue { path: format!("{}.{}", path, key), message, line: line_of(source, &hex) });
+            *color = Dynamic::UNIT;
+        }
+    }
+}
+
+fn check_keys(value: &mut Dynamic, known: &[&str], path: &str, source: &str, issues: &mut Vec<ConfigIssue>) {
+    let Some(mut map) = value.write_lock::<Map>() else {
+        issues.push(issue(path, format!("expected a map, found {}", type_name(value)), source));
+        return;
+    };
+
+    let unknown: Vec<String> = map.keys().filter(|key| !known.contains(&key.as_str())).map(|key| key.to_string()).collect();
+    for key in unknown {
+        let message = match closest(&key, known) {
+            Some(known) => format!("unknown key, did you mean {}?", known),
+            None => "unknown key".to_string(),
+        };
+        issues.push(issue(&format!("{}.{}", path, key), message, source));
+        map.remove(key.as_str());
+    }
+}
+
+/// Key bindings map keys to action names or command lines.
+fn check_bindings(bindings: &mut Dynamic, path: &str, source: &str, issues: &mut Vec<ConfigIssue>) {
+    let Some(mut map) = bindings.write_lock::<Map>() else { return };
+
+    map.retain(|keys, value| {
+        if value.is_string() { return true }
+        issues.push(issue(&format!("{}.{}", path, keys), format!("expected a string, found {}", type_name(value)), source));
+        false
+    });
+}
+
+fn issue(path: &str, message: String, source: &str) -> ConfigIssue {
+    let key = path.rsplit('.').next().unwrap_or(path);
+    ConfigIssue { path: path.to_string(), message, line: line_of(source, key) }
+}
+
+fn same_type(value: &Dynamic, schema: &Dynamic) -> bool {
+    // `4` is fine where `4.5` is expected
+    value.type_name() == schema.type_name() || (schema.is_float() && value.is_int())
+}
+
+fn type_name(value: &Dynamic) -> &'static str {
+    if value.is_int() { return "an integer" }
+    if value.is_float() { return "a number" }
+    if value.is_bool() { return "true or false" }
+    if value.is_string() { return "a string" }
+    if value.is_array() { return "an array" }
+    if value.is_map() { return "a map" }
+    if value.is_unit() { return "()" }
+    "something else"
+}
+
+/// The first line `word` appears on as a whole word.
+fn line_of(source: &str, word: &str) -> Option<usize> {
+    if word.is_empty() { return None }
+
+    let is_word = |ch: char| ch.is_alphanumeric() || ch == '_';
+    source.lines().position(|line| {
+        line.match_indices(word).any(|(index, _)| {
+            let before = line[..index].chars().next_back().is_none_or(|ch| !is_word(ch));
+            let after = line[index + word.len()..].chars().next().is_none_or(|ch| !is_word(ch));
+            before && after
+        })
+    }).map(|index| index + 1)
+}
+
+/// The known key that is at most two edits away from `key`, for typos.
+fn closest<'a>(key: &str, known: &[&'a str]) -> Option<&'a str> {
+    known.iter()
+        .map(|candidate| (edit_distance(key, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
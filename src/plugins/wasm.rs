@@ -0,0 +1,277 @@
+//! Plugins compiled to WebAssembly, loaded from `plugins/*.wasm` next to the Rhai ones with
+//! the `wasm` feature. They run sandboxed: a module only gets the imports below, no files,
+//! no network, a budget of instructions per call and at most `MAX_MEMORY` bytes of memory.
+//!
+//! A module imports from "oxidy":
+//!
+//! ```text
+//! register_command(name_ptr, name_len, description_ptr, description_len, export_ptr, export_len)
+//! on(event_ptr, event_len, export_ptr, export_len)     // the events of `on(...)` in Rhai
+//! log(message_ptr, message_len)                       // to the editor's log file
+//! ```
+//!
+//! and exports `memory`, `alloc(len) -> ptr` and optionally `oxidy_init()`, which runs once
+//! on load to register commands and hooks. The exports named there take `(ptr, len)` of a
+//! JSON request and return the pointer and length of a JSON response packed into an i64 as
+//! `ptr << 32 | len`, or 0 for no changes. The request is
+//!
+//! ```json
+//! { "editor": { "lines": [...], "row": 0, "col": 0, "range": { "start": 0, "end": 2 },
+//!               "path": "...", "filetype": "rs", "readonly": false },
+//!   "args": [...],                 // commands
+//!   "details": { "path": "..." } } // hooks
+//! ```
+//!
+//! and the response has the same meaning as the Rhai `editor` calls, every key optional:
+//! `{ "lines": [...], "cursor": { "row": 0, "col": 0 }, "messages": [...] }`. There is no
+//! `editor.command(...)`: a command line can run shell commands and write files, which would
+//! get around the sandbox, so a response with `commands` is refused.
+//!
+//! This is a subset of what Rhai scripts get: commands, hooks, logging and the `editor` object.
+//! Syntax and filetype definitions, pickers, text boxes, status segments, intervals and
+//! background tasks are only available to Rhai plugins for now.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use rhai::Map;
+use serde_json::{json, Value};
+use wasmtime::{Caller, Config, Engine, Instance, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::plugins::plugin_manager::HOOK_EVENTS;
+use crate::plugins::script_api::{EditorApi, ScriptState};
+
+/// Instructions a call may run, a plugin stuck in a loop is stopped instead of hanging the editor.
+const FUEL: u64 = 1_000_000_000;
+
+/// Bytes of linear memory a module may grow to.
+const MAX_MEMORY: usize = 64 * 1024 * 1024;
+
+/// What `oxidy_init` registered, moved into the host once it returned.
+#[derive(Default)]
+struct Registrations {
+    commands: Vec<(String, String, String)>,
+    hooks: Vec<(String, String)>,
+}
+
+/// What the store of a module holds.
+struct HostState {
+    registrations: Registrations,
+    limits: StoreLimits,
+}
+
+struct LoadedModule {
+    store: Store<HostState>,
+    instance: Instance,
+}
+
+/// A command a module registered, run by calling `export`.
+struct WasmCommand {
+    name: String,
+    description: String,
+    module: usize,
+    export: String,
+}
+
+pub struct WasmHost {
+    engine: Engine,
+    modules: Vec<Mutex<LoadedModule>>,
+    commands: Vec<WasmCommand>,
+    /// Event, module and export, in the order they were registered.
+    hooks: Vec<(String, usize, String)>,
+}
+
+impl WasmHost {
+    pub fn new() -> Result<Self, String> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|error| format!("Could not set up the WebAssembly engine: {}", error))?;
+
+        Ok(Self { engine, modules: vec![], commands: vec![], hooks: vec![] })
+    }
+
+    /// Forgets every module, before the plugins are loaded again.
+    pub fn clear(&mut self) {
+        self.modules.clear();
+        self.commands.clear();
+        self.hooks.clear();
+    }
+
+    /// Instantiates the module at `path` and runs its `oxidy_init`. A module that fails
+    /// registers nothing.
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let module = Module::from_file(&self.engine, path).map_err(|error| error.to_string())?;
+
+        let mut linker = Linker::new(&self.engine);
+        link(&mut linker).map_err(|error| error.to_string())?;
+
+        let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build();
+        let mut store = Store::new(&self.engine, HostState { registrations: Registrations::default(), limits });
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(FUEL).map_err(|error| error.to_string())?;
+        let instance = linker.instantiate(&mut store, &module).map_err(|error| error.to_string())?;
+
+        if let Ok(init) = instance.get_typed_func::<(), ()>(&mut store, "oxidy_init") {
+            init.call(&mut store, ()).map_err(|error| error.to_string())?;
+        }
+
+        let index = self.modules.len();
+        let registrations = std::mem::take(&mut store.data_mut().registrations);
+        for (name, description, export) in registrations.commands {
+            self.commands.retain(|command| command.name != name);
+            self.commands.push(WasmCommand { name, description, module: index, export });
+        }
+        for (event, export) in registrations.hooks {
+            self.hooks.push((event, index, export));
+        }
+
+        self.modules.push(Mutex::new(LoadedModule { store, instance }));
+        Ok(())
+    }
+
+    /// Names and descriptions of the commands modules registered.
+    pub fn commands(&self) -> Vec<(String, String)> {
+        self.commands.iter().map(|command| (command.name.clone(), command.description.clone())).collect()
+    }
+
+    pub fn has_command(&self, name: &str) -> bool {
+        self.commands.iter().any(|command| command.name == name)
+    }
+
+    pub fn call_command(&self, name: &str, editor: &EditorApi, args: Vec<String>) -> Result<(), String> {
+        let command = self.commands.iter()
+            .find(|command| command.name == name)
+            .ok_or_else(|| format!("No command {} in the plugins", name))?;
+
+        let request = json!({ "editor": editor.with(|state| editor_json(state)), "args": args });
+        self.call(command.module, &command.export, request, editor)
+    }
+
+    pub fn has_hooks(&self, event: &str) -> bool {
+        self.hooks.iter().any(|(hooked, _, _)| hooked == event)
+    }
+
+    /// Calls the exports subscribed to `event`, stops at the first one that fails.
+    pub fn call_hooks(&self, event: &str, editor: &EditorApi, details: &Map) -> Result<(), String> {
+        let details: serde_json::Map<String, Value> = details.iter()
+            .map(|(key, value)| (key.to_string(), Value::String(value.to_string())))
+            .collect();
+
+        for (_, module, export) in self.hooks.iter().filter(|(hooked, _, _)| hooked == event) {
+            let request = json!({ "editor": editor.with(|state| editor_json(state)), "details": details });
+            self.call(*module, export, request, editor)?;
+        }
+
+        Ok(())
+    }
+
+    /// Hands `request` to `export` and applies its response to `editor`.
+    fn call(&self, module: usize, export: &str, request: Value, editor: &EditorApi) -> Result<(), String> {
+        let mut module = self.modules[module].lock().unwrap();
+        let LoadedModule { store, instance } = &mut *module;
+        let failed = |error: wasmtime::Error| format!("{}: {}", export, error);
+
+        store.set_fuel(FUEL).map_err(failed)?;
+        let memory = instance.get_memory(&mut *store, "memory").ok_or("The module exports no memory")?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut *store, "alloc").map_err(failed)?;
+        let function = instance.get_typed_func::<(i32, i32), i64>(&mut *store, export).map_err(failed)?;
+
+        let request = request.to_string().into_bytes();
+        let pointer = alloc.call(&mut *store, request.len() as i32).map_err(failed)?;
+        memory.write(&mut *store, pointer as usize, &request).map_err(|error| format!("{}: {}", export, error))?;
+
+        let packed = function.call(&mut *store, (pointer, request.len() as i32)).map_err(failed)?;
+        if packed == 0 { return Ok(()) }
+
+        let (start, len) = ((packed >> 32) as u32 as usize, packed as u32 as usize);
+        let response = memory.data(&*store).get(start..start + len)
+            .ok_or_else(|| format!("{}: the response is out of bounds", export))?;
+        let response: Value = serde_json::from_slice(response)
+            .map_err(|error| format!("{}: the response is no JSON: {}", export, error))?;
+        if response.get("commands").is_some() {
+            return Err(format!("{}: modules can't run commands", export));
+        }
+
+        editor.with(|state| apply_response(state, &response));
+        Ok(())
+    }
+}
+
+/// Adds the imports modules get.
+fn link(linker: &mut Linker<HostState>) -> wasmtime::Result<()> {
+    linker.func_wrap("oxidy", "register_command", |mut caller: Caller<'_, HostState>, name: i32, name_len: i32, description: i32, description_len: i32, export: i32, export_len: i32| -> wasmtime::Result<()> {
+        let name = read_string(&mut caller, name, name_len)?;
+        let description = read_string(&mut caller, description, description_len)?;
+        let export = read_string(&mut caller, export, export_len)?;
+        caller.data_mut().registrations.commands.push((name, description, export));
+        Ok(())
+    })?;
+
+    linker.func_wrap("oxidy", "on", |mut caller: Caller<'_, HostState>, event: i32, event_len: i32, export: i32, export_len: i32| -> wasmtime::Result<()> {
+        let event = read_string(&mut caller, event, event_len)?;
+        if !HOOK_EVENTS.contains(&event.as_str()) {
+            return Err(wasmtime::Error::msg(format!("Unknown event {}, expected one of {}", event, HOOK_EVENTS.join(", "))));
+        }
+
+        let export = read_string(&mut caller, export, export_len)?;
+        caller.data_mut().registrations.hooks.push((event, export));
+        Ok(())
+    })?;
+
+    linker.func_wrap("oxidy", "log", |mut caller: Caller<'_, HostState>, message: i32, message_len: i32| -> wasmtime::Result<()> {
+        let message = read_string(&mut caller, message, message_len)?;
+        crate::log!("[wasm] {}", message);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// The UTF-8 string at `pointer` in the memory of the calling module.
+fn read_string(caller: &mut Caller<'_, HostState>, pointer: i32, len: i32) -> wasmtime::Result<String> {
+    let memory = caller.get_export("memory")
+        .and_then(|export| export.into_memory())
+        .ok_or_else(|| wasmtime::Error::msg("The module exports no memory"))?;
+
+    let start = pointer as u32 as usize;
+    let bytes = memory.data(&*caller).get(start..start + len as u32 as usize)
+        .ok_or_else(|| wasmtime::Error::msg("String out of bounds"))?;
+
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn editor_json(state: &ScriptState) -> Value {
+    json!({
+        "lines": state.lines,
+        "row": state.row,
+        "col": state.col,
+        "range": state.range.map(|(start, end)| json!({ "start": start, "end": end })),
+        "path": state.path,
+        "filetype": state.filetype,
+        "readonly": state.readonly,
+    })
+}
+
+fn apply_response(state: &mut ScriptState, response: &Value) {
+    let strings = |key: &str| -> Option<Vec<String>> {
+        let values = response.get(key)?.as_array()?;
+        Some(values.iter().map(|value| value.as_str().map(String::from).unwrap_or_else(|| value.to_string())).collect())
+    };
+
+    if let Some(lines) = strings("lines") {
+        state.lines = lines;
+        if state.lines.is_empty() {
+            state.lines.push(String::new());
+        }
+        state.lines_changed = true;
+    }
+
+    if let Some(cursor) = response.get("cursor") {
+        let position = |key: &str| cursor.get(key).and_then(Value::as_u64).unwrap_or(0) as usize;
+        state.row = position("row");
+        state.col = position("col");
+        state.cursor_changed = true;
+    }
+
+    state.messages.extend(strings("messages").unwrap_or_default());
+}
@@ -0,0 +1,175 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rhai::{Dynamic, EvalAltResult, FnPtr, Map};
+
+/// Where a text box sits on the screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+impl Position {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "top_left" => Some(Position::TopLeft),
+            "top_right" => Some(Position::TopRight),
+            "bottom_left" => Some(Position::BottomLeft),
+            "bottom_right" => Some(Position::BottomRight),
+            "center" => Some(Position::Center),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WidgetKind {
+    TextBox(Position),
+    // Shown in the status bar after the file
+    Status,
+    // A list to pick a line from, the palette shows it
+    Picker,
+}
+
+/// Something a script put on the screen, by name. A new one with the same name replaces it.
+#[derive(Clone)]
+pub struct Widget {
+    pub name: String,
+    pub kind: WidgetKind,
+    pub title: String,
+    pub lines: Vec<String>,
+    /// Returns fresh lines every `every`, like a clock.
+    pub render: Option<FnPtr>,
+    pub every: Duration,
+    pub rendered: Option<Instant>,
+    /// Gets `editor` and the line picked from a picker.
+    pub on_pick: Option<FnPtr>,
+    // The plugin that showed it, `None` for config.rhai
+    pub script: Option<usize>,
+}
+
+impl Widget {
+    pub fn new(name: &str, kind: WidgetKind, script: Option<usize>) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            title: String::new(),
+            lines: vec![],
+            render: None,
+            every: Duration::from_secs(1),
+            rendered: None,
+            on_pick: None,
+            script,
+        }
+    }
+
+    /// A text box from the options of `text_box(name, #{ ... })`.
+    pub fn text_box(name: &str, options: Map, script: Option<usize>) -> Result<Self, Box<EvalAltResult>> {
+        let position = match options.get("position") {
+            Some(position) => {
+                let position = position.to_string();
+                Position::parse(&position).ok_or_else(|| format!("Unknown position {}, expected top_left, top_right, bottom_left, bottom_right or center", position))?
+            }
+            None => Position::TopRight,
+        };
+
+        let mut widget = Self::new(name, WidgetKind::TextBox(position), script);
+        widget.title = options.get("title").map(|title| title.to_string()).unwrap_or_default();
+        widget.lines = options.get("lines").cloned().map(lines).unwrap_or_default();
+        widget.render = options.get("render").and_then(|render| render.clone().try_cast::<FnPtr>());
+
+        if let Some(every) = options.get("every").and_then(|every| every.as_int().ok()) {
+            widget.every = Duration::from_millis(every.max(100) as u64);
+        }
+
+        Ok(widget)
+    }
+
+    /// Whether `render` should run again.
+    pub fn due(&self) -> bool {
+        self.render.is_some() && self.rendered.is_none_or(|at| at.elapsed() >= self.every)
+    }
+}
+
+/// Lines from what a script returned, an array gives one per element and a string is split.
+pub fn lines(value: Dynamic) -> Vec<String> {
+    if value.is_unit() {
+        return vec![];
+    }
+
+    if value.is_array() {
+        let array = value.cast::<rhai::Array>();
+        return array.into_iter().map(|line| line.to_string()).collect();
+    }
+
+    value.to_string().lines().map(String::from).collect()
+}
+
+/// The widgets scripts have shown, shared with the functions registered for them.
+#[derive(Clone, Default)]
+pub struct WidgetRegistry {
+    widgets: Arc<Mutex<Vec<Widget>>>,
+    changed: Arc<AtomicBool>,
+}
+
+impl WidgetRegistry {
+    pub fn show(&self, widget: Widget) {
+        let mut widgets = self.widgets.lock().unwrap();
+        match widgets.iter_mut().find(|shown| shown.name == widget.name) {
+            Some(shown) => *shown = widget,
+            None => widgets.push(widget),
+        }
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// Takes the widget off the screen and returns it.
+    pub fn close(&self, name: &str) -> Option<Widget> {
+        let mut widgets = self.widgets.lock().unwrap();
+        let index = widgets.iter().position(|widget| widget.name == name)?;
+        self.changed.store(true, Ordering::Relaxed);
+        Some(widgets.remove(index))
+    }
+
+    pub fn retain(&self, keep: impl Fn(&Widget) -> bool) {
+        self.widgets.lock().unwrap().retain(keep);
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    pub fn all(&self) -> Vec<Widget> {
+        self.widgets.lock().unwrap().clone()
+    }
+
+    pub fn due(&self) -> Vec<Widget> {
+        self.widgets.lock().unwrap().iter().filter(|widget| widget.due()).cloned().collect()
+    }
+
+    /// Replaces the lines `render` came up with, a failed one stops rendering.
+    pub fn rendered(&self, name: &str, result: Result<Vec<String>, String>) {
+        let mut widgets = self.widgets.lock().unwrap();
+        let Some(widget) = widgets.iter_mut().find(|widget| widget.name == name) else { return };
+        widget.rendered = Some(Instant::now());
+
+        let lines = match result {
+            Ok(lines) => lines,
+            Err(error) => {
+                widget.render = None;
+                vec![error]
+            }
+        };
+
+        if widget.lines != lines {
+            widget.lines = lines;
+            self.changed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether anything was shown, changed or closed since the last call.
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::services::lsp_manager::ServerKey;
+
+// Linux reports CPU time in clock ticks, this is the value on every common setup
+const TICKS_PER_SECOND: f64 = 100.0;
+const PAGE_SIZE: u64 = 4096;
+
+/// Who owns a child process, and so how it is restarted or killed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProcessOwner {
+    Lsp(ServerKey),
+    Repl,
+    Transform,
+    // A `:!` command, by pid
+    Shell(u32),
+    // A command of `spawn_task`, by task id
+    Task(rhai::INT),
+}
+
+impl ProcessOwner {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProcessOwner::Lsp(_) => "lsp",
+            ProcessOwner::Repl => "repl",
+            ProcessOwner::Transform => "job",
+            ProcessOwner::Shell(_) => "shell",
+            ProcessOwner::Task(_) => "task",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Process {
+    pub owner: ProcessOwner,
+    pub name: String,
+    pub pid: u32,
+    pub started: Instant,
+    /// Percent of one core since the last sample, `None` where /proc isn't available.
+    pub cpu: Option<f64>,
+    /// Resident memory in bytes.
+    pub rss: Option<u64>,
+}
+
+/// Every process the editor spawned and still owns, for `:ps`.
+pub struct ProcessRegistry {
+    processes: Vec<Process>,
+    /// CPU ticks used by each pid at the last sample.
+    ticks: HashMap<u32, (Instant, u64)>,
+}
+
+impl ProcessRegistry {
+    pub fn new() -> Self {
+        Self {
+            processes: vec![],
+            ticks: HashMap::new(),
+        }
+    }
+
+    pub fn processes(&self) -> &[Process] {
+        &self.processes
+    }
+
+    /// Replaces the list with what is running now. Processes that were running before
+    /// keep their start time, new ones start counting now.
+    pub fn update(&mut self, running: Vec<(ProcessOwner, String, u32)>) {
+        let previous = std::mem::take(&mut self.processes);
+
+        self.processes = running.into_iter()
+            .map(|(owner, name, pid)| {
+                let started = previous.iter()
+                    .find(|process| process.pid == pid)
+                    .map(|process| process.started)
+                    .unwrap_or_else(Instant::now);
+
+                Process { owner, name, pid, started, cpu: None, rss: None }
+            })
+            .collect();
+
+        self.ticks.retain(|pid, _| self.processes.iter().any(|process| process.pid == *pid));
+    }
+
+    /// Reads CPU and memory use of every process.
+    pub fn sample(&mut self) {
+        let now = Instant::now();
+
+        for process in self.processes.iter_mut() {
+            process.rss = read_rss(process.pid);
+
+            let ticks = match read_ticks(process.pid) {
+                Some(ticks) => ticks,
+                None => {
+                    process.cpu = None;
+                    continue;
+                }
+            };
+
+            process.cpu = self.ticks.get(&process.pid)
+                .map(|(at, before)| {
+                    let elapsed = now.duration_since(*at).as_secs_f64().max(0.001);
+                    ticks.saturating_sub(*before) as f64 / TICKS_PER_SECOND / elapsed * 100.0
+                });
+            self.ticks.insert(process.pid, (now, ticks));
+        }
+    }
+}
+
+/// Time spent in user and kernel mode, fields 14 and 15 of /proc/<pid>/stat.
+fn read_ticks(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+
+    // The command name in parentheses may contain spaces
+    let fields: Vec<&str> = stat.get(stat.rfind(')')? + 2..)?.split(' ').collect();
+    let user: u64 = fields.get(11)?.parse().ok()?;
+    let system: u64 = fields.get(12)?.parse().ok()?;
+
+    Some(user + system)
+}
+
+fn read_rss(pid: u32) -> Option<u64> {
+    let statm = std::fs::read_to_string(format!("/proc/{}/statm", pid)).ok()?;
+    let pages: u64 = statm.split(' ').nth(1)?.parse().ok()?;
+
+    Some(pages * PAGE_SIZE)
+}
+
+pub fn format_uptime(uptime: Duration) -> String {
+    let secs = uptime.as_secs();
+
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
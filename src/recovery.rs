@@ -0,0 +1,27 @@
+use std::path::PathBuf;
+
+use crate::buffer::Buffer;
+use crate::types::BufferId;
+
+/// Where unsaved buffers are written when the editor can't ask what to do with them.
+pub fn dir() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("oxidy/recovery")
+}
+
+/// Writes the content of `buffer` to the recovery directory. The file is named after the
+/// buffer's path with `/` replaced by `%`, scratch buffers get the pid and their id.
+pub fn write(id: BufferId, buffer: &Buffer) -> std::io::Result<PathBuf> {
+    let dir = dir();
+    std::fs::create_dir_all(&dir)?;
+
+    let name = match buffer.is_scratch() {
+        true => format!("scratch-{}-{}", std::process::id(), id.0),
+        false => buffer.path.replace('/', "%"),
+    };
+    let path = dir.join(name);
+
+    std::fs::write(&path, buffer.file_bytes()?)?;
+    Ok(path)
+}
@@ -1,27 +1,69 @@
-use std::io::{self, stdout, Stdout, Write, StdoutLock};
+use std::io::{self, stdout, Stdout, Write};
 
 use crossterm::cursor::SetCursorStyle;
-use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
-use crossterm::style::{Color, ContentStyle, ResetColor, SetStyle, StyledContent, Stylize};
+use crossterm::event::{DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste, EnableFocusChange, EnableMouseCapture};
+use crossterm::style::{Attribute, Color, ContentStyle, ResetColor, SetAttribute, SetStyle, StyledContent, Stylize};
 use crossterm::{cursor::{self, MoveTo}, terminal, QueueableCommand};
 use crossterm::{queue, ExecutableCommand};
 
-use unicode_width::UnicodeWidthStr;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 use unicode_segmentation::UnicodeSegmentation;
 
-use crate::highlighter::Highlighter;
+use crate::highlighter::{Highlighter, MAX_HIGHLIGHT_LENGTH};
 use crate::plugins::config::Config;
+use crate::plugins::theme;
 use crate::renderer::{Renderer, Layer};
-use crate::buffer::{Buffer, BufferView};
+use crate::buffer::{Buffer, BufferView, Selection};
 use crate::types::{Token, EditorMode, RenderBuffer, RenderCell, RenderLine, Size, Grid, Rect, ViewId};
 use crate::ui::command::Command;
 use crate::ui::ui_manager::UiManager;
 use crate::editor::Editor;
+use crate::links;
+use crate::types::Cursor;
+use crate::collab::RemoteCursor;
+use crate::diagnostics;
+use crate::inlay_hints::{self, InlayHint};
+use crate::folding::{self, Fold};
+use crate::whitespace;
+use crate::markdown::{self, PreviewStyle};
 
 use crate::log;
 
+pub const GUTTER_WIDTH: u16 = 6;
+
+/// Cells `start..end` of a row, which begin at terminal column `column`.
+#[doc(hidden)]
+#[derive(Debug, PartialEq)]
+pub struct DirtySpan {
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The terminal columns a cell takes, 2 for wide characters like CJK, 0 for combining marks.
+fn cell_width(cell: &RenderCell) -> usize {
+    cell.ch.width().unwrap_or(0)
+}
+
 pub struct GutterLayer;
 
+/// Puts the cells of the cursor's line on the cursorline background. Cells with a background
+/// of their own, like the selection, keep it.
+fn highlight_cursor_line(row: &mut [RenderCell], config: &Config) {
+    let theme = config.current_theme();
+    let background = theme.background();
+    let cursor_line = theme.cursor_line();
+
+    for cell in row.iter_mut() {
+        if cell.transparent {
+            *cell = RenderCell::space(config);
+        }
+        if cell.style.background_color == Some(background) {
+            cell.style.background_color = Some(cursor_line);
+        }
+    }
+}
+
 impl Layer for GutterLayer {
     fn render(editor: &Editor, view: &BufferView, ui: &UiManager, config: &Config, rect: Rect) -> Grid<RenderCell> {
         let mut grid = Grid::new(
@@ -48,13 +90,18 @@ impl Layer for GutterLayer {
         let buffer = editor.buffer(&view.buffer).unwrap();
         let total_lines = buffer.lines.len();
 
-        let scroll = view.scroll.vertical;
         let cursor_line = view.cursor.row;
+        let opt = view.options(&config.opt);
 
-        let use_relative = config.opt.relative_numbers.unwrap();
+        let use_relative = opt.relative_numbers.unwrap_or(false);
+
+        let rows = view.screen_rows(total_lines);
 
         for screen_row in 0..rect.rows as usize {
-            let buffer_row = screen_row + scroll;
+            let buffer_row = match rows.get(screen_row) {
+                Some(row) => *row,
+                None => total_lines,
+            };
 
             if buffer_row >= total_lines {
                 for col in 0..gutter_width {
@@ -78,6 +125,19 @@ impl Layer for GutterLayer {
             let text = format!("{:>width$} ", line_number, width = gutter_width - 1);
 
             for (i, ch) in text.chars().enumerate() {
+                if i == 0 {
+                    if let Some(severity) = diagnostics::worst_on(&buffer.diagnostics, buffer_row) {
+                        grid.cells[screen_row][i] = RenderCell {
+                            ch: severity.sign(),
+                            style: ContentStyle::new()
+                                .on(config.current_theme().background())
+                                .with(severity.color()),
+                            transparent: false
+                        };
+                        continue;
+                    }
+                }
+
                 let mut fg = Color::DarkGrey;
 
                 if buffer_row == cursor_line && view.id == active_view.id {
@@ -92,6 +152,22 @@ impl Layer for GutterLayer {
                     transparent: false
                 };
             }
+
+            // In the column between the number and the text
+            let git_sign = buffer.git_signs.get(buffer_row).copied().flatten();
+            if let Some(sign) = git_sign.filter(|_| opt.git_signs.unwrap_or(true)) {
+                grid.cells[screen_row][gutter_width - 1] = RenderCell {
+                    ch: sign.sign(),
+                    style: ContentStyle::new()
+                        .on(config.current_theme().background())
+                        .with(sign.color()),
+                    transparent: false
+                };
+            }
+
+            if opt.cursorline.unwrap_or(false) && buffer_row == cursor_line && view.id == active_view.id {
+                highlight_cursor_line(&mut grid.cells[screen_row], config);
+            }
         }
 
         
@@ -107,17 +183,28 @@ impl TextLayer {
         grid: &mut Grid<RenderCell>,
         buffer: &Buffer,
         view: &BufferView,
+        hover: Option<&Cursor>,
+        remote_cursors: &[RemoteCursor],
         config: &Config,
         rect: Rect,
     ) {
         let bg = config.current_theme().background();
         let fg = config.current_theme().foreground();
 
-        let first_line = view.scroll.vertical;
-        let last_line  = first_line + rect.rows as usize;
+        view.highlighter.update_syntax(buffer, &config.current_theme().to_map());
+        let opt = view.options(&config.opt);
+
+        let rows = view.screen_rows(buffer.lines.len());
+        let code_rows = match view.markdown_preview {
+            true => markdown::code_rows(&buffer.lines),
+            false => vec![],
+        };
 
         for screen_row in 0..rect.rows as usize {
-            let buffer_row = first_line + screen_row;
+            let buffer_row = match rows.get(screen_row) {
+                Some(row) => *row,
+                None => buffer.lines.len(),
+            };
 
             if buffer_row >= buffer.lines.len() {
                 Self::render_empty_line(&mut grid.cells[screen_row], config);
@@ -126,6 +213,18 @@ impl TextLayer {
 
             let text = &buffer.lines[buffer_row];
 
+            // The cursor's line shows its markup, so it can be edited
+            if view.markdown_preview && buffer_row != view.cursor.row {
+                let code = code_rows.get(buffer_row).copied().unwrap_or(false);
+                Self::render_preview_line(&mut grid.cells[screen_row], text, code, view.scroll.horizontal, config);
+                continue;
+            }
+
+            if text.len() > MAX_HIGHLIGHT_LENGTH {
+                Self::render_plain_line(&mut grid.cells[screen_row], text, view.scroll.horizontal, config);
+                continue;
+            }
+
             let tokens = view.highlighter.highlight(text, buffer_row);
 
             Self::render_highlighted_line(
@@ -135,6 +234,216 @@ impl TextLayer {
                 view.scroll.horizontal,
                 config
             );
+
+            if opt.list.unwrap_or(false) {
+                Self::render_whitespace(&mut grid.cells[screen_row], text, view.scroll.horizontal);
+            }
+
+            for pos in [hover, Some(&view.cursor)].into_iter().flatten() {
+                if pos.row == buffer_row {
+                    Self::underline_link(&mut grid.cells[screen_row], text, pos.col, view.scroll.horizontal);
+                }
+            }
+
+            Self::underline_diagnostics(&mut grid.cells[screen_row], buffer, buffer_row, view.scroll.horizontal);
+
+            if let Some(selection) = &view.selection {
+                Self::render_selection(&mut grid.cells[screen_row], selection, text, buffer_row, view.scroll.horizontal);
+            }
+
+            for cursor in remote_cursors.iter().filter(|cursor| cursor.row == buffer_row) {
+                Self::render_remote_cursor(&mut grid.cells[screen_row], cursor, view.scroll.horizontal, config);
+            }
+
+            // Last, since it moves the rest of the line to the right
+            Self::render_inlay_hints(&mut grid.cells[screen_row], &buffer.inlay_hints, buffer_row, view.scroll.horizontal, config);
+
+            if let Some(fold) = folding::closed_at(&view.folds, buffer_row) {
+                Self::render_fold_summary(&mut grid.cells[screen_row], text, fold, view.scroll.horizontal, config);
+            } else if opt.diagnostic_text.unwrap_or(true) {
+                Self::render_diagnostic_text(&mut grid.cells[screen_row], buffer, text, buffer_row, view.scroll.horizontal, config);
+            }
+
+            if opt.cursorline.unwrap_or(false) && buffer_row == view.cursor.row {
+                highlight_cursor_line(&mut grid.cells[screen_row], config);
+            }
+        }
+    }
+
+    /// Draws a line of the Markdown preview, without its markup.
+    fn render_preview_line(row: &mut [RenderCell], text: &str, code: bool, horiz_scroll: usize, config: &Config) {
+        let theme = config.current_theme();
+        let colors = theme.to_map();
+        let color = |key: &str| colors.get(key).copied().unwrap_or(theme.foreground());
+        let base = ContentStyle::new().on(theme.background()).with(theme.foreground());
+
+        for (col, (ch, style)) in markdown::preview_line(text, code).into_iter().enumerate().skip(horiz_scroll) {
+            let style = match style {
+                PreviewStyle::Plain => base,
+                PreviewStyle::Heading(1) => base.with(color("keyword")).bold().underlined(),
+                PreviewStyle::Heading(_) => base.with(color("keyword")).bold(),
+                PreviewStyle::Strong => base.bold(),
+                PreviewStyle::Emphasis => base.italic(),
+                PreviewStyle::Code => base.with(color("string")).on(theme.cursor_line()),
+                PreviewStyle::Marker => base.with(color("number")),
+                PreviewStyle::Quote => base.with(color("comment")).italic(),
+            };
+
+            match row.get_mut(col - horiz_scroll) {
+                Some(cell) => *cell = RenderCell { ch, style, transparent: false },
+                None => return
+            }
+        }
+    }
+
+    /// Replaces tabs, non-breaking spaces and trailing spaces with dim markers.
+    fn render_whitespace(row: &mut [RenderCell], text: &str, horiz_scroll: usize) {
+        for (col, marker) in whitespace::markers(text).into_iter().enumerate() {
+            let marker = match marker {
+                Some(marker) if col >= horiz_scroll => marker,
+                _ => continue
+            };
+
+            if let Some(cell) = row.get_mut(col - horiz_scroll) {
+                cell.ch = marker;
+                cell.style.foreground_color = Some(Color::DarkGrey);
+            }
+        }
+    }
+
+    /// Writes how many lines are folded away after the end of the fold's first line.
+    fn render_fold_summary(row: &mut [RenderCell], text: &str, fold: &Fold, horiz_scroll: usize, config: &Config) {
+        let style = ContentStyle::new()
+            .on(config.current_theme().background())
+            .with(Color::DarkGrey);
+
+        let summary = format!(" ··· {} lines", fold.len());
+        let start = (text.chars().count() + 1).saturating_sub(horiz_scroll);
+
+        for (i, ch) in summary.chars().enumerate() {
+            if let Some(cell) = row.get_mut(start + i) {
+                *cell = RenderCell { ch, style, transparent: false };
+            }
+        }
+    }
+
+    /// Writes the message of the worst diagnostic starting on the row after the end of the line.
+    fn render_diagnostic_text(row: &mut [RenderCell], buffer: &Buffer, text: &str, buffer_row: usize, horiz_scroll: usize, config: &Config) {
+        let diagnostic = match diagnostics::shown_on(&buffer.diagnostics, buffer_row) {
+            Some(diagnostic) => diagnostic,
+            None => return
+        };
+
+        let style = ContentStyle::new()
+            .on(config.current_theme().background())
+            .with(diagnostic.severity.color())
+            .italic();
+
+        let hints: usize = inlay_hints::on_row(&buffer.inlay_hints, buffer_row).iter().map(|hint| hint.width()).sum();
+        let end = text.chars().count() + hints + 2;
+        // Only the first line, the rest is in the diagnostics panel
+        let message = format!("■ {}", diagnostic.message.lines().next().unwrap_or(""));
+
+        for (i, ch) in message.chars().enumerate() {
+            if end + i < horiz_scroll { continue }
+
+            if let Some(cell) = row.get_mut(end + i - horiz_scroll) {
+                *cell = RenderCell { ch, style, transparent: false };
+            }
+        }
+    }
+
+    /// Inserts the hints as dimmed virtual text, pushing the text after them to the right.
+    fn render_inlay_hints(row: &mut [RenderCell], hints: &[InlayHint], buffer_row: usize, horiz_scroll: usize, config: &Config) {
+        let style = ContentStyle::new()
+            .on(config.current_theme().background())
+            .with(Color::DarkGrey)
+            .italic();
+
+        let mut shift = 0;
+
+        for hint in inlay_hints::on_row(hints, buffer_row) {
+            if hint.col < horiz_scroll { continue }
+
+            let at = hint.col - horiz_scroll + shift;
+            if at >= row.len() { return }
+
+            let width = hint.width().min(row.len() - at);
+            row[at..].rotate_right(width);
+
+            for (i, ch) in hint.label.chars().take(width).enumerate() {
+                row[at + i] = RenderCell { ch, style, transparent: false };
+            }
+
+            shift += hint.width();
+        }
+    }
+
+    /// Highlights the cell of another participant's cursor and writes their name right after it.
+    fn render_remote_cursor(row: &mut [RenderCell], cursor: &RemoteCursor, horiz_scroll: usize, config: &Config) {
+        const COLORS: [Color; 4] = [
+            Color::Rgb { r: 234, g: 131, b: 165 },
+            Color::Rgb { r: 146, g: 162, b: 213 },
+            Color::Rgb { r: 144, g: 185, b: 159 },
+            Color::Rgb { r: 230, g: 185, b: 157 },
+        ];
+
+        if cursor.col < horiz_scroll { return }
+
+        let color = COLORS[cursor.site as usize % COLORS.len()];
+        let col = cursor.col - horiz_scroll;
+
+        if let Some(cell) = row.get_mut(col) {
+            cell.style = cell.style.on(color).with(config.current_theme().background());
+        }
+
+        let label = ContentStyle::new().on(color).with(config.current_theme().background());
+
+        for (i, ch) in cursor.name.chars().enumerate() {
+            if let Some(cell) = row.get_mut(col + 1 + i) {
+                *cell = RenderCell { ch, style: label, transparent: false };
+            }
+        }
+    }
+
+    fn render_selection(row: &mut [RenderCell], selection: &Selection, text: &str, buffer_row: usize, horiz_scroll: usize) {
+        let (start, end) = match selection.cols_on(buffer_row, text.chars().count()) {
+            Some(cols) => cols,
+            None => return
+        };
+
+        for logical_col in start.max(horiz_scroll)..end {
+            if let Some(cell) = row.get_mut(logical_col - horiz_scroll) {
+                cell.style = cell.style.on(Color::Rgb { r: 68, g: 68, b: 72 });
+            }
+        }
+    }
+
+    fn underline_diagnostics(row: &mut [RenderCell], buffer: &Buffer, buffer_row: usize, horiz_scroll: usize) {
+        let line_len = buffer.lines[buffer_row].chars().count();
+
+        for diagnostic in &buffer.diagnostics {
+            let (start, end) = match diagnostic.cols_on(buffer_row, line_len) {
+                Some(cols) => cols,
+                None => continue
+            };
+
+            for logical_col in start.max(horiz_scroll)..end {
+                if let Some(cell) = row.get_mut(logical_col - horiz_scroll) {
+                    cell.style = cell.style.undercurled();
+                    cell.style.underline_color = Some(diagnostic.severity.color());
+                }
+            }
+        }
+    }
+
+    fn underline_link(row: &mut [RenderCell], text: &str, col: usize, horiz_scroll: usize) {
+        if let Some(link) = links::link_at(text, col) {
+            for logical_col in link.start.max(horiz_scroll)..link.end {
+                if let Some(cell) = row.get_mut(logical_col - horiz_scroll) {
+                    cell.style = cell.style.underlined();
+                }
+            }
         }
     }
 
@@ -144,6 +453,31 @@ impl TextLayer {
         }
     }
 
+    /// Renders only the visible slice of a very long line, marking that it continues.
+    fn render_plain_line(row: &mut [RenderCell], text: &str, horiz_scroll: usize, config: &Config) {
+        let style = RenderCell::default_style(config);
+        let mut chars = text.chars().skip(horiz_scroll);
+
+        for cell in row.iter_mut() {
+            match chars.next() {
+                Some(ch) => *cell = RenderCell { ch, style, transparent: false },
+                None => return,
+            }
+        }
+
+        if chars.next().is_some() {
+            if let Some(last) = row.last_mut() {
+                *last = RenderCell {
+                    ch: '…',
+                    style: ContentStyle::new()
+                        .on(config.current_theme().background())
+                        .with(Color::DarkGrey),
+                    transparent: false
+                };
+            }
+        }
+    }
+
     fn render_highlighted_line(
         row: &mut [RenderCell],
         text: &str,
@@ -160,7 +494,14 @@ impl TextLayer {
 
             let mut logical_col = token.offset;
 
+            if logical_col >= horiz_scroll + row.len() { return; }
+
             for ch in token.text.chars() {
+                if logical_col < horiz_scroll {
+                    logical_col += 1;
+                    continue;
+                }
+
                 let screen_col = logical_col - horiz_scroll;
 
                 if screen_col >= row.len() { return; }
@@ -185,7 +526,7 @@ impl Layer for TextLayer {
         let buffer = editor.active_buffer();
 
         if let Some(buffer) = buffer {
-            Self::render_lines(&mut grid, buffer, view, config, rect);
+            Self::render_lines(&mut grid, buffer, view, editor.hover.as_ref(), &editor.remote_cursors, config, rect);
         }
 
         grid
@@ -249,16 +590,41 @@ pub struct CrossTermRenderer {
     pub size: Size,
     pub previous_frame: Grid<RenderCell>,
     pub output: Stdout,
+    // Whether the terminal understands OSC 12, checked once at startup
+    sets_cursor_color: bool,
+    // The cursor color last sent, `None` while the terminal has its own
+    cursor_color: Option<Color>,
+    // Whether the terminal draws the cursor shape DECSCUSR asks for, checked once at startup
+    sets_cursor_style: bool,
+    // The Normal mode cursor of the last frame was an inverted cell instead of the hardware one
+    cell_cursor: bool,
+    // Whether the terminal said it draws 24-bit colors, checked once at startup
+    true_color: bool,
 }
 
 impl CrossTermRenderer {
-    pub fn new(size: Size) -> Self {
+    /// Takes over the terminal. Fails if it can't be put in raw mode on the alternate screen,
+    /// what only some terminals support is logged and left off.
+    pub fn new(size: Size) -> io::Result<Self> {
         let mut output = stdout();
-        output.execute(terminal::EnterAlternateScreen).expect("Could not enter Alternate Screen.");
-        terminal::enable_raw_mode().expect("Could not enable raw mode.");
-        output.execute(EnableMouseCapture).expect("Could not enable mouse capture.");
+        output.execute(terminal::EnterAlternateScreen)?;
+        if let Err(error) = terminal::enable_raw_mode() {
+            let _ = output.execute(terminal::LeaveAlternateScreen);
+            return Err(error);
+        }
 
-        Self { 
+        if let Err(error) = output.execute(EnableMouseCapture) {
+            log!("Could not enable mouse capture: {}", error);
+        }
+        if let Err(error) = output.execute(EnableFocusChange) {
+            log!("Could not enable focus change reporting: {}", error);
+        }
+        // Pastes arrive as one event instead of keys, so they skip the keymap
+        if let Err(error) = output.execute(EnableBracketedPaste) {
+            log!("Could not enable bracketed paste: {}", error);
+        }
+
+        Ok(Self { 
             size: size.clone(),
             previous_frame: Grid::new(
                 size.rows as usize,
@@ -266,38 +632,207 @@ impl CrossTermRenderer {
                 RenderCell::blank()
             ),
             output: output,
+            sets_cursor_color: terminal_sets_cursor_color(),
+            cursor_color: None,
+            sets_cursor_style: terminal_sets_cursor_style(),
+            cell_cursor: false,
+            true_color: terminal_true_color(),
+        })
+    }
+
+    /// Draws a composed frame and puts the cursor on it.
+    pub fn present(&mut self, mut frame: Grid<RenderCell>, cursor: Option<ScreenCursor>, config: &Config) {
+        self.cell_cursor = false;
+
+        if let Some(cursor) = cursor {
+            let style = match (cursor.bar, cursor.blink) {
+                (true, true) => SetCursorStyle::BlinkingBar,
+                (true, false) => SetCursorStyle::SteadyBar,
+                (false, true) => SetCursorStyle::BlinkingBlock,
+                (false, false) => SetCursorStyle::SteadyBlock,
+            };
+            let _ = self.output.queue(style);
+            self.set_cursor_color(cursor.color);
+
+            // The block is drawn into the frame, the hardware cursor stays hidden until Insert mode
+            if !cursor.bar && self.uses_cell_cursor(config) {
+                if let Some(cell) = frame.cells.get_mut(cursor.row as usize).and_then(|line| line.get_mut(cursor.col as usize)) {
+                    cell.style.attributes.set(Attribute::Reverse);
+                    self.cell_cursor = true;
+                }
+            }
+
+        }
+
+        if !self.uses_true_color(config) {
+            for cell in frame.cells.iter_mut().flatten() {
+                cell.style = downsample(cell.style);
+            }
         }
+
+        self.draw_frame(frame, cursor.map(|cursor| (cursor.col, cursor.row)), config);
+    }
+
+    /// The `true_color` option, or what the terminal told us without it.
+    fn uses_true_color(&self, config: &Config) -> bool {
+        config.opt.true_color.unwrap_or(self.true_color)
     }
 
-    fn draw_frame(&mut self, frame: Grid<RenderCell>, config: &Config) {
+    /// Whether the Normal mode block is drawn as an inverted cell, from the `cell_cursor`
+    /// option: "always", "never", or "auto" for terminals that can't change the cursor shape.
+    fn uses_cell_cursor(&self, config: &Config) -> bool {
+        match config.opt.cell_cursor.as_deref() {
+            Some("always") => true,
+            Some("never") => false,
+            _ => !self.sets_cursor_style,
+        }
+    }
+
+    /// Sets the cursor color with OSC 12 when it changed, or gives the terminal its own back
+    /// with OSC 112 when `color` is `None`.
+    fn set_cursor_color(&mut self, color: Option<Color>) {
+        if !self.sets_cursor_color || color == self.cursor_color { return }
+
+        let written = match color {
+            Some(Color::Rgb { r, g, b }) => write!(self.output, "\x1b]12;#{:02x}{:02x}{:02x}\x07", r, g, b),
+            _ => write!(self.output, "\x1b]112\x07"),
+        };
+
+        match written {
+            Ok(()) => self.cursor_color = color,
+            Err(error) => self.draw_failed("set cursor color", error),
+        }
+    }
+
+    fn draw_frame(&mut self, frame: Grid<RenderCell>, cursor: Option<(u16, u16)>, config: &Config) {
+        let padding = match self.uses_true_color(config) {
+            true => RenderCell::default_style(config),
+            false => downsample(RenderCell::default_style(config)),
+        };
+
         let mut out = self.output.lock();
+        let result = Self::write_frame(&mut out, &self.previous_frame, &frame, cursor, self.size.cols as usize, padding);
+        drop(out);
+
+        self.previous_frame = frame;
 
-        queue!(out, MoveTo(0, 0)).unwrap();
+        if let Err(error) = result {
+            self.draw_failed("draw frame", error);
+        }
+    }
+
+    /// Writes what changed between `previous` and `frame`, then puts the cursor at its
+    /// column and row. The spans move the cursor too, so it has to come last. Keeps writing
+    /// after an error and returns the first one.
+    fn write_frame(
+        out: &mut impl Write,
+        previous: &Grid<RenderCell>,
+        frame: &Grid<RenderCell>,
+        cursor: Option<(u16, u16)>,
+        cols: usize,
+        padding: ContentStyle,
+    ) -> io::Result<()> {
+        // What the terminal has set, kept across spans since moving the cursor doesn't change it
+        let mut style: Option<ContentStyle> = None;
+        let mut failed: Option<io::Error> = None;
 
         for row in 0..frame.rows() {
             let new_line = &frame.cells[row];
 
-            if let Some(old_line) = self.previous_frame.get(row) {
-                if old_line != new_line {
-                    self.draw_render_line(&mut out, new_line, config);
+            match previous.get(row).and_then(|old_line| Self::dirty_spans(old_line, new_line)) {
+                // Cells on the same columns, only those that changed are written
+                Some(spans) => {
+                    for span in spans {
+                        if let Err(error) = queue!(out, MoveTo(span.column as u16, row as u16)) {
+                            failed.get_or_insert(error);
+                        }
+                        Self::draw_cells(out, &new_line[span.start..span.end], &mut style);
+                    }
+                }
+                None => {
+                    if let Err(error) = queue!(out, MoveTo(0, row as u16)) {
+                        failed.get_or_insert(error);
+                    }
+                    Self::draw_render_line(out, new_line, cols, padding);
+                    style = None;
                 }
-            } else {
-                self.draw_render_line(&mut out, new_line, config);
             }
+        }
 
-            if row + 1 < frame.rows() {
-                write!(out, "\r\n").unwrap();
+        let _ = queue!(out, ResetColor);
+
+        if let Some((col, row)) = cursor {
+            if let Err(error) = queue!(out, MoveTo(col, row)) {
+                failed.get_or_insert(error);
             }
         }
 
-        self.previous_frame = frame;
+        match failed {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Logs a failed write instead of panicking with the terminal in raw mode. Part of the
+    /// frame may be missing on screen, so the next one is drawn in full.
+    fn draw_failed(&mut self, what: &str, error: io::Error) {
+        log!("Could not {}: {}", what, error);
+        self.previous_frame = Grid::new(0, 0, RenderCell::blank());
+    }
+
+    /// Ranges of cells that differ between the two lines. Runs separated by only a few
+    /// unchanged cells are joined, rewriting those is cheaper than another cursor move.
+    /// `None` when a cell takes a different number of columns than before, wide characters
+    /// shift everything after them so the row has to be drawn in full.
+    /// Public for the benchmarks only.
+    #[doc(hidden)]
+    pub fn dirty_spans(old_line: &[RenderCell], new_line: &[RenderCell]) -> Option<Vec<DirtySpan>> {
+        const MIN_GAP: usize = 4;
+
+        if old_line.len() != new_line.len() { return None }
+
+        let mut spans: Vec<DirtySpan> = vec![];
+        let mut column = 0;
+
+        for (cell, (old, new)) in old_line.iter().zip(new_line).enumerate() {
+            let width = cell_width(new);
+            if cell_width(old) != width { return None }
+
+            if old != new {
+                match spans.last_mut() {
+                    Some(span) if cell - span.end < MIN_GAP => span.end = cell + 1,
+                    _ => spans.push(DirtySpan { column, start: cell, end: cell + 1 }),
+                }
+            }
+
+            column += width;
+        }
+
+        Some(spans)
+    }
+
+    /// Writes `cells` at the cursor, switching the style only where it changes.
+    fn draw_cells(output: &mut impl Write, cells: &[RenderCell], current_style: &mut Option<ContentStyle>) {
+        for cell in cells {
+            if current_style.as_ref() != Some(&cell.style) {
+                // SetStyle only adds attributes, clear the ones of the previous style first
+                if current_style.is_none_or(|current| current.attributes != cell.style.attributes) {
+                    queue!(output, SetAttribute(Attribute::Reset)).ok();
+                }
+                queue!(output, SetStyle(cell.style)).ok();
+                *current_style = Some(cell.style);
+            }
+
+            write!(output, "{}", cell.ch).ok();
+        }
     }
     
+    /// Writes a whole row, padded to `cols` in the `padding` style.
     fn draw_render_line(
-        &self,
-        output: &mut StdoutLock,
+        output: &mut impl Write,
         line: &[RenderCell],
-        config: &Config
+        cols: usize,
+        padding: ContentStyle
     ) {
 
         let mut current_style: Option<ContentStyle> = None;
@@ -313,19 +848,14 @@ impl CrossTermRenderer {
             // print the character
             write!(output, "{}", cell.ch).ok();
 
-            // width might be 0,1,2
-            let width = cell.ch.len_utf8();
-            printed_cols += width;
+            printed_cols += cell_width(cell);
         }
 
         // now pad remaining columns
-        let total_cols = self.size.cols as usize;
+        if printed_cols < cols {
+            queue!(output, SetStyle(padding)).ok();
 
-        if printed_cols < total_cols {
-            let style = RenderCell::default_style(config);
-            queue!(output, SetStyle(style)).ok();
-
-            let missing = total_cols - printed_cols;
+            let missing = cols - printed_cols;
             write!(output, "{}", " ".repeat(missing)).ok();
         }
 
@@ -335,105 +865,255 @@ impl CrossTermRenderer {
 
 impl Renderer for CrossTermRenderer {
     fn begin_frame(&mut self) {
-        self.output.queue(terminal::BeginSynchronizedUpdate).expect("Could not begin synchronized update.");
-        self.output.queue(cursor::Hide).expect("Could not hide cursor.");
+        if let Err(error) = self.output.queue(terminal::BeginSynchronizedUpdate) {
+            self.draw_failed("begin synchronized update", error);
+        }
+        if let Err(error) = self.output.queue(cursor::Hide) {
+            self.draw_failed("hide cursor", error);
+        }
     }
 
     fn draw_buffer(&mut self, editor: &Editor, ui: &UiManager, config: &Config) {
-        let gutter_width = 6u16;
-        let ui_offset = ui.top_offset();
+        let (frame, cursor) = compose_frame(editor, ui, config, &self.size);
+        self.present(frame, cursor, config);
+    }
 
-        let mut horizontal_dir = true;
-        let mut prev_x = 0;
-        let mut prev_y = 0;
+    fn end_frame(&mut self) {
+        if !self.cell_cursor {
+            if let Err(error) = self.output.queue(cursor::Show) {
+                self.draw_failed("show cursor", error);
+            }
+        }
+        if let Err(error) = self.output.queue(terminal::EndSynchronizedUpdate) {
+            self.draw_failed("end synchronized update", error);
+        }
+        if let Err(error) = self.output.flush() {
+            self.draw_failed("flush output", error);
+        }
+    }
 
-        let mut final_frame = Grid::new(
-            self.size.rows as usize,
-            self.size.cols as usize,
-            RenderCell::space(config)
-        );
+    fn resize(&mut self, new_size: Size) {
+        // The terminal reflowed what was on screen, so every row is drawn again
+        self.previous_frame = Grid::new(0, 0, RenderCell::blank());
+        self.size = new_size;
+    }
 
-        for (id, view) in editor.views() {
-            let text_width   = view.size.cols - gutter_width;
+    fn invalidate(&mut self) {
+        self.previous_frame = Grid::new(0, 0, RenderCell::blank());
+    }
 
-            let gutter = GutterLayer::render(editor, &view, ui, config, Rect {
-                x: prev_x, y: prev_y,
-                cols: gutter_width as u16,
-                rows: view.size.rows
-            });
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        return self
+    }
+}
 
-            let text = TextLayer::render(editor, &view, ui, config, Rect {
-                x: prev_x, y: prev_y,
-                cols: text_width,
-                rows: view.size.rows
-            });
+/// Where the terminal cursor goes and how it looks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScreenCursor {
+    pub col: u16,
+    pub row: u16,
+    // Insert and Command mode show a bar, Normal mode a block
+    pub bar: bool,
+    pub blink: bool,
+    // `None` leaves the terminal its own color
+    pub color: Option<Color>,
+}
 
-            let view_frame = Composite::merge(&gutter, &text);
+/// Lays the views and the UI out into one frame of `size`, with the cursor that goes on it.
+/// Drawing it is up to the frontend, the terminal or a client attached to the session.
+pub fn compose_frame(editor: &Editor, ui: &UiManager, config: &Config, size: &Size) -> (Grid<RenderCell>, Option<ScreenCursor>) {
+    let gutter_width = GUTTER_WIDTH;
+    let ui_offset = ui.top_offset();
 
-            final_frame.blit(&view_frame, prev_x as usize, ui_offset + prev_y as usize);
+    let mut prev_x = 0;
+    let mut prev_y = 0;
 
-            prev_x += view.size.cols;
-        }
+    let mut final_frame = Grid::new(
+        size.rows as usize,
+        size.cols as usize,
+        RenderCell::space(config)
+    );
 
-        let active_view = editor.active_view();
-        if let Some(active_view) = active_view {
-            let ui_layer = UiLayer::render(editor, &active_view, ui, config, Rect {
-                x: 0, y: 0,
-                cols: self.size.cols,
-                rows: self.size.rows
-            });
+    let active_id = editor.active_view().map(|v| v.id);
 
-            final_frame = Composite::overlay(&final_frame, &ui_layer);
-        }
+    for (id, view) in editor.views() {
+        // Only the active view is visible until splits are supported
+        if Some(*id) != active_id { continue }
 
-        self.draw_frame(final_frame, config);
+        let text_width   = view.size.cols - gutter_width;
 
-        if let Some(active_view) = editor.active_view() {
-            let cursor_pos = active_view.cursor.clone();
-            let line_length = editor.active_buffer().unwrap().line(cursor_pos.row).unwrap().len();
-            
-            let mut col = cursor_pos.col.min(line_length);
-            let mut row = cursor_pos.row  + ui.top_offset()- active_view.scroll.vertical;
+        let gutter = GutterLayer::render(editor, view, ui, config, Rect {
+            x: prev_x, y: prev_y,
+            cols: gutter_width as u16,
+            rows: view.size.rows
+        });
 
-            if active_view.mode != EditorMode::Normal {
-                let _ = self.output.queue(cursor::SetCursorStyle::BlinkingBar);
-            } else {
-                let _ = self.output.queue(cursor::SetCursorStyle::BlinkingBlock);
-            }
+        let text = TextLayer::render(editor, view, ui, config, Rect {
+            x: prev_x, y: prev_y,
+            cols: text_width,
+            rows: view.size.rows
+        });
 
-            if active_view.mode == EditorMode::Command {
-                let command = ui.get::<Command>();
+        let view_frame = Composite::merge(&gutter, &text);
 
-                if let Some(command) = command {
-                    col = command.cursor;
-                    row = 1;
-                }
-            }
+        final_frame.blit(&view_frame, prev_x as usize, ui_offset + prev_y as usize);
 
-            self.output.queue(cursor::MoveTo(gutter_width as u16 + col as u16, row as u16)).expect("Could not move cursor.");
-        }
+        prev_x += view.size.cols;
     }
 
-    fn end_frame(&mut self) {
-        self.output.queue(cursor::Show).expect("Could not show cursor.");
-        self.output.queue(terminal::EndSynchronizedUpdate).expect("Could not end synchronized update.");
-        self.output.flush().expect("Could not flush output.");
-    }
+    let active_view = match editor.active_view() {
+        Some(active_view) => active_view,
+        None => return (final_frame, None)
+    };
 
-    fn resize(&mut self, new_size: Size) {
-        self.size = new_size;
+    let ui_layer = UiLayer::render(editor, &active_view, ui, config, Rect {
+        x: 0, y: 0,
+        cols: size.cols,
+        rows: size.rows
+    });
+
+    final_frame = Composite::overlay(&final_frame, &ui_layer);
+
+    let cursor_pos = active_view.cursor.clone();
+    let buffer = editor.active_buffer().unwrap();
+    let line_length = active_view.line_info(buffer, cursor_pos.row).chars;
+
+    let col = inlay_hints::display_col(&buffer.inlay_hints, cursor_pos.row, cursor_pos.col.min(line_length), active_view.scroll.horizontal);
+    let mut col = col.saturating_sub(active_view.scroll.horizontal);
+    let mut row = active_view.screen_row(cursor_pos.row) + ui.top_offset();
+
+    if let Some(command) = ui.get::<Command>() {
+        if active_view.mode == EditorMode::Command || command.masked {
+            col = command.cursor_col();
+            row = 1;
+        }
     }
 
-    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
-        return self
+    let cursor = ScreenCursor {
+        col: gutter_width + col as u16,
+        row: row as u16,
+        bar: active_view.mode != EditorMode::Normal,
+        blink: config.opt.cursor_blink.unwrap_or(true),
+        color: match config.opt.cursor_color.unwrap_or(true) {
+            true => Some(config.current_theme().cursor()),
+            false => None,
+        },
+    };
+
+    (final_frame, Some(cursor))
+}
+
+/// Whether the terminal takes a cursor color from OSC 12. Most do, the Linux console and
+/// Apple's Terminal don't, and without a TERM nothing is known.
+fn terminal_sets_cursor_color() -> bool {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+    let term = var("TERM");
+
+    !(term.is_empty() || term == "linux" || term == "dumb" || var("TERM_PROGRAM") == "Apple_Terminal")
+}
+
+/// Whether the terminal draws 24-bit colors. Most that do say so in COLORTERM, a few
+/// well-known ones only by their name.
+fn terminal_true_color() -> bool {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+    let (colorterm, term) = (var("COLORTERM"), var("TERM"));
+
+    colorterm == "truecolor" || colorterm == "24bit"
+        || term.ends_with("-direct") || term == "xterm-kitty" || term == "alacritty" || term == "wezterm"
+        || matches!(var("TERM_PROGRAM").as_str(), "iTerm.app" | "WezTerm" | "vscode")
+}
+
+/// The style with its 24-bit colors swapped for the closest of the 256 colors.
+fn downsample(style: ContentStyle) -> ContentStyle {
+    let convert = |color: Option<Color>| color.map(|color| match color {
+        Color::Rgb { r, g, b } => Color::AnsiValue(theme::nearest_ansi((r, g, b))),
+        other => other,
+    });
+
+    ContentStyle {
+        foreground_color: convert(style.foreground_color),
+        background_color: convert(style.background_color),
+        underline_color: convert(style.underline_color),
+        ..style
     }
 }
 
+/// Whether the terminal changes the cursor shape with DECSCUSR. The Linux console keeps
+/// its own underline, and without a TERM nothing is known.
+fn terminal_sets_cursor_style() -> bool {
+    let term = std::env::var("TERM").unwrap_or_default();
+
+    !(term.is_empty() || term == "linux" || term == "dumb")
+}
+
 impl Drop for CrossTermRenderer {
     fn drop(&mut self) {
-        terminal::disable_raw_mode().expect("Could not disable raw mode.");
-        self.output.execute(terminal::LeaveAlternateScreen).expect("Could not leave alternate screen.");
-        self.output.execute(cursor::Show).expect("Could not show cursor.");
-        self.output.execute(DisableMouseCapture).expect("Could not disable mouse capture.");
+        // After a hangup the terminal is gone and all of these fail, that's fine
+        let _ = terminal::disable_raw_mode();
+        let _ = self.output.execute(terminal::LeaveAlternateScreen);
+        let _ = self.output.execute(cursor::Show);
+        // Give the user's own cursor back
+        let _ = self.output.execute(SetCursorStyle::DefaultUserShape);
+        self.set_cursor_color(None);
+        let _ = self.output.flush();
+        let _ = self.output.execute(DisableMouseCapture);
+        let _ = self.output.execute(DisableFocusChange);
+        let _ = self.output.execute(DisableBracketedPaste);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str) -> Vec<RenderCell> {
+        text.chars().map(|ch| RenderCell { ch, style: ContentStyle::new(), transparent: false }).collect()
+    }
+
+    fn spans(old: &str, new: &str) -> Option<Vec<(usize, usize, usize)>> {
+        let spans = CrossTermRenderer::dirty_spans(&line(old), &line(new))?;
+        Some(spans.into_iter().map(|span| (span.column, span.start, span.end)).collect())
+    }
+
+    #[test]
+    fn unchanged_line_has_no_spans() {
+        assert_eq!(spans("hello", "hello"), Some(vec![]));
+    }
+
+    #[test]
+    fn close_changes_are_joined() {
+        assert_eq!(spans("abcdefghijkl", "aXcXefghijkX"), Some(vec![(1, 1, 4), (11, 11, 12)]));
+    }
+
+    #[test]
+    fn spans_start_at_display_columns() {
+        // The wide characters before the change take two columns each
+        assert_eq!(spans("日本 text", "日本 tExt"), Some(vec![(6, 4, 5)]));
+        assert_eq!(spans("日本語", "日本人"), Some(vec![(4, 2, 3)]));
+    }
+
+    #[test]
+    fn different_widths_redraw_the_row() {
+        assert_eq!(spans("ab cd", "ab 語d"), None);
+        assert_eq!(spans("語 x", "a  x"), None);
+        assert_eq!(spans("abc", "abcd"), None);
+    }
+
+    #[test]
+    fn cursor_moves_after_the_changed_cells() {
+        let grid = |rows: &[&str]| Grid { cells: rows.iter().map(|row| line(row)).collect() };
+        let (previous, frame) = (grid(&["hello", "world"]), grid(&["hello", "wOrld"]));
+
+        let mut out = vec![];
+        CrossTermRenderer::write_frame(&mut out, &previous, &frame, Some((0, 0)), 5, ContentStyle::new()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        // The span of row 1 starts with a move to it, the editor cursor has to be moved to last
+        let mut cursor_move = vec![];
+        queue!(cursor_move, MoveTo(0, 0)).unwrap();
+        let cursor_move = String::from_utf8(cursor_move).unwrap();
+
+        assert!(out.contains('O'));
+        assert!(out.ends_with(&cursor_move), "{:?}", out);
     }
 }
@@ -1,7 +1,11 @@
 pub mod crossterm;
+pub mod session;
 pub mod wgpu_renderer;
 pub mod wgpu;
 
+use std::io;
+use std::path::{Path, PathBuf};
+
 use crate::buffer::{Buffer, BufferView};
 use crate::highlighter::Highlighter;
 use crate::plugins::config::Config;
@@ -15,6 +19,25 @@ pub trait Renderer {
     fn end_frame(&mut self);
     fn resize(&mut self, new_size: Size);
 
+    /// Forgets what is on screen, the next frame draws every cell again. Frontends that
+    /// draw everything every frame anyway have nothing to do.
+    fn invalidate(&mut self) {}
+
+    /// Draws the current frame into a PNG. Only the GPU frontend can.
+    fn screenshot(&mut self, _editor: &Editor, _ui: &UiManager, _config: &Config, _path: &Path) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "screenshots need the GUI"))
+    }
+
+    /// Writes every frame drawn from now on into `dir` as numbered PNGs.
+    fn start_recording(&mut self, _dir: PathBuf) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "recording needs the GUI"))
+    }
+
+    /// Ends the recording, returns where the frames went and how many there are.
+    fn stop_recording(&mut self) -> Option<(PathBuf, usize)> {
+        None
+    }
+
     fn as_any_mut(&mut self) -> &mut dyn std::any::Any;
 }
 
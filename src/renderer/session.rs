@@ -0,0 +1,79 @@
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+
+use crate::editor::Editor;
+use crate::plugins::config::Config;
+use crate::renderer::Renderer;
+use crate::renderer::crossterm::compose_frame;
+use crate::session::{self, ServerMessage, WireCell, WireCursor};
+use crate::types::Size;
+use crate::ui::ui_manager::UiManager;
+use crate::log;
+
+/// Draws into a frontend attached with `oxidy --attach`. Frames are laid out like the
+/// terminal's and only the rows that changed are sent.
+pub struct SessionRenderer {
+    stream: UnixStream,
+    size: Size,
+    // The rows the frontend has, empty after a resize so everything is sent again
+    previous: Vec<Vec<WireCell>>,
+    cursor: Option<WireCursor>,
+}
+
+impl SessionRenderer {
+    pub fn new(stream: UnixStream, size: Size) -> Self {
+        Self { stream, size, previous: vec![], cursor: None }
+    }
+}
+
+impl Renderer for SessionRenderer {
+    fn begin_frame(&mut self) {}
+
+    fn draw_buffer(&mut self, editor: &Editor, ui: &UiManager, config: &Config) {
+        let (frame, cursor) = compose_frame(editor, ui, config, &self.size);
+
+        let rows: Vec<Vec<WireCell>> = frame.cells.iter()
+            .map(|row| row.iter().map(session::encode_cell).collect())
+            .collect();
+        let cursor = cursor.as_ref().map(session::encode_cursor);
+
+        let changed: Vec<(usize, Vec<WireCell>)> = rows.iter()
+            .enumerate()
+            .filter(|(i, row)| self.previous.get(*i) != Some(*row))
+            .map(|(i, row)| (i, row.clone()))
+            .collect();
+
+        if changed.is_empty() && rows.len() == self.previous.len() && cursor == self.cursor { return }
+
+        let message = ServerMessage::Frame { rows: rows.len(), changed, cursor };
+        match session::send(&mut self.stream, &message) {
+            Ok(()) => {
+                self.previous = rows;
+                self.cursor = cursor;
+            }
+            Err(error) => log!("Could not send frame to the session client: {}", error),
+        }
+    }
+
+    fn end_frame(&mut self) {}
+
+    fn resize(&mut self, new_size: Size) {
+        self.previous.clear();
+        self.size = new_size;
+    }
+
+    fn invalidate(&mut self) {
+        self.previous.clear();
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        return self
+    }
+}
+
+impl Drop for SessionRenderer {
+    fn drop(&mut self) {
+        // A frontend that was replaced sees the end of the stream and exits
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
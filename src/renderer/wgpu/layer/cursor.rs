@@ -8,6 +8,7 @@ use crate::plugins::config::Config;
 use crate::editor::Editor;
 use crate::ui::ui_manager::UiManager;
 use crate::types::EditorMode;
+use crate::inlay_hints;
 use crate::renderer::wgpu::utils::{calculate_gutter_width, status_bar_height};
 
 pub struct CursorLayer {
@@ -184,12 +185,24 @@ impl Layer for CursorLayer {
         let mut cursor_x_px = 20.0 + calculate_gutter_width(&self.font, &self.font_scale, max_line_number_on_screen);
 
         if let Some(line) = buffer.lines.get(buf_view.cursor.row) {
-            cursor_x_px = self.caret_x_for_line(line, buf_view.cursor.col, cursor_x_px);
+            // Measure the line the way the TextLayer draws it, scrolled and with the inlay hints in it
+            let row = buf_view.cursor.row;
+            let scroll = buf_view.scroll.horizontal;
+            let visible: String = line.chars().skip(scroll).collect();
+            let hints = inlay_hints::scrolled(&buffer.inlay_hints, row, scroll);
+
+            let line: String = inlay_hints::segments(&visible, &hints, row)
+                .into_iter()
+                .map(|(segment, _)| segment)
+                .collect();
+            let col = inlay_hints::display_col(&hints, row, buf_view.cursor.col.saturating_sub(scroll), 0);
+
+            cursor_x_px = self.caret_x_for_line(&line, col, cursor_x_px);
         }
 
         // TODO: These Y positions should be calculated dynamically from font metrics and line spacing
         // matching what the TextLayer uses.
-        let line_top = status_bar_height() + (self.font_scale + 2.0) * (buf_view.cursor.row - buf_view.scroll.vertical) as f32;
+        let line_top = status_bar_height() + (self.font_scale + 2.0) * buf_view.screen_row(buf_view.cursor.row) as f32;
         let line_bottom = line_top + self.font_scale; // approximate line height
 
         self.update_cursor_buffer(queue, cursor_x_px, line_top, line_bottom, self.cursor_width_px);
@@ -0,0 +1,137 @@
+use wgpu::{Device, CommandEncoder, TextureView, Queue};
+use wgpu::util::StagingBelt;
+use winit::dpi::PhysicalSize;
+use crossterm::style::Color;
+
+use super::{Layer, quad_pipeline, QUAD_VERTEX_FLOATS};
+use crate::plugins::config::Config;
+use crate::editor::Editor;
+use crate::ui::ui_manager::UiManager;
+use crate::renderer::wgpu::utils::{srgb_to_linear, status_bar_height};
+
+/// A quad behind the text on the cursor's line, drawn with `cursorline`.
+pub struct CursorLineLayer {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    font_scale: f32,
+    visible: bool,
+    surface_size: PhysicalSize<u32>,
+}
+
+impl CursorLineLayer {
+    /// Uploads a quad spanning the surface's width from `y_top_px` to `y_bot_px`.
+    fn update_quad(&mut self, queue: &Queue, y_top_px: f32, y_bot_px: f32, color: wgpu::Color) {
+        let h = self.surface_size.height as f32;
+
+        // y in NDC: 1.0 top -> -1.0 bottom, so invert
+        let y1 = 1.0 - (y_top_px / h) * 2.0;
+        let y2 = 1.0 - (y_bot_px / h) * 2.0;
+
+        let rgba = [color.r as f32, color.g as f32, color.b as f32, color.a as f32];
+        let corners = [(-1.0, y1), (1.0, y1), (-1.0, y2), (-1.0, y2), (1.0, y1), (1.0, y2)];
+
+        let mut raw = [0f32; 6 * QUAD_VERTEX_FLOATS];
+        for (i, (x, y)) in corners.into_iter().enumerate() {
+            raw[i * QUAD_VERTEX_FLOATS] = x;
+            raw[i * QUAD_VERTEX_FLOATS + 1] = y;
+            raw[i * QUAD_VERTEX_FLOATS + 2..(i + 1) * QUAD_VERTEX_FLOATS].copy_from_slice(&rgba);
+        }
+
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                raw.as_ptr() as *const u8,
+                raw.len() * std::mem::size_of::<f32>(),
+            )
+        };
+        queue.write_buffer(&self.vertex_buffer, 0, bytes);
+    }
+}
+
+impl Layer for CursorLineLayer {
+    fn new(device: &Device, render_format: wgpu::TextureFormat) -> Self {
+        let pipeline = quad_pipeline(device, render_format, "Cursor line");
+
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cursor line VB"),
+            size: (6 * QUAD_VERTEX_FLOATS * std::mem::size_of::<f32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            pipeline,
+            vertex_buffer,
+            font_scale: 26.0,
+            visible: false,
+            surface_size: PhysicalSize::new(1, 1),
+        }
+    }
+
+    fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        self.surface_size = new_size;
+    }
+
+    fn update(
+        &mut self,
+        editor: &Editor,
+        _ui: &UiManager,
+        config: &Config,
+        _device: &Device,
+        queue: &Queue,
+        _surface_size: PhysicalSize<u32>,
+    ) {
+        let buf_view = match editor.active_view() {
+            Some(view) if view.options(&config.opt).cursorline.unwrap_or(false) => view,
+            _ => {
+                self.visible = false;
+                return;
+            }
+        };
+
+        let (r, g, b) = match config.current_theme().cursor_line() {
+            Color::Rgb { r, g, b } => (r, g, b),
+            _ => return
+        };
+        let linear = |c: u8| srgb_to_linear(c as f32 / 255.0) as f64;
+        let color = wgpu::Color { r: linear(r), g: linear(g), b: linear(b), a: 1.0 };
+
+        // Same line positions as the CursorLayer, the full line height so rows don't leave gaps
+        let line_top = status_bar_height() + (self.font_scale + 2.0) * buf_view.screen_row(buf_view.cursor.row) as f32;
+        let line_bottom = line_top + self.font_scale + 2.0;
+
+        self.update_quad(queue, line_top, line_bottom, color);
+        self.visible = true;
+    }
+
+    fn draw(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        _device: &Device,
+        _queue: &Queue,
+        _staging_belt: &mut StagingBelt,
+        _surface_size: PhysicalSize<u32>,
+    ) {
+        if !self.visible { return }
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Cursor line pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.draw(0..6, 0..1);
+    }
+}
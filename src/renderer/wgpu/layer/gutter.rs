@@ -6,9 +6,12 @@ use wgpu_glyph::ab_glyph::{self, Font, FontArc, ScaleFont};
 
 use super::{Layer, get_font};
 use crate::plugins::config::Config;
+use crate::log;
 use crate::editor::Editor;
 use crate::ui::ui_manager::UiManager;
-use crate::renderer::wgpu::utils::{hex_to_wgpu_color, calculate_gutter_width, status_bar_height};
+use crate::diagnostics;
+use crate::renderer::wgpu::utils::{hex_to_wgpu_color, calculate_gutter_width, srgb_to_linear, status_bar_height};
+use crossterm::style::Color;
 
 pub struct GutterLayer {
     glyph_brush: GlyphBrush<()>,
@@ -58,11 +61,10 @@ impl Layer for GutterLayer {
         // Clear previous queued text
         // self.glyph_brush.queue_unbounded(Section { ..Default::default() });
         
-        let use_relative = config.opt.relative_numbers.unwrap();
+        let opt = buf_view.options(&config.opt);
+        let use_relative = opt.relative_numbers.unwrap_or(false);
 
-        for i in 0..(buf_view.size.rows as usize) {
-            // let line_number_ = (i + buf_view.visible_top() + 1).to_string(); // Line numbers are 1-based
-            let buffer_row = i + buf_view.visible_top();
+        for (i, buffer_row) in buf_view.screen_rows(buffer.lines.len()).into_iter().enumerate() {
             let mut color: [f32; 4] = [
                 normal_line_color.r as f32,
                 normal_line_color.g as f32,
@@ -102,6 +104,47 @@ impl Layer for GutterLayer {
                 ],
                 ..Section::default()
             });
+
+            // In front of the number, like in the terminal
+            if let Some(severity) = diagnostics::worst_on(&buffer.diagnostics, buffer_row) {
+                let sign_color = match severity.color() {
+                    Color::Rgb { r, g, b } => [r, g, b].map(|c| srgb_to_linear(c as f32 / 255.0)),
+                    _ => [color[0], color[1], color[2]],
+                };
+
+                self.glyph_brush.queue(Section {
+                    screen_position: (2.0, y_pos),
+                    bounds: (self.gutter_width_px, surface_size.height as f32),
+                    layout,
+                    text: vec![
+                        Text::new(&severity.sign().to_string())
+                            .with_color([sign_color[0], sign_color[1], sign_color[2], 1.0])
+                            .with_scale(self.font_scale),
+                    ],
+                    ..Section::default()
+                });
+            }
+
+            // Between the number and the text
+            let git_sign = buffer.git_signs.get(buffer_row).copied().flatten();
+            if let Some(sign) = git_sign.filter(|_| opt.git_signs.unwrap_or(true)) {
+                let sign_color = match sign.color() {
+                    Color::Rgb { r, g, b } => [r, g, b].map(|c| srgb_to_linear(c as f32 / 255.0)),
+                    _ => [color[0], color[1], color[2]],
+                };
+
+                self.glyph_brush.queue(Section {
+                    screen_position: (self.gutter_width_px + 2.0, y_pos),
+                    bounds: (surface_size.width as f32, surface_size.height as f32),
+                    layout,
+                    text: vec![
+                        Text::new(&sign.sign().to_string())
+                            .with_color([sign_color[0], sign_color[1], sign_color[2], 1.0])
+                            .with_scale(self.font_scale),
+                    ],
+                    ..Section::default()
+                });
+            }
         }
     }
 
@@ -114,7 +157,7 @@ impl Layer for GutterLayer {
         staging_belt: &mut StagingBelt,
         surface_size: PhysicalSize<u32>,
     ) {
-        self.glyph_brush
+        let drawn = self.glyph_brush
             .draw_queued(
                 device,
                 staging_belt,
@@ -122,7 +165,10 @@ impl Layer for GutterLayer {
                 view,
                 surface_size.width,
                 surface_size.height,
-            )
-            .expect("Draw queued for gutter");
+            );
+
+        if let Err(error) = drawn {
+            log!("Could not draw the gutter: {}", error);
+        }
     }
 }
@@ -3,10 +3,12 @@ pub mod text;
 pub mod gutter;
 pub mod ui;
 pub mod cursor;
+pub mod cursor_line;
 
 use wgpu::{CommandEncoder, RenderPass, TextureView, Device, Queue};
 use wgpu::util::StagingBelt;
 use winit::dpi::PhysicalSize;
+use wgpu_glyph::FontId;
 use wgpu_glyph::ab_glyph::{Font, FontArc};
 
 use crate::plugins::config::Config;
@@ -14,6 +16,94 @@ use crate::editor::Editor;
 use crate::ui::ui_manager::UiManager;
 use crate::types::ViewId;
 
+/// System fonts tried, in order, for symbols the bundled font lacks, like Powerline caps and icons.
+const FALLBACK_FONTS: [&str; 6] = [
+    "/usr/share/fonts/TTF/SymbolsNerdFontMono-Regular.ttf",
+    "/usr/share/fonts/truetype/nerd-fonts/SymbolsNerdFontMono-Regular.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/System/Library/Fonts/Apple Symbols.ttf",
+    "C:\\Windows\\Fonts\\seguisym.ttf",
+];
+
+/// x, y and RGBA for each vertex of a quad.
+pub const QUAD_VERTEX_FLOATS: usize = 6;
+
+/// Adds the two triangles of the pixel rectangle `(left, top, right, bottom)`.
+pub fn push_quad(vertices: &mut Vec<f32>, (left, top, right, bottom): (f32, f32, f32, f32), color: [f32; 4], surface_size: PhysicalSize<u32>) {
+    let (w, h) = (surface_size.width as f32, surface_size.height as f32);
+
+    // NDC: -1.0 left -> 1.0 right, 1.0 top -> -1.0 bottom
+    let x1 = (left / w) * 2.0 - 1.0;
+    let x2 = (right / w) * 2.0 - 1.0;
+    let y1 = 1.0 - (top / h) * 2.0;
+    let y2 = 1.0 - (bottom / h) * 2.0;
+
+    for (x, y) in [(x1, y1), (x2, y1), (x1, y2), (x1, y2), (x2, y1), (x2, y2)] {
+        vertices.extend_from_slice(&[x, y]);
+        vertices.extend_from_slice(&color);
+    }
+}
+
+/// A pipeline drawing triangles of `QUAD_VERTEX_FLOATS` floats per vertex in one color each,
+/// for the layers that draw rectangles.
+pub fn quad_pipeline(device: &Device, surface_format: wgpu::TextureFormat, label: &str) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Quad shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/quad.wgsl").into()),
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(&format!("{} pipeline layout", label)),
+        bind_group_layouts: &[],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(&format!("{} pipeline", label)),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: (QUAD_VERTEX_FLOATS * std::mem::size_of::<f32>()) as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &[
+                    wgpu::VertexAttribute {
+                        offset: 0,
+                        shader_location: 0,
+                        format: wgpu::VertexFormat::Float32x2,
+                    },
+                    wgpu::VertexAttribute {
+                        offset: (2 * std::mem::size_of::<f32>()) as wgpu::BufferAddress,
+                        shader_location: 1,
+                        format: wgpu::VertexFormat::Float32x4,
+                    },
+                ],
+            }],
+            compilation_options: wgpu::PipelineCompilationOptions::default()
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default()
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
 pub fn get_font() -> FontArc {
     let font = FontArc::try_from_slice(include_bytes!(
         "../../../JetBrainsMono-Regular.ttf"
@@ -22,6 +112,48 @@ pub fn get_font() -> FontArc {
     font
 }
 
+/// The bundled font followed by the first fallback font found, a symbols font in
+/// the user's font directory wins over the system ones.
+pub fn get_fonts() -> Vec<FontArc> {
+    let user = dirs::font_dir().map(|dir| dir.join("SymbolsNerdFontMono-Regular.ttf"));
+
+    let fallback = user.into_iter()
+        .chain(FALLBACK_FONTS.iter().map(std::path::PathBuf::from))
+        .filter_map(|path| std::fs::read(path).ok())
+        .find_map(|bytes| FontArc::try_from_vec(bytes).ok());
+
+    let mut fonts = vec![get_font()];
+    fonts.extend(fallback);
+    fonts
+}
+
+/// Splits `text` into runs of the first font that has their glyphs, so symbols
+/// the bundled font lacks don't show up as boxes.
+pub fn font_runs<'a>(fonts: &[FontArc], text: &'a str) -> Vec<(FontId, &'a str)> {
+    let mut runs = vec![];
+    let mut start = 0;
+    let mut current = FontId(0);
+
+    for (i, ch) in text.char_indices() {
+        // Glyph 0 is the font's "missing" box
+        let font = fonts.iter().position(|font| font.glyph_id(ch).0 != 0).unwrap_or(0);
+
+        if FontId(font) != current {
+            if i > start {
+                runs.push((current, &text[start..i]));
+            }
+            start = i;
+            current = FontId(font);
+        }
+    }
+
+    if start < text.len() {
+        runs.push((current, &text[start..]));
+    }
+
+    runs
+}
+
 pub trait Layer {
     fn new(device: &Device, render_format: wgpu::TextureFormat) -> Self where Self: Sized;
 
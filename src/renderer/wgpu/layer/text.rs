@@ -2,31 +2,85 @@ use wgpu::{Device, CommandEncoder, TextureView, Queue};
 use wgpu::util::StagingBelt;
 use winit::dpi::PhysicalSize;
 use wgpu_glyph::{GlyphBrushBuilder, Section, Text, ab_glyph, GlyphBrush, Layout};
-use wgpu_glyph::ab_glyph::FontArc;
+use wgpu_glyph::ab_glyph::{Font, FontArc, ScaleFont};
 
-use super::{Layer, get_font};
+use super::{Layer, get_fonts, font_runs, quad_pipeline, push_quad, QUAD_VERTEX_FLOATS};
 use super::gutter::GutterLayer;
 use crate::plugins::config::Config;
+use crate::log;
 use crate::editor::Editor;
 use crate::ui::ui_manager::UiManager;
-use crate::renderer::wgpu::utils::{hex_to_wgpu_color, calculate_gutter_width, status_bar_height};
+use crate::inlay_hints::{self, InlayHint};
+use crate::folding;
+use crate::whitespace;
+use crate::markdown::{self, PreviewStyle};
+use crate::diagnostics::Diagnostic;
+use crate::plugins::theme::Theme;
+use crate::renderer::wgpu::utils::{hex_to_wgpu_color, calculate_gutter_width, srgb_to_linear, status_bar_height};
+use crossterm::style::Color;
+
+/// Diagnostic underlines drawn per frame, the rest is left out.
+const MAX_UNDERLINES: usize = 512;
 
 pub struct TextLayer {
     font: FontArc,
+    fonts: Vec<FontArc>,
     glyph_brush: GlyphBrush<()>,
     font_scale: f32,
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    underlines: usize,
+}
+
+impl TextLayer {
+    /// Where char `col` of `text` starts, measured like the cursor layer does.
+    fn x_at(&self, text: &str, col: usize, start_x: f32) -> f32 {
+        let scaled_font = self.font.as_scaled(self.font_scale);
+        text.chars().take(col).map(|ch| scaled_font.h_advance(scaled_font.glyph_id(ch))).sum::<f32>() + start_x
+    }
+
+    /// Adds a line under the part of `visible` each diagnostic covers. `drawn` is the row as
+    /// drawn, with the hints in it, `visible` starts at `scroll`.
+    fn underline_diagnostics(&self, vertices: &mut Vec<f32>, diagnostics: &[Diagnostic], hints: &[InlayHint], row: usize, line_len: usize, drawn: &str, scroll: usize, start_x: f32, top: f32, surface_size: PhysicalSize<u32>) {
+        for diagnostic in diagnostics {
+            let Some((start, end)) = diagnostic.cols_on(row, line_len) else { continue };
+            if end <= scroll || vertices.len() >= MAX_UNDERLINES * 6 * QUAD_VERTEX_FLOATS { continue }
+
+            let col = |col: usize| inlay_hints::display_col(hints, row, col.max(scroll) - scroll, 0);
+            let left = self.x_at(drawn, col(start), start_x);
+            let right = self.x_at(drawn, col(end), start_x).max(left + 4.0);
+            let bottom = top + self.font_scale + 2.0;
+
+            let color = match diagnostic.severity.color() {
+                Color::Rgb { r, g, b } => [r, g, b].map(|c| srgb_to_linear(c as f32 / 255.0)),
+                _ => [1.0, 0.0, 0.0],
+            };
+            push_quad(vertices, (left, bottom - 2.0, right, bottom), [color[0], color[1], color[2], 1.0], surface_size);
+        }
+    }
 }
 
 impl Layer for TextLayer {
     fn new(device: &Device, render_format: wgpu::TextureFormat) -> Self {
-        let font = get_font();
-        let glyph_brush = GlyphBrushBuilder::using_font(font.clone())
+        let fonts = get_fonts();
+        let glyph_brush = GlyphBrushBuilder::using_fonts(fonts.clone())
             .build(device, render_format);
 
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Diagnostic underline VB"),
+            size: (MAX_UNDERLINES * 6 * QUAD_VERTEX_FLOATS * std::mem::size_of::<f32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
         Self {
-            font,
+            font: fonts[0].clone(),
+            fonts,
             glyph_brush,
-            font_scale: 26.0,        
+            font_scale: 26.0,
+            pipeline: quad_pipeline(device, render_format, "Diagnostic underline"),
+            vertex_buffer,
+            underlines: 0,
         }
     }
 
@@ -36,35 +90,122 @@ impl Layer for TextLayer {
         _ui: &UiManager,
         config: &Config,
         _device: &Device,
-        _queue: &Queue,
+        queue: &Queue,
         _surface_size: PhysicalSize<u32>,
     ) {
         let buf_view = editor.active_view().unwrap();
         let buffer = editor.active_buffer().unwrap();
         let theme = config.current_theme();
-        let fg = hex_to_wgpu_color(&theme.Foreground.unwrap_or_default());
+        let fg = hex_to_wgpu_color(&theme.Foreground.clone().unwrap_or_default());
 
         let layout = Layout::default_single_line();
+        let list = buf_view.options(&config.opt).list.unwrap_or(false);
+        let (fonts, font_scale) = (&self.fonts, self.font_scale);
+
+        let code_rows = match buf_view.markdown_preview {
+            true => markdown::code_rows(&buffer.lines),
+            false => vec![],
+        };
 
         let max_line_number_on_screen = buf_view.visible_top() + buf_view.size.rows as usize;
         let start_x = 20.0 + calculate_gutter_width(&self.font, &self.font_scale, max_line_number_on_screen);
-        
-        for i in 0..(buf_view.size.rows as usize) {
-            let line_index = i + buf_view.visible_top();
+
+        // Only the columns on screen are laid out, long lines would cost the whole line every frame
+        let scaled_font = self.font.as_scaled(self.font_scale);
+        let advance = scaled_font.h_advance(scaled_font.glyph_id('M'));
+        let cols = ((_surface_size.width as f32 - start_x) / advance).max(0.0).ceil() as usize + 1;
+        let scroll = buf_view.scroll.horizontal;
+        let mut underlines: Vec<f32> = vec![];
+
+        for (i, line_index) in buf_view.screen_rows(buffer.lines.len()).into_iter().enumerate() {
             if let Some(line) = buffer.lines.get(line_index) {
+                // The cursor's line shows its markup, so it can be edited
+                if buf_view.markdown_preview && line_index != buf_view.cursor.row {
+                    let code = code_rows.get(line_index).copied().unwrap_or(false);
+                    let pieces = preview_pieces(line, code, &theme, font_scale);
+
+                    let text: Vec<Text> = clip(pieces, scroll, cols).iter()
+                        .flat_map(|(piece, color, scale)| {
+                            font_runs(fonts, piece).into_iter().map(move |(font_id, run)| {
+                                Text::new(run)
+                                    .with_color(*color)
+                                    .with_scale(*scale)
+                                    .with_font_id(font_id)
+                            })
+                        })
+                        .collect();
+
+                    self.glyph_brush.queue(Section {
+                        screen_position: (start_x, status_bar_height() + (self.font_scale + 2.0) * i as f32),
+                        bounds: (_surface_size.width as f32, _surface_size.height as f32),
+                        layout,
+                        text,
+                        ..Section::default()
+                    });
+                    continue;
+                }
+
+                let summary = folding::closed_at(&buf_view.folds, line_index)
+                    .map(|fold| format!(" ··· {} lines", fold.len()));
+
+                let mut segments: Vec<(String, bool)> = vec![];
+                let markers = if list { whitespace::markers(line) } else { vec![] };
+                let mut col = scroll;
+
+                let visible: String = line.chars().skip(scroll).take(cols).collect();
+                let hints = inlay_hints::scrolled(&buffer.inlay_hints, line_index, scroll);
+
+                for (segment, dimmed) in inlay_hints::segments(&visible, &hints, line_index) {
+                    if dimmed || !list {
+                        segments.push((segment.to_string(), dimmed));
+                        continue;
+                    }
+
+                    // Whitespace markers are dimmed like hints
+                    segments.extend(whitespace::pieces(segment, markers.get(col..).unwrap_or(&[])));
+                    col += segment.chars().count();
+                }
+                let drawn: String = segments.iter().map(|(segment, _)| segment.as_str()).collect();
+                let top = status_bar_height() + (self.font_scale + 2.0) * i as f32;
+                let line_len = line.chars().count();
+                self.underline_diagnostics(&mut underlines, &buffer.diagnostics, &hints, line_index, line_len, &drawn, scroll, start_x, top, _surface_size);
+
+                segments.extend(summary.map(|summary| (summary, true)));
+
+                let text: Vec<Text> = segments.iter()
+                    .flat_map(|(segment, dimmed)| {
+                        // Hints and fold summaries are drawn at half the opacity of the text
+                        let alpha = if *dimmed { fg.a as f32 * 0.5 } else { fg.a as f32 };
+
+                        font_runs(fonts, segment).into_iter().map(move |(font_id, run)| {
+                            Text::new(run)
+                                .with_color([fg.r as f32, fg.g as f32, fg.b as f32, alpha])
+                                .with_scale(font_scale)
+                                .with_font_id(font_id)
+                        })
+                    })
+                    .collect();
+
                 self.glyph_brush.queue(Section {
                     screen_position: (start_x, status_bar_height() + (self.font_scale + 2.0) * i as f32),
                     bounds: (_surface_size.width as f32, _surface_size.height as f32),
                     layout,
-                    text: vec![
-                        Text::new(line)
-                            .with_color([fg.r as f32, fg.g as f32, fg.b as f32, fg.a as f32])
-                            .with_scale(self.font_scale),
-                    ],
+                    text,
                     ..Section::default()
                 });
             }
         }
+
+        self.underlines = underlines.len() / (6 * QUAD_VERTEX_FLOATS);
+        if self.underlines > 0 {
+            let bytes = unsafe {
+                std::slice::from_raw_parts(
+                    underlines.as_ptr() as *const u8,
+                    underlines.len() * std::mem::size_of::<f32>(),
+                )
+            };
+            queue.write_buffer(&self.vertex_buffer, 0, bytes);
+        }
     }
 
     fn draw(
@@ -76,7 +217,29 @@ impl Layer for TextLayer {
         staging_belt: &mut StagingBelt,
         surface_size: PhysicalSize<u32>,
     ) {
-        self.glyph_brush
+        if self.underlines > 0 {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Diagnostic underline pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            rpass.draw(0..(self.underlines * 6) as u32, 0..1);
+        }
+
+        let drawn = self.glyph_brush
             .draw_queued(
                 device,
                 staging_belt,
@@ -84,7 +247,74 @@ impl Layer for TextLayer {
                 view,
                 surface_size.width,
                 surface_size.height,
-            )
-            .expect("Draw queued");
+            );
+
+        if let Err(error) = drawn {
+            log!("Could not draw the text: {}", error);
+        }
+    }
+}
+
+/// The part of `pieces` from char `skip` on, `take` chars long.
+fn clip(pieces: Vec<(String, [f32; 4], f32)>, skip: usize, take: usize) -> Vec<(String, [f32; 4], f32)> {
+    let mut clipped = vec![];
+    let mut start = 0;
+
+    for (piece, color, scale) in pieces {
+        let len = piece.chars().count();
+        let (from, to) = (skip.max(start), (skip + take).min(start + len));
+
+        if from < to {
+            let text: String = piece.chars().skip(from - start).take(to - from).collect();
+            clipped.push((text, color, scale));
+        }
+        start += len;
     }
+
+    clipped
+}
+
+/// Runs of a Markdown preview line with their color and scale. Headings are drawn larger,
+/// colors stand in for bold and italic.
+fn preview_pieces(line: &str, code: bool, theme: &Theme, font_scale: f32) -> Vec<(String, [f32; 4], f32)> {
+    let foreground = theme.Foreground.clone().unwrap_or_default();
+    let color = |hex: &Option<String>| {
+        let color = hex_to_wgpu_color(hex.as_deref().unwrap_or(&foreground));
+        [color.r as f32, color.g as f32, color.b as f32, color.a as f32]
+    };
+
+    let mut pieces: Vec<(String, [f32; 4], f32)> = vec![];
+    let mut current: Option<PreviewStyle> = None;
+
+    for (ch, style) in markdown::preview_line(line, code) {
+        if current == Some(style) {
+            if let Some((piece, _, _)) = pieces.last_mut() {
+                piece.push(ch);
+            }
+            continue;
+        }
+
+        let (rgba, scale) = match style {
+            PreviewStyle::Plain => (color(&theme.Foreground), font_scale),
+            PreviewStyle::Heading(level) => {
+                let scale = match level {
+                    1 => 1.4,
+                    2 => 1.25,
+                    3 => 1.1,
+                    _ => 1.0,
+                };
+                (color(&theme.Keyword), font_scale * scale)
+            }
+            PreviewStyle::Strong => (color(&theme.Property), font_scale),
+            PreviewStyle::Emphasis => (color(&theme.Type), font_scale),
+            PreviewStyle::Code => (color(&theme.String), font_scale),
+            PreviewStyle::Marker => (color(&theme.Number), font_scale),
+            PreviewStyle::Quote => (color(&theme.Comment), font_scale),
+        };
+
+        pieces.push((ch.to_string(), rgba, scale));
+        current = Some(style);
+    }
+
+    pieces
 }
@@ -2,31 +2,160 @@ use wgpu::{Device, CommandEncoder, TextureView, Queue};
 use wgpu::util::StagingBelt;
 use winit::dpi::PhysicalSize;
 use wgpu_glyph::{GlyphBrushBuilder, Section, Text, ab_glyph, GlyphBrush, Layout};
-use wgpu_glyph::ab_glyph::FontArc;
+use wgpu_glyph::ab_glyph::{Font, FontArc, ScaleFont};
+use crossterm::style::Color;
 
-use super::{Layer, get_font};
+use super::{Layer, get_fonts, font_runs, quad_pipeline, push_quad, QUAD_VERTEX_FLOATS};
 use super::gutter::GutterLayer;
 use crate::plugins::config::Config;
+use crate::log;
 use crate::editor::Editor;
+use crate::types::{Grid, RenderCell};
+use crate::ui::popup::Popups;
+use crate::ui::ui_element::UiElement;
 use crate::ui::ui_manager::UiManager;
-use crate::renderer::wgpu::utils::{hex_to_wgpu_color, calculate_gutter_width, status_bar_height};
+use crate::renderer::crossterm::GUTTER_WIDTH;
+use crate::renderer::wgpu::utils::{hex_to_wgpu_color, calculate_gutter_width, srgb_to_linear, status_bar_height};
+
+/// Background runs of popups drawn per frame, the rest is left out.
+const MAX_QUADS: usize = 2048;
 
 pub struct UiLayer {
     glyph_brush: GlyphBrush<()>,
     font: ab_glyph::FontArc,
+    fonts: Vec<FontArc>,
     font_scale: f32,
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    quads: usize,
+}
+
+impl UiLayer {
+    /// Lays the popups out on the cells the terminal frontend would have, at the pixels
+    /// `cell_at` maps those cells to, so anchors at the cursor line up.
+    fn queue_popups(&mut self, editor: &Editor, popups: &Popups, config: &Config, queue: &Queue, surface_size: PhysicalSize<u32>) {
+        let (width, height) = (surface_size.width as f32, surface_size.height as f32);
+        let scaled_font = self.font.as_scaled(self.font_scale);
+        let advance = scaled_font.h_advance(scaled_font.glyph_id('M'));
+        let line_height = self.font_scale + 2.0;
+
+        let max_line = editor.active_view()
+            .map(|view| view.visible_top() + view.size.rows as usize)
+            .unwrap_or(0);
+        let start_x = 20.0 + calculate_gutter_width(&self.font, &self.font_scale, max_line);
+
+        let cell_x = |col: usize| start_x + (col as f32 - GUTTER_WIDTH as f32) * advance;
+        let cell_y = |row: usize| status_bar_height() + (row as f32 - 1.0) * line_height;
+
+        let rows = 1 + ((height - status_bar_height()) / line_height).max(0.0) as usize;
+        let cols = GUTTER_WIDTH as usize + ((width - start_x) / advance).max(0.0) as usize;
+
+        let mut grid = Grid::new(rows, cols, RenderCell::blank());
+        popups.render(&mut grid);
+
+        let theme = config.current_theme();
+        let fg = hex_to_wgpu_color(&theme.Foreground.clone().unwrap_or_default());
+        let bg = theme.Background.as_deref().map(hex_to_wgpu_color).unwrap_or(wgpu::Color::BLACK);
+
+        let mut vertices: Vec<f32> = vec![];
+        let mut sections: Vec<((f32, f32), Vec<(String, [f32; 4])>)> = vec![];
+
+        for (row, cells) in grid.cells.iter().enumerate() {
+            let mut col = 0;
+
+            while col < cells.len() {
+                if cells[col].transparent {
+                    col += 1;
+                    continue;
+                }
+
+                // A run of cells on the same background is one quad and one section
+                let background = cells[col].style.background_color;
+                let start = col;
+                let mut pieces: Vec<(String, [f32; 4])> = vec![];
+
+                while col < cells.len() && !cells[col].transparent && cells[col].style.background_color == background {
+                    let color = rgba(cells[col].style.foreground_color, fg);
+                    match pieces.last_mut() {
+                        Some((text, last)) if *last == color => text.push(cells[col].ch),
+                        _ => pieces.push((cells[col].ch.to_string(), color)),
+                    }
+                    col += 1;
+                }
+
+                if vertices.len() < MAX_QUADS * 6 * QUAD_VERTEX_FLOATS {
+                    let rect = (cell_x(start), cell_y(row), cell_x(col), cell_y(row) + line_height);
+                    push_quad(&mut vertices, rect, rgba(background, bg), surface_size);
+                }
+                sections.push(((cell_x(start), cell_y(row)), pieces));
+            }
+        }
+
+        self.quads = vertices.len() / (6 * QUAD_VERTEX_FLOATS);
+        if self.quads > 0 {
+            let bytes = unsafe {
+                std::slice::from_raw_parts(
+                    vertices.as_ptr() as *const u8,
+                    vertices.len() * std::mem::size_of::<f32>(),
+                )
+            };
+            queue.write_buffer(&self.vertex_buffer, 0, bytes);
+        }
+
+        let layout = Layout::default_single_line();
+        for (position, pieces) in &sections {
+            self.glyph_brush.queue(Section {
+                screen_position: *position,
+                bounds: (width, height),
+                layout,
+                text: pieces.iter()
+                    .flat_map(|(piece, color)| {
+                        font_runs(&self.fonts, piece).into_iter().map(|(font_id, run)| {
+                            Text::new(run)
+                                .with_color(*color)
+                                .with_scale(self.font_scale)
+                                .with_font_id(font_id)
+                        })
+                    })
+                    .collect(),
+                ..Section::default()
+            });
+        }
+    }
+}
+
+/// A terminal color of a cell as linear RGBA, the theme's for anything but RGB.
+fn rgba(color: Option<Color>, fallback: wgpu::Color) -> [f32; 4] {
+    match color {
+        Some(Color::Rgb { r, g, b }) => {
+            let linear = |c: u8| srgb_to_linear(c as f32 / 255.0);
+            [linear(r), linear(g), linear(b), 1.0]
+        }
+        _ => [fallback.r as f32, fallback.g as f32, fallback.b as f32, fallback.a as f32],
+    }
 }
 
 impl Layer for UiLayer {
     fn new(device: &Device, render_format: wgpu::TextureFormat) -> Self where Self: Sized {
-        let font = get_font();
-        let glyph_brush = GlyphBrushBuilder::using_font(font.clone())
+        let fonts = get_fonts();
+        let glyph_brush = GlyphBrushBuilder::using_fonts(fonts.clone())
             .build(device, render_format);
 
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Popup VB"),
+            size: (MAX_QUADS * 6 * QUAD_VERTEX_FLOATS * std::mem::size_of::<f32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
         Self {
             glyph_brush,
-            font: font,
+            font: fonts[0].clone(),
+            fonts,
             font_scale: 26.0,
+            pipeline: quad_pipeline(device, render_format, "Popup"),
+            vertex_buffer,
+            quads: 0,
         }
     }
 
@@ -44,20 +173,27 @@ impl Layer for UiLayer {
         let theme = config.current_theme();
         let fg = hex_to_wgpu_color(&theme.Foreground.unwrap_or_default());
         let layout = Layout::default_single_line();
-        
+
         // TODO: Render ui based on ui parameter
         self.glyph_brush.queue(Section {
             screen_position: (20.0 + 8.0, 20.0 + 8.0),
             bounds: (surface_size.width as f32, surface_size.height as f32),
             layout,
-            text: vec![
-                Text::new("Oxidy")
-                    .with_color([fg.r as f32, fg.g as f32, fg.b as f32, fg.a as f32])
-                    .with_scale(self.font_scale),
-            ],
+            text: font_runs(&self.fonts, "Oxidy").into_iter()
+                .map(|(font_id, run)| {
+                    Text::new(run)
+                        .with_color([fg.r as f32, fg.g as f32, fg.b as f32, fg.a as f32])
+                        .with_scale(self.font_scale)
+                        .with_font_id(font_id)
+                })
+                .collect(),
             ..Section::default()
         });
 
+        self.quads = 0;
+        if let Some(popups) = ui.get::<Popups>() {
+            self.queue_popups(editor, popups, config, queue, surface_size);
+        }
     }
 
     fn draw(
@@ -69,7 +205,30 @@ impl Layer for UiLayer {
         staging_belt: &mut StagingBelt,
         surface_size: PhysicalSize<u32>,
     ) {
-        self.glyph_brush
+        // Popup backgrounds first, their text goes on top with the rest of the glyphs
+        if self.quads > 0 {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Popup pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            rpass.draw(0..(self.quads * 6) as u32, 0..1);
+        }
+
+        let drawn = self.glyph_brush
             .draw_queued(
                 device,
                 staging_belt,
@@ -77,8 +236,11 @@ impl Layer for UiLayer {
                 view,
                 surface_size.width,
                 surface_size.height,
-            )
-            .expect("Draw queued for ui");
+            );
+
+        if let Err(error) = drawn {
+            log!("Could not draw the ui: {}", error);
+        }
 
     }
 }
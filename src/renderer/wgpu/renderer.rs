@@ -4,6 +4,9 @@ use wgpu::CompositeAlphaMode;
 use wgpu::util::StagingBelt;
 use winit::dpi::PhysicalSize;
 
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use crate::buffer::{Buffer, BufferView};
@@ -13,9 +16,19 @@ use crate::types::{EditorMode, Size, RenderCell, Grid, Rect, ViewId};
 use crate::ui::ui_manager::UiManager;
 use crate::editor::Editor;
 
-use crate::renderer::wgpu::layer::{Layer, background::BackgroundLayer, text::TextLayer, gutter::GutterLayer, cursor::CursorLayer, ui::UiLayer};
-use crate::renderer::wgpu::utils::{hex_to_wgpu_color, srgb_to_linear};
+use crate::renderer::wgpu::layer::{Layer, background::BackgroundLayer, text::TextLayer, gutter::GutterLayer, cursor::CursorLayer, cursor_line::CursorLineLayer, ui::UiLayer};
+use crate::renderer::wgpu::utils::{hex_to_wgpu_color, srgb_to_linear, calculate_gutter_width, status_bar_height};
+use crate::renderer::wgpu::layer::get_font;
+use crate::renderer::crossterm::GUTTER_WIDTH;
+use wgpu_glyph::ab_glyph::{Font, ScaleFont};
 use crate::renderer::Renderer;
+use crate::log;
+
+/// Frames being written to a directory, one PNG each, to be turned into a GIF.
+struct Recording {
+    dir: PathBuf,
+    frames: usize,
+}
 
 pub struct WgpuRenderer {
     pub surface: Surface<'static>,
@@ -28,6 +41,7 @@ pub struct WgpuRenderer {
     pub size: PhysicalSize<u32>,
 
     layers: Vec<Box<dyn Layer>>,
+    recording: Option<Recording>,
 }
 
 impl WgpuRenderer {
@@ -85,6 +99,7 @@ impl WgpuRenderer {
 
         let mut layers: Vec<Box<dyn Layer>> = Vec::new();
         layers.push(Box::new(BackgroundLayer::new(&device, render_format)));
+        layers.push(Box::new(CursorLineLayer::new(&device, render_format)));
         layers.push(Box::new(GutterLayer::new(&device, render_format)));
         layers.push(Box::new(TextLayer::new(&device, render_format)));
         layers.push(Box::new(UiLayer::new(&device, render_format)));
@@ -103,29 +118,54 @@ impl WgpuRenderer {
             render_format,
             size: inner_size,
             layers,
+            recording: None,
         }
     }
-}
 
-impl Renderer for WgpuRenderer {
-    fn begin_frame(&mut self) {}
+    /// Converts a pixel position into the cell coordinates the terminal frontend would report for it,
+    /// so mouse handling in `App` works the same for both.
+    pub fn cell_at(&self, editor: &Editor, x: f64, y: f64) -> (u16, u16) {
+        let font = get_font();
+        let font_scale = 26.0;
+        let scaled_font = font.as_scaled(font_scale);
+        let advance = scaled_font.h_advance(scaled_font.glyph_id('M'));
 
-    fn draw_buffer(&mut self, editor: &Editor, ui: &UiManager, config: &Config) {
-        let mut encoder = self.device.create_command_encoder(
-            &wgpu::CommandEncoderDescriptor {
-                label: Some("Redraw"),
+        let max_line = editor.active_view()
+            .map(|view| view.visible_top() + view.size.rows as usize)
+            .unwrap_or(0);
+        let start_x = 20.0 + calculate_gutter_width(&font, &font_scale, max_line);
+
+        let col = ((x as f32 - start_x) / advance).max(0.0) as u16 + GUTTER_WIDTH;
+        let row = ((y as f32 - status_bar_height()) / (font_scale + 2.0)).max(0.0) as u16 + 1;
+
+        (col, row)
+    }
+
+    /// Sets the surface up for the current size, after a resize or when it was lost.
+    pub fn configure_surface(&mut self) {
+        self.surface.configure(
+            &self.device,
+            &wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                format: self.render_format,
+                width: self.size.width,
+                height: self.size.height,
+                present_mode: wgpu::PresentMode::AutoVsync,
+                alpha_mode: CompositeAlphaMode::Auto,
+                view_formats: vec![self.render_format],
+                desired_maximum_frame_latency: 2,
             },
         );
+    }
+}
 
-        let frame = self.surface.get_current_texture().expect("Get next frame");
-        let view = &frame
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
+impl WgpuRenderer {
+    /// Clears `view` to the theme background and draws every layer on top.
+    fn draw_layers(&mut self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, editor: &Editor, ui: &UiManager, config: &Config, alpha: f64) {
         let theme = config.current_theme();
         let mut bg_color = hex_to_wgpu_color(&theme.Background.unwrap_or_default());
         
-        bg_color.a = 0.5;
+        bg_color.a = alpha;
         {
             let _render_pass = encoder.begin_render_pass(
                 &wgpu::RenderPassDescriptor {
@@ -150,14 +190,149 @@ impl Renderer for WgpuRenderer {
 
         for layer in &mut self.layers {
             layer.update(editor, ui, config, &self.device, &self.queue, self.size);
-            layer.draw(&mut encoder, view, &self.device, &self.queue, &mut self.staging_belt, self.size);
+            layer.draw(encoder, view, &self.device, &self.queue, &mut self.staging_belt, self.size);
         }
+    }
+
+    /// Draws a frame into a texture instead of the window and reads it back as RGBA rows.
+    /// The background is opaque, unlike in the window.
+    fn capture(&mut self, editor: &Editor, ui: &UiManager, config: &Config) -> io::Result<Vec<u8>> {
+        use wgpu::TextureFormat::*;
+
+        let bgra = match self.render_format {
+            Bgra8Unorm | Bgra8UnormSrgb => true,
+            Rgba8Unorm | Rgba8UnormSrgb => false,
+            format => return Err(io::Error::new(io::ErrorKind::Unsupported, format!("can't read back {:?} frames", format))),
+        };
+
+        let (width, height) = (self.size.width, self.size.height);
+        let extent = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Screenshot"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.render_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Rows in the buffer have to start at multiples of 256 bytes
+        let row_bytes = width * 4;
+        let padded_row_bytes = row_bytes.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Screenshot"),
+            size: padded_row_bytes as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor {
+                label: Some("Screenshot"),
+            },
+        );
+
+        self.draw_layers(&mut encoder, &view, editor, ui, config, 1.0);
+
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: Some(height),
+                },
+            },
+            extent,
+        );
+
+        self.staging_belt.finish();
+        self.queue.submit(Some(encoder.finish()));
+        self.staging_belt.recall();
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::PollType::Wait).map_err(io::Error::other)?;
+        receiver.recv().map_err(io::Error::other)?.map_err(io::Error::other)?;
+
+        let mut pixels = Vec::with_capacity((row_bytes * height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(padded_row_bytes as usize).take(height as usize) {
+                for pixel in row[..row_bytes as usize].chunks_exact(4) {
+                    match bgra {
+                        true => pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]),
+                        false => pixels.extend_from_slice(pixel),
+                    }
+                }
+            }
+        }
+        buffer.unmap();
+
+        Ok(pixels)
+    }
+}
+
+impl Renderer for WgpuRenderer {
+    fn begin_frame(&mut self) {}
+
+    fn draw_buffer(&mut self, editor: &Editor, ui: &UiManager, config: &Config) {
+        let mut encoder = self.device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor {
+                label: Some("Redraw"),
+            },
+        );
+
+        let frame = match self.surface.get_current_texture() {
+            Ok(frame) => frame,
+            // The window changed under the surface, the next frame uses the new configuration
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.configure_surface();
+                return;
+            }
+            Err(wgpu::SurfaceError::Timeout) => return,
+            Err(error) => {
+                log!("Could not get the next frame: {}", error);
+                return;
+            }
+        };
+        let view = &frame
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        self.draw_layers(&mut encoder, view, editor, ui, config, 0.5);
 
         self.staging_belt.finish();
         self.queue.submit(Some(encoder.finish()));
         frame.present();
         
         self.staging_belt.recall();
+
+        let frame_path = self.recording.as_ref()
+            .map(|recording| recording.dir.join(format!("{:05}.png", recording.frames)));
+
+        if let Some(path) = frame_path {
+            match self.screenshot(editor, ui, config, &path) {
+                Ok(()) => if let Some(recording) = &mut self.recording { recording.frames += 1 },
+                Err(error) => {
+                    log!("Stopped recording, could not write {}: {}", path.display(), error);
+                    self.recording = None;
+                }
+            }
+        }
     }
 
     fn end_frame(&mut self) {}
@@ -165,19 +340,7 @@ impl Renderer for WgpuRenderer {
     fn resize(&mut self, new_size: Size) {
         if new_size.cols > 0 && new_size.rows > 0 {
             self.size = PhysicalSize::new(new_size.cols as u32, new_size.rows as u32);
-            self.surface.configure(
-                &self.device,
-                &wgpu::SurfaceConfiguration {
-                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                    format: self.render_format,
-                    width: self.size.width,
-                    height: self.size.height,
-                    present_mode: wgpu::PresentMode::AutoVsync,
-                    alpha_mode: CompositeAlphaMode::Auto,
-                    view_formats: vec![],
-                    desired_maximum_frame_latency: 2,
-                },
-            );
+            self.configure_surface();
 
             for layer in &mut self.layers {
                 layer.resize(self.size);
@@ -185,6 +348,28 @@ impl Renderer for WgpuRenderer {
         }
     }
 
+    fn screenshot(&mut self, editor: &Editor, ui: &UiManager, config: &Config, path: &Path) -> io::Result<()> {
+        let pixels = self.capture(editor, ui, config)?;
+
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.size.width, self.size.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(&pixels).map_err(io::Error::other)
+    }
+
+    fn start_recording(&mut self, dir: PathBuf) -> io::Result<()> {
+        std::fs::create_dir_all(&dir)?;
+        self.recording = Some(Recording { dir, frames: 0 });
+        Ok(())
+    }
+
+    fn stop_recording(&mut self) -> Option<(PathBuf, usize)> {
+        self.recording.take().map(|recording| (recording.dir, recording.frames))
+    }
+
     fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
         self
     }
@@ -0,0 +1,144 @@
+use std::io::{self, Read, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+// Older output is dropped so a chatty process can't grow without bound
+const MAX_OUTPUT_LINES: usize = 1000;
+
+enum ReplEvent {
+    Output(String),
+    Exited,
+}
+
+/// The interpreter `:repl start` uses for a filetype when no command is given.
+pub fn default_command(filetype: &str) -> Option<&'static str> {
+    match filetype {
+        // Unbuffered and interactive even though stdin is a pipe
+        "py" => Some("python3 -i -u"),
+        "js" | "mjs" | "cjs" => Some("node -i"),
+        "hs" => Some("ghci"),
+        "rb" => Some("irb"),
+        "lua" => Some("lua -i"),
+        _ => None,
+    }
+}
+
+/// An interpreter running next to the editor that code is sent to.
+pub struct Repl {
+    pub command: String,
+    /// Everything the process printed, the last line may still be incomplete.
+    pub output: Vec<String>,
+    pub exited: bool,
+    child: Child,
+    stdin: ChildStdin,
+    receiver: Receiver<ReplEvent>,
+}
+
+impl Repl {
+    pub fn spawn(command: &str) -> io::Result<Self> {
+        // exec so stopping the REPL kills the interpreter and not just the shell
+        let mut child = Command::new("sh")
+            .args(["-c", &format!("exec {}", command)])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+        let (sender, receiver) = mpsc::channel();
+
+        // Read in chunks, prompts like ">>> " don't end with a newline
+        Self::forward(stdout, sender.clone(), true);
+        Self::forward(stderr, sender, false);
+
+        Ok(Self {
+            command: command.to_string(),
+            output: vec![String::new()],
+            exited: false,
+            child,
+            stdin,
+            receiver,
+        })
+    }
+
+    fn forward(mut reader: impl Read + Send + 'static, sender: Sender<ReplEvent>, report_exit: bool) {
+        thread::spawn(move || {
+            let mut chunk = [0u8; 4096];
+
+            loop {
+                match reader.read(&mut chunk) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        let text = String::from_utf8_lossy(&chunk[..n]).to_string();
+                        if sender.send(ReplEvent::Output(text)).is_err() { return }
+                    }
+                }
+            }
+
+            if report_exit {
+                let _ = sender.send(ReplEvent::Exited);
+            }
+        });
+    }
+
+    /// Sends code as if it was typed, followed by enter.
+    pub fn send(&mut self, text: &str) -> io::Result<()> {
+        let mut text = text.trim_end_matches('\n').to_string();
+        text.push('\n');
+
+        // Python only runs an indented block once it sees an empty line
+        if text.lines().count() > 1 {
+            text.push('\n');
+        }
+
+        self.stdin.write_all(text.as_bytes())?;
+        self.stdin.flush()
+    }
+
+    /// Collects output that arrived since the last call. Returns true if anything changed.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+
+        while let Ok(event) = self.receiver.try_recv() {
+            changed = true;
+
+            match event {
+                ReplEvent::Output(text) => {
+                    let mut parts = text.split('\n');
+
+                    if let (Some(first), Some(last)) = (parts.next(), self.output.last_mut()) {
+                        last.push_str(first.trim_end_matches('\r'));
+                    }
+                    self.output.extend(parts.map(|part| part.trim_end_matches('\r').to_string()));
+                }
+                ReplEvent::Exited => self.exited = true,
+            }
+        }
+
+        if self.output.len() > MAX_OUTPUT_LINES {
+            self.output.drain(..self.output.len() - MAX_OUTPUT_LINES);
+        }
+
+        changed
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    pub fn stop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        self.exited = true;
+    }
+}
+
+impl Drop for Repl {
+    fn drop(&mut self) {
+        // Also reaps a process that already exited
+        self.stop();
+    }
+}
@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use crate::buffer::Buffer;
+use crate::collab::{self, CollabMessage, Edit, RemoteCursor};
+use crate::log;
+
+// Site id of the host, clients are numbered from 1
+const HOST_SITE: u64 = 0;
+
+pub enum CollabServiceEvent {
+    // A client received the shared buffer from the host
+    Joined { path: String, text: String },
+    RemoteEdit(Edit),
+    PeerJoined(String),
+    PeerLeft(String),
+    // Someone connected to the host with the wrong token
+    PeerRefused,
+    // The host didn't let this client in
+    Refused(String),
+    Disconnected,
+}
+
+enum Incoming {
+    Connected(u64, TcpStream),
+    Message(u64, CollabMessage),
+    Disconnected(u64),
+}
+
+struct Peer {
+    name: String,
+    stream: TcpStream,
+}
+
+enum Role {
+    Host {
+        token: String,
+        // Connected, but not introduced with `Hello` yet
+        pending: HashMap<u64, TcpStream>,
+        peers: HashMap<u64, Peer>,
+        // Every edit in the order the host applied it, with the site it came from
+        history: Vec<(u64, Option<Edit>)>,
+    },
+    Client {
+        stream: TcpStream,
+        site: u64,
+        revision: u64,
+        // Sent to the host but not acknowledged yet
+        awaiting: Option<Option<Edit>>,
+        // Local edits held back until the outstanding one is acknowledged
+        buffered: Vec<Edit>,
+    },
+}
+
+/// Shares one buffer between Oxidy instances over TCP.
+/// The host orders all edits; clients send one edit at a time and transform remote edits against their own.
+pub struct CollabService {
+    role: Role,
+    name: String,
+    receiver: Receiver<Incoming>,
+    cursors: Vec<RemoteCursor>,
+    last_cursor: Option<(usize, usize)>,
+}
+
+fn send(stream: &mut TcpStream, message: &CollabMessage) -> io::Result<()> {
+    let mut json = serde_json::to_string(message)?;
+    json.push('\n');
+    stream.write_all(json.as_bytes())
+}
+
+/// A random token for `collab host`, from the OS seeded keys of the std hasher.
+pub fn new_token() -> String {
+    let half = || {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos());
+        hasher.finish()
+    };
+
+    format!("{:016x}{:016x}", half(), half())
+}
+
+/// Compares every byte so the time taken doesn't tell how much of a guess was right.
+fn tokens_match(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected.bytes().zip(given.bytes()).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+fn spawn_reader(site: u64, stream: TcpStream, sender: Sender<Incoming>) {
+    thread::spawn(move || {
+        let reader = BufReader::new(stream);
+
+        for line in reader.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+
+            match serde_json::from_str::<CollabMessage>(&line) {
+                Ok(message) => {
+                    if sender.send(Incoming::Message(site, message)).is_err() { return }
+                }
+                Err(error) => log!("collab: invalid message: {}", error),
+            }
+        }
+
+        let _ = sender.send(Incoming::Disconnected(site));
+    });
+}
+
+impl CollabService {
+    /// Listens on `address`, only clients that send `token` get to see the buffer.
+    pub fn host(address: &str, name: String, token: String) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut next_site = HOST_SITE + 1;
+
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+
+                let reader = match stream.try_clone() {
+                    Ok(reader) => reader,
+                    Err(_) => continue,
+                };
+
+                if sender.send(Incoming::Connected(next_site, stream)).is_err() { return }
+                spawn_reader(next_site, reader, sender.clone());
+                next_site += 1;
+            }
+        });
+
+        Ok(Self {
+            role: Role::Host { token, pending: HashMap::new(), peers: HashMap::new(), history: Vec::new() },
+            name,
+            receiver,
+            cursors: Vec::new(),
+            last_cursor: None,
+        })
+    }
+
+    pub fn join(address: &str, name: String, token: String) -> io::Result<Self> {
+        let mut stream = TcpStream::connect(address)?;
+        let (sender, receiver) = mpsc::channel();
+
+        send(&mut stream, &CollabMessage::Hello { name: name.clone(), token })?;
+        spawn_reader(HOST_SITE, stream.try_clone()?, sender);
+
+        Ok(Self {
+            role: Role::Client { stream, site: HOST_SITE, revision: 0, awaiting: None, buffered: Vec::new() },
+            name,
+            receiver,
+            cursors: Vec::new(),
+            last_cursor: None,
+        })
+    }
+
+    pub fn is_host(&self) -> bool {
+        matches!(self.role, Role::Host { .. })
+    }
+
+    pub fn cursors(&self) -> &[RemoteCursor] {
+        &self.cursors
+    }
+
+    /// Shares an edit made in the local editor.
+    pub fn local_edit(&mut self, edit: Edit) {
+        self.map_cursors(&edit);
+
+        match &mut self.role {
+            Role::Host { peers, history, .. } => {
+                history.push((HOST_SITE, Some(edit.clone())));
+                broadcast(peers, None, &CollabMessage::Applied { site: HOST_SITE, edit: Some(edit) });
+            }
+            Role::Client { stream, revision, awaiting, buffered, .. } => {
+                if awaiting.is_some() {
+                    buffered.push(edit);
+                } else {
+                    let _ = send(stream, &CollabMessage::Edit { revision: *revision, edit: edit.clone() });
+                    *awaiting = Some(Some(edit));
+                }
+            }
+        }
+    }
+
+    /// Tells the others where the local cursor is, if it moved.
+    pub fn update_cursor(&mut self, row: usize, col: usize) {
+        if self.last_cursor == Some((row, col)) { return }
+        self.last_cursor = Some((row, col));
+
+        match &mut self.role {
+            Role::Host { peers, .. } => {
+                let message = CollabMessage::Cursor { site: HOST_SITE, name: self.name.clone(), row, col };
+                broadcast(peers, None, &message);
+            }
+            Role::Client { stream, site, .. } => {
+                let message = CollabMessage::Cursor { site: *site, name: self.name.clone(), row, col };
+                let _ = send(stream, &message);
+            }
+        }
+    }
+
+    fn map_cursors(&mut self, edit: &Edit) {
+        for cursor in self.cursors.iter_mut() {
+            (cursor.row, cursor.col) = collab::map_position(cursor.row, cursor.col, edit, true);
+        }
+    }
+
+    fn set_cursor(&mut self, site: u64, name: String, row: usize, col: usize) {
+        match self.cursors.iter_mut().find(|cursor| cursor.site == site) {
+            Some(cursor) => {
+                cursor.row = row;
+                cursor.col = col;
+            }
+            None => self.cursors.push(RemoteCursor { site, name, row, col }),
+        }
+    }
+
+    fn remove_cursor(&mut self, site: u64) -> Option<String> {
+        let index = self.cursors.iter().position(|cursor| cursor.site == site)?;
+        Some(self.cursors.remove(index).name)
+    }
+
+    /// Handles everything that arrived from the network since the last call.
+    /// `buffer` is the shared buffer, used to bring new clients up to date.
+    pub fn poll(&mut self, buffer: Option<&Buffer>) -> Vec<CollabServiceEvent> {
+        let mut events = Vec::new();
+
+        while let Ok(incoming) = self.receiver.try_recv() {
+            match incoming {
+                Incoming::Connected(site, stream) => {
+                    if let Role::Host { pending, .. } = &mut self.role {
+                        pending.insert(site, stream);
+                    }
+                }
+                Incoming::Message(site, message) => {
+                    if self.is_host() {
+                        self.handle_host_message(site, message, buffer, &mut events);
+                    } else {
+                        self.handle_client_message(message, &mut events);
+                    }
+                }
+                Incoming::Disconnected(site) => {
+                    if let Role::Host { pending, peers, .. } = &mut self.role {
+                        pending.remove(&site);
+                        peers.remove(&site);
+                        broadcast(peers, None, &CollabMessage::Leave { site });
+
+                        if let Some(name) = self.remove_cursor(site) {
+                            events.push(CollabServiceEvent::PeerLeft(name));
+                        }
+                    } else {
+                        events.push(CollabServiceEvent::Disconnected);
+                    }
+                }
+            }
+        }
+
+        events
+    }
+
+    fn handle_host_message(&mut self, site: u64, message: CollabMessage, buffer: Option<&Buffer>, events: &mut Vec<CollabServiceEvent>) {
+        let Role::Host { token: expected, pending, peers, history } = &mut self.role else { return };
+
+        match message {
+            CollabMessage::Hello { name, token } => {
+                if !tokens_match(expected, &token) {
+                    if let Some(mut stream) = pending.remove(&site) {
+                        let _ = send(&mut stream, &CollabMessage::Refused { reason: "wrong token".to_string() });
+                        let _ = stream.shutdown(std::net::Shutdown::Both);
+                    }
+                    events.push(CollabServiceEvent::PeerRefused);
+                    return;
+                }
+
+                let (path, text) = buffer
+                    .map(|buffer| (buffer.path.clone(), buffer.text()))
+                    .unwrap_or_default();
+
+                let Some(mut stream) = pending.remove(&site) else { return };
+
+                let welcome = CollabMessage::Welcome { site, revision: history.len() as u64, path, text };
+                let _ = send(&mut stream, &welcome);
+                peers.insert(site, Peer { name: name.clone(), stream });
+
+                events.push(CollabServiceEvent::PeerJoined(name));
+            }
+            // Nothing counts before the client was let in
+            _ if !peers.contains_key(&site) => {}
+            CollabMessage::Edit { revision, edit } => {
+                let mut edit = Some(edit);
+
+                for (other_site, other) in history.iter().skip(revision as usize) {
+                    if let (Some(current), Some(other)) = (edit.as_ref(), other.as_ref()) {
+                        edit = collab::transform(current, other, site < *other_site);
+                    }
+                }
+
+                history.push((site, edit.clone()));
+                broadcast(peers, None, &CollabMessage::Applied { site, edit: edit.clone() });
+
+                if let Some(edit) = edit {
+                    self.map_cursors(&edit);
+                    events.push(CollabServiceEvent::RemoteEdit(edit));
+                }
+            }
+            CollabMessage::Cursor { row, col, .. } => {
+                let name = peers.get(&site).map(|peer| peer.name.clone()).unwrap_or_default();
+                broadcast(peers, Some(site), &CollabMessage::Cursor { site, name: name.clone(), row, col });
+                self.set_cursor(site, name, row, col);
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_client_message(&mut self, message: CollabMessage, events: &mut Vec<CollabServiceEvent>) {
+        let Role::Client { stream, site: own_site, revision, awaiting, buffered } = &mut self.role else { return };
+
+        match message {
+            CollabMessage::Welcome { site, revision: host_revision, path, text } => {
+                *own_site = site;
+                *revision = host_revision;
+                events.push(CollabServiceEvent::Joined { path, text });
+            }
+            CollabMessage::Refused { reason } => {
+                events.push(CollabServiceEvent::Refused(reason));
+            }
+            CollabMessage::Applied { site, .. } if site == *own_site => {
+                *revision += 1;
+                *awaiting = None;
+
+                if !buffered.is_empty() {
+                    let edit = buffered.remove(0);
+                    let _ = send(stream, &CollabMessage::Edit { revision: *revision, edit: edit.clone() });
+                    *awaiting = Some(Some(edit));
+                }
+            }
+            CollabMessage::Applied { site, edit } => {
+                *revision += 1;
+                let own_first = *own_site < site;
+
+                let mut remote = edit;
+
+                // Bring the remote edit past everything we did locally that the host hasn't seen yet
+                if let Some(Some(local)) = awaiting.as_mut() {
+                    if let Some(incoming) = remote.as_ref() {
+                        let transformed_local = collab::transform(local, incoming, own_first);
+                        remote = collab::transform(incoming, local, !own_first);
+                        *awaiting = Some(transformed_local);
+                    }
+                }
+
+                let mut transformed_buffer = Vec::new();
+                for local in buffered.drain(..) {
+                    match remote.as_ref() {
+                        Some(incoming) => {
+                            let next = collab::transform(incoming, &local, !own_first);
+                            transformed_buffer.extend(collab::transform(&local, incoming, own_first));
+                            remote = next;
+                        }
+                        None => transformed_buffer.push(local),
+                    }
+                }
+                *buffered = transformed_buffer;
+
+                if let Some(edit) = remote {
+                    self.map_cursors(&edit);
+                    events.push(CollabServiceEvent::RemoteEdit(edit));
+                }
+            }
+            CollabMessage::Cursor { site, name, row, col } => {
+                self.set_cursor(site, name, row, col);
+            }
+            CollabMessage::Leave { site } => {
+                if let Some(name) = self.remove_cursor(site) {
+                    events.push(CollabServiceEvent::PeerLeft(name));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn broadcast(peers: &mut HashMap<u64, Peer>, except: Option<u64>, message: &CollabMessage) {
+    for (site, peer) in peers.iter_mut() {
+        if Some(*site) == except { continue }
+        let _ = send(&mut peer.stream, message);
+    }
+}
+
+impl Drop for CollabService {
+    fn drop(&mut self) {
+        match &mut self.role {
+            Role::Host { peers, .. } => {
+                for peer in peers.values() {
+                    let _ = peer.stream.shutdown(std::net::Shutdown::Both);
+                }
+            }
+            Role::Client { stream, .. } => {
+                let _ = stream.shutdown(std::net::Shutdown::Both);
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use crate::git::{self, BlameLine, GitStatus};
+use crate::types::BufferId;
+
+pub enum GitUpdate {
+    // The lines of a buffer's file as staged, `None` if it isn't tracked
+    Staged(BufferId, Option<Vec<String>>),
+    // The commit of each line of a buffer's file, `None` if it isn't tracked
+    Blame(BufferId, Option<Vec<BlameLine>>),
+    // The repository of the active file, `None` outside of one
+    Status(Option<GitStatus>),
+}
+
+/// Asks git off the main thread, for the gutter signs and the status bar.
+pub struct GitService {
+    sender: Sender<GitUpdate>,
+    receiver: Receiver<GitUpdate>,
+}
+
+impl GitService {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self { sender, receiver }
+    }
+
+    /// Starts reading the staged version of `path`, `poll` hands it over once git answered.
+    pub fn request(&self, id: BufferId, path: &str) {
+        let sender = self.sender.clone();
+        let path = path.to_string();
+
+        thread::spawn(move || {
+            let _ = sender.send(GitUpdate::Staged(id, git::staged(&path)));
+        });
+    }
+
+    /// Starts blaming `path`, which takes a while on files with a long history.
+    pub fn request_blame(&self, id: BufferId, path: &str) {
+        let sender = self.sender.clone();
+        let path = path.to_string();
+
+        thread::spawn(move || {
+            let _ = sender.send(GitUpdate::Blame(id, git::blame(&path)));
+        });
+    }
+
+    /// Starts reading the branch and whether there are changes in the repository of `dir`.
+    pub fn request_status(&self, dir: PathBuf) {
+        let sender = self.sender.clone();
+
+        thread::spawn(move || {
+            let _ = sender.send(GitUpdate::Status(git::status(&dir)));
+        });
+    }
+
+    pub fn poll(&self) -> Vec<GitUpdate> {
+        self.receiver.try_iter().collect()
+    }
+}
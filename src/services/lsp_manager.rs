@@ -0,0 +1,245 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+
+use crate::services::lsp_service::LspService;
+use crate::types::BufferId;
+
+/// How long servers get to exit on their own after `shutdown` before they are killed.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Crashed servers are started again after 1, 2, 4, 8 and 16 seconds, then left alone.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTARTS: u32 = 5;
+
+/// A server that stayed up this long starts counting its crashes from zero again.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// Directories that mark the root of a project, `.git` wins over the others
+/// so a cargo workspace shares one server between its crates.
+const ROOT_MARKERS: [&str; 5] = ["Cargo.toml", "package.json", "go.mod", "pyproject.toml", "compile_commands.json"];
+
+/// Which server handles a buffer: the entry in `oxidy.lsps` and the workspace it runs in.
+/// In single-file mode there is no workspace, every file gets a server of its own
+/// and `root` is the file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServerKey {
+    pub name: String,
+    pub root: String,
+    pub single_file: bool,
+}
+
+impl ServerKey {
+    pub fn new(name: &str, path: &str, single_file: bool) -> Self {
+        let root = match single_file {
+            true => std::fs::canonicalize(path).map(|path| path.to_string_lossy().to_string()).unwrap_or(path.to_string()),
+            false => workspace_root(path),
+        };
+
+        Self { name: name.to_string(), root, single_file }
+    }
+
+    /// The workspace sent with `initialize`, none in single-file mode.
+    pub fn workspace(&self) -> Option<&str> {
+        (!self.single_file).then_some(self.root.as_str())
+    }
+}
+
+/// A server process that went away without being asked to.
+pub struct Crash {
+    pub key: ServerKey,
+    pub status: Option<ExitStatus>,
+    /// When it will be started again, `None` once it crashed too often.
+    pub retry_in: Option<Duration>,
+}
+
+/// The running language servers, one per language and workspace, and which buffer
+/// is opened on which of them.
+pub struct LspManager {
+    servers: HashMap<ServerKey, LspService>,
+    buffers: HashMap<BufferId, ServerKey>,
+    /// Buffers detached with `:lsp detach`, they aren't opened on any server.
+    disabled: HashSet<BufferId>,
+    /// When each server was started and how often it crashed in a row before that.
+    started: HashMap<ServerKey, (Instant, u32)>,
+    /// Crashed servers and when to start them again.
+    restarts: HashMap<ServerKey, Instant>,
+}
+
+impl LspManager {
+    pub fn new() -> Self {
+        Self {
+            servers: HashMap::new(),
+            buffers: HashMap::new(),
+            disabled: HashSet::new(),
+            started: HashMap::new(),
+            restarts: HashMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.servers.is_empty()
+    }
+
+    pub fn contains(&self, key: &ServerKey) -> bool {
+        self.servers.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: ServerKey, lsp: LspService) {
+        let crashes = self.started.get(&key).map_or(0, |(_, crashes)| *crashes);
+        self.restarts.remove(&key);
+        self.started.insert(key.clone(), (Instant::now(), crashes));
+        self.servers.insert(key, lsp);
+    }
+
+    pub fn get_mut(&mut self, key: &ServerKey) -> Option<&mut LspService> {
+        self.servers.get_mut(key)
+    }
+
+    pub fn servers(&self) -> impl Iterator<Item = (&ServerKey, &LspService)> {
+        self.servers.iter()
+    }
+
+    pub fn servers_mut(&mut self) -> impl Iterator<Item = (&ServerKey, &mut LspService)> {
+        self.servers.iter_mut()
+    }
+
+    /// Notes that the buffer was opened on the server, requests for it go there from now on.
+    pub fn attach(&mut self, id: BufferId, key: ServerKey) {
+        self.buffers.insert(id, key);
+    }
+
+    pub fn detach(&mut self, id: BufferId) {
+        self.buffers.remove(&id);
+    }
+
+    /// Detaches the buffer and keeps it from being opened on a server again until `enable`.
+    pub fn disable(&mut self, id: BufferId) {
+        self.buffers.remove(&id);
+        self.disabled.insert(id);
+    }
+
+    pub fn enable(&mut self, id: BufferId) {
+        self.disabled.remove(&id);
+    }
+
+    pub fn is_disabled(&self, id: BufferId) -> bool {
+        self.disabled.contains(&id)
+    }
+
+    /// Drops everything about a closed buffer.
+    pub fn forget(&mut self, id: BufferId) {
+        self.buffers.remove(&id);
+        self.disabled.remove(&id);
+    }
+
+    pub fn key_for(&self, id: BufferId) -> Option<&ServerKey> {
+        self.buffers.get(&id)
+    }
+
+    /// The server the buffer is opened on.
+    pub fn for_buffer(&mut self, id: BufferId) -> Option<&mut LspService> {
+        let key = self.buffers.get(&id)?;
+        self.servers.get_mut(key)
+    }
+
+    /// Shuts one server down, its buffers are opened on nothing until it is started again.
+    /// Forgets about its crashes, so it gets all its automatic restarts back.
+    pub fn stop(&mut self, key: &ServerKey) {
+        if let Some(lsp) = self.servers.remove(key) {
+            Self::shut_down(vec![lsp]);
+        }
+        self.buffers.retain(|_, other| other != key);
+        self.started.remove(key);
+        self.restarts.remove(key);
+    }
+
+    /// Removes the servers whose process exited and schedules their restart,
+    /// waiting twice as long after every crash in a row.
+    pub fn reap_crashed(&mut self) -> Vec<Crash> {
+        let crashed: Vec<ServerKey> = self.servers.iter_mut()
+            .filter(|(_, lsp)| lsp.has_exited())
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        crashed.into_iter()
+            .map(|key| {
+                let status = self.servers.remove(&key).and_then(|mut lsp| lsp.exit_status());
+                self.buffers.retain(|_, other| *other != key);
+
+                let crashes = match self.started.get(&key) {
+                    Some((started, crashes)) if started.elapsed() < STABLE_AFTER => crashes + 1,
+                    _ => 1,
+                };
+                self.started.insert(key.clone(), (Instant::now(), crashes));
+
+                let retry_in = (crashes <= MAX_RESTARTS).then(|| RESTART_DELAY * 2u32.pow(crashes - 1));
+                if let Some(delay) = retry_in {
+                    self.restarts.insert(key.clone(), Instant::now() + delay);
+                }
+
+                Crash { key, status, retry_in }
+            })
+            .collect()
+    }
+
+    /// When the next crashed server is started again.
+    pub fn next_restart(&self) -> Option<Instant> {
+        self.restarts.values().min().copied()
+    }
+
+    /// The crashed servers that are due to be started again.
+    pub fn due_restarts(&mut self) -> Vec<ServerKey> {
+        let now = Instant::now();
+        let due: Vec<ServerKey> = self.restarts.iter()
+            .filter(|(_, at)| **at <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in &due {
+            self.restarts.remove(key);
+        }
+
+        due
+    }
+
+    /// Shuts every server down, on exit.
+    pub fn shutdown(&mut self) {
+        let servers = self.servers.drain().map(|(_, lsp)| lsp).collect();
+        Self::shut_down(servers);
+        self.buffers.clear();
+        self.restarts.clear();
+    }
+
+    /// Asks the servers to shut down and gives them a moment to exit before they are killed.
+    fn shut_down(mut servers: Vec<LspService>) {
+        for lsp in servers.iter_mut() {
+            lsp.shutdown();
+        }
+
+        let started = Instant::now();
+        while started.elapsed() < SHUTDOWN_TIMEOUT && !servers.iter_mut().all(|lsp| lsp.has_exited()) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // Dropping kills the ones that are still running
+    }
+}
+
+/// The workspace a file belongs to: the closest directory with a `.git`, else the
+/// closest one with a project file, else the directory of the file itself.
+pub fn workspace_root(path: &str) -> String {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| Path::new(path).to_path_buf());
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+
+    let find = |markers: &[&str]| {
+        dir.ancestors().find(|ancestor| markers.iter().any(|marker| ancestor.join(marker).exists()))
+    };
+
+    find(&[".git"])
+        .or_else(|| find(&ROOT_MARKERS))
+        .unwrap_or(dir)
+        .to_string_lossy()
+        .to_string()
+}
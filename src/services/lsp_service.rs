@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::{
     sync::mpsc::{self, Sender, Receiver},
     thread,
@@ -11,57 +11,154 @@ use crossterm::style::Color;
 use serde_json::Value;
 
 use crate::buffer::Buffer;
-use crate::lsp::LspResponse::LspDiagnostics;
+use crate::lsp::LspResponse::{LspDiagnostics, LspDiagnosticParams};
 use crate::{
     lsp::{
-        LspMessage::{DidOpenParams, InitializeClientCapabilities, TextDocumentClientCapabilities, TextDocumentSyncClientCapabilities, InitializeParams, InitializedParams, LspMessage, SemanticTokenParams, SemanticTokenTextDocumentItem, TextDocumentItem}, 
-        LspResponse::{LspResponse, LspResponseResult, LspSemanticResponseResult, SemanticTokensFull}
+        LspMessage::{DidOpenParams, InitializeClientCapabilities, TextDocumentClientCapabilities, TextDocumentSyncClientCapabilities, InlayHintClientCapabilities, FoldingRangeClientCapabilities, WindowClientCapabilities, InitializeParams, InitializedParams, LspMessage, SemanticTokenParams, SemanticTokenTextDocumentItem, TextDocumentItem}, 
+        LspResponse::{InlayHintProvider, LspResponse, LspResponseResult, LspSemanticResponseResult, SemanticTokensFullData, SemanticTokensLegend, TextDocumentSync, TextDocumentSyncProvider}
     }, 
     types::Token
 };
 use crate::plugins::theme::Theme;
 use crate::log;
+use crate::types::{CallDirection, LocationKind};
+use crate::text_edit::{FileEdit, TextEdit};
+use crate::inlay_hints::InlayHint;
+use crate::folding::Fold;
+
+// TextDocumentSyncKind
+const SYNC_NONE: i32 = 0;
+const SYNC_INCREMENTAL: i32 = 2;
+
+/// Buffers with at least this many lines also get tokens for just the visible rows,
+/// which arrive long before the full set does.
+const RANGE_TOKENS_MIN_LINES: usize = 2000;
+
+/// Lines of `window/logMessage` kept for `:lsplog`.
+const MAX_LOG_LINES: usize = 2000;
+
+// MessageType
+pub const MESSAGE_ERROR: u64 = 1;
+pub const MESSAGE_WARNING: u64 = 2;
+
+/// A position in a file returned by the server. Rows and columns are zero based.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub path: String,
+    pub row: usize,
+    pub col: usize,
+}
+
+/// A function in the call hierarchy. `location` is where to jump: the call site for
+/// incoming calls, the function itself otherwise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallItem {
+    pub name: String,
+    pub detail: Option<String>,
+    pub location: Location,
+    /// The item as the server sent it, its calls are asked for with it.
+    pub item: Value,
+}
+
+/// The signature of the call at the cursor. `active` is the char range of the current parameter in `label`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub label: String,
+    pub active: Option<(usize, usize)>,
+}
 
 pub enum LspServiceEvent {
     Initialized,
+    Locations(Vec<Location>),
+    /// The functions at the cursor `:calls` starts from.
+    CallHierarchy { direction: CallDirection, items: Vec<CallItem> },
+    /// The calls to or from `parent`.
+    Calls { direction: CallDirection, parent: Value, items: Vec<CallItem> },
+    Hover(Option<String>),
+    WorkspaceEdit(Result<Vec<FileEdit>, String>),
+    Formatting(Result<Vec<TextEdit>, String>),
+    SignatureHelp(Option<Signature>),
+    /// Columns are still UTF-16 offsets, see `InlayHint::to_char_col`.
+    InlayHints { path: String, hints: Vec<InlayHint> },
+    FoldingRanges { path: String, ranges: Vec<Fold> },
     OpenedFile,
-    ReceivedDelta,
     ReceivedSemantics { semantics: LspSemanticResponseResult },
+    Progress,
+    /// A `window/showMessage` for the user, `level` is the LSP MessageType.
+    ShowMessage { level: u64, message: String },
     None
 }
 
+/// Work the server reported with `$/progress`, like indexing a workspace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    pub title: String,
+    pub message: Option<String>,
+    pub percentage: Option<u64>,
+}
+
+/// Where the server is in its startup. Requests are tracked separately, see `PendingRequest`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LspState {
     Uninitialized,
     Initializing,
     Initialized,
     OpeningFile,
-    FileOpened,
-    RequestingSemantics,
-    SemanticsReceived,
-    RequestingDelta,
-    DeltaReceived
+    FileOpened
+}
+
+/// What a request in flight asked for, so its response is parsed the right way when it arrives.
+#[derive(Debug, Clone, PartialEq)]
+enum PendingRequest {
+    Initialize,
+    SemanticTokens,
+    SemanticTokensRange { start: usize, end: usize },
+    Location,
+    PrepareCallHierarchy(CallDirection),
+    Calls { direction: CallDirection, parent: Value },
+    Hover,
+    Rename,
+    Formatting,
+    SignatureHelp,
+    // Responses don't say which file they are for
+    InlayHints { path: String },
+    FoldingRanges { path: String },
+    // Answered with edits like formatting, the buffer is saved after they are applied
+    WillSave,
+    Shutdown,
 }
 
 pub struct LspService {
-    sender: Sender<LspMessage<serde_json::Value>>,
+    /// The entry in `oxidy.lsps` the server was started for.
+    pub name: String,
+    sender: Sender<Value>,
     receiver: Receiver<LspResponse<serde_json::Value>>,
+    diagnostics_receiver: Receiver<LspDiagnosticParams>,
     process: Child,
     data: Option<LspResponseResult>,
     semantics: Option<LspSemanticResponseResult>,
 
-    last_result_id: Option<String>,
     cached_semantic_data: Vec<i32>,
-    server_supports_delta: bool,
+    /// Tokens for the rows `start..=end`, newer than the full ones until those come back.
+    range_semantic_data: Option<(usize, usize, Vec<i32>)>,
+
+    next_id: i32,
+    pending: HashMap<i32, PendingRequest>,
+    /// Uris of the documents sent with didOpen.
+    open_documents: HashSet<String>,
+    /// Work in progress by token, oldest first.
+    progress: Vec<(String, Progress)>,
+    /// Messages the server logged or showed, for `:lsplog`.
+    log: Vec<String>,
 
     state: LspState,
 }
 
 impl LspService {
-    pub fn new(name: String, args: Vec<String>) -> Option<Self> {
-        if name.is_empty() { return None }
+    pub fn new(name: &str, command: String, args: Vec<String>) -> Option<Self> {
+        if command.is_empty() { return None }
 
-        let mut prcs = Command::new(name)
+        let mut prcs = Command::new(command)
             .args(args)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
@@ -77,9 +174,11 @@ impl LspService {
         let stdin = process.stdin.take().unwrap();
         let stdout = process.stdout.take().unwrap();
 
-        let (tx_to_writer, rx_from_main): (Sender<LspMessage<serde_json::Value>>, Receiver<LspMessage<serde_json::Value>>) = mpsc::channel();
+        let (tx_to_writer, rx_from_main): (Sender<Value>, Receiver<Value>) = mpsc::channel();
         let (tx_to_main, rx_from_reader): (Sender<LspResponse<serde_json::Value>>, Receiver<LspResponse<serde_json::Value>>) = mpsc::channel();
 
+        let (tx_diagnostics, rx_diagnostics) = mpsc::channel();
+
         let stderr = process.stderr.take().unwrap();
 
         std::thread::spawn(move || {
@@ -140,10 +239,11 @@ impl LspService {
                 }
 
                 if let Ok(text) = String::from_utf8(buf) {
-                    if let Ok(resp) = serde_json::from_str::<LspResponse<serde_json::Value>>(&text) {
+                    // Diagnostics first, every message parses as a response now that `result` may be missing
+                    if let Ok(resp) = serde_json::from_str::<LspDiagnostics>(&text) && resp.method == "textDocument/publishDiagnostics" {
+                        let _ = tx_diagnostics.send(resp.params);
+                    } else if let Ok(resp) = serde_json::from_str::<LspResponse<serde_json::Value>>(&text) {
                         let _ = tx_to_main.send(resp);
-                    } else if let Ok(resp) = serde_json::from_str::<LspDiagnostics>(&text) {
-                        // TODO: Show diagnostics
                     } else {
                         // eprintln!("⚠️ Failed to parse LSP response: {}", text);
                     }
@@ -153,15 +253,22 @@ impl LspService {
 
         Some(
             Self {
+                name: name.to_string(),
                 sender: tx_to_writer,
                 receiver: rx_from_reader,
+                diagnostics_receiver: rx_diagnostics,
                 process,
                 data: None,
                 semantics: None,
 
-                last_result_id: None,
                 cached_semantic_data: vec![],
-                server_supports_delta: false,
+                range_semantic_data: None,
+
+                next_id: 1,
+                pending: HashMap::new(),
+                progress: vec![],
+                log: vec![],
+                open_documents: HashSet::new(),
 
                 state: LspState::Uninitialized
             }
@@ -173,94 +280,65 @@ impl LspService {
     }
 
     pub fn send<T: serde::Serialize>(&self, msg: LspMessage<T>) {
-        let params_json = serde_json::to_value(msg.params).unwrap();
+        if let Ok(msg_value) = serde_json::to_value(msg) {
+            let _ = self.sender.send(msg_value);
+        }
+    }
 
-        let msg_value = LspMessage::<serde_json::Value> {
-            jsonrpc: msg.jsonrpc,
-            id: msg.id,
-            method: msg.method,
-            params: params_json,
-        };
+    /// Answers a request the server sent.
+    fn reply(&self, id: i32, result: Value) {
+        let _ = self.sender.send(serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": result,
+        }));
+    }
+
+    /// Sends a request with a fresh id and remembers what it was for.
+    fn request<T: serde::Serialize>(&mut self, method: &str, params: T, request: PendingRequest) -> i32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(id, request);
+
+        self.send(LspMessage {
+            jsonrpc: "2.0".into(),
+            id: Some(id),
+            method: method.into(),
+            params,
+        });
+
+        id
+    }
+
+    /// Approximate size of the semantic token data kept for delta updates, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        let range = self.range_semantic_data.as_ref().map_or(0, |(_, _, data)| data.capacity());
+        (self.cached_semantic_data.capacity() + range) * std::mem::size_of::<i32>()
+    }
 
-        let _ = self.sender.send(msg_value);    
+    /// Diagnostics published by the server since the last call.
+    pub fn poll_diagnostics(&mut self) -> Vec<LspDiagnosticParams> {
+        self.diagnostics_receiver.try_iter().collect()
     }
 
     pub fn poll(&mut self) -> LspServiceEvent {
         // Try to read any incoming message
         if let Ok(resp_value) = self.receiver.try_recv() {
             log!("{:?}", resp_value);
-            if resp_value.method.is_some() && resp_value.id.is_none() {
-                let method = resp_value.method.unwrap().as_str();
-
-                // You may want to handle standard ones like "$/progress", etc.
-                // But for now, just ignore all of them.
-                return LspServiceEvent::None;
+            // Notifications and requests from the server, e.g. $/progress or window/workDoneProgress/create
+            if let Some(method) = resp_value.method.clone() {
+                return self.handle_server_message(&method, resp_value);
             }
 
-            match self.state {
-                LspState::Initializing => {
-                    if let Some(init_resp) = self.convert_response::<LspResponseResult>(resp_value) {
-                        let caps = &init_resp.result.capabilities.semanticTokensProvider;
-
-                        if let Some(provider) = &caps.full {
-                            // The LSP may return:
-                            // full: true
-                            // or full: { delta: true }
-                            match provider {
-                                SemanticTokensFull::Options { delta } => self.server_supports_delta = delta.unwrap_or(false),
-                                SemanticTokensFull::Boolean(_) => {}
-                            }
-                        }
-
-                        self.data = Some(init_resp.result);
-
-                        let initialized = LspMessage {
-                            jsonrpc: "2.0".into(),
-                            id: None,
-                            method: "initialized".into(),
-                            params: InitializedParams {},
-                        };
-                        self.send(initialized);
-                        self.state = LspState::Initialized;
-                        return LspServiceEvent::Initialized;
-                    }
-                }
-                LspState::RequestingDelta => {
-                    eprintln!("DELTA");
-                    return LspServiceEvent::ReceivedDelta;
-                }
-
-                LspState::RequestingSemantics => {
-                    if let Some(resp) = self.convert_response::<LspSemanticResponseResult>(resp_value) {
-                        match &resp.result {
-                            LspSemanticResponseResult::Full(full) => {
-                                self.cached_semantic_data = full.data.clone();
-                                self.last_result_id = full.resultId.clone();
-                            }
-
-                            LspSemanticResponseResult::Delta(delta) => {
-                                for edit in &delta.edits {
-                                    let start = edit.start as usize;
-                                    let delete = edit.deleteCount as usize;
-
-                                    self.cached_semantic_data
-                                        .splice(start..start+delete, edit.data.clone());
-                                }
-                                self.last_result_id = delta.resultId.clone();
-                            }
-                        }
-                        // now store semantics
-                        self.semantics = Some(resp.result);
-                        self.state = LspState::SemanticsReceived;
-
-                        return LspServiceEvent::ReceivedSemantics {
-                            semantics: self.semantics.clone().unwrap(),
-                        };
-                    }
+            let request = match resp_value.id.and_then(|id| self.pending.remove(&id)) {
+                Some(request) => request,
+                None => {
+                    log!("Response to an unknown request: {:?}", resp_value.id);
+                    return LspServiceEvent::None;
                 }
+            };
 
-                _ => { /* ignore notifications, etc. */ }
-            }
+            return self.dispatch(request, resp_value);
         }
 
         if self.state == LspState::OpeningFile {
@@ -271,6 +349,191 @@ impl LspService {
         LspServiceEvent::None
     }
 
+    fn handle_server_message(&mut self, method: &str, message: LspResponse<Value>) -> LspServiceEvent {
+        match method {
+            "$/progress" => {
+                self.update_progress(&message.params);
+                LspServiceEvent::Progress
+            }
+            // The server waits for this before it reports progress under the token
+            "window/workDoneProgress/create" => {
+                if let Some(id) = message.id {
+                    self.reply(id, Value::Null);
+                }
+                LspServiceEvent::None
+            }
+            "window/logMessage" => {
+                let (level, text) = Self::parse_message(&message.params);
+                self.push_log(level, &text);
+                LspServiceEvent::None
+            }
+            // Shown like showMessage, none of the offered actions is picked
+            "window/showMessage" | "window/showMessageRequest" => {
+                if let Some(id) = message.id {
+                    self.reply(id, Value::Null);
+                }
+
+                let (level, text) = Self::parse_message(&message.params);
+                self.push_log(level, &text);
+                LspServiceEvent::ShowMessage { level, message: text }
+            }
+            _ => LspServiceEvent::None
+        }
+    }
+
+    fn parse_message(params: &Value) -> (u64, String) {
+        let level = params.get("type").and_then(Value::as_u64).unwrap_or(4);
+        let text = params.get("message").and_then(Value::as_str).unwrap_or_default().to_string();
+        (level, text)
+    }
+
+    fn push_log(&mut self, level: u64, text: &str) {
+        let level = match level {
+            MESSAGE_ERROR => "Error",
+            MESSAGE_WARNING => "Warning",
+            3 => "Info",
+            _ => "Log",
+        };
+
+        self.log.push(format!("[{}] {}", level, text));
+        if self.log.len() > MAX_LOG_LINES {
+            self.log.drain(..self.log.len() - MAX_LOG_LINES);
+        }
+    }
+
+    /// What the server logged or showed, oldest first.
+    pub fn log(&self) -> &[String] {
+        &self.log
+    }
+
+    fn update_progress(&mut self, params: &Value) {
+        let token = params.get("token").map(|token| token.to_string()).unwrap_or_default();
+        let value = match params.get("value") {
+            Some(value) => value,
+            None => return
+        };
+
+        let message = value.get("message").and_then(Value::as_str).map(str::to_string);
+        let percentage = value.get("percentage").and_then(Value::as_u64);
+
+        match value.get("kind").and_then(Value::as_str) {
+            Some("begin") => {
+                self.progress.retain(|(other, _)| *other != token);
+                self.progress.push((token, Progress {
+                    title: value.get("title").and_then(Value::as_str).unwrap_or_default().to_string(),
+                    message,
+                    percentage,
+                }));
+            }
+            Some("report") => {
+                if let Some((_, progress)) = self.progress.iter_mut().find(|(other, _)| *other == token) {
+                    progress.message = message.or(progress.message.take());
+                    progress.percentage = percentage.or(progress.percentage);
+                }
+            }
+            Some("end") => self.progress.retain(|(other, _)| *other != token),
+            _ => {}
+        }
+    }
+
+    /// The work the server started last and hasn't finished yet.
+    pub fn progress(&self) -> Option<&Progress> {
+        self.progress.last().map(|(_, progress)| progress)
+    }
+
+    /// Turns the response to a request into an event.
+    fn dispatch(&mut self, request: PendingRequest, resp_value: LspResponse<Value>) -> LspServiceEvent {
+        match request {
+            PendingRequest::Initialize => self.handle_initialize(resp_value),
+            PendingRequest::SemanticTokens => self.handle_semantic_tokens(resp_value),
+            PendingRequest::SemanticTokensRange { start, end } => self.handle_range_tokens(start, end, resp_value),
+            PendingRequest::Location => LspServiceEvent::Locations(Self::parse_locations(&resp_value.result)),
+            PendingRequest::PrepareCallHierarchy(direction) => LspServiceEvent::CallHierarchy {
+                direction,
+                items: Self::parse_call_items(&resp_value.result, None),
+            },
+            PendingRequest::Calls { direction, parent } => {
+                // Incoming calls come as `from`, outgoing ones as `to`
+                let key = match direction {
+                    CallDirection::Incoming => "from",
+                    CallDirection::Outgoing => "to",
+                };
+                LspServiceEvent::Calls { direction, parent, items: Self::parse_call_items(&resp_value.result, Some(key)) }
+            }
+            PendingRequest::Hover => LspServiceEvent::Hover(Self::parse_hover(&resp_value.result)),
+            PendingRequest::Rename => LspServiceEvent::WorkspaceEdit(match &resp_value.error {
+                Some(error) => Err(error.message.clone()),
+                None => Ok(Self::parse_workspace_edit(&resp_value.result)),
+            }),
+            PendingRequest::Formatting | PendingRequest::WillSave => LspServiceEvent::Formatting(match &resp_value.error {
+                Some(error) => Err(error.message.clone()),
+                None => Ok(Self::parse_text_edits(&resp_value.result)),
+            }),
+            PendingRequest::SignatureHelp => LspServiceEvent::SignatureHelp(Self::parse_signature_help(&resp_value.result)),
+            PendingRequest::InlayHints { path } => LspServiceEvent::InlayHints { path, hints: Self::parse_inlay_hints(&resp_value.result) },
+            PendingRequest::FoldingRanges { path } => LspServiceEvent::FoldingRanges { path, ranges: Self::parse_folding_ranges(&resp_value.result) },
+            PendingRequest::Shutdown => LspServiceEvent::None,
+        }
+    }
+
+    fn handle_initialize(&mut self, resp_value: LspResponse<Value>) -> LspServiceEvent {
+        if let Some(error) = &resp_value.error {
+            self.push_log(MESSAGE_ERROR, &format!("initialize failed: {}", error.message));
+            return LspServiceEvent::None;
+        }
+
+        // Without the capabilities the server still works, just with the defaults of a minimal one
+        let result = match serde_json::from_value::<LspResponseResult>(resp_value.result) {
+            Ok(result) => result,
+            Err(e) => {
+                self.push_log(MESSAGE_WARNING, &format!("Could not read the server capabilities: {}", e));
+                LspResponseResult::default()
+            }
+        };
+
+        self.data = Some(result);
+
+        let initialized = LspMessage {
+            jsonrpc: "2.0".into(),
+            id: None,
+            method: "initialized".into(),
+            params: InitializedParams {},
+        };
+        self.send(initialized);
+        self.state = LspState::Initialized;
+        LspServiceEvent::Initialized
+    }
+
+    fn handle_semantic_tokens(&mut self, resp_value: LspResponse<Value>) -> LspServiceEvent {
+        let resp = match self.convert_response::<LspSemanticResponseResult>(resp_value) {
+            Some(resp) => resp,
+            None => return LspServiceEvent::None
+        };
+
+        apply_semantic_tokens(&mut self.cached_semantic_data, &resp.result);
+        // The full tokens cover the visible rows as well now
+        self.range_semantic_data = None;
+
+        // now store semantics
+        self.semantics = Some(resp.result);
+
+        LspServiceEvent::ReceivedSemantics {
+            semantics: self.semantics.clone().unwrap(),
+        }
+    }
+
+    fn handle_range_tokens(&mut self, start: usize, end: usize, resp_value: LspResponse<Value>) -> LspServiceEvent {
+        let resp = match self.convert_response::<SemanticTokensFullData>(resp_value) {
+            Some(resp) => resp,
+            None => return LspServiceEvent::None
+        };
+
+        self.range_semantic_data = Some((start, end, resp.result.data.clone()));
+
+        LspServiceEvent::ReceivedSemantics {
+            semantics: LspSemanticResponseResult::Full(resp.result),
+        }
+    }
 
     fn convert_response<T>(&self, value: LspResponse<Value>) -> Option<LspResponse<T>>
     where
@@ -283,6 +546,8 @@ impl LspService {
                 method: None,
                 id: value.id,
                 result,
+                params: Value::Null,
+                error: value.error,
             }),
             Err(e) => {
                 eprintln!("⚠️ Failed to parse LSP response payload: {}", e);
@@ -291,40 +556,76 @@ impl LspService {
         }
     }
 
-    pub fn initialize(&mut self, root_uri: &str) {
+    /// Starts the handshake, with the workspace at `root` or without one.
+    pub fn initialize(&mut self, root: Option<&str>) {
         if self.state != LspState::Uninitialized { return; }
 
-        let init = LspMessage {
-            jsonrpc: "2.0".into(),
-            id: Some(1),
-            method: "initialize".into(),
-            params: InitializeParams {
-                capabilities: Some(InitializeClientCapabilities {
-                    text_document: Some(TextDocumentClientCapabilities {
-                        synchronization: Some(TextDocumentSyncClientCapabilities {
-                            did_open: true,
-                            did_change: true,
-                            did_close: true,
-                        })
-                    })
+        let params = InitializeParams {
+            capabilities: Some(InitializeClientCapabilities {
+                text_document: Some(TextDocumentClientCapabilities {
+                    synchronization: Some(TextDocumentSyncClientCapabilities {
+                        did_open: true,
+                        did_change: true,
+                        did_close: true,
+                        did_save: true,
+                        will_save_wait_until: true,
+                    }),
+                    inlay_hint: Some(InlayHintClientCapabilities {
+                        dynamic_registration: false,
+                    }),
+                    folding_range: Some(FoldingRangeClientCapabilities {
+                        line_folding_only: true,
+                    }),
                 }),
-                root_uri: Some(root_uri.into()),
-            },
+                window: Some(WindowClientCapabilities {
+                    work_done_progress: true,
+                }),
+            }),
+            root_uri: root.map(|root| format!("file://{}", root)),
         };
 
-        self.send(init);
+        self.request("initialize", params, PendingRequest::Initialize);
         self.state = LspState::Initializing;
     }
 
-    pub fn open_file(&mut self, uri: &str, contents: &str) {
-        if self.state != LspState::Initialized { return; }
+    /// Asks the server to exit, see `has_exited`. Servers that never got initialized are
+    /// left to be killed.
+    pub fn shutdown(&mut self) {
+        if matches!(self.state, LspState::Uninitialized | LspState::Initializing) { return; }
+
+        self.request("shutdown", Value::Null, PendingRequest::Shutdown);
+        self.send(LspMessage {
+            jsonrpc: "2.0".into(),
+            id: None,
+            method: "exit".into(),
+            params: Value::Null,
+        });
+    }
 
-        let abs = std::fs::canonicalize(uri)
+    pub fn pid(&self) -> u32 {
+        self.process.id()
+    }
+
+    pub fn has_exited(&mut self) -> bool {
+        !matches!(self.process.try_wait(), Ok(None))
+    }
+
+    /// How the process ended, `None` while it is running.
+    pub fn exit_status(&mut self) -> Option<std::process::ExitStatus> {
+        self.process.try_wait().ok().flatten()
+    }
+
+    /// Sends the buffer with didOpen. Does nothing before the server is initialized
+    /// or if the buffer was already opened.
+    pub fn open_file(&mut self, buffer: &Buffer, language_id: &str) {
+        if matches!(self.state, LspState::Uninitialized | LspState::Initializing) { return; }
+
+        let abs = std::fs::canonicalize(&buffer.path)
             .ok()
             .and_then(|p| Some(format!("file://{}", p.to_string_lossy())))
-            .unwrap_or(uri.to_string());
+            .unwrap_or(buffer.path.clone());
 
-        //log!("{:?}", abs);
+        if !self.open_documents.insert(abs.clone()) { return; }
 
         let open = LspMessage {
             jsonrpc: "2.0".into(),
@@ -333,9 +634,9 @@ impl LspService {
             params: DidOpenParams {
                 textDocument: TextDocumentItem {
                     uri: abs,
-                    languageId: "rust".into(),
-                    version: 1,
-                    text: contents.to_string(),
+                    languageId: language_id.into(),
+                    version: buffer.version as u64,
+                    text: buffer.text(),
                 },
             },
         };
@@ -344,48 +645,546 @@ impl LspService {
         self.state = LspState::OpeningFile;
     }
 
+    /// The token legend, `None` if the server doesn't do semantic tokens (e.g. gopls by default).
+    fn semantic_legend(&self) -> Option<&SemanticTokensLegend> {
+        let provider = self.data.as_ref()?.capabilities.semanticTokensProvider.as_ref()?;
+        Some(&provider.legend)
+    }
+
+    /// Whether the tokens of the whole file can be requested, `full: true` or `full: { ... }`.
+    fn supports_full_tokens(&self) -> bool {
+        self.data.as_ref()
+            .and_then(|data| data.capabilities.semanticTokensProvider.as_ref())
+            .is_some_and(|provider| provider.supports_full())
+    }
+
     pub fn request_semantic_tokens(&mut self, buffer: &Buffer) {
-        if self.state != LspState::FileOpened && self.state != LspState::RequestingDelta { return; }
+        if self.state != LspState::FileOpened { return; }
+        if !self.supports_full_tokens() { return; }
 
         let abs = std::fs::canonicalize(&buffer.path)
             .ok()
             .and_then(|p| Some(format!("file://{}", p.to_string_lossy())))
             .unwrap_or(buffer.path.clone());
 
-        let msg = if false {//self.server_supports_delta && self.last_result_id.is_some() {
-            // delta request
-            LspMessage {
-                jsonrpc: "2.0".into(),
-                id: Some(4),
-                method: "textDocument/semanticTokens/full/delta".into(),
-                params: serde_json::json!({
-                    "textDocument": { "uri": abs },
-                    "previousResultId": self.last_result_id.clone().unwrap()
-                }),
+        let params = serde_json::json!({
+            "textDocument": { "uri": abs }
+        });
+
+        self.request("textDocument/semanticTokens/full", params, PendingRequest::SemanticTokens);
+    }
+
+    /// Asks where the symbol at `row`/`col` is defined, declared or has its type defined.
+    pub fn request_location(&mut self, kind: LocationKind, path: &str, row: usize, col: usize) {
+        let method = match kind {
+            LocationKind::Definition => "textDocument/definition",
+            LocationKind::Declaration => "textDocument/declaration",
+            LocationKind::TypeDefinition => "textDocument/typeDefinition",
+        };
+
+        let abs = std::fs::canonicalize(path)
+            .ok()
+            .map(|p| format!("file://{}", p.to_string_lossy()))
+            .unwrap_or(path.to_string());
+
+        let params = serde_json::json!({
+            "textDocument": { "uri": abs },
+            "position": { "line": row, "character": col }
+        });
+
+        self.request(method, params, PendingRequest::Location);
+    }
+
+    pub fn supports_call_hierarchy(&self) -> bool {
+        let provider = self.data.as_ref().and_then(|data| data.capabilities.callHierarchyProvider.as_ref());
+
+        match provider {
+            Some(Value::Bool(enabled)) => *enabled,
+            Some(Value::Object(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Asks for the function at `row`/`col`, its calls are requested with `request_calls` once it arrives.
+    pub fn request_call_hierarchy(&mut self, direction: CallDirection, path: &str, row: usize, col: usize) {
+        let abs = std::fs::canonicalize(path)
+            .ok()
+            .map(|p| format!("file://{}", p.to_string_lossy()))
+            .unwrap_or(path.to_string());
+
+        let params = serde_json::json!({
+            "textDocument": { "uri": abs },
+            "position": { "line": row, "character": col }
+        });
+
+        self.request("textDocument/prepareCallHierarchy", params, PendingRequest::PrepareCallHierarchy(direction));
+    }
+
+    /// Asks for the calls to or from an item of the hierarchy.
+    pub fn request_calls(&mut self, direction: CallDirection, item: &Value) {
+        let method = match direction {
+            CallDirection::Incoming => "callHierarchy/incomingCalls",
+            CallDirection::Outgoing => "callHierarchy/outgoingCalls",
+        };
+
+        let params = serde_json::json!({ "item": item });
+        self.request(method, params, PendingRequest::Calls { direction, parent: item.clone() });
+    }
+
+    /// Reads `CallHierarchyItem`s, or with `key` the incoming/outgoing calls that wrap them.
+    fn parse_call_items(result: &Value, key: Option<&str>) -> Vec<CallItem> {
+        let entries = match result {
+            Value::Array(entries) => entries,
+            _ => return vec![],
+        };
+
+        entries.iter()
+            .filter_map(|entry| {
+                let item = match key {
+                    Some(key) => entry.get(key)?,
+                    None => entry,
+                };
+                let uri = item.get("uri")?.as_str()?;
+
+                // Incoming calls jump to where the call is, in the calling function
+                let call_site = match key {
+                    Some("from") => entry.get("fromRanges").and_then(|ranges| ranges.get(0)),
+                    _ => None,
+                };
+                let range = call_site.or_else(|| item.get("selectionRange")).or_else(|| item.get("range"))?;
+                let start = range.get("start")?;
+
+                Some(CallItem {
+                    name: item.get("name")?.as_str()?.to_string(),
+                    detail: item.get("detail").and_then(Value::as_str).map(String::from),
+                    location: Location {
+                        path: path_from_uri(uri),
+                        row: start.get("line")?.as_u64()? as usize,
+                        col: start.get("character")?.as_u64()? as usize,
+                    },
+                    item: item.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Asks for the documentation of the symbol at `row`/`col`.
+    pub fn request_hover(&mut self, path: &str, row: usize, col: usize) {
+        let abs = std::fs::canonicalize(path)
+            .ok()
+            .map(|p| format!("file://{}", p.to_string_lossy()))
+            .unwrap_or(path.to_string());
+
+        let params = serde_json::json!({
+            "textDocument": { "uri": abs },
+            "position": { "line": row, "character": col }
+        });
+
+        self.request("textDocument/hover", params, PendingRequest::Hover);
+    }
+
+    pub fn request_rename(&mut self, path: &str, row: usize, col: usize, new_name: &str) {
+        let abs = std::fs::canonicalize(path)
+            .ok()
+            .map(|p| format!("file://{}", p.to_string_lossy()))
+            .unwrap_or(path.to_string());
+
+        let params = serde_json::json!({
+            "textDocument": { "uri": abs },
+            "position": { "line": row, "character": col },
+            "newName": new_name
+        });
+
+        self.request("textDocument/rename", params, PendingRequest::Rename);
+    }
+
+    /// Asks for edits to make before the file is saved, see `supports_will_save_wait_until`.
+    pub fn request_will_save(&mut self, path: &str) {
+        let abs = std::fs::canonicalize(path)
+            .ok()
+            .map(|p| format!("file://{}", p.to_string_lossy()))
+            .unwrap_or(path.to_string());
+
+        let params = serde_json::json!({
+            "textDocument": { "uri": abs },
+            // Manual, saves triggered by auto-save skip this request
+            "reason": 1
+        });
+
+        self.request("textDocument/willSaveWaitUntil", params, PendingRequest::WillSave);
+    }
+
+    /// Formats the whole file, or only the rows and columns between `range` when given.
+    pub fn request_formatting(&mut self, path: &str, tab_size: usize, range: Option<((usize, usize), (usize, usize))>) {
+        let abs = std::fs::canonicalize(path)
+            .ok()
+            .map(|p| format!("file://{}", p.to_string_lossy()))
+            .unwrap_or(path.to_string());
+
+        let options = serde_json::json!({ "tabSize": tab_size, "insertSpaces": true });
+
+        let (method, params) = match range {
+            Some(((start_row, start_col), (end_row, end_col))) => ("textDocument/rangeFormatting", serde_json::json!({
+                "textDocument": { "uri": abs },
+                "range": {
+                    "start": { "line": start_row, "character": start_col },
+                    "end": { "line": end_row, "character": end_col }
+                },
+                "options": options
+            })),
+            None => ("textDocument/formatting", serde_json::json!({
+                "textDocument": { "uri": abs },
+                "options": options
+            })),
+        };
+
+        self.request(method, params, PendingRequest::Formatting);
+    }
+
+    fn parse_text_edits(edits: &Value) -> Vec<TextEdit> {
+        edits.as_array().into_iter().flatten()
+            .filter_map(|edit| {
+                let range = edit.get("range")?;
+                let position = |key: &str, field: &str| range.get(key)?.get(field)?.as_u64().map(|n| n as usize);
+
+                Some(TextEdit {
+                    start_row: position("start", "line")?,
+                    start_col: position("start", "character")?,
+                    end_row: position("end", "line")?,
+                    end_col: position("end", "character")?,
+                    new_text: edit.get("newText")?.as_str()?.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    /// A WorkspaceEdit carries either `changes` (uri -> edits) or `documentChanges`.
+    /// File create/rename/delete operations in `documentChanges` are skipped.
+    fn parse_workspace_edit(result: &Value) -> Vec<FileEdit> {
+        if let Some(changes) = result.get("documentChanges").and_then(|c| c.as_array()) {
+            return changes.iter()
+                .filter_map(|change| {
+                    let uri = change.get("textDocument")?.get("uri")?.as_str()?;
+                    Some(FileEdit { path: path_from_uri(uri), edits: Self::parse_text_edits(change.get("edits")?) })
+                })
+                .collect();
+        }
+
+        result.get("changes")
+            .and_then(|changes| changes.as_object())
+            .map(|changes| {
+                changes.iter()
+                    .map(|(uri, edits)| FileEdit { path: path_from_uri(uri), edits: Self::parse_text_edits(edits) })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn supports_inlay_hints(&self) -> bool {
+        let provider = self.data.as_ref().and_then(|data| data.capabilities.inlayHintProvider.as_ref());
+
+        match provider {
+            Some(InlayHintProvider::Boolean(enabled)) => *enabled,
+            Some(InlayHintProvider::Options { .. }) => true,
+            None => false,
+        }
+    }
+
+    pub fn supports_range_formatting(&self) -> bool {
+        let provider = self.data.as_ref().and_then(|data| data.capabilities.documentRangeFormattingProvider.as_ref());
+
+        match provider {
+            Some(serde_json::Value::Bool(enabled)) => *enabled,
+            Some(serde_json::Value::Object(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Asks for the hints between `start_row` and `end_row`, usually the visible lines.
+    /// Returns false if the file isn't open on the server yet.
+    pub fn request_inlay_hints(&mut self, path: &str, start_row: usize, end_row: usize) -> bool {
+        if matches!(self.state, LspState::Uninitialized | LspState::Initializing | LspState::Initialized | LspState::OpeningFile) {
+            return false;
+        }
+        if !self.supports_inlay_hints() { return false }
+
+        let abs = std::fs::canonicalize(path)
+            .ok()
+            .map(|p| format!("file://{}", p.to_string_lossy()))
+            .unwrap_or(path.to_string());
+
+        let params = serde_json::json!({
+            "textDocument": { "uri": abs },
+            "range": {
+                "start": { "line": start_row, "character": 0 },
+                "end": { "line": end_row, "character": 0 }
             }
-        } else {
-            // full request
-            LspMessage {
-                jsonrpc: "2.0".into(),
-                id: Some(4),
-                method: "textDocument/semanticTokens/full".into(),
-                params: serde_json::json!({
-                    "textDocument": { "uri": abs }
-                }),
+        });
+        self.request("textDocument/inlayHint", params, PendingRequest::InlayHints { path: path.to_string() });
+        true
+    }
+
+    pub fn supports_folding_ranges(&self) -> bool {
+        let provider = self.data.as_ref().and_then(|data| data.capabilities.foldingRangeProvider.as_ref());
+
+        match provider {
+            Some(Value::Bool(enabled)) => *enabled,
+            Some(Value::Object(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Asks what can be folded in the file. Returns false if it isn't open on the server yet.
+    pub fn request_folding_ranges(&mut self, path: &str) -> bool {
+        if matches!(self.state, LspState::Uninitialized | LspState::Initializing | LspState::Initialized | LspState::OpeningFile) {
+            return false;
+        }
+        if !self.supports_folding_ranges() { return false }
+
+        let abs = std::fs::canonicalize(path)
+            .ok()
+            .map(|p| format!("file://{}", p.to_string_lossy()))
+            .unwrap_or(path.to_string());
+
+        let params = serde_json::json!({ "textDocument": { "uri": abs } });
+        self.request("textDocument/foldingRange", params, PendingRequest::FoldingRanges { path: path.to_string() });
+        true
+    }
+
+    fn parse_folding_ranges(result: &Value) -> Vec<Fold> {
+        let ranges = match result.as_array() {
+            Some(ranges) => ranges,
+            None => return vec![]
+        };
+
+        ranges.iter()
+            .filter_map(|range| {
+                let start = range.get("startLine")?.as_u64()? as usize;
+                let end = range.get("endLine")?.as_u64()? as usize;
+                (end > start).then_some(Fold { start, end })
+            })
+            .collect()
+    }
+
+    /// Labels are a string or a list of parts that are joined.
+    fn parse_inlay_hints(result: &Value) -> Vec<InlayHint> {
+        let hints = match result.as_array() {
+            Some(hints) => hints,
+            None => return vec![]
+        };
+
+        hints.iter().filter_map(|hint| {
+            let position = hint.get("position")?;
+
+            let mut label = match hint.get("label")? {
+                Value::String(label) => label.clone(),
+                Value::Array(parts) => parts.iter()
+                    .filter_map(|part| part.get("value").and_then(|value| value.as_str()))
+                    .collect(),
+                _ => return None,
+            };
+
+            if hint.get("paddingLeft").and_then(|padding| padding.as_bool()).unwrap_or(false) {
+                label.insert(0, ' ');
+            }
+            if hint.get("paddingRight").and_then(|padding| padding.as_bool()).unwrap_or(false) {
+                label.push(' ');
             }
+
+            Some(InlayHint {
+                row: position.get("line")?.as_u64()? as usize,
+                col: position.get("character")?.as_u64()? as usize,
+                label,
+            })
+        }).collect()
+    }
+
+    pub fn request_signature_help(&mut self, path: &str, row: usize, col: usize) {
+        let abs = std::fs::canonicalize(path)
+            .ok()
+            .map(|p| format!("file://{}", p.to_string_lossy()))
+            .unwrap_or(path.to_string());
+
+        let params = serde_json::json!({
+            "textDocument": { "uri": abs },
+            "position": { "line": row, "character": col }
+        });
+
+        self.request("textDocument/signatureHelp", params, PendingRequest::SignatureHelp);
+    }
+
+    /// Picks the active signature. Parameter labels are either a substring of the signature or an offset pair.
+    fn parse_signature_help(result: &Value) -> Option<Signature> {
+        let signatures = result.get("signatures")?.as_array()?;
+        let index = result.get("activeSignature").and_then(|i| i.as_u64()).unwrap_or(0) as usize;
+        let signature = signatures.get(index).or_else(|| signatures.first())?;
+
+        let label = signature.get("label")?.as_str()?.to_string();
+
+        // Newer servers put the active parameter on the signature itself
+        let parameter = signature.get("activeParameter")
+            .or_else(|| result.get("activeParameter"))
+            .and_then(|i| i.as_u64())
+            .unwrap_or(0) as usize;
+
+        let active = signature.get("parameters")
+            .and_then(|parameters| parameters.as_array())
+            .and_then(|parameters| parameters.get(parameter))
+            .and_then(|parameter| match parameter.get("label")? {
+                Value::String(text) => {
+                    let start = label.find(text.as_str())?;
+                    let start = label[..start].chars().count();
+                    Some((start, start + text.chars().count()))
+                }
+                Value::Array(offsets) => {
+                    let start = offsets.first()?.as_u64()? as usize;
+                    let end = offsets.get(1)?.as_u64()? as usize;
+                    Some((start, end))
+                }
+                _ => None,
+            });
+
+        Some(Signature { label, active })
+    }
+
+    /// Hover contents are MarkupContent, a MarkedString or a list of MarkedStrings.
+    /// Everything is turned into Markdown, code from MarkedStrings gets fenced.
+    fn parse_hover(result: &Value) -> Option<String> {
+        fn marked(value: &Value) -> Option<String> {
+            match value {
+                Value::String(text) => Some(text.clone()),
+                Value::Object(_) => {
+                    let text = value.get("value")?.as_str()?;
+
+                    match value.get("language").and_then(|l| l.as_str()) {
+                        Some(language) => Some(format!("```{}\n{}\n```", language, text)),
+                        None => Some(text.to_string()),
+                    }
+                }
+                _ => None,
+            }
+        }
+
+        let contents = result.get("contents")?;
+        let text = match contents {
+            Value::Array(items) => items.iter().filter_map(marked).collect::<Vec<_>>().join("\n---\n"),
+            other => marked(other)?,
         };
 
-        self.send(msg);
-        self.state = LspState::RequestingSemantics;
+        if text.trim().is_empty() { None } else { Some(text) }
+    }
+
+    /// Servers answer with a Location, a list of Locations or a list of LocationLinks.
+    fn parse_locations(result: &Value) -> Vec<Location> {
+        let items = match result {
+            Value::Array(items) => items.clone(),
+            Value::Null => vec![],
+            other => vec![other.clone()],
+        };
+
+        items.iter()
+            .filter_map(|item| {
+                let uri = item.get("uri").or_else(|| item.get("targetUri"))?.as_str()?;
+                let range = item.get("range").or_else(|| item.get("targetSelectionRange"))?;
+                let start = range.get("start")?;
+
+                Some(Location {
+                    path: path_from_uri(uri),
+                    row: start.get("line")?.as_u64()? as usize,
+                    col: start.get("character")?.as_u64()? as usize,
+                })
+            })
+            .collect()
+    }
+
+    /// How the server wants to be told about edits, see `SYNC_*`.
+    fn sync_options(&self) -> Option<&TextDocumentSync> {
+        match self.data.as_ref().and_then(|data| data.capabilities.textDocumentSync.as_ref()) {
+            Some(TextDocumentSyncProvider::Options(options)) => Some(options),
+            _ => None,
+        }
     }
 
-    pub fn did_change(&mut self, uri: &str, version: u32, new_text: &str) {
-        let abs = std::fs::canonicalize(uri)
+    pub fn supports_will_save_wait_until(&self) -> bool {
+        self.sync_options().is_some_and(|options| options.willSaveWaitUntil)
+    }
+
+    /// Tells the server the buffer was written, with its text if the server asked for it.
+    pub fn did_save(&mut self, buffer: &Buffer) {
+        let include_text = match self.sync_options().and_then(|options| options.save.as_ref()) {
+            Some(Value::Bool(true)) => false,
+            Some(Value::Object(options)) => options.get("includeText").and_then(Value::as_bool).unwrap_or(false),
+            _ => return,
+        };
+
+        let abs = std::fs::canonicalize(&buffer.path)
             .ok()
-            .and_then(|p| Some(format!("file://{}", p.to_string_lossy())))
-            .unwrap_or(uri.to_string());
+            .map(|p| format!("file://{}", p.to_string_lossy()))
+            .unwrap_or(buffer.path.clone());
+
+        if !self.open_documents.contains(&abs) { return; }
+
+        let mut params = serde_json::json!({ "textDocument": { "uri": abs } });
+        if include_text {
+            params["text"] = Value::String(buffer.text());
+        }
 
-        //log!("{:?}", abs);
+        self.send(LspMessage {
+            jsonrpc: "2.0".into(),
+            id: None,
+            method: "textDocument/didSave".into(),
+            params,
+        });
+    }
+
+    /// Tells the server the buffer was closed, it goes back to reading the file from disk.
+    pub fn did_close(&mut self, path: &str) {
+        let abs = std::fs::canonicalize(path)
+            .ok()
+            .map(|p| format!("file://{}", p.to_string_lossy()))
+            .unwrap_or(path.to_string());
+
+        if !self.open_documents.remove(&abs) { return; }
+
+        self.send(LspMessage {
+            jsonrpc: "2.0".into(),
+            id: None,
+            method: "textDocument/didClose".into(),
+            params: serde_json::json!({ "textDocument": { "uri": abs } }),
+        });
+    }
+
+    fn sync_kind(&self) -> i32 {
+        match self.data.as_ref().and_then(|data| data.capabilities.textDocumentSync.as_ref()) {
+            Some(TextDocumentSyncProvider::Kind(kind)) => *kind,
+            Some(TextDocumentSyncProvider::Options(options)) => options.change,
+            // Not initialized yet, the full text works with any server
+            None => 1,
+        }
+    }
+
+    /// Sends the edits since the last sync as ranges if the server does incremental sync,
+    /// otherwise the whole text. `None` means the edits weren't all tracked.
+    pub fn did_change(&mut self, buffer: &Buffer, changes: Option<Vec<TextEdit>>) {
+        let content_changes: Vec<Value> = match (self.sync_kind(), changes) {
+            (SYNC_NONE, _) => return,
+            (_, Some(changes)) if changes.is_empty() => return,
+            (SYNC_INCREMENTAL, Some(changes)) => changes.iter()
+                .map(|change| serde_json::json!({
+                    "range": {
+                        "start": { "line": change.start_row, "character": change.start_col },
+                        "end": { "line": change.end_row, "character": change.end_col }
+                    },
+                    "text": change.new_text
+                }))
+                .collect(),
+            _ => vec![serde_json::json!({ "text": buffer.text() })],
+        };
+
+        let abs = std::fs::canonicalize(&buffer.path)
+            .ok()
+            .and_then(|p| Some(format!("file://{}", p.to_string_lossy())))
+            .unwrap_or(buffer.path.clone());
 
         let msg = LspMessage {
             jsonrpc: "2.0".into(),
@@ -394,92 +1193,167 @@ impl LspService {
             params: serde_json::json!({
                 "textDocument": {
                     "uri": abs,
-                    "version": version,
+                    "version": buffer.version,
                 },
-                "contentChanges": [
-                    { "text": new_text }
-                ]
+                "contentChanges": content_changes
             }),
         };
 
         self.send(msg);
-        self.state = LspState::RequestingDelta;
+    }
+
+    /// Whether the server answers semantic token requests for a range of lines.
+    pub fn supports_range_tokens(&self) -> bool {
+        self.data.as_ref()
+            .and_then(|data| data.capabilities.semanticTokensProvider.as_ref())
+            .and_then(|provider| provider.range.as_ref())
+            .is_some_and(|range| *range != Value::Bool(false))
+    }
+
+    /// Whether a request for the tokens of the whole file is still unanswered.
+    pub fn tokens_pending(&self) -> bool {
+        self.pending.values().any(|request| *request == PendingRequest::SemanticTokens)
+    }
+
+    /// Asks for the tokens of the rows `start..=end` of a large buffer, so they can be drawn
+    /// before the full tokens arrive. Does nothing for small buffers or servers without range support.
+    pub fn request_range_tokens(&mut self, buffer: &Buffer, start: usize, end: usize) {
+        if self.state != LspState::FileOpened { return; }
+        if buffer.lines.len() < RANGE_TOKENS_MIN_LINES || !self.supports_range_tokens() { return; }
+
+        let end = end.min(buffer.lines.len().saturating_sub(1));
+        let abs = std::fs::canonicalize(&buffer.path)
+            .ok()
+            .and_then(|p| Some(format!("file://{}", p.to_string_lossy())))
+            .unwrap_or(buffer.path.clone());
+
+        self.request("textDocument/semanticTokens/range", serde_json::json!({
+            "textDocument": { "uri": abs },
+            "range": {
+                "start": { "line": start, "character": 0 },
+                "end": { "line": end + 1, "character": 0 }
+            }
+        }), PendingRequest::SemanticTokensRange { start, end });
     }
 
     pub fn set_tokens(&self, buffer: &Buffer, theme: Theme) -> Vec<Vec<Token>> {
         let colors = theme.to_map();
+        let mut tokens: Vec<Vec<Token>> = vec![Vec::new(); buffer.lines.len()];
 
-        let mut current_data: [i32; 5];
-        let mut index = 0;
-        let mut previousDeltaStart = 0;
-        let mut previousDeltaLine = 0;
+        let legend = match self.semantic_legend() {
+            Some(legend) => legend,
+            None => return tokens
+        };
 
-        let mut tokens: Vec<Vec<Token>> = vec![Vec::new(); buffer.lines.len()];
-        let mut currTokens: Vec<Token> = Vec::new();
-        let data = &self.cached_semantic_data;
-
-        while index + 4 < data.len() {
-            let current_data: [i32; 5] = data[index..index + 5].try_into().unwrap();
-                
-            let deltaLine = current_data[0];
-            let deltaStart = current_data[1];
-            let length = current_data[2];
-            let tokenIndex = current_data[3];
-            let tokenModifier = current_data[4];
-
-            if deltaLine != 0 {
-                previousDeltaStart = 0;
-                if !currTokens.is_empty() {
-                    tokens[previousDeltaLine as usize] = currTokens;
-                }
-                currTokens = Vec::new();
+        decode_tokens(legend, &self.cached_semantic_data, buffer, &colors, &mut tokens);
+
+        if let Some((start, end, data)) = &self.range_semantic_data {
+            for row in tokens.iter_mut().take(end + 1).skip(*start) {
+                row.clear();
             }
+            decode_tokens(legend, data, buffer, &colors, &mut tokens);
+        }
 
-            let lineIndex = previousDeltaLine + deltaLine;
-            let charStartIndex = previousDeltaStart + deltaStart;
-            let line = buffer.lines[lineIndex as usize].clone();
-            let start_byte = utf16_to_byte_index(line.as_str(), charStartIndex as usize);
-            let end_byte = utf16_to_byte_index(line.as_str(), (charStartIndex + length) as usize);
-            let token_slice = &line[start_byte..end_byte];  
-
-            if let Some(data) = &self.data {
-                let token_type = data.capabilities.semanticTokensProvider.legend.tokenTypes[tokenIndex as usize].clone(); 
-                let mut final_key = token_type.clone();
-
-                let mut mods = vec![];
-                for bit in 0..data.capabilities.semanticTokensProvider.legend.tokenModifiers.len() {
-                    if tokenModifier & (1 << bit) != 0 {
-                        mods.push(data.capabilities.semanticTokensProvider.legend.tokenModifiers[bit].clone());
-                    }
-                }
+        tokens
+    }
+}
 
-                if !mods.is_empty() {
-                    final_key = format!("{}.{:?}", token_type, mods.join("."));
-                }
+/// Replaces `cached` with full tokens or applies the edits of a delta to it.
+pub fn apply_semantic_tokens(cached: &mut Vec<i32>, result: &LspSemanticResponseResult) {
+    match result {
+        LspSemanticResponseResult::Full(full) => *cached = full.data.clone(),
+        LspSemanticResponseResult::Delta(delta) => {
+            for edit in &delta.edits {
+                // Edits past the end are from a server that lost track, clamp them
+                let start = (edit.start as usize).min(cached.len());
+                let end = (start + edit.deleteCount as usize).min(cached.len());
+                cached.splice(start..end, edit.data.clone());
+            }
+        }
+    }
+}
 
-                let style = colors
-                    .get(&final_key)
-                    .or_else(|| colors.get(&token_type));
-                currTokens.push(
-                    Token {
-                        row: lineIndex as usize,
-                        text: token_slice.to_string(),
-                        style: style.copied(),
-                        offset: charStartIndex as usize
-                    }
-                );
+/// Adds the tokens in `data`, encoded relative to each other as the server sends them, to their rows.
+pub fn decode_tokens(legend: &SemanticTokensLegend, data: &[i32], buffer: &Buffer, colors: &HashMap<String, Color>, tokens: &mut [Vec<Token>]) {
+    let mut row = 0;
+    let mut start = 0;
+
+    for chunk in data.chunks_exact(5) {
+        let (delta_line, delta_start, length, token_index, token_modifier) = (chunk[0], chunk[1], chunk[2], chunk[3], chunk[4]);
+
+        if delta_line != 0 {
+            start = 0;
+        }
+        row += delta_line as usize;
+        start += delta_start as usize;
+
+        // Tokens for lines that were deleted since the request
+        let line = match buffer.lines.get(row) {
+            Some(line) => line,
+            None => break
+        };
+        let token_type = match legend.tokenTypes.get(token_index as usize) {
+            Some(token_type) => token_type,
+            None => continue
+        };
+
+        let start_byte = utf16_to_byte_index(line, start);
+        let end_byte = utf16_to_byte_index(line, start + length as usize);
+
+        let mut mods = vec![];
+        for bit in 0..legend.tokenModifiers.len() {
+            if token_modifier & (1 << bit) != 0 {
+                mods.push(legend.tokenModifiers[bit].clone());
             }
+        }
 
-            previousDeltaStart = charStartIndex;
-            previousDeltaLine = lineIndex;
-            index += 5;
+        let mut final_key = token_type.clone();
+        if !mods.is_empty() {
+            final_key = format!("{}.{:?}", token_type, mods.join("."));
         }
 
-        return tokens
+        let style = colors
+            .get(&final_key)
+            .or_else(|| colors.get(token_type));
+        tokens[row].push(
+            Token {
+                row,
+                text: line[start_byte..end_byte].to_string(),
+                style: style.copied(),
+                offset: start
+            }
+        );
     }
 }
 
-fn utf16_to_byte_index(s: &str, utf16_index: usize) -> usize {
+/// The path of a `file://` URI, with escapes like `%20` for a space decoded.
+pub fn path_from_uri(uri: &str) -> String {
+    let encoded = uri.strip_prefix("file://").unwrap_or(uri).as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+
+    while i < encoded.len() {
+        let escaped = encoded.get(i + 1..i + 3)
+            .filter(|_| encoded[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+pub fn utf16_to_byte_index(s: &str, utf16_index: usize) -> usize {
     let mut count = 0;
     for (byte_idx, ch) in s.char_indices() {
         if count == utf16_index {
@@ -493,5 +1367,7 @@ fn utf16_to_byte_index(s: &str, utf16_index: usize) -> usize {
 impl Drop for LspService {
     fn drop(&mut self) {
         let _ = self.process.kill();
+        // Reap the server so quick open/close cycles don't leave zombies behind
+        let _ = self.process.wait();
     }
 }
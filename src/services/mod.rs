@@ -1 +1,6 @@
 pub mod lsp_service;
+pub mod lsp_manager;
+pub mod collab_service;
+pub mod remote_service;
+pub mod session_service;
+pub mod git_service;
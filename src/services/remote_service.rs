@@ -0,0 +1,106 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+use crate::log;
+
+/// A file another `oxidy --remote` invocation asked the running editor to open.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteRequest {
+    pub path: String,
+    // 1-based, like on the command line
+    pub line: Option<usize>,
+    pub col: Option<usize>,
+}
+
+/// Socket the running instance listens on, one per user.
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("oxidy.sock"),
+        None => {
+            let user = std::env::var("USER").unwrap_or_else(|_| "default".to_string());
+            std::env::temp_dir().join(format!("oxidy-{}.sock", user))
+        }
+    }
+}
+
+/// Hands the files to a running instance. Fails if there is none.
+pub fn send(requests: &[RemoteRequest]) -> io::Result<()> {
+    let mut stream = UnixStream::connect(socket_path())?;
+
+    for request in requests {
+        // The running editor may have been started somewhere else
+        let path = std::fs::canonicalize(&request.path)
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_else(|_| std::env::current_dir()
+                .map(|dir| dir.join(&request.path).to_string_lossy().to_string())
+                .unwrap_or_else(|_| request.path.clone()));
+
+        let request = RemoteRequest { path, ..request.clone() };
+        let mut line = serde_json::to_string(&request).map_err(io::Error::other)?;
+        line.push('\n');
+        stream.write_all(line.as_bytes())?;
+    }
+
+    stream.flush()
+}
+
+/// Listens for `oxidy --remote` invocations so files open in this editor instead of a new one.
+pub struct RemoteService {
+    path: PathBuf,
+    receiver: Receiver<RemoteRequest>,
+}
+
+impl RemoteService {
+    pub fn start() -> io::Result<Self> {
+        let path = socket_path();
+
+        // A socket nobody answers on was left behind by an editor that crashed
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, "another instance is already running"));
+            }
+            std::fs::remove_file(&path)?;
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(error) => {
+                        log!("Remote connection failed: {}", error);
+                        continue;
+                    }
+                };
+
+                for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                    match serde_json::from_str::<RemoteRequest>(&line) {
+                        Ok(request) => {
+                            if sender.send(request).is_err() { return }
+                        }
+                        Err(error) => log!("Invalid remote request {:?}: {}", line, error),
+                    }
+                }
+            }
+        });
+
+        Ok(Self { path, receiver })
+    }
+
+    pub fn poll(&self) -> Vec<RemoteRequest> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+impl Drop for RemoteService {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
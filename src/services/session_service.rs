@@ -0,0 +1,101 @@
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use crate::input::InputEvent;
+use crate::session::{self, ClientMessage};
+use crate::types::Size;
+use crate::log;
+
+pub enum SessionEvent {
+    // A frontend said hello, frames go to `stream` and its input arrives on `input`
+    Attached { id: u64, stream: UnixStream, size: Size, input: Receiver<InputEvent> },
+    // The frontend with that id went away, the session keeps running
+    Detached(u64),
+}
+
+/// Lets `oxidy --attach` take over a terminal editor whose terminal hung up while `hangup`
+/// was "detach". It is only started then, the GUI never detaches. Only one frontend is
+/// attached at a time, a new one replaces the last.
+pub struct SessionService {
+    path: PathBuf,
+    receiver: Receiver<SessionEvent>,
+}
+
+impl SessionService {
+    pub fn start() -> io::Result<Self> {
+        session::private_socket_dir()?;
+        let path = session::socket_path(std::process::id());
+        // Pids get reused, a socket by that name belongs to a session that is gone
+        let _ = std::fs::remove_file(&path);
+
+        // Whoever connects gets the buffers and the keyboard, so only the user may
+        let listener = UnixListener::bind(&path)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            for (id, stream) in (0..).zip(listener.incoming()) {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(error) => {
+                        log!("Session connection failed: {}", error);
+                        continue;
+                    }
+                };
+
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    if let Err(error) = serve(id, stream, sender) {
+                        log!("Session client failed: {}", error);
+                    }
+                });
+            }
+        });
+
+        Ok(Self { path, receiver })
+    }
+
+    pub fn poll(&self) -> Vec<SessionEvent> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+/// Forwards the input of one frontend until it disconnects. Connections that don't start
+/// with a hello, like `oxidy --attach` checking which sessions are alive, are dropped.
+fn serve(id: u64, stream: UnixStream, events: Sender<SessionEvent>) -> io::Result<()> {
+    let mut lines = BufReader::new(stream.try_clone()?).lines();
+
+    let size = match lines.next().transpose()?.map(|line| serde_json::from_str::<ClientMessage>(&line)) {
+        Some(Ok(ClientMessage::Hello { cols, rows })) => Size { cols, rows },
+        _ => return Ok(()),
+    };
+
+    let (input_sender, input) = mpsc::channel();
+    if events.send(SessionEvent::Attached { id, stream, size, input }).is_err() {
+        return Ok(());
+    }
+
+    for line in lines.map_while(Result::ok) {
+        match serde_json::from_str::<ClientMessage>(&line) {
+            Ok(ClientMessage::Input(event)) => {
+                // Replaced by a newer frontend
+                if input_sender.send(event).is_err() { return Ok(()) }
+            }
+            Ok(ClientMessage::Hello { .. }) => {}
+            Err(error) => log!("Invalid session message {:?}: {}", line, error),
+        }
+    }
+
+    let _ = events.send(SessionEvent::Detached(id));
+    Ok(())
+}
+
+impl Drop for SessionService {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,287 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use crossterm::style::{Attribute, Color, ContentStyle};
+use crossterm::terminal;
+use serde::{Deserialize, Serialize};
+
+use crate::input::{CrosstermInput, InputEvent, InputHandler};
+use crate::plugins::config::Config;
+use crate::renderer::Renderer;
+use crate::renderer::crossterm::{CrossTermRenderer, ScreenCursor};
+use crate::types::{Grid, RenderCell, Size};
+use crate::signals;
+use crate::log;
+
+/// What an attached frontend sends to the session, one JSON object per line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ClientMessage {
+    // First message, the size of the client's terminal
+    Hello { cols: u16, rows: u16 },
+    Input(InputEvent),
+}
+
+/// What the session sends to the attached frontend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ServerMessage {
+    // The rows that changed since the last frame, with their index, and the frame's height
+    Frame { rows: usize, changed: Vec<(usize, Vec<WireCell>)>, cursor: Option<WireCursor> },
+}
+
+/// A `RenderCell` as it goes over the socket. Colors are RGB or one of the 256 ANSI colors.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WireCell {
+    pub ch: char,
+    pub fg: Option<WireColor>,
+    pub bg: Option<WireColor>,
+    pub underline: Option<WireColor>,
+    // Bits of WIRE_ATTRIBUTES
+    pub attributes: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum WireColor {
+    Rgb(u8, u8, u8),
+    Ansi(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WireCursor {
+    pub col: u16,
+    pub row: u16,
+    pub bar: bool,
+    pub blink: bool,
+    pub color: Option<WireColor>,
+}
+
+/// The attributes that survive the trip, everything the renderer uses.
+const WIRE_ATTRIBUTES: [Attribute; 8] = [
+    Attribute::Bold,
+    Attribute::Dim,
+    Attribute::Italic,
+    Attribute::Underlined,
+    Attribute::Undercurled,
+    Attribute::Reverse,
+    Attribute::CrossedOut,
+    Attribute::Hidden,
+];
+
+/// Directory the session sockets live in, one per editor process. Without a runtime dir
+/// it is a directory of the user's own in the shared temp dir.
+pub fn socket_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let user = std::env::var("USER").unwrap_or_else(|_| "user".to_string());
+            std::env::temp_dir().join(format!("oxidy-{}", user))
+        }
+    }
+}
+
+/// Creates `socket_dir()` readable only by the user. One that others can get into, e.g.
+/// made up front by someone else in /tmp, is refused.
+pub fn private_socket_dir() -> io::Result<PathBuf> {
+    let dir = socket_dir();
+
+    match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(error) => return Err(error),
+    }
+
+    let mode = std::fs::metadata(&dir)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} is open to other users", dir.display())));
+    }
+
+    Ok(dir)
+}
+
+pub fn socket_path(pid: u32) -> PathBuf {
+    socket_dir().join(format!("oxidy-session-{}.sock", pid))
+}
+
+/// Sockets of the sessions that still answer, the most recently started first.
+pub fn sessions() -> Vec<PathBuf> {
+    let mut sockets: Vec<(std::time::SystemTime, PathBuf)> = std::fs::read_dir(socket_dir())
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("oxidy-session-"))
+        .filter(|entry| UnixStream::connect(entry.path()).is_ok())
+        .map(|entry| (entry.metadata().and_then(|meta| meta.modified()).unwrap_or(std::time::UNIX_EPOCH), entry.path()))
+        .collect();
+
+    sockets.sort_by(|a, b| b.0.cmp(&a.0));
+    sockets.into_iter().map(|(_, path)| path).collect()
+}
+
+pub fn encode_color(color: Color) -> Option<WireColor> {
+    let ansi = match color {
+        Color::Reset => return None,
+        Color::Rgb { r, g, b } => return Some(WireColor::Rgb(r, g, b)),
+        Color::AnsiValue(value) => value,
+        Color::Black => 0,
+        Color::DarkRed => 1,
+        Color::DarkGreen => 2,
+        Color::DarkYellow => 3,
+        Color::DarkBlue => 4,
+        Color::DarkMagenta => 5,
+        Color::DarkCyan => 6,
+        Color::Grey => 7,
+        Color::DarkGrey => 8,
+        Color::Red => 9,
+        Color::Green => 10,
+        Color::Yellow => 11,
+        Color::Blue => 12,
+        Color::Magenta => 13,
+        Color::Cyan => 14,
+        Color::White => 15,
+    };
+
+    Some(WireColor::Ansi(ansi))
+}
+
+pub fn decode_color(color: WireColor) -> Color {
+    match color {
+        WireColor::Rgb(r, g, b) => Color::Rgb { r, g, b },
+        WireColor::Ansi(value) => Color::AnsiValue(value),
+    }
+}
+
+pub fn encode_cell(cell: &RenderCell) -> WireCell {
+    let attributes = WIRE_ATTRIBUTES.iter()
+        .enumerate()
+        .filter(|(_, attribute)| cell.style.attributes.has(**attribute))
+        .fold(0, |bits, (i, _)| bits | (1 << i));
+
+    WireCell {
+        ch: cell.ch,
+        fg: cell.style.foreground_color.and_then(encode_color),
+        bg: cell.style.background_color.and_then(encode_color),
+        underline: cell.style.underline_color.and_then(encode_color),
+        attributes,
+    }
+}
+
+pub fn decode_cell(cell: &WireCell) -> RenderCell {
+    let mut style = ContentStyle::new();
+    style.foreground_color = cell.fg.map(decode_color);
+    style.background_color = cell.bg.map(decode_color);
+    style.underline_color = cell.underline.map(decode_color);
+
+    for (i, attribute) in WIRE_ATTRIBUTES.iter().enumerate() {
+        if cell.attributes & (1 << i) != 0 {
+            style.attributes.set(*attribute);
+        }
+    }
+
+    RenderCell { ch: cell.ch, style, transparent: false }
+}
+
+pub fn encode_cursor(cursor: &ScreenCursor) -> WireCursor {
+    WireCursor {
+        col: cursor.col,
+        row: cursor.row,
+        bar: cursor.bar,
+        blink: cursor.blink,
+        color: cursor.color.and_then(encode_color),
+    }
+}
+
+pub fn decode_cursor(cursor: &WireCursor) -> ScreenCursor {
+    ScreenCursor {
+        col: cursor.col,
+        row: cursor.row,
+        bar: cursor.bar,
+        blink: cursor.blink,
+        color: cursor.color.map(decode_color),
+    }
+}
+
+pub fn send<T: Serialize>(stream: &mut UnixStream, message: &T) -> io::Result<()> {
+    let mut line = serde_json::to_string(message).map_err(io::Error::other)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())
+}
+
+/// Attaches this terminal to a running session, the newest one unless `pid` names another.
+/// Returns once the session ends or the connection drops, the session keeps running after
+/// a drop and can be attached to again.
+pub fn attach(pid: Option<u32>) -> io::Result<()> {
+    let path = match pid {
+        Some(pid) => socket_path(pid),
+        None => sessions().into_iter().next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no session to attach to"))?,
+    };
+
+    let mut stream = UnixStream::connect(&path)?;
+    let reader = BufReader::new(stream.try_clone()?);
+
+    let (cols, rows) = terminal::size()?;
+    send(&mut stream, &ClientMessage::Hello { cols, rows })?;
+
+    let (sender, frames) = mpsc::channel();
+    thread::spawn(move || {
+        for line in reader.lines().map_while(Result::ok) {
+            match serde_json::from_str::<ServerMessage>(&line) {
+                Ok(message) => if sender.send(message).is_err() { return },
+                Err(error) => log!("Invalid session message: {}", error),
+            }
+        }
+    });
+
+    let config = Config::default();
+    let mut renderer = CrossTermRenderer::new(Size { cols, rows })?;
+    let mut input = CrosstermInput::new();
+
+    // The frame as the session last drew it, messages only carry the rows that changed
+    let mut cells: Vec<Vec<RenderCell>> = vec![];
+    let mut cursor: Option<ScreenCursor> = None;
+
+    loop {
+        // Closing this terminal only detaches, the session goes on without it
+        if signals::take_hangup() || signals::terminate_requested() {
+            return Ok(());
+        }
+
+        if let Some(event) = input.poll()? {
+            if let InputEvent::Resize(cols, rows) = event {
+                renderer.resize(Size { cols, rows });
+            }
+            send(&mut stream, &ClientMessage::Input(event))?;
+        }
+
+        let mut changed = false;
+        loop {
+            match frames.recv_timeout(Duration::from_millis(1)) {
+                Ok(ServerMessage::Frame { rows, changed: lines, cursor: frame_cursor }) => {
+                    cells.resize(rows, vec![]);
+                    for (row, line) in lines {
+                        if let Some(cells_row) = cells.get_mut(row) {
+                            *cells_row = line.iter().map(decode_cell).collect();
+                        }
+                    }
+                    cursor = frame_cursor.as_ref().map(decode_cursor);
+                    changed = true;
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+
+        if changed {
+            let mut frame = Grid::new(0, 0, RenderCell::blank());
+            frame.cells = cells.clone();
+
+            renderer.begin_frame();
+            renderer.present(frame, cursor, &config);
+            renderer.end_frame();
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::io::{self, Read, Write};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::types::BufferId;
+
+/// Where the output of a shell command goes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShellTarget {
+    // A notification, or a scratch buffer when it is more than a line
+    Show,
+    // Below `row`, for `:r !cmd`
+    Insert { buffer: BufferId, row: usize },
+    // In place of the rows `start..=end` the command read, unless the buffer changed meanwhile
+    Filter { buffer: BufferId, start: usize, end: usize, version: u32 },
+    // In place of the whole buffer, which is saved after, for the formatter of a file type
+    Format { buffer: BufferId, version: u32 },
+}
+
+#[derive(PartialEq)]
+pub struct ShellOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub success: bool,
+}
+
+/// A command running with `sh`, its output is read off the main thread. The child is kept
+/// so it shows up in `:ps` and is killed when it is dropped before it exited.
+pub struct ShellProcess {
+    child: Child,
+    output: Receiver<(String, String)>,
+    status: Option<ExitStatus>,
+}
+
+impl ShellProcess {
+    /// Starts `command`, feeding it `input` on stdin.
+    pub fn start(command: &str, input: Option<String>) -> io::Result<Self> {
+        let mut child = Command::new("sh")
+            .args(["-c", command])
+            .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        if let (Some(mut stdin), Some(input)) = (child.stdin.take(), input) {
+            // Written from its own thread so a command that answers before reading everything can't deadlock us
+            thread::spawn(move || {
+                let _ = stdin.write_all(input.as_bytes());
+            });
+        }
+
+        let mut stdout = child.stdout.take().unwrap();
+        let mut stderr = child.stderr.take().unwrap();
+        let (sender, output) = mpsc::channel();
+
+        thread::spawn(move || {
+            // Both pipes are read at once, a command that fills one can't block on it
+            let errors = thread::spawn(move || {
+                let mut bytes = vec![];
+                let _ = stderr.read_to_end(&mut bytes);
+                bytes
+            });
+
+            let mut bytes = vec![];
+            let _ = stdout.read_to_end(&mut bytes);
+            let errors = errors.join().unwrap_or_default();
+
+            let _ = sender.send((String::from_utf8_lossy(&bytes).into_owned(), String::from_utf8_lossy(&errors).into_owned()));
+        });
+
+        Ok(Self { child, output, status: None })
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// The output once the command exited.
+    pub fn poll(&mut self) -> Option<io::Result<ShellOutput>> {
+        if self.status.is_none() {
+            match self.child.try_wait() {
+                Ok(status) => self.status = status,
+                Err(error) => return Some(Err(error)),
+            }
+        }
+
+        let status = self.status?;
+        let (stdout, stderr) = self.output.try_recv().ok()?;
+        Some(Ok(ShellOutput { stdout, stderr, success: status.success() }))
+    }
+
+    /// Stops the command, `poll` then hands out what it wrote until then as a failure.
+    pub fn kill(&mut self) {
+        if self.status.is_some() { return }
+
+        let _ = self.child.kill();
+        self.status = self.child.wait().ok();
+    }
+}
+
+impl Drop for ShellProcess {
+    fn drop(&mut self) {
+        // A command still running when the editor quits would otherwise keep going
+        self.kill();
+    }
+}
+
+/// A `:!` command running off the main thread.
+pub struct ShellJob {
+    pub command: String,
+    pub target: ShellTarget,
+    process: ShellProcess,
+}
+
+impl ShellJob {
+    pub fn spawn(command: &str, input: Option<String>, target: ShellTarget) -> io::Result<Self> {
+        let process = ShellProcess::start(command, input)?;
+        Ok(Self { command: command.to_string(), target, process })
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.process.pid()
+    }
+
+    /// The output once the command exited.
+    pub fn poll(&mut self) -> Option<io::Result<ShellOutput>> {
+        self.process.poll()
+    }
+
+    pub fn kill(&mut self) {
+        self.process.kill();
+    }
+}
@@ -0,0 +1,55 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set from the signal handler, the main loop shuts down once it sees it.
+static TERMINATE: AtomicBool = AtomicBool::new(false);
+/// The terminal went away, like an ssh connection that dropped.
+static HANGUP: AtomicBool = AtomicBool::new(false);
+/// The terminal changed size, some terminals don't report it as an input event.
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+const SIGHUP: i32 = 1;
+#[cfg(unix)]
+const SIGTERM: i32 = 15;
+#[cfg(unix)]
+const SIGWINCH: i32 = 28;
+
+#[cfg(unix)]
+unsafe extern "C" {
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(signum: i32) {
+    // Only atomic stores, anything else isn't safe in a signal handler
+    match signum {
+        SIGHUP => HANGUP.store(true, Ordering::SeqCst),
+        SIGWINCH => RESIZED.store(true, Ordering::SeqCst),
+        _ => TERMINATE.store(true, Ordering::SeqCst),
+    }
+}
+
+/// Makes SIGTERM and SIGHUP go through the normal shutdown instead of killing the editor
+/// with the terminal still in raw mode and the servers still running, and picks up SIGWINCH.
+pub fn install() {
+    #[cfg(unix)]
+    unsafe {
+        signal(SIGTERM, on_signal);
+        signal(SIGHUP, on_signal);
+        signal(SIGWINCH, on_signal);
+    }
+}
+
+pub fn terminate_requested() -> bool {
+    TERMINATE.load(Ordering::SeqCst)
+}
+
+/// True once per SIGHUP.
+pub fn take_hangup() -> bool {
+    HANGUP.swap(false, Ordering::SeqCst)
+}
+
+/// True once per batch of SIGWINCH, the size is read again after it.
+pub fn take_resize() -> bool {
+    RESIZED.swap(false, Ordering::SeqCst)
+}
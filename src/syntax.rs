@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crossterm::style::Color;
+use tree_sitter::{InputEdit, Language, Parser, Point, Query, QueryCursor, Tree};
+
+use crate::types::Token;
+
+/// A tree-sitter parse of one buffer, re-parsed incrementally as it is edited.
+pub struct SyntaxTree {
+    pub filetype: String,
+    parser: Parser,
+    query: Query,
+    tree: Option<Tree>,
+    /// The text the tree was parsed from and the buffer version it had.
+    source: String,
+    version: u32,
+    /// Byte offset of the start of each line in `source`.
+    line_starts: Vec<usize>,
+    colors: HashMap<String, Color>,
+    /// Tokens of the rows drawn since the last parse.
+    rows: HashMap<usize, Vec<Token>>,
+}
+
+impl fmt::Debug for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SyntaxTree")
+            .field("filetype", &self.filetype)
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+fn grammar(filetype: &str) -> Option<(Language, &'static str)> {
+    match filetype {
+        "rs" => Some((tree_sitter_rust::language(), tree_sitter_rust::HIGHLIGHT_QUERY)),
+        "py" => Some((tree_sitter_python::language(), tree_sitter_python::HIGHLIGHT_QUERY)),
+        _ => None
+    }
+}
+
+/// The theme color a capture like "function.method" is drawn with, `None` for punctuation
+/// and anything else that keeps the foreground.
+fn theme_key(capture: &str) -> Option<&'static str> {
+    let key = match capture {
+        "function.method" => "method",
+        "function.macro" => "macro",
+        "variable.parameter" => "parameter",
+        "variable.builtin" => "keyword",
+        "constant" => "enumMember",
+        "constant.builtin" | "number" | "float" => "number",
+        "constructor" => "type",
+        "property" | "field" => "property",
+        "attribute" | "label" => "macro",
+        "escape" | "char" => "string",
+        "namespace" | "module" => "namespace",
+        "operator" => "operator",
+        _ => match capture.split('.').next().unwrap_or(capture) {
+            "keyword" => "keyword",
+            "comment" => "comment",
+            "function" => "function",
+            "type" => "type",
+            "string" => "string",
+            "variable" => "variable",
+            _ => return None
+        }
+    };
+
+    Some(key)
+}
+
+impl SyntaxTree {
+    /// `None` for file types without a grammar.
+    pub fn for_filetype(filetype: &str) -> Option<Self> {
+        let (language, highlights) = grammar(filetype)?;
+
+        let mut parser = Parser::new();
+        parser.set_language(&language).ok()?;
+        let query = Query::new(&language, highlights).ok()?;
+
+        Some(Self {
+            filetype: filetype.to_string(),
+            parser,
+            query,
+            tree: None,
+            source: String::new(),
+            version: 0,
+            line_starts: vec![0],
+            colors: HashMap::new(),
+            rows: HashMap::new(),
+        })
+    }
+
+    /// Re-parses after the buffer changed. Only the part between the common start and end
+    /// of the old and new text is marked as edited, so tree-sitter reuses the rest.
+    pub fn update(&mut self, lines: &[String], version: u32, colors: &HashMap<String, Color>) {
+        if *colors != self.colors {
+            self.colors = colors.clone();
+            self.rows.clear();
+        }
+
+        if self.tree.is_some() && version == self.version { return }
+
+        let source = lines.join("\n");
+
+        if let Some(tree) = self.tree.as_mut() {
+            let old = self.source.as_bytes();
+            let new = source.as_bytes();
+
+            let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+            let max_suffix = old.len().min(new.len()) - prefix;
+            let suffix = old.iter().rev().zip(new.iter().rev()).take(max_suffix).take_while(|(a, b)| a == b).count();
+
+            tree.edit(&InputEdit {
+                start_byte: prefix,
+                old_end_byte: old.len() - suffix,
+                new_end_byte: new.len() - suffix,
+                start_position: point_at(old, prefix),
+                old_end_position: point_at(old, old.len() - suffix),
+                new_end_position: point_at(new, new.len() - suffix),
+            });
+        }
+
+        self.tree = self.parser.parse(&source, self.tree.as_ref());
+        self.line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        self.source = source;
+        self.version = version;
+        self.rows.clear();
+    }
+
+    fn line(&self, row: usize) -> Option<&str> {
+        let start = *self.line_starts.get(row)?;
+        let end = self.line_starts.get(row + 1).map(|next| next - 1).unwrap_or(self.source.len());
+        self.source.get(start..end)
+    }
+
+    /// Tokens covering all of `line`, the text of `row`. `None` if the tree is behind the buffer.
+    pub fn tokens(&mut self, line: &str, row: usize) -> Option<Vec<Token>> {
+        if self.line(row)? != line { return None }
+
+        if let Some(tokens) = self.rows.get(&row) {
+            return Some(tokens.clone());
+        }
+
+        let tree = self.tree.as_ref()?;
+        let line_start = self.line_starts[row];
+
+        // The first capture of a byte wins, like the order of patterns in the query
+        let mut styles: Vec<Option<Color>> = vec![None; line.len()];
+        let mut taken = vec![false; line.len()];
+
+        let mut cursor = QueryCursor::new();
+        cursor.set_point_range(Point::new(row, 0)..Point::new(row + 1, 0));
+
+        for (capture_match, index) in cursor.captures(&self.query, tree.root_node(), self.source.as_bytes()) {
+            let capture = capture_match.captures[index];
+            let name = &self.query.capture_names()[capture.index as usize];
+
+            let start = capture.node.start_byte().saturating_sub(line_start).min(line.len());
+            let end = capture.node.end_byte().saturating_sub(line_start).min(line.len());
+            let color = theme_key(name).and_then(|key| self.colors.get(key)).copied();
+
+            for byte in start..end {
+                if !taken[byte] {
+                    taken[byte] = true;
+                    styles[byte] = color;
+                }
+            }
+        }
+
+        let tokens = runs(line, row, &styles);
+        self.rows.insert(row, tokens.clone());
+
+        Some(tokens)
+    }
+}
+
+/// Row and byte column of `offset` in `text`.
+fn point_at(text: &[u8], offset: usize) -> Point {
+    let before = &text[..offset];
+    let row = before.iter().filter(|byte| **byte == b'\n').count();
+    let column = before.iter().rev().take_while(|byte| **byte != b'\n').count();
+
+    Point::new(row, column)
+}
+
+/// Splits `line` into tokens where the style of its bytes changes, keeping characters whole.
+pub fn runs(line: &str, row: usize, styles: &[Option<Color>]) -> Vec<Token> {
+    let mut tokens: Vec<Token> = vec![];
+
+    for (offset, ch) in line.char_indices() {
+        let style = styles.get(offset).copied().flatten();
+
+        match tokens.last_mut() {
+            Some(token) if token.style == style => token.text.push(ch),
+            _ => tokens.push(Token { row, text: ch.to_string(), offset, style }),
+        }
+    }
+
+    tokens
+}
@@ -0,0 +1,148 @@
+use std::io;
+
+use crate::buffer::Buffer;
+use crate::encoding;
+use crate::services::lsp_service::utf16_to_byte_index;
+
+/// A replacement of a range in a file. Rows are zero based, columns are UTF-16 offsets like the LSP sends them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub start_row: usize,
+    pub start_col: usize,
+    pub end_row: usize,
+    pub end_col: usize,
+    pub new_text: String,
+}
+
+/// All edits a WorkspaceEdit makes to one file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileEdit {
+    pub path: String,
+    pub edits: Vec<TextEdit>,
+}
+
+/// Applies `edits` to `lines`. Fails without touching anything if a range is outside the text or ranges overlap.
+pub fn apply(lines: &[String], edits: &[TextEdit]) -> Result<Vec<String>, String> {
+    let mut edits = edits.to_vec();
+    edits.sort_by_key(|edit| (edit.start_row, edit.start_col));
+
+    for pair in edits.windows(2) {
+        if (pair[1].start_row, pair[1].start_col) < (pair[0].end_row, pair[0].end_col) {
+            return Err(format!("overlapping edits on line {}", pair[1].start_row + 1));
+        }
+    }
+
+    let mut lines = lines.to_vec();
+
+    // Back to front so earlier positions stay valid
+    for edit in edits.iter().rev() {
+        if edit.end_row >= lines.len() || edit.start_row > edit.end_row {
+            return Err(format!("edit outside the file on line {}", edit.end_row + 1));
+        }
+
+        let start = utf16_to_byte_index(&lines[edit.start_row], edit.start_col);
+        let end = utf16_to_byte_index(&lines[edit.end_row], edit.end_col);
+
+        let mut text = lines[edit.start_row][..start].to_string();
+        text.push_str(&edit.new_text);
+        text.push_str(&lines[edit.end_row][end..]);
+
+        let replacement: Vec<String> = text.split('\n')
+            .map(|line| line.trim_end_matches('\r').to_string())
+            .collect();
+
+        lines.splice(edit.start_row..=edit.end_row, replacement);
+    }
+
+    Ok(lines)
+}
+
+/// Where `row`/`col` ends up after `edits`, so the cursor stays on the same text.
+/// A position inside a replaced range moves to its start.
+pub fn map_position(edits: &[TextEdit], row: usize, col: usize) -> (usize, usize) {
+    let mut edits = edits.to_vec();
+    edits.sort_by_key(|edit| (edit.start_row, edit.start_col));
+
+    let (mut row, mut col) = (row, col);
+
+    // Back to front like `apply`, so each edit's range is still valid when it is looked at
+    for edit in edits.iter().rev() {
+        if (edit.start_row, edit.start_col) >= (row, col) { continue }
+
+        if (edit.end_row, edit.end_col) > (row, col) {
+            row = edit.start_row;
+            col = edit.start_col;
+            continue;
+        }
+
+        let inserted: Vec<&str> = edit.new_text.split('\n').collect();
+        let added_rows = inserted.len() - 1;
+
+        if edit.end_row == row {
+            let start = if added_rows == 0 { edit.start_col } else { 0 };
+            let last_len = inserted.last().map(|line| line.chars().count()).unwrap_or(0);
+            col = start + last_len + (col - edit.end_col);
+        }
+
+        row = row + added_rows - (edit.end_row - edit.start_row);
+    }
+
+    (row, col)
+}
+
+/// Applies edits to a file that isn't open, keeping its encoding and line endings.
+/// Returns the bytes to write back.
+pub fn apply_to_file(path: &str, edits: &[TextEdit]) -> io::Result<Vec<u8>> {
+    let bytes = std::fs::read(path)?;
+    let (content, encoding) = encoding::decode(&bytes);
+
+    let mut buffer = Buffer::from_content(&content, path.to_string());
+    buffer.encoding = encoding;
+    buffer.lines = apply(&buffer.lines, edits).map_err(io::Error::other)?;
+
+    buffer.file_bytes()
+}
+
+/// UTF-16 offset of the `col`th character of `line`.
+pub fn utf16_col(line: &str, col: usize) -> usize {
+    line.chars().take(col).map(char::len_utf16).sum()
+}
+
+impl TextEdit {
+    /// Inserts `text` at a UTF-16 position.
+    pub fn insert(row: usize, col: usize, text: &str) -> Self {
+        Self { start_row: row, start_col: col, end_row: row, end_col: col, new_text: text.to_string() }
+    }
+
+    /// Removes the text between two UTF-16 positions.
+    pub fn delete(start_row: usize, start_col: usize, end_row: usize, end_col: usize) -> Self {
+        Self { start_row, start_col, end_row, end_col, new_text: String::new() }
+    }
+}
+
+/// One edit replacing the lines that differ between `old` and `new`.
+pub fn diff(old: &[String], new: &[String]) -> TextEdit {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_end = old.len() - suffix;
+    let inserted = &new[prefix..new.len() - suffix];
+    let len16 = |line: &String| line.encode_utf16().count();
+
+    if old_end < old.len() {
+        // Whole lines up to the start of the first unchanged line after them
+        let text: String = inserted.iter().map(|line| format!("{}\n", line)).collect();
+        TextEdit { start_row: prefix, start_col: 0, end_row: old_end, end_col: 0, new_text: text }
+    } else if prefix > 0 {
+        // The change runs to the end, start after the last unchanged line instead
+        let text: String = inserted.iter().map(|line| format!("\n{}", line)).collect();
+        let last = old.len() - 1;
+        TextEdit { start_row: prefix - 1, start_col: len16(&old[prefix - 1]), end_row: last, end_col: len16(&old[last]), new_text: text }
+    } else {
+        let last = old.len().saturating_sub(1);
+        TextEdit { start_row: 0, start_col: 0, end_row: last, end_col: old.get(last).map(len16).unwrap_or(0), new_text: new.join("\n") }
+    }
+}
@@ -0,0 +1,52 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// The pane that was active before this one, usually the REPL next to the editor.
+pub const DEFAULT_TARGET: &str = "{last}";
+
+// Named so it doesn't end up on top of the user's own paste buffers
+const SEND_BUFFER: &str = "oxidy-send";
+
+pub fn is_inside() -> bool {
+    std::env::var_os("TMUX").is_some()
+}
+
+fn tmux(args: &[&str]) -> io::Result<()> {
+    let output = Command::new("tmux")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()?;
+
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(message));
+    }
+
+    Ok(())
+}
+
+/// Types `text` into the `target` pane and presses enter.
+/// Goes through a paste buffer so multi-line code arrives as one bracketed paste instead of key by key.
+pub fn send(target: &str, text: &str) -> io::Result<()> {
+    if !is_inside() {
+        return Err(io::Error::other("not running inside tmux"));
+    }
+
+    let mut child = Command::new("tmux")
+        .args(["load-buffer", "-b", SEND_BUFFER, "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.trim_end_matches('\n').as_bytes())?;
+    }
+
+    if !child.wait()?.success() {
+        return Err(io::Error::other("tmux load-buffer failed"));
+    }
+
+    tmux(&["paste-buffer", "-d", "-p", "-b", SEND_BUFFER, "-t", target])?;
+    tmux(&["send-keys", "-t", target, "Enter"])
+}
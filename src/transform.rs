@@ -0,0 +1,198 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+
+use crate::types::BufferId;
+
+enum TransformEvent {
+    Output(String),
+    Finished,
+}
+
+/// Pipes a buffer through a shell command, collecting its output as it streams in.
+pub struct TransformJob {
+    pub command: String,
+    pub buffer: BufferId,
+    // Rows that are replaced by the output, inclusive
+    pub start: usize,
+    pub end: usize,
+    // Version of the buffer the input was taken from
+    pub version: u32,
+    pub output: Vec<String>,
+    pub finished: bool,
+    pub error: Option<String>,
+    child: Child,
+    stderr: Option<JoinHandle<String>>,
+    receiver: Receiver<TransformEvent>,
+}
+
+impl TransformJob {
+    pub fn spawn(command: &str, buffer: BufferId, (start, end): (usize, usize), version: u32, input: String) -> io::Result<Self> {
+        let mut child = Command::new("sh")
+            .args(["-c", command])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let mut stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let mut stderr = child.stderr.take().unwrap();
+        let (sender, receiver) = mpsc::channel();
+
+        // Written from its own thread so a tool that streams before reading everything can't deadlock us
+        thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        });
+
+        let stderr = thread::spawn(move || {
+            let mut output = String::new();
+            let _ = stderr.read_to_string(&mut output);
+            output
+        });
+
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(TransformEvent::Output(line)).is_err() { return }
+                    }
+                    Err(_) => break,
+                }
+            }
+
+            let _ = sender.send(TransformEvent::Finished);
+        });
+
+        Ok(Self {
+            command: command.to_string(),
+            buffer,
+            start,
+            end,
+            version,
+            output: Vec::new(),
+            finished: false,
+            error: None,
+            child,
+            stderr: Some(stderr),
+            receiver,
+        })
+    }
+
+    /// Collects output that arrived since the last call. Returns true if anything changed.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+
+        while let Ok(event) = self.receiver.try_recv() {
+            changed = true;
+
+            match event {
+                TransformEvent::Output(line) => self.output.push(line),
+                TransformEvent::Finished => {
+                    self.finished = true;
+                    self.error = self.wait_for_error();
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Waits for the process to exit and returns why it failed, if it did.
+    fn wait_for_error(&mut self) -> Option<String> {
+        let status = match self.child.wait() {
+            Ok(status) => status,
+            Err(error) => return Some(error.to_string()),
+        };
+
+        if status.success() { return None }
+
+        let stderr = self.stderr.take()
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default();
+        let message = stderr.trim();
+
+        Some(if message.is_empty() { status.to_string() } else { message.to_string() })
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    pub fn cancel(&mut self) {
+        if !self.finished {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+            self.finished = true;
+        }
+    }
+}
+
+impl Drop for TransformJob {
+    fn drop(&mut self) {
+        // A job still streaming when the editor quits would otherwise keep running
+        self.cancel();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+// Above this many cells the LCS table gets too big, changed regions are then diffed as a whole
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Line based diff between `old` and `new`.
+pub fn diff_lines(old: &[String], new: &[String]) -> Vec<DiffLine> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut diff: Vec<DiffLine> = old[..prefix].iter().cloned().map(DiffLine::Same).collect();
+
+    if old_mid.len() * new_mid.len() > MAX_DIFF_CELLS {
+        diff.extend(old_mid.iter().cloned().map(DiffLine::Removed));
+        diff.extend(new_mid.iter().cloned().map(DiffLine::Added));
+    } else {
+        // lcs[i][j] = length of the common subsequence of old_mid[i..] and new_mid[j..]
+        let (n, m) = (old_mid.len(), new_mid.len());
+        let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i][j] = if old_mid[i] == new_mid[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_mid[i] == new_mid[j] {
+                diff.push(DiffLine::Same(old_mid[i].clone()));
+                i += 1;
+                j += 1;
+            } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+                diff.push(DiffLine::Added(new_mid[j].clone()));
+                j += 1;
+            } else {
+                diff.push(DiffLine::Removed(old_mid[i].clone()));
+                i += 1;
+            }
+        }
+    }
+
+    diff.extend(old[old.len() - suffix..].iter().cloned().map(DiffLine::Same));
+    diff
+}
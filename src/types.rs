@@ -1,9 +1,13 @@
 use crossterm::style::{Color, ContentStyle, Stylize};
+use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::{Write, Result};
 use std::path::Path;
 
 use crate::plugins::config::Config;
+use crate::export::ExportFormat;
+use crate::shell::ShellTarget;
+use crate::cmdline::CommandInvocation;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct BufferId(pub u64);
@@ -44,7 +48,7 @@ pub enum EditorMode {
     Normal
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Down,
@@ -52,7 +56,7 @@ pub enum Direction {
     Right
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Key {
     Char(char),
     Enter,
@@ -73,7 +77,7 @@ pub enum Key {
     Unknown,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Modifiers {
     pub ctrl: bool,
     pub alt: bool,
@@ -92,6 +96,71 @@ impl Default for Modifiers {
     }
 }
 
+/// What to look up for the symbol under the cursor.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum LocationKind {
+    Definition,
+    Declaration,
+    TypeDefinition,
+}
+
+impl LocationKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LocationKind::Definition => "definition",
+            LocationKind::Declaration => "declaration",
+            LocationKind::TypeDefinition => "type definition",
+        }
+    }
+}
+
+/// Which side of the call hierarchy `:calls` shows.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CallDirection {
+    Incoming,
+    Outgoing,
+}
+
+impl CallDirection {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CallDirection::Incoming => "incoming calls",
+            CallDirection::Outgoing => "outgoing calls",
+        }
+    }
+}
+
+/// What to send to the REPL when nothing is selected.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ReplScope {
+    Line,
+    Paragraph,
+}
+
+/// The lines an operator like `=` works on when nothing is selected.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum LineScope {
+    Line,
+    Down,
+    Up,
+    Paragraph,
+    ToEnd,
+    ToStart,
+}
+
+impl LineScope {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineScope::Line => "the line",
+            LineScope::Down => "this and the next line",
+            LineScope::Up => "this and the previous line",
+            LineScope::Paragraph => "the paragraph",
+            LineScope::ToEnd => "to the end of the file",
+            LineScope::ToStart => "to the start of the file",
+        }
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub enum EditorAction {
     MoveCursor(Direction),
@@ -102,28 +171,256 @@ pub enum EditorAction {
     InsertNewline,
     StartCommandLine,
     ExecuteCommand,
+    CompleteCommand,
     SwitchBuffer(BufferId),
+    AlternateBuffer,
     SaveCurrentBuffer,
     ChangeMode(EditorMode),
     QuitRequested,
+    OpenLink,
+    GotoFile,
+    GotoLocation(LocationKind),
+    PreviewLocation(LocationKind),
+    Hover,
+    SendToRepl(ReplScope),
+    ContinueList,
+    ToggleCheckbox,
+    ToggleFold,
+    OpenFold,
+    CloseFold,
+    CreateFold,
+    NextDiagnostic,
+    PrevDiagnostic,
     Undo,
-    Redo
+    Redo,
+    Earlier,
+    Later,
+    Reindent(LineScope),
+    FilterLines,
+    GotoFirstLine,
+    GotoLastLine,
+    JumpBack,
+    JumpForward,
+    // Waits for the letter of the mark
+    Mark(MarkCommand),
+    CommandPalette,
+    // An ex command line, from a mapping in the config
+    RunCommand(String)
+}
+
+/// What `m`, `'` and a backtick do with the mark named by the next key.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum MarkCommand {
+    Set,
+    // To the first non-blank of the mark's line
+    Line,
+    Exact,
+}
+
+impl EditorAction {
+    /// The action a config mapping names, like "undo" or "jump_back".
+    pub fn from_name(name: &str) -> Option<EditorAction> {
+        let action = match name {
+            "move_left" => EditorAction::MoveCursor(Direction::Left),
+            "move_right" => EditorAction::MoveCursor(Direction::Right),
+            "move_up" => EditorAction::MoveCursor(Direction::Up),
+            "move_down" => EditorAction::MoveCursor(Direction::Down),
+            "delete_char" => EditorAction::DeleteChar,
+            "newline" => EditorAction::InsertNewline,
+            "command_line" => EditorAction::StartCommandLine,
+            "execute_command" => EditorAction::ExecuteCommand,
+            "complete_command" => EditorAction::CompleteCommand,
+            "command_palette" => EditorAction::CommandPalette,
+            "alternate_buffer" => EditorAction::AlternateBuffer,
+            "save" => EditorAction::SaveCurrentBuffer,
+            "quit" => EditorAction::QuitRequested,
+            "normal_mode" => EditorAction::ChangeMode(EditorMode::Normal),
+            "insert_mode" => EditorAction::ChangeMode(EditorMode::Insert),
+            "command_mode" => EditorAction::ChangeMode(EditorMode::Command),
+            "open_link" => EditorAction::OpenLink,
+            "goto_file" => EditorAction::GotoFile,
+            "goto_definition" => EditorAction::GotoLocation(LocationKind::Definition),
+            "goto_declaration" => EditorAction::GotoLocation(LocationKind::Declaration),
+            "goto_type_definition" => EditorAction::GotoLocation(LocationKind::TypeDefinition),
+            "preview_definition" => EditorAction::PreviewLocation(LocationKind::Definition),
+            "hover" => EditorAction::Hover,
+            "repl_send_line" => EditorAction::SendToRepl(ReplScope::Line),
+            "repl_send_paragraph" => EditorAction::SendToRepl(ReplScope::Paragraph),
+            "continue_list" => EditorAction::ContinueList,
+            "toggle_checkbox" => EditorAction::ToggleCheckbox,
+            "toggle_fold" => EditorAction::ToggleFold,
+            "open_fold" => EditorAction::OpenFold,
+            "close_fold" => EditorAction::CloseFold,
+            "create_fold" => EditorAction::CreateFold,
+            "next_diagnostic" => EditorAction::NextDiagnostic,
+            "prev_diagnostic" => EditorAction::PrevDiagnostic,
+            "undo" => EditorAction::Undo,
+            "redo" => EditorAction::Redo,
+            "earlier" => EditorAction::Earlier,
+            "later" => EditorAction::Later,
+            "reindent_line" => EditorAction::Reindent(LineScope::Line),
+            "reindent_paragraph" => EditorAction::Reindent(LineScope::Paragraph),
+            "filter_lines" => EditorAction::FilterLines,
+            "goto_first_line" => EditorAction::GotoFirstLine,
+            "goto_last_line" => EditorAction::GotoLastLine,
+            "jump_back" => EditorAction::JumpBack,
+            "jump_forward" => EditorAction::JumpForward,
+            "set_mark" => EditorAction::Mark(MarkCommand::Set),
+            "goto_mark_line" => EditorAction::Mark(MarkCommand::Line),
+            "goto_mark" => EditorAction::Mark(MarkCommand::Exact),
+            _ => return None,
+        };
+
+        Some(action)
+    }
+
+    /// Heading the action is listed under in the `:keys` cheatsheet.
+    pub fn category(&self) -> &'static str {
+        match self {
+            EditorAction::MoveCursor(_) | EditorAction::GotoFirstLine | EditorAction::GotoLastLine
+                | EditorAction::JumpBack | EditorAction::JumpForward => "Movement",
+            EditorAction::InsertChar(_) | EditorAction::DeleteChar | EditorAction::InsertNewline
+                | EditorAction::Undo | EditorAction::Redo | EditorAction::Earlier | EditorAction::Later | EditorAction::Reindent(_)
+                | EditorAction::FilterLines => "Editing",
+            EditorAction::ContinueList | EditorAction::ToggleCheckbox => "Lists",
+            EditorAction::ToggleFold | EditorAction::OpenFold | EditorAction::CloseFold | EditorAction::CreateFold => "Folding",
+            EditorAction::InsertCommandChar(_) | EditorAction::DeleteCommandChar
+                | EditorAction::StartCommandLine | EditorAction::ExecuteCommand | EditorAction::CompleteCommand | EditorAction::CommandPalette
+                | EditorAction::RunCommand(_) => "Command line",
+            EditorAction::ChangeMode(_) => "Modes",
+            EditorAction::SwitchBuffer(_) | EditorAction::AlternateBuffer | EditorAction::SaveCurrentBuffer | EditorAction::QuitRequested
+                | EditorAction::OpenLink | EditorAction::GotoFile => "Files",
+            EditorAction::GotoLocation(_) | EditorAction::PreviewLocation(_) | EditorAction::Hover
+                | EditorAction::NextDiagnostic | EditorAction::PrevDiagnostic => "Language server",
+            EditorAction::SendToRepl(_) => "REPL",
+            EditorAction::Mark(_) => "Marks",
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            EditorAction::MoveCursor(dir) => format!("Move cursor {:?}", dir).to_lowercase(),
+            EditorAction::InsertCommandChar(ch) => format!("Type '{}' into the command line", ch),
+            EditorAction::DeleteCommandChar => "Delete command line character".into(),
+            EditorAction::InsertChar(ch) => format!("Insert '{}'", ch),
+            EditorAction::DeleteChar => "Delete character before the cursor".into(),
+            EditorAction::InsertNewline => "Split line".into(),
+            EditorAction::StartCommandLine => "Open the command line".into(),
+            EditorAction::ExecuteCommand => "Run the command line".into(),
+            EditorAction::CompleteCommand => "List what can go after the command, like theme names".into(),
+            EditorAction::CommandPalette => "Pick a command from the list of all of them".into(),
+            EditorAction::RunCommand(line) => format!("Run :{}", line),
+            EditorAction::SwitchBuffer(id) => format!("Switch to buffer {}", id.0),
+            EditorAction::AlternateBuffer => "Switch to the buffer shown before this one".into(),
+            EditorAction::SaveCurrentBuffer => "Save the current buffer".into(),
+            EditorAction::ChangeMode(mode) => format!("Enter {:?} mode", mode).to_lowercase(),
+            EditorAction::QuitRequested => "Quit".into(),
+            EditorAction::OpenLink => "Open the link under the cursor".into(),
+            EditorAction::GotoFile => "Open the file under the cursor".into(),
+            EditorAction::GotoLocation(kind) => format!("Go to {}", kind.name()),
+            EditorAction::PreviewLocation(kind) => format!("Preview the {}, Enter goes there", kind.name()),
+            EditorAction::Hover => "Show documentation for the symbol under the cursor".into(),
+            EditorAction::SendToRepl(scope) => format!("Send the selection or {:?} to the REPL", scope).to_lowercase(),
+            EditorAction::ContinueList => "Split line, continuing the list item".into(),
+            EditorAction::ToggleCheckbox => "Toggle the checkbox of the list item".into(),
+            EditorAction::ToggleFold => "Fold or unfold the section under the cursor".into(),
+            EditorAction::OpenFold => "Unfold the fold under the cursor".into(),
+            EditorAction::CloseFold => "Fold the innermost section around the cursor".into(),
+            EditorAction::CreateFold => "Fold the selected lines".into(),
+            EditorAction::NextDiagnostic => "Go to the next diagnostic".into(),
+            EditorAction::PrevDiagnostic => "Go to the previous diagnostic".into(),
+            EditorAction::Undo => "Undo".into(),
+            EditorAction::Redo => "Redo".into(),
+            EditorAction::Earlier => "Go to the previous text state in time".into(),
+            EditorAction::Later => "Go to the next text state in time".into(),
+            EditorAction::Reindent(scope) => format!("Re-indent the selection or {}", scope.name()),
+            EditorAction::FilterLines => "Filter the selection or line through a shell command".into(),
+            EditorAction::GotoFirstLine => "Go to the first line".into(),
+            EditorAction::GotoLastLine => "Go to the last line".into(),
+            EditorAction::JumpBack => "Go back to where the cursor jumped from".into(),
+            EditorAction::JumpForward => "Go forward again in the jump list".into(),
+            EditorAction::Mark(MarkCommand::Set) => "Set the mark named by the next letter".into(),
+            EditorAction::Mark(MarkCommand::Line) => "Go to the line of a mark".into(),
+            EditorAction::Mark(MarkCommand::Exact) => "Go to the position of a mark".into(),
+        }
+    }
 }
 
 #[derive(PartialEq)]
 pub enum EditorEvent {
     CursorMoved(Cursor),
     CommandCursorMoved(isize),
+    // -1 recalls an older line, 1 a newer one
+    CommandHistory(isize),
     BufferOpened(BufferId),
-    SaveRequested(BufferId),
+    BufferFocused(BufferId),
+    ModeChanged(EditorMode),
+    RunCommandLine(String),
+    BufferClosed { id: BufferId, path: String },
+    // `path` is where `:w <path>` writes to, the buffer takes it over once that worked
+    SaveRequested { id: BufferId, path: Option<String> },
     QuitRequested,
+    QuitWithCode(i32),
     CommandCharInserted(char),
     CommandCharDeleted,
     ExecuteCommand,
+    CompleteCommand,
     ShowCommand,
     HideCommand,
     StartLsp(String),
     RequestDeltaSemantics,
+    OpenUrl(String),
+    OpenFile { path: String, row: Option<usize>, col: Option<usize> },
+    ViewFile(String),
+    HostCollab { address: String, token: Option<String> },
+    JoinCollab { address: String, token: String },
+    StopCollab,
+    ToggleDiagnostics,
+    ShowKeys(Option<String>),
+    ShowFrameStats,
+    ShowStats,
+    CheckHealth,
+    // `None` lists the themes to pick one from
+    SetTheme(Option<String>),
+    // A command a script started with `spawn_task` exited, or couldn't be started
+    ShowLspLog,
+    ShowMessages,
+    RequestLocation(LocationKind),
+    PreviewLocation(LocationKind),
+    ShowCalls(CallDirection),
+    RequestHover,
+    RequestSignatureHelp,
+    HideSignatureHelp,
+    Rename(String),
+    Format,
+    // The command and the rows it gets, the whole buffer without a range
+    StartTransform { command: String, range: Option<(usize, usize)> },
+    AcceptTransform,
+    RejectTransform,
+    StartRepl(Option<String>),
+    StopRepl,
+    SendToRepl(String),
+    ToggleRepl,
+    ToggleUndoTree,
+    ToggleProcesses,
+    Reindent(usize, usize),
+    ScaffoldConfig,
+    RestartLsp,
+    AttachLsp,
+    DetachLsp,
+    Export(ExportFormat, Option<String>),
+    Screenshot(Option<String>),
+    StartRecording(Option<String>),
+    StopRecording,
+    // `None` toggles
+    SetList(Option<bool>),
+    ShowBlame,
+    ShowPalette,
+    // A command from config.rhai, it runs with the plugin manager
+    RunUserCommand(CommandInvocation),
+    // Opens the command line with this typed in
+    PromptCommand(String),
+    RunShell(String, ShellTarget),
     None
 }
 
@@ -0,0 +1,191 @@
+use std::any::Any;
+
+use crossterm::style::{Color, ContentStyle, Stylize};
+use serde_json::Value;
+
+use crate::{types::{CallDirection, RenderCell, Grid}, ui::ui_element::UiElement};
+use crate::services::lsp_service::CallItem;
+
+const MAX_ENTRIES: usize = 12;
+
+struct CallNode {
+    call: CallItem,
+    depth: usize,
+    /// `None` until the calls of this function were asked for.
+    children: Option<Vec<usize>>,
+    expanded: bool,
+    requested: bool,
+}
+
+/// `:calls in|out`, the callers or callees of a function as a tree that grows as it is expanded.
+pub struct CallHierarchyPanel {
+    pub shown: bool,
+    pub direction: CallDirection,
+    pub selected: usize,
+    nodes: Vec<CallNode>,
+    roots: Vec<usize>,
+}
+
+impl CallHierarchyPanel {
+    pub fn new() -> Self {
+        Self {
+            shown: false,
+            direction: CallDirection::Incoming,
+            selected: 0,
+            nodes: vec![],
+            roots: vec![],
+        }
+    }
+
+    pub fn show(&mut self, direction: CallDirection, roots: Vec<CallItem>) {
+        self.direction = direction;
+        self.nodes = roots.into_iter()
+            .map(|call| CallNode { call, depth: 0, children: None, expanded: true, requested: false })
+            .collect();
+        self.roots = (0..self.nodes.len()).collect();
+        self.selected = 0;
+        self.shown = true;
+    }
+
+    /// Adds the calls of the node whose item is `parent`. Returns false if it isn't in the tree
+    /// (anymore) or already has its calls.
+    pub fn add_calls(&mut self, direction: CallDirection, parent: &Value, calls: Vec<CallItem>) -> bool {
+        if direction != self.direction { return false }
+
+        let index = match self.nodes.iter().position(|node| node.children.is_none() && node.call.item == *parent) {
+            Some(index) => index,
+            None => return false
+        };
+
+        let depth = self.nodes[index].depth + 1;
+        let first = self.nodes.len();
+        let count = calls.len();
+
+        self.nodes.extend(calls.into_iter().map(|call| CallNode { call, depth, children: None, expanded: false, requested: false }));
+        self.nodes[index].children = Some((first..first + count).collect());
+
+        true
+    }
+
+    /// Items whose calls should be asked for: expanded nodes that don't have them and
+    /// weren't returned here before.
+    pub fn take_unloaded(&mut self) -> Vec<Value> {
+        let mut unloaded = vec![];
+
+        for index in self.visible() {
+            let node = &mut self.nodes[index];
+            if node.expanded && node.children.is_none() && !node.requested {
+                node.requested = true;
+                unloaded.push(node.call.item.clone());
+            }
+        }
+
+        unloaded
+    }
+
+    /// Node indices in the order they are listed, children of collapsed nodes are left out.
+    fn visible(&self) -> Vec<usize> {
+        let mut visible = vec![];
+        let mut stack: Vec<usize> = self.roots.iter().rev().copied().collect();
+
+        while let Some(index) = stack.pop() {
+            visible.push(index);
+
+            let node = &self.nodes[index];
+            if let (true, Some(children)) = (node.expanded, &node.children) {
+                stack.extend(children.iter().rev());
+            }
+        }
+
+        visible
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        let count = self.visible().len();
+        if count == 0 { return }
+        self.selected = self.selected.saturating_add_signed(delta).min(count - 1);
+    }
+
+    /// Expands or collapses the selected node. Expanding one whose calls aren't known yet
+    /// makes it show up in `take_unloaded`.
+    pub fn set_expanded(&mut self, expanded: bool) {
+        if let Some(index) = self.visible().get(self.selected).copied() {
+            self.nodes[index].expanded = expanded;
+        }
+    }
+
+    pub fn selected_call(&self) -> Option<&CallItem> {
+        self.visible().get(self.selected).map(|index| &self.nodes[*index].call)
+    }
+
+    fn line(&self, index: usize) -> String {
+        let node = &self.nodes[index];
+
+        let marker = match (&node.children, node.expanded) {
+            (Some(children), _) if children.is_empty() => ' ',
+            (_, true) => '▾',
+            (_, false) => '▸',
+        };
+        let file = std::path::Path::new(&node.call.location.path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let detail = node.call.detail.as_deref().map(|detail| format!("  {}", detail)).unwrap_or_default();
+
+        format!("{}{} {}{}  {}:{}", "  ".repeat(node.depth), marker, node.call.name, detail, file, node.call.location.row + 1)
+    }
+}
+
+impl UiElement for CallHierarchyPanel {
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn render(&self, frame: &mut Grid<RenderCell>) {
+        if !self.shown { return }
+
+        let bg = Color::Rgb { r: 22, g: 22, b: 23 };
+        let fg = Color::Rgb { r: 201, g: 199, b: 205 };
+        let header_bg = Color::Rgb { r: 68, g: 68, b: 72 };
+
+        let visible = self.visible();
+        let entries = visible.len().clamp(1, MAX_ENTRIES);
+        let height = entries + 1;
+
+        if frame.rows() < height + 2 { return }
+
+        let top = frame.rows() - height;
+        let width = frame.cols();
+
+        // Keep the selection in the window
+        let first = self.selected.saturating_sub(entries - 1).min(visible.len().saturating_sub(entries));
+
+        let header = format!(" Call hierarchy, {}  j/k select, l/h expand/collapse, Enter jump, q close", self.direction.name());
+        let header_style = ContentStyle::new().on(header_bg).with(fg);
+
+        let mut chars = header.chars();
+        for x in 0..width {
+            let ch = chars.next().unwrap_or(' ');
+            frame.cells[top][x] = RenderCell { ch, style: header_style, transparent: false };
+        }
+
+        for y in 0..entries {
+            let index = first + y;
+            let line = match visible.get(index) {
+                Some(node) => format!(" {}", self.line(*node)),
+                None if visible.is_empty() => " Nothing found".to_string(),
+                None => String::new(),
+            };
+            let style = if index == self.selected && !visible.is_empty() {
+                ContentStyle::new().on(header_bg).with(fg)
+            } else {
+                ContentStyle::new().on(bg).with(fg)
+            };
+
+            let mut chars = line.chars();
+            for x in 0..width {
+                let ch = chars.next().unwrap_or(' ');
+                frame.cells[top + 1 + y][x] = RenderCell { ch, style, transparent: false };
+            }
+        }
+    }
+}
@@ -25,8 +25,9 @@ impl Card {
         }
     }
 
-    pub fn update(&mut self, description: String) {
+    pub fn update(&mut self, description: String, card_type: CardType) {
         self.description = description;
+        self.card_type = card_type;
     }
 
     pub fn get_lines(&self, max_width: usize) -> Vec<String> {
@@ -56,7 +57,6 @@ impl UiElement for Card {
     fn as_any_mut(&mut self) -> &mut dyn Any { self }
 
     fn render(&self, frame: &mut Grid<RenderCell>) {
-        /*
         if self.description.is_empty() { return }
         let top_left = '╭';
         let top_right = '╮';
@@ -66,53 +66,41 @@ impl UiElement for Card {
         let horizontal = '─';
         let vertical = '│';
 
-        let max_width = 63;
-        let max_height = 12;
         let padding = 1;
-        
+        let max_width = 63.min(frame.cols().saturating_sub(2));
+        let max_height = 12;
+
+        if max_width <= 2 + (padding * 2) { return }
+
         let lines = self.get_lines(max_width - 2 - (padding * 2));
-        let width = lines[0].len().min(max_width) + (padding * 2) + 2;
+        let content_width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        let width = content_width + (padding * 2) + 2;
         let height = (lines.len() + 2).clamp(3, max_height);
-        let offset = frame[0].cells.len() - width - 1;
-        let style = self.card_type.style();
 
-        let frame_height = frame.len().clone();
+        if frame.rows() < height + 1 { return }
+
+        let offset = frame.cols() - width - 1;
+        let top = frame.rows() - height - 1;
+        let style = self.card_type.style();
 
         for y in 0..height {
-            let mut render_line = frame[frame.len() - 1 - (height - y)].clone();            
-            let mut char: char;
             for x in 0..width {
-                if y == 0 {
-                    if x == 0 {
-                        char = top_left.clone();
-                    } else if x == width - 1 {
-                        char = top_right.clone();
-                    } else {
-                        char = horizontal.clone();
-                    }
+                let ch = if y == 0 {
+                    if x == 0 { top_left } else if x == width - 1 { top_right } else { horizontal }
                 } else if y == height - 1 {
-                    if x == 0 {
-                        char = bottom_left.clone();
-                    } else if x == width - 1 {
-                        char = bottom_right.clone();
-                    } else {
-                        char = horizontal.clone();
-                    }
+                    if x == 0 { bottom_left } else if x == width - 1 { bottom_right } else { horizontal }
+                } else if x == 0 || x == width - 1 {
+                    vertical
+                } else if x <= padding || x >= width - 1 - padding {
+                    ' '
                 } else {
-                    if x == 0 || x == width - 1 {
-                        char = vertical.clone();
-                    } else if x <= padding || x >= width - 1 - padding {
-                        char = ' ';
-                    } else { 
-                        let mut chars = lines[y - 1].chars();
-                        char = chars.nth(x - 1 - padding).unwrap_or(' ');
-                    }
-                }
-                render_line.cells[x + offset] = RenderCell { ch: char.to_string(), style: style };
+                    lines.get(y - 1)
+                        .and_then(|line| line.chars().nth(x - 1 - padding))
+                        .unwrap_or(' ')
+                };
 
+                frame.cells[top + y][offset + x] = RenderCell { ch, style, transparent: false };
             }
-            frame[frame_height - 1 - (height - y)] = render_line
         }
-        */
     }
 }
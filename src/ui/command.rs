@@ -8,6 +8,10 @@ pub struct Command {
     pub command: String,
     pub shown: bool,
     pub cursor: usize,
+    // Shown in front of the input, e.g. "Passphrase: "
+    pub prompt: String,
+    // Hide typed characters
+    pub masked: bool,
 }
 
 impl Command {
@@ -15,7 +19,9 @@ impl Command {
         Self {
             command: "".to_string(),
             shown: false,
-            cursor: 0
+            cursor: 0,
+            prompt: "".to_string(),
+            masked: false
         }
     }
     
@@ -26,6 +32,29 @@ impl Command {
     pub fn get_position(&self) -> usize {
         return 6 + self.command.len()
     }
+
+    /// Shows a masked input line with `prompt` in front of it.
+    pub fn show_prompt(&mut self, prompt: &str) {
+        self.command.clear();
+        self.cursor = 0;
+        self.prompt = prompt.to_string();
+        self.masked = true;
+        self.shown = true;
+    }
+
+    /// Hides the prompt and returns what was typed into it.
+    pub fn take_prompt(&mut self) -> String {
+        self.prompt.clear();
+        self.masked = false;
+        self.shown = false;
+        self.cursor = 0;
+        std::mem::take(&mut self.command)
+    }
+
+    /// Column of the input cursor, relative to the start of the input.
+    pub fn cursor_col(&self) -> usize {
+        self.prompt.chars().count() + self.cursor
+    }
 }
 
 impl UiElement for Command {
@@ -38,11 +67,17 @@ impl UiElement for Command {
         if !self.shown { return }
 
         let mut render_line = vec![RenderCell::space_col(reset_color) ;frame.cells[1].len()];
-        let text = self.command.clone().on(reset_color.clone()).with(fg.clone());
+        let input = if self.masked {
+            "*".repeat(self.command.chars().count())
+        } else {
+            self.command.clone()
+        };
+        let text = format!("{}{}", self.prompt, input).on(reset_color.clone()).with(fg.clone());
 
         render_line[4] = RenderCell { ch: '', style: text.style().clone(), transparent: false };
    
         for (i, ch) in text.content().chars().enumerate() {
+            if i + 6 >= render_line.len() { break }
             render_line[i + 6] = RenderCell { ch, style: text.style().clone(), transparent: false };
         }
 
@@ -0,0 +1,155 @@
+use std::any::Any;
+
+use crossterm::style::{Attribute, Color, ContentStyle, Stylize};
+
+use crate::{types::{RenderCell, Grid}, ui::ui_element::UiElement};
+
+const MAX_WIDTH: usize = 100;
+const MAX_HEIGHT: usize = 14;
+/// Lines shown above the target before the popup is scrolled.
+const CONTEXT: usize = 3;
+
+/// Where the previewed location is, jumped to when the preview is committed.
+#[derive(Debug, Clone)]
+pub struct PreviewTarget {
+    pub path: String,
+    pub row: usize,
+    pub col: usize,
+}
+
+/// Floating window with the code around a definition, shown by `gp` before jumping there.
+pub struct DefinitionPreview {
+    pub shown: bool,
+    /// Screen cell of the cursor the popup belongs to.
+    pub anchor: (usize, usize),
+    pub scroll: usize,
+    pub target: Option<PreviewTarget>,
+    title: String,
+    /// Row of the first line in the file.
+    first_row: usize,
+    lines: Vec<String>,
+}
+
+impl DefinitionPreview {
+    pub fn new() -> Self {
+        Self {
+            shown: false,
+            anchor: (0, 0),
+            scroll: 0,
+            target: None,
+            title: String::new(),
+            first_row: 0,
+            lines: vec![],
+        }
+    }
+
+    /// Shows `lines`, the whole file, scrolled so the target row is near the top.
+    pub fn show(&mut self, title: String, lines: &[String], target: PreviewTarget, anchor: (usize, usize)) {
+        // Only keep what can be scrolled to in reasonable time
+        self.first_row = target.row.saturating_sub(CONTEXT);
+        let end = (target.row + MAX_HEIGHT * 4).min(lines.len());
+        self.lines = lines.get(self.first_row..end).map(|lines| lines.to_vec()).unwrap_or_default();
+
+        self.title = title;
+        self.anchor = anchor;
+        self.scroll = 0;
+        self.target = Some(target);
+        self.shown = true;
+    }
+
+    pub fn hide(&mut self) {
+        self.shown = false;
+        self.target = None;
+        self.lines.clear();
+    }
+
+    pub fn scroll_by(&mut self, amount: isize) {
+        let max = self.lines.len().saturating_sub(1);
+        self.scroll = (self.scroll as isize + amount).clamp(0, max as isize) as usize;
+    }
+}
+
+impl UiElement for DefinitionPreview {
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn render(&self, frame: &mut Grid<RenderCell>) {
+        if !self.shown || self.lines.is_empty() { return }
+        let target_row = match &self.target {
+            Some(target) => target.row,
+            None => return
+        };
+
+        let bg = Color::Rgb { r: 22, g: 22, b: 23 };
+        let fg = Color::Rgb { r: 201, g: 199, b: 205 };
+        let border = ContentStyle::new().on(bg).with(Color::Rgb { r: 68, g: 68, b: 72 });
+        let number = ContentStyle::new().on(bg).with(Color::Rgb { r: 87, g: 87, b: 94 });
+        let text = ContentStyle::new().on(bg).with(fg);
+        let target = ContentStyle::new().on(Color::Rgb { r: 42, g: 42, b: 46 }).with(fg).attribute(Attribute::Bold);
+
+        let width = MAX_WIDTH.min(frame.cols().saturating_sub(2));
+        if width < 20 { return }
+
+        let (anchor_row, anchor_col) = self.anchor;
+        let below = frame.rows().saturating_sub(anchor_row + 2);
+        let above = anchor_row.saturating_sub(1);
+
+        // Prefer below the cursor, flip above when that has more room
+        let room = below.max(above);
+        let height = (self.lines.len() + 2).min(MAX_HEIGHT).min(room);
+        if height < 3 { return }
+
+        let top = if below >= height || below >= above { anchor_row + 1 } else { anchor_row - height };
+        let left = anchor_col.min(frame.cols() - width);
+
+        let visible = height - 2;
+        let scroll = self.scroll.min(self.lines.len().saturating_sub(visible));
+        let number_width = (self.first_row + self.lines.len()).to_string().len();
+
+        // The file name goes into the top border
+        let title: Vec<char> = format!(" {} ", self.title).chars().collect();
+        let title_len = title.len().min(width.saturating_sub(4));
+        let title = &title[title.len() - title_len..];
+
+        for y in 0..height {
+            let row = self.first_row + scroll + y.saturating_sub(1);
+            let line: Vec<char> = match y {
+                0 => vec![],
+                y if y == height - 1 => vec![],
+                _ => {
+                    let content = self.lines.get(scroll + y - 1).map(|line| line.as_str()).unwrap_or("");
+                    format!("{:>w$} {}", row + 1, content, w = number_width).chars().collect()
+                }
+            };
+
+            for x in 0..width {
+                let cell = if y == 0 || y == height - 1 {
+                    let ch = match (y == 0, x) {
+                        (true, 0) => '╭',
+                        (true, x) if x == width - 1 => '╮',
+                        (true, x) if x >= 2 && x - 2 < title.len() => title[x - 2],
+                        (false, 0) => '╰',
+                        (false, x) if x == width - 1 => '╯',
+                        _ => '─',
+                    };
+                    (ch, border)
+                } else if x == 0 || x == width - 1 {
+                    (if x == width - 1 && scroll + visible < self.lines.len() && y == height - 2 { '┃' } else { '│' }, border)
+                } else {
+                    let style = match (row == target_row, x <= number_width) {
+                        (true, _) => target,
+                        (false, true) => number,
+                        (false, false) => text,
+                    };
+                    let ch = match line.get(x - 1) {
+                        Some('\t') | None => ' ',
+                        Some(ch) => *ch,
+                    };
+                    (ch, style)
+                };
+
+                frame.cells[top + y][left + x] = RenderCell { ch: cell.0, style: cell.1, transparent: false };
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::any::Any;
+
+use crossterm::style::{Color, ContentStyle, Stylize};
+
+use crate::{types::{RenderCell, Grid}, ui::ui_element::UiElement};
+use crate::diagnostics::Diagnostic;
+
+/// Lists the diagnostics of the active buffer at the bottom of the screen.
+pub struct DiagnosticsPanel {
+    pub shown: bool,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl DiagnosticsPanel {
+    pub fn new() -> Self {
+        Self {
+            shown: false,
+            diagnostics: vec![]
+        }
+    }
+
+    pub fn update(&mut self, diagnostics: &[Diagnostic]) {
+        self.diagnostics = diagnostics.to_vec();
+        self.diagnostics.sort_by_key(|d| (d.row, d.col));
+    }
+
+    fn line(diagnostic: &Diagnostic) -> String {
+        let message = diagnostic.message.lines().next().unwrap_or("");
+
+        match &diagnostic.source {
+            Some(source) => format!(" {} {:>4}:{:<3} {} [{}]", diagnostic.severity.sign(), diagnostic.row + 1, diagnostic.col + 1, message, source),
+            None => format!(" {} {:>4}:{:<3} {}", diagnostic.severity.sign(), diagnostic.row + 1, diagnostic.col + 1, message),
+        }
+    }
+}
+
+impl UiElement for DiagnosticsPanel {
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn render(&self, frame: &mut Grid<RenderCell>) {
+        if !self.shown { return }
+
+        let bg = Color::Rgb { r: 22, g: 22, b: 23 };
+        let fg = Color::Rgb { r: 201, g: 199, b: 205 };
+        let header_bg = Color::Rgb { r: 68, g: 68, b: 72 };
+
+        let max_entries = 10;
+        let entries = self.diagnostics.len().clamp(1, max_entries);
+        let height = entries + 1;
+
+        if frame.rows() < height + 2 { return }
+
+        let top = frame.rows() - height;
+        let width = frame.cols();
+
+        let header = format!(" Diagnostics ({})", self.diagnostics.len());
+        let header_style = ContentStyle::new().on(header_bg).with(fg);
+
+        let mut chars = header.chars();
+        for x in 0..width {
+            let ch = chars.next().unwrap_or(' ');
+            frame.cells[top][x] = RenderCell { ch, style: header_style, transparent: false };
+        }
+
+        for y in 0..entries {
+            let (text, style) = match self.diagnostics.get(y) {
+                Some(diagnostic) => (Self::line(diagnostic), ContentStyle::new().on(bg).with(diagnostic.severity.color())),
+                None => (" No diagnostics".to_string(), ContentStyle::new().on(bg).with(fg)),
+            };
+
+            let mut chars = text.chars();
+            for x in 0..width {
+                let ch = chars.next().unwrap_or(' ');
+                frame.cells[top + 1 + y][x] = RenderCell { ch, style, transparent: false };
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crossterm::style::{Attribute, Color, ContentStyle, Stylize};
+
+use crate::ui::popup::{Anchor, Popup, StyledLine};
+
+/// Name of the documentation popup in `Popups`.
+pub const HOVER_POPUP: &str = "hover";
+
+const RULE_WIDTH: usize = 40;
+
+/// Floating documentation popup shown next to the cursor at screen cell `anchor`, wrapped
+/// to fit a screen `cols` wide.
+pub fn hover_popup(markdown: &str, anchor: (usize, usize), cols: usize) -> Popup {
+    let mut popup = Popup::new(HOVER_POPUP, Anchor::Cell { row: anchor.0, col: anchor.1 });
+
+    // Border and padding take two cells on both sides, rules are cut down to the rest
+    let width = popup.limits.max_width.min(cols.saturating_sub(2)).saturating_sub(4).max(1);
+    popup.lines = markdown_lines(markdown).into_iter()
+        .flat_map(|line| {
+            if line.is_empty() {
+                vec![line]
+            } else if line.iter().all(|(ch, _)| *ch == '─') {
+                vec![line[..line.len().min(width)].to_vec()]
+            } else {
+                line.chunks(width).map(<[_]>::to_vec).collect()
+            }
+        })
+        .collect();
+
+    popup
+}
+
+/// Turns the few Markdown constructs servers actually use into styled lines:
+/// headings, code fences, inline code, bold, bullets and rules.
+pub fn markdown_lines(markdown: &str) -> Vec<StyledLine> {
+    let fg = Color::Rgb { r: 201, g: 199, b: 205 };
+    let code = Color::Rgb { r: 146, g: 162, b: 213 };
+    let bg = Color::Rgb { r: 22, g: 22, b: 23 };
+
+    let text_style = ContentStyle::new().on(bg).with(fg);
+    let code_style = ContentStyle::new().on(bg).with(code);
+
+    let mut lines: Vec<StyledLine> = vec![];
+    let mut in_fence = false;
+
+    for raw in markdown.lines() {
+        let trimmed = raw.trim_end();
+
+        if trimmed.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+
+        if in_fence {
+            lines.push(trimmed.chars().map(|ch| (ch, code_style)).collect());
+            continue;
+        }
+
+        if trimmed.len() >= 3 && trimmed.chars().all(|ch| ch == '-' || ch == '_' || ch == '*') {
+            lines.push(vec![('─', text_style); RULE_WIDTH]);
+            continue;
+        }
+
+        let heading = trimmed.trim_start_matches('#');
+        if heading.len() != trimmed.len() && heading.starts_with(' ') {
+            let style = text_style.attribute(Attribute::Bold);
+            lines.push(heading.trim_start().chars().map(|ch| (ch, style)).collect());
+            continue;
+        }
+
+        let (bullet, rest) = match trimmed.trim_start().strip_prefix("- ").or_else(|| trimmed.trim_start().strip_prefix("* ")) {
+            Some(rest) => (true, rest),
+            None => (false, trimmed),
+        };
+
+        let mut line: StyledLine = vec![];
+        if bullet {
+            line.push((' ', text_style));
+            line.push(('•', text_style));
+            line.push((' ', text_style));
+        }
+
+        let mut bold = false;
+        let mut inline_code = false;
+        let mut chars = rest.chars().peekable();
+
+        while let Some(ch) = chars.next() {
+            match ch {
+                '`' => inline_code = !inline_code,
+                '*' if !inline_code && chars.peek() == Some(&'*') => {
+                    chars.next();
+                    bold = !bold;
+                }
+                '\\' if !inline_code && chars.peek().is_some_and(|next| next.is_ascii_punctuation()) => {
+                    line.push((chars.next().unwrap(), text_style));
+                }
+                _ => {
+                    let style = match (inline_code, bold) {
+                        (true, _) => code_style,
+                        (false, true) => text_style.attribute(Attribute::Bold),
+                        (false, false) => text_style,
+                    };
+                    line.push((ch, style));
+                }
+            }
+        }
+
+        lines.push(line);
+    }
+
+    // Blank lines around the content only waste space in the popup
+    while lines.first().is_some_and(|line| line.is_empty()) { lines.remove(0); }
+    while lines.last().is_some_and(|line| line.is_empty()) { lines.pop(); }
+
+    lines
+}
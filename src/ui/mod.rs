@@ -3,3 +3,16 @@ pub mod ui_element;
 pub mod status_bar;
 pub mod card;
 pub mod command;
+pub mod diagnostics_panel;
+pub mod transform_preview;
+pub mod hover;
+pub mod signature_help;
+pub mod repl_panel;
+pub mod undo_tree;
+pub mod show_cmd;
+pub mod definition_preview;
+pub mod process_panel;
+pub mod call_hierarchy;
+pub mod palette;
+pub mod scripted;
+pub mod popup;
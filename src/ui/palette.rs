@@ -0,0 +1,175 @@
+use std::any::Any;
+
+use crossterm::style::{Color, ContentStyle, Stylize};
+
+use crate::{types::{RenderCell, Grid}, ui::ui_element::UiElement};
+use crate::fuzzy;
+
+const MAX_ENTRIES: usize = 12;
+// Width of the name column, longer names push their description to the right
+const NAME_WIDTH: usize = 16;
+
+/// Lists the registered commands with their descriptions, narrowed down by typing.
+/// Scripts use it for their `pick(title, items, callback)` lists too, and the command line
+/// for what can go after a command, like the theme names for `:theme`.
+pub struct CommandPalette {
+    pub shown: bool,
+    pub query: String,
+    /// The name of the script picker shown instead of the commands.
+    pub picker: Option<String>,
+    /// The command whose argument is picked, the command line runs with it.
+    pub completing: Option<String>,
+    title: String,
+    /// Names and descriptions of every command.
+    commands: Vec<(String, String)>,
+    /// Indices into `commands` that match the query, best first.
+    matches: Vec<usize>,
+    pub selected: usize,
+}
+
+impl CommandPalette {
+    pub fn new() -> Self {
+        Self {
+            shown: false,
+            query: String::new(),
+            picker: None,
+            completing: None,
+            title: String::new(),
+            commands: vec![],
+            matches: vec![],
+            selected: 0,
+        }
+    }
+
+    pub fn open(&mut self, commands: Vec<(String, String)>) {
+        self.picker = None;
+        self.completing = None;
+        self.title.clear();
+        self.commands = commands;
+        self.query.clear();
+        self.shown = true;
+        self.filter();
+    }
+
+    /// Shows the items of a script picker, in their order until something is typed.
+    pub fn open_picker(&mut self, name: &str, title: &str, items: Vec<String>) {
+        self.open(items.into_iter().map(|item| (item, String::new())).collect());
+        self.picker = Some(name.to_string());
+        self.title = title.to_string();
+    }
+
+    /// Lists the arguments `command` takes, narrowed down by what was typed after it.
+    pub fn open_completion(&mut self, command: &str, items: Vec<String>, query: &str) {
+        self.open(items.into_iter().map(|item| (item, String::new())).collect());
+        self.completing = Some(command.to_string());
+        self.title = format!(":{}", command);
+        self.query = query.to_string();
+        self.filter();
+    }
+
+    /// Selects `item` if it is listed, e.g. the current value.
+    pub fn select(&mut self, item: &str) {
+        if let Some(position) = self.matches.iter().position(|index| self.commands[*index].0 == item) {
+            self.selected = position;
+        }
+    }
+
+    pub fn push(&mut self, ch: char) {
+        self.query.push(ch);
+        self.filter();
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.filter();
+    }
+
+    /// Matches on the name count for more than matches on the description.
+    fn filter(&mut self) {
+        let mut scored: Vec<(i64, usize)> = self.commands.iter()
+            .enumerate()
+            .filter_map(|(index, (name, description))| {
+                let score = fuzzy::score(&self.query, name).map(|score| score + 10)
+                    .or_else(|| fuzzy::score(&self.query, description))?;
+                Some((score, index))
+            })
+            .collect();
+
+        // The commands come sorted by name, which breaks ties
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        self.matches = scored.into_iter().map(|(_, index)| index).collect();
+        self.selected = 0;
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        if self.matches.is_empty() { return }
+        self.selected = self.selected.saturating_add_signed(delta).min(self.matches.len() - 1);
+    }
+
+    pub fn selected_command(&self) -> Option<&str> {
+        let index = *self.matches.get(self.selected)?;
+        Some(self.commands[index].0.as_str())
+    }
+}
+
+impl UiElement for CommandPalette {
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn render(&self, frame: &mut Grid<RenderCell>) {
+        if !self.shown { return }
+
+        let bg = Color::Rgb { r: 22, g: 22, b: 23 };
+        let fg = Color::Rgb { r: 201, g: 199, b: 205 };
+        let dim = Color::Rgb { r: 120, g: 118, b: 124 };
+        let header_bg = Color::Rgb { r: 68, g: 68, b: 72 };
+
+        let entries = self.matches.len().clamp(1, MAX_ENTRIES);
+        let width = frame.cols().min(80);
+        let left = (frame.cols() - width) / 2;
+        let top = 1;
+
+        if frame.rows() < top + entries + 2 { return }
+
+        // Keep the selection in the window
+        let first = self.selected.saturating_sub(entries - 1);
+
+        let header = match self.picker.as_ref().or(self.completing.as_ref()) {
+            Some(_) => format!(" {} > {}_  ({} of {})", self.title, self.query, self.matches.len(), self.commands.len()),
+            None => format!(" > {}_  ({} of {})", self.query, self.matches.len(), self.commands.len()),
+        };
+        let header_style = ContentStyle::new().on(header_bg).with(fg);
+
+        let mut chars = header.chars();
+        for x in 0..width {
+            let ch = chars.next().unwrap_or(' ');
+            frame.cells[top][left + x] = RenderCell { ch, style: header_style, transparent: false };
+        }
+
+        for y in 0..entries {
+            let index = first + y;
+            let selected = index == self.selected;
+            let row_bg = if selected { header_bg } else { bg };
+
+            let (name, description) = match self.matches.get(index) {
+                Some(command) => {
+                    let (name, description) = &self.commands[*command];
+                    (format!(" {:<width$} ", name, width = NAME_WIDTH), description.as_str())
+                }
+                None if index == 0 && (self.picker.is_some() || self.completing.is_some()) => (" Nothing matches".to_string(), ""),
+                None if index == 0 => (" No matching command".to_string(), ""),
+                None => (String::new(), ""),
+            };
+
+            let name_len = name.chars().count();
+            let mut chars = name.chars().chain(description.chars());
+            for x in 0..width {
+                let ch = chars.next().unwrap_or(' ');
+                let color = if x < name_len { fg } else { dim };
+                let style = ContentStyle::new().on(row_bg).with(color);
+                frame.cells[top + 1 + y][left + x] = RenderCell { ch, style, transparent: false };
+            }
+        }
+    }
+}
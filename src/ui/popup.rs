@@ -0,0 +1,313 @@
+use std::any::Any;
+
+use crossterm::style::{Color, ContentStyle, Stylize};
+
+use crate::{types::{RenderCell, Grid}, ui::ui_element::UiElement};
+
+pub type StyledLine = Vec<(char, ContentStyle)>;
+
+/// Where a popup is placed on the screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anchor {
+    /// Below a screen cell like the cursor's, above it when there is more room there.
+    Cell { row: usize, col: usize },
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Border {
+    None,
+    Rounded,
+    Square,
+}
+
+impl Border {
+    /// Top left, top right, bottom left, bottom right, horizontal and vertical.
+    fn chars(&self) -> Option<[char; 6]> {
+        match self {
+            Border::None => None,
+            Border::Rounded => Some(['╭', '╮', '╰', '╯', '─', '│']),
+            Border::Square => Some(['┌', '┐', '└', '┘', '─', '│']),
+        }
+    }
+
+    fn size(&self) -> usize {
+        if *self == Border::None { 0 } else { 1 }
+    }
+}
+
+/// How large a popup may get, it shrinks to its content and to the screen within these.
+#[derive(Clone, Copy, Debug)]
+pub struct SizeLimits {
+    pub min_width: usize,
+    pub max_width: usize,
+    pub max_height: usize,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        Self { min_width: 10, max_width: 80, max_height: 16 }
+    }
+}
+
+/// The cells a popup covers, border included.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Placement {
+    pub top: usize,
+    pub left: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// A floating window over the buffer. Popups are kept by name in `Popups`, which draws
+/// them lowest `z` first.
+#[derive(Clone)]
+pub struct Popup {
+    pub name: String,
+    pub anchor: Anchor,
+    pub z: i32,
+    pub border: Border,
+    /// Drawn in the top border.
+    pub title: Option<String>,
+    pub limits: SizeLimits,
+    pub scroll: usize,
+    pub lines: Vec<StyledLine>,
+    /// Of the padding and of lines that don't fill the width.
+    pub style: ContentStyle,
+    pub border_style: ContentStyle,
+}
+
+impl Popup {
+    pub fn new(name: &str, anchor: Anchor) -> Self {
+        let bg = Color::Rgb { r: 22, g: 22, b: 23 };
+
+        Self {
+            name: name.to_string(),
+            anchor,
+            z: 0,
+            border: Border::Rounded,
+            title: None,
+            limits: SizeLimits::default(),
+            scroll: 0,
+            lines: vec![],
+            style: ContentStyle::new().on(bg).with(Color::Rgb { r: 201, g: 199, b: 205 }),
+            border_style: ContentStyle::new().on(bg).with(Color::Rgb { r: 68, g: 68, b: 72 }),
+        }
+    }
+
+    /// Sets the lines to `text` in the popup's style.
+    pub fn set_text(&mut self, text: &str) {
+        self.lines = text.lines().map(|line| line.chars().map(|ch| (ch, self.style)).collect()).collect();
+        self.scroll = 0;
+    }
+
+    pub fn scroll_by(&mut self, amount: isize) {
+        let max = self.lines.len().saturating_sub(1);
+        self.scroll = (self.scroll as isize + amount).clamp(0, max as isize) as usize;
+    }
+
+    /// Where the popup goes on a screen of `rows` by `cols` cells, `None` when it doesn't fit.
+    pub fn place(&self, rows: usize, cols: usize) -> Option<Placement> {
+        // Border and a cell of padding on both sides
+        let frame = self.border.size() * 2 + 2;
+        let title = self.title.as_ref().map(|title| title.chars().count() + 4).unwrap_or(0);
+
+        let content = self.lines.iter().map(|line| line.len()).max().unwrap_or(0);
+        let width = (content + frame).max(title).max(self.limits.min_width).min(self.limits.max_width).min(cols);
+        if width <= frame { return None }
+
+        let wanted = (self.lines.len() + self.border.size() * 2).min(self.limits.max_height);
+
+        let (top, left, height) = match self.anchor {
+            Anchor::Cell { row, col } => {
+                let below = rows.saturating_sub(row + 1);
+                let above = row;
+
+                // Prefer below the cell, flip above when that has more room
+                let height = wanted.min(below.max(above));
+                let top = if below >= height || below >= above { row + 1 } else { row - height };
+                (top, col.min(cols - width), height)
+            }
+            anchor => {
+                let height = wanted.min(rows);
+                let (bottom, right) = (rows - height, cols - width);
+
+                match anchor {
+                    Anchor::TopLeft => (0, 0, height),
+                    Anchor::TopRight => (0, right, height),
+                    Anchor::BottomLeft => (bottom, 0, height),
+                    Anchor::BottomRight => (bottom, right, height),
+                    _ => (bottom / 2, right / 2, height),
+                }
+            }
+        };
+
+        if height <= self.border.size() * 2 { return None }
+
+        Some(Placement { top, left, width, height })
+    }
+
+    /// Draws the popup into `frame` where `place` puts it.
+    pub fn draw(&self, frame: &mut Grid<RenderCell>) {
+        let Some(Placement { top, left, width, height }) = self.place(frame.rows(), frame.cols()) else { return };
+
+        let border = self.border.size();
+        let visible = height - border * 2;
+        let scroll = self.scroll.min(self.lines.len().saturating_sub(visible));
+        let title: Vec<char> = self.title.as_deref().unwrap_or("").chars().collect();
+
+        for y in 0..height {
+            for x in 0..width {
+                let edge_row = border == 1 && (y == 0 || y == height - 1);
+                let edge_col = border == 1 && (x == 0 || x == width - 1);
+
+                let cell = match self.border.chars() {
+                    Some([top_left, top_right, bottom_left, bottom_right, horizontal, vertical]) if edge_row => {
+                        let ch = match (y == 0, x) {
+                            (true, 0) => top_left,
+                            (true, x) if x == width - 1 => top_right,
+                            (false, 0) => bottom_left,
+                            (false, x) if x == width - 1 => bottom_right,
+                            // The title sits in the top border after a cell of it
+                            (true, x) if x >= 2 && x - 2 < title.len() && x < width - 2 => title[x - 2],
+                            _ => horizontal,
+                        };
+                        (ch, self.border_style)
+                    }
+                    Some(chars) if edge_col => {
+                        // Mark the side as a scrollbar when there is more to see
+                        let more = (y == 1 && scroll > 0) || (y == height - 2 && scroll + visible < self.lines.len());
+                        (if more && x == width - 1 { '┃' } else { chars[5] }, self.border_style)
+                    }
+                    _ if x == border || x == width - 1 - border => (' ', self.style),
+                    _ => self.lines.get(scroll + y - border)
+                        .and_then(|line| line.get(x - border - 1))
+                        .copied()
+                        .unwrap_or((' ', self.style)),
+                };
+
+                frame.cells[top + y][left + x] = RenderCell { ch: cell.0, style: cell.1, transparent: false };
+            }
+        }
+    }
+}
+
+/// The popups on screen, drawn over the other UI elements.
+pub struct Popups {
+    popups: Vec<Popup>,
+}
+
+impl Popups {
+    pub fn new() -> Self {
+        Self { popups: vec![] }
+    }
+
+    /// Shows `popup`, in place of a shown one with the same name.
+    pub fn show(&mut self, popup: Popup) {
+        match self.popups.iter_mut().find(|shown| shown.name == popup.name) {
+            Some(shown) => *shown = popup,
+            None => self.popups.push(popup),
+        }
+    }
+
+    pub fn hide(&mut self, name: &str) -> Option<Popup> {
+        let index = self.popups.iter().position(|popup| popup.name == name)?;
+        Some(self.popups.remove(index))
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Popup> {
+        self.popups.iter_mut().find(|popup| popup.name == name)
+    }
+
+    pub fn is_shown(&self, name: &str) -> bool {
+        self.popups.iter().any(|popup| popup.name == name)
+    }
+
+    /// The popups lowest `z` first, those with the same `z` in the order they were shown.
+    pub fn in_order(&self) -> Vec<&Popup> {
+        let mut popups: Vec<&Popup> = self.popups.iter().collect();
+        popups.sort_by_key(|popup| popup.z);
+        popups
+    }
+}
+
+impl UiElement for Popups {
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn render(&self, frame: &mut Grid<RenderCell>) {
+        for popup in self.in_order() {
+            popup.draw(frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn popup(anchor: Anchor, lines: usize, width: usize) -> Popup {
+        let mut popup = Popup::new("test", anchor);
+        popup.set_text(&vec!["x".repeat(width); lines].join("\n"));
+        popup
+    }
+
+    #[test]
+    fn opens_below_the_cell_and_flips_above_near_the_bottom() {
+        let below = popup(Anchor::Cell { row: 2, col: 5 }, 3, 20).place(24, 80);
+        assert_eq!(below, Some(Placement { top: 3, left: 5, width: 24, height: 5 }));
+
+        let above = popup(Anchor::Cell { row: 21, col: 5 }, 3, 20).place(24, 80);
+        assert_eq!(above, Some(Placement { top: 16, left: 5, width: 24, height: 5 }));
+    }
+
+    #[test]
+    fn takes_the_larger_side_when_neither_fits() {
+        // 2 rows below, 8 above
+        let placement = popup(Anchor::Cell { row: 8, col: 0 }, 12, 20).place(11, 80).unwrap();
+        assert_eq!((placement.top, placement.height), (0, 8));
+
+        let placement = popup(Anchor::Cell { row: 2, col: 0 }, 12, 20).place(11, 80).unwrap();
+        assert_eq!((placement.top, placement.height), (3, 8));
+    }
+
+    #[test]
+    fn moves_left_to_stay_on_screen() {
+        let placement = popup(Anchor::Cell { row: 0, col: 75 }, 1, 20).place(24, 80).unwrap();
+        assert_eq!((placement.left, placement.width), (56, 24));
+    }
+
+    #[test]
+    fn clamps_to_the_limits_and_the_screen() {
+        let placement = popup(Anchor::TopLeft, 100, 200).place(40, 120).unwrap();
+        assert_eq!((placement.width, placement.height), (80, 16));
+
+        let placement = popup(Anchor::TopLeft, 100, 200).place(10, 30).unwrap();
+        assert_eq!((placement.width, placement.height), (30, 10));
+
+        let placement = popup(Anchor::TopLeft, 1, 1).place(24, 80).unwrap();
+        assert_eq!(placement.width, 10);
+    }
+
+    #[test]
+    fn corners_and_center() {
+        let place = |anchor| popup(anchor, 2, 16).place(24, 80).map(|placement| (placement.top, placement.left));
+
+        assert_eq!(place(Anchor::TopLeft), Some((0, 0)));
+        assert_eq!(place(Anchor::TopRight), Some((0, 60)));
+        assert_eq!(place(Anchor::BottomLeft), Some((20, 0)));
+        assert_eq!(place(Anchor::BottomRight), Some((20, 60)));
+        assert_eq!(place(Anchor::Center), Some((10, 30)));
+    }
+
+    #[test]
+    fn nothing_when_the_screen_is_too_small() {
+        assert_eq!(popup(Anchor::TopLeft, 3, 20).place(2, 80), None);
+        assert_eq!(popup(Anchor::TopLeft, 3, 20).place(24, 4), None);
+        assert_eq!(popup(Anchor::Cell { row: 0, col: 0 }, 3, 20).place(1, 80), None);
+    }
+}
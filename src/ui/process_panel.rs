@@ -0,0 +1,101 @@
+use std::any::Any;
+
+use crossterm::style::{Color, ContentStyle, Stylize};
+
+use crate::{types::{RenderCell, Grid}, ui::ui_element::UiElement};
+use crate::processes::{self, Process};
+
+const MAX_ENTRIES: usize = 10;
+
+/// `:ps`, the processes the editor spawned with their CPU and memory use.
+pub struct ProcessPanel {
+    pub shown: bool,
+    pub selected: usize,
+    pub processes: Vec<Process>,
+}
+
+impl ProcessPanel {
+    pub fn new() -> Self {
+        Self {
+            shown: false,
+            selected: 0,
+            processes: vec![],
+        }
+    }
+
+    pub fn update(&mut self, processes: &[Process]) {
+        self.processes = processes.to_vec();
+        self.selected = self.selected.min(self.processes.len().saturating_sub(1));
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        if self.processes.is_empty() { return }
+        self.selected = self.selected.saturating_add_signed(delta).min(self.processes.len() - 1);
+    }
+
+    pub fn selected_process(&self) -> Option<&Process> {
+        self.processes.get(self.selected)
+    }
+
+    fn line(process: &Process) -> String {
+        let cpu = process.cpu.map(|cpu| format!("{:.1}%", cpu)).unwrap_or("-".into());
+        let rss = process.rss.map(|rss| format!("{:.1} MB", rss as f64 / 1024.0 / 1024.0)).unwrap_or("-".into());
+        let uptime = processes::format_uptime(process.started.elapsed());
+
+        format!("{:<7} {:<5} {:>7} {:>10} {:>8}  {}", process.pid, process.owner.kind(), cpu, rss, uptime, process.name)
+    }
+}
+
+impl UiElement for ProcessPanel {
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn render(&self, frame: &mut Grid<RenderCell>) {
+        if !self.shown { return }
+
+        let bg = Color::Rgb { r: 22, g: 22, b: 23 };
+        let fg = Color::Rgb { r: 201, g: 199, b: 205 };
+        let header_bg = Color::Rgb { r: 68, g: 68, b: 72 };
+
+        let entries = self.processes.len().clamp(1, MAX_ENTRIES);
+        let height = entries + 2;
+
+        if frame.rows() < height + 2 { return }
+
+        let top = frame.rows() - height;
+        let width = frame.cols();
+
+        // Keep the selection in the window
+        let first = self.selected.saturating_sub(entries - 1).min(self.processes.len().saturating_sub(entries));
+
+        let header = format!(" Processes ({})  j/k select, r restart, x kill, q close", self.processes.len());
+        let columns = format!(" {:<7} {:<5} {:>7} {:>10} {:>8}  {}", "PID", "KIND", "CPU", "RSS", "UPTIME", "COMMAND");
+        let header_style = ContentStyle::new().on(header_bg).with(fg);
+
+        let mut lines: Vec<(String, ContentStyle)> = vec![
+            (header, header_style),
+            (columns, ContentStyle::new().on(bg).with(Color::Rgb { r: 87, g: 87, b: 94 })),
+        ];
+
+        if self.processes.is_empty() {
+            lines.push((" No processes running".into(), ContentStyle::new().on(bg).with(fg)));
+        }
+
+        for index in first..(first + entries).min(self.processes.len()) {
+            let style = if index == self.selected {
+                ContentStyle::new().on(header_bg).with(fg)
+            } else {
+                ContentStyle::new().on(bg).with(fg)
+            };
+            lines.push((format!(" {}", Self::line(&self.processes[index])), style));
+        }
+
+        for (y, (line, style)) in lines.iter().enumerate().take(height) {
+            let mut chars = line.chars();
+            for x in 0..width {
+                let ch = chars.next().unwrap_or(' ');
+                frame.cells[top + y][x] = RenderCell { ch, style: *style, transparent: false };
+            }
+        }
+    }
+}
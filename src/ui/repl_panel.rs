@@ -0,0 +1,94 @@
+use std::any::Any;
+
+use crossterm::style::{Color, ContentStyle, Stylize};
+
+use crate::{links::{self, Link}, types::{RenderCell, Grid}, ui::ui_element::UiElement};
+
+/// Shows the latest output of the REPL at the bottom of the screen.
+pub struct ReplPanel {
+    pub shown: bool,
+    pub title: String,
+    pub lines: Vec<String>,
+}
+
+impl ReplPanel {
+    pub fn new() -> Self {
+        Self {
+            shown: false,
+            title: String::new(),
+            lines: vec![]
+        }
+    }
+
+    pub fn update(&mut self, title: &str, output: &[String]) {
+        const MAX_LINES: usize = 12;
+
+        self.title = title.to_string();
+
+        // A trailing empty line is just the newline after the last output
+        let output = match output.last() {
+            Some(last) if last.is_empty() => &output[..output.len() - 1],
+            _ => output,
+        };
+        self.lines = output[output.len().saturating_sub(MAX_LINES)..].to_vec();
+    }
+
+    /// Screen row of the header on a screen `rows` high, `None` when the panel isn't drawn.
+    fn top(&self, rows: usize) -> Option<usize> {
+        let height = self.lines.len().max(1) + 1;
+        if !self.shown || rows < height + 2 { return None }
+
+        Some(rows - height)
+    }
+
+    /// The link in the output at a screen cell, on a screen `rows` high.
+    pub fn link_at(&self, rows: usize, row: usize, col: usize) -> Option<Link> {
+        let line = self.lines.get(row.checked_sub(self.top(rows)? + 1)?)?;
+        // Lines are indented by a cell
+        links::link_at(line, col.checked_sub(1)?)
+    }
+}
+
+impl UiElement for ReplPanel {
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn render(&self, frame: &mut Grid<RenderCell>) {
+        if !self.shown { return }
+
+        let bg = Color::Rgb { r: 22, g: 22, b: 23 };
+        let fg = Color::Rgb { r: 201, g: 199, b: 205 };
+        let header_bg = Color::Rgb { r: 68, g: 68, b: 72 };
+
+        let entries = self.lines.len().max(1);
+        let Some(top) = self.top(frame.rows()) else { return };
+        let width = frame.cols();
+
+        let header = format!(" REPL: {}", self.title);
+        let header_style = ContentStyle::new().on(header_bg).with(fg);
+
+        let mut chars = header.chars();
+        for x in 0..width {
+            let ch = chars.next().unwrap_or(' ');
+            frame.cells[top][x] = RenderCell { ch, style: header_style, transparent: false };
+        }
+
+        let style = ContentStyle::new().on(bg).with(fg);
+
+        for y in 0..entries {
+            let text = self.lines.get(y).map(|line| line.as_str()).unwrap_or("");
+
+            let line = format!(" {}", text);
+            let links = links::find_links(text);
+
+            let mut chars = line.chars();
+            for x in 0..width {
+                let ch = chars.next().unwrap_or(' ');
+                // Links are underlined like in a terminal, Ctrl-click opens them
+                let linked = x > 0 && links.iter().any(|link| link.contains(x - 1));
+                let style = if linked { style.underlined() } else { style };
+                frame.cells[top + 1 + y][x] = RenderCell { ch, style, transparent: false };
+            }
+        }
+    }
+}
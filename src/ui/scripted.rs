@@ -0,0 +1,100 @@
+use std::any::Any;
+
+use crossterm::style::{Attribute, Color, ContentStyle, Stylize};
+
+use crate::{types::{RenderCell, Grid}, ui::ui_element::UiElement};
+use crate::plugins::widgets::{Position, Widget, WidgetKind};
+
+const MAX_WIDTH: usize = 60;
+
+/// Draws the text boxes scripts show with `text_box(name, #{ ... })`, stacked in their corner.
+pub struct ScriptedUiElement {
+    widgets: Vec<Widget>,
+}
+
+impl ScriptedUiElement {
+    pub fn new() -> Self {
+        Self { widgets: vec![] }
+    }
+
+    /// Keeps the text boxes of `widgets`, the others are drawn elsewhere.
+    pub fn set_widgets(&mut self, widgets: &[Widget]) {
+        self.widgets = widgets.iter()
+            .filter(|widget| matches!(widget.kind, WidgetKind::TextBox(_)))
+            .cloned()
+            .collect();
+    }
+
+    fn render_box(&self, frame: &mut Grid<RenderCell>, widget: &Widget, top: usize, left: usize, width: usize, height: usize) {
+        let bg = Color::Rgb { r: 22, g: 22, b: 23 };
+        let text = ContentStyle::new().on(bg).with(Color::Rgb { r: 201, g: 199, b: 205 });
+        let border = ContentStyle::new().on(bg).with(Color::Rgb { r: 68, g: 68, b: 72 });
+        let title_style = text.attribute(Attribute::Bold);
+
+        let title: Vec<char> = match widget.title.is_empty() {
+            true => vec![],
+            false => format!(" {} ", widget.title).chars().collect(),
+        };
+
+        for y in 0..height {
+            let line: Vec<char> = widget.lines.get(y.wrapping_sub(1)).map(|line| line.chars().collect()).unwrap_or_default();
+
+            for x in 0..width {
+                let cell = match (y, x) {
+                    (0, 0) => ('╭', border),
+                    (0, x) if x == width - 1 => ('╮', border),
+                    (0, x) => match title.get(x.wrapping_sub(2)) {
+                        Some(ch) if x < width - 2 => (*ch, title_style),
+                        _ => ('─', border),
+                    },
+                    (y, 0) if y == height - 1 => ('╰', border),
+                    (y, x) if y == height - 1 && x == width - 1 => ('╯', border),
+                    (y, _) if y == height - 1 => ('─', border),
+                    (_, 0) => ('│', border),
+                    (_, x) if x == width - 1 => ('│', border),
+                    (_, x) if x == 1 || x == width - 2 => (' ', text),
+                    (_, x) => (line.get(x - 2).copied().unwrap_or(' '), text),
+                };
+
+                frame.cells[top + y][left + x] = RenderCell { ch: cell.0, style: cell.1, transparent: false };
+            }
+        }
+    }
+}
+
+impl UiElement for ScriptedUiElement {
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn render(&self, frame: &mut Grid<RenderCell>) {
+        // Below the status bar and above the command line
+        let (first_row, last_row) = (1, frame.rows().saturating_sub(1));
+        let mut stacked = [0usize; 5];
+
+        for widget in &self.widgets {
+            let WidgetKind::TextBox(position) = widget.kind else { continue };
+
+            let content = widget.lines.iter().map(|line| line.chars().count()).max().unwrap_or(0)
+                .max(widget.title.chars().count() + 2);
+            let width = (content + 4).min(MAX_WIDTH).min(frame.cols());
+            let height = widget.lines.len() + 2;
+            if width < 5 { continue }
+
+            let slot = position as usize;
+            let offset = stacked[slot];
+            if first_row + offset + height > last_row { continue }
+            stacked[slot] += height;
+
+            let (top, left) = match position {
+                Position::TopLeft => (first_row + offset, 0),
+                Position::TopRight => (first_row + offset, frame.cols() - width),
+                Position::BottomLeft => (last_row - offset - height, 0),
+                Position::BottomRight => (last_row - offset - height, frame.cols() - width),
+                Position::Center => ((first_row + last_row).saturating_sub(height) / 2 + offset, (frame.cols() - width) / 2),
+            };
+            if top + height > last_row { continue }
+
+            self.render_box(frame, widget, top, left, width, height);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::any::Any;
+use std::time::{Duration, Instant};
+
+use crossterm::style::{Color, ContentStyle, Stylize};
+
+use crate::{types::{RenderCell, Grid}, ui::ui_element::UiElement};
+
+/// How long the command line that just ran stays in the corner.
+const FLASH_TIME: Duration = Duration::from_millis(1500);
+
+/// Keys of a mapping that is still being typed, like the `=` of `=j`, and for a moment
+/// the command line that just ran. Drawn in the bottom right corner.
+pub struct ShowCmd {
+    pub pending: String,
+    flash: Option<(String, Instant)>,
+}
+
+impl ShowCmd {
+    pub fn new() -> Self {
+        Self {
+            pending: String::new(),
+            flash: None,
+        }
+    }
+
+    pub fn flash(&mut self, text: String) {
+        self.flash = Some((text, Instant::now()));
+    }
+
+    fn text(&self) -> Option<&str> {
+        if !self.pending.is_empty() {
+            return Some(&self.pending);
+        }
+
+        self.flash.as_ref()
+            .filter(|(_, at)| at.elapsed() < FLASH_TIME)
+            .map(|(text, _)| text.as_str())
+    }
+}
+
+impl UiElement for ShowCmd {
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn render(&self, frame: &mut Grid<RenderCell>) {
+        let text = match self.text() {
+            Some(text) => text,
+            None => return
+        };
+
+        let bg = Color::Rgb { r: 22, g: 22, b: 23 };
+        let fg = Color::Rgb { r: 201, g: 199, b: 205 };
+        let style = ContentStyle::new().on(bg).with(fg);
+
+        // Long command lines keep their end, that is where the arguments are
+        let chars: Vec<char> = text.chars().collect();
+        let width = chars.len().min(frame.cols().saturating_sub(2));
+        if width == 0 || frame.rows() < 2 { return }
+
+        let row = frame.rows() - 1;
+        let left = frame.cols() - width - 1;
+
+        for (x, ch) in chars[chars.len() - width..].iter().enumerate() {
+            frame.cells[row][left + x] = RenderCell { ch: *ch, style, transparent: false };
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::any::Any;
+
+use crossterm::style::{Attribute, Color, ContentStyle, Stylize};
+
+use crate::{types::{RenderCell, Grid}, ui::ui_element::UiElement};
+use crate::services::lsp_service::Signature;
+
+/// Shows the signature of the call being typed, with the current parameter highlighted.
+pub struct SignatureHelp {
+    pub shown: bool,
+    /// Screen cell of the cursor the popup belongs to.
+    pub anchor: (usize, usize),
+    signature: Option<Signature>,
+}
+
+impl SignatureHelp {
+    pub fn new() -> Self {
+        Self {
+            shown: false,
+            anchor: (0, 0),
+            signature: None
+        }
+    }
+
+    pub fn show(&mut self, signature: Signature, anchor: (usize, usize)) {
+        self.signature = Some(signature);
+        self.anchor = anchor;
+        self.shown = true;
+    }
+
+    pub fn hide(&mut self) {
+        self.shown = false;
+        self.signature = None;
+    }
+}
+
+impl UiElement for SignatureHelp {
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn render(&self, frame: &mut Grid<RenderCell>) {
+        let signature = match &self.signature {
+            Some(signature) if self.shown => signature,
+            _ => return
+        };
+
+        let bg = Color::Rgb { r: 22, g: 22, b: 23 };
+        let fg = Color::Rgb { r: 201, g: 199, b: 205 };
+        let border = ContentStyle::new().on(bg).with(Color::Rgb { r: 68, g: 68, b: 72 });
+        let text = ContentStyle::new().on(bg).with(fg);
+        let active = ContentStyle::new().on(bg).with(Color::Rgb { r: 230, g: 185, b: 157 }).attribute(Attribute::Bold);
+
+        let label: Vec<char> = signature.label.chars().collect();
+        let width = (label.len() + 4).min(frame.cols());
+        if width < 5 { return }
+
+        // Above the cursor so it doesn't cover the arguments being typed, below on the first lines
+        let (anchor_row, anchor_col) = self.anchor;
+        let top = if anchor_row >= 3 { anchor_row - 3 } else { anchor_row + 1 };
+        if top + 3 > frame.rows() { return }
+
+        let left = anchor_col.min(frame.cols() - width);
+
+        for y in 0..3 {
+            for x in 0..width {
+                let cell = match (y, x) {
+                    (0, 0) => ('╭', border),
+                    (0, x) if x == width - 1 => ('╮', border),
+                    (2, 0) => ('╰', border),
+                    (2, x) if x == width - 1 => ('╯', border),
+                    (0, _) | (2, _) => ('─', border),
+                    (_, 0) => ('│', border),
+                    (_, x) if x == width - 1 => ('│', border),
+                    (_, x) if x == 1 || x == width - 2 => (' ', text),
+                    (_, x) => {
+                        let i = x - 2;
+                        let style = match signature.active {
+                            Some((start, end)) if i >= start && i < end => active,
+                            _ => text,
+                        };
+                        (label.get(i).copied().unwrap_or(' '), style)
+                    }
+                };
+
+                frame.cells[top + y][left + x] = RenderCell { ch: cell.0, style: cell.1, transparent: false };
+            }
+        }
+    }
+}
@@ -1,19 +1,49 @@
 use std::any::Any;
+use std::collections::HashMap;
+use std::time::Instant;
 
 use crossterm::style::{Color, StyledContent, Stylize};
 
 use crate::{types::{RenderCell, Grid}, ui::ui_element::UiElement};
 use crate::types::{Cursor, EditorMode};
+use crate::plugins::statusbar::{StatusBarConfig, StatusComponent};
+use crate::plugins::theme;
+use crate::git::GitStatus;
 
 pub struct StatusBar {
     pub name: String,
     pub file: String,
     pub pos: Cursor,
+    pub total_lines: usize,
     pub mode: EditorMode,
+    pub errors: usize,
+    pub warnings: usize,
     pub bg: Color,
     pub fg: Color,
     pub left_symbol: String,
-    pub right_symbol: String
+    pub right_symbol: String,
+    /// What the language server is busy with, e.g. "rust-analyzer: 43% Indexing".
+    pub progress: Option<String>,
+    /// Branch and changes of the repository the file is in.
+    pub git: Option<GitStatus>,
+    /// Names and text of the segments scripts added with `status_segment`.
+    pub segments: Vec<(String, String)>,
+    /// What is drawn, from `oxidy.statusbar.components`.
+    pub components: Vec<StatusComponent>,
+    /// Results of the `Eval` snippets by their source, the app evaluates them.
+    pub evaluated: HashMap<String, String>,
+    /// Theme colors a `Color` component can name instead of a hex value.
+    pub colors: HashMap<String, Color>,
+    started: Instant
+}
+
+/// ASCII so it draws with any font.
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// A top-level component ready to draw, spacers share the width that is left.
+enum Part {
+    Item(Vec<StyledContent<String>>),
+    Spacer,
 }
 
 impl UiElement for StatusBar {
@@ -21,37 +51,61 @@ impl UiElement for StatusBar {
     fn as_any_mut(&mut self) -> &mut dyn Any { self }
 
     fn render(&self, frame: &mut Grid<RenderCell>) {
-        let mut items = vec![];
-        let title = self.item(&self.name);
-        let file_path = self.item(&self.file);
-
-        let mode = match self.mode {
-            EditorMode::Insert => " INS",
-            EditorMode::Command => " CMD",
-            _ => ""
-        };
+        let mut parts: Vec<Part> = self.components.iter()
+            .filter_map(|component| match component {
+                StatusComponent::Spacer => Some(Part::Spacer),
+                component => self.component_item(component).map(Part::Item),
+            })
+            .collect();
+
+        // Script segments end the left side, the server's progress starts the right one
+        let right = parts.iter().position(|part| matches!(part, Part::Spacer)).unwrap_or(parts.len());
+        if let Some(progress) = &self.progress {
+            let tick = (self.started.elapsed().as_millis() / 100) as usize % SPINNER.len();
+            parts.insert(right, Part::Item(self.item(&format!("{} {}", SPINNER[tick], progress))));
+        }
+        for (_, text) in self.segments.iter().rev().filter(|(_, text)| !text.is_empty()) {
+            parts.insert(right, Part::Item(self.item(text)));
+        }
 
-        let state = format!("{:02}:{:02}{}", self.pos.col + 1, self.pos.row + 1, mode);
-        let state_item = self.item(&state);
+        // One column between items next to each other
+        let separators = parts.windows(2).filter(|pair| matches!(pair, [Part::Item(_), Part::Item(_)])).count();
 
-        items.extend(title);
-        items.push(self.spacer(1));
-        items.extend(file_path);
+        // Counted in characters, the caps are one column but several bytes
+        let used: usize = parts.iter()
+            .filter_map(|part| match part { Part::Item(item) => Some(item), Part::Spacer => None })
+            .flatten()
+            .map(|content| content.content().chars().count())
+            .sum::<usize>() + separators;
+        let spacers = parts.iter().filter(|part| matches!(part, Part::Spacer)).count();
+        let free = frame.cells[0].len().saturating_sub(used + 1);
 
-        let gap = self.spacer(
-            frame.cells[0].len() - (
-                (self.left_symbol.len()) +
-                (self.right_symbol.len()) + 
-                self.name.len() + self.file.len() + state.len()
-            ) - 9
-        );
-        items.push(gap);
-        items.extend(state_item);
+        let mut contents = vec![];
+        let mut previous_item = false;
+        let mut spacer = 0;
+        for part in parts {
+            match part {
+                Part::Item(item) => {
+                    if previous_item {
+                        contents.push(self.spacer(1));
+                    }
+                    contents.extend(item);
+                    previous_item = true;
+                }
+                Part::Spacer => {
+                    // The last spacer takes what doesn't divide evenly
+                    spacer += 1;
+                    let width = free / spacers + if spacer == spacers { free % spacers } else { 0 };
+                    contents.push(self.spacer(width));
+                    previous_item = false;
+                }
+            }
+        }
 
         let mut render_line = frame.cells[0].clone();
         
         let mut col = 1;
-        for item in items {
+        for item in contents {
             for char in item.content().chars() {
                 if col >= render_line.len() { break; }
 
@@ -67,16 +121,133 @@ impl UiElement for StatusBar {
 
 impl StatusBar {
     pub fn new() -> Self {
+        // Replaced with the configured ones once the config is loaded
+        let (left_symbol, right_symbol) = StatusBarConfig::default().symbols();
+
         Self {
             name: "Oxidy".to_string(),
             file: "file.rs".to_string(),
             pos: Cursor { col: 0, row: 0 },
+            total_lines: 1,
             mode: EditorMode::Normal,
+            errors: 0,
+            warnings: 0,
             bg: Color::Rgb { r: 68, g: 68, b: 72 },
             fg: Color::Rgb { r: 201, g: 199, b: 205 },
-            left_symbol: "".to_string(),
-            right_symbol: "".to_string()
+            left_symbol,
+            right_symbol,
+            progress: None,
+            git: None,
+            segments: vec![],
+            components: StatusBarConfig::default().components,
+            evaluated: HashMap::new(),
+            colors: HashMap::new(),
+            started: Instant::now()
+        }
+    }
+
+    /// The value of a `Field` status bar component, `None` when there is nothing to show.
+    pub fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "filename" => Some(self.file.clone()),
+            "mode" => Some(format!("{:?}", self.mode)),
+            // Only shown outside of normal mode
+            "mode_flag" => match self.mode {
+                EditorMode::Insert => Some("INS".to_string()),
+                EditorMode::Command => Some("CMD".to_string()),
+                EditorMode::Normal => None,
+            },
+            "line" => Some((self.pos.row + 1).to_string()),
+            "column" => Some((self.pos.col + 1).to_string()),
+            "cursor" => Some(format!("{:02}:{:02}", self.pos.col + 1, self.pos.row + 1)),
+            "total_lines" => Some(self.total_lines.to_string()),
+            "errors" => Some(self.errors.to_string()),
+            "warnings" => Some(self.warnings.to_string()),
+            "diagnostics" => (self.errors + self.warnings > 0).then(|| format!("E{} W{}", self.errors, self.warnings)),
+            "git" => self.field("git_branch").map(|branch| branch + &self.field("git_dirty").unwrap_or_default()),
+            "git_branch" => self.git.as_ref().map(|git| git.branch.clone()),
+            "git_dirty" => self.git.as_ref().map(|git| if git.dirty { "*" } else { "" }.to_string()),
+            _ => self.segments.iter().find(|(segment, _)| segment == name).map(|(_, text)| text.clone()),
+        }
+    }
+
+    /// Every field by name, the variables an `Eval` snippet sees. Numbers are integers.
+    pub fn scope(&self) -> rhai::Scope<'static> {
+        let mut scope = rhai::Scope::new();
+        for name in ["filename", "mode", "cursor", "diagnostics", "git", "git_branch", "git_dirty"] {
+            scope.push(name, self.field(name).unwrap_or_default());
+        }
+        for (name, value) in [("line", self.pos.row + 1), ("column", self.pos.col + 1), ("total_lines", self.total_lines),
+            ("errors", self.errors), ("warnings", self.warnings)] {
+            scope.push(name, value as rhai::INT);
+        }
+
+        scope
+    }
+
+    /// The sources of the `Eval` snippets among the components.
+    pub fn snippets(&self) -> Vec<String> {
+        fn collect(component: &StatusComponent, snippets: &mut Vec<String>) {
+            match component {
+                StatusComponent::Eval(source) => snippets.push(source.clone()),
+                StatusComponent::Group(children) => children.iter().for_each(|child| collect(child, snippets)),
+                StatusComponent::Color { content, .. } => collect(content, snippets),
+                StatusComponent::Text(_) | StatusComponent::Field(_) | StatusComponent::Spacer => {}
+            }
+        }
+
+        let mut snippets = vec![];
+        self.components.iter().for_each(|component| collect(component, &mut snippets));
+        snippets
+    }
+
+    /// Draws a top-level component between the caps, `None` if it has nothing to show.
+    fn component_item(&self, component: &StatusComponent) -> Option<Vec<StyledContent<String>>> {
+        let bg = match component {
+            StatusComponent::Color { bg: Some(bg), .. } => self.color(bg).unwrap_or(self.bg),
+            _ => self.bg,
+        };
+
+        let mut contents = self.styled(component, self.fg, bg);
+        contents.retain(|content| !content.content().is_empty());
+        if contents.is_empty() { return None }
+
+        let reset_color = Color::Rgb { r: 22, g: 22, b: 23 };
+        contents.insert(0, " ".to_string().on(bg).with(self.fg));
+        contents.insert(0, self.left_symbol.clone().on(reset_color).with(bg));
+        contents.push(" ".to_string().on(bg).with(self.fg));
+        contents.push(self.right_symbol.clone().on(reset_color).with(bg));
+
+        Some(contents)
+    }
+
+    fn styled(&self, component: &StatusComponent, fg: Color, bg: Color) -> Vec<StyledContent<String>> {
+        let text = match component {
+            StatusComponent::Text(text) => text.clone(),
+            StatusComponent::Field(name) => self.field(name).unwrap_or_default(),
+            StatusComponent::Eval(source) => self.evaluated.get(source).cloned().unwrap_or_default(),
+            StatusComponent::Spacer => " ".to_string(),
+            StatusComponent::Group(children) => {
+                return children.iter().flat_map(|child| self.styled(child, fg, bg)).collect();
+            }
+            StatusComponent::Color { fg: child_fg, bg: child_bg, content } => {
+                let fg = child_fg.as_deref().and_then(|color| self.color(color)).unwrap_or(fg);
+                let bg = child_bg.as_deref().and_then(|color| self.color(color)).unwrap_or(bg);
+                return self.styled(content, fg, bg);
+            }
+        };
+
+        vec![text.on(bg).with(fg)]
+    }
+
+    /// A theme color like "keyword", or a hex value.
+    fn color(&self, name: &str) -> Option<Color> {
+        if let Some(color) = self.colors.get(name) {
+            return Some(*color);
         }
+
+        let (r, g, b) = theme::parse_hex(name)?;
+        Some(Color::Rgb { r, g, b })
     }
 
     fn item(&self, title: &str) -> Vec<StyledContent<String>> {
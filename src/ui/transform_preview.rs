@@ -0,0 +1,104 @@
+use std::any::Any;
+
+use crossterm::style::{Color, ContentStyle, Stylize};
+
+use crate::{types::{RenderCell, Grid}, ui::ui_element::UiElement};
+use crate::transform::DiffLine;
+
+// Unchanged lines shown around each change
+const CONTEXT: usize = 2;
+
+/// Shows what an external transform would change before it is applied.
+pub struct TransformPreview {
+    pub shown: bool,
+    pub title: String,
+    lines: Vec<(char, String)>,
+}
+
+impl TransformPreview {
+    pub fn new() -> Self {
+        Self {
+            shown: false,
+            title: String::new(),
+            lines: vec![]
+        }
+    }
+
+    /// Keeps only the changed lines and a bit of context around them.
+    pub fn update(&mut self, diff: &[DiffLine]) {
+        let changed: Vec<usize> = diff.iter()
+            .enumerate()
+            .filter(|(_, line)| !matches!(line, DiffLine::Same(_)))
+            .map(|(i, _)| i)
+            .collect();
+
+        self.lines.clear();
+        let mut last_shown: Option<usize> = None;
+
+        for (i, line) in diff.iter().enumerate() {
+            let near_change = changed.iter().any(|&c| i + CONTEXT >= c && i <= c + CONTEXT);
+            if !near_change { continue }
+
+            if last_shown.is_some_and(|last| i > last + 1) {
+                self.lines.push(('…', String::new()));
+            }
+            last_shown = Some(i);
+
+            self.lines.push(match line {
+                DiffLine::Same(text) => (' ', text.clone()),
+                DiffLine::Added(text) => ('+', text.clone()),
+                DiffLine::Removed(text) => ('-', text.clone()),
+            });
+        }
+    }
+}
+
+impl UiElement for TransformPreview {
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn render(&self, frame: &mut Grid<RenderCell>) {
+        if !self.shown { return }
+
+        let bg = Color::Rgb { r: 22, g: 22, b: 23 };
+        let fg = Color::Rgb { r: 201, g: 199, b: 205 };
+        let header_bg = Color::Rgb { r: 68, g: 68, b: 72 };
+        let added = Color::Rgb { r: 144, g: 185, b: 159 };
+        let removed = Color::Rgb { r: 234, g: 131, b: 165 };
+
+        let height = frame.rows() / 2;
+        if height < 3 { return }
+
+        let top = frame.rows() - height;
+        let width = frame.cols();
+
+        let mut rows: Vec<(String, ContentStyle)> = vec![
+            (format!(" {}", self.title), ContentStyle::new().on(header_bg).with(fg))
+        ];
+
+        if self.lines.is_empty() {
+            rows.push((" No changes".to_string(), ContentStyle::new().on(bg).with(fg)));
+        }
+
+        for (marker, text) in &self.lines {
+            let color = match marker {
+                '+' => added,
+                '-' => removed,
+                _ => fg,
+            };
+            rows.push((format!("{} {}", marker, text), ContentStyle::new().on(bg).with(color)));
+        }
+
+        for y in 0..height {
+            let (text, style) = rows.get(y)
+                .cloned()
+                .unwrap_or_else(|| (String::new(), ContentStyle::new().on(bg).with(fg)));
+
+            let mut chars = text.chars();
+            for x in 0..width {
+                let ch = chars.next().unwrap_or(' ');
+                frame.cells[top + y][x] = RenderCell { ch, style, transparent: false };
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::any::Any;
+use std::time::{Duration, Instant, SystemTime};
+
+use crossterm::style::{Color, ContentStyle, Stylize};
+
+use crate::{types::{BufferId, RenderCell, Grid}, ui::ui_element::UiElement};
+use crate::undo::UndoTree;
+
+const MAX_ENTRIES: usize = 12;
+
+/// Lists the undo tree of the active buffer so any earlier state can be jumped to.
+pub struct UndoTreePanel {
+    pub shown: bool,
+    /// Node numbers in the order they are listed.
+    pub nodes: Vec<usize>,
+    pub lines: Vec<String>,
+    pub selected: usize,
+    pub current: usize,
+    buffer: Option<BufferId>,
+    updated: Option<Instant>,
+}
+
+impl UndoTreePanel {
+    pub fn new() -> Self {
+        Self {
+            shown: false,
+            nodes: vec![],
+            lines: vec![],
+            selected: 0,
+            current: 0,
+            buffer: None,
+            updated: None,
+        }
+    }
+
+    pub fn update(&mut self, buffer: BufferId, tree: &UndoTree) {
+        let changed = self.buffer != Some(buffer) || self.nodes.len() != tree.nodes().len() || self.current != tree.current();
+
+        // Previews are recomputed for every node, without changes only the ages need refreshing
+        let stale = self.updated.map(|updated| updated.elapsed() >= Duration::from_secs(1)).unwrap_or(true);
+        if !changed && !stale { return }
+
+        self.buffer = Some(buffer);
+        self.updated = Some(Instant::now());
+
+        let layout = tree.layout();
+        let now = SystemTime::now();
+
+        self.lines = layout.iter()
+            .map(|(index, depth)| {
+                let node = &tree.nodes()[*index];
+                let marker = if *index == tree.current() { '>' } else { ' ' };
+
+                let preview = match node.parent {
+                    Some(_) => {
+                        let change = &node.change;
+                        let text = change.inserted.first().map(|line| line.trim()).unwrap_or("");
+                        format!("{}: {}", change.row + 1, text)
+                    }
+                    None => "original".to_string(),
+                };
+
+                format!("{} {}{:<4} {:>8}  {}", marker, "| ".repeat(*depth), index, Self::age(node.time, now), preview)
+            })
+            .collect();
+        self.nodes = layout.into_iter().map(|(index, _)| index).collect();
+        self.current = tree.current();
+
+        // Follow the current node when the tree moved on its own, like after `u`
+        if changed {
+            self.selected = self.nodes.iter().position(|index| *index == self.current).unwrap_or(0);
+        }
+    }
+
+    fn age(time: SystemTime, now: SystemTime) -> String {
+        let secs = now.duration_since(time).map(|age| age.as_secs()).unwrap_or(0);
+
+        match secs {
+            0..=59 => format!("{}s ago", secs),
+            60..=3599 => format!("{}m ago", secs / 60),
+            _ => format!("{}h ago", secs / 3600),
+        }
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        if self.nodes.is_empty() { return }
+        self.selected = self.selected.saturating_add_signed(delta).min(self.nodes.len() - 1);
+    }
+
+    pub fn selected_node(&self) -> Option<usize> {
+        self.nodes.get(self.selected).copied()
+    }
+}
+
+impl UiElement for UndoTreePanel {
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn render(&self, frame: &mut Grid<RenderCell>) {
+        if !self.shown { return }
+
+        let bg = Color::Rgb { r: 22, g: 22, b: 23 };
+        let fg = Color::Rgb { r: 201, g: 199, b: 205 };
+        let header_bg = Color::Rgb { r: 68, g: 68, b: 72 };
+
+        let entries = self.lines.len().clamp(1, MAX_ENTRIES);
+        let height = entries + 1;
+
+        if frame.rows() < height + 2 { return }
+
+        let top = frame.rows() - height;
+        let width = frame.cols();
+
+        // Keep the selection in the window
+        let first = self.selected.saturating_sub(entries - 1).min(self.lines.len().saturating_sub(entries));
+
+        let header = format!(" Undo tree ({} states)  j/k select, Enter jump, q close", self.lines.len());
+        let header_style = ContentStyle::new().on(header_bg).with(fg);
+
+        let mut chars = header.chars();
+        for x in 0..width {
+            let ch = chars.next().unwrap_or(' ');
+            frame.cells[top][x] = RenderCell { ch, style: header_style, transparent: false };
+        }
+
+        for y in 0..entries {
+            let index = first + y;
+            let text = self.lines.get(index).map(|line| line.as_str()).unwrap_or("");
+            let style = if index == self.selected {
+                ContentStyle::new().on(header_bg).with(fg)
+            } else {
+                ContentStyle::new().on(bg).with(fg)
+            };
+
+            let line = format!(" {}", text);
+
+            let mut chars = line.chars();
+            for x in 0..width {
+                let ch = chars.next().unwrap_or(' ');
+                frame.cells[top + 1 + y][x] = RenderCell { ch, style, transparent: false };
+            }
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use std::time::{Duration, SystemTime};
+
+/// How far `:earlier` and `:later` move, a number of changes or an amount of time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistoryStep {
+    Changes(usize),
+    Time(Duration),
+}
+
+impl HistoryStep {
+    /// Parses "3", "10s", "5m" or "1h".
+    pub fn parse(arg: &str) -> Option<Self> {
+        let digits = arg.chars().take_while(|ch| ch.is_ascii_digit()).count();
+        let count: u64 = arg[..digits].parse().ok()?;
+
+        let secs = match &arg[digits..] {
+            "" => return Some(HistoryStep::Changes(count as usize)),
+            "s" => count,
+            "m" => count * 60,
+            "h" => count * 3600,
+            _ => return None,
+        };
+
+        Some(HistoryStep::Time(Duration::from_secs(secs)))
+    }
+}
+
+/// The rows that differ between a node and its parent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LineChange {
+    pub row: usize,
+    /// Rows of the parent from `row` on that were replaced.
+    pub removed: Vec<String>,
+    /// What replaced them.
+    pub inserted: Vec<String>,
+}
+
+impl LineChange {
+    /// The rows between the common start and end of `old` and `new`.
+    pub fn between(old: &[String], new: &[String]) -> Self {
+        let row = first_difference(old, new);
+        let suffix = old[row..].iter().rev().zip(new[row..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        Self {
+            row,
+            removed: old[row..old.len() - suffix].to_vec(),
+            inserted: new[row..new.len() - suffix].to_vec(),
+        }
+    }
+
+    /// Turns the parent's text into the node's.
+    fn apply(&self, lines: &mut Vec<String>) {
+        lines.splice(self.row..self.row + self.removed.len(), self.inserted.iter().cloned());
+    }
+
+    /// Turns the node's text back into the parent's.
+    fn revert(&self, lines: &mut Vec<String>) {
+        lines.splice(self.row..self.row + self.inserted.len(), self.removed.iter().cloned());
+    }
+}
+
+/// A state of the buffer in the undo tree.
+#[derive(Debug, Clone)]
+pub struct UndoNode {
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    /// How the text differs from the parent's, empty for the original text. Changes are
+    /// taken from whole snapshots so edits that don't go through the editor, like
+    /// formatting, are recorded too.
+    pub change: LineChange,
+    pub time: SystemTime,
+    /// The child redo goes to, the branch that was visited last.
+    redo: Option<usize>,
+}
+
+/// Every state a buffer has been in. Editing after an undo starts a new branch
+/// instead of throwing away the changes that were undone.
+#[derive(Debug, Clone)]
+pub struct UndoTree {
+    nodes: Vec<UndoNode>,
+    current: usize,
+    /// The text of the current node, the others are rebuilt from the changes on the way.
+    lines: Vec<String>,
+    /// Buffer version the current node matches.
+    version: u32,
+}
+
+impl UndoTree {
+    pub fn new(lines: Vec<String>, version: u32) -> Self {
+        Self {
+            nodes: vec![UndoNode {
+                parent: None,
+                children: vec![],
+                change: LineChange::default(),
+                time: SystemTime::now(),
+                redo: None,
+            }],
+            current: 0,
+            lines,
+            version,
+        }
+    }
+
+    /// Nodes in the order they were created, their index is their number.
+    pub fn nodes(&self) -> &[UndoNode] {
+        &self.nodes
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn is_recorded(&self, version: u32) -> bool {
+        self.version == version
+    }
+
+    /// Notes that `version` of the buffer is the current node, after jumping to it.
+    pub fn mark_recorded(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    /// Adds the buffer's text as a child of the current node.
+    /// Returns false if nothing changed since the current node.
+    pub fn record(&mut self, lines: &[String], version: u32) -> bool {
+        self.version = version;
+        if self.lines == lines { return false }
+
+        let index = self.nodes.len();
+        self.nodes.push(UndoNode {
+            parent: Some(self.current),
+            children: vec![],
+            change: LineChange::between(&self.lines, lines),
+            time: SystemTime::now(),
+            redo: None,
+        });
+
+        let parent = &mut self.nodes[self.current];
+        parent.children.push(index);
+        parent.redo = Some(index);
+
+        self.current = index;
+        self.lines = lines.to_vec();
+        true
+    }
+
+    /// Moves to the parent node and returns its text.
+    pub fn undo(&mut self) -> Option<&[String]> {
+        let parent = self.nodes[self.current].parent?;
+        self.jump(parent)
+    }
+
+    /// Moves to the child that was visited last and returns its text.
+    pub fn redo(&mut self) -> Option<&[String]> {
+        let child = self.nodes[self.current].redo?;
+        self.jump(child)
+    }
+
+    /// Moves to any node and returns its text. Redo afterwards follows the path that led there.
+    pub fn jump(&mut self, index: usize) -> Option<&[String]> {
+        if index >= self.nodes.len() { return None }
+
+        let mut node = index;
+        while let Some(parent) = self.nodes[node].parent {
+            self.nodes[parent].redo = Some(node);
+            node = parent;
+        }
+
+        // Back up to the closest node both are under, then down the path to `index`
+        let target = self.ancestors(index);
+        let mut node = self.current;
+        while !target.contains(&node) {
+            self.nodes[node].change.revert(&mut self.lines);
+            node = self.nodes[node].parent?;
+        }
+        let common = target.iter().position(|ancestor| *ancestor == node)?;
+        for ancestor in target[..common].iter().rev() {
+            self.nodes[*ancestor].change.apply(&mut self.lines);
+        }
+
+        self.current = index;
+        Some(&self.lines)
+    }
+
+    /// `index` followed by its parents up to the original text.
+    fn ancestors(&self, index: usize) -> Vec<usize> {
+        let mut ancestors = vec![index];
+        while let Some(parent) = self.nodes[*ancestors.last().unwrap()].parent {
+            ancestors.push(parent);
+        }
+        ancestors
+    }
+
+    /// The node to go to for `:earlier`. Steps go through the nodes in the order they
+    /// were made, across branches, so every state can be reached.
+    pub fn earlier(&self, step: HistoryStep) -> usize {
+        match step {
+            HistoryStep::Changes(count) => self.current.saturating_sub(count),
+            HistoryStep::Time(duration) => {
+                let target = self.nodes[self.current].time.checked_sub(duration);
+                target.map(|target| self.made_by(target)).unwrap_or(0)
+            }
+        }
+    }
+
+    /// The node to go to for `:later`.
+    pub fn later(&self, step: HistoryStep) -> usize {
+        let newest = self.nodes.len() - 1;
+
+        match step {
+            HistoryStep::Changes(count) => self.current.saturating_add(count).min(newest),
+            HistoryStep::Time(duration) => {
+                let target = self.nodes[self.current].time.checked_add(duration);
+                target.map(|target| self.made_by(target)).unwrap_or(newest).max(self.current)
+            }
+        }
+    }
+
+    /// The newest node made at or before `time`, the original text if all are newer.
+    fn made_by(&self, time: SystemTime) -> usize {
+        self.nodes.iter().rposition(|node| node.time <= time).unwrap_or(0)
+    }
+
+    /// Depth of every node for drawing the tree, children listed after their parent
+    /// with the newest branch first.
+    pub fn layout(&self) -> Vec<(usize, usize)> {
+        let mut rows = vec![];
+        let mut stack = vec![(0, 0)];
+
+        while let Some((index, depth)) = stack.pop() {
+            rows.push((index, depth));
+
+            let children = &self.nodes[index].children;
+            // The oldest branch continues at the same depth, newer ones are indented
+            for (i, child) in children.iter().enumerate() {
+                stack.push((*child, if i == 0 { depth } else { depth + 1 }));
+            }
+        }
+
+        rows
+    }
+}
+
+/// First row where two versions of a buffer differ.
+pub fn first_difference(old: &[String], new: &[String]) -> usize {
+    old.iter().zip(new)
+        .position(|(a, b)| a != b)
+        .unwrap_or(old.len().min(new.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(String::from).collect()
+    }
+
+    #[test]
+    fn changes_hold_only_the_rows_that_differ() {
+        let change = LineChange::between(&lines("a\nb\nc\nd"), &lines("a\nB\nx\nd"));
+        assert_eq!(change, LineChange { row: 1, removed: lines("b\nc"), inserted: lines("B\nx") });
+
+        let change = LineChange::between(&lines("a\na"), &lines("a\na\na"));
+        assert_eq!((change.removed.len(), change.inserted.len()), (0, 1));
+    }
+
+    #[test]
+    fn jumps_rebuild_the_text_across_branches() {
+        let states = ["one", "one\ntwo", "one\n2\nthree", "zero\none\ntwo", "", "one\ntwo\nthree\nfour"];
+        let mut tree = UndoTree::new(lines(states[0]), 0);
+
+        tree.record(&lines(states[1]), 1);
+        tree.record(&lines(states[2]), 2);
+        tree.undo();
+        tree.record(&lines(states[3]), 3);
+        tree.jump(0);
+        tree.record(&lines(states[4]), 4);
+        tree.jump(2);
+        tree.record(&lines(states[5]), 5);
+
+        for from in 0..states.len() {
+            for (to, state) in states.iter().enumerate() {
+                tree.jump(from);
+                assert_eq!(tree.jump(to), Some(&lines(state)[..]), "{} to {}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_follow_the_last_branch() {
+        let mut tree = UndoTree::new(lines("a"), 0);
+        tree.record(&lines("a\nb"), 1);
+        tree.undo();
+        tree.record(&lines("c"), 2);
+
+        assert_eq!(tree.undo(), Some(&lines("a")[..]));
+        assert_eq!(tree.redo(), Some(&lines("c")[..]));
+        assert_eq!(tree.redo(), None);
+    }
+}
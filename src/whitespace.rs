@@ -0,0 +1,45 @@
+/// What tabs, non-breaking spaces and trailing spaces are drawn as with `list`.
+pub const TAB: char = '→';
+pub const NBSP: char = '␣';
+pub const TRAILING: char = '·';
+
+/// The marker each character of `line` is drawn as with `list`, `None` where the character
+/// is drawn as it is.
+pub fn markers(line: &str) -> Vec<Option<char>> {
+    let trailing_from = line.trim_end().chars().count();
+
+    line.chars()
+        .enumerate()
+        .map(|(i, ch)| match ch {
+            '\t' => Some(TAB),
+            '\u{a0}' => Some(NBSP),
+            ' ' if i >= trailing_from => Some(TRAILING),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Splits `text`, which starts at the character `markers` starts with, into runs of
+/// plain characters and of markers. The flag is true for markers.
+pub fn pieces(text: &str, markers: &[Option<char>]) -> Vec<(String, bool)> {
+    let mut pieces: Vec<(String, bool)> = vec![];
+
+    for (i, ch) in text.chars().enumerate() {
+        let (ch, marker) = match markers.get(i).copied().flatten() {
+            Some(marker) => (marker, true),
+            None => (ch, false),
+        };
+
+        match pieces.last_mut() {
+            Some((piece, is_marker)) if *is_marker == marker => piece.push(ch),
+            _ => pieces.push((ch.to_string(), marker)),
+        }
+    }
+
+    pieces
+}
+
+/// `lines` without whitespace at their ends.
+pub fn strip_trailing(lines: &[String]) -> Vec<String> {
+    lines.iter().map(|line| line.trim_end().to_string()).collect()
+}
@@ -0,0 +1,164 @@
+//! Responses recorded from real language servers, trimmed to the fields that matter, fed
+//! through the same parsing the LSP service uses.
+
+use std::collections::HashMap;
+
+use crossterm::style::Color;
+use serde_json::Value;
+
+use oxidy::buffer::Buffer;
+use oxidy::diagnostics::Diagnostic;
+use oxidy::lsp::LspResponse::{LspDiagnostics, LspResponse, LspResponseResult, LspSemanticResponseResult, SemanticTokensLegend};
+use oxidy::services::lsp_service::{apply_semantic_tokens, decode_tokens, path_from_uri};
+use oxidy::types::Token;
+
+const RUST_SOURCE: &str = "fn main() {\n    let x = \"héllo\";\n}\n";
+const GO_SOURCE: &str = "package main\n\nfunc main() {}\n";
+
+/// Like the service: the envelope first, then the result as the type the request expects.
+fn result<T: for<'de> serde::Deserialize<'de>>(json: &str) -> Result<T, serde_json::Error> {
+    let response: LspResponse<Value> = serde_json::from_str(json).expect("envelope");
+    serde_json::from_value(response.result)
+}
+
+fn initialize(json: &str) -> LspResponseResult {
+    result(json).expect("initialize result")
+}
+
+fn legend(json: &str) -> SemanticTokensLegend {
+    let mut result = initialize(json);
+    result.capabilities.semanticTokensProvider.take().expect("semantic tokens provider").legend
+}
+
+fn decode(legend: &SemanticTokensLegend, data: &[i32], source: &str) -> Vec<Vec<Token>> {
+    let colors: HashMap<String, Color> = [("keyword", Color::Red), ("function", Color::Blue), ("string", Color::Green)]
+        .into_iter()
+        .map(|(token_type, color)| (token_type.to_string(), color))
+        .collect();
+
+    let buffer = Buffer::from_content(source, "test".into());
+    let mut tokens = vec![Vec::new(); buffer.lines.len()];
+    decode_tokens(legend, data, &buffer, &colors, &mut tokens);
+    tokens
+}
+
+fn texts(tokens: &[Token]) -> Vec<(&str, usize)> {
+    tokens.iter().map(|token| (token.text.as_str(), token.offset)).collect()
+}
+
+#[test]
+fn full_with_delta_option() {
+    let rust_analyzer = initialize(include_str!("fixtures/lsp/rust-analyzer-initialize.json"));
+    let provider = rust_analyzer.capabilities.semanticTokensProvider.as_ref().unwrap();
+    assert!(provider.supports_full());
+    assert!(provider.supports_delta());
+    assert_eq!(rust_analyzer.serverInfo.unwrap().name, "rust-analyzer");
+
+    let clangd = initialize(include_str!("fixtures/lsp/clangd-initialize.json"));
+    let provider = clangd.capabilities.semanticTokensProvider.as_ref().unwrap();
+    assert!(provider.supports_full());
+    assert!(provider.supports_delta());
+}
+
+#[test]
+fn full_as_boolean() {
+    let gopls = initialize(include_str!("fixtures/lsp/gopls-initialize.json"));
+    let provider = gopls.capabilities.semanticTokensProvider.as_ref().unwrap();
+    assert!(provider.supports_full());
+    assert!(!provider.supports_delta());
+    assert_eq!(provider.legend.tokenTypes[15], "keyword");
+}
+
+#[test]
+fn server_without_semantic_tokens() {
+    let pyright = initialize(include_str!("fixtures/lsp/pyright-initialize.json"));
+    assert!(pyright.capabilities.semanticTokensProvider.is_none());
+    assert!(pyright.capabilities.textDocumentSync.is_some());
+    assert!(pyright.serverInfo.is_none());
+}
+
+#[test]
+fn full_tokens_with_result_id() {
+    let tokens: LspSemanticResponseResult = result(include_str!("fixtures/lsp/rust-analyzer-full.json")).unwrap();
+    let LspSemanticResponseResult::Full(full) = &tokens else { panic!("expected full tokens, got {:?}", tokens) };
+    assert_eq!(full.resultId.as_deref(), Some("1"));
+
+    let mut cached = vec![];
+    apply_semantic_tokens(&mut cached, &tokens);
+    assert_eq!(cached.len(), 25);
+
+    let decoded = decode(&legend(include_str!("fixtures/lsp/rust-analyzer-initialize.json")), &cached, RUST_SOURCE);
+    assert_eq!(texts(&decoded[0]), [("fn", 0), ("main", 3)]);
+    // Offsets are UTF-16 code units, the é takes one
+    assert_eq!(texts(&decoded[1]), [("let", 4), ("x", 8), ("\"héllo\"", 12)]);
+    assert_eq!(decoded[0][0].style, Some(Color::Red));
+    assert_eq!(decoded[0][1].style, Some(Color::Blue));
+    assert_eq!(decoded[1][1].style, None);
+    assert_eq!(decoded[1][2].style, Some(Color::Green));
+}
+
+#[test]
+fn full_tokens_without_result_id() {
+    let tokens: LspSemanticResponseResult = result(include_str!("fixtures/lsp/gopls-full.json")).unwrap();
+    let LspSemanticResponseResult::Full(full) = &tokens else { panic!("expected full tokens, got {:?}", tokens) };
+    assert_eq!(full.resultId, None);
+
+    let mut cached = vec![];
+    apply_semantic_tokens(&mut cached, &tokens);
+
+    let decoded = decode(&legend(include_str!("fixtures/lsp/gopls-initialize.json")), &cached, GO_SOURCE);
+    assert_eq!(texts(&decoded[0]), [("package", 0), ("main", 8)]);
+    assert!(decoded[1].is_empty());
+    assert_eq!(texts(&decoded[2]), [("func", 0), ("main", 5)]);
+    assert_eq!(decoded[2][1].style, Some(Color::Blue));
+}
+
+#[test]
+fn delta_replaces_a_token() {
+    let mut cached = vec![];
+    apply_semantic_tokens(&mut cached, &result(include_str!("fixtures/lsp/rust-analyzer-full.json")).unwrap());
+
+    let delta: LspSemanticResponseResult = result(include_str!("fixtures/lsp/rust-analyzer-delta.json")).unwrap();
+    let LspSemanticResponseResult::Delta(edits) = &delta else { panic!("expected a delta, got {:?}", delta) };
+    assert_eq!(edits.resultId.as_deref(), Some("2"));
+
+    apply_semantic_tokens(&mut cached, &delta);
+    assert_eq!(cached, [0, 0, 2, 6, 0, 0, 3, 4, 4, 2, 1, 4, 3, 6, 0, 0, 4, 1, 17, 0, 0, 4, 7, 14, 0]);
+}
+
+#[test]
+fn delta_without_data_deletes() {
+    let mut cached = vec![];
+    apply_semantic_tokens(&mut cached, &result(include_str!("fixtures/lsp/rust-analyzer-full.json")).unwrap());
+
+    let delta: LspSemanticResponseResult = result(include_str!("fixtures/lsp/clangd-delta.json")).unwrap();
+    apply_semantic_tokens(&mut cached, &delta);
+
+    let decoded = decode(&legend(include_str!("fixtures/lsp/rust-analyzer-initialize.json")), &cached, RUST_SOURCE);
+    assert_eq!(texts(&decoded[1]), [("let", 4), ("x", 8)]);
+}
+
+#[test]
+fn null_result_is_not_tokens() {
+    let tokens: Result<LspSemanticResponseResult, _> = result(include_str!("fixtures/lsp/pyright-null-result.json"));
+    assert!(tokens.is_err());
+}
+
+#[test]
+fn published_diagnostics_in_chars() {
+    let published: LspDiagnostics = serde_json::from_str(include_str!("fixtures/lsp/rust-analyzer-publish-diagnostics.json")).unwrap();
+    assert_eq!(path_from_uri(&published.params.uri), "/home/user/my project/src/main.rs");
+
+    // The emoji takes two UTF-16 units, the server's 19..21 is the `";` after the string
+    let buffer = Buffer::from_content("fn main() {\n    let x = \"h\u{1F600}llo\";\n}\n", "test".into());
+    let diagnostic = Diagnostic::from_lsp(&published.params.diagnostics[0]).to_char_cols(&buffer.lines);
+    assert_eq!((diagnostic.row, diagnostic.col, diagnostic.end_col), (1, 18, 20));
+    assert_eq!(diagnostic.message, "mismatched types");
+}
+
+#[test]
+fn uris_are_decoded() {
+    assert_eq!(path_from_uri("file:///tmp/a%20b/c%C3%A9.rs"), "/tmp/a b/cé.rs");
+    assert_eq!(path_from_uri("file:///tmp/100%"), "/tmp/100%");
+    assert_eq!(path_from_uri("file:///tmp/%zz"), "/tmp/%zz");
+}